@@ -1,6 +1,8 @@
+#[macro_use]
 extern crate ladspa;
 
-use ladspa::{PluginDescriptor, PortDescriptor, Port, DefaultValue, Data, Plugin, PortConnection};
+use ladspa::{PluginDescriptor, PortDescriptor, Port, DefaultValue, Data, Plugin, PortConnection,
+             InstantiateError, Preset, Unit, Category};
 use std::default::Default;
 
 const MAX_DELAY: Data = 5.0;
@@ -11,12 +13,13 @@ struct Delay {
     buf_idx: usize,
 }
 
-fn new_delay(_: &PluginDescriptor, sample_rate: u64) -> Box<Plugin + Send> {
-    Box::new(Delay {
+fn new_delay(_: &PluginDescriptor, sample_rate: u64)
+             -> Result<Box<dyn Plugin + Send>, InstantiateError> {
+    Ok(Box::new(Delay {
         sample_rate: sample_rate as Data,
         buf: Vec::new(),
         buf_idx: 0,
-    })
+    }))
 }
 
 impl Plugin for Delay {
@@ -26,12 +29,13 @@ impl Plugin for Delay {
         self.buf_idx = 0;
     }
 
-    fn run<'a>(&mut self, sample_count: usize, ports: &[&'a PortConnection<'a>]) {
+    fn run<'a>(&mut self, sample_count: usize, ports: &mut [PortConnection<'a>]) {
         let input = (ports[0].unwrap_audio(), ports[1].unwrap_audio());
-        let mut output = (ports[2].unwrap_audio_mut(), ports[3].unwrap_audio_mut());
-        let delay = ((*ports[4].unwrap_control() * self.sample_rate) as usize,
-                     (*ports[5].unwrap_control() * self.sample_rate) as usize);
-        let dry_wet = (*ports[6].unwrap_control(), *ports[7].unwrap_control());
+        let delay = ((ports[4].unwrap_control() * self.sample_rate) as usize,
+                     (ports[5].unwrap_control() * self.sample_rate) as usize);
+        let dry_wet = (ports[6].unwrap_control(), ports[7].unwrap_control());
+        let (left, right) = ports[2..4].split_at_mut(1);
+        let output = (left[0].unwrap_audio_mut(), right[0].unwrap_audio_mut());
 
         let buffer_read_idx = (self.buf_idx + self.buf.len() - delay.0,
                                self.buf_idx + self.buf.len() - delay.1);
@@ -56,8 +60,7 @@ impl Plugin for Delay {
     }
 }
 
-#[no_mangle]
-pub fn get_ladspa_descriptor(index: u64) -> Option<PluginDescriptor> {
+fn get_descriptor(index: u64) -> Option<PluginDescriptor> {
     match index {
         0 => {
             Some(PluginDescriptor {
@@ -67,6 +70,7 @@ pub fn get_ladspa_descriptor(index: u64) -> Option<PluginDescriptor> {
                 name: "Stereo Delay",
                 maker: "Noah Weninger",
                 copyright: "None",
+                category: Some(Category::Delay),
                 ports: vec![
                     Port {
                         name: "Left Audio In",
@@ -95,6 +99,9 @@ pub fn get_ladspa_descriptor(index: u64) -> Option<PluginDescriptor> {
                         default: Some(DefaultValue::Value1),
                         lower_bound: Some(0.0),
                         upper_bound: Some(MAX_DELAY),
+                        group: None,
+                        scale_points: &[],
+                        unit: Some(Unit::Seconds),
                     },
                     Port {
                         name: "Right Delay (seconds)",
@@ -103,6 +110,9 @@ pub fn get_ladspa_descriptor(index: u64) -> Option<PluginDescriptor> {
                         default: Some(DefaultValue::Value1),
                         lower_bound: Some(0.0),
                         upper_bound: Some(MAX_DELAY),
+                        group: None,
+                        scale_points: &[],
+                        unit: Some(Unit::Seconds),
                     },
                     Port {
                         name: "Left Dry/Wet",
@@ -111,6 +121,9 @@ pub fn get_ladspa_descriptor(index: u64) -> Option<PluginDescriptor> {
                         default: Some(DefaultValue::Middle),
                         lower_bound: Some(0.0),
                         upper_bound: Some(1.0),
+                        group: None,
+                        scale_points: &[],
+                        unit: None,
                     },
                     Port {
                         name: "Right Dry/Wet",
@@ -119,6 +132,19 @@ pub fn get_ladspa_descriptor(index: u64) -> Option<PluginDescriptor> {
                         default: Some(DefaultValue::Middle),
                         lower_bound: Some(0.0),
                         upper_bound: Some(1.0),
+                        group: None,
+                        scale_points: &[],
+                        unit: None,
+                    },
+                ],
+                presets: vec![
+                    Preset {
+                        name: "Slapback",
+                        values: vec![(4, 0.1), (5, 0.12), (6, 0.4), (7, 0.4)],
+                    },
+                    Preset {
+                        name: "Wide Echo",
+                        values: vec![(4, 0.3), (5, 0.45), (6, 0.5), (7, 0.5)],
                     },
                 ],
                 new: new_delay,
@@ -128,3 +154,5 @@ pub fn get_ladspa_descriptor(index: u64) -> Option<PluginDescriptor> {
         _ => None
     }
 }
+
+export_ladspa!(fn get_descriptor);
@@ -0,0 +1,8 @@
+//! The reference plugins of ```ladspa::plugins```, built into one library.
+
+#[macro_use]
+extern crate ladspa;
+
+use ladspa::plugins::{Amp, Delay, Filter, Gate, Panner};
+
+export_ladspa!(Amp, Panner, Delay, Filter, Gate);
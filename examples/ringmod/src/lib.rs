@@ -1,6 +1,7 @@
+#[macro_use]
 extern crate ladspa;
 
-use ladspa::{Plugin, PluginDescriptor, Port, PortConnection, Data};
+use ladspa::{Plugin, PluginDescriptor, Port, PortConnection, Data, InstantiateError, Category};
 use std::default::Default;
 
 struct RingMod {
@@ -8,18 +9,19 @@ struct RingMod {
     sample_rate: u64,
 }
 
-fn new_ringmod(_: &PluginDescriptor, sample_rate: u64) -> Box<Plugin + Send> {
-    Box::new(RingMod {
+fn new_ringmod(_: &PluginDescriptor, sample_rate: u64)
+               -> Result<Box<dyn Plugin + Send>, InstantiateError> {
+    Ok(Box::new(RingMod {
         time: 0,
-        sample_rate: sample_rate,
-    })
+        sample_rate,
+    }))
 }
 
 impl Plugin for RingMod {
-    fn run<'a>(&mut self, sample_count: usize, ports: &[&'a PortConnection<'a>]) {
+    fn run<'a>(&mut self, sample_count: usize, ports: &mut [PortConnection<'a>]) {
         let input = ports[0].unwrap_audio();
-        let mut output = ports[1].unwrap_audio_mut();
-        let freq = *ports[2].unwrap_control();
+        let freq = ports[2].unwrap_control();
+        let output = ports[1].unwrap_audio_mut();
         for i in 0..sample_count {
             output[i] = input[i];
 
@@ -33,8 +35,7 @@ impl Plugin for RingMod {
     }
 }
 
-#[no_mangle]
-pub extern fn get_ladspa_descriptor(index: u64) -> Option<PluginDescriptor> {
+fn get_descriptor(index: u64) -> Option<PluginDescriptor> {
     match index {
         0 => {
             Some(PluginDescriptor {
@@ -44,6 +45,7 @@ pub extern fn get_ladspa_descriptor(index: u64) -> Option<PluginDescriptor> {
                 name: "Mono Ring Modulator",
                 maker: "Noah Weninger",
                 copyright: "None",
+                category: Some(Category::Modulator),
                 ports: vec![Port {
                     name: "Audio In",
                     desc: ladspa::PortDescriptor::AudioInput,
@@ -57,12 +59,18 @@ pub extern fn get_ladspa_descriptor(index: u64) -> Option<PluginDescriptor> {
                     desc: ladspa::PortDescriptor::ControlInput,
                     hint: Some(ladspa::HINT_SAMPLE_RATE | ladspa::HINT_LOGARITHMIC),
                     default: Some(ladspa::DefaultValue::Value440),
-                    lower_bound: Some(0.0),
+                    lower_bound: Some(0.0001),
                     upper_bound: Some(0.5),
+                    group: None,
+                    scale_points: &[],
+                    unit: None,
                 }],
+                presets: vec![],
                 new: new_ringmod
             })
         },
         _ => None
     }
 }
+
+export_ladspa!(fn get_descriptor);
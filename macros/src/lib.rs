@@ -0,0 +1,224 @@
+/*!
+ * Procedural macros for the ```ladspa``` crate. Use them through the ```macros``` feature of
+ * ```ladspa``` rather than depending on this crate directly.
+ */
+
+extern crate proc_macro;
+
+use proc_macro::{Delimiter, TokenStream, TokenTree};
+
+/**
+ * Generates a ```ladspa::DescribedPlugin``` implementation for a struct implementing
+ * ```ladspa::Plugin```.
+ *
+ * The attribute takes a comma separated list of ```key = value``` pairs:
+ *
+ * * ```unique_id```, ```label```: required.
+ * * ```name```, ```maker```, ```copyright```: optional strings. ```name``` defaults to the label.
+ * * ```properties```: an optional expression such as ```ladspa::PROP_REALTIME```.
+ * * ```category```: an optional ```Category``` variant, such as ```Lowpass```.
+ * * ```new```: an optional ```PluginConstructor```. When omitted, instances are created with
+ *   ```Default::default()```.
+ * * ```audio_in```, ```audio_out```, ```control_out```: a port name. May be repeated.
+ * * ```control_in```: ```("Name", lower, upper, Default)``` where ```Default``` names a
+ *   ```DefaultValue``` variant, such as ```Middle``` or ```Value(0.5)```. The ```Unit``` is
+ *   taken from the end of the name, as by ```PluginDescriptorBuilder::control_in```. May be
+ *   repeated.
+ * * ```preset```: ```("Name", (port, value), ...)``` where ```port``` is a port index. May be
+ *   repeated.
+ *
+ * Ports are declared in the order they appear in the attribute.
+ *
+ * ```rust,ignore
+ * #[ladspa_plugin(unique_id = 9001, label = "mono_amp", name = "Mono Amplifier",
+ *                 audio_in = "Input", audio_out = "Output",
+ *                 control_in = ("Gain", 0.0, 2.0, Value1))]
+ * #[derive(Default)]
+ * struct MonoAmp;
+ * ```
+ */
+#[proc_macro_attribute]
+pub fn ladspa_plugin(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let generated = match struct_name(item.clone()) {
+        Ok(name) => parse_args(attr).and_then(|args| describe(&name, &args)),
+        Err(err) => Err(err),
+    };
+    let generated = match generated {
+        Ok(code) => code,
+        Err(err) => format!("compile_error!({:?});", format!("ladspa_plugin: {}", err)),
+    };
+    let mut out = item;
+    out.extend(generated.parse::<TokenStream>().unwrap());
+    out
+}
+
+// Returns the name of the struct the attribute is attached to.
+fn struct_name(item: TokenStream) -> Result<String, String> {
+    let mut tokens = item.into_iter();
+    while let Some(token) = tokens.next() {
+        if let TokenTree::Ident(ref ident) = token {
+            if ident.to_string() == "struct" {
+                let name = match tokens.next() {
+                    Some(TokenTree::Ident(name)) => name.to_string(),
+                    _ => return Err("expected a struct name".to_string()),
+                };
+                if let Some(TokenTree::Punct(ref punct)) = tokens.next() {
+                    if punct.as_char() == '<' {
+                        return Err("generic structs are not supported".to_string());
+                    }
+                }
+                return Ok(name);
+            }
+        }
+    }
+    Err("can only be applied to a struct".to_string())
+}
+
+// Splits a token stream on top level commas, dropping empty pieces.
+fn split_commas(stream: TokenStream) -> Vec<Vec<TokenTree>> {
+    let mut pieces = vec![Vec::new()];
+    for token in stream {
+        match token {
+            TokenTree::Punct(ref punct) if punct.as_char() == ',' => pieces.push(Vec::new()),
+            token => pieces.last_mut().unwrap().push(token),
+        }
+    }
+    pieces.retain(|piece| !piece.is_empty());
+    pieces
+}
+
+fn render(tokens: &[TokenTree]) -> String {
+    tokens.iter().cloned().collect::<TokenStream>().to_string()
+}
+
+fn parse_args(attr: TokenStream) -> Result<Vec<(String, Vec<TokenTree>)>, String> {
+    split_commas(attr)
+        .into_iter()
+        .map(|piece| {
+            match (piece.first(), piece.get(1)) {
+                (Some(TokenTree::Ident(key)), Some(TokenTree::Punct(eq)))
+                    if eq.as_char() == '=' && piece.len() > 2 => {
+                    Ok((key.to_string(), piece[2..].to_vec()))
+                }
+                _ => Err(format!("expected `key = value`, found `{}`", render(&piece))),
+            }
+        })
+        .collect()
+}
+
+fn string_literal(key: &str, value: &[TokenTree]) -> Result<String, String> {
+    let text = render(value);
+    if value.len() == 1 && text.starts_with('"') {
+        Ok(text)
+    } else {
+        Err(format!("`{}` must be a string literal", key))
+    }
+}
+
+fn control_in(value: &[TokenTree]) -> Result<String, String> {
+    let group = match value {
+        [TokenTree::Group(group)] if group.delimiter() == Delimiter::Parenthesis => group,
+        _ => return Err("`control_in` must be a (name, lower, upper, default) tuple".to_string()),
+    };
+    let fields = split_commas(group.stream());
+    if fields.len() != 4 {
+        return Err("`control_in` must be a (name, lower, upper, default) tuple".to_string());
+    }
+    let name = string_literal("control_in", &fields[0])?;
+    Ok(format!("::ladspa::Port {{ name: {}, desc: ::ladspa::PortDescriptor::ControlInput, \
+                default: Some(::ladspa::DefaultValue::{}), lower_bound: Some({}), \
+                upper_bound: Some({}), unit: ::ladspa::Unit::from_name({}), \
+                ..Default::default() }}",
+               name,
+               render(&fields[3]),
+               render(&fields[1]),
+               render(&fields[2]),
+               name))
+}
+
+fn preset(value: &[TokenTree]) -> Result<String, String> {
+    let group = match value {
+        [TokenTree::Group(group)] if group.delimiter() == Delimiter::Parenthesis => group,
+        _ => return Err("`preset` must be a (name, (port, value), ...) tuple".to_string()),
+    };
+    let fields = split_commas(group.stream());
+    let name = match fields.first() {
+        Some(name) => string_literal("preset", name)?,
+        None => return Err("`preset` must be a (name, (port, value), ...) tuple".to_string()),
+    };
+    let values = fields[1..].iter().map(|value| render(value)).collect::<Vec<_>>();
+    Ok(format!("::ladspa::Preset {{ name: {}, values: vec![{}] }}",
+               name,
+               values.join(", ")))
+}
+
+fn describe(name: &str, args: &[(String, Vec<TokenTree>)]) -> Result<String, String> {
+    let mut unique_id = None;
+    let mut label = None;
+    let mut plugin_name = None;
+    let mut maker = "\"\"".to_string();
+    let mut copyright = "\"None\"".to_string();
+    let mut properties = "::ladspa::PROP_NONE".to_string();
+    let mut category = "None".to_string();
+    let mut new = None;
+    let mut ports = Vec::new();
+    let mut presets = Vec::new();
+
+    for (key, value) in args {
+        let simple_port = |desc: &str| -> Result<String, String> {
+            Ok(format!("::ladspa::Port {{ name: {}, desc: ::ladspa::PortDescriptor::{}, \
+                        ..Default::default() }}",
+                       string_literal(key, value)?,
+                       desc))
+        };
+        match key.as_str() {
+            "unique_id" => unique_id = Some(render(value)),
+            "label" => label = Some(string_literal(key, value)?),
+            "name" => plugin_name = Some(string_literal(key, value)?),
+            "maker" => maker = string_literal(key, value)?,
+            "copyright" => copyright = string_literal(key, value)?,
+            "properties" => properties = render(value),
+            "category" => category = format!("Some(::ladspa::Category::{})", render(value)),
+            "new" => new = Some(render(value)),
+            "audio_in" => ports.push(simple_port("AudioInput")?),
+            "audio_out" => ports.push(simple_port("AudioOutput")?),
+            "control_out" => ports.push(simple_port("ControlOutput")?),
+            "control_in" => ports.push(control_in(value)?),
+            "preset" => presets.push(preset(value)?),
+            _ => return Err(format!("unknown key `{}`", key)),
+        }
+    }
+
+    let unique_id = unique_id.ok_or("missing `unique_id`")?;
+    let label = label.ok_or("missing `label`")?;
+    let plugin_name = plugin_name.unwrap_or_else(|| label.clone());
+    let new = new.unwrap_or_else(|| format!("::ladspa::new_default::<{}>", name));
+
+    Ok(format!("impl ::ladspa::DescribedPlugin for {name} {{ \
+                    fn descriptor() -> ::ladspa::PluginDescriptor {{ \
+                        ::ladspa::PluginDescriptor {{ \
+                            unique_id: {unique_id}, \
+                            label: {label}, \
+                            properties: {properties}, \
+                            name: {plugin_name}, \
+                            maker: {maker}, \
+                            copyright: {copyright}, \
+                            category: {category}, \
+                            ports: vec![{ports}], \
+                            presets: vec![{presets}], \
+                            new: {new}, \
+                        }} \
+                    }} \
+                }}",
+               name = name,
+               unique_id = unique_id,
+               label = label,
+               properties = properties,
+               plugin_name = plugin_name,
+               maker = maker,
+               copyright = copyright,
+               category = category,
+               ports = ports.join(", "),
+               presets = presets.join(", "),
+               new = new))
+}
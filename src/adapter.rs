@@ -0,0 +1,4227 @@
+//! Plugins built from other plugins.
+//!
+//! The types here implement ```Plugin``` by wrapping another plugin and changing how it is run,
+//! so a ```PluginDescriptor::new``` function can return them in place of the plugin itself.
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use core::slice;
+
+use super::{channel_names, Context, ControlPort, Data, DefaultValue, DescribedPlugin,
+            InstantiateError, Plugin, PluginDescriptor, Port, PortConnection, PortData,
+            PortDescriptor, PortGroup, Ports, Preset, Properties, Stateful, HINT_INTEGER,
+            HINT_TOGGLED, PROP_HARD_REALTIME_CAPABLE, PROP_INPLACE_BROKEN};
+use super::dsp::Sample;
+use super::dsp::delay::DelayLine;
+use super::dsp::resample::{Quality, Resampler};
+use super::intern::intern;
+use super::mapping::ControlRange;
+use super::units::{db_to_gain, Unit};
+use super::util::{ControlRamp, Crossfader, SmoothedValue};
+#[cfg(not(any(feature = "std", test)))]
+use math::Float;
+#[cfg(feature = "dssi")]
+use super::dssi::{CcMapping, Curve, MidiEvent, MidiMessage};
+
+/// The ports of a ```PromotedPlugin``` for one block, converted to its sample type.
+///
+/// Each kind of port is indexed separately, in the order the ports are declared: ```input(0)```
+/// is the first audio input, ```control(0)``` the first control input and so on.
+pub struct PromotedPorts<'b, S: Sample + 'b> {
+    sample_count: usize,
+    inputs: &'b [Vec<S>],
+    outputs: &'b mut [Vec<S>],
+    controls: &'b [Data],
+    control_outputs: &'b mut [Data],
+}
+
+impl<'b, S: Sample> PromotedPorts<'b, S> {
+    /// The number of samples in the block.
+    pub fn sample_count(&self) -> usize {
+        self.sample_count
+    }
+
+    /// The number of audio inputs.
+    pub fn input_count(&self) -> usize {
+        self.inputs.len()
+    }
+
+    /// The number of audio outputs.
+    pub fn output_count(&self) -> usize {
+        self.outputs.len()
+    }
+
+    /// The samples of the ```n```th audio input.
+    pub fn input(&self, n: usize) -> &[S] {
+        &self.inputs[n][..self.sample_count]
+    }
+
+    /// The buffer of the ```n```th audio output, to be filled in.
+    pub fn output(&mut self, n: usize) -> &mut [S] {
+        &mut self.outputs[n][..self.sample_count]
+    }
+
+    /// The ```n```th audio input along with the ```m```th audio output, for plugins which
+    /// process one into the other.
+    pub fn input_output(&mut self, n: usize, m: usize) -> (&[S], &mut [S]) {
+        (&self.inputs[n][..self.sample_count], &mut self.outputs[m][..self.sample_count])
+    }
+
+    /// The value of the ```n```th control input.
+    pub fn control(&self, n: usize) -> Data {
+        self.controls[n]
+    }
+
+    /// Sets the value of the ```n```th control output.
+    pub fn set_control_output(&mut self, n: usize, value: Data) {
+        self.control_outputs[n] = value;
+    }
+}
+
+/// A plugin processing audio as a ```Sample``` type other than ```Data```, usually ```f64```.
+/// Run it as a ```Plugin``` by wrapping it in ```Promote```. Like ```Plugin```, it must be
+/// ```Send```.
+pub trait PromotedPlugin: Send {
+    /// The type audio is processed as.
+    type Sample: Sample;
+
+    /// Derives everything depending on the sample rate and block size, as
+    /// ```Plugin::initialize```.
+    fn initialize(&mut self, sample_rate: u64, max_block_hint: Option<usize>) {
+        let _ = (sample_rate, max_block_hint);
+    }
+
+    /// Resets all state depending on the history of the instance, as ```Plugin::activate```.
+    fn activate(&mut self) {}
+
+    /// Processes one block, as ```Plugin::run```.
+    fn run(&mut self, ports: &mut PromotedPorts<Self::Sample>);
+
+    /// Whether denormals should be flushed to zero while the plugin runs, as
+    /// ```Plugin::flush_denormals```.
+    fn flush_denormals(&self) -> bool {
+        false
+    }
+
+    /// Returns the plugin as ```Stateful``` if it keeps state beyond its control values, as
+    /// ```Plugin::stateful```.
+    fn stateful(&mut self) -> Option<&mut dyn Stateful> {
+        None
+    }
+
+    /// Returns the plugin as ```Stateful``` for saving its state, as ```Plugin::state```.
+    fn state(&self) -> Option<&dyn Stateful> {
+        None
+    }
+
+    /// Indicates the instance is no longer live, as ```Plugin::deactivate```.
+    fn deactivate(&mut self) {}
+}
+
+/// Runs a ```PromotedPlugin``` as a ```Plugin```, converting the host's audio to the plugin's
+/// sample type before every block and the results back afterwards. Control ports are passed
+/// through as they are.
+///
+/// ```no_run
+/// # use ladspa::{DescribedPlugin, InstantiateError, Plugin, PluginDescriptor, PortConnection};
+/// # use ladspa::adapter::{Promote, PromotedPlugin, PromotedPorts};
+/// # struct Filter;
+/// # impl Filter { fn new(_: u64) -> Filter { Filter } }
+/// # impl PromotedPlugin for Filter {
+/// #     type Sample = f64;
+/// #     fn run(&mut self, _: &mut PromotedPorts<f64>) {}
+/// # }
+/// fn new_filter(_: &PluginDescriptor, sample_rate: u64)
+///               -> Result<Box<dyn Plugin + Send>, InstantiateError> {
+///     Ok(Box::new(Promote::new(Filter::new(sample_rate))))
+/// }
+/// ```
+///
+/// The conversion buffers grow to the largest block seen, so the first blocks allocate.
+pub struct Promote<P: PromotedPlugin> {
+    plugin: P,
+    inputs: Vec<Vec<P::Sample>>,
+    outputs: Vec<Vec<P::Sample>>,
+    controls: Vec<Data>,
+    control_outputs: Vec<Data>,
+}
+
+impl<P: PromotedPlugin> Promote<P> {
+    /// Wraps ```plugin```.
+    pub fn new(plugin: P) -> Promote<P> {
+        Promote {
+            plugin,
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            controls: Vec::new(),
+            control_outputs: Vec::new(),
+        }
+    }
+
+    /// Returns the wrapped plugin.
+    pub fn inner(&self) -> &P {
+        &self.plugin
+    }
+
+    /// Returns the wrapped plugin.
+    pub fn inner_mut(&mut self) -> &mut P {
+        &mut self.plugin
+    }
+
+    /// Unwraps the plugin.
+    pub fn into_inner(self) -> P {
+        self.plugin
+    }
+}
+
+// Makes `buffers` hold `count` buffers of at least `sample_count` samples.
+fn reserve<S: Sample>(buffers: &mut Vec<Vec<S>>, count: usize, sample_count: usize) {
+    buffers.resize_with(count, Vec::new);
+    for buffer in buffers.iter_mut() {
+        if buffer.len() < sample_count {
+            buffer.resize(sample_count, S::ZERO);
+        }
+    }
+}
+
+// The largest block a plugin run in chunks of at most `chunk_size` samples sees, when the host
+// runs blocks of at most `max_block_hint`.
+fn chunk_hint(max_block_hint: Option<usize>, chunk_size: usize) -> usize {
+    max_block_hint.map_or(chunk_size, |max| max.min(chunk_size))
+}
+
+impl<P: PromotedPlugin> Plugin for Promote<P> {
+    fn initialize(&mut self, sample_rate: u64, max_block_hint: Option<usize>) {
+        self.plugin.initialize(sample_rate, max_block_hint);
+    }
+
+    fn activate(&mut self) {
+        self.plugin.activate();
+    }
+
+    fn run<'a>(&mut self, sample_count: usize, ports: &mut [PortConnection<'a>]) {
+        let count = |matches: fn(&PortData) -> bool| {
+            ports.iter().filter(|port| matches(&port.data)).count()
+        };
+        reserve(&mut self.inputs,
+                count(|data| matches!(*data, PortData::AudioInput(_))),
+                sample_count);
+        reserve(&mut self.outputs,
+                count(|data| matches!(*data, PortData::AudioOutput(_))),
+                sample_count);
+        let control_outputs = count(|data| matches!(*data, PortData::ControlOutput(_)));
+        self.control_outputs.resize(control_outputs, 0.0);
+
+        self.controls.clear();
+        let mut inputs = self.inputs.iter_mut();
+        for port in ports.iter() {
+            match port.data {
+                PortData::AudioInput(data) => {
+                    let buffer = inputs.next().unwrap();
+                    for (promoted, &sample) in buffer.iter_mut().zip(&data[..sample_count]) {
+                        *promoted = P::Sample::from_data(sample);
+                    }
+                }
+                PortData::ControlInput(&value) => self.controls.push(value),
+                _ => (),
+            }
+        }
+
+        self.plugin.run(&mut PromotedPorts {
+            sample_count,
+            inputs: &self.inputs,
+            outputs: &mut self.outputs,
+            controls: &self.controls,
+            control_outputs: &mut self.control_outputs,
+        });
+
+        let mut outputs = self.outputs.iter();
+        let mut control_outputs = self.control_outputs.iter();
+        for port in ports {
+            match port.data {
+                PortData::AudioOutput(ref mut data) => {
+                    let buffer = outputs.next().unwrap();
+                    for (sample, promoted) in data.iter_mut().zip(buffer) {
+                        *sample = promoted.to_data();
+                    }
+                }
+                PortData::ControlOutput(ref mut data) => {
+                    **data = *control_outputs.next().unwrap();
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn flush_denormals(&self) -> bool {
+        self.plugin.flush_denormals()
+    }
+
+    fn stateful(&mut self) -> Option<&mut dyn Stateful> {
+        self.plugin.stateful()
+    }
+
+    fn state(&self) -> Option<&dyn Stateful> {
+        self.plugin.state()
+    }
+
+    fn deactivate(&mut self) {
+        self.plugin.deactivate();
+    }
+}
+
+/// A plugin processing one frame at a time: the samples of its ```I``` audio inputs at one point
+/// in time in, those of its ```O``` audio outputs out. Run it as a ```Plugin``` by wrapping it in
+/// ```PerSample```, which takes care of the block loop. Like ```Plugin```, it must be ```Send```.
+///
+/// ```no_run
+/// # use ladspa::Data;
+/// # use ladspa::adapter::SamplePlugin;
+/// struct Balance;
+///
+/// impl SamplePlugin<2, 2> for Balance {
+///     fn process(&mut self, [left, right]: [Data; 2], controls: &[Data]) -> [Data; 2] {
+///         let balance = controls[0];
+///         [left * (1.0 - balance).min(1.0), right * (1.0 + balance).min(1.0)]
+///     }
+/// }
+/// ```
+pub trait SamplePlugin<const I: usize, const O: usize>: Send {
+    /// Derives everything depending on the sample rate, as ```Plugin::initialize```. The block
+    /// size hint is always 1, as the plugin processes one frame at a time.
+    fn initialize(&mut self, sample_rate: u64, max_block_hint: Option<usize>) {
+        let _ = (sample_rate, max_block_hint);
+    }
+
+    /// Resets all state depending on the history of the instance, as ```Plugin::activate```.
+    fn activate(&mut self) {}
+
+    /// Processes one frame, given the values of the control inputs in the order they are
+    /// declared, and returns the output samples.
+    fn process(&mut self, inputs: [Data; I], controls: &[Data]) -> [Data; O];
+
+    /// Sets the control outputs, in the order they are declared, after each block. They are left
+    /// at 0 by default.
+    fn control_outputs(&mut self, outputs: &mut [Data]) {
+        let _ = outputs;
+    }
+
+    /// Whether denormals should be flushed to zero while the plugin runs, as
+    /// ```Plugin::flush_denormals```.
+    fn flush_denormals(&self) -> bool {
+        false
+    }
+
+    /// Returns the plugin as ```Stateful``` if it keeps state beyond its control values, as
+    /// ```Plugin::stateful```.
+    fn stateful(&mut self) -> Option<&mut dyn Stateful> {
+        None
+    }
+
+    /// Returns the plugin as ```Stateful``` for saving its state, as ```Plugin::state```.
+    fn state(&self) -> Option<&dyn Stateful> {
+        None
+    }
+
+    /// Indicates the instance is no longer live, as ```Plugin::deactivate```.
+    fn deactivate(&mut self) {}
+}
+
+/// Runs a ```SamplePlugin``` as a ```Plugin```, calling ```SamplePlugin::process``` for each
+/// frame of every block.
+///
+/// ```no_run
+/// # use ladspa::{DescribedPlugin, InstantiateError, Plugin, PluginDescriptor, PortConnection};
+/// # use ladspa::Data;
+/// # use ladspa::adapter::{PerSample, SamplePlugin};
+/// # struct Balance;
+/// # impl SamplePlugin<2, 2> for Balance {
+/// #     fn process(&mut self, frame: [Data; 2], _: &[Data]) -> [Data; 2] { frame }
+/// # }
+/// fn new_balance(_: &PluginDescriptor, _: u64)
+///                -> Result<Box<dyn Plugin + Send>, InstantiateError> {
+///     Ok(Box::new(PerSample::new(Balance)))
+/// }
+/// ```
+///
+/// Panics when run if the plugin does not have exactly ```I``` audio inputs and ```O``` audio
+/// outputs. The first block allocates the buffers of the control ports.
+pub struct PerSample<P: SamplePlugin<I, O>, const I: usize, const O: usize> {
+    plugin: P,
+    controls: Vec<Data>,
+    control_outputs: Vec<Data>,
+}
+
+impl<P: SamplePlugin<I, O>, const I: usize, const O: usize> PerSample<P, I, O> {
+    /// Wraps ```plugin```.
+    pub fn new(plugin: P) -> PerSample<P, I, O> {
+        PerSample {
+            plugin,
+            controls: Vec::new(),
+            control_outputs: Vec::new(),
+        }
+    }
+
+    /// Returns the wrapped plugin.
+    pub fn inner(&self) -> &P {
+        &self.plugin
+    }
+
+    /// Returns the wrapped plugin.
+    pub fn inner_mut(&mut self) -> &mut P {
+        &mut self.plugin
+    }
+
+    /// Unwraps the plugin.
+    pub fn into_inner(self) -> P {
+        self.plugin
+    }
+}
+
+impl<P: SamplePlugin<I, O>, const I: usize, const O: usize> Plugin for PerSample<P, I, O> {
+    fn initialize(&mut self, sample_rate: u64, _: Option<usize>) {
+        // The plugin only ever sees a frame at a time.
+        self.plugin.initialize(sample_rate, Some(1));
+    }
+
+    fn activate(&mut self) {
+        self.plugin.activate();
+    }
+
+    fn run<'a>(&mut self, sample_count: usize, ports: &mut [PortConnection<'a>]) {
+        let mut ports = Ports::new(ports);
+        self.controls.clear();
+        self.controls.extend(ports.controls());
+        let (plugin, controls) = (&mut self.plugin, &self.controls);
+        ports.zip_audio(sample_count, |&inputs, outputs: &mut [Data; O]| {
+            *outputs = plugin.process(inputs, controls);
+        });
+
+        self.control_outputs.clear();
+        self.control_outputs.extend(ports.control_outputs().map(|_| 0.0));
+        self.plugin.control_outputs(&mut self.control_outputs);
+        for (output, &value) in ports.control_outputs().zip(&self.control_outputs) {
+            *output = value;
+        }
+    }
+
+    fn flush_denormals(&self) -> bool {
+        self.plugin.flush_denormals()
+    }
+
+    fn stateful(&mut self) -> Option<&mut dyn Stateful> {
+        self.plugin.stateful()
+    }
+
+    fn state(&self) -> Option<&dyn Stateful> {
+        self.plugin.state()
+    }
+
+    fn deactivate(&mut self) {
+        self.plugin.deactivate();
+    }
+}
+
+/// A plugin with a fixed number of audio channels, processing one frame at a time, whose audio
+/// ports and constructor can be generated from its type. The channel counts it is declared with
+/// are then the ones it runs with, which the compiler checks.
+///
+/// ```no_run
+/// # use ladspa::{Data, DefaultValue, PluginDescriptor};
+/// # use ladspa::adapter::FramePlugin;
+/// struct Widener;
+///
+/// impl FramePlugin<2, 2> for Widener {
+///     fn new(_: u64) -> Widener {
+///         Widener
+///     }
+///
+///     fn run_frame(&mut self, [left, right]: [Data; 2], controls: &[Data]) -> [Data; 2] {
+///         let (mid, side) = ((left + right) * 0.5, (left - right) * 0.5 * controls[0]);
+///         [mid + side, mid - side]
+///     }
+/// }
+///
+/// # let _ =
+/// PluginDescriptor::builder()
+///     .unique_id(1234)
+///     .label("widener")
+///     .name("Stereo Widener")
+///     .frame_plugin::<Widener, 2, 2>()
+///     .control_in("Width", 0.0..2.0, DefaultValue::Value1)
+///     .build()
+/// # ;
+/// ```
+///
+/// Every ```FramePlugin``` is a ```SamplePlugin```, run by ```PerSample```.
+pub trait FramePlugin<const IN: usize, const OUT: usize>: Send + Sized {
+    /// Creates an instance running at ```sample_rate```, as ```PluginDescriptor::new```.
+    fn new(sample_rate: u64) -> Self;
+
+    /// Derives everything depending on the sample rate, as ```SamplePlugin::initialize```.
+    fn initialize(&mut self, sample_rate: u64, max_block_hint: Option<usize>) {
+        let _ = (sample_rate, max_block_hint);
+    }
+
+    /// Resets all state depending on the history of the instance, as ```Plugin::activate```.
+    fn activate(&mut self) {}
+
+    /// Processes one frame, given the values of the control inputs in the order they are
+    /// declared, and returns the output samples.
+    fn run_frame(&mut self, input: [Data; IN], controls: &[Data]) -> [Data; OUT];
+
+    /// Sets the control outputs after each block, as ```SamplePlugin::control_outputs```.
+    fn control_outputs(&mut self, outputs: &mut [Data]) {
+        let _ = outputs;
+    }
+
+    /// Whether denormals should be flushed to zero while the plugin runs, as
+    /// ```Plugin::flush_denormals```.
+    fn flush_denormals(&self) -> bool {
+        false
+    }
+
+    /// Returns the plugin as ```Stateful``` if it keeps state beyond its control values, as
+    /// ```Plugin::stateful```.
+    fn stateful(&mut self) -> Option<&mut dyn Stateful> {
+        None
+    }
+
+    /// Returns the plugin as ```Stateful``` for saving its state, as ```Plugin::state```.
+    fn state(&self) -> Option<&dyn Stateful> {
+        None
+    }
+
+    /// Indicates the instance is no longer live, as ```Plugin::deactivate```.
+    fn deactivate(&mut self) {}
+}
+
+impl<P: FramePlugin<IN, OUT>, const IN: usize, const OUT: usize> SamplePlugin<IN, OUT> for P {
+    fn initialize(&mut self, sample_rate: u64, max_block_hint: Option<usize>) {
+        FramePlugin::initialize(self, sample_rate, max_block_hint);
+    }
+
+    fn activate(&mut self) {
+        FramePlugin::activate(self);
+    }
+
+    fn process(&mut self, inputs: [Data; IN], controls: &[Data]) -> [Data; OUT] {
+        self.run_frame(inputs, controls)
+    }
+
+    fn control_outputs(&mut self, outputs: &mut [Data]) {
+        FramePlugin::control_outputs(self, outputs);
+    }
+
+    fn flush_denormals(&self) -> bool {
+        FramePlugin::flush_denormals(self)
+    }
+
+    fn stateful(&mut self) -> Option<&mut dyn Stateful> {
+        FramePlugin::stateful(self)
+    }
+
+    fn state(&self) -> Option<&dyn Stateful> {
+        FramePlugin::state(self)
+    }
+
+    fn deactivate(&mut self) {
+        FramePlugin::deactivate(self);
+    }
+}
+
+/// A ```FramePlugin``` with a stereo input and a stereo output. Implement ```FramePlugin<2, 2>```
+/// to get it.
+pub trait StereoPlugin: FramePlugin<2, 2> {}
+
+impl<P: FramePlugin<2, 2>> StereoPlugin for P {}
+
+/// A ```PluginConstructor``` which creates instances of a ```FramePlugin``` with
+/// ```FramePlugin::new```, wrapped in a ```PerSample```. See
+/// ```PluginDescriptorBuilder::frame_plugin```.
+pub fn new_frame<P, const IN: usize, const OUT: usize>(_: &PluginDescriptor, sample_rate: u64)
+                                                       -> Result<Box<dyn Plugin + Send>,
+                                                                 InstantiateError>
+    where P: FramePlugin<IN, OUT> + 'static
+{
+    Ok(Box::new(PerSample::new(P::new(sample_rate))))
+}
+
+// The connections an adapter passes to a wrapped plugin, reused for every run so that running
+// doesn't allocate once they have grown to the number of ports. They borrow from the ports passed
+// to the adapter and from its buffers, so they only hold anything during `lend`.
+struct Connections {
+    connections: Vec<PortConnection<'static>>,
+}
+
+// Clears the connections it holds when dropped, including while unwinding from a panic of the
+// wrapped plugin.
+struct Lent<'v> {
+    connections: &'v mut Vec<PortConnection<'static>>,
+}
+
+impl<'v> Drop for Lent<'v> {
+    fn drop(&mut self) {
+        self.connections.clear();
+    }
+}
+
+impl Connections {
+    fn new() -> Connections {
+        Connections::with_capacity(0)
+    }
+
+    fn with_capacity(capacity: usize) -> Connections {
+        Connections {
+            connections: Vec::with_capacity(capacity),
+        }
+    }
+
+    // Calls `run` with `connections`, which it can't keep past its return.
+    fn lend<'a, I, F, R>(&mut self, connections: I, run: F) -> R
+        where I: IntoIterator<Item = PortConnection<'a>>,
+              F: for<'c> FnOnce(&mut [PortConnection<'c>]) -> R
+    {
+        let lent = Lent { connections: &mut self.connections };
+        lent.connections.clear();
+        for connection in connections {
+            // Cleared by `lent` before `'a` ends, whether `run` returns or panics.
+            lent.connections.push(unsafe {
+                ::core::mem::transmute::<PortConnection<'a>, PortConnection<'static>>(connection)
+            });
+        }
+        run(lent.connections)
+    }
+}
+
+/// Runs a plugin over each host block in chunks of at most a fixed size, moving its control
+/// inputs a step closer to their new values before each chunk.
+///
+/// LADSPA hosts only change controls between blocks, so a plugin reading them once per block
+/// reacts to automation in steps as long as the host's blocks, which can be heard as zipper
+/// noise. Wrapped in a ```SubBlock```, it instead sees each control glide from its value in the
+/// previous block to its new one, updated every ```chunk_size``` samples:
+///
+/// ```no_run
+/// # use ladspa::{DescribedPlugin, InstantiateError, Plugin, PluginDescriptor, PortConnection};
+/// # use ladspa::adapter::SubBlock;
+/// # struct Filter;
+/// # impl Filter { fn new(_: u64) -> Filter { Filter } }
+/// # impl Plugin for Filter { fn run<'a>(&mut self, _: usize, _: &mut [PortConnection<'a>]) {} }
+/// fn new_filter(_: &PluginDescriptor, sample_rate: u64)
+///               -> Result<Box<dyn Plugin + Send>, InstantiateError> {
+///     Ok(Box::new(SubBlock::new(Filter::new(sample_rate), 32)))
+/// }
+/// ```
+///
+/// Toggled and integer controls, which have no values in between, change at the start of the
+/// block. With the ```dssi``` feature, MIDI events are passed to the chunk they fall in. The
+/// first blocks allocate, until the buffers of the adapter have grown to the number of ports.
+pub struct SubBlock<P: Plugin> {
+    plugin: P,
+    chunk_size: usize,
+    // The value of each control input at the end of the previous block, indexed by port. `None`
+    // before the first block after activation, when there is nothing to glide from.
+    previous: Vec<Option<Data>>,
+    // The value passed to each control input for the current chunk, indexed by port.
+    values: Vec<Data>,
+    connections: Connections,
+    #[cfg(feature = "dssi")]
+    events: Vec<MidiEvent>,
+}
+
+impl<P: Plugin> SubBlock<P> {
+    /// Wraps ```plugin```, to be run over chunks of at most ```chunk_size``` samples. Panics if
+    /// ```chunk_size``` is zero.
+    pub fn new(plugin: P, chunk_size: usize) -> SubBlock<P> {
+        assert!(chunk_size > 0, "the chunk size must not be zero");
+        SubBlock {
+            plugin,
+            chunk_size,
+            previous: Vec::new(),
+            values: Vec::new(),
+            connections: Connections::new(),
+            #[cfg(feature = "dssi")]
+            events: Vec::new(),
+        }
+    }
+
+    /// The largest number of samples the wrapped plugin is run over at once.
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// Returns the wrapped plugin.
+    pub fn inner(&self) -> &P {
+        &self.plugin
+    }
+
+    /// Returns the wrapped plugin.
+    pub fn inner_mut(&mut self) -> &mut P {
+        &mut self.plugin
+    }
+
+    /// Unwraps the plugin.
+    pub fn into_inner(self) -> P {
+        self.plugin
+    }
+
+    // Calls `run` for each chunk of the block, with the ports of that chunk.
+    fn run_chunks<'a, F>(&mut self,
+                         sample_count: usize,
+                         ports: &mut [PortConnection<'a>],
+                         mut run: F)
+        where F: for<'c> FnMut(&mut P, usize, usize, &mut [PortConnection<'c>])
+    {
+        self.previous.resize(ports.len(), None);
+        self.values.resize(ports.len(), 0.0);
+        // Written and lent out through a single pointer, so that writing the value of a port
+        // doesn't invalidate the references to the values of the ports before it.
+        let values = self.values.as_mut_ptr();
+        let (plugin, previous) = (&mut self.plugin, &self.previous);
+        let mut offset = 0;
+        while offset < sample_count {
+            let len = (sample_count - offset).min(self.chunk_size);
+            let progress = (offset + len) as Data / sample_count as Data;
+            let connections = ports.iter_mut().enumerate().map(|(n, port)| {
+                let data = match port.data {
+                    PortData::AudioInput(data) => {
+                        PortData::AudioInput(&data[offset..offset + len])
+                    }
+                    PortData::AudioOutput(ref mut data) => {
+                        PortData::AudioOutput(&mut data[offset..offset + len])
+                    }
+                    PortData::ControlInput(&value) => {
+                        let stepped = port.port
+                            .hint
+                            .is_some_and(|hint| hint.intersects(HINT_TOGGLED | HINT_INTEGER));
+                        let value = match previous[n] {
+                            Some(previous) if !stepped => previous + (value - previous) * progress,
+                            _ => value,
+                        };
+                        unsafe {
+                            *values.add(n) = value;
+                            PortData::ControlInput(&*values.add(n))
+                        }
+                    }
+                    PortData::ControlOutput(ref mut data) => PortData::ControlOutput(data),
+                };
+                PortConnection { port: port.port, data }
+            });
+            self.connections.lend(connections, |connections| run(plugin, offset, len, connections));
+            offset += len;
+        }
+        for (previous, port) in self.previous.iter_mut().zip(ports.iter()) {
+            if let PortData::ControlInput(&value) = port.data {
+                *previous = Some(value);
+            }
+        }
+    }
+}
+
+impl<P: Plugin> Plugin for SubBlock<P> {
+    fn initialize(&mut self, sample_rate: u64, max_block_hint: Option<usize>) {
+        let chunk_size = chunk_hint(max_block_hint, self.chunk_size);
+        self.plugin.initialize(sample_rate, Some(chunk_size));
+    }
+
+    fn activate(&mut self) {
+        for previous in &mut self.previous {
+            *previous = None;
+        }
+        self.plugin.activate();
+    }
+
+    fn run<'a>(&mut self, sample_count: usize, ports: &mut [PortConnection<'a>]) {
+        self.run_chunks(sample_count, ports, |plugin, _, len, ports| plugin.run(len, ports));
+    }
+
+    fn run_with_context<'a>(&mut self,
+                            context: Context,
+                            sample_count: usize,
+                            ports: &mut [PortConnection<'a>]) {
+        self.run_chunks(sample_count, ports, |plugin, _, len, ports| {
+            plugin.run_with_context(context, len, ports)
+        });
+    }
+
+    fn flush_denormals(&self) -> bool {
+        self.plugin.flush_denormals()
+    }
+
+    #[cfg(feature = "dssi")]
+    fn run_synth<'a>(&mut self,
+                     sample_count: usize,
+                     ports: &mut [PortConnection<'a>],
+                     events: &[MidiEvent]) {
+        let mut chunk_events = ::core::mem::take(&mut self.events);
+        self.run_chunks(sample_count, ports, |plugin, offset, len, ports| {
+            chunk_events.clear();
+            chunk_events.extend(events.iter()
+                .filter(|event| event.frame >= offset && event.frame < offset + len)
+                .map(|event| MidiEvent { frame: event.frame - offset, ..*event }));
+            plugin.run_synth(len, ports, &chunk_events);
+        });
+        self.events = chunk_events;
+    }
+
+    fn stateful(&mut self) -> Option<&mut dyn Stateful> {
+        self.plugin.stateful()
+    }
+
+    fn state(&self) -> Option<&dyn Stateful> {
+        self.plugin.state()
+    }
+
+    fn deactivate(&mut self) {
+        self.plugin.deactivate();
+    }
+}
+
+/// Runs a plugin over blocks of exactly a fixed size, whatever sizes the host runs it with.
+///
+/// Some processing only works on blocks of a given size, such as FFTs or partitioned convolution.
+/// A ```FixedBlock``` collects the audio inputs until a whole block is available, runs the wrapped
+/// plugin over it, and plays its outputs back while collecting the next one:
+///
+/// ```no_run
+/// # use ladspa::{DescribedPlugin, InstantiateError, Plugin, PluginDescriptor, PortConnection};
+/// # use ladspa::adapter::FixedBlock;
+/// # struct Convolver;
+/// # impl Convolver { fn new(_: u64) -> Convolver { Convolver } }
+/// # impl Plugin for Convolver { fn run<'a>(&mut self, _: usize, _: &mut [PortConnection<'a>]) {} }
+/// fn new_convolver(_: &PluginDescriptor, sample_rate: u64)
+///                  -> Result<Box<dyn Plugin + Send>, InstantiateError> {
+///     Ok(Box::new(FixedBlock::new(Convolver::new(sample_rate), 256)))
+/// }
+/// ```
+///
+/// This delays the audio by ```block_size``` samples. If the plugin has a port made by
+/// ```Port::latency_output```, the adapter adds that delay to the latency the wrapped plugin
+/// reports there, so that hosts can compensate for it. Control inputs are read at the start of
+/// each host block and control outputs written at its end. MIDI events are not passed on. The
+/// first block allocates the buffers of the adapter.
+pub struct FixedBlock<P: Plugin> {
+    plugin: P,
+    block_size: usize,
+    // How many samples of the current block have been collected.
+    position: usize,
+    // The data of each port as seen by the wrapped plugin, indexed by port: a block of samples for
+    // audio ports and a single value for control ports. Audio outputs are played back from the
+    // same buffers they were written to, as the samples of the next block are collected.
+    buffers: Vec<Vec<Data>>,
+    connections: Connections,
+}
+
+/// Another name for ```FixedBlock```, which is named like the other adapters of this module.
+pub type FixedBlockAdapter<P> = FixedBlock<P>;
+
+impl<P: Plugin> FixedBlock<P> {
+    /// Wraps ```plugin```, to be run over blocks of exactly ```block_size``` samples. Panics if
+    /// ```block_size``` is zero.
+    pub fn new(plugin: P, block_size: usize) -> FixedBlock<P> {
+        assert!(block_size > 0, "the block size must not be zero");
+        FixedBlock {
+            plugin,
+            block_size,
+            position: 0,
+            buffers: Vec::new(),
+            connections: Connections::new(),
+        }
+    }
+
+    /// The number of samples the wrapped plugin is run over at once.
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// The number of samples by which the adapter delays the audio, which is the block size.
+    pub fn latency(&self) -> usize {
+        self.block_size
+    }
+
+    /// Returns the wrapped plugin.
+    pub fn inner(&self) -> &P {
+        &self.plugin
+    }
+
+    /// Returns the wrapped plugin.
+    pub fn inner_mut(&mut self) -> &mut P {
+        &mut self.plugin
+    }
+
+    /// Unwraps the plugin.
+    pub fn into_inner(self) -> P {
+        self.plugin
+    }
+
+    // Passes the block to the adapter, calling `run` whenever a whole block has been collected.
+    fn run_blocks<'a, F>(&mut self,
+                         sample_count: usize,
+                         ports: &mut [PortConnection<'a>],
+                         mut run: F)
+        where F: for<'c> FnMut(&mut P, &mut [PortConnection<'c>])
+    {
+        let block_size = self.block_size;
+        self.buffers.resize_with(ports.len(), Vec::new);
+        for (buffer, port) in self.buffers.iter_mut().zip(ports.iter()) {
+            let len = match port.data {
+                PortData::AudioInput(_) | PortData::AudioOutput(_) => block_size,
+                _ => 1,
+            };
+            buffer.resize(len, 0.0);
+            if let PortData::ControlInput(&value) = port.data {
+                buffer[0] = value;
+            }
+        }
+
+        let mut offset = 0;
+        while offset < sample_count {
+            let len = (sample_count - offset).min(block_size - self.position);
+            let (from, to) = (self.position, self.position + len);
+            for (buffer, port) in self.buffers.iter_mut().zip(ports.iter_mut()) {
+                match port.data {
+                    PortData::AudioInput(data) => {
+                        buffer[from..to].copy_from_slice(&data[offset..offset + len]);
+                    }
+                    PortData::AudioOutput(ref mut data) => {
+                        data[offset..offset + len].copy_from_slice(&buffer[from..to]);
+                    }
+                    _ => {}
+                }
+            }
+            self.position = to;
+            offset += len;
+            if self.position == block_size {
+                self.run_block(ports, &mut run);
+                self.position = 0;
+            }
+        }
+
+        for (buffer, port) in self.buffers.iter().zip(ports) {
+            let latency = if port.port.is_latency_output() { block_size as Data } else { 0.0 };
+            if let PortData::ControlOutput(ref mut data) = port.data {
+                **data = buffer[0] + latency;
+            }
+        }
+    }
+
+    // Runs the wrapped plugin over the collected block.
+    fn run_block<'a, F>(&mut self, ports: &[PortConnection<'a>], run: &mut F)
+        where F: for<'c> FnMut(&mut P, &mut [PortConnection<'c>])
+    {
+        let connections = self.buffers.iter_mut().zip(ports).map(|(buffer, port)| {
+            let (ptr, len) = (buffer.as_mut_ptr(), buffer.len());
+            let data = unsafe {
+                match port.data {
+                    PortData::AudioInput(_) => {
+                        PortData::AudioInput(slice::from_raw_parts(ptr, len))
+                    }
+                    PortData::AudioOutput(_) => {
+                        PortData::AudioOutput(slice::from_raw_parts_mut(ptr, len))
+                    }
+                    PortData::ControlInput(_) => PortData::ControlInput(&*ptr),
+                    PortData::ControlOutput(_) => PortData::ControlOutput(&mut *ptr),
+                }
+            };
+            PortConnection { port: port.port, data }
+        });
+        let plugin = &mut self.plugin;
+        self.connections.lend(connections, |connections| run(plugin, connections));
+    }
+}
+
+impl<P: Plugin> Plugin for FixedBlock<P> {
+    fn initialize(&mut self, sample_rate: u64, _: Option<usize>) {
+        self.plugin.initialize(sample_rate, Some(self.block_size));
+    }
+
+    fn activate(&mut self) {
+        self.position = 0;
+        for buffer in &mut self.buffers {
+            for sample in buffer.iter_mut() {
+                *sample = 0.0;
+            }
+        }
+        self.plugin.activate();
+    }
+
+    fn run<'a>(&mut self, sample_count: usize, ports: &mut [PortConnection<'a>]) {
+        let block_size = self.block_size;
+        self.run_blocks(sample_count, ports, |plugin, ports| plugin.run(block_size, ports));
+    }
+
+    fn run_with_context<'a>(&mut self,
+                            context: Context,
+                            sample_count: usize,
+                            ports: &mut [PortConnection<'a>]) {
+        let block_size = self.block_size;
+        self.run_blocks(sample_count, ports, |plugin, ports| {
+            plugin.run_with_context(context, block_size, ports)
+        });
+    }
+
+    fn flush_denormals(&self) -> bool {
+        self.plugin.flush_denormals()
+    }
+
+    fn stateful(&mut self) -> Option<&mut dyn Stateful> {
+        self.plugin.stateful()
+    }
+
+    fn state(&self) -> Option<&dyn Stateful> {
+        self.plugin.state()
+    }
+
+    fn deactivate(&mut self) {
+        self.plugin.deactivate();
+    }
+}
+
+/// How many times faster than the host an ```Oversampled``` plugin runs.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Factor {
+    /// Twice the sample rate of the host.
+    X2,
+    /// Four times the sample rate of the host.
+    X4,
+}
+
+impl Factor {
+    /// The number of samples the wrapped plugin processes per sample of the host.
+    pub fn multiplier(self) -> usize {
+        match self {
+            Factor::X2 => 2,
+            Factor::X4 => 4,
+        }
+    }
+
+    /// The sample rate the wrapped plugin runs at when the host runs at ```sample_rate```.
+    pub fn rate(self, sample_rate: u64) -> u64 {
+        sample_rate * self.multiplier() as u64
+    }
+}
+
+// The number of host samples an `Oversampled` plugin processes at once, which bounds the size of
+// its buffers.
+const OVERSAMPLED_CHUNK: usize = 256;
+
+/// Runs a plugin at two or four times the sample rate of the host.
+///
+/// Nonlinear processing such as distortion and waveshaping creates harmonics above the Nyquist
+/// frequency, which fold back as inharmonic aliasing, as do oscillators with hard edges. Running
+/// them at a higher rate leaves room for those harmonics, which the anti-aliasing filter of the
+/// downsampler then removes. The wrapped plugin must be created for the higher rate:
+///
+/// ```no_run
+/// # use ladspa::{DescribedPlugin, InstantiateError, Plugin, PluginDescriptor, PortConnection};
+/// # use ladspa::adapter::{Factor, Oversampled};
+/// # use ladspa::dsp::resample::Quality;
+/// # struct Distortion;
+/// # impl Distortion { fn new(_: u64) -> Distortion { Distortion } }
+/// # impl Plugin for Distortion {
+/// #     fn run<'a>(&mut self, _: usize, _: &mut [PortConnection<'a>]) {}
+/// # }
+/// fn new_distortion(_: &PluginDescriptor, sample_rate: u64)
+///                   -> Result<Box<dyn Plugin + Send>, InstantiateError> {
+///     let distortion = Distortion::new(Factor::X4.rate(sample_rate));
+///     Ok(Box::new(Oversampled::new(distortion, Factor::X4, Quality::Medium)))
+/// }
+/// ```
+///
+/// Audio inputs are upsampled and audio outputs downsampled with the windowed sinc filters of
+/// ```dsp::resample```, which delays the audio by ```latency``` samples. If the plugin has a port
+/// made by ```Port::latency_output```, the adapter converts the latency the wrapped plugin reports
+/// there to samples of the host and adds its own, so that hosts can compensate for both. Control
+/// ports are passed as they are, and with the ```dssi``` feature MIDI events are passed at the
+/// frame matching theirs. The first block allocates the buffers of the adapter.
+pub struct Oversampled<P: Plugin> {
+    plugin: P,
+    factor: Factor,
+    // Cloned for each audio input and output on the first block.
+    upsampler: Resampler,
+    downsampler: Resampler,
+    // The resampler of each audio port, indexed by port.
+    resamplers: Vec<Option<Resampler>>,
+    // The data of each port as seen by the wrapped plugin, indexed by port: a chunk of samples
+    // at the higher rate for audio ports and a single value for control ports.
+    buffers: Vec<Vec<Data>>,
+    connections: Connections,
+    #[cfg(feature = "dssi")]
+    events: Vec<MidiEvent>,
+}
+
+impl<P: Plugin> Oversampled<P> {
+    /// Wraps ```plugin```, which runs at ```factor``` times the sample rate of the host, resampling
+    /// with ```quality```.
+    pub fn new(plugin: P, factor: Factor, quality: Quality) -> Oversampled<P> {
+        let multiplier = factor.multiplier() as u64;
+        Oversampled {
+            plugin,
+            factor,
+            upsampler: Resampler::new(1, multiplier, quality),
+            downsampler: Resampler::new(multiplier, 1, quality),
+            resamplers: Vec::new(),
+            buffers: Vec::new(),
+            connections: Connections::new(),
+            #[cfg(feature = "dssi")]
+            events: Vec::new(),
+        }
+    }
+
+    /// How many times faster than the host the wrapped plugin runs.
+    pub fn factor(&self) -> Factor {
+        self.factor
+    }
+
+    /// The number of samples by which the resampling filters delay the audio, at the rate of the
+    /// host.
+    pub fn latency(&self) -> usize {
+        self.upsampler.latency() + self.downsampler.latency() / self.factor.multiplier()
+    }
+
+    /// Returns the wrapped plugin.
+    pub fn inner(&self) -> &P {
+        &self.plugin
+    }
+
+    /// Returns the wrapped plugin.
+    pub fn inner_mut(&mut self) -> &mut P {
+        &mut self.plugin
+    }
+
+    /// Unwraps the plugin.
+    pub fn into_inner(self) -> P {
+        self.plugin
+    }
+
+    // Calls `run` for each chunk of the block, with the offset and length of the chunk at the
+    // rate of the host and the ports of the wrapped plugin.
+    fn run_chunks<'a, F>(&mut self,
+                         sample_count: usize,
+                         ports: &mut [PortConnection<'a>],
+                         mut run: F)
+        where F: for<'c> FnMut(&mut P, usize, usize, &mut [PortConnection<'c>])
+    {
+        let multiplier = self.factor.multiplier();
+        if self.resamplers.len() != ports.len() {
+            let (upsampler, downsampler) = (&self.upsampler, &self.downsampler);
+            self.resamplers = ports.iter()
+                .map(|port| match port.data {
+                    PortData::AudioInput(_) => Some(upsampler.clone()),
+                    PortData::AudioOutput(_) => Some(downsampler.clone()),
+                    _ => None,
+                })
+                .collect();
+            self.buffers = ports.iter()
+                .map(|port| match port.data {
+                    PortData::AudioInput(_) | PortData::AudioOutput(_) => {
+                        vec![0.0; OVERSAMPLED_CHUNK * multiplier]
+                    }
+                    _ => vec![0.0],
+                })
+                .collect();
+        }
+        for (buffer, port) in self.buffers.iter_mut().zip(ports.iter()) {
+            if let PortData::ControlInput(&value) = port.data {
+                buffer[0] = value;
+            }
+        }
+
+        let mut offset = 0;
+        while offset < sample_count {
+            let len = (sample_count - offset).min(OVERSAMPLED_CHUNK);
+            let oversampled = len * multiplier;
+            for ((buffer, resampler), port) in
+                self.buffers.iter_mut().zip(&mut self.resamplers).zip(ports.iter()) {
+                if let (PortData::AudioInput(data), Some(resampler)) = (&port.data, resampler) {
+                    resampler.process(&data[offset..offset + len], &mut buffer[..oversampled]);
+                }
+            }
+            let connections = self.buffers.iter_mut().zip(ports.iter()).map(|(buffer, port)| {
+                let ptr = buffer.as_mut_ptr();
+                let data = unsafe {
+                    match port.data {
+                        PortData::AudioInput(_) => {
+                            PortData::AudioInput(slice::from_raw_parts(ptr, oversampled))
+                        }
+                        PortData::AudioOutput(_) => {
+                            PortData::AudioOutput(slice::from_raw_parts_mut(ptr, oversampled))
+                        }
+                        PortData::ControlInput(_) => PortData::ControlInput(&*ptr),
+                        PortData::ControlOutput(_) => PortData::ControlOutput(&mut *ptr),
+                    }
+                };
+                PortConnection { port: port.port, data }
+            });
+            let plugin = &mut self.plugin;
+            self.connections.lend(connections, |connections| run(plugin, offset, len, connections));
+            for ((buffer, resampler), port) in
+                self.buffers.iter().zip(&mut self.resamplers).zip(ports.iter_mut()) {
+                if let (PortData::AudioOutput(ref mut data), Some(resampler)) =
+                    (&mut port.data, resampler) {
+                    resampler.process(&buffer[..oversampled], &mut data[offset..offset + len]);
+                }
+            }
+            offset += len;
+        }
+
+        let latency = self.latency() as Data;
+        for (buffer, port) in self.buffers.iter().zip(ports) {
+            let is_latency = port.port.is_latency_output();
+            if let PortData::ControlOutput(ref mut data) = port.data {
+                **data = if is_latency {
+                    buffer[0] / multiplier as Data + latency
+                } else {
+                    buffer[0]
+                };
+            }
+        }
+    }
+}
+
+impl<P: Plugin> Plugin for Oversampled<P> {
+    fn initialize(&mut self, sample_rate: u64, max_block_hint: Option<usize>) {
+        let chunk_size = chunk_hint(max_block_hint, OVERSAMPLED_CHUNK) * self.factor.multiplier();
+        self.plugin.initialize(self.factor.rate(sample_rate), Some(chunk_size));
+    }
+
+    fn activate(&mut self) {
+        for resampler in self.resamplers.iter_mut().flatten() {
+            resampler.reset();
+        }
+        self.plugin.activate();
+    }
+
+    fn run<'a>(&mut self, sample_count: usize, ports: &mut [PortConnection<'a>]) {
+        let multiplier = self.factor.multiplier();
+        self.run_chunks(sample_count, ports, |plugin, _, len, ports| {
+            plugin.run(len * multiplier, ports)
+        });
+    }
+
+    fn run_with_context<'a>(&mut self,
+                            context: Context,
+                            sample_count: usize,
+                            ports: &mut [PortConnection<'a>]) {
+        let multiplier = self.factor.multiplier();
+        self.run_chunks(sample_count, ports, |plugin, _, len, ports| {
+            plugin.run_with_context(context, len * multiplier, ports)
+        });
+    }
+
+    fn flush_denormals(&self) -> bool {
+        self.plugin.flush_denormals()
+    }
+
+    #[cfg(feature = "dssi")]
+    fn run_synth<'a>(&mut self,
+                     sample_count: usize,
+                     ports: &mut [PortConnection<'a>],
+                     events: &[MidiEvent]) {
+        let multiplier = self.factor.multiplier();
+        let mut chunk_events = ::core::mem::take(&mut self.events);
+        self.run_chunks(sample_count, ports, |plugin, offset, len, ports| {
+            chunk_events.clear();
+            chunk_events.extend(events.iter()
+                .filter(|event| event.frame >= offset && event.frame < offset + len)
+                .map(|event| MidiEvent { frame: (event.frame - offset) * multiplier, ..*event }));
+            plugin.run_synth(len * multiplier, ports, &chunk_events);
+        });
+        self.events = chunk_events;
+    }
+
+    fn stateful(&mut self) -> Option<&mut dyn Stateful> {
+        self.plugin.stateful()
+    }
+
+    fn state(&self) -> Option<&dyn Stateful> {
+        self.plugin.state()
+    }
+
+    fn deactivate(&mut self) {
+        self.plugin.deactivate();
+    }
+}
+
+// The connections passed to a wrapped plugin whose control inputs get values of the adapter's
+// choosing, for whole blocks. Reused for every block so that running doesn't allocate once the
+// buffers have grown to the number of ports.
+struct Rewired {
+    // The values passed to the control inputs, indexed by port.
+    values: Vec<Data>,
+    connections: Connections,
+}
+
+impl Rewired {
+    fn new() -> Rewired {
+        Rewired {
+            values: Vec::new(),
+            connections: Connections::new(),
+        }
+    }
+
+    // Calls `run` with `ports`, except that each control input gets the value `control` returns
+    // given its index and the value set by the host.
+    fn run<'a, P, G, F>(&mut self,
+                        plugin: &mut P,
+                        ports: &mut [PortConnection<'a>],
+                        mut control: G,
+                        run: F)
+        where G: FnMut(usize, Data) -> Data,
+              F: for<'c> FnOnce(&mut P, &mut [PortConnection<'c>])
+    {
+        self.values.resize(ports.len(), 0.0);
+        // Written and lent out through a single pointer, as in `SubBlock::run_chunks`.
+        let values = self.values.as_mut_ptr();
+        let connections = ports.iter_mut().enumerate().map(|(n, port)| {
+            let data = match port.data {
+                PortData::AudioInput(data) => PortData::AudioInput(data),
+                PortData::AudioOutput(ref mut data) => PortData::AudioOutput(data),
+                PortData::ControlInput(&host) => {
+                    unsafe {
+                        *values.add(n) = control(n, host);
+                        PortData::ControlInput(&*values.add(n))
+                    }
+                }
+                PortData::ControlOutput(ref mut data) => PortData::ControlOutput(data),
+            };
+            PortConnection { port: port.port, data }
+        });
+        self.connections.lend(connections, |connections| run(plugin, connections));
+    }
+}
+
+/// How a ```SoftTakeover``` brings a control to a value far from the one the plugin is using.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Takeover {
+    /// Glide to the new value at a constant rate, covering the jump in this many seconds. If the
+    /// value moves again during the glide, it carries on towards the latest one at the same rate.
+    Ramp(Data),
+    /// Keep the value the plugin is using until the incoming one comes back close to it or
+    /// crosses it, as a knob turned by hand eventually does.
+    Pickup,
+}
+
+// The state of a control input driven by a `SoftTakeover`, in the normalized domain.
+#[derive(Copy, Clone)]
+struct TakeoverState {
+    // The value the plugin is using.
+    current: Data,
+    // The value set by the host in the previous block.
+    previous: Data,
+    // How much a ramp moves by per sample.
+    rate: Data,
+    // Whether the plugin is following the host, rather than ramping or waiting for pickup.
+    settled: bool,
+}
+
+/// Keeps chosen control inputs of a plugin from jumping when they are set far from the value the
+/// plugin is using, which can be heard as clicks or thumps.
+///
+/// Jumps happen when a host loads a session or a preset, or when a MIDI controller whose knob is
+/// out of sync with the plugin is first touched. Each port is opted in with the ```Takeover```
+/// which suits where its jumps come from: ```Ramp``` for sessions and automation, since the new
+/// value is reached in any case, and ```Pickup``` for hardware controllers.
+///
+/// ```no_run
+/// # use ladspa::{DescribedPlugin, InstantiateError, Plugin, PluginDescriptor, PortConnection};
+/// # use ladspa::adapter::{SoftTakeover, Takeover};
+/// # struct Filter;
+/// # impl Filter { fn new(_: u64) -> Filter { Filter } }
+/// # impl Plugin for Filter { fn run<'a>(&mut self, _: usize, _: &mut [PortConnection<'a>]) {} }
+/// fn new_filter(_: &PluginDescriptor, sample_rate: u64)
+///               -> Result<Box<dyn Plugin + Send>, InstantiateError> {
+///     Ok(Box::new(SoftTakeover::new(Filter::new(sample_rate), sample_rate)
+///         .port("Cutoff (Hz)", Takeover::Ramp(0.2))
+///         .port("Resonance", Takeover::Pickup)))
+/// }
+/// ```
+///
+/// Distances are measured on the range of the port normalized to 0 to 1, as given by
+/// ```Port::range```, and a change is a jump when it exceeds the threshold, 0.05 by default. The
+/// first block after activation takes the values of the host as they are. Values are updated once
+/// per block; wrap the adapter in a ```SubBlock``` to have ramps advance every few samples, and
+/// wrap a ```MidiControlled``` plugin in it, rather than the other way round, to soften changes
+/// from controllers. Ports are found by name on the first block, which allocates; names which are
+/// not control inputs of the plugin are ignored.
+pub struct SoftTakeover<P: Plugin> {
+    plugin: P,
+    sample_rate: u64,
+    threshold: Data,
+    names: Vec<(&'static str, Takeover)>,
+    // The takeover of each port, indexed by port. Empty until the first block.
+    modes: Vec<Option<Takeover>>,
+    // The range of each port, indexed by port. Empty until the first block.
+    ranges: Vec<ControlRange>,
+    // The state of each port with a takeover, indexed by port. `None` before the first block
+    // after activation.
+    states: Vec<Option<TakeoverState>>,
+    rewired: Rewired,
+}
+
+impl<P: Plugin> SoftTakeover<P> {
+    /// Wraps ```plugin```, running at ```sample_rate```, without softening any port yet.
+    pub fn new(plugin: P, sample_rate: u64) -> SoftTakeover<P> {
+        SoftTakeover {
+            plugin,
+            sample_rate,
+            threshold: 0.05,
+            names: Vec::new(),
+            modes: Vec::new(),
+            ranges: Vec::new(),
+            states: Vec::new(),
+            rewired: Rewired::new(),
+        }
+    }
+
+    /// Softens the jumps of the control input named ```name``` as ```takeover``` says.
+    pub fn port(mut self, name: &'static str, takeover: Takeover) -> SoftTakeover<P> {
+        self.names.push((name, takeover));
+        self
+    }
+
+    /// Sets how far, on the normalized range of a port, its value may move from one block to the
+    /// next before the change counts as a jump.
+    pub fn threshold(mut self, threshold: Data) -> SoftTakeover<P> {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Returns the wrapped plugin.
+    pub fn inner(&self) -> &P {
+        &self.plugin
+    }
+
+    /// Returns the wrapped plugin.
+    pub fn inner_mut(&mut self) -> &mut P {
+        &mut self.plugin
+    }
+
+    /// Unwraps the plugin.
+    pub fn into_inner(self) -> P {
+        self.plugin
+    }
+
+    // Finds the ports to soften, once the ports are known.
+    fn resolve(&mut self, ports: &[PortConnection]) {
+        if self.modes.len() == ports.len() {
+            return;
+        }
+        self.modes = ports.iter()
+            .map(|port| match port.data {
+                PortData::ControlInput(_) => {
+                    self.names
+                        .iter()
+                        .find(|&&(name, _)| name == port.port.name)
+                        .map(|&(_, takeover)| takeover)
+                }
+                _ => None,
+            })
+            .collect();
+        self.ranges = ports.iter().map(|port| port.port.range(self.sample_rate)).collect();
+        self.states = ports.iter().map(|_| None).collect();
+    }
+
+    // Calls `run` with the ports, the softened control inputs getting the values to use.
+    fn run_softened<'a, F>(&mut self,
+                           sample_count: usize,
+                           ports: &mut [PortConnection<'a>],
+                           run: F)
+        where F: for<'c> FnOnce(&mut P, &mut [PortConnection<'c>])
+    {
+        self.resolve(ports);
+        let (modes, ranges, states) = (&self.modes, &self.ranges, &mut self.states);
+        let (threshold, sample_rate) = (self.threshold, self.sample_rate);
+        self.rewired.run(&mut self.plugin,
+                         ports,
+                         |n, host| match modes[n] {
+                             Some(takeover) => {
+                                 let samples_per_second = sample_rate as Data;
+                                 soften(takeover,
+                                        &mut states[n],
+                                        &ranges[n],
+                                        threshold,
+                                        samples_per_second,
+                                        sample_count,
+                                        host)
+                             }
+                             None => host,
+                         },
+                         run);
+    }
+}
+
+// Returns the value a softened port is to have for a block of `sample_count` samples, the host
+// having set it to `host`.
+fn soften(takeover: Takeover,
+          state: &mut Option<TakeoverState>,
+          range: &ControlRange,
+          threshold: Data,
+          samples_per_second: Data,
+          sample_count: usize,
+          host: Data)
+          -> Data {
+    let target = range.to_normalized(host);
+    let state = match *state {
+        Some(ref mut state) => state,
+        None => {
+            *state = Some(TakeoverState {
+                current: target,
+                previous: target,
+                rate: 0.0,
+                settled: true,
+            });
+            return host;
+        }
+    };
+    let settle = |state: &mut TakeoverState| {
+        state.current = target;
+        state.previous = target;
+        state.settled = true;
+        host
+    };
+    if state.settled && (target - state.current).abs() <= threshold {
+        return settle(state);
+    }
+    match takeover {
+        Takeover::Ramp(seconds) => {
+            if state.settled {
+                state.settled = false;
+                state.rate = (target - state.current).abs() /
+                             (seconds * samples_per_second).max(1.0);
+            }
+            let step = state.rate * sample_count as Data;
+            if (target - state.current).abs() <= step {
+                return settle(state);
+            }
+            state.current += if target > state.current { step } else { -step };
+        }
+        Takeover::Pickup => {
+            // Waits from the block the jump happens in, and picks up once the host comes within
+            // the threshold or moves past the value in use.
+            let crossed = (state.previous - state.current) * (target - state.current) <= 0.0;
+            if !state.settled && ((target - state.current).abs() <= threshold || crossed) {
+                return settle(state);
+            }
+            state.settled = false;
+        }
+    }
+    state.previous = target;
+    range.from_normalized(state.current)
+}
+
+impl<P: Plugin> Plugin for SoftTakeover<P> {
+    fn initialize(&mut self, sample_rate: u64, max_block_hint: Option<usize>) {
+        self.sample_rate = sample_rate;
+        // The ranges depend on the sample rate, so are found again.
+        self.modes.clear();
+        self.plugin.initialize(sample_rate, max_block_hint);
+    }
+
+    fn activate(&mut self) {
+        for state in &mut self.states {
+            *state = None;
+        }
+        self.plugin.activate();
+    }
+
+    fn run<'a>(&mut self, sample_count: usize, ports: &mut [PortConnection<'a>]) {
+        self.run_softened(sample_count, ports, |plugin, ports| plugin.run(sample_count, ports));
+    }
+
+    fn run_with_context<'a>(&mut self,
+                            context: Context,
+                            sample_count: usize,
+                            ports: &mut [PortConnection<'a>]) {
+        self.run_softened(sample_count, ports, |plugin, ports| {
+            plugin.run_with_context(context, sample_count, ports)
+        });
+    }
+
+    fn flush_denormals(&self) -> bool {
+        self.plugin.flush_denormals()
+    }
+
+    #[cfg(feature = "dssi")]
+    fn run_synth<'a>(&mut self,
+                     sample_count: usize,
+                     ports: &mut [PortConnection<'a>],
+                     events: &[MidiEvent]) {
+        self.run_softened(sample_count, ports, |plugin, ports| {
+            plugin.run_synth(sample_count, ports, events)
+        });
+    }
+
+    fn stateful(&mut self) -> Option<&mut dyn Stateful> {
+        self.plugin.stateful()
+    }
+
+    fn state(&self) -> Option<&dyn Stateful> {
+        self.plugin.state()
+    }
+
+    fn deactivate(&mut self) {
+        self.plugin.deactivate();
+    }
+}
+
+/// Crossfades between the outputs of a plugin before and after chosen control inputs change, for
+/// controls which switch its structure rather than set a value which can be smoothed, such as the
+/// topology of a filter or the length of a delay.
+///
+/// When a watched control changes between blocks, the adapter keeps a clone of the plugin running
+/// with the old values alongside it for the length of a ```util::Crossfader``` fade, and mixes its
+/// audio outputs out as those of the plugin with the new values are mixed in:
+///
+/// ```no_run
+/// # use ladspa::{DescribedPlugin, InstantiateError, Plugin, PluginDescriptor, PortConnection};
+/// # use ladspa::adapter::Crossfaded;
+/// # #[derive(Clone)]
+/// # struct Filter;
+/// # impl Filter { fn new(_: u64) -> Filter { Filter } }
+/// # impl Plugin for Filter { fn run<'a>(&mut self, _: usize, _: &mut [PortConnection<'a>]) {} }
+/// fn new_filter(_: &PluginDescriptor, sample_rate: u64)
+///               -> Result<Box<dyn Plugin + Send>, InstantiateError> {
+///     Ok(Box::new(Crossfaded::new(Filter::new(sample_rate), 0.02, sample_rate).port("Mode")))
+/// }
+/// ```
+///
+/// Changes during a fade carry on with the fade, the plugin being faded in taking the latest
+/// values. Cloning a plugin usually allocates, as do the first blocks, until the buffers of the
+/// adapter have grown to the number of ports; implement ```Clone::clone_from``` to reuse the
+/// storage of the faded out plugin. Control outputs come from the plugin being faded in, and with
+/// the ```dssi``` feature both plugins receive the MIDI events. Ports are found by name on the
+/// first block; names which are not control inputs of the plugin are ignored.
+pub struct Crossfaded<P: Plugin + Clone> {
+    plugin: P,
+    // The plugin with the values from before the change, while a fade is in progress.
+    old: Option<P>,
+    // The length of a fade, in seconds, to rebuild the fader when the sample rate changes.
+    time: f32,
+    fader: Crossfader,
+    names: Vec<&'static str>,
+    // Whether each port is watched, indexed by port. Empty until the first block.
+    watched: Vec<bool>,
+    // The value of each control input in the previous block, indexed by port. `None` before the
+    // first block after activation.
+    previous: Vec<Option<Data>>,
+    // The value of each watched control before the change being faded out, indexed by port.
+    faded: Vec<Data>,
+    // The data of each output of the faded out plugin, indexed by port: a block of samples for
+    // audio outputs and a single value for control outputs.
+    buffers: Vec<Vec<Data>>,
+    connections: Connections,
+}
+
+impl<P: Plugin + Clone> Crossfaded<P> {
+    /// Wraps ```plugin```, running at ```sample_rate```, to crossfade for ```time``` seconds when
+    /// a watched control changes. No control is watched yet.
+    pub fn new(plugin: P, time: f32, sample_rate: u64) -> Crossfaded<P> {
+        Crossfaded {
+            plugin,
+            old: None,
+            time,
+            fader: Crossfader::new(time, sample_rate),
+            names: Vec::new(),
+            watched: Vec::new(),
+            previous: Vec::new(),
+            faded: Vec::new(),
+            buffers: Vec::new(),
+            connections: Connections::new(),
+        }
+    }
+
+    /// Crossfades when the control input named ```name``` changes.
+    pub fn port(mut self, name: &'static str) -> Crossfaded<P> {
+        self.names.push(name);
+        self
+    }
+
+    /// Returns the wrapped plugin, with the latest values.
+    pub fn inner(&self) -> &P {
+        &self.plugin
+    }
+
+    /// Returns the wrapped plugin, with the latest values.
+    pub fn inner_mut(&mut self) -> &mut P {
+        &mut self.plugin
+    }
+
+    /// Unwraps the plugin with the latest values.
+    pub fn into_inner(self) -> P {
+        self.plugin
+    }
+
+    // Starts a fade if a watched control has changed since the previous block.
+    fn detect_changes(&mut self, ports: &[PortConnection]) {
+        if self.watched.len() != ports.len() {
+            let names = &self.names;
+            self.watched = ports.iter()
+                .map(|port| {
+                    matches!(port.data, PortData::ControlInput(_)) &&
+                    names.contains(&port.port.name)
+                })
+                .collect();
+            self.previous = ports.iter().map(|_| None).collect();
+            self.faded = ports.iter().map(|_| 0.0).collect();
+        }
+        let mut changed = false;
+        for ((port, &watched), &previous) in ports.iter().zip(&self.watched).zip(&self.previous) {
+            if let (true, PortData::ControlInput(&value), Some(previous)) =
+                (watched, &port.data, previous) {
+                changed |= self.fader.on_param_change(previous, value);
+            }
+        }
+        if changed {
+            for (faded, &previous) in self.faded.iter_mut().zip(&self.previous) {
+                *faded = previous.unwrap_or(0.0);
+            }
+            match self.old {
+                Some(ref mut old) => old.clone_from(&self.plugin),
+                None => self.old = Some(self.plugin.clone()),
+            }
+        }
+    }
+
+    // Runs the plugin with the new values through `run`, and, during a fade, the one with the old
+    // values into `buffers` before mixing the two.
+    fn run_faded<'a, F>(&mut self,
+                        sample_count: usize,
+                        ports: &mut [PortConnection<'a>],
+                        mut run: F)
+        where F: for<'c> FnMut(&mut P, &mut [PortConnection<'c>])
+    {
+        self.detect_changes(ports);
+        if self.fader.is_fading() {
+            if let Some(ref mut old) = self.old {
+                self.buffers.resize_with(ports.len(), Vec::new);
+                let ports = self.buffers.iter_mut().zip(ports.iter()).zip(&self.faded);
+                let connections = ports.zip(&self.watched).map(|(((buffer, port), faded),
+                                                                  &watched)| {
+                    buffer.resize(match port.data {
+                        PortData::AudioOutput(_) => sample_count,
+                        _ => 1,
+                    }, 0.0);
+                    let data = unsafe {
+                        match port.data {
+                            PortData::AudioInput(data) => PortData::AudioInput(data),
+                            PortData::AudioOutput(_) => {
+                                let data = slice::from_raw_parts_mut(buffer.as_mut_ptr(),
+                                                                     sample_count);
+                                PortData::AudioOutput(data)
+                            }
+                            PortData::ControlInput(value) => {
+                                PortData::ControlInput(if watched { faded } else { value })
+                            }
+                            PortData::ControlOutput(_) => {
+                                PortData::ControlOutput(&mut *buffer.as_mut_ptr())
+                            }
+                        }
+                    };
+                    PortConnection { port: port.port, data }
+                });
+                self.connections.lend(connections, |connections| run(old, connections));
+            }
+        }
+
+        run(&mut self.plugin, ports);
+
+        if self.fader.is_fading() && self.old.is_some() {
+            for (buffer, port) in self.buffers.iter().zip(ports.iter_mut()) {
+                if let PortData::AudioOutput(ref mut data) = port.data {
+                    let samples = data[..sample_count].iter_mut().zip(buffer);
+                    for (offset, (new, &old)) in samples.enumerate() {
+                        *new = old + (*new - old) * self.fader.gain(offset);
+                    }
+                }
+            }
+            self.fader.advance(sample_count);
+        }
+        if !self.fader.is_fading() {
+            self.old = None;
+        }
+        for (previous, port) in self.previous.iter_mut().zip(ports.iter()) {
+            if let PortData::ControlInput(&value) = port.data {
+                *previous = Some(value);
+            }
+        }
+    }
+}
+
+impl<P: Plugin + Clone> Plugin for Crossfaded<P> {
+    fn initialize(&mut self, sample_rate: u64, max_block_hint: Option<usize>) {
+        self.fader = Crossfader::new(self.time, sample_rate);
+        self.old = None;
+        self.plugin.initialize(sample_rate, max_block_hint);
+    }
+
+    fn activate(&mut self) {
+        self.old = None;
+        self.fader.reset();
+        for previous in &mut self.previous {
+            *previous = None;
+        }
+        self.plugin.activate();
+    }
+
+    fn run<'a>(&mut self, sample_count: usize, ports: &mut [PortConnection<'a>]) {
+        self.run_faded(sample_count, ports, |plugin, ports| plugin.run(sample_count, ports));
+    }
+
+    fn run_with_context<'a>(&mut self,
+                            context: Context,
+                            sample_count: usize,
+                            ports: &mut [PortConnection<'a>]) {
+        self.run_faded(sample_count, ports, |plugin, ports| {
+            plugin.run_with_context(context, sample_count, ports)
+        });
+    }
+
+    fn flush_denormals(&self) -> bool {
+        self.plugin.flush_denormals()
+    }
+
+    #[cfg(feature = "dssi")]
+    fn run_synth<'a>(&mut self,
+                     sample_count: usize,
+                     ports: &mut [PortConnection<'a>],
+                     events: &[MidiEvent]) {
+        self.run_faded(sample_count, ports, |plugin, ports| {
+            plugin.run_synth(sample_count, ports, events)
+        });
+    }
+
+    fn stateful(&mut self) -> Option<&mut dyn Stateful> {
+        self.plugin.stateful()
+    }
+
+    fn state(&self) -> Option<&dyn Stateful> {
+        self.plugin.state()
+    }
+
+    fn deactivate(&mut self) {
+        self.plugin.deactivate();
+    }
+}
+
+// Combines the states of the plugins wrapped by an adapter, each prefixed with its length. Plugins
+// which aren't `Stateful` get an empty state.
+fn save_parts<'s, I>(parts: I) -> Vec<u8>
+    where I: IntoIterator<Item = Option<&'s dyn Stateful>>
+{
+    let mut state = Vec::new();
+    for part in parts {
+        let saved = part.map_or_else(Vec::new, |part| part.save());
+        state.extend_from_slice(&(saved.len() as u32).to_le_bytes());
+        state.extend_from_slice(&saved);
+    }
+    state
+}
+
+// Restores the states combined by `save_parts`, ignoring those which are cut short.
+fn restore_parts<'s, I>(mut state: &[u8], parts: I)
+    where I: IntoIterator<Item = Option<&'s mut dyn Stateful>>
+{
+    for part in parts {
+        if state.len() < 4 {
+            return;
+        }
+        let (len, rest) = state.split_at(4);
+        let len = u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize;
+        if rest.len() < len {
+            return;
+        }
+        let (saved, rest) = rest.split_at(len);
+        if let Some(part) = part {
+            part.restore(saved);
+        }
+        state = rest;
+    }
+}
+
+/// Runs one instance of a mono plugin per channel, as a single plugin with a port per channel for
+/// each of its audio ports and a single set of control ports shared by all channels.
+///
+/// Hosts such as Ardour handle a true stereo or 5.1 plugin better than as many mono instances,
+/// which they have to gang by hand. ```multichannel_descriptor``` generates the descriptor of
+/// such a plugin from that of the mono plugin, with instances created as a ```Multichannel```:
+///
+/// ```no_run
+/// # use ladspa::{DescribedPlugin, InstantiateError, Plugin, PluginDescriptor, PortConnection};
+/// # use ladspa::adapter::multichannel_descriptor;
+/// # struct Filter;
+/// # impl DescribedPlugin for Filter {
+/// #     fn descriptor() -> PluginDescriptor { unimplemented!() }
+/// # }
+/// struct StereoFilter;
+///
+/// impl DescribedPlugin for StereoFilter {
+///     fn descriptor() -> PluginDescriptor {
+///         multichannel_descriptor::<Filter, 2>(4711, "filter_stereo", "Stereo Filter")
+///     }
+/// }
+/// ```
+///
+/// The ports must be laid out as ```multichannel_descriptor``` lays them out: the ports of the
+/// mono plugin in order, each audio port repeated once per channel. Each instance sees the ports
+/// of the mono plugin, with the buffers of its channel. Control outputs are those of the first
+/// channel. The state of ```Stateful``` instances is saved as that of each channel in turn,
+/// prefixed with its length. Nothing is allocated once the adapter is created, save for the
+/// state.
+pub struct Multichannel<P: Plugin> {
+    instances: Vec<P>,
+    // The ports of the mono plugin.
+    mono: Vec<Port>,
+    connections: Connections,
+}
+
+impl<P: Plugin> Multichannel<P> {
+    /// Runs ```instances``` of a mono plugin whose ports are ```ports```, one per channel.
+    pub fn new(instances: Vec<P>, ports: &[Port]) -> Multichannel<P> {
+        Multichannel {
+            connections: Connections::with_capacity(ports.len()),
+            instances,
+            mono: ports.to_vec(),
+        }
+    }
+
+    /// The number of channels, which is the number of instances.
+    pub fn channels(&self) -> usize {
+        self.instances.len()
+    }
+
+    /// Returns the instances, one per channel.
+    pub fn instances(&self) -> &[P] {
+        &self.instances
+    }
+
+    /// Returns the instances, one per channel.
+    pub fn instances_mut(&mut self) -> &mut [P] {
+        &mut self.instances
+    }
+
+    /// Unwraps the instances.
+    pub fn into_instances(self) -> Vec<P> {
+        self.instances
+    }
+
+    // Calls `run` for each instance, with the ports of its channel.
+    fn run_channels<'a, F>(&mut self, ports: &mut [PortConnection<'a>], mut run: F)
+        where F: for<'c> FnMut(&mut P, &mut [PortConnection<'c>])
+    {
+        let channels = self.instances.len();
+        // Backwards, so that the first channel writes the control outputs last.
+        for channel in (0..channels).rev() {
+            // The ports of the adapter not yet matched with a port of the instance.
+            let mut rest = &mut *ports;
+            let connections = self.mono.iter().map(move |port| {
+                let (count, index) = match port.desc {
+                    PortDescriptor::AudioInput | PortDescriptor::AudioOutput => (channels, channel),
+                    _ => (1, 0),
+                };
+                let (taken, tail) = ::core::mem::take(&mut rest).split_at_mut(count);
+                rest = tail;
+                let data = match taken[index].data {
+                    PortData::AudioInput(data) => PortData::AudioInput(data),
+                    PortData::AudioOutput(ref mut data) => PortData::AudioOutput(data),
+                    PortData::ControlInput(data) => PortData::ControlInput(data),
+                    PortData::ControlOutput(ref mut data) => PortData::ControlOutput(data),
+                };
+                PortConnection { port: *port, data }
+            });
+            let instance = &mut self.instances[channel];
+            self.connections.lend(connections, |connections| run(instance, connections));
+        }
+    }
+}
+
+impl<P: Plugin> Plugin for Multichannel<P> {
+    fn initialize(&mut self, sample_rate: u64, max_block_hint: Option<usize>) {
+        for instance in &mut self.instances {
+            instance.initialize(sample_rate, max_block_hint);
+        }
+    }
+
+    fn activate(&mut self) {
+        for instance in &mut self.instances {
+            instance.activate();
+        }
+    }
+
+    fn run<'a>(&mut self, sample_count: usize, ports: &mut [PortConnection<'a>]) {
+        self.run_channels(ports, |instance, ports| instance.run(sample_count, ports));
+    }
+
+    fn run_with_context<'a>(&mut self,
+                            context: Context,
+                            sample_count: usize,
+                            ports: &mut [PortConnection<'a>]) {
+        self.run_channels(ports, |instance, ports| {
+            instance.run_with_context(context, sample_count, ports)
+        });
+    }
+
+    fn flush_denormals(&self) -> bool {
+        self.instances.iter().any(|instance| instance.flush_denormals())
+    }
+
+    #[cfg(feature = "dssi")]
+    fn run_synth<'a>(&mut self,
+                     sample_count: usize,
+                     ports: &mut [PortConnection<'a>],
+                     events: &[MidiEvent]) {
+        self.run_channels(ports, |instance, ports| {
+            instance.run_synth(sample_count, ports, events)
+        });
+    }
+
+    fn stateful(&mut self) -> Option<&mut dyn Stateful> {
+        if self.instances.iter_mut().all(|instance| instance.stateful().is_none()) {
+            return None;
+        }
+        Some(self)
+    }
+
+    fn state(&self) -> Option<&dyn Stateful> {
+        if self.instances.iter().all(|instance| instance.state().is_none()) {
+            return None;
+        }
+        Some(self)
+    }
+
+    fn deactivate(&mut self) {
+        for instance in &mut self.instances {
+            instance.deactivate();
+        }
+    }
+}
+
+/// The states of the channels, each prefixed with its length.
+impl<P: Plugin> Stateful for Multichannel<P> {
+    fn save(&self) -> Vec<u8> {
+        save_parts(self.instances.iter().map(Plugin::state))
+    }
+
+    fn restore(&mut self, state: &[u8]) {
+        restore_parts(state, self.instances.iter_mut().map(Plugin::stateful));
+    }
+}
+
+/// Returns the descriptor of a plugin running ```N``` instances of the mono plugin ```D``` as a
+/// ```Multichannel```, under the given unique ID, label and name. The other properties are those
+/// of ```D```: in particular, it is hard real-time capable if ```D``` is, as the adapter doesn't
+/// allocate once created.
+///
+/// Each audio port of ```D``` becomes a group of ```N``` ports named after it and the channel:
+/// "L" and "R" for two channels, those of 5.1 surround for six and numbers from 1 otherwise.
+/// Control ports are declared once, and presets are kept.
+pub fn multichannel_descriptor<D, const N: usize>(unique_id: u64,
+                                                  label: &'static str,
+                                                  name: &'static str)
+                                                  -> PluginDescriptor
+    where D: DescribedPlugin
+{
+    let mono = D::descriptor();
+    let mut ports = Vec::new();
+    // The index of each port of the mono plugin in the combined descriptor.
+    let mut indices = Vec::with_capacity(mono.ports.len());
+    for port in &mono.ports {
+        indices.push(ports.len());
+        match port.desc {
+            PortDescriptor::AudioInput | PortDescriptor::AudioOutput => {
+                let group = PortGroup {
+                    name: port.name,
+                    desc: port.desc,
+                    channels: channel_names(N),
+                };
+                ports.extend(group.ports());
+            }
+            _ => ports.push(*port),
+        }
+    }
+    let presets = mono.presets
+        .iter()
+        .map(|preset| {
+            Preset {
+                name: preset.name,
+                values: preset.values.iter().map(|&(port, value)| (indices[port], value)).collect(),
+            }
+        })
+        .collect();
+    PluginDescriptor {
+        unique_id,
+        label,
+        properties: mono.properties,
+        name,
+        maker: mono.maker,
+        copyright: mono.copyright,
+        category: mono.category,
+        ports,
+        presets,
+        new: new_multichannel::<D, N>,
+    }
+}
+
+fn new_multichannel<D, const N: usize>(_: &PluginDescriptor, sample_rate: u64)
+                                       -> Result<Box<dyn Plugin + Send>, InstantiateError>
+    where D: DescribedPlugin
+{
+    let mono = D::descriptor();
+    let instances = (0..N)
+        .map(|_| (mono.new)(&mono, sample_rate))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Box::new(Multichannel::new(instances, &mono.ports)))
+}
+
+/// Mixes the input of a plugin back into its output, as set by a ```Port::dry_wet``` control
+/// declared as its last port.
+///
+/// The blend is equal power, which keeps the loudness steady across the range for the
+/// uncorrelated signals most effects produce, and glides across each block as the control
+/// changes. ```dry_wet_descriptor``` adds the control to the descriptor of a plugin and creates
+/// its instances wrapped in the adapter:
+///
+/// ```no_run
+/// # use ladspa::{DescribedPlugin, InstantiateError, Plugin, PluginDescriptor, PortConnection};
+/// # use ladspa::adapter::dry_wet_descriptor;
+/// # struct Reverb;
+/// # impl DescribedPlugin for Reverb {
+/// #     fn descriptor() -> PluginDescriptor { unimplemented!() }
+/// # }
+/// struct MixedReverb;
+///
+/// impl DescribedPlugin for MixedReverb {
+///     fn descriptor() -> PluginDescriptor {
+///         dry_wet_descriptor::<Reverb>()
+///     }
+/// }
+/// ```
+///
+/// The wrapped plugin sees the other ports. The ```n```th audio output is mixed with the ```n```th
+/// audio input, wrapping around to the first input when there are more outputs. If the plugin has
+/// a ```Port::latency_output```, the input is delayed by the latency it reports there before being
+/// mixed, so that both paths stay aligned. The buffers of the adapter grow with the blocks and the
+/// latency, so the first blocks allocate.
+pub struct WithDryWet<P: Plugin> {
+    plugin: P,
+    mix: ControlRamp,
+    // The audio inputs of the block, copied before the plugin may overwrite them, then delayed by
+    // its latency. Indexed like the audio inputs.
+    dry: Vec<Vec<Data>>,
+    delays: Vec<DelayLine>,
+}
+
+impl<P: Plugin> WithDryWet<P> {
+    /// Wraps ```plugin```.
+    pub fn new(plugin: P) -> WithDryWet<P> {
+        WithDryWet {
+            plugin,
+            mix: ControlRamp::new(),
+            dry: Vec::new(),
+            delays: Vec::new(),
+        }
+    }
+
+    /// Returns the wrapped plugin.
+    pub fn inner(&self) -> &P {
+        &self.plugin
+    }
+
+    /// Returns the wrapped plugin.
+    pub fn inner_mut(&mut self) -> &mut P {
+        &mut self.plugin
+    }
+
+    /// Unwraps the plugin.
+    pub fn into_inner(self) -> P {
+        self.plugin
+    }
+
+    // Calls `run` with the ports of the wrapped plugin, then mixes its input into its output.
+    fn run_mixed<'a, F>(&mut self, sample_count: usize, ports: &mut [PortConnection<'a>], run: F)
+        where F: for<'c> FnOnce(&mut P, &mut [PortConnection<'c>])
+    {
+        let (mix, ports) = match ports.split_last_mut() {
+            Some((mix, ports)) => (mix.unwrap_control(), ports),
+            None => return,
+        };
+        let inputs = Ports::new(ports).audio_inputs().count();
+        self.dry.resize_with(inputs, Vec::new);
+        for (dry, input) in self.dry.iter_mut().zip(Ports::new(ports).audio_inputs()) {
+            dry.clear();
+            dry.extend_from_slice(&input[..sample_count]);
+        }
+
+        run(&mut self.plugin, ports);
+
+        let latency = ports.iter()
+            .find(|port| port.port.is_latency_output())
+            .map_or(0, |port| match port.data {
+                PortData::ControlOutput(ref data) => data.round().max(0.0) as usize,
+                _ => 0,
+            });
+        if self.delays.len() != inputs ||
+           self.delays.first().is_some_and(|delay| delay.max_delay() <= latency) {
+            self.delays = (0..inputs).map(|_| DelayLine::new(latency + 1)).collect();
+        }
+        for (dry, delay) in self.dry.iter_mut().zip(&mut self.delays) {
+            for sample in dry.iter_mut() {
+                delay.write(*sample);
+                // The sample just written is 1 sample back.
+                *sample = delay.read(latency + 1);
+            }
+        }
+
+        if inputs == 0 {
+            return;
+        }
+        let gains = self.mix.ramp(mix.clamp(0.0, 1.0), sample_count);
+        for (n, output) in Ports::new(ports).audio_outputs().enumerate() {
+            let samples = output[..sample_count].iter_mut().zip(&self.dry[n % inputs]);
+            for ((out, &dry), mix) in samples.zip(gains.clone()) {
+                let angle = mix * ::core::f32::consts::FRAC_PI_2;
+                *out = *out * angle.sin() + dry * angle.cos();
+            }
+        }
+    }
+}
+
+impl<P: Plugin> Plugin for WithDryWet<P> {
+    fn initialize(&mut self, sample_rate: u64, max_block_hint: Option<usize>) {
+        self.plugin.initialize(sample_rate, max_block_hint);
+    }
+
+    fn activate(&mut self) {
+        self.mix.reset();
+        for delay in &mut self.delays {
+            delay.clear();
+        }
+        self.plugin.activate();
+    }
+
+    fn run<'a>(&mut self, sample_count: usize, ports: &mut [PortConnection<'a>]) {
+        self.run_mixed(sample_count, ports, |plugin, ports| plugin.run(sample_count, ports));
+    }
+
+    fn run_with_context<'a>(&mut self,
+                            context: Context,
+                            sample_count: usize,
+                            ports: &mut [PortConnection<'a>]) {
+        self.run_mixed(sample_count, ports, |plugin, ports| {
+            plugin.run_with_context(context, sample_count, ports)
+        });
+    }
+
+    fn flush_denormals(&self) -> bool {
+        self.plugin.flush_denormals()
+    }
+
+    #[cfg(feature = "dssi")]
+    fn run_synth<'a>(&mut self,
+                     sample_count: usize,
+                     ports: &mut [PortConnection<'a>],
+                     events: &[MidiEvent]) {
+        self.run_mixed(sample_count, ports, |plugin, ports| {
+            plugin.run_synth(sample_count, ports, events)
+        });
+    }
+
+    fn stateful(&mut self) -> Option<&mut dyn Stateful> {
+        self.plugin.stateful()
+    }
+
+    fn state(&self) -> Option<&dyn Stateful> {
+        self.plugin.state()
+    }
+
+    fn deactivate(&mut self) {
+        self.plugin.deactivate();
+    }
+}
+
+/// Returns the descriptor of ```D``` with a ```Port::dry_wet``` control added as its last port,
+/// creating instances wrapped in a ```WithDryWet```. The unique ID, label and name are kept, as
+/// the plugin is meant to be published with the control rather than alongside the plugin without
+/// it.
+pub fn dry_wet_descriptor<D: DescribedPlugin>() -> PluginDescriptor {
+    let mut descriptor = D::descriptor();
+    descriptor.ports.push(Port::dry_wet());
+    descriptor.new = new_dry_wet::<D>;
+    descriptor
+}
+
+fn new_dry_wet<D: DescribedPlugin>(_: &PluginDescriptor, sample_rate: u64)
+                                   -> Result<Box<dyn Plugin + Send>, InstantiateError> {
+    let inner = D::descriptor();
+    Ok(Box::new(WithDryWet::new((inner.new)(&inner, sample_rate)?)))
+}
+
+/// The length of the fades of the plugins made by ```bypass_descriptor```, in seconds.
+pub const BYPASS_FADE_TIME: f32 = 0.01;
+
+/// Bypasses a plugin as set by a ```Port::bypass``` control declared as its last port, fading
+/// between its output and its input instead of switching abruptly.
+///
+/// Once the fade to the input is over, the wrapped plugin is no longer run at all, so bypassed
+/// instances cost next to nothing. It picks up where it left off when the control is switched
+/// back off, fading in over the same time. ```bypass_descriptor``` adds the control to the
+/// descriptor of a plugin and creates its instances wrapped in the adapter:
+///
+/// ```no_run
+/// # use ladspa::{DescribedPlugin, InstantiateError, Plugin, PluginDescriptor, PortConnection};
+/// # use ladspa::adapter::bypass_descriptor;
+/// # struct Reverb;
+/// # impl DescribedPlugin for Reverb {
+/// #     fn descriptor() -> PluginDescriptor { unimplemented!() }
+/// # }
+/// struct BypassableReverb;
+///
+/// impl DescribedPlugin for BypassableReverb {
+///     fn descriptor() -> PluginDescriptor {
+///         bypass_descriptor::<Reverb>()
+///     }
+/// }
+/// ```
+///
+/// As with ```WithDryWet```, the ```n```th audio output takes the ```n```th audio input, wrapping
+/// around to the first input when there are more outputs, and outputs are silent when the plugin
+/// has no audio input. Control outputs keep their last values while the plugin is not run. The
+/// fade is linear, which suits the correlated signals most effects produce from their input; the
+/// input is not delayed by any latency of the plugin. The buffers of the adapter grow with the
+/// blocks, so the first blocks which fade allocate.
+pub struct WithBypass<P: Plugin> {
+    plugin: P,
+    // The weight of the output of the plugin, 1 when processing and 0 when bypassed.
+    wet: SmoothedValue,
+    // Whether the next block is the first since activation, which jumps straight to its state.
+    first: bool,
+    // The audio inputs of the block, copied before the plugin may overwrite them. Indexed like
+    // the audio inputs.
+    dry: Vec<Vec<Data>>,
+    // The weight of the output of the plugin at each sample of a block being faded.
+    gains: Vec<Data>,
+}
+
+impl<P: Plugin> WithBypass<P> {
+    /// Wraps ```plugin```, running at ```sample_rate```, to fade for ```time``` seconds when the
+    /// bypass control changes.
+    pub fn new(plugin: P, time: f32, sample_rate: u64) -> WithBypass<P> {
+        WithBypass {
+            plugin,
+            wet: SmoothedValue::linear(1.0, time, sample_rate),
+            first: true,
+            dry: Vec::new(),
+            gains: Vec::new(),
+        }
+    }
+
+    /// Returns the wrapped plugin.
+    pub fn inner(&self) -> &P {
+        &self.plugin
+    }
+
+    /// Returns the wrapped plugin.
+    pub fn inner_mut(&mut self) -> &mut P {
+        &mut self.plugin
+    }
+
+    /// Unwraps the plugin.
+    pub fn into_inner(self) -> P {
+        self.plugin
+    }
+
+    /// Returns whether the plugin is fully bypassed, and so no longer run.
+    pub fn is_bypassed(&self) -> bool {
+        !self.wet.is_smoothing() && self.wet.current() == 0.0
+    }
+
+    // Calls `run` with the ports of the wrapped plugin unless it is fully bypassed, then fades
+    // its output with its input.
+    fn run_bypassed<'a, F>(&mut self,
+                           sample_count: usize,
+                           ports: &mut [PortConnection<'a>],
+                           run: F)
+        where F: for<'c> FnOnce(&mut P, &mut [PortConnection<'c>])
+    {
+        let (bypass, ports) = match ports.split_last_mut() {
+            Some((bypass, ports)) => (bypass.unwrap_control().as_bool(), ports),
+            None => return,
+        };
+        let target = if bypass { 0.0 } else { 1.0 };
+        if self.first {
+            self.first = false;
+            self.wet.reset(target);
+        } else {
+            self.wet.set_target(target);
+        }
+        if !self.wet.is_smoothing() && self.wet.current() == 1.0 {
+            run(&mut self.plugin, ports);
+            return;
+        }
+
+        let bypassed = self.is_bypassed();
+        let inputs = Ports::new(ports).audio_inputs().count();
+        self.dry.resize_with(inputs, Vec::new);
+        for (dry, input) in self.dry.iter_mut().zip(Ports::new(ports).audio_inputs()) {
+            dry.clear();
+            dry.extend_from_slice(&input[..sample_count]);
+        }
+        if !bypassed {
+            run(&mut self.plugin, ports);
+            self.gains.resize(sample_count, 0.0);
+            self.wet.fill(&mut self.gains[..sample_count]);
+        }
+
+        for (n, output) in Ports::new(ports).audio_outputs().enumerate() {
+            let output = &mut output[..sample_count];
+            match (self.dry.get(n.checked_rem(inputs).unwrap_or(0)), bypassed) {
+                (None, true) => output.fill(0.0),
+                (Some(dry), true) => output.copy_from_slice(dry),
+                (None, false) => {
+                    for (out, &gain) in output.iter_mut().zip(&self.gains) {
+                        *out *= gain;
+                    }
+                }
+                (Some(dry), false) => {
+                    for ((out, &dry), &gain) in output.iter_mut().zip(dry).zip(&self.gains) {
+                        *out = dry + (*out - dry) * gain;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<P: Plugin> Plugin for WithBypass<P> {
+    fn initialize(&mut self, sample_rate: u64, max_block_hint: Option<usize>) {
+        self.wet.set_sample_rate(sample_rate);
+        self.plugin.initialize(sample_rate, max_block_hint);
+    }
+
+    fn activate(&mut self) {
+        self.first = true;
+        self.plugin.activate();
+    }
+
+    fn run<'a>(&mut self, sample_count: usize, ports: &mut [PortConnection<'a>]) {
+        self.run_bypassed(sample_count, ports, |plugin, ports| plugin.run(sample_count, ports));
+    }
+
+    fn run_with_context<'a>(&mut self,
+                            context: Context,
+                            sample_count: usize,
+                            ports: &mut [PortConnection<'a>]) {
+        self.run_bypassed(sample_count, ports, |plugin, ports| {
+            plugin.run_with_context(context, sample_count, ports)
+        });
+    }
+
+    fn flush_denormals(&self) -> bool {
+        self.plugin.flush_denormals()
+    }
+
+    #[cfg(feature = "dssi")]
+    fn run_synth<'a>(&mut self,
+                     sample_count: usize,
+                     ports: &mut [PortConnection<'a>],
+                     events: &[MidiEvent]) {
+        self.run_bypassed(sample_count, ports, |plugin, ports| {
+            plugin.run_synth(sample_count, ports, events)
+        });
+    }
+
+    fn stateful(&mut self) -> Option<&mut dyn Stateful> {
+        self.plugin.stateful()
+    }
+
+    fn state(&self) -> Option<&dyn Stateful> {
+        self.plugin.state()
+    }
+
+    fn deactivate(&mut self) {
+        self.plugin.deactivate();
+    }
+}
+
+/// Returns the descriptor of ```D``` with a ```Port::bypass``` control added as its last port,
+/// creating instances wrapped in a ```WithBypass``` fading for ```BYPASS_FADE_TIME```. The unique
+/// ID, label and name are kept, as with ```dry_wet_descriptor```.
+pub fn bypass_descriptor<D: DescribedPlugin>() -> PluginDescriptor {
+    let mut descriptor = D::descriptor();
+    descriptor.ports.push(Port::bypass());
+    descriptor.new = new_bypass::<D>;
+    descriptor
+}
+
+fn new_bypass<D: DescribedPlugin>(_: &PluginDescriptor, sample_rate: u64)
+                                  -> Result<Box<dyn Plugin + Send>, InstantiateError> {
+    let inner = D::descriptor();
+    let plugin = (inner.new)(&inner, sample_rate)?;
+    Ok(Box::new(WithBypass::new(plugin, BYPASS_FADE_TIME, sample_rate)))
+}
+
+/// Runs two plugins one after the other as a single plugin, the audio outputs of the first
+/// feeding the audio inputs of the second.
+///
+/// ```chain_descriptor``` generates the descriptor of the combined plugin from those of the two,
+/// with instances created as a ```Chain```, and ```chain!``` nests chains of more plugins:
+///
+/// ```no_run
+/// # #[macro_use] extern crate ladspa;
+/// # use ladspa::{DescribedPlugin, InstantiateError, Plugin, PluginDescriptor, PortConnection};
+/// # struct Filter;
+/// # impl DescribedPlugin for Filter {
+/// #     fn descriptor() -> PluginDescriptor { unimplemented!() }
+/// # }
+/// # struct Delay;
+/// # impl DescribedPlugin for Delay {
+/// #     fn descriptor() -> PluginDescriptor { unimplemented!() }
+/// # }
+/// # struct Reverb;
+/// # impl DescribedPlugin for Reverb {
+/// #     fn descriptor() -> PluginDescriptor { unimplemented!() }
+/// # }
+/// struct FilteredDelay;
+///
+/// impl DescribedPlugin for FilteredDelay {
+///     fn descriptor() -> PluginDescriptor {
+///         chain!(4712, "filtered_delay", "Filtered Delay"; Filter, Delay, Reverb)
+///     }
+/// }
+/// ```
+///
+/// The ports must be laid out as ```chain_descriptor``` lays them out: the ports of the first
+/// plugin without its audio outputs, then those of the second without its audio inputs, followed
+/// by a single ```Port::latency_output``` if either plugin reports its latency. The ```n```th audio
+/// input of the second plugin takes the ```n```th audio output of the first, wrapping around to the
+/// first output when there are more inputs, and silence when the first plugin has no audio output.
+/// The latency of the chain is the sum of those of the two plugins. The buffers between the
+/// plugins grow with the blocks, so the first blocks allocate unless ```Plugin::initialize``` is
+/// given the largest block size, and with the ```dssi``` feature both
+/// plugins receive the MIDI events. The state of ```Stateful``` plugins is saved as that of the
+/// first plugin followed by that of the second, each prefixed with its length.
+pub struct Chain<A: Plugin, B: Plugin> {
+    first: A,
+    second: B,
+    // The ports of each plugin, and where each is connected.
+    first_ports: Vec<Port>,
+    first_links: Vec<Link>,
+    second_ports: Vec<Port>,
+    second_links: Vec<Link>,
+    // The index of the latency output of the chain, if it has one.
+    latency_port: Option<usize>,
+    // The latency reported by each plugin in the current block.
+    latency: [Data; 2],
+    // The audio passed from the first plugin to the second, one buffer per audio output of the
+    // first plugin and at least one.
+    buffers: Vec<Vec<Data>>,
+    connections: Connections,
+}
+
+// Where a port of one of the plugins of a `Chain` or a `Parallel` is connected.
+#[derive(Copy, Clone, Debug)]
+enum Link {
+    // The port of the combined plugin at this index.
+    Outer(usize),
+    // The buffer of the combined plugin at this index.
+    Inner(usize),
+    // The latency of the plugin, combined into that of the combined plugin.
+    Latency,
+}
+
+// Calls `run` with the ports of one of the plugins of a `Chain` or a `Parallel`, `plugin_ports`,
+// connected as set by `links`. Inner links take whole buffers, which must have been resized to the
+// block.
+fn run_wired<F>(connections: &mut Connections,
+                plugin_ports: &[Port],
+                links: &[Link],
+                ports: &mut [PortConnection],
+                buffers: &mut [Vec<Data>],
+                latency: &mut Data,
+                run: F)
+    where F: for<'c> FnOnce(&mut [PortConnection<'c>])
+{
+    let wired = plugin_ports.iter().zip(links).map(|(port, &link)| {
+        let data = match link {
+            Link::Outer(index) => match ports[index].data {
+                PortData::AudioInput(data) => PortData::AudioInput(data),
+                PortData::AudioOutput(ref mut data) => {
+                    let data = &mut **data as *mut [Data];
+                    PortData::AudioOutput(unsafe { &mut *data })
+                }
+                PortData::ControlInput(data) => PortData::ControlInput(data),
+                PortData::ControlOutput(ref mut data) => {
+                    let data = &mut **data as *mut Data;
+                    PortData::ControlOutput(unsafe { &mut *data })
+                }
+            },
+            Link::Inner(index) => {
+                let data = &mut *buffers[index] as *mut [Data];
+                match port.desc {
+                    PortDescriptor::AudioOutput => PortData::AudioOutput(unsafe { &mut *data }),
+                    _ => PortData::AudioInput(unsafe { &*data }),
+                }
+            }
+            Link::Latency => {
+                let data = &mut *latency as *mut Data;
+                PortData::ControlOutput(unsafe { &mut *data })
+            }
+        };
+        PortConnection { port: *port, data }
+    });
+    connections.lend(wired, run);
+}
+
+// Prefixes the name of a control port or preset with that of its plugin, unless it is empty.
+fn prefixed(plugin: &'static str, name: &'static str) -> &'static str {
+    if plugin.is_empty() {
+        name
+    } else {
+        intern(format!("{}: {}", plugin, name))
+    }
+}
+
+// Appends the ports of `descriptor` connected to ports of a combined plugin, as set by `links`,
+// to `ports`, and its presets to `presets`. Control ports and presets are renamed after the plugin.
+fn combine_ports(descriptor: &PluginDescriptor,
+                 links: &[Link],
+                 ports: &mut Vec<Port>,
+                 presets: &mut Vec<Preset>) {
+    for (port, &link) in descriptor.ports.iter().zip(links) {
+        // Ports connected to ones already added, such as shared inputs, are skipped.
+        if let Link::Outer(index) = link {
+            if index == ports.len() {
+                ports.push(match port.desc {
+                    PortDescriptor::ControlInput | PortDescriptor::ControlOutput => {
+                        Port { name: prefixed(descriptor.name, port.name), ..*port }
+                    }
+                    _ => *port,
+                });
+            }
+        }
+    }
+    presets.extend(descriptor.presets.iter().map(|preset| {
+        Preset {
+            name: prefixed(descriptor.name, preset.name),
+            values: preset.values
+                .iter()
+                .filter_map(|&(port, value)| match links[port] {
+                    Link::Outer(index) => Some((index, value)),
+                    _ => None,
+                })
+                .collect(),
+        }
+    }));
+}
+
+// The properties of a plugin combining two plugins which only see buffers of the combined plugin
+// for one direction of their audio ports: never broken in place, and never hard real-time
+// capable, as the buffers between the plugins grow with the blocks of hosts which don't give a
+// maximum block size, which LADSPA hosts never do.
+fn combine_properties(first: Properties, second: Properties) -> Properties {
+    (first | second) - (PROP_HARD_REALTIME_CAPABLE | PROP_INPLACE_BROKEN)
+}
+
+// Reserves room for blocks of `max_block_hint` samples in the buffers between combined plugins,
+// so that hosts giving that size don't see the first blocks allocate.
+fn reserve_buffers(buffers: &mut [Vec<Data>], max_block_hint: Option<usize>) {
+    if let Some(max) = max_block_hint {
+        for buffer in buffers {
+            buffer.reserve(max.saturating_sub(buffer.len()));
+        }
+    }
+}
+
+// Lays out the ports of a chain of plugins with the ports `first` and `second`, as described on
+// `Chain`. Returns where each port of each plugin is connected, and the index of the latency
+// output of the chain if it has one, which is also its last port.
+fn chain_links(first: &[Port], second: &[Port]) -> (Vec<Link>, Vec<Link>, Option<usize>) {
+    let mut count = 0;
+    let mut outputs = 0;
+    let mut latency = false;
+    let first_links = first.iter()
+        .map(|port| {
+            if port.is_latency_output() {
+                latency = true;
+                Link::Latency
+            } else if let PortDescriptor::AudioOutput = port.desc {
+                outputs += 1;
+                Link::Inner(outputs - 1)
+            } else {
+                count += 1;
+                Link::Outer(count - 1)
+            }
+        })
+        .collect();
+    let mut inputs = 0;
+    let second_links = second.iter()
+        .map(|port| {
+            if port.is_latency_output() {
+                latency = true;
+                Link::Latency
+            } else if let PortDescriptor::AudioInput = port.desc {
+                inputs += 1;
+                Link::Inner((inputs - 1) % outputs.max(1))
+            } else {
+                count += 1;
+                Link::Outer(count - 1)
+            }
+        })
+        .collect();
+    (first_links, second_links, if latency { Some(count) } else { None })
+}
+
+impl<A: Plugin, B: Plugin> Chain<A, B> {
+    /// Runs ```first```, whose ports are ```first_ports```, into ```second```, whose ports are
+    /// ```second_ports```.
+    pub fn new(first: A, first_ports: &[Port], second: B, second_ports: &[Port]) -> Chain<A, B> {
+        let (first_links, second_links, latency_port) = chain_links(first_ports, second_ports);
+        let outputs = first_ports.iter()
+            .filter(|port| matches!(port.desc, PortDescriptor::AudioOutput))
+            .count();
+        Chain {
+            first,
+            second,
+            first_ports: first_ports.to_vec(),
+            first_links,
+            second_ports: second_ports.to_vec(),
+            second_links,
+            latency_port,
+            latency: [0.0; 2],
+            buffers: (0..outputs.max(1)).map(|_| Vec::new()).collect(),
+            connections: Connections::with_capacity(first_ports.len().max(second_ports.len())),
+        }
+    }
+
+    /// Returns the first plugin.
+    pub fn first(&self) -> &A {
+        &self.first
+    }
+
+    /// Returns the first plugin.
+    pub fn first_mut(&mut self) -> &mut A {
+        &mut self.first
+    }
+
+    /// Returns the second plugin.
+    pub fn second(&self) -> &B {
+        &self.second
+    }
+
+    /// Returns the second plugin.
+    pub fn second_mut(&mut self) -> &mut B {
+        &mut self.second
+    }
+
+    /// Unwraps the two plugins.
+    pub fn into_inner(self) -> (A, B) {
+        (self.first, self.second)
+    }
+
+    // Calls `run` for each plugin in turn, with its ports, then reports the latency of the chain.
+    fn run_chain<'a, F>(&mut self,
+                        sample_count: usize,
+                        ports: &mut [PortConnection<'a>],
+                        mut run: F)
+        where F: for<'c> FnMut(&mut dyn Plugin, &mut [PortConnection<'c>])
+    {
+        for buffer in &mut self.buffers {
+            buffer.resize(sample_count, 0.0);
+        }
+
+        let [first_latency, second_latency] = &mut self.latency;
+        let first = &mut self.first;
+        run_wired(&mut self.connections,
+                  &self.first_ports,
+                  &self.first_links,
+                  ports,
+                  &mut self.buffers,
+                  first_latency,
+                  |connections| run(first, connections));
+        let second = &mut self.second;
+        run_wired(&mut self.connections,
+                  &self.second_ports,
+                  &self.second_links,
+                  ports,
+                  &mut self.buffers,
+                  second_latency,
+                  |connections| run(second, connections));
+
+        if let Some(index) = self.latency_port {
+            *ports[index].unwrap_control_out() = self.latency[0] + self.latency[1];
+        }
+    }
+}
+
+impl<A: Plugin, B: Plugin> Plugin for Chain<A, B> {
+    fn initialize(&mut self, sample_rate: u64, max_block_hint: Option<usize>) {
+        reserve_buffers(&mut self.buffers, max_block_hint);
+        self.first.initialize(sample_rate, max_block_hint);
+        self.second.initialize(sample_rate, max_block_hint);
+    }
+
+    fn activate(&mut self) {
+        self.latency = [0.0; 2];
+        self.first.activate();
+        self.second.activate();
+    }
+
+    fn run<'a>(&mut self, sample_count: usize, ports: &mut [PortConnection<'a>]) {
+        self.run_chain(sample_count, ports, |plugin, ports| plugin.run(sample_count, ports));
+    }
+
+    fn run_with_context<'a>(&mut self,
+                            context: Context,
+                            sample_count: usize,
+                            ports: &mut [PortConnection<'a>]) {
+        self.run_chain(sample_count, ports, |plugin, ports| {
+            plugin.run_with_context(context, sample_count, ports)
+        });
+    }
+
+    fn flush_denormals(&self) -> bool {
+        self.first.flush_denormals() || self.second.flush_denormals()
+    }
+
+    #[cfg(feature = "dssi")]
+    fn run_synth<'a>(&mut self,
+                     sample_count: usize,
+                     ports: &mut [PortConnection<'a>],
+                     events: &[MidiEvent]) {
+        self.run_chain(sample_count, ports, |plugin, ports| {
+            plugin.run_synth(sample_count, ports, events)
+        });
+    }
+
+    fn stateful(&mut self) -> Option<&mut dyn Stateful> {
+        if self.first.stateful().is_none() && self.second.stateful().is_none() {
+            return None;
+        }
+        Some(self)
+    }
+
+    fn state(&self) -> Option<&dyn Stateful> {
+        if self.first.state().is_none() && self.second.state().is_none() {
+            return None;
+        }
+        Some(self)
+    }
+
+    fn deactivate(&mut self) {
+        self.first.deactivate();
+        self.second.deactivate();
+    }
+}
+
+/// The states of the two plugins, each prefixed with its length.
+impl<A: Plugin, B: Plugin> Stateful for Chain<A, B> {
+    fn save(&self) -> Vec<u8> {
+        save_parts([self.first.state(), self.second.state()])
+    }
+
+    fn restore(&mut self, state: &[u8]) {
+        restore_parts(state, [self.first.stateful(), self.second.stateful()]);
+    }
+}
+
+/// Returns the descriptor of a plugin running ```A``` into ```B``` as a ```Chain```, under the
+/// given unique ID, label and name. The maker, copyright and category are those of ```A```.
+///
+/// The control ports of each plugin are renamed after it, as in "Filter: Cutoff", unless the name
+/// of its descriptor is empty, as with the nested chains of ```chain!```. Chaining two plugins of
+/// the same name gives ports of the same name. The presets of both plugins are kept, each only
+/// setting the controls of its plugin. The chain is never hard real-time capable, as its buffers
+/// grow with the blocks, and never broken in place, as neither plugin sees the buffers of both its
+/// inputs and its outputs.
+pub fn chain_descriptor<A, B>(unique_id: u64, label: &'static str, name: &'static str)
+                              -> PluginDescriptor
+    where A: DescribedPlugin,
+          B: DescribedPlugin
+{
+    let (first, second) = (A::descriptor(), B::descriptor());
+    let (first_links, second_links, latency_port) = chain_links(&first.ports, &second.ports);
+    let mut ports = Vec::new();
+    let mut presets = Vec::new();
+    combine_ports(&first, &first_links, &mut ports, &mut presets);
+    combine_ports(&second, &second_links, &mut ports, &mut presets);
+    if latency_port.is_some() {
+        ports.push(Port::latency_output());
+    }
+    PluginDescriptor {
+        unique_id,
+        label,
+        properties: combine_properties(first.properties, second.properties),
+        name,
+        maker: first.maker,
+        copyright: first.copyright,
+        category: first.category,
+        ports,
+        presets,
+        new: new_chain::<A, B>,
+    }
+}
+
+fn new_chain<A, B>(_: &PluginDescriptor, sample_rate: u64)
+                   -> Result<Box<dyn Plugin + Send>, InstantiateError>
+    where A: DescribedPlugin,
+          B: DescribedPlugin
+{
+    let (first, second) = (A::descriptor(), B::descriptor());
+    Ok(Box::new(Chain::new((first.new)(&first, sample_rate)?,
+                           &first.ports,
+                           (second.new)(&second, sample_rate)?,
+                           &second.ports)))
+}
+
+/// Describes ```A``` running into ```B``` as a ```Chain``` with an empty name, so that chains
+/// including it keep the names of its ports. ```chain!``` nests chains of more than two plugins
+/// with it. It is not meant to be exported itself: its unique ID is 0 and its label is empty.
+pub struct Chained<A, B>(PhantomData<(A, B)>);
+
+impl<A: DescribedPlugin, B: DescribedPlugin> DescribedPlugin for Chained<A, B> {
+    fn descriptor() -> PluginDescriptor {
+        chain_descriptor::<A, B>(0, "", "")
+    }
+}
+
+/// Returns the descriptor of a plugin running two or more plugins one after the other, under the
+/// given unique ID, label and name. The plugins are given as types implementing
+/// ```DescribedPlugin```:
+///
+/// ```no_run
+/// # #[macro_use] extern crate ladspa;
+/// # use ladspa::{DescribedPlugin, PluginDescriptor};
+/// # struct Filter;
+/// # impl DescribedPlugin for Filter {
+/// #     fn descriptor() -> PluginDescriptor { unimplemented!() }
+/// # }
+/// # struct Delay;
+/// # impl DescribedPlugin for Delay {
+/// #     fn descriptor() -> PluginDescriptor { unimplemented!() }
+/// # }
+/// # struct Reverb;
+/// # impl DescribedPlugin for Reverb {
+/// #     fn descriptor() -> PluginDescriptor { unimplemented!() }
+/// # }
+/// # let _ =
+/// chain!(4712, "filtered_delay", "Filtered Delay"; Filter, Delay, Reverb)
+/// # ;
+/// ```
+///
+/// With two plugins, this is ```adapter::chain_descriptor```. With more, the last ones are nested
+/// as ```adapter::Chained``` plugins, which keep the names of their ports, so each control is
+/// renamed after the plugin it belongs to whatever its position in the chain.
+#[macro_export]
+macro_rules! chain {
+    (@nest $last:ty) => { $last };
+    (@nest $first:ty, $($rest:ty),+) => {
+        $crate::adapter::Chained<$first, $crate::chain!(@nest $($rest),+)>
+    };
+    ($unique_id:expr, $label:expr, $name:expr; $first:ty, $($rest:ty),+ $(,)*) => {
+        $crate::adapter::chain_descriptor::<$first, $crate::chain!(@nest $($rest),+)>(
+            $unique_id, $label, $name)
+    };
+}
+
+/// Runs two plugins side by side on the same input as a single plugin, summing their outputs with
+/// a gain control for each.
+///
+/// Useful for parallel compression, or for multiband designs splitting the signal with filters in
+/// each branch. ```parallel_descriptor``` generates the descriptor of the combined plugin from
+/// those of the two, with instances created as a ```Parallel```:
+///
+/// ```no_run
+/// # use ladspa::{DescribedPlugin, InstantiateError, Plugin, PluginDescriptor, PortConnection};
+/// # use ladspa::adapter::parallel_descriptor;
+/// # struct Compressor;
+/// # impl DescribedPlugin for Compressor {
+/// #     fn descriptor() -> PluginDescriptor { unimplemented!() }
+/// # }
+/// # struct Amp;
+/// # impl DescribedPlugin for Amp {
+/// #     fn descriptor() -> PluginDescriptor { unimplemented!() }
+/// # }
+/// struct ParallelCompressor;
+///
+/// impl DescribedPlugin for ParallelCompressor {
+///     fn descriptor() -> PluginDescriptor {
+///         parallel_descriptor::<Compressor, Amp>(4713, "parallel_comp", "Parallel Compressor")
+///     }
+/// }
+/// ```
+///
+/// The ports must be laid out as ```parallel_descriptor``` lays them out: the ports of the first
+/// plugin, then the control ports of the second, the gains of the two branches, and a single
+/// ```Port::latency_output``` if either plugin reports its latency. The audio ports are those of
+/// the first plugin: the ```n```th audio input of the second plugin takes the ```n```th input,
+/// wrapping around to the first input when there are more, and its ```n```th audio output is
+/// summed into the ```n```th output in the same way. When the branches report different latencies,
+/// the output of the one with less is delayed to match the other, up to ```set_max_latency```, and
+/// the latency of the combined plugin is the longest. The delays are allocated with the adapter,
+/// but its buffers grow with the blocks, so the first blocks allocate, and with the ```dssi```
+/// feature both plugins receive the MIDI events.
+/// The state of ```Stateful``` plugins is saved as with ```Chain```.
+pub struct Parallel<A: Plugin, B: Plugin> {
+    first: A,
+    second: B,
+    // The ports of each plugin, and where each is connected.
+    first_ports: Vec<Port>,
+    first_links: Vec<Link>,
+    second_ports: Vec<Port>,
+    second_links: Vec<Link>,
+    // The index of each audio output of the combined plugin, which are those of the first plugin.
+    outputs: Vec<usize>,
+    // The index of the gain of each branch.
+    gain_ports: [usize; 2],
+    // The index of the latency output of the combined plugin, if it has one.
+    latency_port: Option<usize>,
+    // The latency reported by each plugin in the current block.
+    latency: [Data; 2],
+    gains: [ControlRamp; 2],
+    // The audio outputs of the first plugin, then those of the second, then a silent buffer for
+    // the audio inputs of the second plugin when the first has none.
+    buffers: Vec<Vec<Data>>,
+    // Delay the outputs of the plugins to align the branches, indexed like `buffers`, long enough
+    // for the maximum latency so that the delays can change without allocating.
+    delays: Vec<DelayLine>,
+    connections: Connections,
+}
+
+// The longest latency difference between the branches of a `Parallel` compensated for, unless
+// changed with `set_max_latency`.
+const MAX_PARALLEL_LATENCY: usize = 8192;
+
+// Where the ports of a `Parallel` are, as laid out by `parallel_links`.
+struct ParallelLayout {
+    first_links: Vec<Link>,
+    second_links: Vec<Link>,
+    outputs: Vec<usize>,
+    // The number of audio outputs of the second plugin.
+    second_outputs: usize,
+    gain_ports: [usize; 2],
+    latency_port: Option<usize>,
+}
+
+// Lays out the ports of two plugins run in parallel with the ports `first` and `second`, as
+// described on `Parallel`.
+fn parallel_links(first: &[Port], second: &[Port]) -> ParallelLayout {
+    let mut count = 0;
+    let mut inputs = Vec::new();
+    let mut outputs = Vec::new();
+    let mut latency = false;
+    let first_links = first.iter()
+        .map(|port| {
+            if port.is_latency_output() {
+                latency = true;
+                return Link::Latency;
+            }
+            match port.desc {
+                PortDescriptor::AudioInput => inputs.push(count),
+                PortDescriptor::AudioOutput => outputs.push(count),
+                _ => {}
+            }
+            count += 1;
+            match port.desc {
+                PortDescriptor::AudioOutput => Link::Inner(outputs.len() - 1),
+                _ => Link::Outer(count - 1),
+            }
+        })
+        .collect();
+    let second_outputs = second.iter()
+        .filter(|port| matches!(port.desc, PortDescriptor::AudioOutput))
+        .count();
+    let silence = outputs.len() + second_outputs;
+    let (mut second_inputs, mut second_outputs_seen) = (0, 0);
+    let second_links = second.iter()
+        .map(|port| {
+            if port.is_latency_output() {
+                latency = true;
+                return Link::Latency;
+            }
+            match port.desc {
+                PortDescriptor::AudioInput => {
+                    second_inputs += 1;
+                    if inputs.is_empty() {
+                        Link::Inner(silence)
+                    } else {
+                        Link::Outer(inputs[(second_inputs - 1) % inputs.len()])
+                    }
+                }
+                PortDescriptor::AudioOutput => {
+                    second_outputs_seen += 1;
+                    Link::Inner(outputs.len() + second_outputs_seen - 1)
+                }
+                _ => {
+                    count += 1;
+                    Link::Outer(count - 1)
+                }
+            }
+        })
+        .collect();
+    ParallelLayout {
+        first_links,
+        second_links,
+        outputs,
+        second_outputs,
+        gain_ports: [count, count + 1],
+        latency_port: if latency { Some(count + 2) } else { None },
+    }
+}
+
+impl<A: Plugin, B: Plugin> Parallel<A, B> {
+    /// Runs ```first```, whose ports are ```first_ports```, alongside ```second```, whose ports
+    /// are ```second_ports```.
+    pub fn new(first: A, first_ports: &[Port], second: B, second_ports: &[Port])
+               -> Parallel<A, B> {
+        let layout = parallel_links(first_ports, second_ports);
+        let buffers = layout.outputs.len() + layout.second_outputs + 1;
+        Parallel {
+            first,
+            second,
+            first_ports: first_ports.to_vec(),
+            first_links: layout.first_links,
+            second_ports: second_ports.to_vec(),
+            second_links: layout.second_links,
+            outputs: layout.outputs,
+            gain_ports: layout.gain_ports,
+            latency_port: layout.latency_port,
+            latency: [0.0; 2],
+            gains: [ControlRamp::new(), ControlRamp::new()],
+            buffers: (0..buffers).map(|_| Vec::new()).collect(),
+            // The silent buffer is left undelayed.
+            delays: (1..buffers).map(|_| DelayLine::new(MAX_PARALLEL_LATENCY + 1)).collect(),
+            connections: Connections::with_capacity(first_ports.len().max(second_ports.len())),
+        }
+    }
+
+    /// Sets the longest difference in latency between the branches which is compensated for,
+    /// 8192 samples by default; longer ones are only partly. Allocates the delays again, silent,
+    /// so call it before running.
+    pub fn set_max_latency(&mut self, max_latency: usize) {
+        for delay in &mut self.delays {
+            *delay = DelayLine::new(max_latency + 1);
+        }
+    }
+
+    /// Returns the first plugin.
+    pub fn first(&self) -> &A {
+        &self.first
+    }
+
+    /// Returns the first plugin.
+    pub fn first_mut(&mut self) -> &mut A {
+        &mut self.first
+    }
+
+    /// Returns the second plugin.
+    pub fn second(&self) -> &B {
+        &self.second
+    }
+
+    /// Returns the second plugin.
+    pub fn second_mut(&mut self) -> &mut B {
+        &mut self.second
+    }
+
+    /// Unwraps the two plugins.
+    pub fn into_inner(self) -> (A, B) {
+        (self.first, self.second)
+    }
+
+    // Calls `run` for each plugin, with its ports, then aligns and sums their outputs.
+    fn run_parallel<'a, F>(&mut self,
+                           sample_count: usize,
+                           ports: &mut [PortConnection<'a>],
+                           mut run: F)
+        where F: for<'c> FnMut(&mut dyn Plugin, &mut [PortConnection<'c>])
+    {
+        for buffer in &mut self.buffers {
+            buffer.resize(sample_count, 0.0);
+        }
+        let [first_latency, second_latency] = &mut self.latency;
+        let first = &mut self.first;
+        run_wired(&mut self.connections,
+                  &self.first_ports,
+                  &self.first_links,
+                  ports,
+                  &mut self.buffers,
+                  first_latency,
+                  |connections| run(first, connections));
+        let second = &mut self.second;
+        run_wired(&mut self.connections,
+                  &self.second_ports,
+                  &self.second_links,
+                  ports,
+                  &mut self.buffers,
+                  second_latency,
+                  |connections| run(second, connections));
+
+        let latency = self.latency.map(|latency| latency.round().max(0.0) as usize);
+        let total = latency[0].max(latency[1]);
+        if let Some(index) = self.latency_port {
+            *ports[index].unwrap_control_out() = total as Data;
+        }
+        let first_outputs = self.outputs.len();
+        // The silent buffer is left alone.
+        let outputs = self.buffers.len() - 1;
+        for (n, (buffer, delay)) in self.buffers.iter_mut().zip(&mut self.delays).enumerate() {
+            let branch = if n < first_outputs { 0 } else { 1 };
+            // The sample just written is 1 sample back.
+            let delay_time = total - latency[branch] + 1;
+            for sample in buffer.iter_mut() {
+                delay.write(*sample);
+                *sample = delay.read(delay_time);
+            }
+        }
+
+        let (gains, gain_ports) = (&mut self.gains, self.gain_ports);
+        let [first_gain, second_gain] = [0, 1].map(|branch| {
+            gains[branch].ramp(db_to_gain(ports[gain_ports[branch]].unwrap_control()), sample_count)
+        });
+        let second = &self.buffers[first_outputs..outputs];
+        for (n, &index) in self.outputs.iter().enumerate() {
+            let output = &mut ports[index].unwrap_audio_out()[..sample_count];
+            let samples = output.iter_mut().zip(&self.buffers[n]);
+            for ((out, &sample), gain) in samples.zip(first_gain.clone()) {
+                *out = sample * gain;
+            }
+            for buffer in second.iter().skip(n).step_by(first_outputs) {
+                let samples = output.iter_mut().zip(buffer);
+                for ((out, &sample), gain) in samples.zip(second_gain.clone()) {
+                    *out += sample * gain;
+                }
+            }
+        }
+    }
+}
+
+impl<A: Plugin, B: Plugin> Plugin for Parallel<A, B> {
+    fn initialize(&mut self, sample_rate: u64, max_block_hint: Option<usize>) {
+        reserve_buffers(&mut self.buffers, max_block_hint);
+        self.first.initialize(sample_rate, max_block_hint);
+        self.second.initialize(sample_rate, max_block_hint);
+    }
+
+    fn activate(&mut self) {
+        self.latency = [0.0; 2];
+        for gain in &mut self.gains {
+            gain.reset();
+        }
+        for delay in &mut self.delays {
+            delay.clear();
+        }
+        self.first.activate();
+        self.second.activate();
+    }
+
+    fn run<'a>(&mut self, sample_count: usize, ports: &mut [PortConnection<'a>]) {
+        self.run_parallel(sample_count, ports, |plugin, ports| plugin.run(sample_count, ports));
+    }
+
+    fn run_with_context<'a>(&mut self,
+                            context: Context,
+                            sample_count: usize,
+                            ports: &mut [PortConnection<'a>]) {
+        self.run_parallel(sample_count, ports, |plugin, ports| {
+            plugin.run_with_context(context, sample_count, ports)
+        });
+    }
+
+    fn flush_denormals(&self) -> bool {
+        self.first.flush_denormals() || self.second.flush_denormals()
+    }
+
+    #[cfg(feature = "dssi")]
+    fn run_synth<'a>(&mut self,
+                     sample_count: usize,
+                     ports: &mut [PortConnection<'a>],
+                     events: &[MidiEvent]) {
+        self.run_parallel(sample_count, ports, |plugin, ports| {
+            plugin.run_synth(sample_count, ports, events)
+        });
+    }
+
+    fn stateful(&mut self) -> Option<&mut dyn Stateful> {
+        if self.first.stateful().is_none() && self.second.stateful().is_none() {
+            return None;
+        }
+        Some(self)
+    }
+
+    fn state(&self) -> Option<&dyn Stateful> {
+        if self.first.state().is_none() && self.second.state().is_none() {
+            return None;
+        }
+        Some(self)
+    }
+
+    fn deactivate(&mut self) {
+        self.first.deactivate();
+        self.second.deactivate();
+    }
+}
+
+/// The states of the two plugins, as those of a ```Chain```.
+impl<A: Plugin, B: Plugin> Stateful for Parallel<A, B> {
+    fn save(&self) -> Vec<u8> {
+        save_parts([self.first.state(), self.second.state()])
+    }
+
+    fn restore(&mut self, state: &[u8]) {
+        restore_parts(state, [self.first.stateful(), self.second.stateful()]);
+    }
+}
+
+// The gain of a branch of a `Parallel`, in decibels. Not named "Gain", which the plugin may
+// already use.
+fn branch_gain(plugin: &'static str) -> Port {
+    Port {
+        name: prefixed(plugin, "Level"),
+        desc: PortDescriptor::ControlInput,
+        hint: None,
+        default: Some(DefaultValue::Value0),
+        lower_bound: Some(-70.0),
+        upper_bound: Some(12.0),
+        group: None,
+        scale_points: &[],
+        unit: Some(Unit::Db),
+    }
+}
+
+/// Returns the descriptor of a plugin running ```A``` and ```B``` side by side as a
+/// ```Parallel```, under the given unique ID, label and name. The maker, copyright and category
+/// are those of ```A```.
+///
+/// As with ```chain_descriptor```, the control ports and presets of each plugin are renamed after
+/// it, and so are the gains of the branches, named "Level", from -70 to 12 dB and at 0 dB by
+/// default. The properties are combined as by ```chain_descriptor```.
+pub fn parallel_descriptor<A, B>(unique_id: u64, label: &'static str, name: &'static str)
+                                 -> PluginDescriptor
+    where A: DescribedPlugin,
+          B: DescribedPlugin
+{
+    let (first, second) = (A::descriptor(), B::descriptor());
+    let layout = parallel_links(&first.ports, &second.ports);
+    // The audio outputs of the first plugin are also those of the combined plugin.
+    let first_links = layout.first_links
+        .iter()
+        .map(|&link| match link {
+            Link::Inner(output) => Link::Outer(layout.outputs[output]),
+            link => link,
+        })
+        .collect::<Vec<_>>();
+    let mut ports = Vec::new();
+    let mut presets = Vec::new();
+    combine_ports(&first, &first_links, &mut ports, &mut presets);
+    combine_ports(&second, &layout.second_links, &mut ports, &mut presets);
+    ports.push(branch_gain(first.name));
+    ports.push(branch_gain(second.name));
+    if layout.latency_port.is_some() {
+        ports.push(Port::latency_output());
+    }
+    PluginDescriptor {
+        unique_id,
+        label,
+        properties: combine_properties(first.properties, second.properties),
+        name,
+        maker: first.maker,
+        copyright: first.copyright,
+        category: first.category,
+        ports,
+        presets,
+        new: new_parallel::<A, B>,
+    }
+}
+
+fn new_parallel<A, B>(_: &PluginDescriptor, sample_rate: u64)
+                      -> Result<Box<dyn Plugin + Send>, InstantiateError>
+    where A: DescribedPlugin,
+          B: DescribedPlugin
+{
+    let (first, second) = (A::descriptor(), B::descriptor());
+    Ok(Box::new(Parallel::new((first.new)(&first, sample_rate)?,
+                              &first.ports,
+                              (second.new)(&second, sample_rate)?,
+                              &second.ports)))
+}
+
+// The largest feedback gain a `Feedback` applies, keeping the loop stable around plugins with
+// unity gain.
+const MAX_FEEDBACK: Data = 0.99;
+
+// The largest sample fed back by a `Feedback`, about +12 dBFS, so that plugins with more than
+// unity gain saturate instead of blowing up.
+const FEEDBACK_CEILING: Data = 4.0;
+
+/// Feeds the audio outputs of a plugin back into its audio inputs, through a delay and a gain set
+/// by a "Loop Feedback" control declared as its last port.
+///
+/// The ```n```th audio input receives the ```n```th audio output, delayed by a fixed number of
+/// samples and scaled by the control, on top of the signal from the host. This turns a delay or a
+/// filter into a flanger, a comb filter or a Karplus-Strong string without writing the loop by
+/// hand. ```feedback_descriptor``` adds the control to the descriptor of a plugin and creates its
+/// instances wrapped in the adapter:
+///
+/// ```no_run
+/// # use ladspa::{DescribedPlugin, InstantiateError, Plugin, PluginDescriptor, PortConnection};
+/// # use ladspa::adapter::feedback_descriptor;
+/// # struct Delay;
+/// # impl DescribedPlugin for Delay {
+/// #     fn descriptor() -> PluginDescriptor { unimplemented!() }
+/// # }
+/// struct Comb;
+///
+/// impl DescribedPlugin for Comb {
+///     fn descriptor() -> PluginDescriptor {
+///         feedback_descriptor::<Delay, 1>()
+///     }
+/// }
+/// ```
+///
+/// As an output can only be fed back once it has been computed, the plugin is run over chunks of
+/// at most the feedback delay, so that with a delay of 1 sample it is run once per sample. The
+/// control goes from -1 to 1 but the gain is held within +/-0.99, and the samples fed back are
+/// clamped to +/-4, with NaN and infinities dropped, so that the loop can't run away. Any latency
+/// of the plugin adds to the delay of the loop. With the ```dssi``` feature, MIDI events are passed
+/// to the chunk they fall in. The buffers of the adapter are allocated when it is created, from the
+/// ports of the plugin, so running it doesn't allocate.
+pub struct Feedback<P: Plugin> {
+    plugin: P,
+    delay: usize,
+    gain: ControlRamp,
+    // The feedback gain at each sample of the current chunk.
+    gains: Vec<Data>,
+    // The outputs fed back, indexed like the audio outputs.
+    delays: Vec<DelayLine>,
+    // The inputs passed to the plugin for the current chunk, indexed like the audio inputs.
+    inputs: Vec<Vec<Data>>,
+    connections: Connections,
+    #[cfg(feature = "dssi")]
+    events: Vec<MidiEvent>,
+}
+
+impl<P: Plugin> Feedback<P> {
+    /// Wraps ```plugin```, whose ports are ```ports```, feeding its outputs back after ```delay```
+    /// samples. Panics if ```delay``` is zero.
+    pub fn new(plugin: P, ports: &[Port], delay: usize) -> Feedback<P> {
+        assert!(delay > 0, "the feedback delay must not be zero");
+        let inputs = ports.iter()
+            .filter(|port| matches!(port.desc, PortDescriptor::AudioInput))
+            .count();
+        let outputs = ports.iter()
+            .filter(|port| matches!(port.desc, PortDescriptor::AudioOutput))
+            .count();
+        Feedback {
+            plugin,
+            delay,
+            gain: ControlRamp::new(),
+            gains: Vec::with_capacity(delay),
+            delays: (0..outputs).map(|_| DelayLine::new(delay)).collect(),
+            inputs: (0..inputs).map(|_| Vec::with_capacity(delay)).collect(),
+            connections: Connections::with_capacity(ports.len()),
+            #[cfg(feature = "dssi")]
+            events: Vec::with_capacity(::dssi::MAX_BLOCK_EVENTS),
+        }
+    }
+
+    /// The delay of the loop, in samples, not counting the latency of the plugin.
+    pub fn delay(&self) -> usize {
+        self.delay
+    }
+
+    /// Returns the wrapped plugin.
+    pub fn inner(&self) -> &P {
+        &self.plugin
+    }
+
+    /// Returns the wrapped plugin.
+    pub fn inner_mut(&mut self) -> &mut P {
+        &mut self.plugin
+    }
+
+    /// Unwraps the plugin.
+    pub fn into_inner(self) -> P {
+        self.plugin
+    }
+
+    // Calls `run` for each chunk of the block, with the ports of the wrapped plugin for that chunk
+    // and the outputs fed back into the inputs.
+    fn run_looped<'a, F>(&mut self,
+                         sample_count: usize,
+                         ports: &mut [PortConnection<'a>],
+                         mut run: F)
+        where F: for<'c> FnMut(&mut P, usize, usize, &mut [PortConnection<'c>])
+    {
+        let (gain, ports) = match ports.split_last_mut() {
+            Some((gain, ports)) => (gain.unwrap_control(), ports),
+            None => return,
+        };
+        let gain = gain.clamp(-MAX_FEEDBACK, MAX_FEEDBACK);
+        let mut ramp = self.gain.ramp(gain, sample_count);
+        let outputs = Ports::new(ports).audio_outputs().count();
+        if self.delays.len() != outputs {
+            self.delays = (0..outputs).map(|_| DelayLine::new(self.delay)).collect();
+        }
+        self.inputs.resize_with(Ports::new(ports).audio_inputs().count(), Vec::new);
+        for input in &mut self.inputs {
+            input.resize(self.delay.min(sample_count), 0.0);
+        }
+
+        let mut offset = 0;
+        while offset < sample_count {
+            let len = (sample_count - offset).min(self.delay);
+            self.gains.clear();
+            self.gains.extend(ramp.by_ref().take(len));
+            let gains = &self.gains;
+            let buffers = self.inputs.iter_mut().enumerate();
+            for ((n, buffer), input) in buffers.zip(Ports::new(ports).audio_inputs()) {
+                let input = &input[offset..offset + len];
+                match self.delays.get(n) {
+                    Some(delay) => {
+                        let samples = buffer.iter_mut().zip(input).zip(gains).enumerate();
+                        for (j, ((sample, &input), &gain)) in samples {
+                            // The outputs up to the start of the chunk have been written.
+                            *sample = input + delay.read(self.delay - j) * gain;
+                        }
+                    }
+                    None => buffer[..len].copy_from_slice(input),
+                }
+            }
+
+            let mut inputs = self.inputs.iter();
+            let connections = ports.iter_mut().map(|port| {
+                let data = match port.data {
+                    PortData::AudioInput(_) => {
+                        PortData::AudioInput(inputs.next().map_or(&[][..], |input| &input[..len]))
+                    }
+                    PortData::AudioOutput(ref mut data) => {
+                        PortData::AudioOutput(&mut data[offset..offset + len])
+                    }
+                    PortData::ControlInput(data) => PortData::ControlInput(data),
+                    PortData::ControlOutput(ref mut data) => PortData::ControlOutput(data),
+                };
+                PortConnection { port: port.port, data }
+            });
+            let plugin = &mut self.plugin;
+            self.connections.lend(connections, |connections| run(plugin, offset, len, connections));
+
+            for (delay, output) in self.delays.iter_mut().zip(Ports::new(ports).audio_outputs()) {
+                for &sample in &output[offset..offset + len] {
+                    delay.write(if sample.is_finite() {
+                        sample.clamp(-FEEDBACK_CEILING, FEEDBACK_CEILING)
+                    } else {
+                        0.0
+                    });
+                }
+            }
+            offset += len;
+        }
+    }
+}
+
+impl<P: Plugin> Plugin for Feedback<P> {
+    fn initialize(&mut self, sample_rate: u64, max_block_hint: Option<usize>) {
+        let chunk_size = chunk_hint(max_block_hint, self.delay);
+        self.plugin.initialize(sample_rate, Some(chunk_size));
+    }
+
+    fn activate(&mut self) {
+        self.gain.reset();
+        for delay in &mut self.delays {
+            delay.clear();
+        }
+        self.plugin.activate();
+    }
+
+    fn run<'a>(&mut self, sample_count: usize, ports: &mut [PortConnection<'a>]) {
+        self.run_looped(sample_count, ports, |plugin, _, len, ports| plugin.run(len, ports));
+    }
+
+    fn run_with_context<'a>(&mut self,
+                            context: Context,
+                            sample_count: usize,
+                            ports: &mut [PortConnection<'a>]) {
+        self.run_looped(sample_count, ports, |plugin, _, len, ports| {
+            plugin.run_with_context(context, len, ports)
+        });
+    }
+
+    fn flush_denormals(&self) -> bool {
+        self.plugin.flush_denormals()
+    }
+
+    #[cfg(feature = "dssi")]
+    fn run_synth<'a>(&mut self,
+                     sample_count: usize,
+                     ports: &mut [PortConnection<'a>],
+                     events: &[MidiEvent]) {
+        let mut chunk_events = ::core::mem::take(&mut self.events);
+        self.run_looped(sample_count, ports, |plugin, offset, len, ports| {
+            chunk_events.clear();
+            chunk_events.extend(events.iter()
+                .filter(|event| event.frame >= offset && event.frame < offset + len)
+                .map(|event| MidiEvent { frame: event.frame - offset, ..*event }));
+            plugin.run_synth(len, ports, &chunk_events);
+        });
+        self.events = chunk_events;
+    }
+
+    fn stateful(&mut self) -> Option<&mut dyn Stateful> {
+        self.plugin.stateful()
+    }
+
+    fn state(&self) -> Option<&dyn Stateful> {
+        self.plugin.state()
+    }
+
+    fn deactivate(&mut self) {
+        self.plugin.deactivate();
+    }
+}
+
+/// Returns the descriptor of ```D``` with a "Loop Feedback" control added as its last port,
+/// creating instances wrapped in a ```Feedback``` with a delay of ```DELAY``` samples. The unique
+/// ID, label and name are kept, as with ```dry_wet_descriptor```. The plugin is no longer broken
+/// in place, as it only sees buffers of the adapter for its audio inputs, and stays hard real-time
+/// capable if it was, as the adapter doesn't allocate once created.
+pub fn feedback_descriptor<D: DescribedPlugin, const DELAY: usize>() -> PluginDescriptor {
+    let mut descriptor = D::descriptor();
+    descriptor.ports.push(Port {
+        name: "Loop Feedback",
+        desc: PortDescriptor::ControlInput,
+        hint: None,
+        default: Some(DefaultValue::Middle),
+        lower_bound: Some(-1.0),
+        upper_bound: Some(1.0),
+        group: None,
+        scale_points: &[],
+        unit: None,
+    });
+    descriptor.properties.remove(PROP_INPLACE_BROKEN);
+    descriptor.new = new_feedback::<D, DELAY>;
+    descriptor
+}
+
+fn new_feedback<D: DescribedPlugin, const DELAY: usize>(_: &PluginDescriptor, sample_rate: u64)
+                                                        -> Result<Box<dyn Plugin + Send>,
+                                                                  InstantiateError> {
+    let inner = D::descriptor();
+    Ok(Box::new(Feedback::new((inner.new)(&inner, sample_rate)?, &inner.ports, DELAY)))
+}
+
+/// Drives control inputs of a plugin from MIDI controllers, as listed in a table of
+/// ```dssi::CcMapping```s. See the ```dssi``` module.
+///
+/// The changes of mapped controllers are consumed by the adapter: before the wrapped plugin runs,
+/// each port they are mapped to takes the value of the last change in the block, and the events
+/// are not passed on, so ```run_synth``` only sees notes and unmapped messages. To apply changes
+/// at the frame they happen at rather than at the start of the block, wrap the adapter in a
+/// ```SubBlock```, which passes each chunk the events falling in it.
+///
+/// A port keeps the value of its controller until the host changes it, for example by automating
+/// it or loading a preset. Ports are found by name on the first block; mappings to names which
+/// are not control inputs of the plugin are ignored. The first block allocates.
+#[cfg(feature = "dssi")]
+pub struct MidiControlled<P: Plugin> {
+    plugin: P,
+    sample_rate: u64,
+    mappings: &'static [CcMapping],
+    // The port driven by each mapping, indexed like `mappings`. Empty until the first block.
+    targets: Vec<Option<usize>>,
+    // For each port set by a controller since the host last changed it, the value the host had
+    // set at the time and the one from the controller. Indexed by port.
+    overrides: Vec<Option<(Data, Data)>>,
+    rewired: Rewired,
+    events: Vec<MidiEvent>,
+}
+
+#[cfg(feature = "dssi")]
+impl<P: Plugin> MidiControlled<P> {
+    /// Wraps ```plugin```, running at ```sample_rate```, to have its control inputs driven as
+    /// listed in ```mappings```.
+    pub fn new(plugin: P, sample_rate: u64, mappings: &'static [CcMapping]) -> MidiControlled<P> {
+        MidiControlled {
+            plugin,
+            sample_rate,
+            mappings,
+            targets: Vec::new(),
+            overrides: Vec::new(),
+            rewired: Rewired::new(),
+            events: Vec::new(),
+        }
+    }
+
+    /// Returns the wrapped plugin.
+    pub fn inner(&self) -> &P {
+        &self.plugin
+    }
+
+    /// Returns the wrapped plugin.
+    pub fn inner_mut(&mut self) -> &mut P {
+        &mut self.plugin
+    }
+
+    /// Unwraps the plugin.
+    pub fn into_inner(self) -> P {
+        self.plugin
+    }
+
+    // Finds the ports of the mappings, once the ports are known.
+    fn resolve(&mut self, ports: &[PortConnection]) {
+        if self.overrides.len() == ports.len() {
+            return;
+        }
+        self.targets = self.mappings
+            .iter()
+            .map(|mapping| {
+                ports.iter().position(|port| {
+                    port.port.name == mapping.port && matches!(port.data, PortData::ControlInput(_))
+                })
+            })
+            .collect();
+        self.overrides = ports.iter().map(|_| None).collect();
+    }
+
+    // Applies a change of a mapped controller.
+    fn control_change(&mut self, ports: &[PortConnection], event: &MidiEvent) -> bool {
+        let value = match event.message {
+            MidiMessage::ControlChange { value, .. } => value,
+            _ => return false,
+        };
+        let found = self.mappings
+            .iter()
+            .zip(&self.targets)
+            .find(|&(mapping, target)| target.is_some() && mapping.matches(&event.message));
+        let (mapping, port) = match found {
+            Some((mapping, &Some(port))) => (mapping, port),
+            _ => return false,
+        };
+        if let PortData::ControlInput(&host) = ports[port].data {
+            let value = controller_value(mapping, &ports[port].port, self.sample_rate, value);
+            self.overrides[port] = Some((host, value));
+        }
+        true
+    }
+
+    // Calls `run` with the ports, the control inputs set by controllers getting their values.
+    fn run_with_overrides<'a, F>(&mut self, ports: &mut [PortConnection<'a>], run: F)
+        where F: for<'c> FnOnce(&mut P, &mut [PortConnection<'c>])
+    {
+        self.resolve(ports);
+        let overrides = &mut self.overrides;
+        self.rewired.run(&mut self.plugin,
+                         ports,
+                         |n, host| match overrides[n] {
+                             Some((seen, value)) if seen == host => value,
+                             // The host changing the port takes it back from the controller.
+                             _ => {
+                                 overrides[n] = None;
+                                 host
+                             }
+                         },
+                         run);
+    }
+}
+
+// Maps the value of a controller over the range of a port, as described by the mapping.
+#[cfg(feature = "dssi")]
+fn controller_value(mapping: &CcMapping, port: &Port, sample_rate: u64, value: u8) -> Data {
+    let mut range = port.range(sample_rate);
+    if let Some((lower, upper)) = mapping.range {
+        range.lower = lower;
+        range.upper = upper;
+    }
+    if let Some(curve) = mapping.curve {
+        range.logarithmic = curve == Curve::Logarithmic;
+    }
+    range.from_normalized(Data::from(value.min(127)) / 127.0)
+}
+
+#[cfg(feature = "dssi")]
+impl<P: Plugin> Plugin for MidiControlled<P> {
+    fn initialize(&mut self, sample_rate: u64, max_block_hint: Option<usize>) {
+        self.sample_rate = sample_rate;
+        self.plugin.initialize(sample_rate, max_block_hint);
+    }
+
+    fn activate(&mut self) {
+        for value in &mut self.overrides {
+            *value = None;
+        }
+        self.plugin.activate();
+    }
+
+    fn run<'a>(&mut self, sample_count: usize, ports: &mut [PortConnection<'a>]) {
+        self.run_with_overrides(ports, |plugin, ports| plugin.run(sample_count, ports));
+    }
+
+    fn run_with_context<'a>(&mut self,
+                            context: Context,
+                            sample_count: usize,
+                            ports: &mut [PortConnection<'a>]) {
+        self.run_with_overrides(ports, |plugin, ports| {
+            plugin.run_with_context(context, sample_count, ports)
+        });
+    }
+
+    fn flush_denormals(&self) -> bool {
+        self.plugin.flush_denormals()
+    }
+
+    fn run_synth<'a>(&mut self,
+                     sample_count: usize,
+                     ports: &mut [PortConnection<'a>],
+                     events: &[MidiEvent]) {
+        self.resolve(ports);
+        let mut passed = ::core::mem::take(&mut self.events);
+        passed.clear();
+        for event in events {
+            if !self.control_change(ports, event) {
+                passed.push(*event);
+            }
+        }
+        self.run_with_overrides(ports, |plugin, ports| {
+            plugin.run_synth(sample_count, ports, &passed)
+        });
+        self.events = passed;
+    }
+
+    fn stateful(&mut self) -> Option<&mut dyn Stateful> {
+        self.plugin.stateful()
+    }
+
+    fn state(&self) -> Option<&dyn Stateful> {
+        self.plugin.state()
+    }
+
+    fn deactivate(&mut self) {
+        self.plugin.deactivate();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    // A plugin doing nothing, whose state is a single byte.
+    struct Memory(u8);
+
+    impl Plugin for Memory {
+        fn run<'a>(&mut self, _: usize, _: &mut [PortConnection<'a>]) {}
+
+        fn stateful(&mut self) -> Option<&mut dyn Stateful> {
+            Some(self)
+        }
+
+        fn state(&self) -> Option<&dyn Stateful> {
+            Some(self)
+        }
+    }
+
+    impl Stateful for Memory {
+        fn save(&self) -> Vec<u8> {
+            vec![self.0]
+        }
+
+        fn restore(&mut self, state: &[u8]) {
+            if let [value] = *state {
+                self.0 = value;
+            }
+        }
+    }
+
+    struct Stateless;
+
+    impl Plugin for Stateless {
+        fn run<'a>(&mut self, _: usize, _: &mut [PortConnection<'a>]) {}
+    }
+
+    // The same as `Memory`, processing f64.
+    struct PromotedMemory(u8);
+
+    impl PromotedPlugin for PromotedMemory {
+        type Sample = f64;
+
+        fn run(&mut self, _: &mut PromotedPorts<f64>) {}
+
+        fn stateful(&mut self) -> Option<&mut dyn Stateful> {
+            Some(self)
+        }
+
+        fn state(&self) -> Option<&dyn Stateful> {
+            Some(self)
+        }
+    }
+
+    impl Stateful for PromotedMemory {
+        fn save(&self) -> Vec<u8> {
+            vec![self.0]
+        }
+
+        fn restore(&mut self, state: &[u8]) {
+            self.0 = state[0];
+        }
+    }
+
+    // Scales its input by its control input, reports a latency of 2 samples and records the
+    // number of samples of every run.
+    #[derive(Default)]
+    struct Recorder {
+        runs: Vec<usize>,
+    }
+
+    impl Plugin for Recorder {
+        fn run<'a>(&mut self, sample_count: usize, ports: &mut [PortConnection<'a>]) {
+            self.runs.push(sample_count);
+            let gain = ports[2].unwrap_control();
+            let input = ports[0].unwrap_audio();
+            let output = &mut ports[1].unwrap_audio_mut()[..sample_count];
+            for (out, &sample) in output.iter_mut().zip(input) {
+                *out = sample * gain;
+            }
+            *ports[3].unwrap_control_out() = 2.0;
+        }
+    }
+
+    // Runs `plugin` over the host blocks of the sizes in `blocks`, with `input` and the gain
+    // `gain`, and returns its output and its latency output.
+    fn run_blocks<P: Plugin>(plugin: &mut P, blocks: &[usize], input: &[Data], gain: Data)
+                             -> (Vec<Data>, Data) {
+        let gain_port = Port {
+            name: "Gain",
+            desc: PortDescriptor::ControlInput,
+            ..Default::default()
+        };
+        let ports = [mono()[0], mono()[1], gain_port, Port::latency_output()];
+        let mut output = vec![0.0; input.len()];
+        let mut latency = 0.0;
+        let mut offset = 0;
+        for &len in blocks {
+            plugin.run(len,
+                       &mut [PortConnection {
+                                 port: ports[0],
+                                 data: PortData::AudioInput(&input[offset..offset + len]),
+                             },
+                             PortConnection {
+                                 port: ports[1],
+                                 data: PortData::AudioOutput(&mut output[offset..offset + len]),
+                             },
+                             PortConnection { port: ports[2], data: PortData::ControlInput(&gain) },
+                             PortConnection {
+                                 port: ports[3],
+                                 data: PortData::ControlOutput(&mut latency),
+                             }]);
+            offset += len;
+        }
+        (output, latency)
+    }
+
+    fn mono() -> [Port; 2] {
+        [Port { name: "In", desc: PortDescriptor::AudioInput, ..Default::default() },
+         Port { name: "Out", desc: PortDescriptor::AudioOutput, ..Default::default() }]
+    }
+
+    // A plugin doing nothing, claiming to be hard real-time capable.
+    struct HardRealtime;
+
+    impl Plugin for HardRealtime {
+        fn run<'a>(&mut self, _: usize, _: &mut [PortConnection<'a>]) {}
+    }
+
+    fn new_hard_realtime(_: &PluginDescriptor, _: u64)
+                         -> Result<Box<dyn Plugin + Send>, InstantiateError> {
+        Ok(Box::new(HardRealtime))
+    }
+
+    impl DescribedPlugin for HardRealtime {
+        fn descriptor() -> PluginDescriptor {
+            PluginDescriptor::builder()
+                .unique_id(::UniqueId::experimental(996).get())
+                .label("hard_realtime")
+                .name("Hard Realtime")
+                .maker("ladspa.rs")
+                .copyright("None")
+                .properties(PROP_HARD_REALTIME_CAPABLE)
+                .audio_in("Input")
+                .audio_out("Output")
+                .new(new_hard_realtime)
+                .build()
+                .unwrap()
+        }
+    }
+
+    // Runs `plugin`, whose ports are those of `mono`, over `input` into `output`.
+    #[cfg(feature = "rt-audit")]
+    fn run_mono<P: Plugin>(plugin: &mut P, input: &[Data], output: &mut [Data]) {
+        let ports = mono();
+        plugin.run(input.len(),
+                   &mut [PortConnection { port: ports[0], data: PortData::AudioInput(input) },
+                         PortConnection { port: ports[1], data: PortData::AudioOutput(output) }]);
+    }
+
+    #[test]
+    fn chain_saves_the_state_of_both_plugins() {
+        let chain = Chain::new(Memory(1), &mono(), Memory(2), &mono());
+        let state = chain.state().unwrap().save();
+        let mut restored = Chain::new(Memory(0), &mono(), Memory(0), &mono());
+        restored.stateful().unwrap().restore(&state);
+        assert_eq!((restored.first().0, restored.second().0), (1, 2));
+    }
+
+    #[test]
+    fn combined_plugins_are_not_hard_realtime_capable() {
+        let chain = chain_descriptor::<HardRealtime, HardRealtime>(1, "chain", "Chain");
+        assert!(!chain.properties.contains(PROP_HARD_REALTIME_CAPABLE));
+        let parallel = parallel_descriptor::<HardRealtime, HardRealtime>(2, "parallel", "Parallel");
+        assert!(!parallel.properties.contains(PROP_HARD_REALTIME_CAPABLE));
+    }
+
+    #[cfg(feature = "rt-audit")]
+    #[test]
+    fn chains_given_the_block_size_run_without_allocating() {
+        let mut chain = Chain::new(Stateless, &mono(), Stateless, &mono());
+        chain.initialize(48000, Some(64));
+        chain.activate();
+        let (input, mut output) = ([0.0; 64], [0.0; 64]);
+        let ((), violations) = ::rt_audit::audit(|| run_mono(&mut chain, &input, &mut output));
+        assert!(violations.is_empty(), "{}", violations);
+    }
+
+    #[test]
+    fn parallel_skips_stateless_plugins() {
+        let parallel = Parallel::new(Stateless, &mono(), Memory(7), &mono());
+        let state = parallel.state().unwrap().save();
+        let mut restored = Parallel::new(Stateless, &mono(), Memory(0), &mono());
+        restored.stateful().unwrap().restore(&state);
+        assert_eq!(restored.second().0, 7);
+
+        assert!(Parallel::new(Stateless, &mono(), Stateless, &mono()).stateful().is_none());
+    }
+
+    // Copies its input to its output.
+    struct Through;
+
+    impl Plugin for Through {
+        fn run<'a>(&mut self, sample_count: usize, ports: &mut [PortConnection<'a>]) {
+            let input = ports[0].unwrap_audio();
+            ports[1].unwrap_audio_mut()[..sample_count].copy_from_slice(&input[..sample_count]);
+        }
+    }
+
+    // Runs a ramp through `Through` in parallel with a silenced `Recorder`, reporting a latency of
+    // 2 samples, and returns the output and the latency output.
+    fn run_through_and_recorder(parallel: &mut Parallel<Through, Recorder>) -> (Vec<Data>, Data) {
+        let ports = recorder_ports();
+        let input: Vec<Data> = (1..9).map(|n| n as Data).collect();
+        let mut output = vec![0.0; 8];
+        // The recorder is silenced by its gain, while the gains of the branches are 0 dB.
+        let (silent, unity, mut latency) = (0.0, 0.0, 0.0);
+        parallel.run(8,
+                     &mut [PortConnection { port: ports[0], data: PortData::AudioInput(&input) },
+                           PortConnection {
+                               port: ports[1],
+                               data: PortData::AudioOutput(&mut output),
+                           },
+                           PortConnection { port: ports[2], data: PortData::ControlInput(&silent) },
+                           PortConnection { port: ports[2], data: PortData::ControlInput(&unity) },
+                           PortConnection { port: ports[2], data: PortData::ControlInput(&unity) },
+                           PortConnection {
+                               port: ports[3],
+                               data: PortData::ControlOutput(&mut latency),
+                           }]);
+        (output, latency)
+    }
+
+    fn recorder_ports() -> [Port; 4] {
+        let gain = Port { name: "Gain", desc: PortDescriptor::ControlInput, ..Default::default() };
+        [mono()[0], mono()[1], gain, Port::latency_output()]
+    }
+
+    #[test]
+    fn parallel_delays_the_branch_with_less_latency() {
+        let mut parallel = Parallel::new(Through, &mono(), Recorder::default(), &recorder_ports());
+        let (output, latency) = run_through_and_recorder(&mut parallel);
+        assert_eq!(output, [0.0, 0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        assert_eq!(latency, 2.0);
+
+        let mut parallel = Parallel::new(Through, &mono(), Recorder::default(), &recorder_ports());
+        parallel.set_max_latency(1);
+        let (output, _) = run_through_and_recorder(&mut parallel);
+        assert_eq!(output, [0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0]);
+    }
+
+    #[test]
+    fn multichannel_saves_every_channel() {
+        let stereo = Multichannel::new(vec![Memory(3), Memory(4)], &mono());
+        let state = stereo.state().unwrap().save();
+        let mut restored = Multichannel::new(vec![Memory(0), Memory(0)], &mono());
+        restored.stateful().unwrap().restore(&state);
+        assert_eq!((restored.instances()[0].0, restored.instances()[1].0), (3, 4));
+    }
+
+    #[cfg(feature = "rt-audit")]
+    #[test]
+    fn multichannel_runs_without_allocating() {
+        let descriptor = multichannel_descriptor::<HardRealtime, 2>(3, "stereo", "Stereo");
+        assert!(descriptor.properties.contains(PROP_HARD_REALTIME_CAPABLE));
+        let mut stereo = Multichannel::new(vec![HardRealtime, HardRealtime], &mono());
+        stereo.activate();
+        let (left, right) = ([0.0; 64], [0.0; 64]);
+        let (mut out_left, mut out_right) = ([0.0; 64], [0.0; 64]);
+        let ((), violations) = ::rt_audit::audit(|| {
+            stereo.run(64,
+                       &mut [PortConnection {
+                                 port: descriptor.ports[0],
+                                 data: PortData::AudioInput(&left),
+                             },
+                             PortConnection {
+                                 port: descriptor.ports[1],
+                                 data: PortData::AudioInput(&right),
+                             },
+                             PortConnection {
+                                 port: descriptor.ports[2],
+                                 data: PortData::AudioOutput(&mut out_left),
+                             },
+                             PortConnection {
+                                 port: descriptor.ports[3],
+                                 data: PortData::AudioOutput(&mut out_right),
+                             }])
+        });
+        assert!(violations.is_empty(), "{}", violations);
+    }
+
+    #[cfg(feature = "rt-audit")]
+    #[test]
+    fn feedback_runs_without_allocating() {
+        let descriptor = feedback_descriptor::<HardRealtime, 16>();
+        assert!(descriptor.properties.contains(PROP_HARD_REALTIME_CAPABLE));
+        let mut feedback = Feedback::new(HardRealtime, &mono(), 16);
+        feedback.activate();
+        let (input, mut output, gain) = ([0.0; 64], [0.0; 64], 0.5);
+        let ((), violations) = ::rt_audit::audit(|| {
+            feedback.run(64,
+                         &mut [PortConnection {
+                                   port: descriptor.ports[0],
+                                   data: PortData::AudioInput(&input),
+                               },
+                               PortConnection {
+                                   port: descriptor.ports[1],
+                                   data: PortData::AudioOutput(&mut output),
+                               },
+                               PortConnection {
+                                   port: descriptor.ports[2],
+                                   data: PortData::ControlInput(&gain),
+                               }])
+        });
+        assert!(violations.is_empty(), "{}", violations);
+    }
+
+    // Runs `input` through `Through` with its output fed back after 4 samples, scaled by `gain`.
+    fn run_feedback(input: &[Data], gain: Data) -> Vec<Data> {
+        let mut feedback = Feedback::new(Through, &mono(), 4);
+        feedback.activate();
+        let gain_port = Port { desc: PortDescriptor::ControlInput, ..Default::default() };
+        let mut output = vec![0.0; input.len()];
+        feedback.run(input.len(),
+                     &mut [PortConnection { port: mono()[0], data: PortData::AudioInput(input) },
+                           PortConnection {
+                               port: mono()[1],
+                               data: PortData::AudioOutput(&mut output),
+                           },
+                           PortConnection {
+                               port: gain_port,
+                               data: PortData::ControlInput(&gain),
+                           }]);
+        output
+    }
+
+    #[test]
+    fn feedback_repeats_the_output_after_the_delay() {
+        let mut impulse = [0.0; 14];
+        impulse[0] = 1.0;
+        let output = run_feedback(&impulse, 0.5);
+        let mut expected = [0.0; 14];
+        for (n, &echo) in [1.0, 0.5, 0.25, 0.125].iter().enumerate() {
+            expected[n * 4] = echo;
+        }
+        assert_eq!(output, expected);
+
+        let output = run_feedback(&impulse, -3.0);
+        assert_eq!((output[4], output[8]), (-MAX_FEEDBACK, MAX_FEEDBACK * MAX_FEEDBACK));
+    }
+
+    #[test]
+    fn feedback_drops_nan_and_clamps_loud_samples() {
+        let output = run_feedback(&[Data::NAN, 100.0, -Data::INFINITY, 0.0, 0.0, 0.0, 0.0], 0.5);
+        assert!(output[0].is_nan());
+        assert_eq!(output[4..], [0.0, FEEDBACK_CEILING * 0.5, 0.0]);
+    }
+
+    #[test]
+    fn truncated_states_are_ignored() {
+        let mut chain = Chain::new(Memory(1), &mono(), Memory(2), &mono());
+        let state = chain.state().unwrap().save();
+        chain.stateful().unwrap().restore(&[1, 0, 0, 0, 9, 5, 0]);
+        assert_eq!((chain.first().0, chain.second().0), (9, 2));
+        chain.stateful().unwrap().restore(&state[..3]);
+        assert_eq!((chain.first().0, chain.second().0), (9, 2));
+    }
+
+    #[test]
+    fn promote_forwards_the_state() {
+        let mut promoted = Promote::new(PromotedMemory(5));
+        assert_eq!(promoted.state().unwrap().save(), vec![5]);
+        promoted.stateful().unwrap().restore(&[6]);
+        assert_eq!(promoted.inner().0, 6);
+    }
+
+    #[test]
+    fn fixed_blocks_delay_the_output_by_a_block() {
+        let mut fixed = FixedBlock::new(Recorder::default(), 4);
+        fixed.activate();
+        let input: Vec<_> = (1..17).map(|sample| sample as Data).collect();
+        let (output, latency) = run_blocks(&mut fixed, &[3, 5, 7, 1], &input, 2.0);
+        let expected: Vec<_> = (0..16).map(|n| if n < 4 { 0.0 } else { 2.0 * (n - 3) as Data })
+            .collect();
+        assert_eq!(output, expected);
+        assert_eq!(fixed.inner().runs, [4, 4, 4, 4]);
+        assert_eq!(latency, 6.0);
+        assert_eq!(fixed.latency(), 4);
+    }
+
+    #[test]
+    fn sub_blocks_glide_the_controls_between_blocks() {
+        let mut sub_block = SubBlock::new(Recorder::default(), 2);
+        sub_block.activate();
+        let (output, _) = run_blocks(&mut sub_block, &[3], &[1.0; 3], 0.5);
+        assert_eq!(output, [0.5; 3]);
+        let (output, latency) = run_blocks(&mut sub_block, &[8], &[1.0; 8], 2.5);
+        assert_eq!(output, [1.0, 1.0, 1.5, 1.5, 2.0, 2.0, 2.5, 2.5]);
+        assert_eq!(sub_block.inner().runs, [2, 1, 2, 2, 2, 2]);
+        assert_eq!(latency, 2.0);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn lent_connections_are_cleared_when_the_plugin_panics() {
+        use std::panic::{self, AssertUnwindSafe};
+
+        let mut connections = Connections::new();
+        let mut output = [0.0; 4];
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let port = PortConnection { port: mono()[1], data: PortData::AudioOutput(&mut output) };
+            connections.lend(Some(port), |connections: &mut [PortConnection]| {
+                assert_eq!(connections.len(), 1);
+                panic!("the plugin failed");
+            })
+        }));
+        assert!(result.is_err());
+        assert!(connections.connections.is_empty());
+    }
+}
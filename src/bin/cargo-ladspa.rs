@@ -0,0 +1,55 @@
+//! Cargo subcommand generating new plugin crates and installing the libraries they build:
+//!
+//! ```text
+//! cargo ladspa new my-plugin
+//! cargo ladspa install [--system] target/release/libmy_plugin.so
+//! ```
+//!
+//! See ```ladspa::scaffold``` for what is generated, and ```ladspa::install``` for where libraries
+//! are installed.
+
+extern crate ladspa;
+
+use std::env;
+use std::path::Path;
+use std::process;
+
+use ladspa::install::{self, Scope};
+use ladspa::scaffold;
+
+fn usage() -> ! {
+    eprintln!("usage: cargo ladspa new NAME");
+    eprintln!("       cargo ladspa install [--system] LIBRARY");
+    process::exit(2);
+}
+
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    // Cargo runs subcommands with the name of the subcommand as the first argument.
+    if args.first().map(String::as_str) == Some("ladspa") {
+        args.remove(0);
+    }
+    let result = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["new", name] => {
+            scaffold::generate(Path::new(name), name)
+                .map(|()| format!("Created plugin crate `{}`", name))
+                .map_err(|error| error.to_string())
+        }
+        ["install", library] => install(library, Scope::User),
+        ["install", "--system", library] => install(library, Scope::System),
+        _ => usage(),
+    };
+    match result {
+        Ok(message) => println!("{}", message),
+        Err(error) => {
+            eprintln!("error: {}", error);
+            process::exit(1);
+        }
+    }
+}
+
+fn install(library: &str, scope: Scope) -> Result<String, String> {
+    install::install(Path::new(library), scope)
+        .map(|path| format!("Installed {}", path.display()))
+        .map_err(|error| error.to_string())
+}
@@ -0,0 +1,242 @@
+//! Smoke tests LADSPA plugin libraries before they are installed into a host:
+//!
+//! ```text
+//! ladspa-validate target/release/libmy_plugins.so
+//! ```
+//!
+//! Every plugin in each library has its descriptor checked for the mistakes hosts trip over, then
+//! is instantiated at several sample rates with silence and an impulse run through it. Outputs
+//! which are NaN, infinite or denormal are reported. Exits with status 1 if any problem was found.
+
+extern crate ladspa;
+
+use std::env;
+use std::process;
+
+use ladspa::{Data, PortDescriptor};
+use ladspa::ffi::ladspa_h;
+use ladspa::host::{self, LoadedPlugin, PluginInstance};
+
+const SAMPLE_RATES: [u64; 4] = [22050, 44100, 48000, 96000];
+const BLOCK_SIZE: usize = 512;
+// The number of blocks each signal lasts, long enough for unstable feedback to blow up.
+const BLOCKS: usize = 64;
+
+fn main() {
+    let paths: Vec<String> = env::args().skip(1).collect();
+    if paths.is_empty() {
+        eprintln!("usage: ladspa-validate LIBRARY...");
+        process::exit(2);
+    }
+
+    let mut failed = false;
+    for path in &paths {
+        let plugins = match host::load(path) {
+            Ok(plugins) => plugins,
+            Err(error) => {
+                println!("{}: {}", path, error);
+                failed = true;
+                continue;
+            }
+        };
+        if plugins.is_empty() {
+            println!("{}: no plugins found", path);
+            failed = true;
+        }
+        for plugin in &plugins {
+            let problems = validate(plugin);
+            if problems.is_empty() {
+                println!("{}: {} ({}): ok", path, plugin.label(), plugin.unique_id());
+            } else {
+                println!("{}: {} ({}):", path, plugin.label(), plugin.unique_id());
+                for problem in &problems {
+                    println!("    {}", problem);
+                }
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
+fn validate(plugin: &LoadedPlugin) -> Vec<String> {
+    let mut problems = check_descriptor(plugin);
+    // Running a plugin whose ports can't be told apart would only report meaningless failures.
+    let mut ports = 0..plugin.port_count();
+    if ports.all(|port| legal_port(plugin.port_descriptor_bits(port))) {
+        for &sample_rate in &SAMPLE_RATES {
+            problems.extend(check_output(plugin, sample_rate));
+        }
+    }
+    problems
+}
+
+// Whether a port is exactly one of input and output, and exactly one of audio and control.
+fn legal_port(bits: i32) -> bool {
+    let direction = bits & (ladspa_h::PORT_INPUT | ladspa_h::PORT_OUTPUT);
+    let kind = bits & (ladspa_h::PORT_AUDIO | ladspa_h::PORT_CONTROL);
+    bits & !0xf == 0 &&
+    (direction == ladspa_h::PORT_INPUT || direction == ladspa_h::PORT_OUTPUT) &&
+    (kind == ladspa_h::PORT_AUDIO || kind == ladspa_h::PORT_CONTROL)
+}
+
+fn check_descriptor(plugin: &LoadedPlugin) -> Vec<String> {
+    let mut problems = Vec::new();
+    if plugin.label().is_empty() || plugin.label().contains(char::is_whitespace) {
+        problems.push(format!("label \"{}\" is empty or contains whitespace", plugin.label()));
+    }
+    if plugin.name().is_empty() {
+        problems.push(String::from("name is empty"));
+    }
+    if !ladspa::unique_id::is_valid(plugin.unique_id()) {
+        problems.push(format!("unique ID is outside of 1 to {}", ladspa::unique_id::MAX));
+    }
+    for port in 0..plugin.port_count() {
+        let name = plugin.port_name(port);
+        if name.is_empty() {
+            problems.push(format!("port {} has an empty name", port));
+        }
+        let bits = plugin.port_descriptor_bits(port);
+        if !legal_port(bits) {
+            problems.push(format!("port \"{}\" has illegal descriptor flags {:#x}", name, bits));
+        }
+        if let Err(problem) = check_default(plugin, port) {
+            problems.push(format!("port \"{}\" {}", name, problem));
+        }
+    }
+    problems
+}
+
+// Checks that the default of `port` can be computed and lies within its bounds. The bounds of
+// `HINT_SAMPLE_RATE` ports depend on the sample rate, so those are checked at each one tested.
+fn check_default(plugin: &LoadedPlugin, port: usize) -> Result<(), String> {
+    let hint = plugin.port_range_hint(port);
+    let flags = hint.hint_descriptor;
+    let bound = |flag, value| if flags & flag != 0 { Some(value) } else { None };
+    let lower = bound(ladspa_h::HINT_BOUNDED_BELOW, hint.lower_bound);
+    let upper = bound(ladspa_h::HINT_BOUNDED_ABOVE, hint.upper_bound);
+    if let (Some(lower), Some(upper)) = (lower, upper) {
+        if lower > upper {
+            return Err(format!("has a lower bound of {} above its upper bound of {}",
+                               lower, upper));
+        }
+    }
+    let default = flags & ladspa_h::HINT_DEFAULT_MASK;
+    let (needs_lower, needs_upper) = match default {
+        0 => return Ok(()),
+        ladspa_h::HINT_DEFAULT_MINIMUM => (true, false),
+        ladspa_h::HINT_DEFAULT_LOW | ladspa_h::HINT_DEFAULT_MIDDLE |
+        ladspa_h::HINT_DEFAULT_HIGH => (true, true),
+        ladspa_h::HINT_DEFAULT_MAXIMUM => (false, true),
+        ladspa_h::HINT_DEFAULT_0 | ladspa_h::HINT_DEFAULT_1 | ladspa_h::HINT_DEFAULT_100 |
+        ladspa_h::HINT_DEFAULT_440 => (false, false),
+        other => return Err(format!("has an unknown default {:#x}", other)),
+    };
+    if needs_lower && lower.is_none() || needs_upper && upper.is_none() {
+        return Err(String::from("has a default relative to a bound it doesn't have"));
+    }
+    if needs_lower && needs_upper && flags & ladspa_h::HINT_LOGARITHMIC != 0 &&
+       (hint.lower_bound <= 0.0 || hint.upper_bound <= 0.0) {
+        return Err(String::from("has a logarithmic default but bounds which are not positive"));
+    }
+    let scaled = flags & ladspa_h::HINT_SAMPLE_RATE != 0;
+    let sample_rates: &[u64] = if scaled { &SAMPLE_RATES } else { &[1] };
+    for &sample_rate in sample_rates {
+        let value = plugin.default_value(port, sample_rate).unwrap_or(0.0);
+        let scale = if scaled { sample_rate as Data } else { 1.0 };
+        if lower.is_some_and(|lower| value < lower * scale) ||
+           upper.is_some_and(|upper| value > upper * scale) {
+            return Err(if scaled {
+                format!("has a default of {} outside of its bounds at {} Hz", value, sample_rate)
+            } else {
+                format!("has a default of {} outside of its bounds", value)
+            });
+        }
+        if flags & ladspa_h::HINT_TOGGLED != 0 && value != 0.0 && value != 1.0 {
+            return Err(format!("is toggled but has a default of {}", value));
+        }
+    }
+    Ok(())
+}
+
+fn check_output(plugin: &LoadedPlugin, sample_rate: u64) -> Vec<String> {
+    let mut problems = Vec::new();
+    for &(signal, impulse) in &[("silence", false), ("an impulse", true)] {
+        let mut instance = match plugin.instantiate(sample_rate, BLOCK_SIZE) {
+            Ok(instance) => instance,
+            Err(error) => {
+                problems.push(format!("at {} Hz: {}", sample_rate, error));
+                return problems;
+            }
+        };
+        set_lower_bounds(plugin, &mut instance, sample_rate);
+        instance.activate();
+        for block in 0..BLOCKS {
+            for port in 0..plugin.port_count() {
+                if let PortDescriptor::AudioInput = plugin.port_descriptor(port) {
+                    let input = instance.input_mut(port);
+                    for sample in input.iter_mut() {
+                        *sample = 0.0;
+                    }
+                    if impulse && block == 0 {
+                        input[0] = 1.0;
+                    }
+                }
+            }
+            instance.run(BLOCK_SIZE);
+            if let Some(problem) = find_bad_output(plugin, &instance) {
+                problems.push(format!("at {} Hz, with {}: {} in block {}",
+                                      sample_rate, signal, problem, block));
+                break;
+            }
+        }
+    }
+    problems
+}
+
+// Sets the control inputs without a default, which instances start at 0, to their lower bound
+// if they have one.
+fn set_lower_bounds(plugin: &LoadedPlugin, instance: &mut PluginInstance, sample_rate: u64) {
+    for port in 0..plugin.port_count() {
+        let hint = plugin.port_range_hint(port);
+        if let PortDescriptor::ControlInput = plugin.port_descriptor(port) {
+            if plugin.default_value(port, sample_rate).is_none() &&
+               hint.hint_descriptor & ladspa_h::HINT_BOUNDED_BELOW != 0 {
+                let mut value = hint.lower_bound;
+                if hint.hint_descriptor & ladspa_h::HINT_SAMPLE_RATE != 0 {
+                    value *= sample_rate as Data;
+                }
+                instance.set_control(port, value);
+            }
+        }
+    }
+}
+
+fn find_bad_output(plugin: &LoadedPlugin, instance: &PluginInstance) -> Option<String> {
+    for port in 0..plugin.port_count() {
+        let control;
+        let values = match plugin.port_descriptor(port) {
+            PortDescriptor::AudioOutput => instance.output(port),
+            PortDescriptor::ControlOutput => {
+                control = [instance.control(port)];
+                &control[..]
+            }
+            _ => continue,
+        };
+        let kind = values.iter().find_map(|&value| if value.is_nan() {
+            Some("NaN")
+        } else if value.is_infinite() {
+            Some("infinite")
+        } else if value.is_subnormal() {
+            Some("denormal")
+        } else {
+            None
+        });
+        if let Some(kind) = kind {
+            return Some(format!("output \"{}\" is {}", plugin.port_name(port), kind));
+        }
+    }
+    None
+}
@@ -0,0 +1,319 @@
+use core::error::Error;
+use core::fmt;
+use core::ops::Range;
+use alloc::vec::Vec;
+
+use super::{Category, Data, DefaultValue, PluginConstructor, PluginDescriptor, Port, PortDescriptor,
+            PortGroup, Preset, Properties, Unit, PROP_NONE};
+use super::adapter::{new_frame, FramePlugin};
+use super::channel_names;
+use super::unique_id;
+
+/// Builds a ```PluginDescriptor``` one property at a time, checking the result for mistakes
+/// which would otherwise only show up once the plugin is loaded by a host.
+///
+/// ```no_run
+/// # use ladspa::{DefaultValue, InstantiateError, Plugin, PluginDescriptor};
+/// # fn new_amp(_: &PluginDescriptor, _: u64)
+/// #            -> Result<Box<dyn Plugin + Send>, InstantiateError> { unimplemented!() }
+/// # let _ =
+/// PluginDescriptor::builder()
+///     .unique_id(1234)
+///     .label("amp")
+///     .name("Amplifier")
+///     .audio_in("Input")
+///     .audio_out("Output")
+///     .control_in("Gain", 0.0..2.0, DefaultValue::Middle)
+///     .new(new_amp)
+///     .build()
+/// # ;
+/// ```
+pub struct PluginDescriptorBuilder {
+    unique_id: Option<u64>,
+    label: Option<&'static str>,
+    properties: Properties,
+    name: Option<&'static str>,
+    maker: &'static str,
+    copyright: &'static str,
+    category: Option<Category>,
+    ports: Vec<Port>,
+    presets: Vec<Preset>,
+    new: Option<PluginConstructor>,
+}
+
+/// The ways in which building a ```PluginDescriptor``` can fail.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuildError {
+    /// No unique ID was given.
+    MissingUniqueId,
+    /// No label was given.
+    MissingLabel,
+    /// No name was given.
+    MissingName,
+    /// No constructor function was given.
+    MissingConstructor,
+    /// The unique ID is 0 or above ```unique_id::MAX```.
+    InvalidUniqueId(u64),
+    /// The label is empty or contains whitespace.
+    InvalidLabel(&'static str),
+    /// The named port has a lower bound greater than its upper bound.
+    InvalidBounds(&'static str),
+    /// The named port has an ```Invalid``` port descriptor.
+    InvalidPort(&'static str),
+    /// The named preset sets a port which isn't a control input.
+    InvalidPreset(&'static str),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BuildError::MissingUniqueId => write!(f, "no unique ID was given"),
+            BuildError::MissingLabel => write!(f, "no label was given"),
+            BuildError::MissingName => write!(f, "no name was given"),
+            BuildError::MissingConstructor => write!(f, "no constructor function was given"),
+            BuildError::InvalidUniqueId(id) => {
+                write!(f, "unique ID {} is outside of 1 to {}", id, unique_id::MAX)
+            }
+            BuildError::InvalidLabel(label) => {
+                write!(f, "label \"{}\" is empty or contains whitespace", label)
+            }
+            BuildError::InvalidBounds(port) => {
+                write!(f, "port \"{}\" has a lower bound above its upper bound", port)
+            }
+            BuildError::InvalidPort(port) => {
+                write!(f, "port \"{}\" has an invalid port descriptor", port)
+            }
+            BuildError::InvalidPreset(preset) => {
+                write!(f, "preset \"{}\" sets a port which is not a control input", preset)
+            }
+        }
+    }
+}
+
+impl Error for BuildError {}
+
+impl PluginDescriptor {
+    /// Returns a ```PluginDescriptorBuilder``` with no properties and an empty port list.
+    pub fn builder() -> PluginDescriptorBuilder {
+        PluginDescriptorBuilder {
+            unique_id: None,
+            label: None,
+            properties: PROP_NONE,
+            name: None,
+            maker: "",
+            copyright: "None",
+            category: None,
+            ports: Vec::new(),
+            presets: Vec::new(),
+            new: None,
+        }
+    }
+}
+
+impl PluginDescriptorBuilder {
+    /// Sets ```PluginDescriptor::unique_id```. Required, and must be between 1 and
+    /// ```unique_id::MAX```.
+    pub fn unique_id(mut self, unique_id: u64) -> PluginDescriptorBuilder {
+        self.unique_id = Some(unique_id);
+        self
+    }
+
+    /// Sets ```PluginDescriptor::label```. Required, and may not contain whitespace.
+    pub fn label(mut self, label: &'static str) -> PluginDescriptorBuilder {
+        self.label = Some(label);
+        self
+    }
+
+    /// Sets ```PluginDescriptor::properties```. Defaults to ```PROP_NONE```.
+    pub fn properties(mut self, properties: Properties) -> PluginDescriptorBuilder {
+        self.properties = properties;
+        self
+    }
+
+    /// Sets ```PluginDescriptor::name```. Required.
+    pub fn name(mut self, name: &'static str) -> PluginDescriptorBuilder {
+        self.name = Some(name);
+        self
+    }
+
+    /// Sets ```PluginDescriptor::maker```. Defaults to an empty string.
+    pub fn maker(mut self, maker: &'static str) -> PluginDescriptorBuilder {
+        self.maker = maker;
+        self
+    }
+
+    /// Sets ```PluginDescriptor::copyright```. Defaults to "None".
+    pub fn copyright(mut self, copyright: &'static str) -> PluginDescriptorBuilder {
+        self.copyright = copyright;
+        self
+    }
+
+    /// Sets ```PluginDescriptor::category```. Defaults to none.
+    pub fn category(mut self, category: Category) -> PluginDescriptorBuilder {
+        self.category = Some(category);
+        self
+    }
+
+    /// Sets ```PluginDescriptor::new```. Required.
+    pub fn new(mut self, new: PluginConstructor) -> PluginDescriptorBuilder {
+        self.new = Some(new);
+        self
+    }
+
+    /// Appends an arbitrary port.
+    pub fn port(mut self, port: Port) -> PluginDescriptorBuilder {
+        self.ports.push(port);
+        self
+    }
+
+    /// Appends an audio input port.
+    pub fn audio_in(self, name: &'static str) -> PluginDescriptorBuilder {
+        self.port(Port {
+            name,
+            desc: PortDescriptor::AudioInput,
+            ..Default::default()
+        })
+    }
+
+    /// Appends an audio output port.
+    pub fn audio_out(self, name: &'static str) -> PluginDescriptorBuilder {
+        self.port(Port {
+            name,
+            desc: PortDescriptor::AudioOutput,
+            ..Default::default()
+        })
+    }
+
+    /// Appends a port for each channel of ```group```.
+    pub fn group(mut self, group: PortGroup) -> PluginDescriptorBuilder {
+        self.ports.extend(group.ports());
+        self
+    }
+
+    /// Sets ```new``` to create instances of the ```FramePlugin``` ```P``` and appends its audio
+    /// ports: ```IN``` inputs, then ```OUT``` outputs. A single channel is a port named "Input"
+    /// or "Output", two are a ```PortGroup::stereo_in``` or ```stereo_out``` of that name, six a
+    /// 5.1 surround group and any other number a group of channels numbered from 1.
+    pub fn frame_plugin<P, const IN: usize, const OUT: usize>(self) -> PluginDescriptorBuilder
+        where P: FramePlugin<IN, OUT> + 'static
+    {
+        self.new(new_frame::<P, IN, OUT>)
+            .channels(PortDescriptor::AudioInput, "Input", IN)
+            .channels(PortDescriptor::AudioOutput, "Output", OUT)
+    }
+
+    // Appends `count` audio ports of the kind `desc`, named as described in `frame_plugin`.
+    fn channels(self, desc: PortDescriptor, name: &'static str, count: usize)
+                -> PluginDescriptorBuilder {
+        match count {
+            0 => self,
+            1 => {
+                self.port(Port {
+                    name,
+                    desc,
+                    ..Default::default()
+                })
+            }
+            _ => {
+                self.group(PortGroup {
+                    name,
+                    desc,
+                    channels: channel_names(count),
+                })
+            }
+        }
+    }
+
+    /// Appends a control input port bounded by ```range```, with the ```Unit``` its name ends
+    /// with in parentheses, if any.
+    pub fn control_in(self,
+                      name: &'static str,
+                      range: Range<Data>,
+                      default: DefaultValue)
+                      -> PluginDescriptorBuilder {
+        self.port(Port {
+            name,
+            desc: PortDescriptor::ControlInput,
+            hint: None,
+            default: Some(default),
+            lower_bound: Some(range.start),
+            upper_bound: Some(range.end),
+            group: None,
+            scale_points: &[],
+            unit: Unit::from_name(name),
+        })
+    }
+
+    /// Appends an unbounded control output port.
+    pub fn control_out(self, name: &'static str) -> PluginDescriptorBuilder {
+        self.port(Port {
+            name,
+            desc: PortDescriptor::ControlOutput,
+            ..Default::default()
+        })
+    }
+
+    /// Appends a ```Port::latency_output```.
+    pub fn latency_out(self) -> PluginDescriptorBuilder {
+        self.port(Port::latency_output())
+    }
+
+    /// Appends a factory preset setting each listed control input, by port index, to a value.
+    pub fn preset(mut self,
+                  name: &'static str,
+                  values: &[(usize, Data)])
+                  -> PluginDescriptorBuilder {
+        self.presets.push(Preset {
+            name,
+            values: values.to_vec(),
+        });
+        self
+    }
+
+    /// Checks the collected properties and returns the finished ```PluginDescriptor```.
+    pub fn build(self) -> Result<PluginDescriptor, BuildError> {
+        let unique_id = self.unique_id.ok_or(BuildError::MissingUniqueId)?;
+        let label = self.label.ok_or(BuildError::MissingLabel)?;
+        let name = self.name.ok_or(BuildError::MissingName)?;
+        let new = self.new.ok_or(BuildError::MissingConstructor)?;
+
+        if !unique_id::is_valid(unique_id) {
+            return Err(BuildError::InvalidUniqueId(unique_id));
+        }
+        if label.is_empty() || label.contains(char::is_whitespace) {
+            return Err(BuildError::InvalidLabel(label));
+        }
+        for port in &self.ports {
+            if let PortDescriptor::Invalid = port.desc {
+                return Err(BuildError::InvalidPort(port.name));
+            }
+            if let (Some(lower), Some(upper)) = (port.lower_bound, port.upper_bound) {
+                if lower > upper {
+                    return Err(BuildError::InvalidBounds(port.name));
+                }
+            }
+        }
+
+        for preset in &self.presets {
+            for &(index, _) in &preset.values {
+                match self.ports.get(index).map(|port| port.desc) {
+                    Some(PortDescriptor::ControlInput) => {}
+                    _ => return Err(BuildError::InvalidPreset(preset.name)),
+                }
+            }
+        }
+
+        Ok(PluginDescriptor {
+            unique_id,
+            label,
+            properties: self.properties,
+            name,
+            maker: self.maker,
+            copyright: self.copyright,
+            category: self.category,
+            ports: self.ports,
+            presets: self.presets,
+            new,
+        })
+    }
+}
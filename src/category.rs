@@ -0,0 +1,113 @@
+/// What kind of processing a plugin does, following the class hierarchy of the LADSPA RDF
+/// ontology. Hosts reading the RDF or LV2 metadata file plugins under it in their menus, instead
+/// of leaving them uncategorized.
+///
+/// Pick the most specific one which fits: a ```Lowpass``` plugin is also listed under
+/// ```Filter```, and a ```Compressor``` under ```Dynamics```.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Category {
+    /// Utilities such as meters, mixers and format converters.
+    Utility,
+    /// Sound sources.
+    Generator,
+    /// Periodic sound sources.
+    Oscillator,
+    /// Simulations of acoustic spaces or devices.
+    Simulator,
+    /// Reverberation.
+    Reverb,
+    /// Effects working in the time domain.
+    Time,
+    /// Delays and echoes.
+    Delay,
+    /// Phasers.
+    Phaser,
+    /// Flangers.
+    Flanger,
+    /// Choruses.
+    Chorus,
+    /// Effects working in the frequency domain.
+    Frequency,
+    /// Frequency analysers.
+    FrequencyMeter,
+    /// Filters.
+    Filter,
+    /// Lowpass filters.
+    Lowpass,
+    /// Highpass filters.
+    Highpass,
+    /// Bandpass filters.
+    Bandpass,
+    /// Comb filters.
+    Comb,
+    /// Allpass filters.
+    Allpass,
+    /// Equalizers.
+    Eq,
+    /// Parametric equalizers.
+    ParaEq,
+    /// Graphic equalizers, with many fixed bands.
+    MultiEq,
+    /// Pitch shifters.
+    Pitch,
+    /// Effects on the amplitude of the signal.
+    Amplitude,
+    /// Gains and amplifiers.
+    Amplifier,
+    /// Waveshapers.
+    Waveshaper,
+    /// Ring and amplitude modulators.
+    Modulator,
+    /// Distortion.
+    Distortion,
+    /// Dynamics processors.
+    Dynamics,
+    /// Compressors.
+    Compressor,
+    /// Expanders.
+    Expander,
+    /// Limiters.
+    Limiter,
+    /// Noise gates.
+    Gate,
+}
+
+impl Category {
+    /// The name of the class in the LADSPA RDF ontology, such as ```"LowpassPlugin"```.
+    pub fn rdf_class(self) -> &'static str {
+        match self {
+            Category::Utility => "UtilityPlugin",
+            Category::Generator => "GeneratorPlugin",
+            Category::Oscillator => "OscillatorPlugin",
+            Category::Simulator => "SimulatorPlugin",
+            Category::Reverb => "ReverbPlugin",
+            Category::Time => "TimePlugin",
+            Category::Delay => "DelayPlugin",
+            Category::Phaser => "PhaserPlugin",
+            Category::Flanger => "FlangerPlugin",
+            Category::Chorus => "ChorusPlugin",
+            Category::Frequency => "FrequencyPlugin",
+            Category::FrequencyMeter => "FrequencyMeterPlugin",
+            Category::Filter => "FilterPlugin",
+            Category::Lowpass => "LowpassPlugin",
+            Category::Highpass => "HighpassPlugin",
+            Category::Bandpass => "BandpassPlugin",
+            Category::Comb => "CombPlugin",
+            Category::Allpass => "AllpassPlugin",
+            Category::Eq => "EQPlugin",
+            Category::ParaEq => "ParaEQPlugin",
+            Category::MultiEq => "MultiEQPlugin",
+            Category::Pitch => "PitchPlugin",
+            Category::Amplitude => "AmplitudePlugin",
+            Category::Amplifier => "AmplifierPlugin",
+            Category::Waveshaper => "WaveshaperPlugin",
+            Category::Modulator => "ModulatorPlugin",
+            Category::Distortion => "DistortionPlugin",
+            Category::Dynamics => "DynamicsPlugin",
+            Category::Compressor => "CompressorPlugin",
+            Category::Expander => "ExpanderPlugin",
+            Category::Limiter => "LimiterPlugin",
+            Category::Gate => "GatePlugin",
+        }
+    }
+}
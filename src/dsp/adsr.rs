@@ -0,0 +1,239 @@
+use Data;
+use super::Sample;
+#[cfg(not(any(feature = "std", test)))]
+use math::Float;
+
+/// The segment an ```Adsr``` is in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Stage {
+    /// The gate is off and the envelope has finished releasing; its value is 0.
+    Idle,
+    /// Rising towards 1 after the gate opened.
+    Attack,
+    /// Falling from 1 towards the sustain level.
+    Decay,
+    /// Holding the sustain level until the gate closes.
+    Sustain,
+    /// Falling towards 0 after the gate closed.
+    Release,
+}
+
+/// What happens when the gate of an ```Adsr``` opens while the envelope is still sounding.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Retrigger {
+    /// Attacks from the current value, avoiding clicks. Suits legato playing.
+    FromCurrent,
+    /// Jumps back to 0 and attacks from there, so every note starts the same way.
+    FromZero,
+}
+
+// How far past their target the exponential curves aim, relative to the full scale. The attack
+// aims well past 1 so it stays close to a straight line; the decay and release aim barely past
+// their targets, giving the usual fast-then-slow exponential fall. A segment ends when it
+// crosses its target.
+const ATTACK_OVERSHOOT: f64 = 0.3;
+const DECAY_OVERSHOOT: f64 = 0.0001;
+
+/// An attack, decay, sustain, release envelope generator for instruments and gates.
+///
+/// Segments are exponential curves and their times are in seconds: the attack and decay times
+/// are the times taken to rise from 0 to 1 and fall from 1 to the sustain level, and the release
+/// time the time taken to fall from 1 to 0, so shorter falls take proportionally less time.
+///
+/// The gate is sample accurate: open and close it between calls to ```tick```, or between calls
+/// to ```fill``` on the parts of the block before and after an event:
+///
+/// ```rust,ignore
+/// let mut position = 0;
+/// for event in events {
+///     let offset = event.time.tick as usize;
+///     self.envelope.fill(&mut envelope[position..offset]);
+///     position = offset;
+///     match event.kind {
+///         NoteOn { .. } => self.envelope.gate_on(),
+///         NoteOff { .. } => self.envelope.gate_off(),
+///         _ => (),
+///     }
+/// }
+/// self.envelope.fill(&mut envelope[position..sample_count]);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Adsr<S: Sample = Data> {
+    attack_time: f32,
+    decay_time: f32,
+    sustain: f32,
+    release_time: f32,
+    sample_rate: u64,
+    retrigger: Retrigger,
+    stage: Stage,
+    value: S,
+    // Each segment computes `value = base + value * coefficient`.
+    attack: (S, S),
+    decay: (S, S),
+    release: (S, S),
+}
+
+// The base and coefficient of an exponential segment from `from` to `to`, aiming `overshoot`
+// past `to` and taking `time` seconds.
+fn segment<S: Sample>(from: f64, to: f64, overshoot: f64, time: f32, sample_rate: u64) -> (S, S) {
+    let samples = f64::from(time) * sample_rate as f64;
+    let aim = if to > from { to + overshoot } else { to - overshoot };
+    let coefficient = if samples > 0.0 {
+        (-((from - aim) / (to - aim)).abs().ln() / samples).exp()
+    } else {
+        0.0
+    };
+    (S::from_f64(aim * (1.0 - coefficient)), S::from_f64(coefficient))
+}
+
+impl<S: Sample> Adsr<S> {
+    /// Creates an idle envelope. The sustain level is between 0 and 1.
+    pub fn new(attack_time: f32, decay_time: f32, sustain: f32, release_time: f32,
+               sample_rate: u64)
+               -> Adsr<S> {
+        let mut adsr = Adsr {
+            attack_time,
+            decay_time,
+            sustain: sustain.clamp(0.0, 1.0),
+            release_time,
+            sample_rate,
+            retrigger: Retrigger::FromCurrent,
+            stage: Stage::Idle,
+            value: S::ZERO,
+            attack: (S::ZERO, S::ZERO),
+            decay: (S::ZERO, S::ZERO),
+            release: (S::ZERO, S::ZERO),
+        };
+        adsr.update();
+        adsr
+    }
+
+    fn update(&mut self) {
+        let sustain = f64::from(self.sustain);
+        self.attack = segment(0.0, 1.0, ATTACK_OVERSHOOT, self.attack_time, self.sample_rate);
+        self.decay = segment(1.0, sustain, DECAY_OVERSHOOT, self.decay_time, self.sample_rate);
+        self.release = segment(1.0, 0.0, DECAY_OVERSHOOT, self.release_time, self.sample_rate);
+    }
+
+    /// Changes the attack time, in seconds.
+    pub fn set_attack(&mut self, time: f32) {
+        if time != self.attack_time {
+            self.attack_time = time;
+            self.update();
+        }
+    }
+
+    /// Changes the decay time, in seconds.
+    pub fn set_decay(&mut self, time: f32) {
+        if time != self.decay_time {
+            self.decay_time = time;
+            self.update();
+        }
+    }
+
+    /// Changes the sustain level, between 0 and 1.
+    pub fn set_sustain(&mut self, level: f32) {
+        let level = level.clamp(0.0, 1.0);
+        if level != self.sustain {
+            self.sustain = level;
+            self.update();
+        }
+    }
+
+    /// Changes the release time, in seconds.
+    pub fn set_release(&mut self, time: f32) {
+        if time != self.release_time {
+            self.release_time = time;
+            self.update();
+        }
+    }
+
+    /// Changes the sample rate, keeping the times in seconds.
+    pub fn set_sample_rate(&mut self, sample_rate: u64) {
+        if sample_rate != self.sample_rate {
+            self.sample_rate = sample_rate;
+            self.update();
+        }
+    }
+
+    /// Changes what opening the gate does while the envelope is sounding. Defaults to
+    /// ```Retrigger::FromCurrent```.
+    pub fn set_retrigger(&mut self, retrigger: Retrigger) {
+        self.retrigger = retrigger;
+    }
+
+    /// Opens the gate, starting the attack.
+    pub fn gate_on(&mut self) {
+        if self.retrigger == Retrigger::FromZero {
+            self.value = S::ZERO;
+        }
+        self.stage = Stage::Attack;
+    }
+
+    /// Closes the gate, starting the release unless the envelope is already idle.
+    pub fn gate_off(&mut self) {
+        if self.stage != Stage::Idle {
+            self.stage = Stage::Release;
+        }
+    }
+
+    /// Silences the envelope at once, as needed in ```Plugin::activate```.
+    pub fn reset(&mut self) {
+        self.stage = Stage::Idle;
+        self.value = S::ZERO;
+    }
+
+    /// The current segment.
+    pub fn stage(&self) -> Stage {
+        self.stage
+    }
+
+    /// Whether the envelope is sounding, that is, not idle. Voices can be freed once this is
+    /// false.
+    pub fn is_active(&self) -> bool {
+        self.stage != Stage::Idle
+    }
+
+    /// The current value, between 0 and 1.
+    pub fn value(&self) -> S {
+        self.value
+    }
+
+    /// Advances by one sample and returns the new value.
+    pub fn tick(&mut self) -> S {
+        let sustain = S::from_f64(f64::from(self.sustain));
+        match self.stage {
+            Stage::Idle => (),
+            Stage::Attack => {
+                self.value = self.attack.0 + self.value * self.attack.1;
+                if self.value >= S::ONE {
+                    self.value = S::ONE;
+                    self.stage = Stage::Decay;
+                }
+            }
+            Stage::Decay => {
+                self.value = self.decay.0 + self.value * self.decay.1;
+                if self.value <= sustain {
+                    self.value = sustain;
+                    self.stage = Stage::Sustain;
+                }
+            }
+            Stage::Sustain => self.value = sustain,
+            Stage::Release => {
+                self.value = self.release.0 + self.value * self.release.1;
+                if self.value <= S::ZERO {
+                    self.value = S::ZERO;
+                    self.stage = Stage::Idle;
+                }
+            }
+        }
+        self.value
+    }
+
+    /// Fills ```out``` with the next values of the envelope.
+    pub fn fill(&mut self, out: &mut [S]) {
+        for sample in out.iter_mut() {
+            *sample = self.tick();
+        }
+    }
+}
@@ -0,0 +1,364 @@
+//! Second order IIR filters, with coefficients from Robert Bristow-Johnson's "Audio EQ Cookbook".
+//!
+//! ```no_run
+//! # use ladspa::PortConnection;
+//! # use ladspa::dsp::biquad::*;
+//! # struct P { filter: Biquad }
+//! # fn new(sample_rate: u64) -> P {
+//! // In the constructor:
+//! let filter = Biquad::new(FilterType::Lowpass, 1000.0, BUTTERWORTH_Q, sample_rate);
+//! # P { filter }
+//! # }
+//! # impl P { fn run(&mut self, ports: &[&PortConnection], input: &[f32], mut output: &mut [f32]) {
+//!
+//! // In run, once per block, then over the samples:
+//! self.filter.set_frequency(ports[2].unwrap_control());
+//! self.filter.process(input, &mut output);
+//! # }
+//! # }
+//! ```
+//!
+//! Coefficients are always computed in ```f64```; the filter state is kept in the ```Sample```
+//! type of the filter. Filters with very low cutoff frequencies relative to the sample rate are
+//! noticeably more accurate in ```f64```.
+
+use core::f64::consts::PI;
+
+use Data;
+use super::Sample;
+#[cfg(not(any(feature = "std", test)))]
+use math::Float;
+
+/// The Q giving a maximally flat passband for ```Lowpass``` and ```Highpass``` filters.
+pub const BUTTERWORTH_Q: f32 = core::f32::consts::FRAC_1_SQRT_2;
+
+/// The response of a biquad filter. The gains of the peaking and shelving filters are in dB.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FilterType {
+    /// Passes frequencies below the cutoff, with a 12 dB per octave slope above it.
+    Lowpass,
+    /// Passes frequencies above the cutoff, with a 12 dB per octave slope below it.
+    Highpass,
+    /// Passes a band around the center frequency, with a peak gain of 0 dB. Higher Qs give
+    /// narrower bands.
+    Bandpass,
+    /// Removes a band around the center frequency.
+    Notch,
+    /// Passes all frequencies, shifting their phase around the center frequency.
+    Allpass,
+    /// Boosts or cuts a band around the center frequency.
+    Peaking { gain: f32 },
+    /// Boosts or cuts frequencies below the corner frequency.
+    LowShelf { gain: f32 },
+    /// Boosts or cuts frequencies above the corner frequency.
+    HighShelf { gain: f32 },
+}
+
+/// The coefficients of a biquad filter, normalized so that ```a0``` is 1:
+///
+/// ```text
+/// y[n] = b0 x[n] + b1 x[n-1] + b2 x[n-2] - a1 y[n-1] - a2 y[n-2]
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Coefficients<S: Sample = Data> {
+    pub b0: S,
+    pub b1: S,
+    pub b2: S,
+    pub a1: S,
+    pub a2: S,
+}
+
+impl<S: Sample> Coefficients<S> {
+    /// Coefficients passing the input through unchanged.
+    pub fn identity() -> Coefficients<S> {
+        Coefficients { b0: S::ONE, b1: S::ZERO, b2: S::ZERO, a1: S::ZERO, a2: S::ZERO }
+    }
+
+    /// Computes the coefficients of a filter of type ```filter``` at ```frequency``` Hz. The
+    /// frequency is limited to just below the Nyquist frequency, and Q to a small positive value,
+    /// keeping the filter stable whatever the control inputs are set to.
+    pub fn new(filter: FilterType, frequency: f32, q: f32, sample_rate: u64)
+               -> Coefficients<S> {
+        let sample_rate = sample_rate as f64;
+        let frequency = f64::from(frequency).clamp(0.0, sample_rate * 0.499);
+        let q = f64::from(q).max(1e-3);
+        let w0 = 2.0 * PI * frequency / sample_rate;
+        let (sin, cos) = (w0.sin(), w0.cos());
+        let alpha = sin / (2.0 * q);
+        let amplitude = |gain: f32| 10.0f64.powf(f64::from(gain) / 40.0);
+
+        let (b0, b1, b2, a0, a1, a2) = match filter {
+            FilterType::Lowpass => {
+                let b = (1.0 - cos) / 2.0;
+                (b, 2.0 * b, b, 1.0 + alpha, -2.0 * cos, 1.0 - alpha)
+            }
+            FilterType::Highpass => {
+                let b = (1.0 + cos) / 2.0;
+                (b, -2.0 * b, b, 1.0 + alpha, -2.0 * cos, 1.0 - alpha)
+            }
+            FilterType::Bandpass => (alpha, 0.0, -alpha, 1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+            FilterType::Notch => (1.0, -2.0 * cos, 1.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+            FilterType::Allpass => {
+                (1.0 - alpha, -2.0 * cos, 1.0 + alpha, 1.0 + alpha, -2.0 * cos, 1.0 - alpha)
+            }
+            FilterType::Peaking { gain } => {
+                let a = amplitude(gain);
+                (1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a,
+                 1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a)
+            }
+            FilterType::LowShelf { gain } => {
+                let a = amplitude(gain);
+                let k = 2.0 * a.sqrt() * alpha;
+                (a * ((a + 1.0) - (a - 1.0) * cos + k),
+                 2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                 a * ((a + 1.0) - (a - 1.0) * cos - k),
+                 (a + 1.0) + (a - 1.0) * cos + k,
+                 -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                 (a + 1.0) + (a - 1.0) * cos - k)
+            }
+            FilterType::HighShelf { gain } => {
+                let a = amplitude(gain);
+                let k = 2.0 * a.sqrt() * alpha;
+                (a * ((a + 1.0) + (a - 1.0) * cos + k),
+                 -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                 a * ((a + 1.0) + (a - 1.0) * cos - k),
+                 (a + 1.0) - (a - 1.0) * cos + k,
+                 2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                 (a + 1.0) - (a - 1.0) * cos - k)
+            }
+        };
+
+        Coefficients {
+            b0: S::from_f64(b0 / a0),
+            b1: S::from_f64(b1 / a0),
+            b2: S::from_f64(b2 / a0),
+            a1: S::from_f64(a1 / a0),
+            a2: S::from_f64(a2 / a0),
+        }
+    }
+
+    /// A ```Lowpass``` filter.
+    pub fn lowpass(frequency: f32, q: f32, sample_rate: u64) -> Coefficients<S> {
+        Coefficients::new(FilterType::Lowpass, frequency, q, sample_rate)
+    }
+
+    /// A ```Highpass``` filter.
+    pub fn highpass(frequency: f32, q: f32, sample_rate: u64) -> Coefficients<S> {
+        Coefficients::new(FilterType::Highpass, frequency, q, sample_rate)
+    }
+
+    /// A ```Bandpass``` filter.
+    pub fn bandpass(frequency: f32, q: f32, sample_rate: u64) -> Coefficients<S> {
+        Coefficients::new(FilterType::Bandpass, frequency, q, sample_rate)
+    }
+
+    /// A ```Peaking``` filter.
+    pub fn peaking(frequency: f32, q: f32, gain: f32, sample_rate: u64) -> Coefficients<S> {
+        Coefficients::new(FilterType::Peaking { gain }, frequency, q, sample_rate)
+    }
+
+    /// A ```LowShelf``` filter.
+    pub fn low_shelf(frequency: f32, q: f32, gain: f32, sample_rate: u64) -> Coefficients<S> {
+        Coefficients::new(FilterType::LowShelf { gain }, frequency, q, sample_rate)
+    }
+
+    /// A ```HighShelf``` filter.
+    pub fn high_shelf(frequency: f32, q: f32, gain: f32, sample_rate: u64) -> Coefficients<S> {
+        Coefficients::new(FilterType::HighShelf { gain }, frequency, q, sample_rate)
+    }
+}
+
+/// A biquad filter in transposed direct form II, which keeps the coefficients it was built from
+/// and recomputes them only when one of them, or the sample rate, changes. Calling the setters
+/// with the control inputs every block is therefore cheap.
+#[derive(Clone, Debug)]
+pub struct Biquad<S: Sample = Data> {
+    filter: FilterType,
+    frequency: f32,
+    q: f32,
+    sample_rate: u64,
+    coefficients: Coefficients<S>,
+    s1: S,
+    s2: S,
+}
+
+impl<S: Sample> Biquad<S> {
+    /// Creates a filter with silent state.
+    pub fn new(filter: FilterType, frequency: f32, q: f32, sample_rate: u64) -> Biquad<S> {
+        Biquad {
+            filter,
+            frequency,
+            q,
+            sample_rate,
+            coefficients: Coefficients::new(filter, frequency, q, sample_rate),
+            s1: S::ZERO,
+            s2: S::ZERO,
+        }
+    }
+
+    /// Changes the type, frequency and Q of the filter at once.
+    pub fn set(&mut self, filter: FilterType, frequency: f32, q: f32) {
+        if filter != self.filter || frequency != self.frequency || q != self.q {
+            self.filter = filter;
+            self.frequency = frequency;
+            self.q = q;
+            self.update();
+        }
+    }
+
+    /// Changes the type of the filter.
+    pub fn set_filter_type(&mut self, filter: FilterType) {
+        let (frequency, q) = (self.frequency, self.q);
+        self.set(filter, frequency, q);
+    }
+
+    /// Changes the cutoff or center frequency, in Hz.
+    pub fn set_frequency(&mut self, frequency: f32) {
+        let (filter, q) = (self.filter, self.q);
+        self.set(filter, frequency, q);
+    }
+
+    /// Changes the Q.
+    pub fn set_q(&mut self, q: f32) {
+        let (filter, frequency) = (self.filter, self.frequency);
+        self.set(filter, frequency, q);
+    }
+
+    /// Changes the sample rate, keeping the frequency in Hz.
+    pub fn set_sample_rate(&mut self, sample_rate: u64) {
+        if sample_rate != self.sample_rate {
+            self.sample_rate = sample_rate;
+            self.update();
+        }
+    }
+
+    fn update(&mut self) {
+        self.coefficients = Coefficients::new(self.filter, self.frequency, self.q,
+                                              self.sample_rate);
+    }
+
+    /// The type of the filter.
+    pub fn filter_type(&self) -> FilterType {
+        self.filter
+    }
+
+    /// The cutoff or center frequency, in Hz.
+    pub fn frequency(&self) -> f32 {
+        self.frequency
+    }
+
+    /// The Q.
+    pub fn q(&self) -> f32 {
+        self.q
+    }
+
+    /// The coefficients currently in use.
+    pub fn coefficients(&self) -> Coefficients<S> {
+        self.coefficients
+    }
+
+    /// Clears the filter state, as needed in ```Plugin::activate```.
+    pub fn reset(&mut self) {
+        self.s1 = S::ZERO;
+        self.s2 = S::ZERO;
+    }
+
+    /// Filters one sample.
+    pub fn tick(&mut self, input: S) -> S {
+        let c = &self.coefficients;
+        let output = c.b0 * input + self.s1;
+        self.s1 = c.b1 * input - c.a1 * output + self.s2;
+        self.s2 = c.b2 * input - c.a2 * output;
+        output
+    }
+
+    /// Filters ```input``` into ```output```, processing as many samples as the shorter one
+    /// holds.
+    pub fn process(&mut self, input: &[S], output: &mut [S]) {
+        for (out, &sample) in output.iter_mut().zip(input) {
+            *out = self.tick(sample);
+        }
+    }
+
+    /// Filters ```buf``` in place.
+    pub fn process_in_place(&mut self, buf: &mut [S]) {
+        for sample in buf.iter_mut() {
+            *sample = self.tick(*sample);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The gain of the filter at DC if `z` is 1, and at the Nyquist frequency if it is -1.
+    fn gain(c: &Coefficients<f64>, z: f64) -> f64 {
+        ((c.b0 + c.b1 * z + c.b2) / (1.0 + c.a1 * z + c.a2)).abs()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-6, "{} is not {}", actual, expected);
+    }
+
+    fn assert_coefficients(c: Coefficients<f64>, expected: [f64; 5]) {
+        for (&actual, &expected) in [c.b0, c.b1, c.b2, c.a1, c.a2].iter().zip(&expected) {
+            assert_close(actual, expected);
+        }
+    }
+
+    #[test]
+    fn coefficients_follow_the_audio_eq_cookbook() {
+        let q = BUTTERWORTH_Q;
+        assert_coefficients(Coefficients::lowpass(1000.0, q, 48000),
+                            [0.003916127, 0.007832253, 0.003916127, -1.815341083, 0.831005589]);
+        assert_coefficients(Coefficients::highpass(1000.0, q, 48000),
+                            [0.911586668, -1.823173336, 0.911586668, -1.815341083, 0.831005589]);
+        assert_coefficients(Coefficients::peaking(1000.0, 1.0, 6.0, 48000),
+                            [1.043953087, -1.895320724, 0.867722285, -1.895320724, 0.911675372]);
+    }
+
+    #[test]
+    fn passes_and_stops_at_dc_and_nyquist() {
+        let lowpass = Coefficients::lowpass(1000.0, BUTTERWORTH_Q, 48000);
+        assert_close(gain(&lowpass, 1.0), 1.0);
+        assert_close(gain(&lowpass, -1.0), 0.0);
+
+        let highpass = Coefficients::highpass(1000.0, BUTTERWORTH_Q, 48000);
+        assert_close(gain(&highpass, 1.0), 0.0);
+        assert_close(gain(&highpass, -1.0), 1.0);
+
+        let boost = 10.0f64.powf(6.0 / 20.0);
+        let low_shelf = Coefficients::low_shelf(1000.0, BUTTERWORTH_Q, 6.0, 48000);
+        assert_close(gain(&low_shelf, 1.0), boost);
+        assert_close(gain(&low_shelf, -1.0), 1.0);
+
+        let high_shelf = Coefficients::high_shelf(1000.0, BUTTERWORTH_Q, 6.0, 48000);
+        assert_close(gain(&high_shelf, 1.0), 1.0);
+        assert_close(gain(&high_shelf, -1.0), boost);
+    }
+
+    #[test]
+    fn filtering_dc_settles_at_the_dc_gain() {
+        let mut filter = Biquad::<f64>::new(FilterType::LowShelf { gain: -12.0 },
+                                            200.0,
+                                            BUTTERWORTH_Q,
+                                            48000);
+        let mut output = 0.0;
+        for _ in 0..48000 {
+            output = filter.tick(1.0);
+        }
+        assert_close(output, 10.0f64.powf(-12.0 / 20.0));
+    }
+
+    #[test]
+    fn changing_the_sample_rate_recomputes_the_coefficients() {
+        let mut filter = Biquad::<f64>::new(FilterType::Lowpass, 1000.0, BUTTERWORTH_Q, 48000);
+        filter.set_sample_rate(96000);
+        assert_eq!(filter.coefficients(), Coefficients::lowpass(1000.0, BUTTERWORTH_Q, 96000));
+        assert_eq!(filter.frequency(), 1000.0);
+        filter.set_sample_rate(44100);
+        assert_eq!(filter.coefficients(), Coefficients::lowpass(1000.0, BUTTERWORTH_Q, 44100));
+        assert_ne!(filter.coefficients(), Coefficients::lowpass(1000.0, BUTTERWORTH_Q, 48000));
+    }
+}
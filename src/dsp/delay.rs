@@ -0,0 +1,209 @@
+//! A delay line for echoes, chorus, flangers and reverbs.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use Data;
+use super::Sample;
+use units::{Division, TempoSync};
+
+/// How ```DelayLine::read_interpolated``` reads between samples.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Rounds the delay down to a whole number of samples.
+    None,
+    /// Linear interpolation between the two nearest samples. Cheap, but dulls high frequencies
+    /// when the delay is modulated.
+    Linear,
+    /// Cubic Hermite interpolation over the four nearest samples.
+    Cubic,
+}
+
+/// A ring buffer of past samples which can be read at any delay up to the one it was created
+/// for, including between samples.
+///
+/// A delay of ```n``` reads the sample written ```n``` calls to ```write``` ago, so reading
+/// before writing gives a feedback loop of exactly the requested delay:
+///
+/// ```no_run
+/// # use ladspa::dsp::delay::{DelayLine, Interpolation};
+/// # struct P { delay: DelayLine }
+/// # impl P {
+/// # fn run(&mut self, input: &[f32], output: &mut [f32], delay: f32, feedback: f32) {
+/// for (input, output) in input.iter().zip(output.iter_mut()) {
+///     let delayed = self.delay.read_interpolated(delay, Interpolation::Cubic);
+///     self.delay.write(*input + delayed * feedback);
+///     *output = *input + delayed;
+/// }
+/// # }
+/// # }
+/// ```
+///
+/// The storage is allocated once, in ```new```, rounded up to a power of two so wrapping around
+/// is a mask. Reading and writing never allocate.
+#[derive(Clone, Debug)]
+pub struct DelayLine<S: Sample = Data> {
+    buffer: Vec<S>,
+    mask: usize,
+    // Where the next sample will be written.
+    position: usize,
+    max_delay: usize,
+}
+
+impl<S: Sample> DelayLine<S> {
+    /// Creates a silent delay line holding up to ```max_delay``` samples.
+    pub fn new(max_delay: usize) -> DelayLine<S> {
+        let max_delay = max_delay.max(1);
+        // Cubic interpolation reads one sample past the longest delay.
+        let len = (max_delay + 2).next_power_of_two();
+        DelayLine {
+            buffer: vec![S::ZERO; len],
+            mask: len - 1,
+            position: 0,
+            max_delay,
+        }
+    }
+
+    /// Creates a silent delay line holding at least ```seconds``` of audio.
+    pub fn with_time(seconds: f32, sample_rate: u64) -> DelayLine<S> {
+        DelayLine::new((f64::from(seconds) * sample_rate as f64) as usize + 1)
+    }
+
+    /// Creates a silent delay line holding at least ```longest``` at tempos down to ```min_bpm```,
+    /// for delays synchronized with ```units::TempoSync```.
+    pub fn with_tempo(longest: Division, min_bpm: f32, sample_rate: u64) -> DelayLine<S> {
+        DelayLine::new(TempoSync::new(min_bpm, sample_rate).samples(longest) as usize + 1)
+    }
+
+    /// The longest delay which can be read, in samples.
+    pub fn max_delay(&self) -> usize {
+        self.max_delay
+    }
+
+    /// Silences the delay line, as needed in ```Plugin::activate```.
+    pub fn clear(&mut self) {
+        for sample in self.buffer.iter_mut() {
+            *sample = S::ZERO;
+        }
+    }
+
+    /// Pushes a sample into the delay line.
+    pub fn write(&mut self, sample: S) {
+        self.buffer[self.position] = sample;
+        self.position = (self.position + 1) & self.mask;
+    }
+
+    // The sample written `delay` writes ago, without clamping.
+    fn at(&self, delay: usize) -> S {
+        self.buffer[self.position.wrapping_sub(delay) & self.mask]
+    }
+
+    /// Reads the sample written ```delay``` samples ago. The delay is clamped between 1 and
+    /// ```max_delay```.
+    pub fn read(&self, delay: usize) -> S {
+        self.at(delay.clamp(1, self.max_delay))
+    }
+
+    /// Reads ```delay``` samples back, interpolating linearly. The delay is clamped between 1
+    /// and ```max_delay```, and a delay which isn't finite reads 1 sample back.
+    pub fn read_linear(&self, delay: f32) -> S {
+        let (whole, fraction) = self.split(delay, 1);
+        let (a, b) = (self.at(whole), self.at(whole + 1));
+        a + (b - a) * fraction
+    }
+
+    /// Reads ```delay``` samples back, using cubic Hermite interpolation. The interpolation
+    /// needs a sample on each side of the two it falls between, so the delay is clamped between
+    /// 2 and ```max_delay```, and a delay which isn't finite reads 2 samples back.
+    pub fn read_cubic(&self, delay: f32) -> S {
+        let (whole, fraction) = self.split(delay, 2);
+        let (y0, y1, y2, y3) =
+            (self.at(whole - 1), self.at(whole), self.at(whole + 1), self.at(whole + 2));
+        let half = S::from_f64(0.5);
+        let c1 = half * (y2 - y0);
+        let c2 = y0 - S::from_f64(2.5) * y1 + S::from_f64(2.0) * y2 - half * y3;
+        let c3 = half * (y3 - y0) + S::from_f64(1.5) * (y1 - y2);
+        ((c3 * fraction + c2) * fraction + c1) * fraction + y1
+    }
+
+    /// Reads ```delay``` samples back using ```interpolation```.
+    pub fn read_interpolated(&self, delay: f32, interpolation: Interpolation) -> S {
+        match interpolation {
+            Interpolation::None => self.at(self.split(delay, 1).0),
+            Interpolation::Linear => self.read_linear(delay),
+            Interpolation::Cubic => self.read_cubic(delay),
+        }
+    }
+
+    // Clamps `delay` and splits it into whole samples and the fraction of the next one. NaN
+    // would pass through `clamp`, and infinities are as meaningless, so they read at `min`.
+    fn split(&self, delay: f32, min: usize) -> (usize, S) {
+        let delay = if delay.is_finite() { f64::from(delay) } else { min as f64 };
+        let delay = delay.clamp(min as f64, self.max_delay as f64);
+        let whole = delay as usize;
+        (whole, S::from_f64(delay - whole as f64))
+    }
+
+    /// Reads the sample delayed by ```delay``` and then writes ```input```, returning the
+    /// delayed sample: a plain delay of ```delay``` samples.
+    pub fn tick(&mut self, input: S, delay: usize) -> S {
+        let output = self.read(delay);
+        self.write(input);
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A delay line holding 1, 2, ... 8, written in that order.
+    fn ramp() -> DelayLine<f32> {
+        let mut delay = DelayLine::new(8);
+        for sample in 1..9 {
+            delay.write(sample as f32);
+        }
+        delay
+    }
+
+    #[test]
+    fn whole_delays_read_the_samples_written_that_long_ago() {
+        let delay = ramp();
+        assert_eq!(delay.read(1), 8.0);
+        assert_eq!(delay.read(8), 1.0);
+        assert_eq!(delay.read(0), 8.0);
+        assert_eq!(delay.read(100), 1.0);
+    }
+
+    #[test]
+    fn fractional_delays_interpolate_between_samples() {
+        let delay = ramp();
+        assert_eq!(delay.read_linear(1.5), 7.5);
+        assert_eq!(delay.read_linear(3.25), 5.75);
+        assert_eq!(delay.read_interpolated(3.75, Interpolation::None), 6.0);
+        // Cubic interpolation follows a straight line exactly.
+        assert!((delay.read_cubic(2.5) - 6.5).abs() < 1e-6);
+        assert!((delay.read_cubic(5.25) - 3.75).abs() < 1e-6);
+        assert_eq!(delay.read_cubic(1.0), 7.0);
+    }
+
+    #[test]
+    fn tick_delays_by_whole_samples() {
+        let mut delay = DelayLine::<f32>::new(4);
+        let output: Vec<_> = (1..8).map(|sample| delay.tick(sample as f32, 3)).collect();
+        assert_eq!(output, [0.0, 0.0, 0.0, 1.0, 2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn delays_which_are_not_finite_read_at_the_shortest_delay() {
+        let mut delay = DelayLine::<f32>::new(8);
+        for sample in 1..9 {
+            delay.write(sample as f32);
+        }
+        for &nan in &[f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            assert_eq!(delay.read_linear(nan), 8.0);
+            assert_eq!(delay.read_cubic(nan), 7.0);
+            assert_eq!(delay.read_interpolated(nan, Interpolation::None), 8.0);
+        }
+    }
+}
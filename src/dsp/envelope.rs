@@ -0,0 +1,154 @@
+use Data;
+use super::Sample;
+#[cfg(not(any(feature = "std", test)))]
+use math::Float;
+
+/// What an ```EnvelopeFollower``` measures.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Detection {
+    /// The absolute value of the signal. Reacts to every transient, as needed by limiters and
+    /// gates.
+    Peak,
+    /// The root mean square of the signal, closer to its perceived loudness. Suits compressors.
+    Rms,
+}
+
+/// Tracks the level of a signal, rising at the attack rate when it gets louder and falling at the
+/// release rate when it gets quieter. The times are time constants in milliseconds: the time
+/// taken to cover about 63% of a change in level.
+///
+/// Follow the signal sample by sample with ```tick``` or ```process```, or once per block with
+/// ```process_block``` when the envelope only drives control values:
+///
+/// ```no_run
+/// # use ladspa::dsp::EnvelopeFollower;
+/// # struct P { follower: EnvelopeFollower }
+/// # impl P { fn run(&mut self, input: &[f32], threshold: f32) -> f32 {
+/// let level = self.follower.process_block(input);
+/// let gain = if level > threshold { threshold / level } else { 1.0 };
+/// # gain
+/// # }
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct EnvelopeFollower<S: Sample = Data> {
+    detection: Detection,
+    attack_time: f32,
+    release_time: f32,
+    sample_rate: u64,
+    attack: S,
+    release: S,
+    // The peak level, or the mean square in RMS mode.
+    state: S,
+}
+
+// The fraction of the distance to the target left after `samples` samples of a one pole filter
+// with a time constant of `time` milliseconds.
+fn coefficient(time: f32, sample_rate: u64, samples: usize) -> f64 {
+    let time = f64::from(time) / 1000.0 * sample_rate as f64;
+    if time > 0.0 { (-(samples as f64) / time).exp() } else { 0.0 }
+}
+
+impl<S: Sample> EnvelopeFollower<S> {
+    /// Creates a follower starting from silence.
+    pub fn new(detection: Detection, attack_time: f32, release_time: f32, sample_rate: u64)
+               -> EnvelopeFollower<S> {
+        EnvelopeFollower {
+            detection,
+            attack_time,
+            release_time,
+            sample_rate,
+            attack: S::from_f64(coefficient(attack_time, sample_rate, 1)),
+            release: S::from_f64(coefficient(release_time, sample_rate, 1)),
+            state: S::ZERO,
+        }
+    }
+
+    /// Changes what is measured.
+    pub fn set_detection(&mut self, detection: Detection) {
+        if detection != self.detection {
+            self.detection = detection;
+            self.state = S::ZERO;
+        }
+    }
+
+    /// Changes the attack time, in milliseconds.
+    pub fn set_attack(&mut self, time: f32) {
+        if time != self.attack_time {
+            self.attack_time = time;
+            self.attack = S::from_f64(coefficient(time, self.sample_rate, 1));
+        }
+    }
+
+    /// Changes the release time, in milliseconds.
+    pub fn set_release(&mut self, time: f32) {
+        if time != self.release_time {
+            self.release_time = time;
+            self.release = S::from_f64(coefficient(time, self.sample_rate, 1));
+        }
+    }
+
+    /// Changes the sample rate, keeping the times in milliseconds.
+    pub fn set_sample_rate(&mut self, sample_rate: u64) {
+        self.sample_rate = sample_rate;
+        self.attack = S::from_f64(coefficient(self.attack_time, sample_rate, 1));
+        self.release = S::from_f64(coefficient(self.release_time, sample_rate, 1));
+    }
+
+    /// Returns to silence, as needed in ```Plugin::activate```.
+    pub fn reset(&mut self) {
+        self.state = S::ZERO;
+    }
+
+    /// The current level.
+    pub fn value(&self) -> S {
+        match self.detection {
+            Detection::Peak => self.state,
+            Detection::Rms => S::from_f64(self.state.to_f64().sqrt()),
+        }
+    }
+
+    // The quantity tracked for one sample.
+    fn measure(&self, sample: S) -> S {
+        match self.detection {
+            Detection::Peak => sample.abs(),
+            Detection::Rms => sample * sample,
+        }
+    }
+
+    /// Follows one sample and returns the new level.
+    pub fn tick(&mut self, input: S) -> S {
+        let level = self.measure(input);
+        let coefficient = if level > self.state { self.attack } else { self.release };
+        self.state = level + (self.state - level) * coefficient;
+        self.value()
+    }
+
+    /// Follows ```input```, writing the level after each sample to ```output```. Processes as
+    /// many samples as the shorter one holds.
+    pub fn process(&mut self, input: &[S], output: &mut [S]) {
+        for (out, &sample) in output.iter_mut().zip(input) {
+            *out = self.tick(sample);
+        }
+    }
+
+    /// Follows a whole block at once, using its peak or mean square as the level for its whole
+    /// duration, and returns the level at its end. Much cheaper than ```tick```, at the cost of
+    /// reacting to changes within the block only at its end.
+    pub fn process_block(&mut self, input: &[S]) -> S {
+        if input.is_empty() {
+            return self.value();
+        }
+        let level = match self.detection {
+            Detection::Peak => super::simd::peak(input),
+            Detection::Rms => {
+                let sum = input.iter().fold(S::ZERO, |sum, &sample| sum + sample * sample);
+                sum / S::from_f64(input.len() as f64)
+            }
+        };
+        let time = if level > self.state { self.attack_time } else { self.release_time };
+        let coefficient = S::from_f64(coefficient(time, self.sample_rate, input.len()));
+        self.state = level + (self.state - level) * coefficient;
+        self.value()
+    }
+}
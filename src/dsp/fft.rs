@@ -0,0 +1,545 @@
+//! Fast Fourier transforms and short-time Fourier analysis and resynthesis, for spectral plugins.
+//!
+//! The transforms are iterative radix-2 FFTs, so sizes must be powers of two. They compute their
+//! twiddle factors and scratch buffers when created, in ```f64``` whatever the ```Sample```
+//! type; transforming never allocates.
+//!
+//! Most plugins only need ```Stft```, which cuts the input into overlapping windowed frames,
+//! hands the spectrum of each to a closure and adds the results back together:
+//!
+//! ```no_run
+//! # use ladspa::PortConnection;
+//! # use ladspa::dsp::fft::*;
+//! # struct P { stft: Stft }
+//! # fn new() -> P {
+//! // In the constructor:
+//! let stft = Stft::new(2048, 512, Window::Hann);
+//! # P { stft }
+//! # }
+//! # impl P { fn run(&mut self, ports: &[&PortConnection], input: &[f32], mut output: &mut [f32]) {
+//!
+//! // In run:
+//! let threshold = ports[2].unwrap_control();
+//! self.stft.process(input, &mut output, |spectrum| {
+//!     for bin in spectrum.iter_mut() {
+//!         if bin.norm() < threshold {
+//!             *bin = Complex::default();
+//!         }
+//!     }
+//! });
+//! # }
+//! # }
+//! ```
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::f64::consts::PI;
+use core::ops::{Add, AddAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+
+use Data;
+use super::Sample;
+#[cfg(not(any(feature = "std", test)))]
+use math::Float;
+
+/// A complex number, the type of the bins of a spectrum.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Complex<S: Sample = Data> {
+    pub re: S,
+    pub im: S,
+}
+
+impl<S: Sample> Complex<S> {
+    /// Creates ```re + im i```.
+    pub fn new(re: S, im: S) -> Complex<S> {
+        Complex { re, im }
+    }
+
+    /// Creates the number with magnitude ```norm``` and phase ```arg```, in radians.
+    pub fn from_polar(norm: S, arg: S) -> Complex<S> {
+        let arg = arg.to_f64();
+        Complex::new(norm * S::from_f64(arg.cos()), norm * S::from_f64(arg.sin()))
+    }
+
+    /// The complex conjugate.
+    pub fn conj(self) -> Complex<S> {
+        Complex::new(self.re, -self.im)
+    }
+
+    /// The square of the magnitude, which is cheaper to compute than the magnitude itself.
+    pub fn norm_sqr(self) -> S {
+        self.re * self.re + self.im * self.im
+    }
+
+    /// The magnitude.
+    pub fn norm(self) -> S {
+        S::from_f64(self.norm_sqr().to_f64().sqrt())
+    }
+
+    /// The phase, in radians between -π and π.
+    pub fn arg(self) -> S {
+        S::from_f64(self.im.to_f64().atan2(self.re.to_f64()))
+    }
+
+    /// Multiplies both parts by ```factor```.
+    pub fn scale(self, factor: S) -> Complex<S> {
+        Complex::new(self.re * factor, self.im * factor)
+    }
+}
+
+impl<S: Sample> Add for Complex<S> {
+    type Output = Complex<S>;
+
+    fn add(self, other: Complex<S>) -> Complex<S> {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl<S: Sample> Sub for Complex<S> {
+    type Output = Complex<S>;
+
+    fn sub(self, other: Complex<S>) -> Complex<S> {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl<S: Sample> Mul for Complex<S> {
+    type Output = Complex<S>;
+
+    fn mul(self, other: Complex<S>) -> Complex<S> {
+        Complex::new(self.re * other.re - self.im * other.im,
+                     self.re * other.im + self.im * other.re)
+    }
+}
+
+impl<S: Sample> Neg for Complex<S> {
+    type Output = Complex<S>;
+
+    fn neg(self) -> Complex<S> {
+        Complex::new(-self.re, -self.im)
+    }
+}
+
+impl<S: Sample> AddAssign for Complex<S> {
+    fn add_assign(&mut self, other: Complex<S>) {
+        *self = *self + other;
+    }
+}
+
+impl<S: Sample> SubAssign for Complex<S> {
+    fn sub_assign(&mut self, other: Complex<S>) {
+        *self = *self - other;
+    }
+}
+
+impl<S: Sample> MulAssign for Complex<S> {
+    fn mul_assign(&mut self, other: Complex<S>) {
+        *self = *self * other;
+    }
+}
+
+// e^(-2πi k / n)
+fn twiddle<S: Sample>(k: usize, n: usize) -> Complex<S> {
+    let angle = -2.0 * PI * k as f64 / n as f64;
+    Complex::new(S::from_f64(angle.cos()), S::from_f64(angle.sin()))
+}
+
+fn check_size(size: usize, min: usize) {
+    if !size.is_power_of_two() || size < min {
+        panic!("FFT size {} is not a power of two of at least {}", size, min);
+    }
+}
+
+/// A complex FFT of a fixed size.
+#[derive(Clone, Debug)]
+pub struct Fft<S: Sample = Data> {
+    // e^(-2πi k / size) for k below size / 2.
+    twiddles: Vec<Complex<S>>,
+    // The index each element is swapped with before the butterflies.
+    reversed: Vec<usize>,
+}
+
+impl<S: Sample> Fft<S> {
+    /// Prepares transforms of ```size``` points. Panics if ```size``` is not a power of two.
+    pub fn new(size: usize) -> Fft<S> {
+        check_size(size, 1);
+        let bits = size.trailing_zeros();
+        Fft {
+            twiddles: (0..size / 2).map(|k| twiddle(k, size)).collect(),
+            reversed: (0..size)
+                .map(|i| if bits == 0 { 0 } else { i.reverse_bits() >> (usize::BITS - bits) })
+                .collect(),
+        }
+    }
+
+    /// The number of points transformed.
+    pub fn size(&self) -> usize {
+        self.reversed.len()
+    }
+
+    /// Replaces ```buf```, which must hold ```size``` points, by its discrete Fourier transform.
+    /// The result is not normalized.
+    pub fn forward(&self, buf: &mut [Complex<S>]) {
+        self.transform(buf, false);
+    }
+
+    /// Replaces ```buf```, which must hold ```size``` points, by its inverse discrete Fourier
+    /// transform, divided by ```size``` so that ```inverse``` undoes ```forward```.
+    pub fn inverse(&self, buf: &mut [Complex<S>]) {
+        self.transform(buf, true);
+        let scale = S::ONE / S::from_f64(self.size() as f64);
+        for point in buf.iter_mut() {
+            *point = point.scale(scale);
+        }
+    }
+
+    fn transform(&self, buf: &mut [Complex<S>], inverse: bool) {
+        let size = self.size();
+        assert_eq!(buf.len(), size, "FFT of size {} given {} points", size, buf.len());
+        for (i, &j) in self.reversed.iter().enumerate() {
+            if i < j {
+                buf.swap(i, j);
+            }
+        }
+        let mut len = 2;
+        while len <= size {
+            let half = len / 2;
+            let step = size / len;
+            for start in (0..size).step_by(len) {
+                for k in 0..half {
+                    let twiddle = self.twiddles[k * step];
+                    let twiddle = if inverse { twiddle.conj() } else { twiddle };
+                    let even = buf[start + k];
+                    let odd = buf[start + k + half] * twiddle;
+                    buf[start + k] = even + odd;
+                    buf[start + k + half] = even - odd;
+                }
+            }
+            len *= 2;
+        }
+    }
+}
+
+/// An FFT of real signals, computed with a complex FFT of half the size. The spectrum of
+/// ```size``` samples has ```size / 2 + 1``` bins, from 0 Hz to the Nyquist frequency.
+#[derive(Clone, Debug)]
+pub struct RealFft<S: Sample = Data> {
+    size: usize,
+    fft: Fft<S>,
+    // e^(-2πi k / size) for k up to size / 2.
+    twiddles: Vec<Complex<S>>,
+    scratch: Vec<Complex<S>>,
+}
+
+impl<S: Sample> RealFft<S> {
+    /// Prepares transforms of ```size``` samples. Panics if ```size``` is not a power of two of
+    /// at least 2.
+    pub fn new(size: usize) -> RealFft<S> {
+        check_size(size, 2);
+        RealFft {
+            size,
+            fft: Fft::new(size / 2),
+            twiddles: (0..size / 2 + 1).map(|k| twiddle(k, size)).collect(),
+            scratch: vec![Complex::default(); size / 2],
+        }
+    }
+
+    /// The number of samples transformed.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// The number of bins in a spectrum.
+    pub fn bins(&self) -> usize {
+        self.size / 2 + 1
+    }
+
+    /// Writes the spectrum of ```input```, which must hold ```size``` samples, to ```output```,
+    /// which must hold ```bins``` points. The result is not normalized.
+    pub fn forward(&mut self, input: &[S], output: &mut [Complex<S>]) {
+        let half = self.size / 2;
+        assert_eq!(input.len(), self.size, "FFT of size {} given {} samples",
+                   self.size, input.len());
+        assert_eq!(output.len(), half + 1, "FFT of size {} given {} bins",
+                   self.size, output.len());
+        // Transform the even samples as the real parts and the odd ones as the imaginary parts,
+        // then separate the two spectra.
+        for (point, pair) in self.scratch.iter_mut().zip(input.chunks(2)) {
+            *point = Complex::new(pair[0], pair[1]);
+        }
+        self.fft.forward(&mut self.scratch);
+        let half_scale = S::from_f64(0.5);
+        for (k, (bin, &twiddle)) in output.iter_mut().zip(&self.twiddles).enumerate() {
+            let z = self.scratch[k % half];
+            let mirror = self.scratch[(half - k) % half].conj();
+            let even = (z + mirror).scale(half_scale);
+            let odd = (z - mirror) * Complex::new(S::ZERO, -half_scale);
+            *bin = even + twiddle * odd;
+        }
+    }
+
+    /// Writes the signal with the spectrum ```input```, which must hold ```bins``` points, to
+    /// ```output```, which must hold ```size``` samples. The result is divided by ```size``` so
+    /// that ```inverse``` undoes ```forward```. The first and last bins should be real, as they
+    /// are in the spectrum of any real signal.
+    pub fn inverse(&mut self, input: &[Complex<S>], output: &mut [S]) {
+        let half = self.size / 2;
+        assert_eq!(input.len(), half + 1, "FFT of size {} given {} bins",
+                   self.size, input.len());
+        assert_eq!(output.len(), self.size, "FFT of size {} given {} samples",
+                   self.size, output.len());
+        let half_scale = S::from_f64(0.5);
+        for k in 0..half {
+            let x = input[k];
+            let mirror = input[half - k].conj();
+            let even = (x + mirror).scale(half_scale);
+            let odd = (x - mirror).scale(half_scale) * self.twiddles[k].conj();
+            self.scratch[k] = even + Complex::new(-odd.im, odd.re);
+        }
+        self.fft.inverse(&mut self.scratch);
+        for (pair, point) in output.chunks_mut(2).zip(&self.scratch) {
+            pair[0] = point.re;
+            pair[1] = point.im;
+        }
+    }
+}
+
+/// The shape of the frames an ```Stft``` analyzes. The windows are periodic, as suited to
+/// overlap-add.
+///
+/// As ```Stft``` applies the window twice, it reproduces its input exactly only if the hop is
+/// short enough for the squared windows to add up to a constant. The hops below are the
+/// longest which do so among the powers of two.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Window {
+    /// No window, for frames which don't overlap: a hop of a whole frame.
+    Rectangular,
+    /// A raised cosine reaching zero at the frame edges; a good default. A hop of a quarter
+    /// frame.
+    Hann,
+    /// A raised cosine stopping short of zero, with a lower first sidelobe than Hann. A hop of a
+    /// quarter frame.
+    Hamming,
+    /// Lower sidelobes than Hann at the cost of a wider main lobe. A hop of an eighth of a
+    /// frame.
+    Blackman,
+}
+
+impl Window {
+    /// Fills ```buf``` with the window, spanning its whole length.
+    pub fn fill<S: Sample>(self, buf: &mut [S]) {
+        let len = buf.len() as f64;
+        for (n, sample) in buf.iter_mut().enumerate() {
+            let phase = 2.0 * PI * n as f64 / len;
+            let value = match self {
+                Window::Rectangular => 1.0,
+                Window::Hann => 0.5 - 0.5 * phase.cos(),
+                Window::Hamming => 0.54 - 0.46 * phase.cos(),
+                Window::Blackman => 0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos(),
+            };
+            *sample = S::from_f64(value);
+        }
+    }
+}
+
+/// Streams audio through a short-time Fourier transform: the input is cut into frames of
+/// ```size``` samples starting every ```hop``` samples, each frame is windowed and transformed,
+/// and the spectra, once processed, are transformed back, windowed again and overlap-added into
+/// the output.
+///
+/// The window is applied both before and after processing, and the output scaled to make up for
+/// the overlap, so that leaving the spectra untouched reproduces the input, delayed by
+/// ```latency``` samples.
+#[derive(Clone, Debug)]
+pub struct Stft<S: Sample = Data> {
+    fft: RealFft<S>,
+    hop: usize,
+    window: Vec<S>,
+    // The output scaling making up for the overlapping windows.
+    gain: S,
+    // The last `size` input samples and the output being overlap-added.
+    input: Vec<S>,
+    output: Vec<S>,
+    frame: Vec<S>,
+    spectrum: Vec<Complex<S>>,
+    // The number of samples taken since the last frame.
+    filled: usize,
+}
+
+impl<S: Sample> Stft<S> {
+    /// Prepares frames of ```size``` samples, taken every ```hop``` samples. Panics if ```size```
+    /// is not a power of two of at least 2 or if ```hop``` is not between 1 and ```size```.
+    pub fn new(size: usize, hop: usize, window: Window) -> Stft<S> {
+        check_size(size, 2);
+        if hop == 0 || hop > size {
+            panic!("STFT hop {} is not between 1 and the frame size {}", hop, size);
+        }
+        let mut samples = vec![S::ZERO; size];
+        window.fill(&mut samples);
+
+        // Every output sample is scaled by the sum of the squared window at the positions a hop
+        // apart in the overlapping frames, which averages to the sum of the whole squared window
+        // divided by the hop.
+        let total: f64 = (0..size).map(|i| (samples[i] * samples[i]).to_f64()).sum();
+        let gain = hop as f64 / total;
+
+        Stft {
+            fft: RealFft::new(size),
+            hop,
+            window: samples,
+            gain: S::from_f64(gain),
+            input: vec![S::ZERO; size],
+            output: vec![S::ZERO; size],
+            frame: vec![S::ZERO; size],
+            spectrum: vec![Complex::default(); size / 2 + 1],
+            filled: 0,
+        }
+    }
+
+    /// The number of samples in a frame.
+    pub fn size(&self) -> usize {
+        self.fft.size()
+    }
+
+    /// The number of samples between frames.
+    pub fn hop(&self) -> usize {
+        self.hop
+    }
+
+    /// The number of bins in the spectra passed to ```process```.
+    pub fn bins(&self) -> usize {
+        self.fft.bins()
+    }
+
+    /// The delay between the input and the output, in samples.
+    pub fn latency(&self) -> usize {
+        self.size()
+    }
+
+    /// Clears the input and output, as needed in ```Plugin::activate```.
+    pub fn reset(&mut self) {
+        for sample in self.input.iter_mut().chain(self.output.iter_mut()) {
+            *sample = S::ZERO;
+        }
+        self.filled = 0;
+    }
+
+    /// Processes ```input``` into ```output```, calling ```process``` with the spectrum of every
+    /// frame completed along the way, to be modified in place. Processes as many samples as the
+    /// shorter one holds.
+    pub fn process<F>(&mut self, input: &[S], output: &mut [S], mut process: F)
+        where F: FnMut(&mut [Complex<S>])
+    {
+        let (size, hop) = (self.size(), self.hop);
+        for (out, &sample) in output.iter_mut().zip(input) {
+            *out = self.output[self.filled];
+            self.input[size - hop + self.filled] = sample;
+            self.filled += 1;
+            if self.filled < hop {
+                continue;
+            }
+            self.filled = 0;
+
+            for ((frame, &sample), &window) in self.frame.iter_mut().zip(&self.input)
+                .zip(&self.window) {
+                *frame = sample * window;
+            }
+            self.fft.forward(&self.frame, &mut self.spectrum);
+            process(&mut self.spectrum);
+            self.fft.inverse(&self.spectrum, &mut self.frame);
+
+            self.output.copy_within(hop.., 0);
+            for sample in self.output[size - hop..].iter_mut() {
+                *sample = S::ZERO;
+            }
+            for ((out, &sample), &window) in self.output.iter_mut().zip(&self.frame)
+                .zip(&self.window) {
+                *out += sample * window * self.gain;
+            }
+            self.input.copy_within(hop.., 0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An arbitrary but repeatable signal.
+    fn signal(len: usize) -> Vec<f64> {
+        (0..len).map(|n| ((n * 7919 + 13) % 97) as f64 / 48.0 - 1.0).collect()
+    }
+
+    fn dft(input: &[Complex<f64>]) -> Vec<Complex<f64>> {
+        let len = input.len();
+        (0..len)
+            .map(|k| {
+                let mut sum = Complex::default();
+                for (n, &x) in input.iter().enumerate() {
+                    sum += x * twiddle(k * n % len, len);
+                }
+                sum
+            })
+            .collect()
+    }
+
+    fn assert_close(value: Complex<f64>, expected: Complex<f64>) {
+        assert!((value - expected).norm() < 1e-9, "{:?} is not {:?}", value, expected);
+    }
+
+    #[test]
+    fn the_fft_matches_the_dft() {
+        for &size in &[1, 2, 4, 8, 64, 256] {
+            let re = signal(size);
+            let im = signal(size + 5);
+            let input: Vec<_> = re.iter().zip(&im[5..]).map(|(&re, &im)| Complex::new(re, im))
+                .collect();
+            let fft = Fft::new(size);
+            let mut output = input.clone();
+            fft.forward(&mut output);
+            for (&value, &expected) in output.iter().zip(&dft(&input)) {
+                assert_close(value, expected);
+            }
+            fft.inverse(&mut output);
+            for (&value, &expected) in output.iter().zip(&input) {
+                assert_close(value, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn the_real_fft_matches_the_dft() {
+        for &size in &[2, 4, 16, 128] {
+            let input = signal(size);
+            let complex: Vec<_> = input.iter().map(|&re| Complex::new(re, 0.0)).collect();
+            let mut fft = RealFft::new(size);
+            let mut spectrum = vec![Complex::default(); fft.bins()];
+            fft.forward(&input, &mut spectrum);
+            for (&value, &expected) in spectrum.iter().zip(&dft(&complex)) {
+                assert_close(value, expected);
+            }
+            let mut output = vec![0.0; size];
+            fft.inverse(&spectrum, &mut output);
+            for (&value, &expected) in output.iter().zip(&input) {
+                assert!((value - expected).abs() < 1e-9, "{} is not {}", value, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn untouched_spectra_give_back_the_delayed_input() {
+        for &(window, hop) in &[(Window::Rectangular, 64),
+                                (Window::Hann, 16),
+                                (Window::Hamming, 16),
+                                (Window::Blackman, 8)] {
+            let mut stft = Stft::new(64, hop, window);
+            let input = signal(1024);
+            let mut output = vec![0.0; 1024];
+            stft.process(&input, &mut output, |_| {});
+            let latency = stft.latency();
+            for (&value, &expected) in output[latency..].iter().zip(&input) {
+                assert!((value - expected).abs() < 1e-9,
+                        "{:?}: {} is not {}", window, value, expected);
+            }
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use core::f64::consts::PI;
+use core::marker::PhantomData;
+
+use Data;
+use super::Sample;
+use units::{Division, TempoSync};
+#[cfg(not(any(feature = "std", test)))]
+use math::Float;
+
+/// The shape of the wave produced by an ```Lfo```. The periods start at phase 0: the sine and
+/// triangle rise through zero there, and the other shapes jump.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LfoShape {
+    /// A smooth sine.
+    Sine,
+    /// Rises from 0 to the top over the first quarter of each period, falls to the bottom over
+    /// the next half and rises back to 0.
+    Triangle,
+    /// High for the first half of each period and low for the second.
+    Square,
+    /// Ramps up over each period, then drops.
+    SawUp,
+    /// Drops at the start of each period, then ramps down.
+    SawDown,
+    /// A new random value at the start of every period, held until the next.
+    SampleAndHold,
+}
+
+/// The range of the values produced by an ```Lfo```.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Polarity {
+    /// From -1 to 1, as needed for pitch or panning.
+    Bipolar,
+    /// From 0 to 1, as needed for gains.
+    Unipolar,
+}
+
+/// Returns the frequency, in Hz, of a cycle lasting ```beats``` beats at ```bpm``` beats per
+/// minute: ```bpm_to_hz(120.0, 0.5)``` is an eighth note cycle at 120 BPM, or 4 Hz.
+pub fn bpm_to_hz(bpm: f32, beats: f32) -> f32 {
+    bpm / (60.0 * beats)
+}
+
+/// A low frequency oscillator, for modulating the parameters of effects such as chorus,
+/// tremolo and auto-pan.
+///
+/// Unlike ```osc::Oscillator```, the shapes are not band-limited: at modulation rates the sharp
+/// edges are wanted.
+///
+/// ```no_run
+/// # use ladspa::PortConnection;
+/// # use ladspa::dsp::Lfo;
+/// # struct P { lfo: Lfo }
+/// # impl P { fn run(&mut self, ports: &[&PortConnection], input: &[f32], mut output: &mut [f32]) {
+/// # let depth = 0.5;
+/// // Tremolo:
+/// self.lfo.set_frequency(ports[2].unwrap_control());
+/// for (input, output) in input.iter().zip(output.iter_mut()) {
+///     *output = *input * (1.0 - depth * self.lfo.tick());
+/// }
+/// # }
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Lfo<S: Sample = Data> {
+    shape: LfoShape,
+    polarity: Polarity,
+    frequency: f32,
+    sample_rate: u64,
+    // The position in the period, from 0 to 1, how far it moves every sample, and how far the
+    // output is shifted from it.
+    phase: f64,
+    increment: f64,
+    offset: f64,
+    // The value held by `SampleAndHold`, and the state of the generator it is drawn from.
+    held: f64,
+    seed: u32,
+    random: u32,
+    _sample: PhantomData<S>,
+}
+
+// Wraps a phase into [0, 1).
+fn wrap(phase: f64) -> f64 {
+    let phase = phase - phase as i64 as f64;
+    if phase < 0.0 { phase + 1.0 } else { phase }
+}
+
+impl<S: Sample> Lfo<S> {
+    /// Creates a bipolar LFO at ```frequency``` Hz, starting at phase 0.
+    pub fn new(shape: LfoShape, frequency: f32, sample_rate: u64) -> Lfo<S> {
+        let mut lfo = Lfo {
+            shape,
+            polarity: Polarity::Bipolar,
+            frequency,
+            sample_rate,
+            phase: 0.0,
+            increment: 0.0,
+            offset: 0.0,
+            held: 0.0,
+            seed: 0x2545_f491,
+            random: 0,
+            _sample: PhantomData,
+        };
+        lfo.set_frequency(frequency);
+        lfo.reset();
+        lfo
+    }
+
+    /// Changes the shape, keeping the phase.
+    pub fn set_shape(&mut self, shape: LfoShape) {
+        self.shape = shape;
+    }
+
+    /// Changes the range of the output.
+    pub fn set_polarity(&mut self, polarity: Polarity) {
+        self.polarity = polarity;
+    }
+
+    /// Changes the frequency, in Hz, keeping the phase.
+    pub fn set_frequency(&mut self, frequency: f32) {
+        self.frequency = frequency;
+        self.increment = f64::from(frequency).max(0.0) / self.sample_rate as f64;
+    }
+
+    /// Sets the frequency to one cycle every ```beats``` beats at ```bpm``` beats per minute.
+    pub fn set_tempo(&mut self, bpm: f32, beats: f32) {
+        self.set_frequency(bpm_to_hz(bpm, beats));
+    }
+
+    /// Sets the frequency to one cycle every ```division``` at the tempo of ```sync```.
+    pub fn sync(&mut self, sync: &TempoSync, division: Division) {
+        self.set_tempo(sync.tempo(), division.beats());
+    }
+
+    /// Changes the sample rate, keeping the frequency in Hz.
+    pub fn set_sample_rate(&mut self, sample_rate: u64) {
+        self.sample_rate = sample_rate;
+        let frequency = self.frequency;
+        self.set_frequency(frequency);
+    }
+
+    /// Shifts the output by ```offset```, as a fraction of the period. LFOs for the two channels
+    /// of an auto-pan are typically offset by half a period.
+    pub fn set_phase_offset(&mut self, offset: f64) {
+        self.offset = wrap(offset);
+    }
+
+    /// Moves to ```phase```, as a fraction of the period, for example to restart the LFO on the
+    /// beat.
+    pub fn set_phase(&mut self, phase: f64) {
+        self.phase = wrap(phase);
+    }
+
+    /// Changes the seed of the values drawn by ```SampleAndHold```, which start over from it.
+    /// LFOs with the same seed produce the same values.
+    pub fn set_seed(&mut self, seed: u32) {
+        self.seed = seed;
+        self.random = seed | 1;
+    }
+
+    /// Returns to phase 0 and restarts the random values, as needed in ```Plugin::activate```.
+    pub fn reset(&mut self) {
+        self.phase = 0.0;
+        let seed = self.seed;
+        self.set_seed(seed);
+        self.held = self.next_random();
+    }
+
+    /// The shape.
+    pub fn shape(&self) -> LfoShape {
+        self.shape
+    }
+
+    /// The frequency, in Hz.
+    pub fn frequency(&self) -> f32 {
+        self.frequency
+    }
+
+    /// The current phase, as a fraction of the period, not including the offset.
+    pub fn phase(&self) -> f64 {
+        self.phase
+    }
+
+    // A uniformly distributed value from -1 to 1.
+    fn next_random(&mut self) -> f64 {
+        // xorshift32
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 17;
+        self.random ^= self.random << 5;
+        f64::from(self.random) / f64::from(u32::MAX) * 2.0 - 1.0
+    }
+
+    /// Returns the next value.
+    pub fn tick(&mut self) -> S {
+        let t = wrap(self.phase + self.offset);
+        let value = match self.shape {
+            LfoShape::Sine => (2.0 * PI * t).sin(),
+            LfoShape::Triangle => 1.0 - 4.0 * (wrap(t + 0.25) - 0.5).abs(),
+            LfoShape::Square => if t < 0.5 { 1.0 } else { -1.0 },
+            LfoShape::SawUp => 2.0 * t - 1.0,
+            LfoShape::SawDown => 1.0 - 2.0 * t,
+            LfoShape::SampleAndHold => self.held,
+        };
+        let phase = self.phase + self.increment;
+        if wrap(phase + self.offset) < t {
+            self.held = self.next_random();
+        }
+        self.phase = wrap(phase);
+        S::from_f64(match self.polarity {
+            Polarity::Bipolar => value,
+            Polarity::Unipolar => 0.5 * value + 0.5,
+        })
+    }
+
+    /// Fills ```out``` with the next values.
+    pub fn fill(&mut self, out: &mut [S]) {
+        for sample in out.iter_mut() {
+            *sample = self.tick();
+        }
+    }
+}
@@ -0,0 +1,278 @@
+//! A brickwall limiter which looks ahead to reduce the gain before peaks arrive.
+//!
+//! A limiter reacting to peaks as they come either lets their start through or distorts them
+//! with an instant gain change. ```LookaheadLimiter``` delays the audio instead, so that it sees
+//! each peak ```lookahead``` milliseconds early and fades the gain down in time for it:
+//!
+//! ```no_run
+//! # use ladspa::{Plugin, PortConnection, Ports};
+//! # use ladspa::dsp::limiter::LookaheadLimiter;
+//! # use ladspa::units::db_to_gain;
+//! # struct Brickwall { limiter: LookaheadLimiter }
+//! # impl Plugin for Brickwall {
+//! fn run<'a>(&mut self, sample_count: usize, ports: &mut [PortConnection<'a>]) {
+//!     let mut ports = Ports::new(ports);
+//!     self.limiter.set_ceiling(db_to_gain(ports.control(0)));
+//!     let input = ports.audio_input(0);
+//!     for (out, &sample) in ports.audio_output(0)[..sample_count].iter_mut().zip(input) {
+//!         *out = self.limiter.tick(sample);
+//!     }
+//!     self.limiter.report_latency(&mut ports);
+//! }
+//! # }
+//! ```
+//!
+//! The output never exceeds the ceiling, but for rounding errors: the gain is the lowest needed by
+//! any sample still in the delay, followed by an exponential release once the peak has passed,
+//! and the drop to it is spread evenly over the lookahead.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use {Data, Ports};
+use super::Sample;
+use super::delay::DelayLine;
+#[cfg(not(any(feature = "std", test)))]
+use math::Float;
+
+/// A lookahead limiter for one or more channels, which are limited together so that their
+/// balance is kept. See the module documentation.
+///
+/// All the storage is allocated in ```new```; processing never allocates. The audio is delayed by
+/// ```latency``` samples, which ```report_latency``` passes on to the host.
+#[derive(Clone, Debug)]
+pub struct LookaheadLimiter<S: Sample = Data> {
+    // One per channel, holding the audio until its gain is known.
+    delays: Vec<DelayLine<S>>,
+    lookahead: usize,
+    ceiling: S,
+    release_time: f32,
+    sample_rate: u64,
+    release: S,
+    // The gains needed by the samples in the delay, as a queue of (gain, time) increasing in both,
+    // so that the front is the lowest. A ring buffer of `lookahead + 1` entries.
+    minimum: Vec<(S, u64)>,
+    front: usize,
+    len: usize,
+    time: u64,
+    // The gain after the release, and the last `lookahead + 1` values of it in a ring buffer,
+    // with their sum, for the moving average.
+    envelope: S,
+    history: Vec<S>,
+    position: usize,
+    sum: f64,
+    gain: S,
+}
+
+// The fraction of the distance to the target left after one sample of a one pole filter with a
+// time constant of `time` milliseconds.
+fn coefficient(time: f32, sample_rate: u64) -> f64 {
+    let time = f64::from(time) / 1000.0 * sample_rate as f64;
+    if time > 0.0 { (-1.0 / time).exp() } else { 0.0 }
+}
+
+impl<S: Sample> LookaheadLimiter<S> {
+    /// Creates a limiter for ```channels``` channels, looking ```lookahead``` milliseconds ahead
+    /// and releasing with a time constant of ```release``` milliseconds, with a ceiling of 1. The
+    /// lookahead is at least one sample.
+    pub fn new(channels: usize, lookahead: f32, release: f32, sample_rate: u64)
+               -> LookaheadLimiter<S> {
+        let lookahead = ((f64::from(lookahead) / 1000.0 * sample_rate as f64).round() as usize)
+            .max(1);
+        LookaheadLimiter {
+            delays: (0..channels.max(1)).map(|_| DelayLine::new(lookahead)).collect(),
+            lookahead,
+            ceiling: S::ONE,
+            release_time: release,
+            sample_rate,
+            release: S::from_f64(coefficient(release, sample_rate)),
+            minimum: vec![(S::ONE, 0); lookahead + 1],
+            front: 0,
+            len: 0,
+            time: 0,
+            envelope: S::ONE,
+            history: vec![S::ONE; lookahead + 1],
+            position: 0,
+            sum: (lookahead + 1) as f64,
+            gain: S::ONE,
+        }
+    }
+
+    /// The number of channels limited together.
+    pub fn channels(&self) -> usize {
+        self.delays.len()
+    }
+
+    /// The number of samples by which the audio is delayed, which is the lookahead.
+    pub fn latency(&self) -> usize {
+        self.lookahead
+    }
+
+    /// Reports ```latency``` through the ```Port::latency_output``` of the plugin, so that the
+    /// latency hosts compensate for always matches the lookahead. Panics if the plugin has no
+    /// latency output.
+    pub fn report_latency(&self, ports: &mut Ports) {
+        ports.set_latency(self.lookahead);
+    }
+
+    /// Changes the highest level let through, as a gain rather than in dB.
+    pub fn set_ceiling(&mut self, ceiling: S) {
+        self.ceiling = ceiling;
+    }
+
+    /// The highest level let through.
+    pub fn ceiling(&self) -> S {
+        self.ceiling
+    }
+
+    /// Changes the release time, in milliseconds.
+    pub fn set_release(&mut self, time: f32) {
+        if time != self.release_time {
+            self.release_time = time;
+            self.release = S::from_f64(coefficient(time, self.sample_rate));
+        }
+    }
+
+    /// The gain applied to the last sample output, 1 when the limiter is not reducing it. Useful
+    /// for gain reduction meters.
+    pub fn gain(&self) -> S {
+        self.gain
+    }
+
+    /// Silences the delay and releases the gain, as needed in ```Plugin::activate```.
+    pub fn reset(&mut self) {
+        for delay in &mut self.delays {
+            delay.clear();
+        }
+        self.front = 0;
+        self.len = 0;
+        self.envelope = S::ONE;
+        for value in self.history.iter_mut() {
+            *value = S::ONE;
+        }
+        self.sum = self.history.len() as f64;
+        self.gain = S::ONE;
+    }
+
+    // Computes the gain to apply to the sample leaving the delay, given the peak of the samples
+    // entering it.
+    fn next_gain(&mut self, peak: S) -> S {
+        let needed = if peak > self.ceiling { self.ceiling / peak } else { S::ONE };
+        let size = self.minimum.len();
+        // Gains behind the new one which are not lower can never be the lowest again.
+        while self.len > 0 && self.minimum[(self.front + self.len - 1) % size].0 >= needed {
+            self.len -= 1;
+        }
+        self.minimum[(self.front + self.len) % size] = (needed, self.time);
+        self.len += 1;
+        // Drop the gain of the sample which has just left the delay.
+        if self.minimum[self.front].1 + size as u64 <= self.time {
+            self.front = (self.front + 1) % size;
+            self.len -= 1;
+        }
+        self.time += 1;
+        let lowest = self.minimum[self.front].0;
+
+        self.envelope = if lowest < self.envelope {
+            lowest
+        } else {
+            lowest + (self.envelope - lowest) * self.release
+        };
+        self.sum += (self.envelope - self.history[self.position]).to_f64();
+        self.history[self.position] = self.envelope;
+        self.position = (self.position + 1) % size;
+        if self.position == 0 {
+            // Keeps rounding errors from building up in the running sum.
+            self.sum = self.history.iter().map(|value| value.to_f64()).sum();
+        }
+        self.gain = S::from_f64(self.sum / size as f64);
+        self.gain
+    }
+
+    /// Limits one sample of a single channel limiter, returning the sample leaving the delay.
+    pub fn tick(&mut self, input: S) -> S {
+        let gain = self.next_gain(input.abs());
+        self.delays[0].tick(input, self.lookahead) * gain
+    }
+
+    /// Limits one sample of each channel at once, in place. Only the first ```channels```
+    /// samples of ```frame``` are processed.
+    pub fn tick_frame(&mut self, frame: &mut [S]) {
+        let peak = frame.iter().take(self.delays.len()).fold(S::ZERO, |peak, &sample| {
+            let level = sample.abs();
+            if level > peak { level } else { peak }
+        });
+        let gain = self.next_gain(peak);
+        let lookahead = self.lookahead;
+        for (sample, delay) in frame.iter_mut().zip(&mut self.delays) {
+            *sample = delay.tick(*sample, lookahead) * gain;
+        }
+    }
+
+    /// Limits a block of a single channel limiter in place.
+    pub fn process(&mut self, buf: &mut [S]) {
+        for sample in buf.iter_mut() {
+            *sample = self.tick(*sample);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::f32::consts::PI;
+
+    use {PortConnection, PortData};
+    use super::*;
+
+    #[test]
+    fn loud_signals_stay_under_the_ceiling() {
+        let mut limiter = LookaheadLimiter::<f32>::new(1, 5.0, 50.0, 48000);
+        limiter.set_ceiling(0.5);
+        let mut signal: Vec<f32> = (0..4800)
+            .map(|n| 4.0 * (2.0 * PI * 1000.0 * n as f32 / 48000.0).sin())
+            .collect();
+        signal[2400] = 20.0;
+        signal[2401] = -20.0;
+        limiter.process(&mut signal);
+        for &sample in &signal {
+            assert!(sample.abs() <= 0.5 + 1e-5, "{} exceeds the ceiling", sample);
+        }
+        assert!(limiter.gain() < 1.0);
+    }
+
+    #[test]
+    fn the_latency_is_the_lookahead_in_samples() {
+        let limiter = LookaheadLimiter::<f32>::new(1, 1.5, 50.0, 44100);
+        // 1.5 ms at 44.1 kHz is 66.15 samples.
+        assert_eq!(limiter.latency(), 66);
+        let mut latency = 0.0;
+        let mut connections = [PortConnection {
+                                   port: ::Port::latency_output(),
+                                   data: PortData::ControlOutput(&mut latency),
+                               }];
+        limiter.report_latency(&mut Ports::new(&mut connections));
+        assert_eq!(latency, 66.0);
+
+        let mut impulse = vec![0.0; 80];
+        impulse[0] = 0.1;
+        let mut limiter = LookaheadLimiter::<f32>::new(1, 1.5, 50.0, 44100);
+        limiter.process(&mut impulse);
+        assert_eq!(impulse.iter().position(|&sample| sample != 0.0), Some(66));
+    }
+
+    #[test]
+    fn channels_are_limited_together() {
+        let mut limiter = LookaheadLimiter::<f32>::new(2, 1.0, 50.0, 48000);
+        let lookahead = limiter.latency();
+        // A third sample past the channels of the limiter, which must be left alone.
+        let mut frames = vec![[2.0, 0.5, 100.0]; lookahead * 4];
+        for frame in &mut frames {
+            limiter.tick_frame(frame);
+        }
+        for frame in &frames[lookahead * 2..] {
+            assert!((frame[0] - 1.0).abs() < 1e-5, "{} is not at the ceiling", frame[0]);
+            assert!((frame[1] - 0.25).abs() < 1e-5, "{} is not reduced as much", frame[1]);
+            assert_eq!(frame[2], 100.0);
+        }
+    }
+}
@@ -0,0 +1,29 @@
+//! Signal processing routines for use in ```Plugin::run```.
+
+mod sample;
+pub use self::sample::Sample;
+
+mod adsr;
+pub use self::adsr::{Adsr, Retrigger, Stage};
+
+mod envelope;
+pub use self::envelope::{Detection, EnvelopeFollower};
+
+mod lfo;
+pub use self::lfo::{Lfo, LfoShape, Polarity, bpm_to_hz};
+
+pub mod simd;
+
+pub mod biquad;
+
+pub mod delay;
+
+pub mod osc;
+
+pub mod fft;
+
+pub mod limiter;
+
+pub mod resample;
+
+pub mod shaper;
@@ -0,0 +1,195 @@
+//! Band-limited oscillators for synthesizers and modulation.
+//!
+//! Naive sawtooth, square and triangle waves have infinitely sharp edges or corners which alias
+//! badly. These are corrected with polynomial band-limited steps (polyBLEP) and ramps
+//! (polyBLAMP): the two samples around each discontinuity are smoothed, which removes most of the
+//! aliasing for a few operations per sample.
+//!
+//! ```no_run
+//! # use ladspa::PortConnection;
+//! # use ladspa::dsp::osc::*;
+//! # struct P { osc: Oscillator }
+//! # fn new(sample_rate: u64) -> P {
+//! // In the constructor:
+//! let osc = Oscillator::new(Waveform::Saw, 440.0, sample_rate);
+//! # P { osc }
+//! # }
+//! # impl P { fn run(&mut self, ports: &[&PortConnection], input: &[f32], mut output: &mut [f32]) {
+//!
+//! // In run:
+//! self.osc.set_frequency(ports[1].unwrap_control());
+//! self.osc.fill(&mut output);
+//! # }
+//! # }
+//! ```
+//!
+//! The phase is kept in ```f64``` whatever the ```Sample``` type, so long notes don't drift.
+
+use core::f64::consts::PI;
+use core::marker::PhantomData;
+
+use Data;
+use super::Sample;
+#[cfg(not(any(feature = "std", test)))]
+use math::Float;
+
+/// The shape of the wave produced by an ```Oscillator```. All of them range from -1 to 1 and,
+/// except for the sawtooth, start at 0 or +1 and rise through the first quarter of the period,
+/// as the sine does.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Waveform {
+    /// A pure tone.
+    Sine,
+    /// Ramps up from -1 to 1 over each period.
+    Saw,
+    /// +1 for the first half of each period and -1 for the second.
+    Square,
+    /// Ramps from 0 up to 1, down to -1 and back to 0 over each period.
+    Triangle,
+}
+
+/// A band-limited oscillator.
+///
+/// Changing the frequency keeps the phase, so it can be modulated every sample without clicks,
+/// either with ```set_frequency``` between calls to ```tick``` or with
+/// ```fill_modulated```.
+#[derive(Clone, Debug)]
+pub struct Oscillator<S: Sample = Data> {
+    waveform: Waveform,
+    frequency: f32,
+    sample_rate: u64,
+    // The position in the period, from 0 to 1, and how far it moves every sample.
+    phase: f64,
+    increment: f64,
+    _sample: PhantomData<S>,
+}
+
+// The correction to apply around a step of +2 at phase 0, at phase `t` with a phase increment of
+// `dt` per sample.
+fn poly_blep(t: f64, dt: f64) -> f64 {
+    if t < dt {
+        let t = t / dt;
+        t + t - t * t - 1.0
+    } else if t > 1.0 - dt {
+        let t = (t - 1.0) / dt;
+        t * t + t + t + 1.0
+    } else {
+        0.0
+    }
+}
+
+// The correction to apply around a change of slope of +2 per sample at phase 0; the integral of
+// `poly_blep`.
+fn poly_blamp(t: f64, dt: f64) -> f64 {
+    if t < dt {
+        let t = t / dt - 1.0;
+        -t * t * t / 3.0
+    } else if t > 1.0 - dt {
+        let t = (t - 1.0) / dt + 1.0;
+        t * t * t / 3.0
+    } else {
+        0.0
+    }
+}
+
+// Wraps a phase into [0, 1).
+fn wrap(phase: f64) -> f64 {
+    phase - phase as i64 as f64
+}
+
+impl<S: Sample> Oscillator<S> {
+    /// Creates an oscillator at ```frequency``` Hz, starting at phase 0.
+    pub fn new(waveform: Waveform, frequency: f32, sample_rate: u64) -> Oscillator<S> {
+        let mut osc = Oscillator {
+            waveform,
+            frequency,
+            sample_rate,
+            phase: 0.0,
+            increment: 0.0,
+            _sample: PhantomData,
+        };
+        osc.set_frequency(frequency);
+        osc
+    }
+
+    /// Changes the waveform, keeping the phase.
+    pub fn set_waveform(&mut self, waveform: Waveform) {
+        self.waveform = waveform;
+    }
+
+    /// Changes the frequency, in Hz, keeping the phase. Frequencies are limited to between 0 and
+    /// the Nyquist frequency.
+    pub fn set_frequency(&mut self, frequency: f32) {
+        self.frequency = frequency;
+        self.increment = (f64::from(frequency) / self.sample_rate as f64).clamp(0.0, 0.5);
+    }
+
+    /// Changes the sample rate, keeping the frequency in Hz.
+    pub fn set_sample_rate(&mut self, sample_rate: u64) {
+        self.sample_rate = sample_rate;
+        let frequency = self.frequency;
+        self.set_frequency(frequency);
+    }
+
+    /// Moves to ```phase```, as a fraction of the period.
+    pub fn set_phase(&mut self, phase: f64) {
+        self.phase = wrap(phase.abs());
+    }
+
+    /// Returns to phase 0, as needed in ```Plugin::activate``` or when a note starts.
+    pub fn reset(&mut self) {
+        self.phase = 0.0;
+    }
+
+    /// The waveform.
+    pub fn waveform(&self) -> Waveform {
+        self.waveform
+    }
+
+    /// The frequency, in Hz.
+    pub fn frequency(&self) -> f32 {
+        self.frequency
+    }
+
+    /// The current phase, as a fraction of the period.
+    pub fn phase(&self) -> f64 {
+        self.phase
+    }
+
+    /// Returns the next sample.
+    pub fn tick(&mut self) -> S {
+        let (t, dt) = (self.phase, self.increment);
+        let value = match self.waveform {
+            Waveform::Sine => (2.0 * PI * t).sin(),
+            Waveform::Saw => 2.0 * t - 1.0 - poly_blep(t, dt),
+            Waveform::Square => {
+                let naive = if t < 0.5 { 1.0 } else { -1.0 };
+                naive + poly_blep(t, dt) - poly_blep(wrap(t + 0.5), dt)
+            }
+            Waveform::Triangle => {
+                // The corners are at the quarter periods; move the lower one to phase 0.
+                let t = wrap(t + 0.25);
+                let naive = if t < 0.5 { 4.0 * t - 1.0 } else { 3.0 - 4.0 * t };
+                naive + 4.0 * dt * (poly_blamp(t, dt) - poly_blamp(wrap(t + 0.5), dt))
+            }
+        };
+        self.phase = wrap(t + dt);
+        S::from_f64(value)
+    }
+
+    /// Fills ```out``` with the next samples.
+    pub fn fill(&mut self, out: &mut [S]) {
+        for sample in out.iter_mut() {
+            *sample = self.tick();
+        }
+    }
+
+    /// Fills ```out``` with the next samples, taking the frequency of each from ```frequency```,
+    /// in Hz. Processes as many samples as the shorter one holds; the last frequency is kept.
+    pub fn fill_modulated(&mut self, out: &mut [S], frequency: &[S]) {
+        for (sample, &frequency) in out.iter_mut().zip(frequency) {
+            self.set_frequency(frequency.to_f64() as f32);
+            *sample = self.tick();
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! Sample rate conversion with a polyphase windowed sinc filter.
+//!
+//! ```Resampler``` converts a stream block by block, as needed for oversampling inside
+//! ```Plugin::run```; ```resample``` converts a whole buffer at once, as needed to bring an
+//! impulse response or a wavetable recorded at a fixed rate to the rate passed to
+//! ```instantiate```:
+//!
+//! ```no_run
+//! # use ladspa::{InstantiateError, Plugin, PluginDescriptor, PortConnection};
+//! # use ladspa::dsp::resample::{resample, Quality};
+//! # static IMPULSE_RESPONSE: [f32; 4] = [1.0, 0.5, 0.25, 0.125];
+//! # struct Reverb;
+//! # impl Reverb { fn new(_: Vec<f32>) -> Reverb { Reverb } }
+//! # impl Plugin for Reverb { fn run<'a>(&mut self, _: usize, _: &mut [PortConnection<'a>]) {} }
+//! fn new_reverb(_: &PluginDescriptor, sample_rate: u64)
+//!               -> Result<Box<dyn Plugin + Send>, InstantiateError> {
+//!     let ir = resample(&IMPULSE_RESPONSE, 48000, sample_rate, Quality::High);
+//!     Ok(Box::new(Reverb::new(ir)))
+//! }
+//! ```
+//!
+//! Any pair of rates can be converted: the filter is tabulated at a fixed number of fractional
+//! positions between input samples and interpolated linearly between them.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::f64::consts::PI;
+
+use Data;
+use super::Sample;
+#[cfg(not(any(feature = "std", test)))]
+use math::Float;
+
+// The number of fractional positions the filter is tabulated at.
+const PHASES: usize = 256;
+
+/// The trade-off between the accuracy and the cost of a ```Resampler```.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Quality {
+    /// 16 taps, passing frequencies up to 85% of the lower Nyquist frequency.
+    Low,
+    /// 32 taps, passing frequencies up to 90% of the lower Nyquist frequency.
+    Medium,
+    /// 64 taps, passing frequencies up to 95% of the lower Nyquist frequency.
+    High,
+}
+
+impl Quality {
+    // The number of taps when upsampling and the passband, relative to the Nyquist frequency.
+    fn parameters(self) -> (usize, f64) {
+        match self {
+            Quality::Low => (16, 0.85),
+            Quality::Medium => (32, 0.9),
+            Quality::High => (64, 0.95),
+        }
+    }
+}
+
+/// Converts a stream of samples from one sample rate to another.
+///
+/// The input and output don't advance in step, so ```process``` reports how much of each it
+/// used. When oversampling by a whole factor, every input sample gives exactly that many output
+/// samples and the other way round.
+///
+/// The filter is centered ```latency``` input samples in the past, which delays the output by as
+/// much.
+#[derive(Clone, Debug)]
+pub struct Resampler<S: Sample = Data> {
+    taps: usize,
+    // Input samples per output sample.
+    step: f64,
+    // The filter for each tabulated position from 0 to 1 inclusive, its taps in the order of
+    // `history`.
+    table: Vec<S>,
+    // The last `taps` input samples, stored twice so they can always be read in order as a
+    // single slice starting at `position`.
+    history: Vec<S>,
+    position: usize,
+    // The time of the next output sample after the latest input sample, in input samples.
+    // Outputs are due while it is below 1.
+    time: f64,
+}
+
+impl<S: Sample> Resampler<S> {
+    /// Prepares a conversion from ```input_rate``` to ```output_rate```.
+    pub fn new(input_rate: u64, output_rate: u64, quality: Quality) -> Resampler<S> {
+        let (taps, passband) = quality.parameters();
+        // Filter below the lower of the two Nyquist frequencies, widening the filter to keep
+        // the same steepness when that is the output's.
+        let scale = (output_rate as f64 / input_rate as f64).min(1.0);
+        let cutoff = passband * scale;
+        let taps = ((taps as f64 / scale) as usize + 1) & !1;
+        let half = (taps / 2) as f64;
+
+        let mut table = vec![S::ZERO; (PHASES + 1) * taps];
+        let mut coefficients = vec![0.0; taps];
+        for (phase, row) in table.chunks_mut(taps).enumerate() {
+            let fraction = phase as f64 / PHASES as f64;
+            let mut sum = 0.0;
+            for (j, coefficient) in coefficients.iter_mut().enumerate() {
+                // The distance between this output and the input the coefficient applies to.
+                let u = (taps - 1 - j) as f64 - half + fraction;
+                let x = PI * cutoff * u;
+                let sinc = if x == 0.0 { 1.0 } else { x.sin() / x };
+                let w = PI * u / half;
+                let window = 0.42 + 0.5 * w.cos() + 0.08 * (2.0 * w).cos();
+                *coefficient = sinc * window;
+                sum += *coefficient;
+            }
+            for (value, coefficient) in row.iter_mut().zip(&coefficients) {
+                *value = S::from_f64(coefficient / sum);
+            }
+        }
+
+        Resampler {
+            taps,
+            step: input_rate as f64 / output_rate as f64,
+            table,
+            history: vec![S::ZERO; 2 * taps],
+            position: 0,
+            time: 1.0,
+        }
+    }
+
+    /// The delay of the output, in input samples.
+    pub fn latency(&self) -> usize {
+        self.taps / 2
+    }
+
+    /// The number of input samples consumed per output sample produced.
+    pub fn ratio(&self) -> f64 {
+        self.step
+    }
+
+    /// Clears the history, as needed in ```Plugin::activate```.
+    pub fn reset(&mut self) {
+        for sample in self.history.iter_mut() {
+            *sample = S::ZERO;
+        }
+        self.position = 0;
+        self.time = 1.0;
+    }
+
+    fn push(&mut self, sample: S) {
+        self.history[self.position] = sample;
+        self.history[self.position + self.taps] = sample;
+        self.position = (self.position + 1) % self.taps;
+    }
+
+    fn interpolate(&self) -> S {
+        let position = self.time * PHASES as f64;
+        let phase = position as usize;
+        let fraction = S::from_f64(position - phase as f64);
+        let history = &self.history[self.position..self.position + self.taps];
+        let first = &self.table[phase * self.taps..(phase + 1) * self.taps];
+        let second = &self.table[(phase + 1) * self.taps..(phase + 2) * self.taps];
+        let mut output = S::ZERO;
+        for ((&sample, &a), &b) in history.iter().zip(first).zip(second) {
+            output += sample * (a + (b - a) * fraction);
+        }
+        output
+    }
+
+    /// Converts ```input``` into ```output```, stopping when either runs out. Returns the number
+    /// of input samples consumed and of output samples produced.
+    pub fn process(&mut self, input: &[S], output: &mut [S]) -> (usize, usize) {
+        let (mut consumed, mut produced) = (0, 0);
+        loop {
+            while self.time < 1.0 {
+                if produced == output.len() {
+                    return (consumed, produced);
+                }
+                output[produced] = self.interpolate();
+                produced += 1;
+                self.time += self.step;
+            }
+            if consumed == input.len() {
+                return (consumed, produced);
+            }
+            self.time -= 1.0;
+            self.push(input[consumed]);
+            consumed += 1;
+        }
+    }
+}
+
+/// Converts a whole buffer from ```input_rate``` to ```output_rate```. The output starts at the
+/// same time as the input, without the latency of a ```Resampler```, and lasts as long.
+pub fn resample<S: Sample>(input: &[S], input_rate: u64, output_rate: u64, quality: Quality)
+                           -> Vec<S> {
+    let mut resampler = Resampler::new(input_rate, output_rate, quality);
+    let len = (input.len() as f64 * output_rate as f64 / input_rate as f64) as usize;
+    let mut output = vec![S::ZERO; len];
+    // Skip the latency: the first output is due once the first input is centered in the filter.
+    resampler.time += resampler.latency() as f64;
+    let (_, mut produced) = resampler.process(input, &mut output);
+    let silence = [S::ZERO; 64];
+    while produced < len {
+        produced += resampler.process(&silence, &mut output[produced..]).1;
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use core::f64::consts::PI;
+
+    use super::*;
+
+    fn sine(hz: f64, sample_rate: u64, len: usize) -> Vec<f32> {
+        (0..len).map(|n| (2.0 * PI * hz * n as f64 / sample_rate as f64).sin() as f32).collect()
+    }
+
+    #[test]
+    fn oversampling_gives_a_whole_number_of_outputs_per_input() {
+        let mut resampler = Resampler::<f32>::new(48000, 96000, Quality::Medium);
+        let mut output = [0.0; 256];
+        assert_eq!(resampler.process(&[0.0; 100], &mut output), (100, 200));
+        let mut resampler = Resampler::<f32>::new(96000, 48000, Quality::Medium);
+        assert_eq!(resampler.process(&[0.0; 100], &mut output), (100, 50));
+    }
+
+    #[test]
+    fn the_output_is_delayed_by_the_latency() {
+        let mut resampler = Resampler::<f32>::new(48000, 48000, Quality::Low);
+        let mut input = [0.0; 64];
+        input[0] = 1.0;
+        let mut output = [0.0; 64];
+        resampler.process(&input, &mut output);
+        let peak = (0..64).max_by(|&a, &b| output[a].total_cmp(&output[b])).unwrap();
+        assert_eq!(peak, resampler.latency());
+    }
+
+    #[test]
+    fn sines_keep_their_frequency_and_phase() {
+        let input = sine(1000.0, 44100, 4410);
+        let output = resample(&input, 44100, 48000, Quality::High);
+        assert_eq!(output.len(), 4800);
+        let expected = sine(1000.0, 48000, 4800);
+        // Away from the edges, where the filter sees silence past the ends of the input.
+        for (&out, &expected) in output[200..4600].iter().zip(&expected[200..4600]) {
+            assert!((out - expected).abs() < 1e-3, "{} is not {}", out, expected);
+        }
+    }
+}
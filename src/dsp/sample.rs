@@ -0,0 +1,77 @@
+use core::fmt::Debug;
+use core::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+
+use Data;
+use super::simd::Kernels;
+
+/// A floating point type audio can be processed in: ```f32```, the type of ```Data```, or
+/// ```f64``` for code which needs the extra precision, such as recursive filters with low cutoff
+/// frequencies.
+///
+/// The routines in ```dsp``` and ```util``` are generic over it, and ```adapter::Promote``` runs
+/// a plugin in ```f64``` while the host exchanges ```f32``` buffers with it.
+pub trait Sample: Copy + Default + PartialOrd + Debug + Send + Sync + 'static + Kernels
+    + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> + Div<Output = Self>
+    + Neg<Output = Self> + AddAssign + SubAssign + MulAssign + DivAssign
+{
+    /// Zero, which is also silence.
+    const ZERO: Self;
+    /// One.
+    const ONE: Self;
+
+    /// Converts from the type used by the host.
+    fn from_data(value: Data) -> Self;
+    /// Converts to the type used by the host, rounding if needed.
+    fn to_data(self) -> Data;
+    /// Converts from ```f64```, rounding if needed.
+    fn from_f64(value: f64) -> Self;
+    /// Converts to ```f64```.
+    fn to_f64(self) -> f64;
+
+    /// Returns the absolute value.
+    fn abs(self) -> Self {
+        if self < Self::ZERO { -self } else { self }
+    }
+}
+
+impl Sample for f32 {
+    const ZERO: f32 = 0.0;
+    const ONE: f32 = 1.0;
+
+    fn from_data(value: Data) -> f32 {
+        value
+    }
+
+    fn to_data(self) -> Data {
+        self
+    }
+
+    fn from_f64(value: f64) -> f32 {
+        value as f32
+    }
+
+    fn to_f64(self) -> f64 {
+        f64::from(self)
+    }
+}
+
+impl Sample for f64 {
+    const ZERO: f64 = 0.0;
+    const ONE: f64 = 1.0;
+
+    fn from_data(value: Data) -> f64 {
+        f64::from(value)
+    }
+
+    fn to_data(self) -> Data {
+        self as Data
+    }
+
+    fn from_f64(value: f64) -> f64 {
+        value
+    }
+
+    fn to_f64(self) -> f64 {
+        self
+    }
+}
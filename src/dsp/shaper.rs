@@ -0,0 +1,221 @@
+//! Waveshaping and soft clipping, for distortion and saturation effects.
+//!
+//! The transfer functions are available on their own, to apply sample by sample, or through a
+//! ```Shaper``` which adds a drive gain and can run at twice the sample rate:
+//!
+//! ```no_run
+//! # use ladspa::PortConnection;
+//! # use ladspa::dsp::simd;
+//! # use ladspa::dsp::shaper::*;
+//! # use ladspa::dsp::resample::Quality;
+//! # struct P { shaper: Shaper }
+//! # fn new() -> P {
+//! // In the constructor:
+//! let shaper = Shaper::oversampled(Curve::Tanh, Quality::Medium);
+//! # P { shaper }
+//! # }
+//! # impl P { fn run(&mut self, ports: &[&PortConnection], input: &[f32], mut output: &mut [f32]) {
+//!
+//! // In run:
+//! self.shaper.set_drive(ports[2].unwrap_control());
+//! simd::copy(&mut output, input);
+//! self.shaper.process(&mut output);
+//! # }
+//! # }
+//! ```
+//!
+//! Shaping adds harmonics, and those above the Nyquist frequency fold back down as inharmonic
+//! aliases. Oversampling pushes the Nyquist frequency up an octave so that fewer of them fold
+//! back, at the cost of the latency of the resampling filters.
+
+use alloc::vec::Vec;
+
+use Data;
+use super::Sample;
+use super::resample::{Quality, Resampler};
+#[cfg(not(any(feature = "std", test)))]
+use math::Float;
+
+// The number of samples an oversampled `Shaper` processes at once, sized so its buffers fit on
+// the stack.
+const CHUNK: usize = 64;
+
+/// The hyperbolic tangent: a smooth saturation approaching ±1.
+pub fn tanh<S: Sample>(x: S) -> S {
+    let x = x.to_f64().clamp(-20.0, 20.0);
+    S::from_f64(1.0 - 2.0 / ((2.0 * x).exp() + 1.0))
+}
+
+/// A cubic soft clipper, ```1.5 x - 0.5 x³```, reaching ±1 at ±1 and clipping beyond. Cheaper
+/// than ```tanh``` and more linear at low levels.
+pub fn cubic<S: Sample>(x: S) -> S {
+    let x = hard_clip(x);
+    S::from_f64(1.5) * x - S::from_f64(0.5) * x * x * x
+}
+
+/// Limits ```x``` to between -1 and 1.
+pub fn hard_clip<S: Sample>(x: S) -> S {
+    if x > S::ONE {
+        S::ONE
+    } else if x < -S::ONE {
+        -S::ONE
+    } else {
+        x
+    }
+}
+
+/// An arbitrary transfer function, tabulated between -1 and 1 and interpolated linearly. Inputs
+/// beyond that range take the value at the nearest end.
+#[derive(Clone, Debug)]
+pub struct LookupTable<S: Sample = Data> {
+    values: Vec<S>,
+}
+
+impl<S: Sample> LookupTable<S> {
+    /// Tabulates ```function``` at ```size``` points evenly spread from -1 to 1. Panics if
+    /// ```size``` is less than 2.
+    pub fn new<F: Fn(f64) -> f64>(size: usize, function: F) -> LookupTable<S> {
+        assert!(size >= 2, "a lookup table needs at least 2 points");
+        let last = (size - 1) as f64;
+        let point = |i: usize| S::from_f64(function(2.0 * i as f64 / last - 1.0));
+        LookupTable::from_values((0..size).map(point).collect())
+    }
+
+    /// Uses ```values``` as the outputs for inputs evenly spread from -1 to 1. Panics if there
+    /// are less than 2.
+    pub fn from_values(values: Vec<S>) -> LookupTable<S> {
+        assert!(values.len() >= 2, "a lookup table needs at least 2 points");
+        LookupTable { values }
+    }
+
+    /// The tabulated values.
+    pub fn values(&self) -> &[S] {
+        &self.values
+    }
+
+    /// Evaluates the function at ```x```.
+    pub fn eval(&self, x: S) -> S {
+        let last = self.values.len() - 1;
+        let position = ((x.to_f64() + 1.0) * 0.5 * last as f64).clamp(0.0, last as f64);
+        let index = (position as usize).min(last - 1);
+        let fraction = S::from_f64(position - index as f64);
+        let (a, b) = (self.values[index], self.values[index + 1]);
+        a + (b - a) * fraction
+    }
+}
+
+/// The transfer function of a ```Shaper```.
+#[derive(Clone, Debug)]
+pub enum Curve<S: Sample = Data> {
+    /// ```tanh```.
+    Tanh,
+    /// ```cubic```.
+    Cubic,
+    /// ```hard_clip```.
+    HardClip,
+    /// A ```LookupTable```.
+    Table(LookupTable<S>),
+}
+
+impl<S: Sample> Curve<S> {
+    /// Applies the function to ```x```.
+    pub fn apply(&self, x: S) -> S {
+        match *self {
+            Curve::Tanh => tanh(x),
+            Curve::Cubic => cubic(x),
+            Curve::HardClip => hard_clip(x),
+            Curve::Table(ref table) => table.eval(x),
+        }
+    }
+}
+
+/// Applies a ```Curve``` to audio after multiplying it by a drive gain, optionally at twice the
+/// sample rate.
+#[derive(Clone, Debug)]
+pub struct Shaper<S: Sample = Data> {
+    curve: Curve<S>,
+    drive: S,
+    oversampling: Option<(Resampler<S>, Resampler<S>)>,
+}
+
+impl<S: Sample> Shaper<S> {
+    /// Creates a shaper running at the sample rate, with a drive of 1.
+    pub fn new(curve: Curve<S>) -> Shaper<S> {
+        Shaper { curve, drive: S::ONE, oversampling: None }
+    }
+
+    /// Creates a shaper running at twice the sample rate, resampling with ```quality```.
+    pub fn oversampled(curve: Curve<S>, quality: Quality) -> Shaper<S> {
+        Shaper {
+            curve,
+            drive: S::ONE,
+            oversampling: Some((Resampler::new(1, 2, quality), Resampler::new(2, 1, quality))),
+        }
+    }
+
+    /// Changes the transfer function.
+    pub fn set_curve(&mut self, curve: Curve<S>) {
+        self.curve = curve;
+    }
+
+    /// Changes the gain applied before the transfer function.
+    pub fn set_drive(&mut self, drive: S) {
+        self.drive = drive;
+    }
+
+    /// The transfer function.
+    pub fn curve(&self) -> &Curve<S> {
+        &self.curve
+    }
+
+    /// The gain applied before the transfer function.
+    pub fn drive(&self) -> S {
+        self.drive
+    }
+
+    /// Whether the shaper runs at twice the sample rate.
+    pub fn is_oversampled(&self) -> bool {
+        self.oversampling.is_some()
+    }
+
+    /// The delay of the output, in samples: that of the resampling filters when oversampled, 0
+    /// otherwise.
+    pub fn latency(&self) -> usize {
+        match self.oversampling {
+            Some((ref up, ref down)) => up.latency() + down.latency() / 2,
+            None => 0,
+        }
+    }
+
+    /// Clears the resampling filters, as needed in ```Plugin::activate```.
+    pub fn reset(&mut self) {
+        if let Some((ref mut up, ref mut down)) = self.oversampling {
+            up.reset();
+            down.reset();
+        }
+    }
+
+    /// Shapes ```buf``` in place.
+    pub fn process(&mut self, buf: &mut [S]) {
+        let (curve, drive) = (&self.curve, self.drive);
+        let (up, down) = match self.oversampling {
+            Some((ref mut up, ref mut down)) => (up, down),
+            None => {
+                for sample in buf.iter_mut() {
+                    *sample = curve.apply(*sample * drive);
+                }
+                return;
+            }
+        };
+        let mut oversampled = [S::ZERO; 2 * CHUNK];
+        for chunk in buf.chunks_mut(CHUNK) {
+            let oversampled = &mut oversampled[..2 * chunk.len()];
+            // Whole factors convert in step, so both calls use all of their input.
+            up.process(chunk, oversampled);
+            for sample in oversampled.iter_mut() {
+                *sample = curve.apply(*sample * drive);
+            }
+            down.process(oversampled, chunk);
+        }
+    }
+}
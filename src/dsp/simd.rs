@@ -0,0 +1,366 @@
+//! Vectorized operations on audio buffers.
+//!
+//! These cover the inner loops found in most plugins. With the ```simd``` feature enabled they use
+//! SSE instructions on x86 and x86-64 for ```f32``` buffers; otherwise they are written in fixed
+//! size chunks which the compiler can usually vectorize itself. Either way they never allocate, so
+//! they are safe to use in hard real-time code.
+//!
+//! Functions taking two buffers process as many samples as the shorter one holds, and those
+//! converting between interleaved and planar audio as many frames as all the buffers hold.
+
+use super::Sample;
+
+/// Copies ```src``` into ```dst```.
+pub fn copy<S: Sample>(dst: &mut [S], src: &[S]) {
+    let len = dst.len().min(src.len());
+    dst[..len].copy_from_slice(&src[..len]);
+}
+
+/// Adds ```src``` to ```dst```, sample by sample.
+pub fn add<S: Sample>(dst: &mut [S], src: &[S]) {
+    S::mix(dst, src, S::ONE)
+}
+
+/// Multiplies every sample of ```buf``` by ```gain```.
+pub fn scale<S: Sample>(buf: &mut [S], gain: S) {
+    S::scale(buf, gain)
+}
+
+/// Adds ```src``` scaled by ```gain``` to ```dst```, as needed by ```Plugin::run_adding```.
+pub fn mix<S: Sample>(dst: &mut [S], src: &[S], gain: S) {
+    S::mix(dst, src, gain)
+}
+
+/// Returns the largest absolute value in ```buf```, or 0 if it is empty.
+pub fn peak<S: Sample>(buf: &[S]) -> S {
+    S::peak(buf)
+}
+
+/// Interleaves the planar ```channels``` into ```dst```, which receives a frame holding a sample
+/// of each channel after the other, as used by most audio libraries and file formats.
+///
+/// ```no_run
+/// # use ladspa::dsp::simd;
+/// # struct P { frames: Vec<f32> }
+/// # impl P { fn run(&mut self, left: &[f32], right: &[f32]) {
+/// simd::interleave(&mut self.frames, &[&left, &right]);
+/// # }
+/// # }
+/// ```
+pub fn interleave<S: Sample>(dst: &mut [S], channels: &[&[S]]) {
+    match *channels {
+        [] => {}
+        [mono] => copy(dst, mono),
+        [left, right] => S::interleave2(dst, left, right),
+        _ => {
+            let count = channels.len();
+            for (channel, src) in channels.iter().enumerate() {
+                for (frame, &sample) in dst.chunks_exact_mut(count).zip(*src) {
+                    frame[channel] = sample;
+                }
+            }
+        }
+    }
+}
+
+/// Splits the interleaved frames of ```src``` into the planar ```channels```, undoing
+/// ```interleave```.
+pub fn deinterleave<S: Sample>(src: &[S], channels: &mut [&mut [S]]) {
+    match *channels {
+        [] => {}
+        [ref mut mono] => copy(mono, src),
+        [ref mut left, ref mut right] => S::deinterleave2(src, left, right),
+        _ => {
+            let count = channels.len();
+            for (channel, dst) in channels.iter_mut().enumerate() {
+                for (sample, frame) in dst.iter_mut().zip(src.chunks_exact(count)) {
+                    *sample = frame[channel];
+                }
+            }
+        }
+    }
+}
+
+/// The implementations of the routines above for each ```Sample``` type, letting ```f32``` use
+/// SSE.
+#[doc(hidden)]
+pub trait Kernels: Sized {
+    fn scale(buf: &mut [Self], gain: Self);
+    fn mix(dst: &mut [Self], src: &[Self], gain: Self);
+    fn peak(buf: &[Self]) -> Self;
+    fn interleave2(dst: &mut [Self], left: &[Self], right: &[Self]);
+    fn deinterleave2(src: &[Self], left: &mut [Self], right: &mut [Self]);
+}
+
+impl Kernels for f32 {
+    fn scale(buf: &mut [f32], gain: f32) {
+        imp::scale(buf, gain)
+    }
+
+    fn mix(dst: &mut [f32], src: &[f32], gain: f32) {
+        imp::mix(dst, src, gain)
+    }
+
+    fn peak(buf: &[f32]) -> f32 {
+        imp::peak(buf)
+    }
+
+    fn interleave2(dst: &mut [f32], left: &[f32], right: &[f32]) {
+        imp::interleave2(dst, left, right)
+    }
+
+    fn deinterleave2(src: &[f32], left: &mut [f32], right: &mut [f32]) {
+        imp::deinterleave2(src, left, right)
+    }
+}
+
+impl Kernels for f64 {
+    fn scale(buf: &mut [f64], gain: f64) {
+        portable::scale(buf, gain)
+    }
+
+    fn mix(dst: &mut [f64], src: &[f64], gain: f64) {
+        portable::mix(dst, src, gain)
+    }
+
+    fn peak(buf: &[f64]) -> f64 {
+        portable::peak(buf)
+    }
+
+    fn interleave2(dst: &mut [f64], left: &[f64], right: &[f64]) {
+        portable::interleave2(dst, left, right)
+    }
+
+    fn deinterleave2(src: &[f64], left: &mut [f64], right: &mut [f64]) {
+        portable::deinterleave2(src, left, right)
+    }
+}
+
+mod portable {
+    use super::super::Sample;
+
+    // The number of samples processed per iteration, chosen to fill the widest common vector
+    // registers.
+    const CHUNK: usize = 8;
+
+    fn max<S: Sample>(a: S, b: S) -> S {
+        if b > a { b } else { a }
+    }
+
+    pub fn scale<S: Sample>(buf: &mut [S], gain: S) {
+        let mut chunks = buf.chunks_exact_mut(CHUNK);
+        for chunk in &mut chunks {
+            for sample in chunk {
+                *sample *= gain;
+            }
+        }
+        for sample in chunks.into_remainder() {
+            *sample *= gain;
+        }
+    }
+
+    pub fn mix<S: Sample>(dst: &mut [S], src: &[S], gain: S) {
+        let len = dst.len().min(src.len());
+        let (dst, src) = (&mut dst[..len], &src[..len]);
+        let mut dst_chunks = dst.chunks_exact_mut(CHUNK);
+        let mut src_chunks = src.chunks_exact(CHUNK);
+        for (d, s) in (&mut dst_chunks).zip(&mut src_chunks) {
+            for i in 0..CHUNK {
+                d[i] += s[i] * gain;
+            }
+        }
+        for (d, s) in dst_chunks.into_remainder().iter_mut().zip(src_chunks.remainder()) {
+            *d += *s * gain;
+        }
+    }
+
+    pub fn peak<S: Sample>(buf: &[S]) -> S {
+        let mut chunks = buf.chunks_exact(CHUNK);
+        let mut peaks = [S::ZERO; CHUNK];
+        for chunk in &mut chunks {
+            for i in 0..CHUNK {
+                peaks[i] = max(peaks[i], chunk[i].abs());
+            }
+        }
+        let peak = chunks.remainder().iter().fold(S::ZERO, |peak, s| max(peak, s.abs()));
+        peaks.iter().fold(peak, |peak, &p| max(peak, p))
+    }
+
+    pub fn interleave2<S: Sample>(dst: &mut [S], left: &[S], right: &[S]) {
+        for ((frame, &l), &r) in dst.chunks_exact_mut(2).zip(left).zip(right) {
+            frame[0] = l;
+            frame[1] = r;
+        }
+    }
+
+    pub fn deinterleave2<S: Sample>(src: &[S], left: &mut [S], right: &mut [S]) {
+        for ((frame, l), r) in src.chunks_exact(2).zip(left).zip(right) {
+            *l = frame[0];
+            *r = frame[1];
+        }
+    }
+}
+
+#[cfg(not(all(feature = "simd", any(target_arch = "x86_64",
+                                    all(target_arch = "x86", target_feature = "sse")))))]
+use self::portable as imp;
+
+#[cfg(all(feature = "simd", any(target_arch = "x86_64",
+                                all(target_arch = "x86", target_feature = "sse"))))]
+mod imp {
+    #[cfg(target_arch = "x86")]
+    use core::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use core::arch::x86_64::*;
+
+    use Data;
+
+    // The number of samples in an SSE register.
+    const LANES: usize = 4;
+
+    pub fn scale(buf: &mut [Data], gain: Data) {
+        let mut chunks = buf.chunks_exact_mut(LANES);
+        unsafe {
+            let gain = _mm_set1_ps(gain);
+            for chunk in &mut chunks {
+                let ptr = chunk.as_mut_ptr();
+                _mm_storeu_ps(ptr, _mm_mul_ps(_mm_loadu_ps(ptr), gain));
+            }
+        }
+        for sample in chunks.into_remainder() {
+            *sample *= gain;
+        }
+    }
+
+    pub fn mix(dst: &mut [Data], src: &[Data], gain: Data) {
+        let len = dst.len().min(src.len());
+        let (dst, src) = (&mut dst[..len], &src[..len]);
+        let mut dst_chunks = dst.chunks_exact_mut(LANES);
+        let mut src_chunks = src.chunks_exact(LANES);
+        unsafe {
+            let gain = _mm_set1_ps(gain);
+            for (d, s) in (&mut dst_chunks).zip(&mut src_chunks) {
+                let ptr = d.as_mut_ptr();
+                let scaled = _mm_mul_ps(_mm_loadu_ps(s.as_ptr()), gain);
+                _mm_storeu_ps(ptr, _mm_add_ps(_mm_loadu_ps(ptr), scaled));
+            }
+        }
+        for (d, s) in dst_chunks.into_remainder().iter_mut().zip(src_chunks.remainder()) {
+            *d += *s * gain;
+        }
+    }
+
+    pub fn peak(buf: &[Data]) -> Data {
+        let mut chunks = buf.chunks_exact(LANES);
+        let mut peaks: [Data; LANES] = [0.0; LANES];
+        unsafe {
+            // Clearing the sign bit gives the absolute value.
+            let sign = _mm_set1_ps(-0.0);
+            let mut peak = _mm_setzero_ps();
+            for chunk in &mut chunks {
+                peak = _mm_max_ps(peak, _mm_andnot_ps(sign, _mm_loadu_ps(chunk.as_ptr())));
+            }
+            _mm_storeu_ps(peaks.as_mut_ptr(), peak);
+        }
+        let peak = chunks.remainder().iter().fold(0.0, |peak: Data, s| peak.max(s.abs()));
+        peaks.iter().fold(peak, |peak, &p| peak.max(p))
+    }
+
+    pub fn interleave2(dst: &mut [Data], left: &[Data], right: &[Data]) {
+        let frames = (dst.len() / 2).min(left.len()).min(right.len());
+        let (dst, left, right) = (&mut dst[..frames * 2], &left[..frames], &right[..frames]);
+        let mut dst_chunks = dst.chunks_exact_mut(LANES * 2);
+        let mut left_chunks = left.chunks_exact(LANES);
+        let mut right_chunks = right.chunks_exact(LANES);
+        unsafe {
+            for ((d, l), r) in (&mut dst_chunks).zip(&mut left_chunks).zip(&mut right_chunks) {
+                let (l, r) = (_mm_loadu_ps(l.as_ptr()), _mm_loadu_ps(r.as_ptr()));
+                _mm_storeu_ps(d.as_mut_ptr(), _mm_unpacklo_ps(l, r));
+                _mm_storeu_ps(d.as_mut_ptr().add(LANES), _mm_unpackhi_ps(l, r));
+            }
+        }
+        super::portable::interleave2(dst_chunks.into_remainder(),
+                                     left_chunks.remainder(),
+                                     right_chunks.remainder());
+    }
+
+    pub fn deinterleave2(src: &[Data], left: &mut [Data], right: &mut [Data]) {
+        let frames = (src.len() / 2).min(left.len()).min(right.len());
+        let (src, left, right) = (&src[..frames * 2], &mut left[..frames], &mut right[..frames]);
+        let mut src_chunks = src.chunks_exact(LANES * 2);
+        let mut left_chunks = left.chunks_exact_mut(LANES);
+        let mut right_chunks = right.chunks_exact_mut(LANES);
+        unsafe {
+            for ((s, l), r) in (&mut src_chunks).zip(&mut left_chunks).zip(&mut right_chunks) {
+                let (a, b) = (_mm_loadu_ps(s.as_ptr()), _mm_loadu_ps(s.as_ptr().add(LANES)));
+                // Even lanes hold the left channel, odd ones the right.
+                _mm_storeu_ps(l.as_mut_ptr(), _mm_shuffle_ps(a, b, 0b10_00_10_00));
+                _mm_storeu_ps(r.as_mut_ptr(), _mm_shuffle_ps(a, b, 0b11_01_11_01));
+            }
+        }
+        super::portable::deinterleave2(src_chunks.remainder(),
+                                       left_chunks.into_remainder(),
+                                       right_chunks.into_remainder());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    // Empty buffers, buffers shorter than a vector, and lengths leaving a remainder after whole
+    // vectors and chunks.
+    const LENGTHS: [usize; 6] = [0, 1, 3, 7, 17, 1023];
+
+    // Samples of both signs, differing from one lane to the next.
+    fn signal(len: usize, seed: f32) -> Vec<f32> {
+        (0..len).map(|n| ((n as f32 + seed) * 0.37).sin() * (n % 5 + 1) as f32).collect()
+    }
+
+    #[test]
+    fn copy_copies_as_many_samples_as_the_shorter_buffer_holds() {
+        for &len in &LENGTHS {
+            let src = signal(len, 1.0);
+            let mut dst = vec![9.0; len + 2];
+            copy(&mut dst, &src);
+            assert_eq!(dst[..len], src[..]);
+            assert_eq!(dst[len..], [9.0, 9.0]);
+        }
+    }
+
+    #[test]
+    fn add_scale_and_mix_match_the_scalar_routines() {
+        for &len in &LENGTHS {
+            let src = signal(len, 1.0);
+            let (mut vectorized, mut scalar) = (signal(len, 2.0), signal(len, 2.0));
+            add(&mut vectorized, &src);
+            portable::mix(&mut scalar, &src, 1.0);
+            assert_eq!(vectorized, scalar, "add, {} samples", len);
+
+            scale(&mut vectorized, -0.3);
+            portable::scale(&mut scalar, -0.3);
+            assert_eq!(vectorized, scalar, "scale, {} samples", len);
+
+            mix(&mut vectorized, &src, 0.7);
+            portable::mix(&mut scalar, &src, 0.7);
+            assert_eq!(vectorized, scalar, "mix, {} samples", len);
+        }
+    }
+
+    #[test]
+    fn peak_matches_the_scalar_routine() {
+        for &len in &LENGTHS {
+            let mut buf = signal(len, 3.0);
+            assert_eq!(peak(&buf), portable::peak(&buf), "{} samples", len);
+            // The loudest sample in the remainder, and negative.
+            if let Some(last) = buf.last_mut() {
+                *last = -10.0;
+                assert_eq!(peak(&buf), 10.0, "{} samples", len);
+            }
+        }
+        assert_eq!(peak::<f32>(&[]), 0.0);
+    }
+}
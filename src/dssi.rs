@@ -0,0 +1,212 @@
+//! Exporting plugins as DSSI plugins, which can receive MIDI.
+//!
+//! With the ```dssi``` feature enabled, the plugin library exports ```dssi_descriptor``` next to
+//! ```ladspa_descriptor```. DSSI hosts run plugins through ```Plugin::run_synth```, which is
+//! passed the MIDI events received during the block. Its default implementation ignores them and
+//! calls ```Plugin::run```, so effects keep working unchanged when loaded by a DSSI host.
+//!
+//! Synths which should follow MIDI controllers can leave them to the crate: wrapped in an
+//! ```adapter::MidiControlled```, they see the control inputs listed in a table of ```cc```
+//! mappings move with their controllers, and only the other events reach ```run_synth```:
+//!
+//! ```no_run
+//! # use ladspa::{InstantiateError, Plugin, PluginDescriptor, PortConnection};
+//! # use ladspa::adapter::MidiControlled;
+//! # use ladspa::dssi::{cc, CcMapping, Curve};
+//! # struct Synth;
+//! # impl Synth { fn new(_: u64) -> Synth { Synth } }
+//! # impl Plugin for Synth { fn run<'a>(&mut self, _: usize, _: &mut [PortConnection<'a>]) {} }
+//! const CONTROLLERS: &[CcMapping] = &[
+//!     cc(74).to("Cutoff (Hz)").curve(Curve::Logarithmic),
+//!     cc(71).to("Resonance").range(0.5..4.0),
+//! ];
+//!
+//! fn new_synth(_: &PluginDescriptor, sample_rate: u64)
+//!              -> Result<Box<dyn Plugin + Send>, InstantiateError> {
+//!     Ok(Box::new(MidiControlled::new(Synth::new(sample_rate), sample_rate, CONTROLLERS)))
+//! }
+//! ```
+
+// c_ulong is only 32 bits wide on some platforms, so widening it is not always a no-op.
+#![allow(clippy::useless_conversion)]
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::ops::Range;
+use core::ptr;
+
+use libc::{c_ulong, c_void};
+
+use Data;
+use ffi::{self, dssi_h};
+use lock::{at_unload, Lock};
+
+/// A MIDI message sent to the plugin by the host.
+///
+/// A ```NoteOn``` with a velocity of zero is reported as a ```NoteOff```.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MidiMessage {
+    /// A key was pressed.
+    NoteOn { channel: u8, note: u8, velocity: u8 },
+    /// A key was released.
+    NoteOff { channel: u8, note: u8, velocity: u8 },
+    /// The pressure on a held key changed (polyphonic aftertouch).
+    PolyPressure { channel: u8, note: u8, pressure: u8 },
+    /// A controller, such as the modulation wheel, changed value.
+    ControlChange { channel: u8, controller: u8, value: u8 },
+    /// A different program was selected.
+    ProgramChange { channel: u8, program: u8 },
+    /// The pressure on all held keys changed (channel aftertouch).
+    ChannelPressure { channel: u8, pressure: u8 },
+    /// The pitch wheel moved. ```value``` ranges from -8192 to 8191, with 0 at the center.
+    PitchBend { channel: u8, value: i16 },
+}
+
+/// The most MIDI events passed to ```Plugin::run_synth``` in a block. Past it, the rest of the
+/// block's events are dropped rather than allocating on the audio thread.
+pub const MAX_BLOCK_EVENTS: usize = 512;
+
+/// A MIDI message along with the time at which it happens.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MidiEvent {
+    /// The offset of the event from the start of the block, in samples.
+    pub frame: usize,
+
+    /// The message itself.
+    pub message: MidiMessage,
+}
+
+/// How the values of a MIDI controller, from 0 to 127, are spread over the range of the control
+/// input it is mapped to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Curve {
+    /// In even steps.
+    Linear,
+    /// In even steps on a logarithmic scale, as suits frequencies and times. Falls back to
+    /// ```Linear``` if the range includes 0 or negative values.
+    Logarithmic,
+}
+
+/// A MIDI controller driving a control input, made with ```cc```. See the module documentation.
+///
+/// The controller's values are mapped like normalized values by ```Port::range```: over the
+/// bounds of the port, following its hints unless a ```curve``` is given, and rounded for
+/// integer and toggled ports.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CcMapping {
+    /// The number of the controller, from 0 to 127.
+    pub controller: u8,
+    /// The MIDI channel to listen to, from 0 to 15, or ```None``` for all of them.
+    pub channel: Option<u8>,
+    /// The name of the control input driven by the controller.
+    pub port: &'static str,
+    /// How the controller's values are spread over the range, or ```None``` to follow the hints
+    /// of the port.
+    pub curve: Option<Curve>,
+    /// The values the lowest and highest controller values map to, or ```None``` for the bounds
+    /// of the port.
+    pub range: Option<(Data, Data)>,
+}
+
+/// Starts a mapping of controller number ```controller```, on all channels, to be directed to a
+/// port with ```CcMapping::to```.
+pub const fn cc(controller: u8) -> CcMapping {
+    CcMapping {
+        controller,
+        channel: None,
+        port: "",
+        curve: None,
+        range: None,
+    }
+}
+
+impl CcMapping {
+    /// Directs the controller to the control input named ```port```.
+    pub const fn to(self, port: &'static str) -> CcMapping {
+        CcMapping { port, ..self }
+    }
+
+    /// Only listens to the controller on ```channel```, from 0 to 15.
+    pub const fn channel(self, channel: u8) -> CcMapping {
+        CcMapping { channel: Some(channel), ..self }
+    }
+
+    /// Spreads the controller's values along ```curve``` rather than as the hints of the port
+    /// say.
+    pub const fn curve(self, curve: Curve) -> CcMapping {
+        CcMapping { curve: Some(curve), ..self }
+    }
+
+    /// Maps the controller's values over ```range``` rather than the bounds of the port.
+    pub const fn range(self, range: Range<Data>) -> CcMapping {
+        CcMapping { range: Some((range.start, range.end)), ..self }
+    }
+
+    /// Whether the message is a change of this controller.
+    pub fn matches(&self, message: &MidiMessage) -> bool {
+        match *message {
+            MidiMessage::ControlChange { channel, controller, .. } => {
+                controller == self.controller && self.channel.is_none_or(|own| own == channel)
+            }
+            _ => false,
+        }
+    }
+}
+
+// The descriptors built so far. `None` until the library is first queried, and again once it is
+// unloaded.
+static DESCRIPTORS: Lock<Option<Vec<Option<Box<Exported>>>>> = Lock::new(None);
+
+// A descriptor handed to hosts. Boxed so that the pointer given out stays valid as the table grows.
+struct Exported(dssi_h::Descriptor);
+
+// Hosts may query descriptors from any thread, and only read them.
+unsafe impl Send for Exported {}
+
+#[doc(hidden)]
+#[no_mangle]
+// Exported so the plugin is recognised by DSSI hosts.
+pub extern "C" fn dssi_descriptor(index: c_ulong) -> *const c_void {
+    unsafe {
+        let index = index as usize;
+        if let Some(Some(exported)) =
+            DESCRIPTORS.lock().as_ref().and_then(|table| table.get(index)) {
+            return &exported.0 as *const dssi_h::Descriptor as *const c_void;
+        }
+
+        // The LADSPA descriptor is cached (and eventually freed) by the ffi module. Building it
+        // runs the plugin's code, so the table isn't locked meanwhile.
+        let ladspa = ffi::ladspa_descriptor(index as c_ulong);
+        if ladspa.is_null() {
+            return ptr::null();
+        }
+
+        let mut descriptors = DESCRIPTORS.lock();
+        let descriptors = &mut descriptors.get_or_insert_with(|| {
+            at_unload(global_destruct);
+            Vec::new()
+        });
+        if descriptors.len() <= index {
+            descriptors.resize_with(index + 1, || None);
+        }
+        let exported = descriptors[index].get_or_insert_with(|| {
+            Box::new(Exported(dssi_h::Descriptor {
+                api_version: 1,
+                ladspa_plugin: ladspa,
+                configure: None,
+                get_program: None,
+                select_program: None,
+                get_midi_controller_for_port: None,
+                run_synth: Some(ffi::run_synth),
+                run_synth_adding: None,
+                run_multiple_synths: None,
+                run_multiple_synths_adding: None,
+            }))
+        });
+        &exported.0 as *const dssi_h::Descriptor as *const c_void
+    }
+}
+
+extern "C" fn global_destruct() {
+    drop(DESCRIPTORS.lock().take());
+}
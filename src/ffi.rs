@@ -1,18 +1,35 @@
-use std::{mem, ptr};
-use libc::{self, c_char, c_ulong};
-use std::slice;
-use std::cell::RefCell;
-use vec_map::VecMap;
-use std::ffi::CString;
-use std::panic::{catch_unwind, AssertUnwindSafe};
-
-use super::PluginDescriptor;
+// c_ulong is only 32 bits wide on some platforms, so widening it is not always a no-op.
+#![allow(clippy::useless_conversion)]
+
+use core::mem;
+use core::ptr;
+use libc::{c_char, c_int, c_ulong, c_void};
+use core::slice;
+use core::panic::AssertUnwindSafe;
+#[cfg(feature = "std")]
+use std::panic::catch_unwind;
+#[cfg(feature = "std")]
+use std::sync::Once;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::ffi::CString;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::{Context, PluginDescriptor, PortConnection, PortData, PortDescriptor};
+use super::dsp::simd;
+use super::util::DenormalGuard;
 use super::get_ladspa_descriptor;
+use super::lock::{at_unload, Lock};
+#[cfg(feature = "std")]
+use super::unique_id;
 
+// Evaluates to `Some` of the result of `$code`, or `None` if it panicked.
+#[cfg(feature = "std")]
 macro_rules! call_user_code {
     ($code:expr, $name:expr) => {
         match catch_unwind(move || $code) {
-            Ok(x) => x,
+            Ok(x) => Some(x),
             Err(_) => {
                 println!("ladspa.rs: panic in {} suppressed.", $name);
                 None
@@ -21,6 +38,22 @@ macro_rules! call_user_code {
     }
 }
 
+// Without std, panics can't be caught: they abort, so `$code` always evaluates to `Some`.
+#[cfg(not(feature = "std"))]
+macro_rules! call_user_code {
+    ($code:expr, $name:expr) => {
+        {
+            let _ = $name;
+            Some(call(move || $code))
+        }
+    }
+}
+
+#[cfg(not(feature = "std"))]
+fn call<R, F: FnOnce() -> R>(f: F) -> R {
+    f()
+}
+
 // essentially ladspa.h API translated to rust.
 pub mod ladspa_h {
     use libc::{c_void, c_char, c_int, c_ulong, c_float};
@@ -79,6 +112,7 @@ pub mod ladspa_h {
     pub const HINT_SAMPLE_RATE: PortRangeHintDescriptor = 0x8;
     pub const HINT_LOGARITHMIC: PortRangeHintDescriptor = 0x10;
     pub const HINT_INTEGER: PortRangeHintDescriptor = 0x20;
+    pub const HINT_DEFAULT_MASK: PortRangeHintDescriptor = 0x3C0;
     pub const HINT_DEFAULT_MINIMUM: PortRangeHintDescriptor = 0x40;
     pub const HINT_DEFAULT_LOW: PortRangeHintDescriptor = 0x80;
     pub const HINT_DEFAULT_MIDDLE: PortRangeHintDescriptor = 0xC0;
@@ -90,232 +124,1120 @@ pub mod ladspa_h {
     pub const HINT_DEFAULT_440: PortRangeHintDescriptor = 0x2C0;
 }
 
-static mut DESCRIPTORS: *mut Vec<*mut ladspa_h::Descriptor> =
-    0 as *mut Vec<*mut ladspa_h::Descriptor>;
+// The parts of dssi.h, and of the ALSA sequencer event it uses, needed to export a plugin.
+#[cfg(feature = "dssi")]
+pub mod dssi_h {
+    use libc::{c_char, c_int, c_ulong};
+    use super::ladspa_h;
+
+    pub const EVENT_NOTEON: u8 = 6;
+    pub const EVENT_NOTEOFF: u8 = 7;
+    pub const EVENT_KEYPRESS: u8 = 8;
+    pub const EVENT_CONTROLLER: u8 = 10;
+    pub const EVENT_PGMCHANGE: u8 = 11;
+    pub const EVENT_CHANPRESS: u8 = 12;
+    pub const EVENT_PITCHBEND: u8 = 13;
+
+    // snd_seq_event_t. DSSI hosts store the event's offset into the block in `time_tick`.
+    #[repr(C)]
+    #[derive(Copy, Clone)]
+    pub struct SeqEvent {
+        pub kind: u8,
+        pub flags: u8,
+        pub tag: u8,
+        pub queue: u8,
+        pub time_tick: u32,
+        pub time_nsec: u32,
+        pub source: [u8; 2],
+        pub dest: [u8; 2],
+        // A union of the per-type event data. Notes are laid out as channel, note, velocity,
+        // off velocity and duration, controls as channel, 3 padding bytes, param and value.
+        pub data: [u8; 12],
+    }
+
+    #[repr(C)]
+    pub struct ProgramDescriptor {
+        pub bank: c_ulong,
+        pub program: c_ulong,
+        pub name: *const c_char,
+    }
+
+    pub type RunSynth = unsafe extern "C" fn(instance: ladspa_h::Handle,
+                                             sample_count: c_ulong,
+                                             events: *mut SeqEvent,
+                                             event_count: c_ulong);
+
+    pub type RunMultipleSynths = extern "C" fn(instance_count: c_ulong,
+                                               instances: *mut ladspa_h::Handle,
+                                               sample_count: c_ulong,
+                                               events: *mut *mut SeqEvent,
+                                               event_counts: *mut c_ulong);
+
+    #[repr(C)]
+    pub struct Descriptor {
+        pub api_version: c_int,
+        pub ladspa_plugin: *const ladspa_h::Descriptor,
+        pub configure: Option<extern "C" fn(instance: ladspa_h::Handle,
+                                            key: *const c_char,
+                                            value: *const c_char)
+                                            -> *mut c_char>,
+        pub get_program: Option<extern "C" fn(instance: ladspa_h::Handle, index: c_ulong)
+                                              -> *const ProgramDescriptor>,
+        pub select_program: Option<extern "C" fn(instance: ladspa_h::Handle,
+                                                 bank: c_ulong,
+                                                 program: c_ulong)>,
+        pub get_midi_controller_for_port: Option<extern "C" fn(instance: ladspa_h::Handle,
+                                                               port: c_ulong)
+                                                               -> c_int>,
+        pub run_synth: Option<RunSynth>,
+        pub run_synth_adding: Option<RunSynth>,
+        pub run_multiple_synths: Option<RunMultipleSynths>,
+        pub run_multiple_synths_adding: Option<RunMultipleSynths>,
+    }
+}
+
+// The number of samples `run_adding` processes at once for plugins without a native
+// implementation.
+const SCRATCH_SIZE: usize = 1024;
+
+// Everything handed to hosts. `None` until the library is first queried, and again once it is
+// unloaded.
+static DESCRIPTORS: Lock<Option<Exports>> = Lock::new(None);
 
-// It seems that ladspa_descriptor is deleted during link time optimization unless we
-// call it from somewhere.
+// Set once the unique IDs of the library's plugins have been checked, on the first query.
+#[cfg(feature = "std")]
+static UNIQUE_IDS_CHECKED: Once = Once::new();
+
+struct Exports {
+    // The descriptors built so far, indexed by plugin.
+    descriptors: Vec<Option<Box<Exported>>>,
+    // The strings the descriptors point to. Plugin packs repeat the same port names, maker and
+    // copyright across many descriptors, which then share a single copy of each.
+    strings: BTreeMap<&'static str, CString>,
+}
+
+/// The entry points hosts look up in a plugin library, for ```export_ladspa!``` to reference.
+/// They live in this crate rather than the plugin's, so link time optimization would otherwise
+/// discard them as unused.
+#[doc(hidden)]
 #[allow(dead_code)]
-unsafe fn _lto_workaround() {
-    ladspa_descriptor(0);
+pub struct EntryPoints {
+    ladspa: [*const c_void; 3],
+    #[cfg(feature = "dssi")]
+    dssi: *const c_void,
+    #[cfg(feature = "lv2")]
+    lv2: *const c_void,
+    #[cfg(all(unix, feature = "gstreamer"))]
+    gstreamer: *const c_void,
 }
 
+unsafe impl Sync for EntryPoints {}
+
+#[doc(hidden)]
+pub static ENTRY_POINTS: EntryPoints = EntryPoints {
+    ladspa: [ladspa_descriptor as *const c_void,
+             ladspa_rs_save_state as *const c_void,
+             ladspa_rs_restore_state as *const c_void],
+    #[cfg(feature = "dssi")]
+    dssi: ::dssi::dssi_descriptor as *const c_void,
+    #[cfg(feature = "lv2")]
+    lv2: ::lv2::lv2_descriptor as *const c_void,
+    #[cfg(all(unix, feature = "gstreamer"))]
+    gstreamer: &::gstreamer::gst_plugin_desc as *const _ as *const c_void,
+};
+
 #[no_mangle]
 // Exported so the plugin is recognised by ladspa hosts.
 pub unsafe extern "C" fn ladspa_descriptor(index: c_ulong) -> *mut ladspa_h::Descriptor {
-    if DESCRIPTORS == ptr::null_mut() {
-        libc::atexit(global_destruct);
-        DESCRIPTORS = mem::transmute(Box::new(Vec::<*mut ladspa_h::Descriptor>::new()));
-    }
+    #[cfg(feature = "std")]
+    UNIQUE_IDS_CHECKED.call_once(check_unique_ids);
 
     // If it's already been generated, return the cached copy.
-    if (index as usize) < (*DESCRIPTORS).len() {
-        return mem::transmute(&*(*DESCRIPTORS)[index as usize]);
+    let index = index as usize;
+    if let Some(Some(exported)) =
+        DESCRIPTORS.lock().as_ref().and_then(|exports| exports.descriptors.get(index)) {
+        return exported.raw();
+    }
+
+    // The plugin's code runs without the table locked, so that it can't deadlock the library
+    // however it behaves. Hosts querying the library from several threads at once may then
+    // build the same descriptor twice, in which case the first one stored is kept.
+    let plugin = match call_user_code!(get_ladspa_descriptor(index as u64),
+                                       "get_ladspa_descriptor") {
+        Some(Some(plugin)) => plugin,
+        _ => return ptr::null_mut(),
+    };
+
+    let mut exports = DESCRIPTORS.lock();
+    let Exports { ref mut descriptors, ref mut strings } = *exports.get_or_insert_with(|| {
+        at_unload(global_destruct);
+        Exports { descriptors: Vec::new(), strings: BTreeMap::new() }
+    });
+    if let Some(Some(exported)) = descriptors.get(index) {
+        return exported.raw();
     }
 
-    let descriptor = call_user_code!(get_ladspa_descriptor(index as u64), "get_ladspa_descriptor");
+    // Converting the descriptor panics if any of its strings contain a nul byte.
+    let mut strings = AssertUnwindSafe(&mut *strings);
+    match call_user_code!(Exported::new(plugin, &mut strings), "ladspa_descriptor") {
+        Some(exported) => {
+            // store in global descriptor table. Hosts may ask for descriptors in any order, so
+            // the table is indexed directly and grown as needed.
+            if descriptors.len() <= index {
+                descriptors.resize_with(index + 1, || None);
+            }
+            descriptors[index].insert(exported).raw()
+        }
+        None => ptr::null_mut(),
+    }
+}
 
-    match descriptor {
-        Some(plugin) => {
-            let desc = mem::transmute(Box::new(ladspa_h::Descriptor {
+// A descriptor handed to hosts, along with everything it points to. Boxed so that the pointer
+// given out stays valid as the table grows, until the library is unloaded and the box dropped.
+struct Exported {
+    raw: ladspa_h::Descriptor,
+    // Only borrowed by `raw`, which also points to strings owned by `Exports`. Moving them
+    // doesn't move the memory they own.
+    _port_descriptors: Vec<ladspa_h::PortDescriptor>,
+    _port_names: Vec<*mut c_char>,
+    _port_range_hints: Vec<ladspa_h::PortRangeHint>,
+    _plugin: Box<PluginDescriptor>,
+}
+
+// Hosts may query descriptors from any thread, and only read them.
+unsafe impl Send for Exported {}
+
+impl Exported {
+    fn new(plugin: PluginDescriptor,
+           strings: &mut BTreeMap<&'static str, CString>)
+           -> Box<Exported> {
+        // Hosts don't explain why they reject a plugin or misbehave with it, so mistakes in the
+        // descriptor are reported here while developing, and the plugin left out.
+        #[cfg(debug_assertions)]
+        if let Err(errors) = plugin.validate() {
+            let errors: Vec<_> = errors.iter().map(::alloc::string::ToString::to_string).collect();
+            panic!("plugin \"{}\" has an invalid descriptor: {}", plugin.label, errors.join("; "));
+        }
+        let plugin = Box::new(plugin);
+        let mut string = |s: &'static str| {
+            strings.entry(s).or_insert_with(|| CString::new(s).unwrap()).as_ptr() as *mut c_char
+        };
+        let mut port_descriptors: Vec<ladspa_h::PortDescriptor> =
+            plugin.ports.iter().map(|port| port.desc as i32).collect();
+        let mut port_names: Vec<*mut c_char> =
+            plugin.ports.iter().map(|port| string(port.name)).collect();
+        let mut port_range_hints: Vec<ladspa_h::PortRangeHint> = plugin.ports
+            .iter()
+            .map(|port| ladspa_h::PortRangeHint {
+                hint_descriptor: port.hint.map(|x| x.bits()).unwrap_or(0) |
+                                 port.default_hint() |
+                                 port.lower_bound.map(|_| ladspa_h::HINT_BOUNDED_BELOW)
+                                     .unwrap_or(0) |
+                                 port.upper_bound.map(|_| ladspa_h::HINT_BOUNDED_ABOVE)
+                                     .unwrap_or(0),
+                lower_bound: port.lower_bound.unwrap_or(0_f32),
+                upper_bound: port.upper_bound.unwrap_or(0_f32),
+            })
+            .collect();
+        Box::new(Exported {
+            raw: ladspa_h::Descriptor {
                 unique_id: plugin.unique_id as c_ulong,
-                label: CString::new(plugin.label).unwrap().into_raw(),
+                label: string(plugin.label),
                 properties: plugin.properties.bits(),
-                name: CString::new(plugin.name).unwrap().into_raw(),
-                maker: CString::new(plugin.maker).unwrap().into_raw(),
-                copyright: CString::new(plugin.copyright).unwrap().into_raw(),
-
+                name: string(plugin.name),
+                maker: string(plugin.maker),
+                copyright: string(plugin.copyright),
                 port_count: plugin.ports.len() as c_ulong,
-                port_descriptors: mem::transmute::<_, &mut [i32]>(
-                    plugin.ports.iter().map(|port|
-                                            port.desc as i32
-                                           ).collect::<Vec<_>>().into_boxed_slice()).as_mut_ptr(),
-                port_names: mem::transmute::<_, &mut [*mut c_char]>(
-                    plugin.ports.iter().map(|port|
-                                            CString::new(port.name).unwrap().into_raw()
-                                           ).collect::<Vec<_>>().into_boxed_slice()).as_mut_ptr(),
-                port_range_hints: mem::transmute::<_, &mut [ladspa_h::PortRangeHint]>(
-                    plugin.ports.iter().map(|port|
-                                            ladspa_h::PortRangeHint {
-                                                hint_descriptor: port.hint.map(|x| x.bits()).unwrap_or(0) |
-                                                    port.default.map(|x| x as i32).unwrap_or(0) |
-                                                    port.lower_bound.map(|_| ladspa_h::HINT_BOUNDED_BELOW)
-                                                    .unwrap_or(0) |
-                                                    port.upper_bound.map(|_| ladspa_h::HINT_BOUNDED_ABOVE)
-                                                    .unwrap_or(0),
-                                                 lower_bound: port.lower_bound.unwrap_or(0_f32),
-                                                 upper_bound: port.upper_bound.unwrap_or(0_f32),
-                                            }
-                                         ).collect::<Vec<_>>().into_boxed_slice()).as_mut_ptr(),
-                implementation_data: mem::transmute(Box::new(plugin)),
-                instantiate: instantiate,
-                connect_port: connect_port,
-                run: run,
-                cleanup: cleanup,
-                run_adding: None,
-                set_run_adding_gain: None,
+                port_descriptors: port_descriptors.as_mut_ptr(),
+                port_names: port_names.as_mut_ptr(),
+                port_range_hints: port_range_hints.as_mut_ptr(),
+                implementation_data: &*plugin as *const PluginDescriptor as *mut c_void,
+                instantiate,
+                connect_port,
+                run,
+                cleanup,
+                run_adding: Some(run_adding),
+                set_run_adding_gain: Some(set_run_adding_gain),
                 activate: Some(activate),
                 deactivate: Some(deactivate),
-            }));
+            },
+            _port_descriptors: port_descriptors,
+            _port_names: port_names,
+            _port_range_hints: port_range_hints,
+            _plugin: plugin,
+        })
+    }
 
-            // store in global descriptor table
-            (*DESCRIPTORS).push(desc);
-            desc
-        }
-        None => ptr::null_mut(),
+    // The descriptor as given to hosts, which must not modify it.
+    fn raw(&self) -> *mut ladspa_h::Descriptor {
+        &self.raw as *const ladspa_h::Descriptor as *mut ladspa_h::Descriptor
     }
 }
 
-extern "C" fn global_destruct() {
-    unsafe {
-        let descs: Box<Vec<*mut ladspa_h::Descriptor>> = mem::transmute(DESCRIPTORS);
-        for desc in descs.iter() {
-            drop_descriptor(mem::transmute(*desc));
-        }
+// Exports `plugin` like `ladspa_descriptor` does, for the tests of the host module to load
+// without a library. Never freed.
+#[cfg(all(test, feature = "std"))]
+pub fn export(plugin: PluginDescriptor) -> *const ladspa_h::Descriptor {
+    let strings = Box::leak(Box::new(BTreeMap::new()));
+    Box::leak(Exported::new(plugin, strings)).raw()
+}
+
+// Reports clashing and experimental unique IDs when the library is first queried.
+#[cfg(feature = "std")]
+fn check_unique_ids() {
+    let descriptors: Vec<_> = match call_user_code!(
+        (0..).map_while(|index| unsafe { get_ladspa_descriptor(index) }).collect(),
+        "get_ladspa_descriptor") {
+        Some(descriptors) => descriptors,
+        None => return,
+    };
+    if let Err(error) = unique_id::check(&descriptors) {
+        println!("ladspa.rs: {}.", error);
+    }
+    for descriptor in descriptors.iter().filter(|desc| unique_id::is_experimental(desc.unique_id)) {
+        println!("ladspa.rs: plugin \"{}\" has unique ID {}, which is reserved for development.",
+                 descriptor.label, descriptor.unique_id);
     }
 }
 
-unsafe fn drop_descriptor(desc: &mut ladspa_h::Descriptor) {
-    CString::from_raw(desc.label);
-    CString::from_raw(desc.name);
-    CString::from_raw(desc.maker);
-    CString::from_raw(desc.copyright);
-    Vec::from_raw_parts(desc.port_descriptors,
-                        desc.port_count as usize,
-                        desc.port_count as usize);
-    Vec::from_raw_parts(desc.port_names,
-                        desc.port_count as usize,
-                        desc.port_count as usize)
-        .iter()
-        .map(|&x| CString::from_raw(x))
-        .collect::<Vec<_>>();
-    Vec::from_raw_parts(desc.port_range_hints,
-                        desc.port_count as usize,
-                        desc.port_count as usize);
-    mem::transmute::<_, Box<PluginDescriptor>>(desc.implementation_data);
+extern "C" fn global_destruct() {
+    // Dropping the table frees the descriptors along with everything they point to.
+    drop(DESCRIPTORS.lock().take());
 }
 
 // The handle that is given to ladspa.
-struct Handle<'a> {
+struct Handle {
     descriptor: &'static super::PluginDescriptor,
-    plugin: Box<super::Plugin + Send + 'static>,
-    port_map: VecMap<super::PortConnection<'a>>,
-    ports: Vec<&'a super::PortConnection<'a>>,
+    sample_rate: u64,
+    plugin: Box<dyn super::Plugin + Send + 'static>,
+    // Where the host connected each port, indexed by port number. Null until it is connected.
+    locations: Vec<*mut ladspa_h::Data>,
+    // The connections passed to the plugin, rebuilt from `locations` for every block or chunk
+    // without reallocating. They only hold anything while the plugin runs.
+    connections: Vec<PortConnection<'static>>,
+    // Indexed by port number: whether the port shares its location with an output for the
+    // current block, and if so the buffer the plugin sees in its place, holding a copy of an
+    // input or collecting an output copied back after the plugin runs. Blocks with such ports are
+    // run in chunks of at most `SCRATCH_SIZE` samples, which these have room for.
+    aliased: Vec<bool>,
+    detached: Vec<Vec<ladspa_h::Data>>,
+    run_adding_gain: ladspa_h::Data,
+    // Indexed by port number, with `SCRATCH_SIZE` samples for each audio output and nothing for
+    // the other ports. `run_adding` runs plugins without a native implementation into these.
+    scratch: Vec<Vec<ladspa_h::Data>>,
+    // The decoded events of the current block, reused across blocks.
+    #[cfg(feature = "dssi")]
+    midi_events: Vec<super::dssi::MidiEvent>,
+    // The events of `midi_events` falling in the current chunk, relative to its start.
+    #[cfg(feature = "dssi")]
+    chunk_events: Vec<super::dssi::MidiEvent>,
+    // Set once user code has panicked. A poisoned instance never calls into the plugin again and
+    // only outputs silence.
+    poisoned: bool,
+    // Whether `Plugin::activate` was called more recently than `Plugin::deactivate`. Calls the
+    // host makes out of order are fixed up so the plugin always sees activate, any number of
+    // runs, then deactivate.
+    active: bool,
+}
+
+// Recovers the handle created by `instantiate`.
+unsafe fn handle_mut<'a>(instance: ladspa_h::Handle) -> &'a mut Handle {
+    &mut *(instance as *mut Handle)
 }
 
 extern "C" fn instantiate(descriptor: *const ladspa_h::Descriptor,
                           sample_rate: c_ulong)
                           -> ladspa_h::Handle {
     unsafe {
-        let desc: &mut ladspa_h::Descriptor = mem::transmute(descriptor);
-
-        let rust_desc: &super::PluginDescriptor = mem::transmute(desc.implementation_data);
-        let rust_plugin = match call_user_code!(Some((rust_desc.new)(rust_desc, sample_rate as u64)),
-                                                "PluginDescriptor::run") {
-            Some(plug) => plug,
+        let rust_desc = &*((*descriptor).implementation_data as *const PluginDescriptor);
+        let sample_rate = u64::from(sample_rate);
+        let mut rust_plugin = match call_user_code!((rust_desc.new)(rust_desc, sample_rate),
+                                                    "PluginDescriptor::new") {
+            Some(Ok(plug)) => plug,
+            Some(Err(_err)) => {
+                #[cfg(feature = "std")]
+                println!("ladspa.rs: failed to instantiate {}: {}", rust_desc.label, _err);
+                return ptr::null_mut();
+            }
             None => return ptr::null_mut(),
         };
-        let port_map: VecMap<super::PortConnection> = VecMap::new();
-        let ports: Vec<&super::PortConnection> = Vec::new();
+        // LADSPA hosts don't say how large their blocks are.
+        let mut user = AssertUnwindSafe(&mut rust_plugin);
+        if call_user_code!(user.initialize(sample_rate, None), "Plugin::initialize").is_none() {
+            return ptr::null_mut();
+        }
+        let port_count = rust_desc.ports.len();
+        let scratch = rust_desc.ports
+            .iter()
+            .map(|port| match port.desc {
+                super::PortDescriptor::AudioOutput => vec![0.0; SCRATCH_SIZE],
+                _ => Vec::new(),
+            })
+            .collect();
+        let detached = rust_desc.ports
+            .iter()
+            .map(|port| match port.desc {
+                super::PortDescriptor::AudioInput | super::PortDescriptor::AudioOutput => {
+                    Vec::with_capacity(SCRATCH_SIZE)
+                }
+                _ => Vec::with_capacity(1),
+            })
+            .collect();
 
-        mem::transmute(Box::new(Handle {
+        Box::into_raw(Box::new(Handle {
             descriptor: rust_desc,
+            sample_rate,
             plugin: rust_plugin,
-            port_map: port_map,
-            ports: ports,
-        }))
+            locations: vec![ptr::null_mut(); port_count],
+            connections: Vec::with_capacity(port_count),
+            aliased: vec![false; port_count],
+            detached,
+            run_adding_gain: 1.0,
+            scratch,
+            #[cfg(feature = "dssi")]
+            midi_events: Vec::with_capacity(super::dssi::MAX_BLOCK_EVENTS),
+            #[cfg(feature = "dssi")]
+            chunk_events: Vec::with_capacity(super::dssi::MAX_BLOCK_EVENTS),
+            poisoned: false,
+            active: false,
+        })) as ladspa_h::Handle
     }
 }
 
 extern "C" fn connect_port(instance: ladspa_h::Handle,
                            port_num: c_ulong,
                            data_location: *mut ladspa_h::Data) {
-    unsafe {
-        let handle: &mut Handle = mem::transmute(instance);
+    // Panics on out of range port numbers or invalid port descriptors.
+    if call_user_code!(unsafe { connect_port_unchecked(instance, port_num, data_location) },
+                       "connect_port").is_none() {
+        unsafe {
+            handle_mut(instance).poisoned = true;
+        }
+    }
+}
 
-        let port = handle.descriptor.ports[port_num as usize];
+unsafe fn connect_port_unchecked(instance: ladspa_h::Handle,
+                                 port_num: c_ulong,
+                                 data_location: *mut ladspa_h::Data) {
+    let handle = handle_mut(instance);
+    if let PortDescriptor::Invalid = handle.descriptor.ports[port_num as usize].desc {
+        panic!("Invalid port descriptor!");
+    }
+    handle.locations[port_num as usize] = data_location;
+}
+
+// Whether `port` is written by the plugin.
+fn is_output(port: &super::Port) -> bool {
+    matches!(port.desc, PortDescriptor::AudioOutput | PortDescriptor::ControlOutput)
+}
+
+// The values of the host's buffers port `n` covers over `len` samples from `offset`, as a start
+// and a length, or `None` if the plugin doesn't see them because audio outputs are connected to
+// the scratch buffers.
+unsafe fn host_range(handle: &Handle,
+                     n: usize,
+                     offset: usize,
+                     len: usize,
+                     scratch: bool)
+                     -> Option<(usize, usize)> {
+    let location = handle.locations[n];
+    let size = mem::size_of::<ladspa_h::Data>();
+    match handle.descriptor.ports[n].desc {
+        PortDescriptor::AudioOutput if scratch => None,
+        PortDescriptor::AudioInput | PortDescriptor::AudioOutput => {
+            Some((location.add(offset) as usize, len * size))
+        }
+        _ => Some((location as usize, size)),
+    }
+}
 
-        // Create appropriate pointers to port data. Mutable locations are wrapped in refcells.
+// Connects the plugin's ports for a run over `len` samples from `offset` into the host's block,
+// with the audio outputs connected to `handle.scratch` instead if `scratch` is set. Returns
+// whether every port is connected; the plugin is given no ports otherwise, and mustn't be run.
+//
+// This is where the plugin's outputs are made exclusive. An input the host connected to the same
+// buffer as an output, as LADSPA allows for audio ports, is copied to a buffer of its own before
+// the plugin runs, and an output sharing its buffer with another output is written to its own
+// buffer and copied back by `disconnect`, so that no two ports the plugin sees overlap.
+unsafe fn connect(handle: &mut Handle, offset: usize, len: usize, scratch: bool) -> bool {
+    handle.connections.clear();
+    if !connected(handle) {
+        return false;
+    }
+    find_aliased(handle, offset, len, scratch);
+    let ports = &handle.descriptor.ports;
+    for (n, port) in ports.iter().enumerate() {
+        let location = handle.locations[n];
+        let detached = &mut handle.detached[n];
+        if handle.aliased[n] {
+            let host = match port.desc {
+                PortDescriptor::AudioInput => slice::from_raw_parts(location.add(offset), len),
+                PortDescriptor::ControlInput => slice::from_raw_parts(location, 1),
+                _ => &[],
+            };
+            detached.clear();
+            detached.extend_from_slice(host);
+            let size = if let PortDescriptor::AudioOutput = port.desc { len } else { 1 };
+            detached.resize(size, 0.0);
+        }
         let data = match port.desc {
-            super::PortDescriptor::AudioInput => {
-                // Initially create a size 0 slice because we don't know how big it will be yet.
-                super::PortData::AudioInput(slice::from_raw_parts(data_location, 0))
+            PortDescriptor::AudioInput if handle.aliased[n] => {
+                PortData::AudioInput(slice::from_raw_parts(detached.as_ptr(), len))
+            }
+            PortDescriptor::AudioInput => {
+                PortData::AudioInput(slice::from_raw_parts(location.add(offset), len))
             }
-            super::PortDescriptor::AudioOutput => {
-                // Same here.
-                super::PortData::AudioOutput(RefCell::new(slice::from_raw_parts_mut(data_location,
-                                                                                    0)))
+            PortDescriptor::AudioOutput if scratch => {
+                let scratch = handle.scratch[n].as_mut_ptr();
+                PortData::AudioOutput(slice::from_raw_parts_mut(scratch, len))
             }
-            super::PortDescriptor::ControlInput => {
-                super::PortData::ControlInput(mem::transmute(data_location))
+            PortDescriptor::AudioOutput if handle.aliased[n] => {
+                PortData::AudioOutput(slice::from_raw_parts_mut(detached.as_mut_ptr(), len))
             }
-            super::PortDescriptor::ControlOutput => {
-                super::PortData::ControlOutput(RefCell::new(mem::transmute(data_location)))
+            PortDescriptor::AudioOutput => {
+                PortData::AudioOutput(slice::from_raw_parts_mut(location.add(offset), len))
             }
-            super::PortDescriptor::Invalid => panic!("Invalid port descriptor!"),
+            PortDescriptor::ControlInput if handle.aliased[n] => {
+                PortData::ControlInput(&*detached.as_ptr())
+            }
+            PortDescriptor::ControlInput => PortData::ControlInput(&*location),
+            _ if handle.aliased[n] => PortData::ControlOutput(&mut *detached.as_mut_ptr()),
+            _ => PortData::ControlOutput(&mut *location),
         };
+        handle.connections.push(PortConnection { port: *port, data });
+    }
+    true
+}
 
-        let conn = super::PortConnection {
-            port: port,
-            data: data,
-        };
-        handle.port_map.insert(port_num as usize, conn);
+// Whether the host connected every port.
+fn connected(handle: &Handle) -> bool {
+    handle.locations.iter().all(|location| !location.is_null())
+}
+
+// Sets `handle.aliased` for a run over `len` samples from `offset`, as described on `connect`.
+// Returns whether any port is aliased. Every port must be connected.
+unsafe fn find_aliased(handle: &mut Handle, offset: usize, len: usize, scratch: bool) -> bool {
+    let ports = &handle.descriptor.ports;
+    for n in 0..ports.len() {
+        let range = host_range(handle, n, offset, len, scratch);
+        handle.aliased[n] = (0..ports.len()).any(|other| {
+            other != n && is_output(&ports[other]) && (!is_output(&ports[n]) || other < n) &&
+            match (range, host_range(handle, other, offset, len, scratch)) {
+                (Some((start, size)), Some((other_start, other_size))) => {
+                    start < other_start + other_size && other_start < start + size
+                }
+                _ => false,
+            }
+        });
+    }
+    handle.aliased.contains(&true)
+}
+
+// The number of samples `run`, `run_synth` and native `run_adding` run the plugin over at once:
+// the whole block, unless some of its ports are aliased, in which case it is run in chunks of at
+// most `SCRATCH_SIZE` samples so that the detached buffers never grow on the audio thread.
+unsafe fn chunk_size(handle: &mut Handle, sample_count: usize) -> usize {
+    if connected(handle) && find_aliased(handle, 0, sample_count, false) {
+        SCRATCH_SIZE
+    } else {
+        sample_count
+    }
+}
+
+// Calls `run` with the offset and length of each chunk of the block, once even for empty blocks,
+// with the plugin's ports connected for that chunk. Returns whether every port is connected; the
+// plugin is not run otherwise.
+unsafe fn run_chunks<F>(handle: &mut Handle, sample_count: usize, mut run: F) -> bool
+    where F: FnMut(&mut Handle, usize, usize)
+{
+    let chunk_size = chunk_size(handle, sample_count);
+    let mut offset = 0;
+    loop {
+        let len = (sample_count - offset).min(chunk_size);
+        if !connect(handle, offset, len, false) {
+            return false;
+        }
+        run(handle, offset, len);
+        disconnect(handle, offset, len);
+        offset += len;
+        if offset == sample_count {
+            return true;
+        }
+    }
+}
+
+// Drops the connections made by `connect` once the plugin has run, copying the outputs which
+// were written to buffers of their own to the host's.
+unsafe fn disconnect(handle: &mut Handle, offset: usize, len: usize) {
+    handle.connections.clear();
+    let ports = &handle.descriptor.ports;
+    for (n, port) in ports.iter().enumerate().filter(|&(n, _)| handle.aliased[n]) {
+        let location = handle.locations[n];
+        match port.desc {
+            PortDescriptor::AudioOutput => {
+                let host = slice::from_raw_parts_mut(location.add(offset), len);
+                host.copy_from_slice(&handle.detached[n][..len]);
+            }
+            PortDescriptor::ControlOutput => *location = handle.detached[n][0],
+            _ => {}
+        }
+    }
+}
+
+// Flushes denormals to zero until the returned guard is dropped, if the plugin asks for it.
+fn denormal_guard(plugin: &dyn super::Plugin) -> Option<DenormalGuard> {
+    if plugin.flush_denormals() {
+        Some(DenormalGuard::new())
+    } else {
+        None
+    }
+}
+
+// Runs `process`, panicking afterwards if it wasn't real-time safe but the plugin claims to be.
+#[cfg(feature = "rt-audit")]
+fn audited<F: FnOnce()>(descriptor: &PluginDescriptor, name: &str, process: F) {
+    if descriptor.properties.contains(super::PROP_HARD_REALTIME_CAPABLE) {
+        let ((), violations, backtrace) = super::rt_audit::audit_traced(process);
+        if !violations.is_empty() {
+            panic!("{} of hard real-time plugin {} made {}, the first of them at:\n{}",
+                   name,
+                   descriptor.label,
+                   violations,
+                   backtrace.map(|backtrace| backtrace.to_string()).unwrap_or_default());
+        }
+    } else {
+        process();
+    }
+}
+
+#[cfg(not(feature = "rt-audit"))]
+fn audited<F: FnOnce()>(_: &PluginDescriptor, _: &str, process: F) {
+    process();
+}
 
-        // Depends on the assumption that ports will be recreated whenever port_map changes
-        let handle_ptr: &mut Handle = mem::transmute(instance);
-        if handle.port_map.len() == handle.descriptor.ports.len() {
-            handle_ptr.ports = handle.port_map.values().collect();
+// Zero the audio outputs, used in place of running a poisoned instance.
+unsafe fn silence_outputs(handle: &mut Handle, sample_count: usize) {
+    for (port, &location) in handle.descriptor.ports.iter().zip(&handle.locations) {
+        if let (PortDescriptor::AudioOutput, false) = (port.desc, location.is_null()) {
+            slice::from_raw_parts_mut(location, sample_count).fill(0.0);
         }
     }
 }
 
+// Reports a call the host made out of order.
+fn lifecycle_warning(_handle: &Handle, _message: &str) {
+    #[cfg(feature = "std")]
+    println!("ladspa.rs: {}: {}", _handle.descriptor.label, _message);
+}
+
+fn activate_plugin(handle: &mut Handle) {
+    if !handle.poisoned {
+        let mut user = AssertUnwindSafe(&mut *handle);
+        handle.poisoned = call_user_code!(user.plugin.activate(), "Plugin::activate").is_none();
+    }
+    handle.active = true;
+}
+
+fn deactivate_plugin(handle: &mut Handle) {
+    if !handle.poisoned {
+        let mut user = AssertUnwindSafe(&mut *handle);
+        handle.poisoned = call_user_code!(user.plugin.deactivate(), "Plugin::deactivate")
+            .is_none();
+    }
+    handle.active = false;
+}
+
+// Activates the plugin if the host is about to run it without having done so.
+fn ensure_active(handle: &mut Handle) {
+    if !handle.active {
+        lifecycle_warning(handle, "run before activate, activating");
+        activate_plugin(handle);
+    }
+}
+
 extern "C" fn run(instance: ladspa_h::Handle, sample_count: c_ulong) {
     unsafe {
-        let handle: &mut Handle = mem::transmute(instance);
-        for (_, port) in handle.port_map.iter_mut() {
-            match port.data {
-                super::PortData::AudioOutput(ref mut data) => {
-                    let ptr = data.borrow_mut().as_mut_ptr();
-                    *data.borrow_mut() = slice::from_raw_parts_mut(ptr, sample_count as usize);
-                }
-                super::PortData::AudioInput(ref mut data) => {
-                    let ptr = data.as_ptr();
-                    *data = slice::from_raw_parts(ptr, sample_count as usize);
-                }
-                _ => {}
+        let handle = handle_mut(instance);
+        let sample_count = sample_count as usize;
+        ensure_active(handle);
+        if !handle.poisoned {
+            if !connected(handle) {
+                silence_outputs(handle, sample_count);
+                return;
+            }
+            let user = AssertUnwindSafe(&mut *handle);
+            handle.poisoned = call_user_code!({
+                                                  let handle = user;
+                                                  let _guard = denormal_guard(&*handle.0.plugin);
+                                                  let context = Context::new(handle.0.descriptor,
+                                                                             handle.0.sample_rate);
+                                                  audited(handle.0.descriptor, "Plugin::run", || {
+                                                      run_chunks(handle.0,
+                                                                 sample_count,
+                                                                 |handle, _, len| {
+                                                          handle.plugin.run_with_context(
+                                                              context,
+                                                              len,
+                                                              &mut handle.connections)
+                                                      });
+                                                  })
+                                              },
+                                              "Plugin::run")
+                .is_none();
+            handle.connections.clear();
+        }
+        if handle.poisoned {
+            silence_outputs(handle, sample_count);
+        }
+    }
+}
+
+extern "C" fn run_adding(instance: ladspa_h::Handle, sample_count: c_ulong) {
+    unsafe {
+        let handle = handle_mut(instance);
+        let sample_count = sample_count as usize;
+        ensure_active(handle);
+        // Adding silence leaves the outputs untouched, so there is nothing to do when poisoned.
+        if !handle.poisoned {
+            let user = AssertUnwindSafe(&mut *handle);
+            handle.poisoned = call_user_code!({
+                                                  let handle = user;
+                                                  let _guard = denormal_guard(&*handle.0.plugin);
+                                                  let gain = handle.0.run_adding_gain;
+                                                  audited(handle.0.descriptor,
+                                                          "Plugin::run_adding",
+                                                          || {
+                                                      let handle = handle.0;
+                                                      if handle.plugin.native_run_adding() {
+                                                          run_adding_natively(handle,
+                                                                              gain,
+                                                                              sample_count)
+                                                      } else {
+                                                          run_into_scratch(handle,
+                                                                           gain,
+                                                                           sample_count)
+                                                      }
+                                                  })
+                                              },
+                                              "Plugin::run_adding")
+                .is_none();
+            handle.connections.clear();
+        }
+    }
+}
+
+// Implements `run_adding` for plugins with a native implementation.
+// Like `run_into_scratch`, it adds nothing to the outputs if the ports aren't all connected.
+unsafe fn run_adding_natively(handle: &mut Handle, gain: ladspa_h::Data, sample_count: usize) {
+    run_chunks(handle, sample_count, |handle, _, len| {
+        handle.plugin.run_adding(gain, len, &mut handle.connections)
+    });
+}
+
+// Implements `run_adding` for plugins without a native implementation: runs the plugin over
+// chunks of the block with its audio outputs connected to the scratch buffers, mixing those into
+// the host's after each chunk. If the ports aren't all connected, the plugin isn't run and adds
+// silence: the outputs are left untouched.
+unsafe fn run_into_scratch(handle: &mut Handle, gain: ladspa_h::Data, sample_count: usize) {
+    let context = Context::new(handle.descriptor, handle.sample_rate);
+    let mut offset = 0;
+    while offset < sample_count {
+        let len = (sample_count - offset).min(SCRATCH_SIZE);
+        if !connect(handle, offset, len, true) {
+            return;
+        }
+        handle.plugin.run_with_context(context, len, &mut handle.connections);
+        disconnect(handle, offset, len);
+        let ports = handle.descriptor.ports.iter().zip(&handle.locations).zip(&handle.scratch);
+        for ((port, &location), scratch) in ports {
+            if let (PortDescriptor::AudioOutput, false) = (port.desc, location.is_null()) {
+                let host = slice::from_raw_parts_mut(location.add(offset), len);
+                simd::mix(host, &scratch[..len], gain);
             }
         }
-        let mut handle = AssertUnwindSafe(handle);
-        call_user_code!(Some({
-                            let ref mut handle = *handle;
-                            handle.plugin.run(sample_count as usize, &handle.ports)
-                        }),
-                        "Plugin::run");
+        offset += len;
+    }
+}
+
+// Converts an ALSA sequencer event to a `MidiEvent`, skipping the types plugins don't need.
+#[cfg(feature = "dssi")]
+fn decode_midi_event(event: &dssi_h::SeqEvent) -> Option<super::dssi::MidiEvent> {
+    use super::dssi::MidiMessage;
+
+    let data = &event.data;
+    let channel = data[0];
+    let value = i32::from_ne_bytes([data[8], data[9], data[10], data[11]]);
+    let message = match event.kind {
+        dssi_h::EVENT_NOTEON if data[2] == 0 => MidiMessage::NoteOff {
+            channel,
+            note: data[1],
+            velocity: 0,
+        },
+        dssi_h::EVENT_NOTEON => MidiMessage::NoteOn {
+            channel,
+            note: data[1],
+            velocity: data[2],
+        },
+        dssi_h::EVENT_NOTEOFF => MidiMessage::NoteOff {
+            channel,
+            note: data[1],
+            velocity: data[2],
+        },
+        dssi_h::EVENT_KEYPRESS => MidiMessage::PolyPressure {
+            channel,
+            note: data[1],
+            pressure: data[2],
+        },
+        dssi_h::EVENT_CONTROLLER => MidiMessage::ControlChange {
+            channel,
+            controller: u32::from_ne_bytes([data[4], data[5], data[6], data[7]]) as u8,
+            value: value as u8,
+        },
+        dssi_h::EVENT_PGMCHANGE => MidiMessage::ProgramChange {
+            channel,
+            program: value as u8,
+        },
+        dssi_h::EVENT_CHANPRESS => MidiMessage::ChannelPressure {
+            channel,
+            pressure: value as u8,
+        },
+        dssi_h::EVENT_PITCHBEND => MidiMessage::PitchBend {
+            channel,
+            value: value as i16,
+        },
+        _ => return None,
+    };
+    Some(super::dssi::MidiEvent {
+        frame: event.time_tick as usize,
+        message,
+    })
+}
+
+// Runs the plugin over `len` samples from `offset` into the block passed to `run_synth`, with the
+// events falling in them.
+#[cfg(feature = "dssi")]
+fn run_synth_chunk(handle: &mut Handle, offset: usize, len: usize) {
+    let Handle { ref mut plugin, ref mut connections, ref midi_events, ref mut chunk_events, .. } =
+        *handle;
+    chunk_events.clear();
+    chunk_events.extend(midi_events.iter()
+        .filter(|event| event.frame >= offset && event.frame < offset + len)
+        .map(|event| super::dssi::MidiEvent { frame: event.frame - offset, ..*event }));
+    plugin.run_synth(len, connections, chunk_events);
+}
+
+// Exposed through the DSSI descriptor.
+#[cfg(feature = "dssi")]
+pub unsafe extern "C" fn run_synth(instance: ladspa_h::Handle,
+                                   sample_count: c_ulong,
+                                   events: *mut dssi_h::SeqEvent,
+                                   event_count: c_ulong) {
+    let handle = handle_mut(instance);
+    let sample_count = sample_count as usize;
+    ensure_active(handle);
+    handle.midi_events.clear();
+    if !events.is_null() {
+        let events = slice::from_raw_parts(events, event_count as usize);
+        let room = super::dssi::MAX_BLOCK_EVENTS - handle.midi_events.len();
+        handle.midi_events.extend(events.iter().filter_map(decode_midi_event).take(room));
+    }
+    if !handle.poisoned {
+        if !connected(handle) {
+            silence_outputs(handle, sample_count);
+            return;
+        }
+        let user = AssertUnwindSafe(&mut *handle);
+        handle.poisoned = call_user_code!({
+                                              let handle = user;
+                                              let _guard = denormal_guard(&*handle.0.plugin);
+                                              audited(handle.0.descriptor, "Plugin::run_synth", || {
+                                                  run_chunks(handle.0,
+                                                             sample_count,
+                                                             run_synth_chunk);
+                                              })
+                                          },
+                                          "Plugin::run_synth")
+            .is_none();
+        handle.connections.clear();
+    }
+    if handle.poisoned {
+        silence_outputs(handle, sample_count);
+    }
+}
+
+extern "C" fn set_run_adding_gain(instance: ladspa_h::Handle, gain: ladspa_h::Data) {
+    unsafe {
+        handle_mut(instance).run_adding_gain = gain;
     }
 }
 
 extern "C" fn activate(instance: ladspa_h::Handle) {
     unsafe {
-        let handle: &mut Handle = mem::transmute(instance);
-        let mut handle = AssertUnwindSafe(handle);
-        call_user_code!(Some(handle.plugin.activate()), "Plugin::activate");
+        let handle = handle_mut(instance);
+        if handle.active {
+            lifecycle_warning(handle, "activate while active, deactivating first");
+            deactivate_plugin(handle);
+        }
+        activate_plugin(handle);
     }
 }
+
 extern "C" fn deactivate(instance: ladspa_h::Handle) {
     unsafe {
-        let handle: &mut Handle = mem::transmute(instance);
-        let mut handle = AssertUnwindSafe(handle);
-        call_user_code!(Some(handle.plugin.deactivate()), "Plugin::deactivate");
+        let handle = handle_mut(instance);
+        if handle.active {
+            deactivate_plugin(handle);
+        } else {
+            lifecycle_warning(handle, "deactivate while inactive, ignoring");
+        }
     }
 }
 
-// extern "C" fn run_adding(instance: ladspa_h::Handle, sample_count: c_ulong) {
-// }
-// extern "C" fn set_run_adding_gain(instance: ladspa_h::Handle, gain: ladspa_h::Data) {
-// }
-
 extern "C" fn cleanup(instance: ladspa_h::Handle) {
     unsafe {
-        mem::transmute::<_, Box<Handle>>(instance);
+        let handle = handle_mut(instance);
+        if handle.active {
+            lifecycle_warning(handle, "cleanup while active, deactivating first");
+            deactivate_plugin(handle);
+        }
+        let handle = AssertUnwindSafe(Box::from_raw(instance as *mut Handle));
+        call_user_code!(drop(handle), "Plugin::drop");
+    }
+}
+
+/// Receives the state saved by ```ladspa_rs_save_state```.
+pub type StateWriter = extern "C" fn(context: *mut c_void, data: *const u8, len: usize);
+
+/// Calls ```write``` with the state of ```instance```, an instance of a plugin from this library,
+/// and returns 1, or returns 0 if the plugin isn't ```Stateful``` or its instance is poisoned.
+///
+/// Not part of LADSPA: this lets the hosts of the ```host``` module save the state of plugins
+/// built with this crate. The host must not run the instance concurrently.
+#[no_mangle]
+pub unsafe extern "C" fn ladspa_rs_save_state(instance: ladspa_h::Handle,
+                                              write: StateWriter,
+                                              context: *mut c_void)
+                                              -> c_int {
+    let handle = handle_mut(instance);
+    if handle.poisoned {
+        return 0;
+    }
+    let user = AssertUnwindSafe(&*handle);
+    let state = call_user_code!(user.plugin.state().map(|plugin| plugin.save()),
+                                "Stateful::save");
+    match state {
+        Some(Some(state)) => {
+            write(context, state.as_ptr(), state.len());
+            1
+        }
+        Some(None) => 0,
+        None => {
+            handle.poisoned = true;
+            0
+        }
+    }
+}
+
+/// Restores the ```len``` bytes at ```data```, saved by ```ladspa_rs_save_state```, into
+/// ```instance```. Returns 1 on success, or 0 if the plugin isn't ```Stateful``` or its instance
+/// is poisoned.
+#[no_mangle]
+pub unsafe extern "C" fn ladspa_rs_restore_state(instance: ladspa_h::Handle,
+                                                 data: *const u8,
+                                                 len: usize)
+                                                 -> c_int {
+    let handle = handle_mut(instance);
+    if handle.poisoned {
+        return 0;
+    }
+    let state = if data.is_null() { &[][..] } else { slice::from_raw_parts(data, len) };
+    let mut user = AssertUnwindSafe(&mut *handle);
+    let restored = call_user_code!(user.plugin.stateful().map(|plugin| plugin.restore(state)),
+                                   "Stateful::restore");
+    match restored {
+        Some(Some(())) => 1,
+        Some(None) => 0,
+        None => {
+            handle.poisoned = true;
+            0
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::ptr;
+    use std::sync::Mutex;
+
+    use super::*;
+    use super::super::{InstantiateError, Plugin, UniqueId};
+
+    // Adds 1 to its input, and panics if the input starts with a negative sample.
+    struct Offset;
+
+    impl Plugin for Offset {
+        fn run<'a>(&mut self, sample_count: usize, ports: &mut [PortConnection<'a>]) {
+            let input = ports[0].unwrap_audio();
+            assert!(input[0] >= 0.0, "negative input");
+            let output = &mut ports[1].unwrap_audio_mut()[..sample_count];
+            for (out, &sample) in output.iter_mut().zip(input) {
+                *out = sample + 1.0;
+            }
+        }
+    }
+
+    fn new_offset(_: &PluginDescriptor,
+                  _: u64)
+                  -> Result<Box<dyn Plugin + Send>, InstantiateError> {
+        Ok(Box::new(Offset))
+    }
+
+    // The calls made to the `Lifecycle` plugins, in order.
+    static CALLS: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+
+    // Records the calls made to it in `CALLS`.
+    struct Lifecycle;
+
+    impl Plugin for Lifecycle {
+        fn initialize(&mut self, _: u64, _: Option<usize>) {
+            CALLS.lock().unwrap().push("initialize");
+        }
+
+        fn activate(&mut self) {
+            CALLS.lock().unwrap().push("activate");
+        }
+
+        fn run<'a>(&mut self, _: usize, _: &mut [PortConnection<'a>]) {
+            CALLS.lock().unwrap().push("run");
+        }
+
+        fn deactivate(&mut self) {
+            CALLS.lock().unwrap().push("deactivate");
+        }
+    }
+
+    impl Drop for Lifecycle {
+        fn drop(&mut self) {
+            CALLS.lock().unwrap().push("drop");
+        }
+    }
+
+    fn new_lifecycle(_: &PluginDescriptor,
+                     _: u64)
+                     -> Result<Box<dyn Plugin + Send>, InstantiateError> {
+        Ok(Box::new(Lifecycle))
+    }
+
+    fn describe(label: &'static str, new: super::super::PluginConstructor)
+                -> &'static ladspa_h::Descriptor {
+        let plugin = PluginDescriptor::builder()
+            .unique_id(UniqueId::experimental(997).get())
+            .label(label)
+            .name(label)
+            .maker("ladspa.rs")
+            .copyright("None")
+            .audio_in("Input")
+            .audio_out("Output")
+            .new(new)
+            .build()
+            .unwrap();
+        unsafe { &*export(plugin) }
+    }
+
+    fn offset() -> &'static ladspa_h::Descriptor {
+        describe("test_offset", new_offset)
+    }
+
+    #[test]
+    fn calls_made_out_of_order_are_fixed_up() {
+        let descriptor = describe("test_lifecycle", new_lifecycle);
+        let instance = (descriptor.instantiate)(descriptor, 48000);
+        let mut buffer = [0.0; 4];
+        (descriptor.connect_port)(instance, 0, buffer.as_mut_ptr());
+        (descriptor.connect_port)(instance, 1, buffer.as_mut_ptr());
+        (descriptor.run)(instance, 4);
+        (descriptor.activate.unwrap())(instance);
+        (descriptor.deactivate.unwrap())(instance);
+        (descriptor.deactivate.unwrap())(instance);
+        (descriptor.activate.unwrap())(instance);
+        (descriptor.activate.unwrap())(instance);
+        (descriptor.cleanup)(instance);
+        assert_eq!(*CALLS.lock().unwrap(),
+                   ["initialize", "activate", "run", "deactivate", "activate", "deactivate",
+                    "activate", "deactivate", "activate", "deactivate", "drop"]);
+    }
+
+    #[test]
+    fn panicking_instances_output_silence_from_then_on() {
+        let descriptor = offset();
+        let instance = (descriptor.instantiate)(descriptor, 48000);
+        let mut input = [-1.0; 4];
+        let mut output = [5.0; 4];
+        (descriptor.connect_port)(instance, 0, input.as_mut_ptr());
+        (descriptor.connect_port)(instance, 1, output.as_mut_ptr());
+        (descriptor.activate.unwrap())(instance);
+        (descriptor.run)(instance, 4);
+        assert_eq!(output, [0.0; 4]);
+        input.fill(1.0);
+        (descriptor.run)(instance, 4);
+        assert_eq!(output, [0.0; 4]);
+        (descriptor.cleanup)(instance);
+    }
+
+    #[test]
+    fn inputs_and_outputs_may_share_a_buffer() {
+        let descriptor = offset();
+        let instance = (descriptor.instantiate)(descriptor, 48000);
+        let mut buffer = [1.0, 2.0, 3.0, 4.0];
+        (descriptor.connect_port)(instance, 0, buffer.as_mut_ptr());
+        (descriptor.connect_port)(instance, 1, buffer.as_mut_ptr());
+        (descriptor.activate.unwrap())(instance);
+        (descriptor.run)(instance, 4);
+        assert_eq!(buffer, [2.0, 3.0, 4.0, 5.0]);
+        (descriptor.set_run_adding_gain.unwrap())(instance, 0.5);
+        (descriptor.run_adding.unwrap())(instance, 4);
+        assert_eq!(buffer, [3.5, 5.0, 6.5, 8.0]);
+        (descriptor.cleanup)(instance);
+    }
+
+    #[test]
+    fn blocks_sharing_buffers_are_run_in_chunks() {
+        let descriptor = offset();
+        let instance = (descriptor.instantiate)(descriptor, 48000);
+        let mut buffer = vec![1.0; SCRATCH_SIZE * 2 + 3];
+        (descriptor.connect_port)(instance, 0, buffer.as_mut_ptr());
+        (descriptor.connect_port)(instance, 1, buffer.as_mut_ptr());
+        (descriptor.activate.unwrap())(instance);
+        (descriptor.run)(instance, buffer.len() as c_ulong);
+        assert!(buffer.iter().all(|&sample| sample == 2.0));
+        let handle = unsafe { handle_mut(instance) };
+        assert!(handle.detached.iter().all(|detached| detached.capacity() <= SCRATCH_SIZE));
+        (descriptor.cleanup)(instance);
+    }
+
+    #[test]
+    fn plugins_are_not_run_with_unconnected_ports() {
+        let descriptor = offset();
+        let instance = (descriptor.instantiate)(descriptor, 48000);
+        let mut input = [2.0; 4];
+        let mut output = [5.0; 4];
+        (descriptor.connect_port)(instance, 1, output.as_mut_ptr());
+        (descriptor.run)(instance, 4);
+        assert_eq!(output, [0.0; 4]);
+
+        output = [5.0; 4];
+        (descriptor.run_adding.unwrap())(instance, 4);
+        assert_eq!(output, [5.0; 4]);
+
+        (descriptor.connect_port)(instance, 0, input.as_mut_ptr());
+        (descriptor.run)(instance, 4);
+        assert_eq!(output, [3.0; 4]);
+        (descriptor.run_adding.unwrap())(instance, 4);
+        assert_eq!(output, [6.0; 4]);
+
+        (descriptor.connect_port)(instance, 0, ptr::null_mut());
+        (descriptor.run)(instance, 4);
+        assert_eq!(output, [0.0; 4]);
+        (descriptor.cleanup)(instance);
     }
 }
@@ -0,0 +1,723 @@
+//! Exporting plugins as GStreamer elements.
+//!
+//! With the ```gstreamer``` feature enabled, the plugin library is also a GStreamer plugin. Once
+//! it is copied to a directory listed in ```GST_PLUGIN_PATH```, every plugin returned by
+//! ```get_ladspa_descriptor``` with as many audio inputs as audio outputs is available as an
+//! audio filter element named by ```element_name```:
+//!
+//! ```text
+//! gst-launch-1.0 audiotestsrc ! audioconvert ! ladspa-rs-delay delay=0.25 ! autoaudiosink
+//! ```
+//!
+//! The elements process interleaved 32 bit float audio, with one channel per audio input, at
+//! any rate. Control inputs are float properties named by ```property_name```, and control
+//! outputs are read-only properties. Unlike GStreamer's own ```ladspa``` wrapper, this runs the
+//! plugin directly rather than through a second plugin loader.
+//!
+//! GStreamer is loaded when it loads the plugin rather than linked, so it is not needed to build.
+
+// c_ulong is only 32 bits wide on some platforms, so widening it is not always a no-op.
+#![allow(clippy::useless_conversion)]
+
+use std::cell::UnsafeCell;
+use std::ffi::{CStr, CString};
+use std::mem;
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use libc::{self, c_char, c_int, c_ulong, c_void};
+
+use ffi::{self, ladspa_h};
+use super::{Data, PluginDescriptor, Port, PortDescriptor, HINT_SAMPLE_RATE};
+
+// The parts of the GObject and GStreamer headers needed to define an element.
+mod gst_h {
+    use libc::{c_char, c_int, c_void};
+
+    pub type GType = usize;
+    pub type Boolean = c_int;
+
+    pub const TRUE: Boolean = 1;
+    pub const FALSE: Boolean = 0;
+
+    pub const FLOW_OK: c_int = 0;
+    pub const FLOW_NOT_NEGOTIATED: c_int = -4;
+    pub const FLOW_ERROR: c_int = -5;
+
+    pub const PAD_SRC: c_int = 1;
+    pub const PAD_SINK: c_int = 2;
+    pub const PAD_ALWAYS: c_int = 0;
+
+    pub const MAP_READ: c_int = 1;
+    pub const MAP_WRITE: c_int = 2;
+
+    pub const PARAM_READABLE: c_int = 1;
+    pub const PARAM_WRITABLE: c_int = 2;
+
+    pub const RANK_NONE: u32 = 0;
+
+    // The offsets of the virtual functions overridden in `GObjectClass`.
+    pub const OBJECT_SET_PROPERTY: usize = 24;
+    pub const OBJECT_GET_PROPERTY: usize = 32;
+    pub const OBJECT_FINALIZE: usize = 48;
+
+    // The offsets of the virtual functions overridden in `GstBaseTransformClass`, from the end
+    // of the `GstElementClass` it starts with.
+    pub const TRANSFORM_SET_CAPS: usize = 32;
+    pub const TRANSFORM_STOP: usize = 96;
+    pub const TRANSFORM_TRANSFORM: usize = 152;
+
+    #[repr(C)]
+    pub struct TypeInfo {
+        pub class_size: u16,
+        pub base_init: *const c_void,
+        pub base_finalize: *const c_void,
+        pub class_init: extern "C" fn(class: *mut c_void, class_data: *mut c_void),
+        pub class_finalize: *const c_void,
+        pub class_data: *const c_void,
+        pub instance_size: u16,
+        pub n_preallocs: u16,
+        pub instance_init: extern "C" fn(instance: *mut c_void, class: *mut c_void),
+        pub value_table: *const c_void,
+    }
+
+    #[repr(C)]
+    pub struct TypeQuery {
+        pub type_: GType,
+        pub type_name: *const c_char,
+        pub class_size: u32,
+        pub instance_size: u32,
+    }
+
+    #[repr(C)]
+    pub struct MapInfo {
+        pub memory: *mut c_void,
+        pub flags: c_int,
+        pub data: *mut u8,
+        pub size: usize,
+        pub maxsize: usize,
+        pub user_data: [*mut c_void; 4],
+        pub reserved: [*mut c_void; 4],
+    }
+
+    #[repr(C)]
+    pub struct PluginDesc {
+        pub major_version: c_int,
+        pub minor_version: c_int,
+        pub name: *const c_char,
+        pub description: *const c_char,
+        pub plugin_init: extern "C" fn(plugin: *mut c_void) -> Boolean,
+        pub version: *const c_char,
+        pub license: *const c_char,
+        pub source: *const c_char,
+        pub package: *const c_char,
+        pub origin: *const c_char,
+        pub release_datetime: *const c_char,
+        pub reserved: [*mut c_void; 4],
+    }
+}
+
+const LIBRARIES: [&[u8]; 3] = [b"libgobject-2.0.so.0\0",
+                               b"libgstreamer-1.0.so.0\0",
+                               b"libgstbase-1.0.so.0\0"];
+
+// The number of frames processed by each call to the plugin's `run`.
+const BLOCK_SIZE: usize = 1024;
+
+// The rate at which the defaults of the properties are computed, as it is only known once the
+// element is negotiating its format.
+const DEFAULT_SAMPLE_RATE: u64 = 44100;
+
+#[cfg(target_endian = "little")]
+const FORMAT: &str = "F32LE";
+#[cfg(target_endian = "big")]
+const FORMAT: &str = "F32BE";
+
+/// Returns the name of the GStreamer element running ```plugin```, derived from its label.
+pub fn element_name(plugin: &PluginDescriptor) -> String {
+    format!("ladspa-rs-{}", sanitize(plugin.label))
+}
+
+/// Returns the name of the GStreamer property controlling ```port```, derived from its name.
+/// Ports whose names turn into the same property name, or into the name of a property of every
+/// element, get their index appended.
+pub fn property_name(port: &Port) -> String {
+    let name = sanitize(port.name);
+    if name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        name
+    } else {
+        format!("port-{}", name)
+    }
+}
+
+// Lowercases `name` and replaces every run of other characters than letters and digits with a
+// single `-`.
+fn sanitize(name: &str) -> String {
+    let mut sanitized = String::new();
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            sanitized.push(c.to_ascii_lowercase());
+        } else if !sanitized.is_empty() && !sanitized.ends_with('-') {
+            sanitized.push('-');
+        }
+    }
+    let len = sanitized.trim_end_matches('-').len();
+    sanitized.truncate(len);
+    sanitized
+}
+
+// The functions used from GObject and GStreamer.
+struct Api {
+    type_query: unsafe extern "C" fn(GType, *mut gst_h::TypeQuery),
+    type_from_name: unsafe extern "C" fn(*const c_char) -> GType,
+    type_register_static: unsafe extern "C" fn(GType, *const c_char, *const gst_h::TypeInfo,
+                                               c_int)
+                                               -> GType,
+    type_class_peek_parent: unsafe extern "C" fn(*mut c_void) -> *mut c_void,
+    object_class_find_property: unsafe extern "C" fn(*mut c_void, *const c_char) -> *mut c_void,
+    object_class_install_property: unsafe extern "C" fn(*mut c_void, u32, *mut c_void),
+    param_spec_float: unsafe extern "C" fn(*const c_char, *const c_char, *const c_char, f32,
+                                           f32, f32, c_int)
+                                           -> *mut c_void,
+    value_get_float: unsafe extern "C" fn(*const c_void) -> f32,
+    value_set_float: unsafe extern "C" fn(*mut c_void, f32),
+    element_get_type: unsafe extern "C" fn() -> GType,
+    base_transform_get_type: unsafe extern "C" fn() -> GType,
+    element_register: unsafe extern "C" fn(*mut c_void, *const c_char, u32, GType)
+                                           -> gst_h::Boolean,
+    element_class_set_static_metadata: unsafe extern "C" fn(*mut c_void, *const c_char,
+                                                            *const c_char, *const c_char,
+                                                            *const c_char),
+    element_class_add_pad_template: unsafe extern "C" fn(*mut c_void, *mut c_void),
+    pad_template_new: unsafe extern "C" fn(*const c_char, c_int, c_int, *mut c_void)
+                                           -> *mut c_void,
+    caps_from_string: unsafe extern "C" fn(*const c_char) -> *mut c_void,
+    caps_get_structure: unsafe extern "C" fn(*const c_void, u32) -> *mut c_void,
+    structure_get_int: unsafe extern "C" fn(*const c_void, *const c_char, *mut c_int)
+                                            -> gst_h::Boolean,
+    mini_object_unref: unsafe extern "C" fn(*mut c_void),
+    buffer_map: unsafe extern "C" fn(*mut c_void, *mut gst_h::MapInfo, c_int) -> gst_h::Boolean,
+    buffer_unmap: unsafe extern "C" fn(*mut c_void, *mut gst_h::MapInfo),
+    // The sizes of the parent classes and instance, which the virtual functions and the data of
+    // this module come after.
+    element_class_size: usize,
+    transform_class_size: usize,
+    transform_instance_size: usize,
+}
+
+use self::gst_h::GType;
+
+impl Api {
+    // Loads the libraries, which GStreamer has already loaded when it loads this plugin.
+    unsafe fn load() -> Result<Api, String> {
+        let mut libraries = Vec::new();
+        for name in &LIBRARIES {
+            let library = libc::dlopen(name.as_ptr() as *const c_char, libc::RTLD_NOW);
+            if library.is_null() {
+                let err = libc::dlerror();
+                return Err(if err.is_null() {
+                    String::from("failed to load GStreamer")
+                } else {
+                    CStr::from_ptr(err).to_string_lossy().into_owned()
+                });
+            }
+            libraries.push(library);
+        }
+        // The libraries stay loaded for as long as the program runs.
+        let find = |name: &str| {
+            libraries.iter()
+                .map(|&library| libc::dlsym(library, name.as_ptr() as *const c_char))
+                .find(|symbol| !symbol.is_null())
+                .ok_or_else(|| format!("missing {}", name.trim_end_matches('\0')))
+        };
+        macro_rules! symbol {
+            ($name:expr) => {
+                *(&find(concat!($name, "\0"))? as *const *mut c_void as *const _)
+            }
+        }
+        let mut api = Api {
+            type_query: symbol!("g_type_query"),
+            type_from_name: symbol!("g_type_from_name"),
+            type_register_static: symbol!("g_type_register_static"),
+            type_class_peek_parent: symbol!("g_type_class_peek_parent"),
+            object_class_find_property: symbol!("g_object_class_find_property"),
+            object_class_install_property: symbol!("g_object_class_install_property"),
+            param_spec_float: symbol!("g_param_spec_float"),
+            value_get_float: symbol!("g_value_get_float"),
+            value_set_float: symbol!("g_value_set_float"),
+            element_get_type: symbol!("gst_element_get_type"),
+            base_transform_get_type: symbol!("gst_base_transform_get_type"),
+            element_register: symbol!("gst_element_register"),
+            element_class_set_static_metadata: symbol!("gst_element_class_set_static_metadata"),
+            element_class_add_pad_template: symbol!("gst_element_class_add_pad_template"),
+            pad_template_new: symbol!("gst_pad_template_new"),
+            caps_from_string: symbol!("gst_caps_from_string"),
+            caps_get_structure: symbol!("gst_caps_get_structure"),
+            structure_get_int: symbol!("gst_structure_get_int"),
+            mini_object_unref: symbol!("gst_mini_object_unref"),
+            buffer_map: symbol!("gst_buffer_map"),
+            buffer_unmap: symbol!("gst_buffer_unmap"),
+            element_class_size: 0,
+            transform_class_size: 0,
+            transform_instance_size: 0,
+        };
+        let element = api.query((api.element_get_type)());
+        let transform = api.query((api.base_transform_get_type)());
+        api.element_class_size = element.class_size as usize;
+        api.transform_class_size = transform.class_size as usize;
+        api.transform_instance_size = transform.instance_size as usize;
+        Ok(api)
+    }
+
+    unsafe fn query(&self, type_: GType) -> gst_h::TypeQuery {
+        let mut query = gst_h::TypeQuery {
+            type_: 0,
+            type_name: ptr::null(),
+            class_size: 0,
+            instance_size: 0,
+        };
+        (self.type_query)(type_, &mut query);
+        query
+    }
+}
+
+// Leaks `s` as a C string, for the strings GStreamer keeps for as long as the program runs.
+fn leak_c_str(s: &str) -> *const c_char {
+    CString::new(s.replace('\0', "")).unwrap().into_raw()
+}
+
+// An element type, pointed to by its class. Leaked when the type is registered.
+struct Element {
+    api: &'static Api,
+    ladspa: &'static ladspa_h::Descriptor,
+    plugin: &'static PluginDescriptor,
+    audio_inputs: Vec<usize>,
+    audio_outputs: Vec<usize>,
+    // The port controlled by each property, by property ID minus one.
+    properties: Vec<usize>,
+    parent_class: *mut c_void,
+}
+
+// An element, pointed to by its instance.
+struct Instance {
+    // The value of each control port, as the bits of a `Data`, set and read through the
+    // properties from any thread.
+    controls: Vec<AtomicU32>,
+    // Only used from the streaming thread.
+    processing: UnsafeCell<Processing>,
+}
+
+struct Processing {
+    handle: ladspa_h::Handle,
+    sample_rate: u64,
+    // The buffers connected to the ports of the plugin.
+    buffers: Vec<Vec<Data>>,
+}
+
+impl Processing {
+    fn close(&mut self, element: &Element) {
+        if !self.handle.is_null() {
+            if let Some(deactivate) = element.ladspa.deactivate {
+                deactivate(self.handle);
+            }
+            (element.ladspa.cleanup)(self.handle);
+            self.handle = ptr::null_mut();
+        }
+    }
+}
+
+unsafe fn element_of_class(class: *mut c_void, api: &Api) -> &'static Element {
+    &**((class as *mut u8).add(api.transform_class_size) as *const *const Element)
+}
+
+// The element type and instance of a GObject, found through the class pointer every GObject
+// starts with.
+unsafe fn instance_of(object: *mut c_void) -> (&'static Element, &'static Instance) {
+    let class = *(object as *const *mut c_void);
+    // Every element type points to the same `Api`, so any of them can find the offsets.
+    let api = API.load(Ordering::Acquire);
+    let element = element_of_class(class, &*api);
+    let instance = (object as *mut u8).add(element.api.transform_instance_size) as
+                   *const *const Instance;
+    (element, &**instance)
+}
+
+static API: ::std::sync::atomic::AtomicPtr<Api> =
+    ::std::sync::atomic::AtomicPtr::new(ptr::null_mut());
+
+unsafe fn set_vfunc(class: *mut c_void, offset: usize, function: *const c_void) {
+    *((class as *mut u8).add(offset) as *mut *const c_void) = function;
+}
+
+extern "C" fn class_init(class: *mut c_void, data: *mut c_void) {
+    unsafe {
+        let element = &mut *(data as *mut Element);
+        let api = element.api;
+        *((class as *mut u8).add(api.transform_class_size) as *mut *const Element) = element;
+        element.parent_class = (api.type_class_peek_parent)(class);
+
+        set_vfunc(class, gst_h::OBJECT_SET_PROPERTY, set_property as *const c_void);
+        set_vfunc(class, gst_h::OBJECT_GET_PROPERTY, get_property as *const c_void);
+        set_vfunc(class, gst_h::OBJECT_FINALIZE, finalize as *const c_void);
+        let transform = api.element_class_size;
+        set_vfunc(class, transform + gst_h::TRANSFORM_SET_CAPS, set_caps as *const c_void);
+        set_vfunc(class, transform + gst_h::TRANSFORM_STOP, stop as *const c_void);
+        set_vfunc(class, transform + gst_h::TRANSFORM_TRANSFORM, transform_buffer as *const c_void);
+
+        let plugin = element.plugin;
+        // GStreamer requires every field of the metadata to be filled in.
+        let or_unknown = |s: &'static str| if s.is_empty() { "Unknown" } else { s };
+        (api.element_class_set_static_metadata)(class,
+                                                leak_c_str(or_unknown(plugin.name)),
+                                                leak_c_str("Filter/Effect/Audio"),
+                                                leak_c_str(&format!("LADSPA plugin {}",
+                                                                    plugin.label)),
+                                                leak_c_str(or_unknown(plugin.maker)));
+
+        let caps = CString::new(format!("audio/x-raw, format=(string){}, \
+                                         layout=(string)interleaved, \
+                                         rate=(int)[ 1, 2147483647 ], channels=(int){}",
+                                        FORMAT,
+                                        element.audio_inputs.len()))
+            .unwrap();
+        let pads: [(&[u8], c_int); 2] = [(b"sink\0", gst_h::PAD_SINK), (b"src\0", gst_h::PAD_SRC)];
+        for &(name, direction) in &pads {
+            let caps = (api.caps_from_string)(caps.as_ptr());
+            let template = (api.pad_template_new)(name.as_ptr() as *const c_char,
+                                                  direction,
+                                                  gst_h::PAD_ALWAYS,
+                                                  caps);
+            (api.element_class_add_pad_template)(class, template);
+            (api.mini_object_unref)(caps);
+        }
+
+        for (index, port) in plugin.ports.iter().enumerate() {
+            let flags = match port.desc {
+                PortDescriptor::ControlInput => gst_h::PARAM_READABLE | gst_h::PARAM_WRITABLE,
+                PortDescriptor::ControlOutput => gst_h::PARAM_READABLE,
+                _ => continue,
+            };
+            let mut name = CString::new(property_name(port)).unwrap();
+            if !(api.object_class_find_property)(class, name.as_ptr()).is_null() {
+                name = CString::new(format!("{}-{}", property_name(port), index)).unwrap();
+            }
+            let (min, max) = property_range(port);
+            let label = CString::new(port.name.replace('\0', "")).unwrap();
+            let spec = (api.param_spec_float)(name.as_ptr(),
+                                              label.as_ptr(),
+                                              label.as_ptr(),
+                                              min,
+                                              max,
+                                              initial_value(port).max(min).min(max),
+                                              flags);
+            element.properties.push(index);
+            (api.object_class_install_property)(class, element.properties.len() as u32, spec);
+        }
+    }
+}
+
+// The range of the property of `port`. The bounds of ports which depend on the sample rate
+// aren't known until the element is running, so those are left unbounded.
+fn property_range(port: &Port) -> (f32, f32) {
+    let scaled = port.hint.is_some_and(|hint| hint.contains(HINT_SAMPLE_RATE));
+    if scaled {
+        (-Data::MAX, Data::MAX)
+    } else {
+        let lower = port.lower_bound.unwrap_or(-Data::MAX);
+        (lower, port.upper_bound.unwrap_or(Data::MAX).max(lower))
+    }
+}
+
+fn initial_value(port: &Port) -> Data {
+    port.default_at(DEFAULT_SAMPLE_RATE).or(port.lower_bound).unwrap_or(0.0)
+}
+
+extern "C" fn instance_init(object: *mut c_void, class: *mut c_void) {
+    unsafe {
+        let api = &*API.load(Ordering::Acquire);
+        let element = element_of_class(class, api);
+        let instance = Box::new(Instance {
+            controls: element.plugin
+                .ports
+                .iter()
+                .map(|port| AtomicU32::new(initial_value(port).to_bits()))
+                .collect(),
+            processing: UnsafeCell::new(Processing {
+                handle: ptr::null_mut(),
+                sample_rate: 0,
+                buffers: element.plugin
+                    .ports
+                    .iter()
+                    .map(|port| match port.desc {
+                        PortDescriptor::AudioInput | PortDescriptor::AudioOutput => {
+                            vec![0.0; BLOCK_SIZE]
+                        }
+                        _ => vec![0.0],
+                    })
+                    .collect(),
+            }),
+        });
+        let slot = (object as *mut u8).add(api.transform_instance_size) as *mut *mut Instance;
+        *slot = Box::into_raw(instance);
+    }
+}
+
+extern "C" fn finalize(object: *mut c_void) {
+    unsafe {
+        let (element, instance) = instance_of(object);
+        let mut instance = Box::from_raw(instance as *const Instance as *mut Instance);
+        instance.processing.get_mut().close(element);
+        let parent: Option<extern "C" fn(*mut c_void)> =
+            *((element.parent_class as *const u8).add(gst_h::OBJECT_FINALIZE) as *const _);
+        if let Some(parent) = parent {
+            parent(object);
+        }
+    }
+}
+
+extern "C" fn set_property(object: *mut c_void, id: u32, value: *const c_void, _: *mut c_void) {
+    unsafe {
+        let (element, instance) = instance_of(object);
+        if let Some(&port) = element.properties.get((id as usize).wrapping_sub(1)) {
+            let value = (element.api.value_get_float)(value);
+            instance.controls[port].store(value.to_bits(), Ordering::Relaxed);
+        }
+    }
+}
+
+extern "C" fn get_property(object: *mut c_void, id: u32, value: *mut c_void, _: *mut c_void) {
+    unsafe {
+        let (element, instance) = instance_of(object);
+        if let Some(&port) = element.properties.get((id as usize).wrapping_sub(1)) {
+            let control = Data::from_bits(instance.controls[port].load(Ordering::Relaxed));
+            (element.api.value_set_float)(value, control);
+        }
+    }
+}
+
+// Instantiates the plugin for the negotiated sample rate.
+extern "C" fn set_caps(object: *mut c_void, caps: *mut c_void, _: *mut c_void) -> gst_h::Boolean {
+    unsafe {
+        let (element, instance) = instance_of(object);
+        let processing = &mut *instance.processing.get();
+        let api = element.api;
+        let mut rate: c_int = 0;
+        let structure = (api.caps_get_structure)(caps, 0);
+        if structure.is_null() ||
+           (api.structure_get_int)(structure, b"rate\0".as_ptr() as *const c_char, &mut rate) ==
+           gst_h::FALSE || rate <= 0 {
+            return gst_h::FALSE;
+        }
+        let sample_rate = rate as u64;
+        if !processing.handle.is_null() && processing.sample_rate == sample_rate {
+            return gst_h::TRUE;
+        }
+        processing.close(element);
+        let handle = (element.ladspa.instantiate)(element.ladspa, sample_rate as c_ulong);
+        if handle.is_null() {
+            return gst_h::FALSE;
+        }
+        for (port, buffer) in processing.buffers.iter_mut().enumerate() {
+            (element.ladspa.connect_port)(handle, port as c_ulong, buffer.as_mut_ptr());
+        }
+        if let Some(activate) = element.ladspa.activate {
+            activate(handle);
+        }
+        processing.handle = handle;
+        processing.sample_rate = sample_rate;
+        gst_h::TRUE
+    }
+}
+
+extern "C" fn stop(object: *mut c_void) -> gst_h::Boolean {
+    unsafe {
+        let (element, instance) = instance_of(object);
+        (*instance.processing.get()).close(element);
+    }
+    gst_h::TRUE
+}
+
+fn empty_map() -> gst_h::MapInfo {
+    gst_h::MapInfo {
+        memory: ptr::null_mut(),
+        flags: 0,
+        data: ptr::null_mut(),
+        size: 0,
+        maxsize: 0,
+        user_data: [ptr::null_mut(); 4],
+        reserved: [ptr::null_mut(); 4],
+    }
+}
+
+extern "C" fn transform_buffer(object: *mut c_void, input: *mut c_void, output: *mut c_void)
+                               -> c_int {
+    unsafe {
+        let (element, instance) = instance_of(object);
+        let processing = &mut *instance.processing.get();
+        if processing.handle.is_null() {
+            return gst_h::FLOW_NOT_NEGOTIATED;
+        }
+        let api = element.api;
+        let (mut input_map, mut output_map) = (empty_map(), empty_map());
+        if (api.buffer_map)(input, &mut input_map, gst_h::MAP_READ) == gst_h::FALSE {
+            return gst_h::FLOW_ERROR;
+        }
+        if (api.buffer_map)(output, &mut output_map, gst_h::MAP_WRITE) == gst_h::FALSE {
+            (api.buffer_unmap)(input, &mut input_map);
+            return gst_h::FLOW_ERROR;
+        }
+        let channels = element.audio_inputs.len();
+        let frames = input_map.size.min(output_map.size) / (channels * 4);
+        let input_samples = slice::from_raw_parts(input_map.data as *const Data, frames * channels);
+        let output_samples = slice::from_raw_parts_mut(output_map.data as *mut Data,
+                                                       frames * channels);
+        process(element, instance, processing, input_samples, output_samples);
+        (api.buffer_unmap)(output, &mut output_map);
+        (api.buffer_unmap)(input, &mut input_map);
+        gst_h::FLOW_OK
+    }
+}
+
+// Runs the plugin over interleaved `input`, writing interleaved `output`, a block at a time.
+fn process(element: &Element, instance: &Instance, processing: &mut Processing, input: &[Data],
+           output: &mut [Data]) {
+    let channels = element.audio_inputs.len();
+    for (input, output) in input.chunks(BLOCK_SIZE * channels)
+        .zip(output.chunks_mut(BLOCK_SIZE * channels)) {
+        let frames = input.len() / channels;
+        for (port, control) in instance.controls.iter().enumerate() {
+            if let PortDescriptor::ControlInput = element.plugin.ports[port].desc {
+                processing.buffers[port][0] = Data::from_bits(control.load(Ordering::Relaxed));
+            }
+        }
+        for (channel, &port) in element.audio_inputs.iter().enumerate() {
+            let buffer = &mut processing.buffers[port];
+            for (sample, frame) in buffer.iter_mut().zip(input.chunks(channels)) {
+                *sample = frame[channel];
+            }
+        }
+        (element.ladspa.run)(processing.handle, frames as c_ulong);
+        for (channel, &port) in element.audio_outputs.iter().enumerate() {
+            let buffer = &processing.buffers[port];
+            for (frame, &sample) in output.chunks_mut(channels).zip(buffer.iter()) {
+                frame[channel] = sample;
+            }
+        }
+        for (port, control) in instance.controls.iter().enumerate() {
+            if let PortDescriptor::ControlOutput = element.plugin.ports[port].desc {
+                control.store(processing.buffers[port][0].to_bits(), Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+// Registers an element type for each plugin of the library which has matching audio inputs
+// and outputs.
+extern "C" fn plugin_init(gst_plugin: *mut c_void) -> gst_h::Boolean {
+    let api: &'static Api = unsafe {
+        let existing = API.load(Ordering::Acquire);
+        if existing.is_null() {
+            match Api::load() {
+                Ok(api) => {
+                    let api = Box::into_raw(Box::new(api));
+                    API.store(api, Ordering::Release);
+                    &*api
+                }
+                Err(err) => {
+                    println!("ladspa.rs: {}", err);
+                    return gst_h::FALSE;
+                }
+            }
+        } else {
+            &*existing
+        }
+    };
+    let mut registered = false;
+    for index in 0.. {
+        let ladspa = match unsafe { ffi::ladspa_descriptor(index as c_ulong).as_ref() } {
+            Some(ladspa) => ladspa,
+            None => break,
+        };
+        let plugin = unsafe { &*(ladspa.implementation_data as *const PluginDescriptor) };
+        let ports = |desc: PortDescriptor| -> Vec<usize> {
+            plugin.ports
+                .iter()
+                .enumerate()
+                .filter(|&(_, port)| mem::discriminant(&port.desc) == mem::discriminant(&desc))
+                .map(|(index, _)| index)
+                .collect()
+        };
+        let (audio_inputs, audio_outputs) = (ports(PortDescriptor::AudioInput),
+                                             ports(PortDescriptor::AudioOutput));
+        if audio_inputs.is_empty() || audio_inputs.len() != audio_outputs.len() {
+            continue;
+        }
+        let type_name = CString::new(format!("GstLadspaRs{}", plugin.unique_id)).unwrap();
+        unsafe {
+            if (api.type_from_name)(type_name.as_ptr()) != 0 {
+                println!("ladspa.rs: {}: a GStreamer element with ID {} already exists",
+                         plugin.label,
+                         plugin.unique_id);
+                continue;
+            }
+            let element = Box::into_raw(Box::new(Element {
+                api,
+                ladspa,
+                plugin,
+                audio_inputs,
+                audio_outputs,
+                properties: Vec::new(),
+                parent_class: ptr::null_mut(),
+            }));
+            let info = gst_h::TypeInfo {
+                class_size: (api.transform_class_size + 8) as u16,
+                base_init: ptr::null(),
+                base_finalize: ptr::null(),
+                class_init,
+                class_finalize: ptr::null(),
+                class_data: element as *const c_void,
+                instance_size: (api.transform_instance_size + 8) as u16,
+                n_preallocs: 0,
+                instance_init,
+                value_table: ptr::null(),
+            };
+            let type_ = (api.type_register_static)((api.base_transform_get_type)(),
+                                                   type_name.as_ptr(),
+                                                   &info,
+                                                   0);
+            let name = CString::new(element_name(plugin)).unwrap();
+            if type_ != 0 &&
+               (api.element_register)(gst_plugin, name.as_ptr(), gst_h::RANK_NONE, type_) !=
+               gst_h::FALSE {
+                registered = true;
+            }
+        }
+    }
+    if registered { gst_h::TRUE } else { gst_h::FALSE }
+}
+
+// Only read by GStreamer.
+#[doc(hidden)]
+pub struct PluginDesc(#[allow(dead_code)] gst_h::PluginDesc);
+
+unsafe impl Sync for PluginDesc {}
+
+#[doc(hidden)]
+#[no_mangle]
+#[allow(non_upper_case_globals)]
+// Exported so the library is recognised by GStreamer.
+pub static gst_plugin_desc: PluginDesc = PluginDesc(gst_h::PluginDesc {
+    major_version: 1,
+    minor_version: 0,
+    name: b"ladspars\0" as *const u8 as *const c_char,
+    description: b"Plugins written with the ladspa crate\0" as *const u8 as *const c_char,
+    plugin_init,
+    version: concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char,
+    license: b"unknown\0" as *const u8 as *const c_char,
+    source: b"ladspa.rs\0" as *const u8 as *const c_char,
+    package: b"ladspa.rs\0" as *const u8 as *const c_char,
+    origin: concat!(env!("CARGO_PKG_REPOSITORY"), "\0").as_ptr() as *const c_char,
+    release_datetime: ptr::null(),
+    reserved: [ptr::null_mut(); 4],
+});
@@ -0,0 +1,165 @@
+//! Measuring how much CPU time plugins need.
+//!
+//! ```measure``` runs a plugin over white noise at every combination of the configured sample
+//! rates and block sizes, and reports the time taken per block along with the speed relative to
+//! real time. Printing the results gives a table which is easy to compare between commits:
+//!
+//! ```no_run
+//! # use ladspa::harness::bench::{self, BenchConfig};
+//! # fn filter_descriptor() -> ladspa::PluginDescriptor { unimplemented!() }
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! for measurement in bench::measure(&filter_descriptor(), &BenchConfig::default())? {
+//!     println!("{}", measurement);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! To benchmark with Criterion instead, which takes care of statistics and regression tracking,
+//! time ```BlockRunner::run```:
+//!
+//! ```rust,ignore
+//! let descriptor = filter_descriptor();
+//! let mut runner = BlockRunner::new(&descriptor, 48000, 256)?;
+//! c.bench_function("filter 256", |b| b.iter(|| runner.run()));
+//! ```
+//!
+//! Build benchmarks in release mode; debug builds say little about real performance.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use super::super::{Data, InstantiateError, PluginDescriptor, PortDescriptor};
+use super::TestHost;
+
+/// A plugin instance ready to process blocks of white noise, with control inputs at their
+/// defaults.
+pub struct BlockRunner<'d> {
+    host: TestHost<'d>,
+}
+
+impl<'d> BlockRunner<'d> {
+    /// Instantiates and activates the plugin described by ```descriptor```, and fills its audio
+    /// inputs with noise.
+    pub fn new(descriptor: &'d PluginDescriptor,
+               sample_rate: u64,
+               block_size: usize)
+               -> Result<BlockRunner<'d>, InstantiateError> {
+        let mut host = TestHost::new(descriptor, sample_rate, block_size)?;
+        let mut state: u32 = 1;
+        for (index, port) in descriptor.ports.iter().enumerate() {
+            if let PortDescriptor::AudioInput = port.desc {
+                for sample in host.input_mut(index) {
+                    state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                    *sample = (state >> 8) as Data / (1 << 23) as Data - 1.0;
+                }
+            }
+        }
+        host.activate();
+        Ok(BlockRunner { host })
+    }
+
+    /// Returns the host running the plugin, for instance to change its controls.
+    pub fn host(&mut self) -> &mut TestHost<'d> {
+        &mut self.host
+    }
+
+    /// Processes one block.
+    pub fn run(&mut self) {
+        let block_size = self.host.block_size();
+        self.host.run(block_size);
+    }
+}
+
+/// Settings for ```measure```.
+#[derive(Clone, Debug)]
+pub struct BenchConfig {
+    /// The sample rates to measure at.
+    pub sample_rates: Vec<u64>,
+    /// The block sizes to measure with.
+    pub block_sizes: Vec<usize>,
+    /// How much audio to process before timing starts, in seconds.
+    pub warmup: f64,
+    /// How much audio to process while timing each combination, in seconds.
+    pub audio: f64,
+}
+
+impl Default for BenchConfig {
+    fn default() -> BenchConfig {
+        BenchConfig {
+            sample_rates: vec![44100, 48000, 96000],
+            block_sizes: vec![64, 128, 256, 512, 1024, 2048, 4096],
+            warmup: 1.0,
+            audio: 10.0,
+        }
+    }
+}
+
+/// The cost of running a plugin at one sample rate and block size.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Measurement {
+    /// The sample rate the plugin ran at.
+    pub sample_rate: u64,
+    /// The number of samples in each block.
+    pub block_size: usize,
+    /// The number of blocks timed.
+    pub blocks: usize,
+    /// The average time taken by one call to ```run```.
+    pub per_block: Duration,
+}
+
+impl Measurement {
+    /// Returns how many times faster than real time the plugin ran: 100 means processing a
+    /// block took 1% of the time it takes to play it.
+    pub fn realtime_factor(&self) -> f64 {
+        let audio = self.block_size as f64 / self.sample_rate as f64;
+        audio / self.per_block.as_secs_f64()
+    }
+}
+
+impl fmt::Display for Measurement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "{:>6} Hz {:>5} samples: {:>10.3} us per block, {:>8.1}x real time",
+               self.sample_rate,
+               self.block_size,
+               self.per_block.as_secs_f64() * 1e6,
+               self.realtime_factor())
+    }
+}
+
+// Runs enough blocks to cover the given amount of audio, at least one.
+fn blocks_for(seconds: f64, sample_rate: u64, block_size: usize) -> usize {
+    ((seconds * sample_rate as f64 / block_size as f64).ceil() as usize).max(1)
+}
+
+/// Times the plugin described by ```descriptor``` at every combination of sample rate and block
+/// size in ```config```, in that order.
+pub fn measure(descriptor: &PluginDescriptor,
+               config: &BenchConfig)
+               -> Result<Vec<Measurement>, InstantiateError> {
+    let mut measurements = Vec::new();
+    for &sample_rate in &config.sample_rates {
+        for &block_size in &config.block_sizes {
+            let mut runner = BlockRunner::new(descriptor, sample_rate, block_size)?;
+            for _ in 0..blocks_for(config.warmup, sample_rate, block_size) {
+                runner.run();
+            }
+
+            let blocks = blocks_for(config.audio, sample_rate, block_size);
+            let start = Instant::now();
+            for _ in 0..blocks {
+                runner.run();
+            }
+            let elapsed = start.elapsed();
+
+            measurements.push(Measurement {
+                sample_rate,
+                block_size,
+                blocks,
+                per_block: elapsed / blocks as u32,
+            });
+        }
+    }
+    Ok(measurements)
+}
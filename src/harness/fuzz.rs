@@ -0,0 +1,106 @@
+//! Fuzzing the processing code of plugins.
+//!
+//! ```run``` turns arbitrary bytes into a sample rate, control values, block sizes and input
+//! audio, and pushes them through a plugin with a ```TestHost```. It panics if the plugin panics
+//! or outputs a NaN or an infinity, which a fuzzer reports as a crash. With ```cargo fuzz```, a
+//! complete fuzz target is:
+//!
+//! ```rust,ignore
+//! #![no_main]
+//! #[macro_use] extern crate libfuzzer_sys;
+//! extern crate my_plugins;
+//!
+//! fuzz_target!(|data: &[u8]| {
+//!     ladspa::harness::fuzz::run(&my_plugins::filter_descriptor(), data);
+//! });
+//! ```
+//!
+//! Control values stay within the range of their port, as in ```props::check```, so crashes are
+//! reproducible from a host.
+
+use super::super::{Data, PluginDescriptor, PortDescriptor};
+use super::props::control_value;
+use super::{non_finite_output, TestHost};
+
+/// The sample rates picked from by the first byte of the input.
+pub const SAMPLE_RATES: [u64; 6] = [22050, 32000, 44100, 48000, 96000, 192000];
+
+/// The largest block size ```run``` uses.
+pub const MAX_BLOCK_SIZE: usize = 4096;
+
+/// The largest number of blocks ```run``` processes, however long the input.
+pub const MAX_BLOCKS: usize = 64;
+
+// Reads the fuzzer input front to back, producing zeros once it runs out.
+struct Bytes<'d> {
+    data: &'d [u8],
+}
+
+impl<'d> Bytes<'d> {
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn u8(&mut self) -> u8 {
+        match self.data.split_first() {
+            Some((&byte, rest)) => {
+                self.data = rest;
+                byte
+            }
+            None => 0,
+        }
+    }
+
+    fn u16(&mut self) -> u16 {
+        u16::from(self.u8()) | u16::from(self.u8()) << 8
+    }
+}
+
+/// Decodes ```data``` and runs the plugin described by ```descriptor``` on it. Does nothing if
+/// the plugin refuses to be instantiated.
+///
+/// The input is read as:
+///
+/// 1. one byte choosing one of ```SAMPLE_RATES```,
+/// 2. two bytes per control input, mapped onto its range,
+/// 3. then, for up to ```MAX_BLOCKS``` blocks while input remains: two bytes for the block size,
+///    up to ```MAX_BLOCK_SIZE```, followed by two bytes per sample for each audio input.
+///
+/// Missing bytes are read as zeros.
+///
+/// # Panics
+///
+/// Panics if the plugin panics or if any of its outputs is not finite after a block.
+pub fn run(descriptor: &PluginDescriptor, data: &[u8]) {
+    let mut bytes = Bytes { data };
+    let sample_rate = SAMPLE_RATES[usize::from(bytes.u8()) % SAMPLE_RATES.len()];
+    let mut host = match TestHost::new(descriptor, sample_rate, MAX_BLOCK_SIZE) {
+        Ok(host) => host,
+        Err(_) => return,
+    };
+    for (index, port) in descriptor.ports.iter().enumerate() {
+        if let PortDescriptor::ControlInput = port.desc {
+            let unit = f64::from(bytes.u16()) / f64::from(u16::MAX);
+            host.set_control(index, control_value(port, sample_rate, unit));
+        }
+    }
+
+    host.activate();
+    let mut blocks = 0;
+    while !bytes.is_empty() && blocks < MAX_BLOCKS {
+        let block_size = 1 + usize::from(bytes.u16()) % MAX_BLOCK_SIZE;
+        for (index, port) in descriptor.ports.iter().enumerate() {
+            if let PortDescriptor::AudioInput = port.desc {
+                for sample in &mut host.input_mut(index)[..block_size] {
+                    *sample = Data::from(bytes.u16() as i16) / 32768.0;
+                }
+            }
+        }
+        host.run(block_size);
+        if let Some(kind) = non_finite_output(descriptor, &host, block_size) {
+            panic!("after {} blocks: {}", blocks + 1, kind);
+        }
+        blocks += 1;
+    }
+    host.deactivate();
+}
@@ -0,0 +1,183 @@
+//! Running plugins offline, without a host.
+//!
+//! ```TestHost``` drives a single instance from safe Rust, for unit tests of a plugin.
+//! ```props::check``` runs it with random settings within its port hints, and ```fuzz::run```
+//! with settings chosen by a fuzzer. ```bench::measure``` times it.
+//!
+//! ```render_file``` runs a plugin over a WAV file and writes the result to another, which makes
+//! it easy to listen to a plugin while developing it or to compare its output against a known
+//! good rendering in a regression test:
+//!
+//! ```no_run
+//! # fn amp_descriptor() -> ladspa::PluginDescriptor { unimplemented!() }
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! // Port 2 is the gain control.
+//! ladspa::harness::render_file(&amp_descriptor(), "drums.wav", "drums_amp.wav", &[(2, 0.5)], 256)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+
+use super::{Data, InstantiateError, PluginDescriptor, PortDescriptor};
+use self::props::FailureKind;
+
+pub mod bench;
+pub mod fuzz;
+pub mod props;
+pub mod wav;
+mod test_host;
+
+pub use self::test_host::TestHost;
+use self::wav::{Wav, WavError};
+
+/// The ways in which rendering can fail.
+#[derive(Debug)]
+pub enum HarnessError {
+    /// A WAV file could not be read or written.
+    Wav(WavError),
+    /// The plugin could not be instantiated.
+    Instantiate(InstantiateError),
+    /// The input has a different number of channels than the plugin has audio inputs.
+    ChannelMismatch { inputs: usize, channels: usize },
+    /// The given port index is not a control input.
+    InvalidControl(usize),
+    /// The plugin has a port with an ```Invalid``` port descriptor.
+    InvalidPort(&'static str),
+}
+
+impl fmt::Display for HarnessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HarnessError::Wav(ref err) => write!(f, "{}", err),
+            HarnessError::Instantiate(ref err) => write!(f, "instantiation failed: {}", err),
+            HarnessError::ChannelMismatch { inputs, channels } => {
+                write!(f,
+                       "the plugin has {} audio inputs but the input has {} channels",
+                       inputs,
+                       channels)
+            }
+            HarnessError::InvalidControl(port) => write!(f, "port {} is not a control input", port),
+            HarnessError::InvalidPort(port) => {
+                write!(f, "port \"{}\" has an invalid port descriptor", port)
+            }
+        }
+    }
+}
+
+impl Error for HarnessError {}
+
+impl From<WavError> for HarnessError {
+    fn from(err: WavError) -> HarnessError {
+        HarnessError::Wav(err)
+    }
+}
+
+impl From<InstantiateError> for HarnessError {
+    fn from(err: InstantiateError) -> HarnessError {
+        HarnessError::Instantiate(err)
+    }
+}
+
+/// Runs a new instance of the plugin described by ```descriptor``` over ```input``` in blocks
+/// of ```block_size``` samples, and returns the contents of its audio outputs.
+///
+/// Each channel of ```input``` feeds one audio input, in order. Plugins without audio inputs
+/// accept any number of channels and simply run for the length of the input. Control inputs are
+/// set from ```controls```, pairs of a port index and a value, and otherwise start at their
+/// default value or lower bound.
+pub fn render(descriptor: &PluginDescriptor,
+              input: &Wav,
+              controls: &[(usize, Data)],
+              block_size: usize)
+              -> Result<Wav, HarnessError> {
+    let ports = 0..descriptor.ports.len();
+    let audio_inputs: Vec<usize> = ports.clone()
+        .filter(|&port| matches!(descriptor.ports[port].desc, PortDescriptor::AudioInput))
+        .collect();
+    let audio_outputs: Vec<usize> = ports
+        .filter(|&port| matches!(descriptor.ports[port].desc, PortDescriptor::AudioOutput))
+        .collect();
+    if !audio_inputs.is_empty() && audio_inputs.len() != input.channels.len() {
+        return Err(HarnessError::ChannelMismatch {
+            inputs: audio_inputs.len(),
+            channels: input.channels.len(),
+        });
+    }
+    if let Some(port) = descriptor.ports.iter().find(|port| {
+        matches!(port.desc, PortDescriptor::Invalid)
+    }) {
+        return Err(HarnessError::InvalidPort(port.name));
+    }
+    for &(port, _) in controls {
+        match descriptor.ports.get(port).map(|port| port.desc) {
+            Some(PortDescriptor::ControlInput) => (),
+            _ => return Err(HarnessError::InvalidControl(port)),
+        }
+    }
+
+    let block_size = block_size.max(1);
+    let mut host = TestHost::new(descriptor, u64::from(input.sample_rate), block_size)?;
+    for &(port, value) in controls {
+        host.set_control(port, value);
+    }
+
+    let length = input.len();
+    let mut outputs = vec![Vec::with_capacity(length); audio_outputs.len()];
+    host.activate();
+    let mut start = 0;
+    while start < length {
+        let end = length.min(start + block_size);
+        for (&port, channel) in audio_inputs.iter().zip(input.channels.iter()) {
+            host.input_mut(port)[..end - start].copy_from_slice(&channel[start..end]);
+        }
+        host.run(end - start);
+        for (&port, channel) in audio_outputs.iter().zip(outputs.iter_mut()) {
+            channel.extend_from_slice(&host.output(port)[..end - start]);
+        }
+        start = end;
+    }
+    host.deactivate();
+
+    Ok(Wav {
+        sample_rate: input.sample_rate,
+        channels: outputs,
+    })
+}
+
+/// Reads the WAV file at ```input```, runs it through the plugin with ```render``` and writes
+/// the result to the WAV file at ```output```.
+pub fn render_file<P, Q>(descriptor: &PluginDescriptor,
+                         input: P,
+                         output: Q,
+                         controls: &[(usize, Data)],
+                         block_size: usize)
+                         -> Result<(), HarnessError>
+    where P: AsRef<Path>,
+          Q: AsRef<Path>
+{
+    let input = wav::read(input)?;
+    let rendered = render(descriptor, &input, controls, block_size)?;
+    wav::write(output, &rendered)?;
+    Ok(())
+}
+
+// Returns the first output of the last block which is a NaN or an infinity.
+fn non_finite_output(descriptor: &PluginDescriptor,
+                     host: &TestHost,
+                     block_size: usize)
+                     -> Option<FailureKind> {
+    for (port, desc) in descriptor.ports.iter().map(|port| port.desc).enumerate() {
+        let values = match desc {
+            PortDescriptor::AudioOutput => &host.output(port)[..block_size],
+            PortDescriptor::ControlOutput => &[host.control(port)][..],
+            _ => continue,
+        };
+        if let Some(&value) = values.iter().find(|value| !value.is_finite()) {
+            return Some(FailureKind::NonFinite { port, value });
+        }
+    }
+    None
+}
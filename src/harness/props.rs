@@ -0,0 +1,246 @@
+//! Property-based checks of plugins against their port hints.
+//!
+//! ```check``` runs a plugin many times with random control values, sample rates, block sizes and
+//! input audio, and reports the first case in which it panicked or produced a NaN or an infinity.
+//! Control values are drawn from the range each port declares, honouring its hints, so a failure
+//! is something a host could actually trigger:
+//!
+//! ```no_run
+//! # use ladspa::harness::props::{check, CheckConfig};
+//! # fn filter_descriptor() -> ladspa::PluginDescriptor { unimplemented!() }
+//! #[test]
+//! fn filter_stays_finite() {
+//!     if let Err(failure) = check(&filter_descriptor(), &CheckConfig::default()) {
+//!         panic!("{}", failure);
+//!     }
+//! }
+//! ```
+//!
+//! Each case is reproducible from its seed. To generate values with ```proptest``` or
+//! ```quickcheck``` instead, map a uniformly distributed number through ```control_value```.
+
+use std::any::Any;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+use super::super::{Data, Port, PluginDescriptor, PortDescriptor};
+use super::{non_finite_output, TestHost};
+
+/// Maps ```unit```, between 0 and 1, to a value within the range of ```port``` at
+/// ```sample_rate```, honouring its hints:
+///
+/// * toggled ports get 0 below 0.5 and 1 above it,
+/// * logarithmic ports with positive bounds are covered evenly on a logarithmic scale,
+/// * integer ports get whole numbers, each about equally likely,
+/// * sample rate ports have their bounds multiplied by ```sample_rate```.
+///
+/// Missing bounds are replaced by a range one unit wide next to the other bound, or by -1 to 1.
+pub fn control_value(port: &Port, sample_rate: u64, unit: f64) -> Data {
+    let range = port.range(sample_rate);
+    let unit = unit.clamp(0.0, 1.0);
+    if range.integer && !range.toggled {
+        // Rounding to the nearest would make the ends half as likely as the values between.
+        let (lower, upper) = (f64::from(range.lower.min(range.upper)).ceil(),
+                              f64::from(range.lower.max(range.upper)).floor());
+        let value = if upper < lower {
+            lower
+        } else {
+            (lower + (unit * (upper - lower + 1.0)).floor()).min(upper)
+        };
+        return value as Data;
+    }
+    range.from_normalized(unit as Data)
+}
+
+/// Settings for ```check```.
+#[derive(Clone, Debug)]
+pub struct CheckConfig {
+    /// The number of random cases to run.
+    pub cases: usize,
+    /// The seed of the first case. Case ```n``` uses ```seed + n```.
+    pub seed: u64,
+    /// The sample rates to pick from.
+    pub sample_rates: Vec<u64>,
+    /// The largest block size to run. Each block gets a random size between 1 and this.
+    pub max_block_size: usize,
+    /// The number of blocks to run in each case.
+    pub blocks: usize,
+}
+
+impl Default for CheckConfig {
+    fn default() -> CheckConfig {
+        CheckConfig {
+            cases: 64,
+            seed: 0,
+            sample_rates: vec![22050, 44100, 48000, 96000, 192000],
+            max_block_size: 4096,
+            blocks: 8,
+        }
+    }
+}
+
+/// What went wrong in a failed case.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FailureKind {
+    /// The plugin panicked, with this message.
+    Panic(String),
+    /// An output port held a NaN or an infinity after running.
+    NonFinite {
+        /// The index of the output port.
+        port: usize,
+        /// The offending value.
+        value: Data,
+    },
+}
+
+impl fmt::Display for FailureKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FailureKind::Panic(ref msg) => write!(f, "the plugin panicked: {}", msg),
+            FailureKind::NonFinite { port, value } => {
+                write!(f, "port {} output {}", port, value)
+            }
+        }
+    }
+}
+
+/// A case in which the plugin misbehaved, with everything needed to reproduce it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Failure {
+    /// The seed the case was generated from.
+    pub seed: u64,
+    /// The sample rate the plugin was instantiated at.
+    pub sample_rate: u64,
+    /// The value of every control input, as pairs of a port index and a value.
+    pub controls: Vec<(usize, Data)>,
+    /// The sizes of the blocks run, up to and including the failing one.
+    pub block_sizes: Vec<usize>,
+    /// What went wrong.
+    pub kind: FailureKind,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "case with seed {} failed at {} Hz, controls {:?} and block sizes {:?}: {}",
+               self.seed,
+               self.sample_rate,
+               self.controls,
+               self.block_sizes,
+               self.kind)
+    }
+}
+
+/// Runs ```config.cases``` random cases through the plugin described by ```descriptor```,
+/// stopping at the first failure.
+///
+/// Each case instantiates the plugin at a random sample rate, sets every control input to a
+/// random value within its range, then runs ```config.blocks``` blocks of random sizes over white
+/// noise. A case fails if the plugin panics or if any audio or control output is not finite.
+/// Panic messages are still printed by the panic hook.
+pub fn check(descriptor: &PluginDescriptor, config: &CheckConfig) -> Result<(), Failure> {
+    for case in 0..config.cases as u64 {
+        run_case(descriptor, config, config.seed.wrapping_add(case))?;
+    }
+    Ok(())
+}
+
+/// Runs the case of ```check``` generated from ```seed``` alone, to reproduce a failure.
+pub fn run_case(descriptor: &PluginDescriptor,
+                config: &CheckConfig,
+                seed: u64)
+                -> Result<(), Failure> {
+    let mut rng = Rng::new(seed);
+    let sample_rate = if config.sample_rates.is_empty() {
+        44100
+    } else {
+        config.sample_rates[rng.below(config.sample_rates.len())]
+    };
+    let controls: Vec<(usize, Data)> = descriptor.ports
+        .iter()
+        .enumerate()
+        .filter(|&(_, port)| matches!(port.desc, PortDescriptor::ControlInput))
+        .map(|(index, port)| (index, control_value(port, sample_rate, rng.unit())))
+        .collect();
+    let mut failure = Failure {
+        seed,
+        sample_rate,
+        controls,
+        block_sizes: Vec::new(),
+        kind: FailureKind::Panic(String::new()),
+    };
+
+    let max_block_size = config.max_block_size.max(1);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| -> Option<FailureKind> {
+        let mut host = match TestHost::new(descriptor, sample_rate, max_block_size) {
+            Ok(host) => host,
+            // Refusing a sample rate is allowed.
+            Err(_) => return None,
+        };
+        for &(port, value) in &failure.controls {
+            host.set_control(port, value);
+        }
+        host.activate();
+        for _ in 0..config.blocks {
+            let block_size = 1 + rng.below(max_block_size);
+            failure.block_sizes.push(block_size);
+            for (index, port) in descriptor.ports.iter().enumerate() {
+                if let PortDescriptor::AudioInput = port.desc {
+                    for sample in &mut host.input_mut(index)[..block_size] {
+                        *sample = rng.unit() as Data * 2.0 - 1.0;
+                    }
+                }
+            }
+            host.run(block_size);
+            if let Some(kind) = non_finite_output(descriptor, &host, block_size) {
+                return Some(kind);
+            }
+        }
+        host.deactivate();
+        None
+    }));
+
+    let kind = match result {
+        Ok(None) => return Ok(()),
+        Ok(Some(kind)) => kind,
+        Err(payload) => FailureKind::Panic(panic_message(&*payload)),
+    };
+    failure.kind = kind;
+    Err(failure)
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "unknown panic payload".to_string()
+    }
+}
+
+// A small xorshift generator, so cases don't depend on an external crate.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        // Spread consecutive seeds apart; xorshift needs a non-zero state.
+        Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    // Uniform in [0, 1).
+    fn unit(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
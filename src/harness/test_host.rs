@@ -0,0 +1,220 @@
+use std::mem;
+use std::thread;
+
+use super::super::{Context, Data, InstantiateError, Plugin, PluginDescriptor, PortConnection,
+                   PortData, PortDescriptor, HINT_SAMPLE_RATE};
+use super::super::util::DenormalGuard;
+
+/// A host for unit testing plugins written with this crate, without going through the C API.
+///
+/// Like ```host::PluginInstance```, each port is connected to a buffer owned by the host: audio
+/// ports get one sample per frame of the block size, control ports a single value. Control inputs
+/// start at their default value, or at their lower bound if they have none. Panics in the plugin
+/// are not caught, so assertions inside it fail the test.
+///
+/// ```no_run
+/// # use ladspa::harness::TestHost;
+/// # fn get_ladspa_descriptor(_: u64) -> Option<ladspa::PluginDescriptor> { None }
+/// let descriptor = get_ladspa_descriptor(0).unwrap();
+/// let mut host = TestHost::new(&descriptor, 44100, 64).unwrap();
+/// host.set_control(2, 0.5);
+/// host.input_mut(0).copy_from_slice(&[1.0; 64]);
+/// host.activate();
+/// host.run(64);
+/// assert!(host.output(1).iter().all(|&sample| sample == 0.5));
+/// ```
+pub struct TestHost<'d> {
+    descriptor: &'d PluginDescriptor,
+    plugin: Box<dyn Plugin + Send>,
+    sample_rate: u64,
+    buffers: Vec<Vec<Data>>,
+    block_size: usize,
+    active: bool,
+}
+
+impl<'d> TestHost<'d> {
+    /// Instantiates the plugin described by ```descriptor``` at ```sample_rate```, with buffers
+    /// of ```block_size``` samples.
+    pub fn new(descriptor: &'d PluginDescriptor,
+               sample_rate: u64,
+               block_size: usize)
+               -> Result<TestHost<'d>, InstantiateError> {
+        let buffers = descriptor.ports
+            .iter()
+            .map(|port| match port.desc {
+                PortDescriptor::AudioInput | PortDescriptor::AudioOutput => vec![0.0; block_size],
+                _ => {
+                    let lower = port.lower_bound.map(|lower| {
+                        if port.hint.is_some_and(|hint| hint.contains(HINT_SAMPLE_RATE)) {
+                            lower * sample_rate as Data
+                        } else {
+                            lower
+                        }
+                    });
+                    vec![port.default_at(sample_rate).or(lower).unwrap_or(0.0)]
+                }
+            })
+            .collect();
+        let mut plugin = (descriptor.new)(descriptor, sample_rate)?;
+        plugin.initialize(sample_rate, Some(block_size));
+        Ok(TestHost {
+            descriptor,
+            plugin,
+            sample_rate,
+            buffers,
+            block_size,
+            active: false,
+        })
+    }
+
+    /// The descriptor the plugin was instantiated from.
+    pub fn descriptor(&self) -> &'d PluginDescriptor {
+        self.descriptor
+    }
+
+    /// The sample rate the plugin was instantiated at.
+    pub fn sample_rate(&self) -> u64 {
+        self.sample_rate
+    }
+
+    /// The largest number of samples which can be processed by a single call to ```run```.
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// Returns whether ```activate``` has been called since the last ```deactivate```.
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Returns the current value of control port ```port```.
+    pub fn control(&self, port: usize) -> Data {
+        self.buffers[port][0]
+    }
+
+    /// Sets the value of control input ```port```, to be read on the next call to ```run```.
+    /// Panics if ```port``` is not a control input.
+    pub fn set_control(&mut self, port: usize, value: Data) {
+        self.expect(port, PortDescriptor::ControlInput, "a control input");
+        self.buffers[port][0] = value;
+    }
+
+    /// Returns where the current value of control port ```port``` is in its range, between 0
+    /// and 1. See ```Port::range```.
+    pub fn normalized(&self, port: usize) -> Data {
+        self.descriptor.ports[port].range(self.sample_rate).to_normalized(self.control(port))
+    }
+
+    /// Sets control input ```port``` to the value at ```normalized```, between 0 and 1, in its
+    /// range, as a host slider would. Panics if ```port``` is not a control input.
+    pub fn set_normalized(&mut self, port: usize, normalized: Data) {
+        let value = self.descriptor.ports[port].range(self.sample_rate).from_normalized(normalized);
+        self.set_control(port, value);
+    }
+
+    /// Returns the buffer connected to audio input ```port``` so it can be filled before ```run```.
+    /// Panics if ```port``` is not an audio input.
+    pub fn input_mut(&mut self, port: usize) -> &mut [Data] {
+        self.expect(port, PortDescriptor::AudioInput, "an audio input");
+        &mut self.buffers[port]
+    }
+
+    /// Returns the buffer connected to audio output ```port```. Panics if ```port``` is not an
+    /// audio output.
+    pub fn output(&self, port: usize) -> &[Data] {
+        self.expect(port, PortDescriptor::AudioOutput, "an audio output");
+        &self.buffers[port]
+    }
+
+    /// Returns the buffer connected to audio output ```port``` so it can be filled before
+    /// ```run_adding```. Panics if ```port``` is not an audio output.
+    pub fn output_mut(&mut self, port: usize) -> &mut [Data] {
+        self.expect(port, PortDescriptor::AudioOutput, "an audio output");
+        &mut self.buffers[port]
+    }
+
+    fn expect(&self, port: usize, desc: PortDescriptor, kind: &str) {
+        let actual = self.descriptor.ports[port].desc;
+        assert!(mem::discriminant(&actual) == mem::discriminant(&desc),
+                "port {} is not {}",
+                port,
+                kind);
+    }
+
+    /// Moves the instance to ```sample_rate```, deactivating it if needed and calling
+    /// ```Plugin::initialize``` as a host changing its rate would. Control values are kept as
+    /// they are. Activate the instance again to carry on running it.
+    pub fn set_sample_rate(&mut self, sample_rate: u64) {
+        self.deactivate();
+        self.sample_rate = sample_rate;
+        self.plugin.initialize(sample_rate, Some(self.block_size));
+    }
+
+    /// Activates the instance. Does nothing if it is already active.
+    pub fn activate(&mut self) {
+        if !self.active {
+            self.plugin.activate();
+            self.active = true;
+        }
+    }
+
+    /// Processes ```sample_count``` samples with ```Plugin::run_with_context```. Panics if the
+    /// instance is not active or if ```sample_count``` exceeds the block size.
+    pub fn run(&mut self, sample_count: usize) {
+        let context = Context::new(self.descriptor, self.sample_rate);
+        self.process(sample_count,
+                     |plugin, ports| plugin.run_with_context(context, sample_count, ports));
+    }
+
+    /// Processes ```sample_count``` samples with ```Plugin::run_adding```, mixing the output into
+    /// the current contents of the output buffers. Panics if the instance is not active or if
+    /// ```sample_count``` exceeds the block size.
+    pub fn run_adding(&mut self, gain: Data, sample_count: usize) {
+        self.process(sample_count,
+                     |plugin, ports| plugin.run_adding(gain, sample_count, ports));
+    }
+
+    fn process<F>(&mut self, sample_count: usize, f: F)
+        where F: for<'a> FnOnce(&mut (dyn Plugin + Send), &mut [PortConnection<'a>])
+    {
+        assert!(self.active, "the plugin must be activated before it is run");
+        assert!(sample_count <= self.block_size, "sample count exceeds block size");
+
+        let mut connections: Vec<PortConnection> = self.descriptor
+            .ports
+            .iter()
+            .zip(self.buffers.iter_mut())
+            .map(|(port, buffer)| {
+                let data = match port.desc {
+                    PortDescriptor::AudioInput => PortData::AudioInput(&buffer[..sample_count]),
+                    PortDescriptor::AudioOutput => {
+                        PortData::AudioOutput(&mut buffer[..sample_count])
+                    }
+                    PortDescriptor::ControlInput => PortData::ControlInput(&buffer[0]),
+                    _ => PortData::ControlOutput(&mut buffer[0]),
+                };
+                PortConnection { port: *port, data }
+            })
+            .collect();
+
+        let _guard = if self.plugin.flush_denormals() { Some(DenormalGuard::new()) } else { None };
+        f(&mut *self.plugin, &mut connections);
+    }
+
+    /// Deactivates the instance. Does nothing if it is not active.
+    pub fn deactivate(&mut self) {
+        if self.active {
+            self.plugin.deactivate();
+            self.active = false;
+        }
+    }
+}
+
+impl<'d> Drop for TestHost<'d> {
+    fn drop(&mut self) {
+        // Don't call into a plugin which just failed an assertion.
+        if !thread::panicking() {
+            self.deactivate();
+        }
+    }
+}
@@ -0,0 +1,198 @@
+//! Minimal reading and writing of WAV files.
+//!
+//! Reads integer PCM of 8, 16, 24 or 32 bits and floating point of 32 or 64 bits, in plain or
+//! extensible format. Writes 32 bit floating point, so that rendering doesn't lose precision.
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use Data;
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_FLOAT: u16 = 3;
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// The ways in which reading a WAV file can fail.
+#[derive(Debug)]
+pub enum WavError {
+    /// The file could not be read or written.
+    Io(io::Error),
+    /// The file is not a WAV file, or is damaged.
+    Malformed(&'static str),
+    /// The file uses a sample format which isn't supported.
+    Unsupported { format: u16, bits: u16 },
+}
+
+impl fmt::Display for WavError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WavError::Io(ref err) => write!(f, "{}", err),
+            WavError::Malformed(what) => write!(f, "malformed WAV file: {}", what),
+            WavError::Unsupported { format, bits } => {
+                write!(f, "unsupported WAV sample format {} with {} bits", format, bits)
+            }
+        }
+    }
+}
+
+impl Error for WavError {}
+
+impl From<io::Error> for WavError {
+    fn from(err: io::Error) -> WavError {
+        WavError::Io(err)
+    }
+}
+
+/// Audio data along with its sample rate, stored one ```Vec``` per channel.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Wav {
+    /// The sample rate in Hz.
+    pub sample_rate: u32,
+    /// The samples of each channel. All channels have the same length.
+    pub channels: Vec<Vec<Data>>,
+}
+
+impl Wav {
+    /// The number of samples in each channel.
+    pub fn len(&self) -> usize {
+        self.channels.first().map_or(0, Vec::len)
+    }
+
+    /// Returns whether there are no samples.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+// Converts one sample to a float in [-1, 1].
+fn decode(sample: &[u8], format: u16) -> Data {
+    match (format, sample.len()) {
+        (FORMAT_PCM, 1) => (f32::from(sample[0]) - 128.0) / 128.0,
+        (FORMAT_PCM, 2) => f32::from(i16::from_le_bytes([sample[0], sample[1]])) / 32_768.0,
+        (FORMAT_PCM, 3) => {
+            // Place the 24 bits at the top of an i32 to sign extend them.
+            let value = i32::from_le_bytes([0, sample[0], sample[1], sample[2]]) >> 8;
+            value as f32 / 8_388_608.0
+        }
+        (FORMAT_PCM, 4) => {
+            let value = i32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]]);
+            (f64::from(value) / 2_147_483_648.0) as f32
+        }
+        (FORMAT_FLOAT, 4) => f32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]]),
+        (_, _) => {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(sample);
+            f64::from_le_bytes(bytes) as f32
+        }
+    }
+}
+
+/// Reads the WAV file at ```path```.
+pub fn read<P: AsRef<Path>>(path: P) -> Result<Wav, WavError> {
+    let mut bytes = Vec::new();
+    BufReader::new(File::open(path)?).read_to_end(&mut bytes)?;
+
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err(WavError::Malformed("missing RIFF/WAVE header"));
+    }
+
+    let mut fmt = None;
+    let mut data = None;
+    let mut at = 12;
+    while at + 8 <= bytes.len() {
+        let id = &bytes[at..at + 4];
+        let size = u32_at(&bytes, at + 4) as usize;
+        let body = at + 8;
+        let end = body.saturating_add(size).min(bytes.len());
+        match id {
+            b"fmt " => fmt = Some(&bytes[body..end]),
+            b"data" => data = Some(&bytes[body..end]),
+            _ => {}
+        }
+        // Chunks are padded to an even size.
+        at = body.saturating_add(size).saturating_add(size & 1);
+    }
+    let fmt = fmt.ok_or(WavError::Malformed("missing fmt chunk"))?;
+    let data = data.ok_or(WavError::Malformed("missing data chunk"))?;
+    if fmt.len() < 16 {
+        return Err(WavError::Malformed("fmt chunk too short"));
+    }
+
+    let mut format = u16_at(fmt, 0);
+    let channels = usize::from(u16_at(fmt, 2));
+    let sample_rate = u32_at(fmt, 4);
+    let bits = u16_at(fmt, 14);
+    if format == FORMAT_EXTENSIBLE {
+        if fmt.len() < 26 {
+            return Err(WavError::Malformed("extensible fmt chunk too short"));
+        }
+        // The sub format GUID starts with the format code.
+        format = u16_at(fmt, 24);
+    }
+    let supported = match format {
+        FORMAT_PCM => bits == 8 || bits == 16 || bits == 24 || bits == 32,
+        FORMAT_FLOAT => bits == 32 || bits == 64,
+        _ => false,
+    };
+    if !supported {
+        return Err(WavError::Unsupported { format, bits });
+    }
+    if channels == 0 {
+        return Err(WavError::Malformed("no channels"));
+    }
+
+    let width = usize::from(bits / 8);
+    let frames = data.len() / (width * channels);
+    let mut wav = Wav {
+        sample_rate,
+        channels: vec![Vec::with_capacity(frames); channels],
+    };
+    for frame in data.chunks_exact(width * channels) {
+        for (channel, sample) in wav.channels.iter_mut().zip(frame.chunks_exact(width)) {
+            channel.push(decode(sample, format));
+        }
+    }
+    Ok(wav)
+}
+
+/// Writes ```wav``` to ```path``` as 32 bit floating point samples.
+pub fn write<P: AsRef<Path>>(path: P, wav: &Wav) -> Result<(), WavError> {
+    let channels = wav.channels.len() as u16;
+    let frames = wav.len();
+    let data_size = frames * usize::from(channels) * 4;
+    if data_size > u32::MAX as usize - 50 {
+        return Err(WavError::Malformed("too much audio for a WAV file"));
+    }
+
+    let mut out = BufWriter::new(File::create(path)?);
+    out.write_all(b"RIFF")?;
+    out.write_all(&(data_size as u32 + 36).to_le_bytes())?;
+    out.write_all(b"WAVEfmt ")?;
+    out.write_all(&16u32.to_le_bytes())?;
+    out.write_all(&FORMAT_FLOAT.to_le_bytes())?;
+    out.write_all(&channels.to_le_bytes())?;
+    out.write_all(&wav.sample_rate.to_le_bytes())?;
+    out.write_all(&(wav.sample_rate * u32::from(channels) * 4).to_le_bytes())?;
+    out.write_all(&(channels * 4).to_le_bytes())?;
+    out.write_all(&32u16.to_le_bytes())?;
+    out.write_all(b"data")?;
+    out.write_all(&(data_size as u32).to_le_bytes())?;
+    for frame in 0..frames {
+        for channel in &wav.channels {
+            out.write_all(&channel[frame].to_le_bytes())?;
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
@@ -0,0 +1,76 @@
+// Opening shared libraries with the dynamic loader of the platform: dlopen on Unix, LoadLibrary
+// on Windows.
+
+#[cfg(unix)]
+pub use self::unix::{close, open, symbol};
+#[cfg(windows)]
+pub use self::windows::{close, open, symbol};
+
+#[cfg(unix)]
+mod unix {
+    use std::ffi::{CStr, CString};
+    use std::path::Path;
+
+    use libc::{self, c_char, c_void};
+
+    unsafe fn dlerror_string() -> String {
+        let err = libc::dlerror();
+        if err.is_null() {
+            String::from("unknown error")
+        } else {
+            CStr::from_ptr(err).to_string_lossy().into_owned()
+        }
+    }
+
+    // Returns the handle of the library at `path`, or the message of the loader.
+    pub unsafe fn open(path: &Path) -> Result<*mut c_void, String> {
+        let c_path = CString::new(path.to_string_lossy().into_owned())
+            .map_err(|e| e.to_string())?;
+        let handle = libc::dlopen(c_path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL);
+        if handle.is_null() { Err(dlerror_string()) } else { Ok(handle) }
+    }
+
+    // Looks up `name`, which must end with a nul byte.
+    pub unsafe fn symbol(handle: *mut c_void, name: &[u8]) -> Option<*mut c_void> {
+        let symbol = libc::dlsym(handle, name.as_ptr() as *const c_char);
+        if symbol.is_null() { None } else { Some(symbol) }
+    }
+
+    pub unsafe fn close(handle: *mut c_void) {
+        libc::dlclose(handle);
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::io;
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::Path;
+
+    use libc::{c_char, c_int, c_void};
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn LoadLibraryW(name: *const u16) -> *mut c_void;
+        fn GetProcAddress(module: *mut c_void, name: *const c_char) -> *mut c_void;
+        fn FreeLibrary(module: *mut c_void) -> c_int;
+    }
+
+    // Returns the handle of the library at `path`, or the message of the loader. Wide strings
+    // are used so that paths outside the current code page work.
+    pub unsafe fn open(path: &Path) -> Result<*mut c_void, String> {
+        let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+        let handle = LoadLibraryW(wide.as_ptr());
+        if handle.is_null() { Err(io::Error::last_os_error().to_string()) } else { Ok(handle) }
+    }
+
+    // Looks up `name`, which must end with a nul byte.
+    pub unsafe fn symbol(handle: *mut c_void, name: &[u8]) -> Option<*mut c_void> {
+        let symbol = GetProcAddress(handle, name.as_ptr() as *const c_char);
+        if symbol.is_null() { None } else { Some(symbol) }
+    }
+
+    pub unsafe fn close(handle: *mut c_void) {
+        FreeLibrary(handle);
+    }
+}
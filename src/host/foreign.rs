@@ -0,0 +1,252 @@
+// c_ulong is only 32 bits wide on some platforms, so widening it is not always a no-op.
+#![allow(clippy::useless_conversion)]
+
+use std::ffi::CStr;
+use std::ptr;
+
+use libc::{c_char, c_ulong};
+
+use ffi::ladspa_h;
+use super::{decode_port_descriptor, LoadedPlugin};
+use super::super::lock::Lock;
+use super::super::{ControlHint, Data, DefaultValue, InstantiateError, Plugin, PluginDescriptor,
+                   Port, PortConnection, PortData, Properties, Unit};
+
+// A C descriptor wrapped into a `PluginDescriptor`, with the strings copied out of it.
+struct Foreign {
+    descriptor: *const ladspa_h::Descriptor,
+    label: &'static str,
+    name: &'static str,
+    maker: &'static str,
+    copyright: &'static str,
+    port_names: Vec<&'static str>,
+    // Keeps the library of a `LoadedPlugin` loaded for as long as the program runs.
+    _plugin: Option<LoadedPlugin>,
+}
+
+unsafe impl Send for Foreign {}
+
+// Every descriptor wrapped so far. `PluginConstructor` is a plain function, so `new_foreign`
+// finds the C descriptor to instantiate here, by the address of the label it was given: each
+// wrapped descriptor has its own copy of the label.
+static FOREIGN: Lock<Vec<Foreign>> = Lock::new(Vec::new());
+
+unsafe fn leak_str(s: *const c_char) -> &'static str {
+    if s.is_null() {
+        ""
+    } else {
+        Box::leak(CStr::from_ptr(s).to_string_lossy().into_owned().into_boxed_str())
+    }
+}
+
+fn default_value(bits: i32) -> Option<DefaultValue> {
+    match bits & ladspa_h::HINT_DEFAULT_MASK {
+        ladspa_h::HINT_DEFAULT_MINIMUM => Some(DefaultValue::Minimum),
+        ladspa_h::HINT_DEFAULT_LOW => Some(DefaultValue::Low),
+        ladspa_h::HINT_DEFAULT_MIDDLE => Some(DefaultValue::Middle),
+        ladspa_h::HINT_DEFAULT_HIGH => Some(DefaultValue::High),
+        ladspa_h::HINT_DEFAULT_MAXIMUM => Some(DefaultValue::Maximum),
+        ladspa_h::HINT_DEFAULT_0 => Some(DefaultValue::Value0),
+        ladspa_h::HINT_DEFAULT_1 => Some(DefaultValue::Value1),
+        ladspa_h::HINT_DEFAULT_100 => Some(DefaultValue::Value100),
+        ladspa_h::HINT_DEFAULT_440 => Some(DefaultValue::Value440),
+        _ => None,
+    }
+}
+
+fn port(name: &'static str, desc: i32, hint: ladspa_h::PortRangeHint) -> Port {
+    let bits = hint.hint_descriptor;
+    let control_hint = ControlHint::from_bits_truncate(bits);
+    Port {
+        name,
+        desc: decode_port_descriptor(desc),
+        hint: if control_hint.is_empty() { None } else { Some(control_hint) },
+        default: default_value(bits),
+        lower_bound: if bits & ladspa_h::HINT_BOUNDED_BELOW != 0 {
+            Some(hint.lower_bound)
+        } else {
+            None
+        },
+        upper_bound: if bits & ladspa_h::HINT_BOUNDED_ABOVE != 0 {
+            Some(hint.upper_bound)
+        } else {
+            None
+        },
+        group: None,
+        scale_points: &[],
+        unit: Unit::from_name(name),
+    }
+}
+
+fn descriptor_of(foreign: &Foreign) -> PluginDescriptor {
+    let raw = unsafe { &*foreign.descriptor };
+    let ports = foreign.port_names
+        .iter()
+        .enumerate()
+        .map(|(index, &name)| unsafe {
+            port(name, *raw.port_descriptors.add(index), *raw.port_range_hints.add(index))
+        })
+        .collect();
+    PluginDescriptor {
+        unique_id: u64::from(raw.unique_id),
+        label: foreign.label,
+        properties: Properties::from_bits_truncate(raw.properties),
+        name: foreign.name,
+        maker: foreign.maker,
+        copyright: foreign.copyright,
+        category: None,
+        ports,
+        presets: Vec::new(),
+        new: new_foreign,
+    }
+}
+
+// Wraps `descriptor`, reusing the strings of an earlier wrapping of the same descriptor.
+unsafe fn wrap(descriptor: *const ladspa_h::Descriptor, plugin: Option<LoadedPlugin>)
+               -> PluginDescriptor {
+    let mut foreign = FOREIGN.lock();
+    if let Some(existing) = foreign.iter().find(|existing| existing.descriptor == descriptor) {
+        return descriptor_of(existing);
+    }
+    let raw = &*descriptor;
+    let port_names = (0..raw.port_count as usize)
+        .map(|index| leak_str(*raw.port_names.add(index)))
+        .collect();
+    // Give every wrapped descriptor a label of its own, even if it is empty, so that
+    // `new_foreign` can tell them apart.
+    let label = leak_str(raw.label);
+    let label = if label.is_empty() { Box::leak(Box::from(" ")) } else { label };
+    foreign.push(Foreign {
+        descriptor,
+        label,
+        name: leak_str(raw.name),
+        maker: leak_str(raw.maker),
+        copyright: leak_str(raw.copyright),
+        port_names,
+        _plugin: plugin,
+    });
+    descriptor_of(foreign.last().unwrap())
+}
+
+/// Converts a descriptor obtained from a C plugin library into a ```PluginDescriptor```, so that
+/// it can be inspected and instantiated like a plugin written with this crate.
+///
+/// Instances created through ```PluginDescriptor::new``` forward every call to the C plugin,
+/// connecting its ports to the buffers passed to ```Plugin::run```. The strings of the descriptor
+/// are copied once per descriptor and never freed, like those of the plugins of this crate.
+///
+/// # Safety
+///
+/// ```descriptor``` must point to a valid LADSPA descriptor which stays valid, and its library
+/// loaded, for as long as the program runs. ```LoadedPlugin::to_descriptor``` takes care of that.
+pub unsafe fn wrap_descriptor(descriptor: *const ladspa_h::Descriptor) -> PluginDescriptor {
+    wrap(descriptor, None)
+}
+
+impl LoadedPlugin {
+    /// Converts the plugin into a ```PluginDescriptor```, so that it can be inspected and
+    /// instantiated like a plugin written with this crate. See ```wrap_descriptor```; the library
+    /// stays loaded for as long as the program runs.
+    pub fn to_descriptor(&self) -> PluginDescriptor {
+        unsafe { wrap(self.descriptor, Some(self.clone())) }
+    }
+}
+
+fn new_foreign(desc: &PluginDescriptor, sample_rate: u64)
+               -> Result<Box<dyn Plugin + Send>, InstantiateError> {
+    let descriptor = FOREIGN.lock()
+        .iter()
+        .find(|foreign| ptr::eq(foreign.label, desc.label))
+        .map(|foreign| foreign.descriptor)
+        .ok_or_else(|| InstantiateError::Other(String::from("not a wrapped C descriptor")))?;
+    let handle = unsafe { ((*descriptor).instantiate)(descriptor, sample_rate as c_ulong) };
+    if handle.is_null() {
+        return Err(InstantiateError::Other(String::from("the C plugin failed to instantiate")));
+    }
+    Ok(Box::new(ForeignPlugin { descriptor, handle }))
+}
+
+// An instance of a wrapped C plugin.
+struct ForeignPlugin {
+    descriptor: *const ladspa_h::Descriptor,
+    handle: ladspa_h::Handle,
+}
+
+// LADSPA instances may be moved between threads as long as they are not used concurrently.
+unsafe impl Send for ForeignPlugin {}
+
+impl ForeignPlugin {
+    fn raw(&self) -> &ladspa_h::Descriptor {
+        unsafe { &*self.descriptor }
+    }
+
+    // Connects the C plugin to the buffers of this block.
+    fn connect<'a>(&mut self, ports: &mut [PortConnection<'a>]) {
+        for (index, port) in ports.iter_mut().enumerate() {
+            let data = match port.data {
+                PortData::AudioInput(data) => data.as_ptr() as *mut Data,
+                PortData::AudioOutput(ref mut data) => data.as_mut_ptr(),
+                PortData::ControlInput(data) => data as *const Data as *mut Data,
+                PortData::ControlOutput(ref mut data) => &mut **data as *mut Data,
+            };
+            (self.raw().connect_port)(self.handle, index as c_ulong, data);
+        }
+    }
+}
+
+impl Plugin for ForeignPlugin {
+    fn activate(&mut self) {
+        if let Some(activate) = self.raw().activate {
+            activate(self.handle);
+        }
+    }
+
+    fn run<'a>(&mut self, sample_count: usize, ports: &mut [PortConnection<'a>]) {
+        self.connect(ports);
+        (self.raw().run)(self.handle, sample_count as c_ulong);
+    }
+
+    fn native_run_adding(&self) -> bool {
+        self.raw().run_adding.is_some() && self.raw().set_run_adding_gain.is_some()
+    }
+
+    fn run_adding<'a>(&mut self, gain: Data, sample_count: usize,
+                      ports: &mut [PortConnection<'a>]) {
+        match (self.raw().run_adding, self.raw().set_run_adding_gain) {
+            (Some(run_adding), Some(set_run_adding_gain)) => {
+                set_run_adding_gain(self.handle, gain);
+                self.connect(ports);
+                run_adding(self.handle, sample_count as c_ulong);
+            }
+            // Without native support, run into scratch buffers and mix those in.
+            _ => {
+                let saved: Vec<Vec<Data>> = ports.iter()
+                    .map(|port| match port.data {
+                        PortData::AudioOutput(ref data) => data.to_vec(),
+                        _ => Vec::new(),
+                    })
+                    .collect();
+                self.run(sample_count, ports);
+                for (port, saved) in ports.iter_mut().zip(saved) {
+                    if let PortData::AudioOutput(ref mut data) = port.data {
+                        for (out, old) in data.iter_mut().zip(saved) {
+                            *out = old + *out * gain;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn deactivate(&mut self) {
+        if let Some(deactivate) = self.raw().deactivate {
+            deactivate(self.handle);
+        }
+    }
+}
+
+impl Drop for ForeignPlugin {
+    fn drop(&mut self) {
+        (self.raw().cleanup)(self.handle);
+    }
+}
@@ -0,0 +1,561 @@
+use std::mem;
+
+use super::{HostError, InstanceState, LoadedPlugin, PluginInstance};
+use super::pool::{Job, Pool};
+use super::super::{Data, PortDescriptor};
+use super::super::dsp::delay::DelayLine;
+
+/// Identifies a plugin instance added to a ```Graph```.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NodeId(usize);
+
+// The longest latency difference between paths `Graph` compensates for, unless changed with
+// `set_max_latency`.
+const MAX_LATENCY: usize = 8192;
+
+// Carries the audio of output port `from_port` of node `from` to input port `to_port` of node
+// `to`, which comes later in the graph.
+struct Connection {
+    from: usize,
+    from_port: usize,
+    to: usize,
+    to_port: usize,
+    // How many samples the audio is delayed by to line up with the other inputs of `to`, and the
+    // line doing it, long enough for the maximum latency so that the delay can change without
+    // allocating or losing the audio it holds.
+    compensation: usize,
+    delay: DelayLine,
+}
+
+/// Plugin instances with their audio ports wired together, run with a single call.
+///
+/// Connections only go from a node to a later one, so the graph can't contain cycles. An input
+/// fed by several outputs receives their sum; inputs with no connection keep whatever was written
+/// to them through ```node_mut```.
+///
+/// Each node runs once every node feeding it has. With ```set_threads```, nodes which don't
+/// depend on each other, such as the effects of separate buses, run on several threads at once;
+/// the output is the same whatever the number of threads.
+///
+/// Plugins reporting a latency on a control output named "latency" delay the audio going through
+/// them. Where paths with different latencies meet, the earlier ones are delayed to line up with
+/// the latest, so that the audio stays in phase; ```latency``` tells how far behind the inputs of
+/// the graph the outputs of a node are. The latencies are read as each block runs, and compensated
+/// up to ```set_max_latency```, with delay lines allocated when connecting so that running never
+/// allocates.
+///
+/// ```no_run
+/// # use ladspa::host::{self, Graph};
+/// # fn main() -> Result<(), ladspa::host::HostError> {
+/// # let catalogue = host::discover();
+/// # let (splitter, delay, reverb) = (&catalogue.plugins()[0], &catalogue.plugins()[1],
+/// #                                  &catalogue.plugins()[2]);
+/// # let block = [0.0; 256];
+/// let mut graph = Graph::new(256);
+/// let split = graph.add(&splitter, 48000)?;
+/// let (left, right) = (graph.add(&delay, 48000)?, graph.add(&reverb, 48000)?);
+/// graph.connect(split, 1, left, 0);
+/// graph.connect(split, 2, right, 0);
+/// graph.activate();
+/// graph.node_mut(split).input_mut(0).copy_from_slice(&block);
+/// graph.run(256);
+/// # Ok(())
+/// # }
+/// ```
+pub struct Graph {
+    nodes: Vec<PluginInstance>,
+    connections: Vec<Connection>,
+    block_size: usize,
+    // The nodes in the order they run: each level of nodes only depends on earlier ones.
+    schedule: Vec<usize>,
+    // Where each level ends in `schedule`.
+    levels: Vec<usize>,
+    pool: Option<Pool>,
+    // Reused for every level, so that running doesn't allocate.
+    jobs: Vec<Job>,
+    // How far behind the inputs of the graph the audio reaching the inputs of each node is.
+    offsets: Vec<usize>,
+    max_latency: usize,
+}
+
+// `jobs` only points to the nodes during `run`, and instances may be moved between threads.
+unsafe impl Send for Graph {}
+
+fn is_port(plugin: &LoadedPlugin, port: usize, desc: PortDescriptor) -> bool {
+    port < plugin.port_count() &&
+    mem::discriminant(&plugin.port_descriptor(port)) == mem::discriminant(&desc)
+}
+
+// The audio ports of `plugin` of type `desc`, in order.
+fn audio_ports(plugin: &LoadedPlugin, desc: PortDescriptor) -> Vec<usize> {
+    (0..plugin.port_count()).filter(|&port| is_port(plugin, port, desc)).collect()
+}
+
+impl Graph {
+    /// Creates an empty graph processing blocks of up to ```block_size``` samples.
+    pub fn new(block_size: usize) -> Graph {
+        Graph {
+            nodes: Vec::new(),
+            connections: Vec::new(),
+            block_size,
+            schedule: Vec::new(),
+            levels: Vec::new(),
+            pool: None,
+            jobs: Vec::new(),
+            offsets: Vec::new(),
+            max_latency: MAX_LATENCY,
+        }
+    }
+
+    /// Runs nodes which don't depend on each other on up to ```threads``` threads, the one
+    /// calling ```run``` included. 1, the default, runs every node on the calling thread, and 0
+    /// is taken as 1.
+    ///
+    /// The threads wait for work between blocks, and are stopped when the graph is dropped or the
+    /// number of threads changes.
+    pub fn set_threads(&mut self, threads: usize) {
+        if threads != self.threads() {
+            self.pool = if threads > 1 { Some(Pool::new(threads - 1)) } else { None };
+        }
+    }
+
+    /// The number of threads nodes run on. See ```set_threads```.
+    pub fn threads(&self) -> usize {
+        self.pool.as_ref().map_or(1, Pool::threads)
+    }
+
+    /// Sets the longest difference in latency between paths meeting at a node which is compensated
+    /// for, 8192 samples by default; longer ones are only partly. Allocates the delay lines of
+    /// every connection again, silent, so call it before running.
+    pub fn set_max_latency(&mut self, max_latency: usize) {
+        self.max_latency = max_latency;
+        for connection in &mut self.connections {
+            connection.delay = DelayLine::new(max_latency + 1);
+        }
+    }
+
+    /// The longest difference in latency compensated for. See ```set_max_latency```.
+    pub fn max_latency(&self) -> usize {
+        self.max_latency
+    }
+
+    // Sorts the nodes into levels, each node one level after the latest of those feeding it.
+    fn update_schedule(&mut self) {
+        let mut level = vec![0; self.nodes.len()];
+        // Connections only go forwards, so the level of a node is final once its turn comes.
+        for from in 0..self.nodes.len() {
+            for connection in self.connections.iter().filter(|connection| connection.from == from) {
+                level[connection.to] = level[connection.to].max(level[from] + 1);
+            }
+        }
+        self.schedule = (0..self.nodes.len()).collect();
+        self.schedule.sort_by_key(|&node| level[node]);
+        self.levels.clear();
+        for (n, &node) in self.schedule.iter().enumerate() {
+            if self.schedule.get(n + 1).is_none_or(|&next| level[next] != level[node]) {
+                self.levels.push(n + 1);
+            }
+        }
+    }
+
+    /// The largest number of samples which can be processed by a single call to ```run```.
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// The number of nodes.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Whether the graph has no nodes.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Instantiates ```plugin``` at ```sample_rate``` and adds it after the existing nodes.
+    pub fn add(&mut self, plugin: &LoadedPlugin, sample_rate: u64) -> Result<NodeId, HostError> {
+        let instance = plugin.instantiate(sample_rate, self.block_size)?;
+        Ok(self.add_instance(instance))
+    }
+
+    /// Adds an existing instance after the existing nodes. Panics if its block size is smaller
+    /// than the graph's.
+    pub fn add_instance(&mut self, instance: PluginInstance) -> NodeId {
+        assert!(instance.block_size() >= self.block_size,
+                "instance block size is smaller than the graph's");
+        self.nodes.push(instance);
+        self.offsets.push(0);
+        self.update_schedule();
+        NodeId(self.nodes.len() - 1)
+    }
+
+    /// Feeds audio output ```from_port``` of ```from``` into audio input ```to_port``` of
+    /// ```to```. Panics if ```from``` was not added before ```to``` or if the ports are not an
+    /// audio output and an audio input.
+    pub fn connect(&mut self, from: NodeId, from_port: usize, to: NodeId, to_port: usize) {
+        assert!(from.0 < to.0, "connections must go from a node to a later one");
+        assert!(is_port(self.nodes[from.0].plugin(), from_port, PortDescriptor::AudioOutput),
+                "port {} is not an audio output",
+                from_port);
+        assert!(is_port(self.nodes[to.0].plugin(), to_port, PortDescriptor::AudioInput),
+                "port {} is not an audio input",
+                to_port);
+        self.connections.push(Connection {
+            from: from.0,
+            from_port,
+            to: to.0,
+            to_port,
+            compensation: 0,
+            delay: DelayLine::new(self.max_latency + 1),
+        });
+        self.update_schedule();
+    }
+
+    /// Removes every connection between ```from``` and ```to```.
+    pub fn disconnect(&mut self, from: NodeId, to: NodeId) {
+        self.connections.retain(|connection| connection.from != from.0 || connection.to != to.0);
+        self.update_schedule();
+    }
+
+    /// How many samples the outputs of node ```node``` lag behind the inputs of the graph: the
+    /// latency it reports plus that of the longest path feeding it, as of the last ```run```.
+    pub fn latency(&self, node: NodeId) -> usize {
+        self.offsets[node.0] + self.nodes[node.0].latency()
+    }
+
+    /// The instance of node ```node```.
+    pub fn node(&self, node: NodeId) -> &PluginInstance {
+        &self.nodes[node.0]
+    }
+
+    /// The instance of node ```node```, to set its control inputs or fill its unconnected audio
+    /// inputs.
+    pub fn node_mut(&mut self, node: NodeId) -> &mut PluginInstance {
+        &mut self.nodes[node.0]
+    }
+
+    /// Sets control input ```port``` of node ```node```.
+    pub fn set_control(&mut self, node: NodeId, port: usize, value: Data) {
+        self.nodes[node.0].set_control(port, value);
+    }
+
+    /// Sets control input ```port``` of node ```node``` to the value at ```normalized```, between
+    /// 0 and 1, in its range. See ```PluginInstance::set_normalized```.
+    pub fn set_normalized(&mut self, node: NodeId, port: usize, normalized: Data) {
+        self.nodes[node.0].set_normalized(port, normalized);
+    }
+
+    /// Activates every node.
+    pub fn activate(&mut self) {
+        for node in &mut self.nodes {
+            node.activate();
+        }
+    }
+
+    /// Deactivates every node.
+    pub fn deactivate(&mut self) {
+        for node in &mut self.nodes {
+            node.deactivate();
+        }
+    }
+
+    /// Returns the state of every node, in the order they were added.
+    pub fn save_state(&self) -> Vec<InstanceState> {
+        self.nodes.iter().map(PluginInstance::save_state).collect()
+    }
+
+    /// Restores the states returned by ```save_state```, in order. Extra states or nodes are
+    /// ignored.
+    pub fn restore_state(&mut self, states: &[InstanceState]) {
+        for (node, state) in self.nodes.iter_mut().zip(states) {
+            node.restore_state(state);
+        }
+    }
+
+    /// Runs every node over ```sample_count``` samples, passing audio along the connections
+    /// first. Panics if ```sample_count``` exceeds the block size.
+    pub fn run(&mut self, sample_count: usize) {
+        assert!(sample_count <= self.block_size, "sample count exceeds block size");
+        let mut start = 0;
+        for &end in &self.levels {
+            let level = &self.schedule[start..end];
+            for &to in level {
+                gather(&mut self.nodes,
+                       &mut self.connections,
+                       &mut self.offsets,
+                       to,
+                       sample_count);
+            }
+            match self.pool {
+                Some(ref pool) if level.len() > 1 => {
+                    self.jobs.clear();
+                    let nodes = self.nodes.as_mut_ptr();
+                    self.jobs.extend(level.iter().map(|&node| Job(unsafe { nodes.add(node) })));
+                    // The nodes of a level are all different, and only read by their job.
+                    unsafe {
+                        pool.run(&self.jobs, sample_count);
+                    }
+                    self.jobs.clear();
+                }
+                _ => {
+                    for &node in level {
+                        self.nodes[node].run(sample_count);
+                    }
+                }
+            }
+            start = end;
+        }
+    }
+}
+
+// Fills the connected inputs of node `to` with the sum of the outputs feeding them, in the order
+// of the connections so that the result doesn't depend on scheduling. The outputs are delayed to
+// line up with the one lagging furthest behind, going by the latencies the nodes feeding `to`
+// reported on this block, as far as the delay lines allow.
+fn gather(nodes: &mut [PluginInstance],
+          connections: &mut [Connection],
+          offsets: &mut [usize],
+          to: usize,
+          sample_count: usize) {
+    let (sources, rest) = nodes.split_at_mut(to);
+    let node = &mut rest[0];
+    let arrival = |connection: &Connection| {
+        offsets[connection.from] + sources[connection.from].latency()
+    };
+    let offset = connections.iter()
+        .filter(|connection| connection.to == to)
+        .map(arrival)
+        .max()
+        .unwrap_or(0);
+    // Clear every connected input before summing, as an input may have several sources.
+    for connection in connections.iter_mut().filter(|connection| connection.to == to) {
+        connection.compensation = offset - arrival(connection);
+        for sample in &mut node.input_mut(connection.to_port)[..sample_count] {
+            *sample = 0.0;
+        }
+    }
+    offsets[to] = offset;
+    for connection in connections.iter_mut().filter(|connection| connection.to == to) {
+        let output = sources[connection.from].output(connection.from_port);
+        let input = &mut node.input_mut(connection.to_port)[..sample_count];
+        // Undelayed audio goes through the line too, so that it holds the past samples needed
+        // when the compensation grows.
+        for (sample, &source) in input.iter_mut().zip(output) {
+            connection.delay.write(source);
+            // The sample just written is 1 sample back.
+            *sample += connection.delay.read(connection.compensation + 1);
+        }
+    }
+}
+
+/// Plugin instances run one after the other, like the effects of a rack: the audio outputs of
+/// each feed the audio inputs of the next.
+///
+/// When the number of channels changes along the chain, input ```n``` of a plugin receives
+/// output ```n``` modulo the number of outputs of the previous one, so a mono plugin followed by
+/// a stereo one feeds both of its inputs.
+///
+/// ```no_run
+/// # use ladspa::host::{self, Chain};
+/// # fn main() -> Result<(), ladspa::host::HostError> {
+/// # let catalogue = host::discover();
+/// # let (equalizer, compressor) = (&catalogue.plugins()[0], &catalogue.plugins()[1]);
+/// # let block = [0.0; 256];
+/// let mut rack = Chain::new(256);
+/// let eq = rack.push(&equalizer, 48000)?;
+/// rack.push(&compressor, 48000)?;
+/// rack.set_control(eq, 3, 2.5);
+/// rack.activate();
+/// rack.input_mut(0).copy_from_slice(&block);
+/// rack.run(256);
+/// let processed = rack.output(0);
+/// # Ok(())
+/// # }
+/// ```
+pub struct Chain {
+    graph: Graph,
+}
+
+impl Chain {
+    /// Creates an empty chain processing blocks of up to ```block_size``` samples.
+    pub fn new(block_size: usize) -> Chain {
+        Chain { graph: Graph::new(block_size) }
+    }
+
+    /// The number of plugins in the chain.
+    pub fn len(&self) -> usize {
+        self.graph.len()
+    }
+
+    /// Whether the chain is empty.
+    pub fn is_empty(&self) -> bool {
+        self.graph.is_empty()
+    }
+
+    /// Instantiates ```plugin``` at ```sample_rate``` and appends it to the chain.
+    pub fn push(&mut self, plugin: &LoadedPlugin, sample_rate: u64) -> Result<NodeId, HostError> {
+        let instance = plugin.instantiate(sample_rate, self.graph.block_size())?;
+        Ok(self.push_instance(instance))
+    }
+
+    /// Appends an existing instance to the chain. Panics if its block size is smaller than the
+    /// chain's.
+    pub fn push_instance(&mut self, instance: PluginInstance) -> NodeId {
+        let previous = self.graph.nodes.last().map(|node| {
+            audio_ports(node.plugin(), PortDescriptor::AudioOutput)
+        });
+        let inputs = audio_ports(instance.plugin(), PortDescriptor::AudioInput);
+        let node = self.graph.add_instance(instance);
+        if let Some(outputs) = previous {
+            if !outputs.is_empty() {
+                for (n, &input) in inputs.iter().enumerate() {
+                    self.graph.connect(NodeId(node.0 - 1), outputs[n % outputs.len()], node, input);
+                }
+            }
+        }
+        node
+    }
+
+    /// The instance of plugin ```node```.
+    pub fn node(&self, node: NodeId) -> &PluginInstance {
+        self.graph.node(node)
+    }
+
+    /// The instance of plugin ```node```, to change its settings.
+    pub fn node_mut(&mut self, node: NodeId) -> &mut PluginInstance {
+        self.graph.node_mut(node)
+    }
+
+    /// Sets control input ```port``` of plugin ```node```.
+    pub fn set_control(&mut self, node: NodeId, port: usize, value: Data) {
+        self.graph.set_control(node, port, value);
+    }
+
+    /// Sets control input ```port``` of plugin ```node``` to the value at ```normalized```,
+    /// between 0 and 1, in its range. See ```PluginInstance::set_normalized```.
+    pub fn set_normalized(&mut self, node: NodeId, port: usize, normalized: Data) {
+        self.graph.set_normalized(node, port, normalized);
+    }
+
+    /// The buffer of the ```channel```th audio input of the first plugin, to be filled before
+    /// ```run```. Panics if the chain is empty or there is no such input.
+    pub fn input_mut(&mut self, channel: usize) -> &mut [Data] {
+        let first = self.graph.nodes.first_mut().expect("the chain is empty");
+        let port = audio_ports(first.plugin(), PortDescriptor::AudioInput)[channel];
+        first.input_mut(port)
+    }
+
+    /// The buffer of the ```channel```th audio output of the last plugin. Panics if the chain is
+    /// empty or there is no such output.
+    pub fn output(&self, channel: usize) -> &[Data] {
+        let last = self.graph.nodes.last().expect("the chain is empty");
+        let port = audio_ports(last.plugin(), PortDescriptor::AudioOutput)[channel];
+        last.output(port)
+    }
+
+    /// Activates every plugin.
+    pub fn activate(&mut self) {
+        self.graph.activate();
+    }
+
+    /// Deactivates every plugin.
+    pub fn deactivate(&mut self) {
+        self.graph.deactivate();
+    }
+
+    /// Returns the state of every plugin, in order.
+    pub fn save_state(&self) -> Vec<InstanceState> {
+        self.graph.save_state()
+    }
+
+    /// Restores the states returned by ```save_state```, in order.
+    pub fn restore_state(&mut self, states: &[InstanceState]) {
+        self.graph.restore_state(states);
+    }
+
+    /// How many samples the outputs of the chain lag behind its inputs: the sum of the latencies
+    /// the plugins reported on the last ```run```.
+    pub fn latency(&self) -> usize {
+        self.graph.nodes.len().checked_sub(1).map_or(0, |last| self.graph.latency(NodeId(last)))
+    }
+
+    /// Runs the chain over ```sample_count``` samples. Panics if ```sample_count``` exceeds the
+    /// block size.
+    pub fn run(&mut self, sample_count: usize) {
+        self.graph.run(sample_count);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::tests::delay;
+
+    // Adds a node feeding one delaying its input by 3 samples in parallel with one delaying it by
+    // 7, both feeding the same input of a last node. Returns the four nodes.
+    fn paths(graph: &mut Graph) -> (NodeId, NodeId, NodeId, NodeId) {
+        let plugin = delay();
+        let nodes: Vec<_> = (0..4).map(|_| graph.add(&plugin, 48000).unwrap()).collect();
+        let (split, short, long, mix) = (nodes[0], nodes[1], nodes[2], nodes[3]);
+        graph.set_control(short, 2, 3.0);
+        graph.set_control(long, 2, 7.0);
+        graph.connect(split, 1, short, 0);
+        graph.connect(split, 1, long, 0);
+        graph.connect(short, 1, mix, 0);
+        graph.connect(long, 1, mix, 0);
+        (split, short, long, mix)
+    }
+
+    // Runs an impulse through the paths of `paths`.
+    fn unequal_paths(threads: usize) -> (Vec<Data>, usize) {
+        let mut graph = Graph::new(16);
+        graph.set_threads(threads);
+        let (split, _, _, mix) = paths(&mut graph);
+        graph.activate();
+        graph.run(16);
+        graph.node_mut(split).input_mut(0)[0] = 1.0;
+        graph.run(16);
+        (graph.node(mix).output(1)[..16].to_vec(), graph.latency(mix))
+    }
+
+    #[test]
+    fn parallel_paths_are_delayed_to_line_up() {
+        for threads in 1..3 {
+            let (output, latency) = unequal_paths(threads);
+            let mut expected = vec![0.0; 16];
+            expected[7] = 2.0;
+            assert_eq!(output, expected);
+            assert_eq!(latency, 7);
+        }
+    }
+
+    #[test]
+    fn growing_latencies_keep_the_delayed_audio() {
+        let mut graph = Graph::new(16);
+        let (split, _, long, mix) = paths(&mut graph);
+        graph.activate();
+        graph.node_mut(split).input_mut(0).fill(1.0);
+        graph.run(16);
+        graph.run(16);
+        graph.set_control(long, 2, 11.0);
+        graph.run(16);
+        assert_eq!(graph.latency(mix), 11);
+        assert!(graph.node(mix).output(1)[..16].iter().all(|&sample| sample == 2.0));
+    }
+
+    #[test]
+    fn latencies_are_compensated_up_to_the_maximum() {
+        let mut graph = Graph::new(16);
+        graph.set_max_latency(2);
+        let (split, _, _, mix) = paths(&mut graph);
+        graph.activate();
+        graph.run(16);
+        graph.node_mut(split).input_mut(0)[0] = 1.0;
+        graph.run(16);
+        let mut expected = [0.0; 16];
+        expected[5] = 1.0;
+        expected[7] = 1.0;
+        assert_eq!(graph.node(mix).output(1)[..16], expected);
+    }
+}
@@ -0,0 +1,886 @@
+//! Loading and running LADSPA plugins from other libraries.
+//!
+//! This is the other side of the LADSPA API: rather than exporting plugins written in Rust, it
+//! opens an existing plugin library (written in any language), enumerates the plugins it exposes
+//! and runs them. Port buffers are owned by the ```PluginInstance```, so no unsafe code is needed
+//! to drive a plugin.
+//!
+//! ```discover``` lists the plugins installed on the system, as found along ```LADSPA_PATH```:
+//!
+//! ```no_run
+//! # use ladspa::host;
+//! let catalogue = host::discover();
+//! for plugin in catalogue.plugins() {
+//!     println!("{} {}: {}", plugin.unique_id(), plugin.label(), plugin.path().display());
+//! }
+//! let reverb = catalogue.find(1050).expect("reverb not installed");
+//! ```
+
+// c_ulong is only 32 bits wide on some platforms, so widening it is not always a no-op.
+#![allow(clippy::useless_conversion)]
+
+use std::env;
+use std::error::Error;
+use std::ffi::CStr;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::Arc;
+
+use libc::{c_char, c_int, c_ulong, c_void};
+
+use ffi::{self, ladspa_h};
+use super::{default_for, Data, PortDescriptor, Unit};
+use super::mapping::ControlRange;
+
+mod dl;
+mod foreign;
+mod graph;
+mod params;
+mod pool;
+#[cfg(unix)]
+mod sandbox;
+mod state;
+pub use self::foreign::wrap_descriptor;
+pub use self::graph::{Chain, Graph, NodeId};
+pub use self::params::{Parameter, ParameterChange, ParameterModel, Subscription};
+#[cfg(unix)]
+pub use self::sandbox::{serve_sandbox, SandboxedInstance, SandboxedPlugin};
+pub use self::state::InstanceState;
+
+type DescriptorFn = unsafe extern "C" fn(index: c_ulong) -> *const ladspa_h::Descriptor;
+type RunFn = extern "C" fn(instance: ladspa_h::Handle, sample_count: c_ulong);
+// The extensions exported by plugin libraries built with this crate, see `ffi`.
+type SaveStateFn = unsafe extern "C" fn(instance: ladspa_h::Handle,
+                                        write: ffi::StateWriter,
+                                        context: *mut c_void)
+                                        -> c_int;
+type RestoreStateFn = unsafe extern "C" fn(instance: ladspa_h::Handle,
+                                           data: *const u8,
+                                           len: usize)
+                                           -> c_int;
+
+/// The ways in which loading or instantiating a plugin can fail.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HostError {
+    /// The library could not be opened. Contains the message reported by the dynamic loader.
+    Open(String),
+    /// The library does not export a ```ladspa_descriptor``` function.
+    NotLadspa,
+    /// The plugin returned a null handle from ```instantiate```.
+    InstantiateFailed,
+    /// The plugin has no control input with the given name.
+    NoSuchPort(String),
+    /// The sandbox process of a ```SandboxedInstance``` could not be started. Contains what went
+    /// wrong.
+    Sandbox(String),
+}
+
+impl fmt::Display for HostError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HostError::Open(ref msg) => write!(f, "failed to open library: {}", msg),
+            HostError::NotLadspa => write!(f, "library does not export ladspa_descriptor"),
+            HostError::InstantiateFailed => write!(f, "plugin instantiation failed"),
+            HostError::NoSuchPort(ref name) => write!(f, "no control input named {:?}", name),
+            HostError::Sandbox(ref msg) => write!(f, "failed to start sandbox: {}", msg),
+        }
+    }
+}
+
+impl Error for HostError {}
+
+// An open plugin library. Closed once the last plugin and instance referring to it are dropped.
+struct Library {
+    path: PathBuf,
+    handle: *mut c_void,
+    descriptor_fn: DescriptorFn,
+    // Only found in libraries built with this crate.
+    save_state: Option<SaveStateFn>,
+    restore_state: Option<RestoreStateFn>,
+}
+
+// Library handles may be used and closed from any thread.
+unsafe impl Send for Library {}
+unsafe impl Sync for Library {}
+
+impl Drop for Library {
+    fn drop(&mut self) {
+        // Null for the plugins of tests, which don't come from a library.
+        if !self.handle.is_null() {
+            unsafe {
+                dl::close(self.handle);
+            }
+        }
+    }
+}
+
+/// Opens the plugin library at ```path``` and returns every plugin it exposes.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<LoadedPlugin>, HostError> {
+    let path = path.as_ref();
+    unsafe {
+        let handle = dl::open(path).map_err(HostError::Open)?;
+        let symbol = match dl::symbol(handle, b"ladspa_descriptor\0") {
+            Some(symbol) => symbol,
+            None => {
+                dl::close(handle);
+                return Err(HostError::NotLadspa);
+            }
+        };
+        let library = Arc::new(Library {
+            path: path.to_path_buf(),
+            handle,
+            descriptor_fn: *(&symbol as *const *mut c_void as *const DescriptorFn),
+            save_state: dl::symbol(handle, b"ladspa_rs_save_state\0")
+                .map(|symbol| *(&symbol as *const *mut c_void as *const SaveStateFn)),
+            restore_state: dl::symbol(handle, b"ladspa_rs_restore_state\0")
+                .map(|symbol| *(&symbol as *const *mut c_void as *const RestoreStateFn)),
+        });
+
+        let mut plugins = Vec::new();
+        loop {
+            let descriptor = (library.descriptor_fn)(plugins.len() as c_ulong);
+            if descriptor.is_null() {
+                break;
+            }
+            plugins.push(LoadedPlugin {
+                library: library.clone(),
+                descriptor,
+            });
+        }
+        Ok(plugins)
+    }
+}
+
+/// A plugin exposed by a library opened with ```load```.
+#[derive(Clone)]
+pub struct LoadedPlugin {
+    // Keeps the library loaded for as long as the descriptor is in use.
+    library: Arc<Library>,
+    descriptor: *const ladspa_h::Descriptor,
+}
+
+// Descriptors are immutable once returned by ladspa_descriptor.
+unsafe impl Send for LoadedPlugin {}
+unsafe impl Sync for LoadedPlugin {}
+
+fn decode_port_descriptor(bits: i32) -> PortDescriptor {
+    let is_input = bits & ladspa_h::PORT_INPUT != 0;
+    if bits & ladspa_h::PORT_AUDIO != 0 {
+        if is_input { PortDescriptor::AudioInput } else { PortDescriptor::AudioOutput }
+    } else if bits & ladspa_h::PORT_CONTROL != 0 {
+        if is_input { PortDescriptor::ControlInput } else { PortDescriptor::ControlOutput }
+    } else {
+        PortDescriptor::Invalid
+    }
+}
+
+unsafe fn c_str<'a>(s: *const c_char) -> &'a str {
+    if s.is_null() {
+        ""
+    } else {
+        CStr::from_ptr(s).to_str().unwrap_or("")
+    }
+}
+
+impl LoadedPlugin {
+    fn raw(&self) -> &ladspa_h::Descriptor {
+        unsafe { &*self.descriptor }
+    }
+
+    /// The path of the library the plugin was loaded from.
+    pub fn path(&self) -> &Path {
+        &self.library.path
+    }
+
+    /// The unique ID of the plugin.
+    pub fn unique_id(&self) -> u64 {
+        u64::from(self.raw().unique_id)
+    }
+
+    /// The label of the plugin.
+    pub fn label(&self) -> &str {
+        unsafe { c_str(self.raw().label) }
+    }
+
+    /// The name of the plugin.
+    pub fn name(&self) -> &str {
+        unsafe { c_str(self.raw().name) }
+    }
+
+    /// The maker of the plugin.
+    pub fn maker(&self) -> &str {
+        unsafe { c_str(self.raw().maker) }
+    }
+
+    /// The copyright of the plugin.
+    pub fn copyright(&self) -> &str {
+        unsafe { c_str(self.raw().copyright) }
+    }
+
+    /// The raw LADSPA property bits of the plugin.
+    pub fn properties(&self) -> i32 {
+        self.raw().properties
+    }
+
+    /// The number of ports the plugin has.
+    pub fn port_count(&self) -> usize {
+        self.raw().port_count as usize
+    }
+
+    /// The name of port ```port```. Panics if the port does not exist.
+    pub fn port_name(&self, port: usize) -> &str {
+        assert!(port < self.port_count(), "port index out of range");
+        unsafe { c_str(*self.raw().port_names.add(port)) }
+    }
+
+    /// The index of the first port named ```name```, or ```None``` if there is no such port.
+    pub fn port_index(&self, name: &str) -> Option<usize> {
+        (0..self.port_count()).find(|&port| self.port_name(port) == name)
+    }
+
+    /// The type of port ```port```. Panics if the port does not exist.
+    pub fn port_descriptor(&self, port: usize) -> PortDescriptor {
+        assert!(port < self.port_count(), "port index out of range");
+        decode_port_descriptor(self.port_descriptor_bits(port))
+    }
+
+    /// The raw LADSPA descriptor bits of port ```port```, including combinations which
+    /// ```port_descriptor``` can't represent. Panics if the port does not exist.
+    pub fn port_descriptor_bits(&self, port: usize) -> i32 {
+        assert!(port < self.port_count(), "port index out of range");
+        unsafe { *self.raw().port_descriptors.add(port) }
+    }
+
+    /// What the value of port ```port``` measures, as far as its name tells: LADSPA descriptors
+    /// have no place for units, but plugins conventionally end port names with them, as in
+    /// ```"Cutoff (Hz)"```. See ```Unit::from_name```. Panics if the port does not exist.
+    pub fn port_unit(&self, port: usize) -> Option<Unit> {
+        Unit::from_name(self.port_name(port))
+    }
+
+    /// The range hint of port ```port```. Panics if the port does not exist.
+    pub fn port_range_hint(&self, port: usize) -> ladspa_h::PortRangeHint {
+        assert!(port < self.port_count(), "port index out of range");
+        unsafe { *self.raw().port_range_hints.add(port) }
+    }
+
+    /// The value port ```port``` starts at in an instance running at ```sample_rate```, or
+    /// ```None``` if it has no default. See ```default_for```. Panics if the port does not exist.
+    pub fn default_value(&self, port: usize, sample_rate: u64) -> Option<Data> {
+        let hint = self.port_range_hint(port);
+        default_for(hint.hint_descriptor, hint.lower_bound, hint.upper_bound, sample_rate)
+    }
+
+    /// The values port ```port``` covers in an instance running at ```sample_rate```, to map them
+    /// to and from the normalized range of sliders and controllers. Panics if the port does not
+    /// exist.
+    pub fn port_range(&self, port: usize, sample_rate: u64) -> ControlRange {
+        let hint = self.port_range_hint(port);
+        let bits = hint.hint_descriptor;
+        ControlRange::from_hint(bits,
+                                Some(hint.lower_bound)
+                                    .filter(|_| bits & ladspa_h::HINT_BOUNDED_BELOW != 0),
+                                Some(hint.upper_bound)
+                                    .filter(|_| bits & ladspa_h::HINT_BOUNDED_ABOVE != 0),
+                                sample_rate)
+    }
+
+    /// Creates a new instance of the plugin running at ```sample_rate```, able to process blocks
+    /// of up to ```block_size``` samples. Control inputs start at their default value, or 0 if
+    /// they have none.
+    pub fn instantiate(&self, sample_rate: u64, block_size: usize)
+                       -> Result<PluginInstance, HostError> {
+        let handle = (self.raw().instantiate)(self.descriptor, sample_rate as c_ulong);
+        if handle.is_null() {
+            return Err(HostError::InstantiateFailed);
+        }
+        let buffers = (0..self.port_count())
+            .map(|port| match self.port_descriptor(port) {
+                PortDescriptor::AudioInput | PortDescriptor::AudioOutput => vec![0.0; block_size],
+                _ => vec![self.default_value(port, sample_rate).unwrap_or(0.0)],
+            })
+            .collect();
+        let native = self.raw().run_adding.is_some() && self.raw().set_run_adding_gain.is_some();
+        let scratch = (0..self.port_count())
+            .map(|port| match self.port_descriptor(port) {
+                PortDescriptor::AudioOutput if !native => vec![0.0; block_size],
+                _ => Vec::new(),
+            })
+            .collect();
+        let mut instance = PluginInstance {
+            plugin: self.clone(),
+            handle,
+            buffers,
+            shared: vec![None; self.port_count()],
+            glides: vec![None; self.port_count()],
+            scratch,
+            sample_rate,
+            block_size,
+            active: false,
+        };
+        instance.connect_at(0, false);
+        Ok(instance)
+    }
+
+    /// Checks whether the plugin produces the same output when its audio outputs share buffers
+    /// with its audio inputs, as hosts may arrange unless ```PROP_INPLACE_BROKEN``` is set.
+    ///
+    /// Runs one instance with separate buffers and one in place, over a few blocks of the same
+    /// test signal with every control input in the middle of its range, and returns whether their
+    /// outputs matched. A plugin which fails this without declaring ```PROP_INPLACE_BROKEN```
+    /// will corrupt audio in some hosts.
+    pub fn check_inplace(&self, sample_rate: u64, block_size: usize) -> Result<bool, HostError> {
+        let mut separate = self.instantiate(sample_rate, block_size)?;
+        let mut inplace = self.instantiate(sample_rate, block_size)?;
+        inplace.set_inplace(true);
+
+        for port in 0..self.port_count() {
+            if let PortDescriptor::ControlInput = self.port_descriptor(port) {
+                let hint = self.port_range_hint(port);
+                let lower = if hint.hint_descriptor & ladspa_h::HINT_BOUNDED_BELOW != 0 {
+                    hint.lower_bound
+                } else {
+                    0.0
+                };
+                let upper = if hint.hint_descriptor & ladspa_h::HINT_BOUNDED_ABOVE != 0 {
+                    hint.upper_bound
+                } else {
+                    lower + 1.0
+                };
+                let value = (lower + upper) / 2.0;
+                separate.set_control(port, value);
+                inplace.set_control(port, value);
+            }
+        }
+
+        separate.activate();
+        inplace.activate();
+        let mut phase: u32 = 1;
+        for _ in 0..4 {
+            for port in 0..self.port_count() {
+                if let PortDescriptor::AudioInput = self.port_descriptor(port) {
+                    // A cheap pseudo random signal, identical for both instances.
+                    for (a, b) in separate.input_mut(port).iter_mut().zip(inplace.input_mut(port)) {
+                        phase = phase.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                        *a = (phase >> 8) as Data / (1 << 23) as Data - 1.0;
+                        *b = *a;
+                    }
+                }
+            }
+            separate.run(block_size);
+            inplace.run(block_size);
+            for port in 0..self.port_count() {
+                if let PortDescriptor::AudioOutput = self.port_descriptor(port) {
+                    if separate.output(port) != inplace.output(port) {
+                        return Ok(false);
+                    }
+                }
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// The directories searched by ```discover```: those listed in the ```LADSPA_PATH``` environment
+/// variable, separated by colons, or semicolons on Windows. If it is unset or empty, they are
+/// ```~/.ladspa```, ```/usr/local/lib/ladspa``` and ```/usr/lib/ladspa``` on Unix, preceded by
+/// ```~/Library/Audio/Plug-Ins/LADSPA``` and ```/Library/Audio/Plug-Ins/LADSPA``` on macOS, and
+/// ```%APPDATA%\LADSPA``` on Windows, which has no standard location.
+pub fn search_path() -> Vec<PathBuf> {
+    match env::var_os("LADSPA_PATH") {
+        Some(ref path) if !path.is_empty() => env::split_paths(path).collect(),
+        _ => default_search_path(),
+    }
+}
+
+#[cfg(unix)]
+fn default_search_path() -> Vec<PathBuf> {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    let mut dirs = Vec::new();
+    if cfg!(target_os = "macos") {
+        dirs.extend(home.as_ref().map(|home| home.join("Library/Audio/Plug-Ins/LADSPA")));
+        dirs.push(PathBuf::from("/Library/Audio/Plug-Ins/LADSPA"));
+    }
+    dirs.extend(home.map(|home| home.join(".ladspa")));
+    dirs.push(PathBuf::from("/usr/local/lib/ladspa"));
+    dirs.push(PathBuf::from("/usr/lib/ladspa"));
+    dirs
+}
+
+#[cfg(windows)]
+fn default_search_path() -> Vec<PathBuf> {
+    env::var_os("APPDATA").map(|appdata| Path::new(&appdata).join("LADSPA")).into_iter().collect()
+}
+
+// The extensions of the files `discover` loads. On macOS, hosts look for `.so` files as on other
+// Unix systems, but Cargo builds `.dylib` ones.
+#[cfg(target_os = "macos")]
+const LIBRARY_EXTENSIONS: &[&str] = &["so", "dylib"];
+#[cfg(not(target_os = "macos"))]
+const LIBRARY_EXTENSIONS: &[&str] = &[env::consts::DLL_EXTENSION];
+
+/// The plugins installed on the system, as found by ```discover```.
+pub struct Catalogue {
+    plugins: Vec<LoadedPlugin>,
+    errors: Vec<(PathBuf, HostError)>,
+}
+
+impl Catalogue {
+    /// Every plugin found, in the order of the search path, then of the library file names, then
+    /// of their index in the library.
+    pub fn plugins(&self) -> &[LoadedPlugin] {
+        &self.plugins
+    }
+
+    /// The libraries which could not be loaded, and why.
+    pub fn errors(&self) -> &[(PathBuf, HostError)] {
+        &self.errors
+    }
+
+    /// The plugin with unique ID ```unique_id```. If several libraries contain one, the first
+    /// found wins, so directories earlier in the search path take precedence.
+    pub fn find(&self, unique_id: u64) -> Option<&LoadedPlugin> {
+        self.plugins.iter().find(|plugin| plugin.unique_id() == unique_id)
+    }
+
+    /// The plugin labelled ```label```, taking precedence into account like ```find```.
+    pub fn find_label(&self, label: &str) -> Option<&LoadedPlugin> {
+        self.plugins.iter().find(|plugin| plugin.label() == label)
+    }
+}
+
+/// Loads every plugin library in the directories of ```search_path```. The plugins are not
+/// instantiated, only their descriptors are read.
+pub fn discover() -> Catalogue {
+    discover_in(&search_path())
+}
+
+/// Loads every plugin library in ```dirs```, without descending into subdirectories.
+/// Directories which don't exist are skipped.
+pub fn discover_in<P: AsRef<Path>>(dirs: &[P]) -> Catalogue {
+    let mut catalogue = Catalogue {
+        plugins: Vec::new(),
+        errors: Vec::new(),
+    };
+    for dir in dirs {
+        let mut paths: Vec<PathBuf> = match fs::read_dir(dir) {
+            Ok(entries) => entries.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                                  .filter(|path| {
+                                      path.extension().is_some_and(|extension| {
+                                          LIBRARY_EXTENSIONS.iter()
+                                                            .any(|&ext| extension == ext)
+                                      }) && path.is_file()
+                                  })
+                                  .collect(),
+            Err(_) => continue,
+        };
+        paths.sort();
+        for path in paths {
+            match load(&path) {
+                Ok(plugins) => catalogue.plugins.extend(plugins),
+                Err(error) => catalogue.errors.push((path, error)),
+            }
+        }
+    }
+    catalogue
+}
+
+/// A running instance of a ```LoadedPlugin```.
+///
+/// Each port is connected to a buffer owned by the instance: audio ports get one sample per
+/// frame of the block size given to ```instantiate```, control ports get a single value.
+pub struct PluginInstance {
+    plugin: LoadedPlugin,
+    handle: ladspa_h::Handle,
+    buffers: Vec<Vec<Data>>,
+    // For audio outputs processed in place, the input port whose buffer they are connected to.
+    shared: Vec<Option<usize>>,
+    // The glide in progress on each control input set with `ramp_control`, indexed by port.
+    glides: Vec<Option<Glide>>,
+    // Where audio outputs are rendered when ```run_adding``` is emulated, indexed by port. Empty
+    // for other ports, and for every port if the plugin supports ```run_adding``` itself.
+    scratch: Vec<Vec<Data>>,
+    sample_rate: u64,
+    block_size: usize,
+    active: bool,
+}
+
+// LADSPA instances may be moved between threads as long as they are not used concurrently.
+unsafe impl Send for PluginInstance {}
+
+// A control input moving linearly to `target`, which it reaches in `remaining` samples, by `step`
+// per sample.
+#[derive(Copy, Clone, Debug)]
+struct Glide {
+    target: Data,
+    step: Data,
+    remaining: usize,
+}
+
+// The longest run of samples a plugin is given while a control glides, so that plugins reading
+// their controls once per block follow it in steps too small to hear.
+const GLIDE_CHUNK: usize = 32;
+
+impl PluginInstance {
+    // Connects every port, the audio ports `offset` samples into their buffers, and the audio
+    // outputs to their scratch buffers if `scratch` is set.
+    fn connect_at(&mut self, offset: usize, scratch: bool) {
+        for port in 0..self.buffers.len() {
+            let buffer = match self.plugin.port_descriptor(port) {
+                PortDescriptor::AudioOutput if scratch => &mut self.scratch[port][offset..],
+                PortDescriptor::AudioInput | PortDescriptor::AudioOutput => {
+                    &mut self.buffers[self.shared[port].unwrap_or(port)][offset..]
+                }
+                _ => &mut self.buffers[port][..],
+            };
+            (self.plugin.raw().connect_port)(self.handle, port as c_ulong, buffer.as_mut_ptr());
+        }
+    }
+
+    /// Connects the ```n```th audio output to the buffer of the ```n```th audio input, as hosts
+    /// processing in place do, or back to its own buffer. Useful to test how a plugin behaves
+    /// when its buffers alias; see ```LoadedPlugin::check_inplace```.
+    pub fn set_inplace(&mut self, inplace: bool) {
+        let ports = 0..self.plugin.port_count();
+        let inputs = ports.clone().filter(|&port| {
+            matches!(self.plugin.port_descriptor(port), PortDescriptor::AudioInput)
+        });
+        let outputs = ports.filter(|&port| {
+            matches!(self.plugin.port_descriptor(port), PortDescriptor::AudioOutput)
+        });
+        let pairs: Vec<(usize, usize)> = outputs.zip(inputs).collect();
+        for (output, input) in pairs {
+            self.shared[output] = if inplace { Some(input) } else { None };
+        }
+        self.connect_at(0, false);
+    }
+
+    /// The plugin this is an instance of.
+    pub fn plugin(&self) -> &LoadedPlugin {
+        &self.plugin
+    }
+
+    /// The largest number of samples which can be processed by a single call to ```run```.
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// Returns the current value of control port ```port```.
+    pub fn control(&self, port: usize) -> Data {
+        self.buffers[port][0]
+    }
+
+    /// The latency the plugin reported on its last ```run```, in samples, on the control output
+    /// named "latency" by convention. 0 if it has no such port.
+    pub fn latency(&self) -> usize {
+        (0..self.plugin.port_count())
+            .find(|&port| {
+                matches!(self.plugin.port_descriptor(port), PortDescriptor::ControlOutput) &&
+                self.plugin.port_name(port) == "latency"
+            })
+            .map_or(0, |port| self.control(port).round().max(0.0) as usize)
+    }
+
+    /// Sets the value of control input ```port```, to be read on the next call to ```run```.
+    /// Stops any glide started by ```ramp_control```.
+    pub fn set_control(&mut self, port: usize, value: Data) {
+        self.buffers[port][0] = value;
+        self.glides[port] = None;
+    }
+
+    /// Moves the control input named ```name``` to ```value``` linearly over the next
+    /// ```ramp_ms``` milliseconds of audio, or at once if ```ramp_ms``` is not positive.
+    ///
+    /// Most plugins only read their controls at the start of each block, so while a control
+    /// glides, ```run``` processes the block in chunks of a few samples with the control updated
+    /// before each, making automation smooth even for plugins which don't smooth their controls
+    /// themselves.
+    pub fn ramp_control(&mut self, name: &str, value: Data, ramp_ms: f32)
+                        -> Result<(), HostError> {
+        let port = self.plugin
+            .port_index(name)
+            .filter(|&port| {
+                matches!(self.plugin.port_descriptor(port), PortDescriptor::ControlInput)
+            })
+            .ok_or_else(|| HostError::NoSuchPort(String::from(name)))?;
+        let samples = (f64::from(ramp_ms) * self.sample_rate as f64 / 1000.0).round();
+        if samples < 1.0 {
+            self.set_control(port, value);
+            return Ok(());
+        }
+        let samples = samples as usize;
+        self.glides[port] = Some(Glide {
+            target: value,
+            step: (value - self.control(port)) / samples as Data,
+            remaining: samples,
+        });
+        Ok(())
+    }
+
+    /// Returns whether a control input set with ```ramp_control``` is still moving.
+    pub fn is_ramping(&self) -> bool {
+        self.glides.iter().any(Option::is_some)
+    }
+
+    // Moves the gliding controls on by `len` samples, to their values at the end of a chunk of
+    // that length.
+    fn advance_glides(&mut self, len: usize) {
+        for (buffer, glide) in self.buffers.iter_mut().zip(&mut self.glides) {
+            if let Some(ref mut current) = *glide {
+                if current.remaining <= len {
+                    buffer[0] = current.target;
+                    *glide = None;
+                } else {
+                    current.remaining -= len;
+                    // From the target, so that rounding errors don't build up.
+                    buffer[0] = current.target - current.step * current.remaining as Data;
+                }
+            }
+        }
+    }
+
+    /// Returns where the current value of control port ```port``` is in its range, between 0
+    /// and 1. See ```LoadedPlugin::port_range```.
+    pub fn normalized(&self, port: usize) -> Data {
+        self.plugin.port_range(port, self.sample_rate).to_normalized(self.control(port))
+    }
+
+    /// Sets control input ```port``` to the value at ```normalized```, between 0 and 1, in its
+    /// range, as a host slider would.
+    pub fn set_normalized(&mut self, port: usize, normalized: Data) {
+        let value = self.plugin.port_range(port, self.sample_rate).from_normalized(normalized);
+        self.set_control(port, value);
+    }
+
+    /// Returns the buffer connected to audio input ```port``` so it can be filled before ```run```.
+    pub fn input_mut(&mut self, port: usize) -> &mut [Data] {
+        &mut self.buffers[port]
+    }
+
+    /// Returns the buffer connected to audio output ```port```. For outputs processed in place,
+    /// this is the buffer of the input they share.
+    pub fn output(&self, port: usize) -> &[Data] {
+        &self.buffers[self.shared[port].unwrap_or(port)]
+    }
+
+    /// Activates the instance. Does nothing if it is already active.
+    pub fn activate(&mut self) {
+        if !self.active {
+            if let Some(activate) = self.plugin.raw().activate {
+                activate(self.handle);
+            }
+            self.active = true;
+        }
+    }
+
+    /// Processes ```sample_count``` samples. Panics if ```sample_count``` exceeds the block size.
+    pub fn run(&mut self, sample_count: usize) {
+        assert!(sample_count <= self.block_size, "sample count exceeds block size");
+        let run = self.plugin.raw().run;
+        self.process(sample_count, false, run);
+    }
+
+    /// Whether the plugin implements ```run_adding``` itself, rather than having it emulated.
+    pub fn native_run_adding(&self) -> bool {
+        self.plugin.raw().run_adding.is_some() && self.plugin.raw().set_run_adding_gain.is_some()
+    }
+
+    /// Processes ```sample_count``` samples, adding the output of the plugin, scaled by
+    /// ```gain```, to the current contents of the audio output buffers. Panics if
+    /// ```sample_count``` exceeds the block size.
+    ///
+    /// Plugins without ```run_adding``` are run into scratch buffers allocated at instantiation,
+    /// which are then mixed in, so this works with any plugin.
+    pub fn run_adding(&mut self, sample_count: usize, gain: Data) {
+        assert!(sample_count <= self.block_size, "sample count exceeds block size");
+        let raw = self.plugin.raw();
+        if let (Some(run_adding), Some(set_run_adding_gain)) = (raw.run_adding,
+                                                                raw.set_run_adding_gain) {
+            set_run_adding_gain(self.handle, gain);
+            self.process(sample_count, false, run_adding);
+            return;
+        }
+        let run = raw.run;
+        self.process(sample_count, true, run);
+        for (port, scratch) in self.scratch.iter().enumerate().filter(|(_, s)| !s.is_empty()) {
+            let output = &mut self.buffers[self.shared[port].unwrap_or(port)][..sample_count];
+            for (out, &sample) in output.iter_mut().zip(scratch) {
+                *out += sample * gain;
+            }
+        }
+    }
+
+    // Calls `run` for `sample_count` samples, in chunks while controls glide, with the audio
+    // outputs connected to their scratch buffers if `scratch` is set.
+    fn process(&mut self, sample_count: usize, scratch: bool, run: RunFn) {
+        if !scratch && !self.is_ramping() {
+            run(self.handle, sample_count as c_ulong);
+            return;
+        }
+        let mut offset = 0;
+        while offset < sample_count {
+            let len = if self.is_ramping() {
+                (sample_count - offset).min(GLIDE_CHUNK)
+            } else {
+                sample_count - offset
+            };
+            self.advance_glides(len);
+            self.connect_at(offset, scratch);
+            run(self.handle, len as c_ulong);
+            offset += len;
+        }
+        self.connect_at(0, false);
+    }
+
+    /// Deactivates the instance. Does nothing if it is not active.
+    pub fn deactivate(&mut self) {
+        if self.active {
+            if let Some(deactivate) = self.plugin.raw().deactivate {
+                deactivate(self.handle);
+            }
+            self.active = false;
+        }
+    }
+
+    /// Returns the values of the control inputs, along with the state of plugins built with this
+    /// crate which implement ```Stateful```.
+    pub fn save_state(&self) -> InstanceState {
+        extern "C" fn write(context: *mut c_void, data: *const u8, len: usize) {
+            let state = unsafe { &mut *(context as *mut Vec<u8>) };
+            if !data.is_null() {
+                state.extend_from_slice(unsafe { std::slice::from_raw_parts(data, len) });
+            }
+        }
+
+        let controls = (0..self.plugin.port_count())
+            .filter(|&port| {
+                matches!(self.plugin.port_descriptor(port), PortDescriptor::ControlInput)
+            })
+            .map(|port| (port, self.control(port)))
+            .collect();
+        let data = self.plugin.library.save_state.and_then(|save_state| {
+            let mut state = Vec::new();
+            let saved = unsafe {
+                save_state(self.handle, write, &mut state as *mut Vec<u8> as *mut c_void)
+            };
+            if saved != 0 { Some(state) } else { None }
+        });
+        InstanceState { controls, data }
+    }
+
+    /// Brings the instance back to a state returned by ```save_state```. Controls which aren't
+    /// inputs of this plugin are skipped, as is the opaque state if the plugin can't restore it.
+    pub fn restore_state(&mut self, state: &InstanceState) {
+        for &(port, value) in &state.controls {
+            if port < self.plugin.port_count() &&
+               matches!(self.plugin.port_descriptor(port), PortDescriptor::ControlInput) {
+                self.set_control(port, value);
+            }
+        }
+        if let (Some(restore_state), Some(data)) = (self.plugin.library.restore_state,
+                                                   state.data.as_ref()) {
+            unsafe {
+                restore_state(self.handle, data.as_ptr(), data.len());
+            }
+        }
+    }
+}
+
+impl Drop for PluginInstance {
+    fn drop(&mut self) {
+        self.deactivate();
+        (self.plugin.raw().cleanup)(self.handle);
+        self.handle = ptr::null_mut();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::ptr;
+    use std::sync::Arc;
+
+    use libc::c_ulong;
+
+    use ffi::{self, ladspa_h};
+    use super::{Library, LoadedPlugin};
+    use super::super::{Data, DefaultValue, InstantiateError, Plugin, PluginDescriptor,
+                       PortConnection, UniqueId};
+    use super::super::dsp::delay::DelayLine;
+
+    unsafe extern "C" fn no_descriptor(_: c_ulong) -> *const ladspa_h::Descriptor {
+        ptr::null()
+    }
+
+    // Loads `plugin` as a host would load it from a library, through the same entry points.
+    pub fn load_descriptor(plugin: PluginDescriptor) -> LoadedPlugin {
+        LoadedPlugin {
+            library: Arc::new(Library {
+                path: PathBuf::new(),
+                handle: ptr::null_mut(),
+                descriptor_fn: no_descriptor,
+                save_state: None,
+                restore_state: None,
+            }),
+            descriptor: ffi::export(plugin),
+        }
+    }
+
+    // Delays its input by the whole number of samples set on its control input, and reports them
+    // as its latency.
+    struct Delay {
+        line: DelayLine,
+    }
+
+    impl Plugin for Delay {
+        fn activate(&mut self) {
+            self.line.clear();
+        }
+
+        fn run<'a>(&mut self, sample_count: usize, ports: &mut [PortConnection<'a>]) {
+            let delay = ports[2].unwrap_control() as usize;
+            let input = ports[0].unwrap_audio();
+            let output = &mut ports[1].unwrap_audio_mut()[..sample_count];
+            for (out, &sample) in output.iter_mut().zip(input) {
+                self.line.write(sample);
+                *out = if delay == 0 { sample } else { self.line.read(delay + 1) };
+            }
+            *ports[3].unwrap_control_out() = delay as Data;
+        }
+    }
+
+    fn new_delay(_: &PluginDescriptor, _: u64) -> Result<Box<dyn Plugin + Send>, InstantiateError> {
+        Ok(Box::new(Delay { line: DelayLine::new(65) }))
+    }
+
+    // A plugin delaying its input by up to 64 samples, set on port 2, and reporting the delay
+    // on its latency output, port 3.
+    pub fn delay() -> LoadedPlugin {
+        load_descriptor(PluginDescriptor::builder()
+                            .unique_id(UniqueId::experimental(999).get())
+                            .label("test_delay")
+                            .name("Test Delay")
+                            .maker("ladspa.rs")
+                            .copyright("None")
+                            .audio_in("Input")
+                            .audio_out("Output")
+                            .control_in("Delay", 0.0..64.0, DefaultValue::Minimum)
+                            .latency_out()
+                            .new(new_delay)
+                            .build()
+                            .unwrap())
+    }
+
+    #[test]
+    fn instances_run_and_report_their_latency() {
+        let mut instance = delay().instantiate(48000, 16).unwrap();
+        instance.set_control(2, 3.0);
+        instance.activate();
+        instance.input_mut(0)[0] = 1.0;
+        instance.run(16);
+        assert_eq!(instance.output(1)[..5], [0.0, 0.0, 0.0, 1.0, 0.0]);
+        assert_eq!(instance.latency(), 3);
+    }
+}
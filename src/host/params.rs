@@ -0,0 +1,363 @@
+use std::fmt;
+
+use super::{LoadedPlugin, PluginInstance};
+use super::super::{Data, PortDescriptor, Unit};
+use super::super::mapping::ControlRange;
+
+/// A control input of a loaded plugin, as a ```ParameterModel``` presents it.
+#[derive(Clone, Debug)]
+pub struct Parameter {
+    port: usize,
+    name: String,
+    range: ControlRange,
+    unit: Option<Unit>,
+    default: Data,
+    value: Data,
+}
+
+impl Parameter {
+    /// The index of the control input among all the ports of the plugin.
+    pub fn port(&self) -> usize {
+        self.port
+    }
+
+    /// The name of the control input.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The range of the control input, at the sample rate the model was created for.
+    pub fn range(&self) -> &ControlRange {
+        &self.range
+    }
+
+    /// What the value measures, as far as the name of the port tells. See
+    /// ```LoadedPlugin::port_unit```.
+    pub fn unit(&self) -> Option<Unit> {
+        self.unit
+    }
+
+    /// The default value, or 0 if the plugin gives none.
+    pub fn default(&self) -> Data {
+        self.default
+    }
+
+    /// The current value.
+    pub fn value(&self) -> Data {
+        self.value
+    }
+
+    /// Where the current value is in the range, between 0 and 1.
+    pub fn normalized(&self) -> Data {
+        self.range.to_normalized(self.value)
+    }
+
+    /// Formats the current value for display: "On" or "Off" for toggles, whole numbers for
+    /// integers and otherwise with the unit of the port, if known.
+    pub fn display(&self) -> String {
+        if self.range.toggled {
+            String::from(if self.value > 0.0 { "On" } else { "Off" })
+        } else if self.range.integer {
+            format!("{}", self.value.round() as i64)
+        } else {
+            match self.unit {
+                Some(unit) => unit.format(self.value),
+                None => format!("{:.2}", self.value),
+            }
+        }
+    }
+}
+
+/// A change to a parameter, as passed to the callbacks of a ```ParameterModel```.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ParameterChange {
+    /// The index of the parameter in the model.
+    pub index: usize,
+    /// The index of its control input among all the ports of the plugin.
+    pub port: usize,
+    /// The new value.
+    pub value: Data,
+    /// Where the new value is in the range, between 0 and 1.
+    pub normalized: Data,
+}
+
+/// Identifies a callback added to a ```ParameterModel```, to remove it with
+/// ```ParameterModel::unsubscribe```.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Subscription(usize);
+
+type Callback = Box<dyn FnMut(&ParameterChange) + Send>;
+
+/// The control inputs of a plugin as parameters for a user interface, a remote control protocol
+/// or automation to work with.
+///
+/// Values are set either as they are or normalized between 0 and 1, and always stored as the
+/// range of the port allows: clamped to the bounds, on a logarithmic scale for
+/// ```HINT_LOGARITHMIC``` ports, rounded for ```HINT_INTEGER``` ones and either 0 or 1 for
+/// ```HINT_TOGGLED``` ones. Every change is passed to the subscribed callbacks, so that the
+/// frontends showing the parameters stay in step, and marks the parameter for ```apply``` to
+/// pass on to the instance:
+///
+/// ```no_run
+/// # use ladspa::host::{self, ParameterModel};
+/// # let mut instance = host::discover().plugins()[0].instantiate(48000, 256).unwrap();
+/// let mut model = ParameterModel::from_instance(&instance);
+/// model.subscribe(|change| println!("port {} is now {}", change.port, change.value));
+/// let cutoff = model.find("Cutoff (Hz)").unwrap();
+/// model.set_normalized(cutoff, 0.25);
+/// println!("{}", model.parameter(cutoff).display());
+/// model.apply(&mut instance);
+/// ```
+pub struct ParameterModel {
+    parameters: Vec<Parameter>,
+    // Whether each parameter has changed since the last `apply`, indexed like `parameters`.
+    changed: Vec<bool>,
+    // Indexed by subscription. `None` once unsubscribed.
+    callbacks: Vec<Option<Callback>>,
+}
+
+impl ParameterModel {
+    /// The parameters of ```plugin``` running at ```sample_rate```, at their default values.
+    pub fn new(plugin: &LoadedPlugin, sample_rate: u64) -> ParameterModel {
+        let parameters: Vec<Parameter> = (0..plugin.port_count())
+            .filter(|&port| matches!(plugin.port_descriptor(port), PortDescriptor::ControlInput))
+            .map(|port| {
+                let range = plugin.port_range(port, sample_rate);
+                let default = plugin.default_value(port, sample_rate).unwrap_or(0.0);
+                Parameter {
+                    port,
+                    name: String::from(plugin.port_name(port)),
+                    range,
+                    unit: plugin.port_unit(port),
+                    default,
+                    value: default,
+                }
+            })
+            .collect();
+        ParameterModel {
+            changed: vec![false; parameters.len()],
+            parameters,
+            callbacks: Vec::new(),
+        }
+    }
+
+    /// The parameters of ```instance```, at their current values.
+    pub fn from_instance(instance: &PluginInstance) -> ParameterModel {
+        let mut model = ParameterModel::new(instance.plugin(), instance.sample_rate);
+        for parameter in &mut model.parameters {
+            parameter.value = instance.control(parameter.port);
+        }
+        model
+    }
+
+    /// Every parameter, in the order of the ports of the plugin.
+    pub fn parameters(&self) -> &[Parameter] {
+        &self.parameters
+    }
+
+    /// The parameter with index ```index```. Panics if there is no such parameter.
+    pub fn parameter(&self, index: usize) -> &Parameter {
+        &self.parameters[index]
+    }
+
+    /// The index of the first parameter named ```name```, or ```None``` if there is none.
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.parameters.iter().position(|parameter| parameter.name == name)
+    }
+
+    /// Sets the parameter with index ```index``` to ```value```, as its range allows. Panics if
+    /// there is no such parameter.
+    pub fn set(&mut self, index: usize, value: Data) {
+        let normalized = self.parameters[index].range.to_normalized(value);
+        self.set_normalized(index, normalized);
+    }
+
+    /// Sets the parameter with index ```index``` to the value at ```normalized```, between 0 and
+    /// 1, in its range. Panics if there is no such parameter.
+    pub fn set_normalized(&mut self, index: usize, normalized: Data) {
+        let value = self.parameters[index].range.from_normalized(normalized);
+        self.update(index, value);
+    }
+
+    /// Sets the parameter with index ```index``` back to its default value. Panics if there is no
+    /// such parameter.
+    pub fn reset(&mut self, index: usize) {
+        let default = self.parameters[index].default;
+        self.update(index, default);
+    }
+
+    // Stores `value`, already within the range, and passes the change on if there is one.
+    fn update(&mut self, index: usize, value: Data) {
+        let parameter = &mut self.parameters[index];
+        if parameter.value.to_bits() == value.to_bits() {
+            return;
+        }
+        parameter.value = value;
+        self.changed[index] = true;
+        let change = ParameterChange {
+            index,
+            port: parameter.port,
+            value,
+            normalized: parameter.normalized(),
+        };
+        for callback in self.callbacks.iter_mut().flatten() {
+            callback(&change);
+        }
+    }
+
+    /// Calls ```callback``` with every later change, until unsubscribed.
+    pub fn subscribe<F>(&mut self, callback: F) -> Subscription
+        where F: FnMut(&ParameterChange) + Send + 'static
+    {
+        self.callbacks.push(Some(Box::new(callback)));
+        Subscription(self.callbacks.len() - 1)
+    }
+
+    /// Stops calling the callback added by the ```subscribe``` call which returned
+    /// ```subscription```.
+    pub fn unsubscribe(&mut self, subscription: Subscription) {
+        if let Some(callback) = self.callbacks.get_mut(subscription.0) {
+            *callback = None;
+        }
+    }
+
+    /// Sets the control inputs of ```instance``` whose parameters changed since the last call.
+    /// The instance should be of the plugin the model was created for.
+    pub fn apply(&mut self, instance: &mut PluginInstance) {
+        for (parameter, changed) in self.parameters.iter().zip(&mut self.changed) {
+            if *changed {
+                instance.set_control(parameter.port, parameter.value);
+                *changed = false;
+            }
+        }
+    }
+
+    /// Takes the values of the control inputs of ```instance```, as after
+    /// ```PluginInstance::restore_state```, passing the changes on to the callbacks.
+    pub fn sync(&mut self, instance: &PluginInstance) {
+        for index in 0..self.parameters.len() {
+            let value = instance.control(self.parameters[index].port);
+            self.update(index, value);
+            self.changed[index] = false;
+        }
+    }
+}
+
+impl fmt::Debug for ParameterModel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ParameterModel")
+            .field("parameters", &self.parameters)
+            .field("subscriptions", &self.callbacks.iter().flatten().count())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use super::super::tests::load_descriptor;
+    use super::super::super::{new_default, DefaultValue, Plugin, PluginDescriptor, Port,
+                              PortConnection, UniqueId, HINT_INTEGER, HINT_LOGARITHMIC,
+                              HINT_TOGGLED};
+    use ffi::ladspa_h;
+
+    #[derive(Default)]
+    struct Silent;
+
+    impl Plugin for Silent {
+        fn run<'a>(&mut self, _: usize, _: &mut [PortConnection<'a>]) {}
+    }
+
+    // The parameters of a plugin with a logarithmic, an integer and a toggled control, followed
+    // by a logarithmic range starting at 0, which descriptors made in Rust can't have but those
+    // of other plugins may.
+    fn model() -> ParameterModel {
+        let control = |name, hint, lower, upper| Port {
+            name,
+            desc: PortDescriptor::ControlInput,
+            hint: Some(hint),
+            default: Some(DefaultValue::Minimum),
+            lower_bound: Some(lower),
+            upper_bound: Some(upper),
+            ..Default::default()
+        };
+        let plugin = load_descriptor(PluginDescriptor::builder()
+                                         .unique_id(UniqueId::experimental(995).get())
+                                         .label("test_parameters")
+                                         .name("Test Parameters")
+                                         .audio_out("Output")
+                                         .port(control("Cutoff", HINT_LOGARITHMIC, 20.0, 20000.0))
+                                         .port(control("Steps", HINT_INTEGER, 0.0, 10.0))
+                                         .port(control("Bypass", HINT_TOGGLED, 0.0, 1.0))
+                                         .new(new_default::<Silent>)
+                                         .build()
+                                         .unwrap());
+        let mut model = ParameterModel::new(&plugin, 48000);
+        let logarithmic = ladspa_h::HINT_LOGARITHMIC;
+        model.parameters.push(Parameter {
+            port: 4,
+            name: String::from("Drive"),
+            range: ControlRange::from_hint(logarithmic, Some(0.0), Some(10.0), 48000),
+            unit: None,
+            default: 0.0,
+            value: 0.0,
+        });
+        model.changed.push(false);
+        model
+    }
+
+    fn assert_close(actual: Data, expected: Data) {
+        assert!((actual - expected).abs() <= expected.abs() * 1e-5 + 1e-6,
+                "{} is not {}",
+                actual,
+                expected);
+    }
+
+    #[test]
+    fn values_follow_the_hints_of_their_ports() {
+        let mut model = model();
+        let (cutoff, steps, bypass, drive) = (0, 1, 2, 3);
+        model.set_normalized(cutoff, 0.5);
+        assert_close(model.parameter(cutoff).value(), 632.4555);
+        model.set_normalized(steps, 0.34);
+        assert_eq!(model.parameter(steps).value(), 3.0);
+        model.set(bypass, 0.7);
+        assert_eq!((model.parameter(bypass).value(), model.parameter(bypass).display()),
+                   (1.0, String::from("On")));
+        model.set_normalized(drive, 0.5);
+        assert_eq!(model.parameter(drive).value(), 5.0);
+
+        for index in 0..4 {
+            for &normalized in &[0.0, 0.3, 0.5, 1.0] {
+                model.set_normalized(index, normalized);
+                let value = model.parameter(index).value();
+                let normalized = model.parameter(index).normalized();
+                model.set(index, value);
+                assert_close(model.parameter(index).value(), value);
+                model.set_normalized(index, normalized);
+                assert_close(model.parameter(index).value(), value);
+            }
+        }
+    }
+
+    #[test]
+    fn subscribers_are_told_of_each_change_once() {
+        let mut model = model();
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let (first, second) = (changes.clone(), changes.clone());
+        let subscription = model.subscribe(move |change| first.lock().unwrap().push(*change));
+        model.subscribe(move |change| second.lock().unwrap().push(*change));
+
+        model.set(1, 4.0);
+        model.set(1, 4.2);
+        let change = ParameterChange { index: 1, port: 2, value: 4.0, normalized: 0.4 };
+        assert_eq!(*changes.lock().unwrap(), [change, change]);
+
+        model.unsubscribe(subscription);
+        model.reset(1);
+        assert_eq!(changes.lock().unwrap().len(), 3);
+        assert_eq!(changes.lock().unwrap()[2].value, 0.0);
+    }
+}
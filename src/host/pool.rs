@@ -0,0 +1,216 @@
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use super::PluginInstance;
+
+// An instance for a worker to run. Only handed out for the duration of `Pool::run`, which never
+// gives the same instance to two workers.
+#[derive(Copy, Clone)]
+pub struct Job(pub *mut PluginInstance);
+
+// What the workers are to do, published by `Pool::run`.
+struct Work {
+    // Incremented for each batch of jobs, so that workers know when there is a new one.
+    generation: u64,
+    jobs: *const Job,
+    len: usize,
+    sample_count: usize,
+    // The number of workers still running jobs of the current batch.
+    pending: usize,
+    // What the first job to panic on a worker panicked with, to be passed on by `Pool::run`.
+    panic: Option<Box<dyn Any + Send>>,
+    exit: bool,
+}
+
+// The pointers are only followed between `Pool::run` publishing them and every worker having
+// finished with them.
+unsafe impl Send for Work {}
+
+struct Shared {
+    work: Mutex<Work>,
+    // Signalled when a batch is published or the workers are to exit.
+    start: Condvar,
+    // Signalled when the last worker finishes a batch.
+    done: Condvar,
+    // The index of the next job to take.
+    next: AtomicUsize,
+}
+
+// Worker threads running plugin instances in parallel, the calling thread taking part.
+pub struct Pool {
+    shared: Arc<Shared>,
+    workers: Vec<thread::JoinHandle<()>>,
+}
+
+// Runs jobs from `jobs` until none are left.
+unsafe fn take_jobs(next: &AtomicUsize, jobs: *const Job, len: usize, sample_count: usize) {
+    loop {
+        let index = next.fetch_add(1, Ordering::Relaxed);
+        if index >= len {
+            return;
+        }
+        (*(*jobs.add(index)).0).run(sample_count);
+    }
+}
+
+// Counts a worker out of the current batch when dropped, even if it panics, so that `Pool::run`
+// doesn't wait for it forever.
+struct Finished<'s>(&'s Shared);
+
+impl<'s> Drop for Finished<'s> {
+    fn drop(&mut self) {
+        let mut work = self.0.work.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        work.pending -= 1;
+        if work.pending == 0 {
+            self.0.done.notify_one();
+        }
+    }
+}
+
+fn work(shared: &Shared) {
+    let mut generation = 0;
+    loop {
+        let (jobs, len, sample_count) = {
+            let mut work = shared.work.lock().unwrap();
+            while work.generation == generation && !work.exit {
+                work = shared.start.wait(work).unwrap();
+            }
+            if work.exit {
+                return;
+            }
+            generation = work.generation;
+            (work.jobs, work.len, work.sample_count)
+        };
+        let finished = Finished(shared);
+        // Keeps the worker running if a job panics, and the panic for the caller of `Pool::run`.
+        let result = panic::catch_unwind(AssertUnwindSafe(|| unsafe {
+            take_jobs(&shared.next, jobs, len, sample_count);
+        }));
+        if let Err(payload) = result {
+            shared.work.lock().unwrap().panic.get_or_insert(payload);
+        }
+        drop(finished);
+    }
+}
+
+impl Pool {
+    // Starts `workers` threads.
+    pub fn new(workers: usize) -> Pool {
+        let shared = Arc::new(Shared {
+            work: Mutex::new(Work {
+                generation: 0,
+                jobs: std::ptr::null(),
+                len: 0,
+                sample_count: 0,
+                pending: 0,
+                panic: None,
+                exit: false,
+            }),
+            start: Condvar::new(),
+            done: Condvar::new(),
+            next: AtomicUsize::new(0),
+        });
+        let workers = (0..workers)
+            .map(|_| {
+                let shared = Arc::clone(&shared);
+                thread::Builder::new()
+                    .name(String::from("ladspa-graph"))
+                    .spawn(move || work(&shared))
+                    .expect("failed to start a graph worker thread")
+            })
+            .collect();
+        Pool { shared, workers }
+    }
+
+    // The number of threads running jobs, the calling one included.
+    pub fn threads(&self) -> usize {
+        self.workers.len() + 1
+    }
+
+    // Runs every job over `sample_count` samples, returning once they have all run. Each job must
+    // point to a different instance, which nothing else uses until this returns. If a job panics,
+    // the others still run, and the panic is passed on once the workers are done with `jobs`.
+    pub unsafe fn run(&self, jobs: &[Job], sample_count: usize) {
+        {
+            let mut work = self.shared.work.lock().unwrap();
+            self.shared.next.store(0, Ordering::Relaxed);
+            work.generation += 1;
+            work.jobs = jobs.as_ptr();
+            work.len = jobs.len();
+            work.sample_count = sample_count;
+            work.pending = self.workers.len();
+            self.shared.start.notify_all();
+        }
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            take_jobs(&self.shared.next, jobs.as_ptr(), jobs.len(), sample_count);
+        }));
+        let mut work = self.shared.work.lock().unwrap();
+        while work.pending > 0 {
+            work = self.shared.done.wait(work).unwrap();
+        }
+        let panic = work.panic.take();
+        drop(work);
+        if let Some(payload) = result.err().or(panic) {
+            panic::resume_unwind(payload);
+        }
+    }
+}
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        self.shared.work.lock().unwrap().exit = true;
+        self.shared.start.notify_all();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{self, AssertUnwindSafe};
+
+    use super::{Job, Pool};
+    use super::super::tests::delay;
+
+    #[test]
+    fn jobs_run_on_every_thread() {
+        let plugin = delay();
+        let mut instances: Vec<_> =
+            (0..8).map(|_| plugin.instantiate(48000, 16).unwrap()).collect();
+        for (n, instance) in instances.iter_mut().enumerate() {
+            instance.activate();
+            instance.input_mut(0)[0] = n as f32;
+        }
+        let jobs: Vec<_> = instances.iter_mut().map(|instance| Job(instance)).collect();
+        let pool = Pool::new(3);
+        unsafe {
+            pool.run(&jobs, 16);
+        }
+        for (n, instance) in instances.iter().enumerate() {
+            assert_eq!(instance.output(1)[0], n as f32);
+        }
+    }
+
+    #[test]
+    fn panics_are_passed_on_once_every_job_has_run() {
+        let plugin = delay();
+        // Running the small instances over more samples than their block size panics.
+        let mut instances: Vec<_> = (0..8)
+            .map(|n| plugin.instantiate(48000, if n % 3 == 0 { 8 } else { 16 }).unwrap())
+            .collect();
+        let jobs: Vec<_> = instances.iter_mut().map(|instance| Job(instance)).collect();
+        let pool = Pool::new(3);
+        // Whichever thread the panics happen on, the pool keeps working.
+        for _ in 0..50 {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| unsafe { pool.run(&jobs, 16) }));
+            assert!(result.is_err());
+        }
+        unsafe {
+            pool.run(&jobs, 8);
+        }
+    }
+}
@@ -0,0 +1,782 @@
+use std::convert::TryInto;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::mem::size_of;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::{Path, PathBuf};
+use std::process::{self, Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use libc::{self, c_int, c_void};
+
+use ffi::ladspa_h;
+use super::{decode_port_descriptor, load, HostError, LoadedPlugin, PluginInstance};
+use super::super::{default_for, Data, PortDescriptor, Unit};
+use super::super::mapping::ControlRange;
+
+// Set in the environment of sandbox processes: the shared memory file, the library, the unique ID
+// of the plugin, the sample rate and the block size, one per line.
+const SANDBOX_ENV: &str = "LADSPA_RS_SANDBOX";
+
+// The commands sent to the sandbox process, one byte each, and its reply once it has carried one
+// out. Once started, the process first sends the descriptor of the plugin, and needs to be told
+// to map the shared memory before running anything.
+const MAP: u8 = b'm';
+const ACTIVATE: u8 = b'a';
+const DEACTIVATE: u8 = b'd';
+const RUN: u8 = b'r';
+const DONE: u8 = b'k';
+
+// Numbers the shared memory files of the sandboxes of this process.
+static SANDBOXES: AtomicUsize = AtomicUsize::new(0);
+
+// A file mapped into the memory of both processes. Starts with the sample count of the block to
+// run, followed by a value per control port and a block of samples per audio port, in port order.
+struct SharedMemory {
+    path: PathBuf,
+    data: *mut Data,
+    len: usize,
+}
+
+// The number of values in the shared memory of a plugin with ports `ports`, and where the data of
+// each port starts.
+fn layout<I>(ports: I, block_size: usize) -> (usize, Vec<usize>)
+    where I: IntoIterator<Item = PortDescriptor>
+{
+    let mut len = 1;
+    let offsets = ports.into_iter()
+        .map(|port| {
+            let offset = len;
+            len += match port {
+                PortDescriptor::AudioInput | PortDescriptor::AudioOutput => block_size,
+                _ => 1,
+            };
+            offset
+        })
+        .collect();
+    (len, offsets)
+}
+
+impl SharedMemory {
+    // Maps `len` values of the file at `path`, creating it if `create` is set. A file created here
+    // is readable and writable by its owner only, as it holds the audio of the host.
+    fn map(path: &Path, len: usize, create: bool) -> Result<SharedMemory, String> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(create)
+            .mode(0o600)
+            .open(path)
+            .map_err(|error| format!("{}: {}", path.display(), error))?;
+        let bytes = len * size_of::<Data>();
+        if create {
+            file.set_len(bytes as u64).map_err(|error| error.to_string())?;
+        }
+        let data = unsafe {
+            libc::mmap(ptr::null_mut(),
+                       bytes,
+                       libc::PROT_READ | libc::PROT_WRITE,
+                       libc::MAP_SHARED,
+                       file.as_raw_fd(),
+                       0)
+        };
+        if data == libc::MAP_FAILED {
+            return Err(String::from("failed to map the shared memory"));
+        }
+        Ok(SharedMemory { path: path.to_path_buf(), data: data as *mut Data, len })
+    }
+
+    fn values(&mut self) -> &mut [Data] {
+        unsafe { slice::from_raw_parts_mut(self.data, self.len) }
+    }
+}
+
+impl Drop for SharedMemory {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.data as *mut c_void, self.len * size_of::<Data>());
+        }
+    }
+}
+
+// A port of a `SandboxedPlugin`.
+#[derive(Clone)]
+struct SandboxedPort {
+    name: String,
+    descriptor: i32,
+    hint: ladspa_h::PortRangeHint,
+}
+
+/// The descriptor of a plugin running in a sandbox process, as the process reported it. Mirrors
+/// the metadata of ```LoadedPlugin```, without the library being opened in this process.
+#[derive(Clone)]
+pub struct SandboxedPlugin {
+    path: PathBuf,
+    unique_id: u64,
+    label: String,
+    name: String,
+    maker: String,
+    copyright: String,
+    properties: i32,
+    ports: Vec<SandboxedPort>,
+}
+
+impl SandboxedPlugin {
+    /// The path of the library the plugin was loaded from.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The unique ID of the plugin.
+    pub fn unique_id(&self) -> u64 {
+        self.unique_id
+    }
+
+    /// The label of the plugin.
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// The name of the plugin.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The maker of the plugin.
+    pub fn maker(&self) -> &str {
+        &self.maker
+    }
+
+    /// The copyright of the plugin.
+    pub fn copyright(&self) -> &str {
+        &self.copyright
+    }
+
+    /// The raw LADSPA property bits of the plugin.
+    pub fn properties(&self) -> i32 {
+        self.properties
+    }
+
+    /// The number of ports the plugin has.
+    pub fn port_count(&self) -> usize {
+        self.ports.len()
+    }
+
+    /// The name of port ```port```. Panics if the port does not exist.
+    pub fn port_name(&self, port: usize) -> &str {
+        &self.ports[port].name
+    }
+
+    /// The index of the first port named ```name```, or ```None``` if there is no such port.
+    pub fn port_index(&self, name: &str) -> Option<usize> {
+        self.ports.iter().position(|port| port.name == name)
+    }
+
+    /// The type of port ```port```. Panics if the port does not exist.
+    pub fn port_descriptor(&self, port: usize) -> PortDescriptor {
+        decode_port_descriptor(self.ports[port].descriptor)
+    }
+
+    /// The raw LADSPA descriptor bits of port ```port```. Panics if the port does not exist.
+    pub fn port_descriptor_bits(&self, port: usize) -> i32 {
+        self.ports[port].descriptor
+    }
+
+    /// What the value of port ```port``` measures, as far as its name tells. See
+    /// ```LoadedPlugin::port_unit```. Panics if the port does not exist.
+    pub fn port_unit(&self, port: usize) -> Option<Unit> {
+        Unit::from_name(self.port_name(port))
+    }
+
+    /// The range hint of port ```port```. Panics if the port does not exist.
+    pub fn port_range_hint(&self, port: usize) -> ladspa_h::PortRangeHint {
+        self.ports[port].hint
+    }
+
+    /// The value port ```port``` starts at in an instance running at ```sample_rate```, or
+    /// ```None``` if it has no default. Panics if the port does not exist.
+    pub fn default_value(&self, port: usize, sample_rate: u64) -> Option<Data> {
+        let hint = self.ports[port].hint;
+        default_for(hint.hint_descriptor, hint.lower_bound, hint.upper_bound, sample_rate)
+    }
+
+    /// The values port ```port``` covers in an instance running at ```sample_rate```. See
+    /// ```LoadedPlugin::port_range```. Panics if the port does not exist.
+    pub fn port_range(&self, port: usize, sample_rate: u64) -> ControlRange {
+        let hint = self.ports[port].hint;
+        let bits = hint.hint_descriptor;
+        ControlRange::from_hint(bits,
+                                Some(hint.lower_bound)
+                                    .filter(|_| bits & ladspa_h::HINT_BOUNDED_BELOW != 0),
+                                Some(hint.upper_bound)
+                                    .filter(|_| bits & ladspa_h::HINT_BOUNDED_ABOVE != 0),
+                                sample_rate)
+    }
+
+    fn port_descriptors(&self) -> impl Iterator<Item = PortDescriptor> + '_ {
+        (0..self.port_count()).map(move |port| self.port_descriptor(port))
+    }
+}
+
+// Encodes the descriptor of `plugin`, to be sent to the host by the sandbox process.
+fn encode(plugin: &LoadedPlugin) -> Vec<u8> {
+    fn string(message: &mut Vec<u8>, string: &str) {
+        message.extend_from_slice(&(string.len() as u32).to_le_bytes());
+        message.extend_from_slice(string.as_bytes());
+    }
+    let mut message = Vec::new();
+    message.extend_from_slice(&plugin.unique_id().to_le_bytes());
+    string(&mut message, plugin.label());
+    string(&mut message, plugin.name());
+    string(&mut message, plugin.maker());
+    string(&mut message, plugin.copyright());
+    message.extend_from_slice(&plugin.properties().to_le_bytes());
+    message.extend_from_slice(&(plugin.port_count() as u32).to_le_bytes());
+    for port in 0..plugin.port_count() {
+        let hint = plugin.port_range_hint(port);
+        string(&mut message, plugin.port_name(port));
+        message.extend_from_slice(&plugin.port_descriptor_bits(port).to_le_bytes());
+        message.extend_from_slice(&hint.hint_descriptor.to_le_bytes());
+        message.extend_from_slice(&hint.lower_bound.to_le_bytes());
+        message.extend_from_slice(&hint.upper_bound.to_le_bytes());
+    }
+    let mut framed = (message.len() as u32).to_le_bytes().to_vec();
+    framed.extend(message);
+    framed
+}
+
+// Reads back the fields of a message made by `encode`.
+struct Decoder<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        if self.bytes.len() < N {
+            return Err(String::from("truncated descriptor"));
+        }
+        let (field, rest) = self.bytes.split_at(N);
+        self.bytes = rest;
+        Ok(field.try_into().unwrap())
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        self.take().map(u32::from_le_bytes)
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        self.take().map(i32::from_le_bytes)
+    }
+
+    fn f32(&mut self) -> Result<f32, String> {
+        self.take().map(f32::from_le_bytes)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.u32()? as usize;
+        if self.bytes.len() < len {
+            return Err(String::from("truncated descriptor"));
+        }
+        let (string, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        String::from_utf8(string.to_vec()).map_err(|error| error.to_string())
+    }
+
+    fn plugin(&mut self, path: &Path) -> Result<SandboxedPlugin, String> {
+        let unique_id = self.take().map(u64::from_le_bytes)?;
+        let (label, name, maker, copyright) =
+            (self.string()?, self.string()?, self.string()?, self.string()?);
+        let properties = self.i32()?;
+        let ports = (0..self.u32()?)
+            .map(|_| {
+                Ok(SandboxedPort {
+                    name: self.string()?,
+                    descriptor: self.i32()?,
+                    hint: ladspa_h::PortRangeHint {
+                        hint_descriptor: self.i32()?,
+                        lower_bound: self.f32()?,
+                        upper_bound: self.f32()?,
+                    },
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(SandboxedPlugin {
+            path: path.to_path_buf(),
+            unique_id,
+            label,
+            name,
+            maker,
+            copyright,
+            properties,
+            ports,
+        })
+    }
+}
+
+// The sandbox process of a `SandboxedInstance`.
+struct Process {
+    child: Child,
+    commands: ChildStdin,
+    replies: ChildStdout,
+}
+
+impl Process {
+    // Sends `command` and waits up to `timeout` for the reply. Returns `false` if the process
+    // crashed or didn't reply in time.
+    fn send(&mut self, command: u8, timeout: Duration) -> bool {
+        if self.commands.write_all(&[command]).is_err() || !self.wait(timeout) {
+            return false;
+        }
+        let mut reply = [0];
+        self.replies.read_exact(&mut reply).is_ok() && reply[0] == DONE
+    }
+
+    // Waits up to `timeout` for the process to reply, or to exit.
+    fn wait(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut poll = libc::pollfd {
+            fd: self.replies.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            let left = left.as_millis().min(c_int::MAX as u128) as c_int;
+            match unsafe { libc::poll(&mut poll, 1, left) } {
+                1 => return true,
+                // Signals handled by this process interrupt the wait.
+                -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => {}
+                _ => return false,
+            }
+        }
+    }
+
+    // Reads the descriptor the process sends once it has instantiated the plugin.
+    fn descriptor(&mut self, path: &Path, timeout: Duration) -> Result<SandboxedPlugin, String> {
+        let failed = || String::from("the sandbox process failed to start");
+        if !self.wait(timeout) {
+            return Err(failed());
+        }
+        let mut len = [0; 4];
+        self.replies.read_exact(&mut len).map_err(|_| failed())?;
+        let mut message = vec![0; u32::from_le_bytes(len) as usize];
+        self.replies.read_exact(&mut message).map_err(|_| failed())?;
+        Decoder { bytes: &message }.plugin(path)
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// Starts the sandbox processes of a `SandboxedInstance`.
+#[derive(Clone)]
+struct Launcher {
+    helper: PathBuf,
+    library: PathBuf,
+    settings: String,
+    timeout: Duration,
+}
+
+impl Launcher {
+    // Starts a sandbox process, and waits for it to instantiate the plugin and describe it.
+    fn spawn(&self) -> Result<(Process, SandboxedPlugin), HostError> {
+        let mut child = Command::new(&self.helper)
+            .env(SANDBOX_ENV, &self.settings)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|error| HostError::Sandbox(error.to_string()))?;
+        let mut process = Process {
+            commands: child.stdin.take().unwrap(),
+            replies: child.stdout.take().unwrap(),
+            child,
+        };
+        // Instantiating may take a while longer than running a block.
+        let plugin = process.descriptor(&self.library, self.timeout * 10)
+            .map_err(HostError::Sandbox)?;
+        Ok((process, plugin))
+    }
+
+    // Starts a sandbox process for a plugin already described by `plugin`, with the shared
+    // memory already created.
+    fn respawn(&self, plugin: &SandboxedPlugin) -> Result<Process, HostError> {
+        let (mut process, respawned) = self.spawn()?;
+        let ports = |plugin: &SandboxedPlugin| -> Vec<i32> {
+            plugin.ports.iter().map(|port| port.descriptor).collect()
+        };
+        if ports(&respawned) != ports(plugin) {
+            return Err(HostError::Sandbox(String::from("the plugin has changed")));
+        }
+        if !process.send(MAP, self.timeout) {
+            return Err(HostError::Sandbox(String::from("the sandbox process failed to start")));
+        }
+        Ok(process)
+    }
+}
+
+// Starts a new process whenever one is requested, until the instance is dropped, trying again
+// after the timeout if it fails.
+fn restart(launcher: Launcher,
+           plugin: SandboxedPlugin,
+           requests: Receiver<()>,
+           restarted: SyncSender<Process>) {
+    while requests.recv().is_ok() {
+        loop {
+            match launcher.respawn(&plugin) {
+                Ok(process) => {
+                    if restarted.send(process).is_err() {
+                        return;
+                    }
+                    break;
+                }
+                Err(_) => {
+                    if let Err(RecvTimeoutError::Disconnected) =
+                        requests.recv_timeout(launcher.timeout) {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// An instance of a plugin running in a separate process, so that a plugin which crashes or
+/// hangs can't take the host down with it.
+///
+/// The process is the host program itself, started again, unless another program is given with
+/// ```with_helper```; either way it must call ```serve_sandbox``` first thing in ```main```:
+///
+/// ```no_run
+/// use ladspa::host::{self, SandboxedInstance};
+///
+/// fn main() {
+///     host::serve_sandbox();
+///     let mut reverb = SandboxedInstance::spawn("/usr/lib/ladspa/reverb.so", 1050, 48000, 256)
+///         .expect("failed to start the sandbox");
+///     reverb.activate();
+///     reverb.input_mut(0).fill(0.5);
+///     reverb.run(256);
+/// }
+/// ```
+///
+/// The library is only opened in the sandbox process, which sends the descriptor of the plugin
+/// back, so none of the code of the plugin runs in this one. Audio and control values go through
+/// memory shared with the process, and only a byte goes through a pipe each way to have it run a
+/// block.
+///
+/// If the process dies, or takes longer than the timeout to run a block, it is killed and the
+/// outputs are silenced. A new process is started on another thread, so that ```run``` doesn't
+/// wait for it: the blocks run until it is ready are silent too. It gets the same control values,
+/// and is activated if the instance is, but the plugin loses any other state it had.
+pub struct SandboxedInstance {
+    plugin: SandboxedPlugin,
+    block_size: usize,
+    timeout: Duration,
+    memory: SharedMemory,
+    offsets: Vec<usize>,
+    process: Option<Process>,
+    // Asks for a new process after a crash, and hands it over once started. Bounded, so that
+    // neither end allocates.
+    requests: SyncSender<()>,
+    restarted: Receiver<Process>,
+    active: bool,
+    crashes: usize,
+}
+
+// The shared memory is only used by one thread of this process at a time.
+unsafe impl Send for SandboxedInstance {}
+
+impl SandboxedInstance {
+    /// Starts a sandbox process running an instance of the plugin with unique ID
+    /// ```unique_id``` in library ```library```, at ```sample_rate```, able to process blocks of
+    /// up to ```block_size``` samples. The process is this program.
+    pub fn spawn<P: AsRef<Path>>(library: P,
+                                 unique_id: u64,
+                                 sample_rate: u64,
+                                 block_size: usize)
+                                 -> Result<SandboxedInstance, HostError> {
+        let helper = env::current_exe().map_err(|error| HostError::Sandbox(error.to_string()))?;
+        SandboxedInstance::with_helper(library, unique_id, sample_rate, block_size, &helper)
+    }
+
+    /// Like ```spawn```, with ```helper``` as the sandbox process. It must call
+    /// ```serve_sandbox``` first thing in ```main```.
+    pub fn with_helper<P: AsRef<Path>>(library: P,
+                                       unique_id: u64,
+                                       sample_rate: u64,
+                                       block_size: usize,
+                                       helper: &Path)
+                                       -> Result<SandboxedInstance, HostError> {
+        let failed = |message: &str| HostError::Sandbox(String::from(message));
+        let library = library.as_ref();
+        let path = env::temp_dir().join(format!("ladspa-rs-sandbox-{}-{}",
+                                                process::id(),
+                                                SANDBOXES.fetch_add(1, Ordering::Relaxed)));
+        let settings = [path.to_str().ok_or_else(|| failed("temp path not UTF-8"))?,
+                        library.to_str().ok_or_else(|| failed("library path not UTF-8"))?,
+                        &unique_id.to_string(),
+                        &sample_rate.to_string(),
+                        &block_size.to_string()]
+            .join("\n");
+        let launcher = Launcher {
+            helper: helper.to_path_buf(),
+            library: library.to_path_buf(),
+            settings,
+            timeout: Duration::from_secs(1),
+        };
+        let (mut process, plugin) = launcher.spawn()?;
+        let (len, offsets) = layout(plugin.port_descriptors(), block_size);
+        let memory = SharedMemory::map(&path, len, true).map_err(HostError::Sandbox)?;
+        let (requests, requested) = mpsc::sync_channel(1);
+        let (started, restarted) = mpsc::sync_channel(1);
+        let mut instance = SandboxedInstance {
+            plugin: plugin.clone(),
+            block_size,
+            timeout: launcher.timeout,
+            memory,
+            offsets,
+            process: None,
+            requests,
+            restarted,
+            active: false,
+            crashes: 0,
+        };
+        for port in 0..plugin.port_count() {
+            if let PortDescriptor::ControlInput = plugin.port_descriptor(port) {
+                let value = plugin.default_value(port, sample_rate).unwrap_or(0.0);
+                instance.set_control(port, value);
+            }
+        }
+        if !process.send(MAP, launcher.timeout) {
+            return Err(failed("the sandbox process failed to start"));
+        }
+        instance.process = Some(process);
+        thread::Builder::new()
+            .name(String::from("ladspa-sandbox"))
+            .spawn(move || restart(launcher, plugin, requested, started))
+            .map_err(|error| HostError::Sandbox(error.to_string()))?;
+        Ok(instance)
+    }
+
+    /// The plugin this is an instance of.
+    pub fn plugin(&self) -> &SandboxedPlugin {
+        &self.plugin
+    }
+
+    /// The largest number of samples which can be processed by a single call to ```run```.
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// Sets how long the process may take to run a block before it is taken to have hung. One
+    /// second by default.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// The number of times the process has crashed or hung.
+    pub fn crashes(&self) -> usize {
+        self.crashes
+    }
+
+    /// Whether a process is running the plugin, rather than being restarted after a crash.
+    pub fn is_running(&self) -> bool {
+        self.process.is_some()
+    }
+
+    /// Returns the current value of control port ```port```.
+    pub fn control(&self, port: usize) -> Data {
+        unsafe { *self.memory.data.add(self.offsets[port]) }
+    }
+
+    /// Sets the value of control input ```port```, to be read on the next call to ```run```.
+    pub fn set_control(&mut self, port: usize, value: Data) {
+        let offset = self.offsets[port];
+        self.memory.values()[offset] = value;
+    }
+
+    /// Returns the buffer of audio input ```port``` so it can be filled before ```run```.
+    pub fn input_mut(&mut self, port: usize) -> &mut [Data] {
+        let offset = self.offsets[port];
+        let block_size = self.block_size;
+        &mut self.memory.values()[offset..offset + block_size]
+    }
+
+    /// Returns the buffer of audio output ```port```.
+    pub fn output(&self, port: usize) -> &[Data] {
+        unsafe { slice::from_raw_parts(self.memory.data.add(self.offsets[port]), self.block_size) }
+    }
+
+    /// Activates the instance. Does nothing if it is already active.
+    pub fn activate(&mut self) {
+        if !self.active {
+            self.active = true;
+            self.send(ACTIVATE);
+        }
+    }
+
+    /// Deactivates the instance. Does nothing if it is not active.
+    pub fn deactivate(&mut self) {
+        if self.active {
+            self.active = false;
+            self.send(DEACTIVATE);
+        }
+    }
+
+    /// Processes ```sample_count``` samples in the sandbox process. If it crashes, or is still
+    /// being restarted after a crash, the outputs are silenced. Panics if ```sample_count```
+    /// exceeds the block size.
+    pub fn run(&mut self, sample_count: usize) {
+        assert!(sample_count <= self.block_size, "sample count exceeds block size");
+        if self.process.is_none() {
+            if let Ok(process) = self.restarted.try_recv() {
+                self.process = Some(process);
+                if self.active {
+                    self.send(ACTIVATE);
+                }
+            }
+        }
+        self.memory.values()[0] = Data::from_bits(sample_count as u32);
+        if !self.send(RUN) {
+            self.silence();
+        }
+    }
+
+    // Sends `command` to the process, if there is one, dropping it and having a new one started
+    // if it fails to carry it out.
+    fn send(&mut self, command: u8) -> bool {
+        let timeout = self.timeout;
+        let done = match self.process {
+            Some(ref mut process) => process.send(command, timeout),
+            None => return false,
+        };
+        if !done {
+            self.process = None;
+            self.crashes += 1;
+            let _ = self.requests.try_send(());
+        }
+        done
+    }
+
+    // Zeroes the audio outputs.
+    fn silence(&mut self) {
+        for port in 0..self.plugin.port_count() {
+            if let PortDescriptor::AudioOutput = self.plugin.port_descriptor(port) {
+                let offset = self.offsets[port];
+                let block_size = self.block_size;
+                self.memory.values()[offset..offset + block_size].fill(0.0);
+            }
+        }
+    }
+}
+
+impl Drop for SandboxedInstance {
+    fn drop(&mut self) {
+        // Closing the pipe makes the process clean up and exit, and closing the channels stops
+        // the thread restarting it.
+        self.process = None;
+        let _ = fs::remove_file(&self.memory.path);
+    }
+}
+
+/// Serves a ```SandboxedInstance``` and exits if this process was started as its sandbox, and
+/// returns at once otherwise. Call it first thing in ```main``` in programs which sandbox
+/// plugins, before anything which shouldn't happen twice.
+pub fn serve_sandbox() {
+    let settings = match env::var(SANDBOX_ENV) {
+        Ok(settings) => settings,
+        Err(_) => return,
+    };
+    process::exit(match serve(&settings) {
+        Ok(()) => 0,
+        Err(message) => {
+            eprintln!("ladspa.rs sandbox: {}", message);
+            1
+        }
+    });
+}
+
+// Runs the plugin described by `settings`, as commanded through standard input.
+fn serve(settings: &str) -> Result<(), String> {
+    // Replies go through a copy of standard output, which is pointed at standard error so that
+    // whatever the plugin prints doesn't get mixed up with them, from the moment its library is
+    // loaded.
+    let mut replies = unsafe {
+        let replies = libc::dup(1);
+        if replies < 0 || libc::dup2(2, 1) < 0 {
+            return Err(String::from("failed to redirect standard output"));
+        }
+        File::from_raw_fd(replies)
+    };
+    let settings: Vec<&str> = settings.split('\n').collect();
+    let [path, library, unique_id, sample_rate, block_size] = settings[..] else {
+        return Err(String::from("malformed settings"));
+    };
+    let number = |value: &str| value.parse::<u64>().map_err(|error| error.to_string());
+    let (unique_id, sample_rate) = (number(unique_id)?, number(sample_rate)?);
+    let block_size = number(block_size)? as usize;
+    let plugins = load(library).map_err(|error| error.to_string())?;
+    let plugin = plugins.iter()
+        .find(|plugin| plugin.unique_id() == unique_id)
+        .ok_or_else(|| String::from("plugin not found in the library"))?;
+    let (len, offsets) = layout((0..plugin.port_count()).map(|port| plugin.port_descriptor(port)),
+                                block_size);
+    let mut instance = plugin.instantiate(sample_rate, block_size)
+        .map_err(|error| error.to_string())?;
+    replies.write_all(&encode(plugin)).map_err(|error| error.to_string())?;
+    let mut memory = None;
+    let mut commands = io::stdin().lock();
+    let mut command = [0];
+    // Stops once the other end of the pipe is closed.
+    while commands.read_exact(&mut command).is_ok() {
+        match (command[0], memory.as_mut()) {
+            (MAP, _) => memory = Some(SharedMemory::map(Path::new(path), len, false)?),
+            (ACTIVATE, _) => instance.activate(),
+            (DEACTIVATE, _) => instance.deactivate(),
+            (RUN, Some(memory)) => run(plugin, &mut instance, memory.values(), &offsets),
+            _ => return Err(String::from("unexpected command")),
+        }
+        replies.write_all(&[DONE]).map_err(|error| error.to_string())?;
+    }
+    Ok(())
+}
+
+// Runs `instance` over the block in `values`, laid out at `offsets`.
+fn run(plugin: &LoadedPlugin,
+       instance: &mut PluginInstance,
+       values: &mut [Data],
+       offsets: &[usize]) {
+    let sample_count = (values[0].to_bits() as usize).min(instance.block_size());
+    for (port, &offset) in offsets.iter().enumerate() {
+        match plugin.port_descriptor(port) {
+            PortDescriptor::ControlInput => instance.set_control(port, values[offset]),
+            PortDescriptor::AudioInput => {
+                instance.input_mut(port)[..sample_count]
+                    .copy_from_slice(&values[offset..offset + sample_count]);
+            }
+            _ => {}
+        }
+    }
+    instance.run(sample_count);
+    for (port, &offset) in offsets.iter().enumerate() {
+        match plugin.port_descriptor(port) {
+            PortDescriptor::ControlOutput => values[offset] = instance.control(port),
+            PortDescriptor::AudioOutput => {
+                values[offset..offset + sample_count]
+                    .copy_from_slice(&instance.output(port)[..sample_count]);
+            }
+            _ => {}
+        }
+    }
+}
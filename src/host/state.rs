@@ -0,0 +1,101 @@
+use std::convert::TryInto;
+
+use super::super::Data;
+
+// Starts every state encoded by `InstanceState::to_bytes`, followed by a format version.
+const MAGIC: &[u8; 4] = b"LRSS";
+const VERSION: u8 = 1;
+
+/// Everything needed to bring a ```PluginInstance``` back to where it was, as returned by
+/// ```PluginInstance::save_state```.
+///
+/// ```to_bytes``` and ```from_bytes``` convert it to a portable format for storing in files:
+///
+/// ```no_run
+/// # use std::fs;
+/// # use ladspa::host::{self, Chain, InstanceState};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let mut chain = Chain::new(256);
+/// # let eq = chain.push(&host::discover().plugins()[0], 48000)?;
+/// fs::write("rack.state", chain.node(eq).save_state().to_bytes())?;
+/// let state = InstanceState::from_bytes(&fs::read("rack.state")?).expect("corrupt state");
+/// chain.node_mut(eq).restore_state(&state);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InstanceState {
+    /// The port number and value of every control input.
+    pub controls: Vec<(usize, Data)>,
+    /// The opaque state of a ```Stateful``` plugin built with this crate, or ```None``` for
+    /// other plugins.
+    pub data: Option<Vec<u8>>,
+}
+
+impl InstanceState {
+    /// Encodes the state, in a format which doesn't depend on the platform.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&(self.controls.len() as u32).to_le_bytes());
+        for &(port, value) in &self.controls {
+            bytes.extend_from_slice(&(port as u32).to_le_bytes());
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        match self.data {
+            Some(ref data) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+                bytes.extend_from_slice(data);
+            }
+            None => bytes.push(0),
+        }
+        bytes
+    }
+
+    /// Decodes a state encoded by ```to_bytes```, or returns ```None``` if ```bytes``` doesn't
+    /// hold one.
+    pub fn from_bytes(bytes: &[u8]) -> Option<InstanceState> {
+        let mut reader = Reader(bytes);
+        if reader.take(MAGIC.len())? != MAGIC || reader.take(1)? != [VERSION] {
+            return None;
+        }
+        let count = reader.u32()? as usize;
+        let mut controls = Vec::with_capacity(count.min(bytes.len() / 8));
+        for _ in 0..count {
+            let port = reader.u32()? as usize;
+            let value = Data::from_le_bytes(reader.take(4)?.try_into().ok()?);
+            controls.push((port, value));
+        }
+        let data = match reader.take(1)? {
+            [0] => None,
+            [1] => {
+                let len = reader.u32()? as usize;
+                Some(reader.take(len)?.to_vec())
+            }
+            _ => return None,
+        };
+        if !reader.0.is_empty() {
+            return None;
+        }
+        Some(InstanceState { controls, data })
+    }
+}
+
+// Reads the fields of an encoded state in order.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(taken)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+}
@@ -0,0 +1,146 @@
+//! Installing built plugin libraries where hosts look for them.
+//!
+//! Cargo names libraries after the platform's conventions, but LADSPA hosts don't always follow
+//! them: on macOS they scan for ```.so``` files rather than ```.dylib```. ```install``` copies a
+//! library into the user's or the system's LADSPA directory under the name hosts expect:
+//!
+//! ```no_run
+//! # use std::path::Path;
+//! # use ladspa::install::Scope;
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let path = ladspa::install::install(Path::new("target/release/libmy_gain.dylib"),
+//!                                     Scope::User)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The ```cargo-ladspa``` binary does the same as ```cargo ladspa install LIBRARY```.
+//!
+//! The directories for ```Scope::User``` and ```Scope::System``` are:
+//!
+//! * on Linux and other Unix systems, ```~/.ladspa``` and ```/usr/local/lib/ladspa```;
+//! * on macOS, ```~/Library/Audio/Plug-Ins/LADSPA``` and ```/Library/Audio/Plug-Ins/LADSPA```;
+//! * on Windows, ```%APPDATA%\LADSPA``` and ```%ProgramFiles%\LADSPA```.
+
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Whether to install for the current user only or for everyone.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Scope {
+    /// A directory in the user's home, which needs no special permissions.
+    User,
+    /// A directory shared by all users, which usually needs administrator rights to write to.
+    System,
+}
+
+/// The ways in which installing a library can fail.
+#[derive(Debug)]
+pub enum InstallError {
+    /// The environment variable locating the directory for the scope is not set.
+    NoDirectory(Scope),
+    /// The file to install doesn't have the extension of a shared library.
+    NotALibrary(PathBuf),
+    /// The library could not be copied.
+    Io(io::Error),
+}
+
+impl fmt::Display for InstallError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InstallError::NoDirectory(scope) => {
+                write!(f, "could not locate the {:?} LADSPA directory", scope)
+            }
+            InstallError::NotALibrary(ref path) => {
+                write!(f, "{} is not a shared library", path.display())
+            }
+            InstallError::Io(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for InstallError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            InstallError::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for InstallError {
+    fn from(err: io::Error) -> InstallError {
+        InstallError::Io(err)
+    }
+}
+
+// The extension hosts look for. On macOS, they expect the same as on other Unix systems.
+#[cfg(unix)]
+const EXTENSION: &str = "so";
+#[cfg(windows)]
+const EXTENSION: &str = "dll";
+
+/// The LADSPA directory for ```scope``` on this platform, or ```None``` if the environment
+/// variable it is based on is not set.
+pub fn directory(scope: Scope) -> Option<PathBuf> {
+    let (variable, base) = match scope {
+        Scope::User => USER_DIRECTORY,
+        Scope::System => SYSTEM_DIRECTORY,
+    };
+    match variable {
+        Some(variable) => env::var_os(variable).map(|value| Path::new(&value).join(base)),
+        None => Some(PathBuf::from(base)),
+    }
+}
+
+// The directories for each scope, as an optional environment variable and a path relative to it.
+#[cfg(target_os = "macos")]
+const USER_DIRECTORY: (Option<&str>, &str) = (Some("HOME"), "Library/Audio/Plug-Ins/LADSPA");
+#[cfg(target_os = "macos")]
+const SYSTEM_DIRECTORY: (Option<&str>, &str) = (None, "/Library/Audio/Plug-Ins/LADSPA");
+#[cfg(all(unix, not(target_os = "macos")))]
+const USER_DIRECTORY: (Option<&str>, &str) = (Some("HOME"), ".ladspa");
+#[cfg(all(unix, not(target_os = "macos")))]
+const SYSTEM_DIRECTORY: (Option<&str>, &str) = (None, "/usr/local/lib/ladspa");
+#[cfg(windows)]
+const USER_DIRECTORY: (Option<&str>, &str) = (Some("APPDATA"), "LADSPA");
+#[cfg(windows)]
+const SYSTEM_DIRECTORY: (Option<&str>, &str) = (Some("ProgramFiles"), "LADSPA");
+
+/// The file name ```library``` is installed under: its own, with the extension hosts look for.
+/// Returns ```None``` if it is not a shared library as built by Cargo.
+pub fn installed_name(library: &Path) -> Option<String> {
+    if library.extension() != Some(env::consts::DLL_EXTENSION.as_ref()) {
+        return None;
+    }
+    let stem = library.file_stem()?.to_str()?;
+    Some(format!("{}.{}", stem, EXTENSION))
+}
+
+/// Copies ```library``` to the LADSPA directory for ```scope```, creating it if needed. Returns
+/// the path of the installed copy.
+pub fn install(library: &Path, scope: Scope) -> Result<PathBuf, InstallError> {
+    let dir = directory(scope).ok_or(InstallError::NoDirectory(scope))?;
+    install_to(library, &dir)
+}
+
+/// Copies ```library``` to ```dir``` under its ```installed_name```, creating ```dir``` if needed.
+/// Returns the path of the installed copy.
+pub fn install_to(library: &Path, dir: &Path) -> Result<PathBuf, InstallError> {
+    let name = installed_name(library).ok_or_else(|| InstallError::NotALibrary(library.into()))?;
+    fs::create_dir_all(dir)?;
+    let target = dir.join(name);
+    // Overwriting a library in place corrupts it for the processes which have it loaded, and on
+    // macOS invalidates its code signature, so the old one is unlinked first.
+    if let Err(err) = fs::remove_file(&target) {
+        if err.kind() != io::ErrorKind::NotFound {
+            return Err(err.into());
+        }
+    }
+    fs::copy(library, &target)?;
+    Ok(target)
+}
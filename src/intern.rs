@@ -0,0 +1,22 @@
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use super::lock::Lock;
+
+// Every string interned so far. Lookups are linear, which is fine for the few dozen port names
+// of a plugin library.
+static STRINGS: Lock<Vec<&'static str>> = Lock::new(Vec::new());
+
+// Returns a string equal to `s` which lives as long as the program. Each distinct string is
+// leaked once, however often it is interned, so descriptors can be built from generated names
+// without growing memory every time.
+pub fn intern(s: String) -> &'static str {
+    let mut strings = STRINGS.lock();
+    if let Some(&interned) = strings.iter().find(|&&interned| interned == s) {
+        return interned;
+    }
+    let interned: &'static str = Box::leak(s.into_boxed_str());
+    strings.push(interned);
+    interned
+}
@@ -0,0 +1,377 @@
+//! Running a plugin as a standalone JACK client, to hear it without installing it into a host.
+//!
+//! ```no_run
+//! # fn get_ladspa_descriptor(_: u64) -> Option<ladspa::PluginDescriptor> { None }
+//! fn main() {
+//!     let descriptor = get_ladspa_descriptor(0).unwrap();
+//!     ladspa::jack::run_as_jack_client(&descriptor).unwrap();
+//! }
+//! ```
+//!
+//! The client is named after the plugin's label and gets a JACK port for each audio port; the
+//! outputs are connected to the system's playback ports. Control inputs are set by typing lines
+//! of the form ```<port name or index> <value>``` on standard input, ```list``` shows every
+//! control port with its current value, and ```quit``` or the end of the input stops the client.
+//!
+//! JACK is loaded when the client starts rather than linked, so plugins built with this feature
+//! still load on systems without it.
+
+// c_ulong is only 32 bits wide on some platforms, so widening it is not always a no-op.
+#![allow(clippy::useless_conversion)]
+
+use std::error::Error;
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::io::{self, BufRead};
+use std::ptr;
+use std::slice;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use libc::{self, c_char, c_int, c_ulong, c_void};
+
+use super::{Context, Data, InstantiateError, Plugin, PluginDescriptor, PortConnection, PortData,
+            PortDescriptor};
+use super::util::DenormalGuard;
+
+#[cfg(target_os = "macos")]
+const LIBRARY: &[u8] = b"libjack.0.dylib\0";
+#[cfg(not(target_os = "macos"))]
+const LIBRARY: &[u8] = b"libjack.so.0\0";
+
+const AUDIO_TYPE: &[u8] = b"32 bit float mono audio\0";
+const NO_START_SERVER: c_int = 0x01;
+const PORT_IS_INPUT: c_ulong = 0x1;
+const PORT_IS_OUTPUT: c_ulong = 0x2;
+const PORT_IS_PHYSICAL: c_ulong = 0x4;
+
+type ProcessCallback = extern "C" fn(nframes: u32, arg: *mut c_void) -> c_int;
+
+/// The ways in which running a JACK client can fail.
+#[derive(Debug)]
+pub enum JackError {
+    /// The JACK library could not be loaded. Contains the message reported by the dynamic
+    /// loader.
+    Load(String),
+    /// The client could not be opened, usually because no JACK server is running.
+    Open,
+    /// The JACK port for the named plugin port could not be registered.
+    Register(&'static str),
+    /// The plugin could not be instantiated.
+    Instantiate(InstantiateError),
+    /// The client could not be activated.
+    Activate,
+}
+
+impl fmt::Display for JackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            JackError::Load(ref msg) => write!(f, "failed to load JACK: {}", msg),
+            JackError::Open => write!(f, "failed to open a JACK client; is the server running?"),
+            JackError::Register(port) => write!(f, "failed to register JACK port \"{}\"", port),
+            JackError::Instantiate(ref err) => write!(f, "failed to instantiate plugin: {}", err),
+            JackError::Activate => write!(f, "failed to activate the JACK client"),
+        }
+    }
+}
+
+impl Error for JackError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            JackError::Instantiate(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+// The functions used from libjack.
+struct Jack {
+    library: *mut c_void,
+    client_open: unsafe extern "C" fn(*const c_char, c_int, *mut c_int, ...) -> *mut c_void,
+    client_close: unsafe extern "C" fn(*mut c_void) -> c_int,
+    set_process_callback: unsafe extern "C" fn(*mut c_void, ProcessCallback, *mut c_void)
+                                               -> c_int,
+    port_register: unsafe extern "C" fn(*mut c_void, *const c_char, *const c_char, c_ulong,
+                                        c_ulong)
+                                        -> *mut c_void,
+    port_get_buffer: unsafe extern "C" fn(*mut c_void, u32) -> *mut c_void,
+    port_name: unsafe extern "C" fn(*mut c_void) -> *const c_char,
+    get_sample_rate: unsafe extern "C" fn(*mut c_void) -> u32,
+    get_ports: unsafe extern "C" fn(*mut c_void, *const c_char, *const c_char, c_ulong)
+                                    -> *mut *const c_char,
+    connect: unsafe extern "C" fn(*mut c_void, *const c_char, *const c_char) -> c_int,
+    free: unsafe extern "C" fn(*mut c_void),
+    activate: unsafe extern "C" fn(*mut c_void) -> c_int,
+    deactivate: unsafe extern "C" fn(*mut c_void) -> c_int,
+}
+
+impl Jack {
+    fn load() -> Result<Jack, JackError> {
+        unsafe {
+            let library = libc::dlopen(LIBRARY.as_ptr() as *const c_char, libc::RTLD_NOW);
+            if library.is_null() {
+                let err = libc::dlerror();
+                return Err(JackError::Load(if err.is_null() {
+                    String::from("unknown error")
+                } else {
+                    CStr::from_ptr(err).to_string_lossy().into_owned()
+                }));
+            }
+            macro_rules! symbol {
+                ($name:expr) => {{
+                    let name = concat!($name, "\0");
+                    let symbol = libc::dlsym(library, name.as_ptr() as *const c_char);
+                    if symbol.is_null() {
+                        libc::dlclose(library);
+                        return Err(JackError::Load(String::from(concat!("missing ", $name))));
+                    }
+                    *(&symbol as *const *mut c_void as *const _)
+                }}
+            }
+            Ok(Jack {
+                library,
+                client_open: symbol!("jack_client_open"),
+                client_close: symbol!("jack_client_close"),
+                set_process_callback: symbol!("jack_set_process_callback"),
+                port_register: symbol!("jack_port_register"),
+                port_get_buffer: symbol!("jack_port_get_buffer"),
+                port_name: symbol!("jack_port_name"),
+                get_sample_rate: symbol!("jack_get_sample_rate"),
+                get_ports: symbol!("jack_get_ports"),
+                connect: symbol!("jack_connect"),
+                free: symbol!("jack_free"),
+                activate: symbol!("jack_activate"),
+                deactivate: symbol!("jack_deactivate"),
+            })
+        }
+    }
+}
+
+impl Drop for Jack {
+    fn drop(&mut self) {
+        unsafe {
+            libc::dlclose(self.library);
+        }
+    }
+}
+
+// Everything the process callback needs. Owned by `run_as_jack_client`, which outlives the
+// callback.
+struct Client<'d> {
+    jack: &'d Jack,
+    descriptor: &'d PluginDescriptor,
+    plugin: Box<dyn Plugin + Send>,
+    sample_rate: u64,
+    // The JACK port of each audio port, null for control ports.
+    jack_ports: Vec<*mut c_void>,
+    // The value of each control port, as the bits of a `Data`, shared with the thread reading
+    // standard input.
+    controls: Arc<Vec<AtomicU32>>,
+    control_buffers: Vec<Data>,
+    // Reused by every call to `process` so it doesn't allocate. Only valid during the call.
+    connections: Vec<PortConnection<'static>>,
+}
+
+impl<'d> Client<'d> {
+    fn process(&mut self, nframes: u32) {
+        for (buffer, control) in self.control_buffers.iter_mut().zip(self.controls.iter()) {
+            *buffer = Data::from_bits(control.load(Ordering::Relaxed));
+        }
+        let frames = nframes as usize;
+        let controls = self.control_buffers.as_mut_ptr();
+        for (index, port) in self.descriptor.ports.iter().enumerate() {
+            // The buffers only need to live until the end of this call, when the connections
+            // are cleared.
+            let data = unsafe {
+                let jack_port = self.jack_ports[index];
+                let audio = || (self.jack.port_get_buffer)(jack_port, nframes) as *mut Data;
+                let control = &mut *controls.add(index);
+                match port.desc {
+                    PortDescriptor::AudioInput => {
+                        PortData::AudioInput(slice::from_raw_parts(audio(), frames))
+                    }
+                    PortDescriptor::AudioOutput => {
+                        PortData::AudioOutput(slice::from_raw_parts_mut(audio(), frames))
+                    }
+                    PortDescriptor::ControlInput => PortData::ControlInput(control),
+                    _ => PortData::ControlOutput(control),
+                }
+            };
+            self.connections.push(PortConnection { port: *port, data });
+        }
+        {
+            let _guard = if self.plugin.flush_denormals() {
+                Some(DenormalGuard::new())
+            } else {
+                None
+            };
+            let context = Context::new(self.descriptor, self.sample_rate);
+            self.plugin.run_with_context(context, frames, &mut self.connections);
+        }
+        self.connections.clear();
+        for (index, port) in self.descriptor.ports.iter().enumerate() {
+            if let PortDescriptor::ControlOutput = port.desc {
+                let value = self.control_buffers[index];
+                self.controls[index].store(value.to_bits(), Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+extern "C" fn process(nframes: u32, arg: *mut c_void) -> c_int {
+    let client = unsafe { &mut *(arg as *mut Client) };
+    client.process(nframes);
+    0
+}
+
+/// Runs the plugin described by ```descriptor``` as a JACK client until ```quit``` is typed or
+/// standard input is closed. See the module documentation.
+pub fn run_as_jack_client(descriptor: &PluginDescriptor) -> Result<(), JackError> {
+    let jack = Jack::load()?;
+    let name = CString::new(descriptor.label).unwrap_or_default();
+    let handle = unsafe { (jack.client_open)(name.as_ptr(), NO_START_SERVER, ptr::null_mut()) };
+    if handle.is_null() {
+        return Err(JackError::Open);
+    }
+    let result = run_client(&jack, handle, descriptor);
+    unsafe {
+        (jack.client_close)(handle);
+    }
+    result
+}
+
+fn run_client(jack: &Jack, handle: *mut c_void, descriptor: &PluginDescriptor)
+              -> Result<(), JackError> {
+    let mut jack_ports = Vec::with_capacity(descriptor.ports.len());
+    for port in &descriptor.ports {
+        let flags = match port.desc {
+            PortDescriptor::AudioInput => PORT_IS_INPUT,
+            PortDescriptor::AudioOutput => PORT_IS_OUTPUT,
+            _ => {
+                jack_ports.push(ptr::null_mut());
+                continue;
+            }
+        };
+        let name = CString::new(port.name).unwrap_or_default();
+        let jack_port = unsafe {
+            (jack.port_register)(handle, name.as_ptr(), AUDIO_TYPE.as_ptr() as *const c_char,
+                                 flags, 0)
+        };
+        if jack_port.is_null() {
+            return Err(JackError::Register(port.name));
+        }
+        jack_ports.push(jack_port);
+    }
+
+    let sample_rate = u64::from(unsafe { (jack.get_sample_rate)(handle) });
+    let mut plugin = (descriptor.new)(descriptor, sample_rate).map_err(JackError::Instantiate)?;
+    plugin.initialize(sample_rate, None);
+    let controls: Arc<Vec<AtomicU32>> = Arc::new(descriptor.ports
+        .iter()
+        .map(|port| {
+            let value = port.default_at(sample_rate).or(port.lower_bound).unwrap_or(0.0);
+            AtomicU32::new(value.to_bits())
+        })
+        .collect());
+    let mut client = Box::new(Client {
+        jack,
+        descriptor,
+        plugin,
+        sample_rate,
+        jack_ports,
+        controls: controls.clone(),
+        control_buffers: vec![0.0; descriptor.ports.len()],
+        connections: Vec::with_capacity(descriptor.ports.len()),
+    });
+    client.plugin.activate();
+    let arg = &mut *client as *mut Client as *mut c_void;
+    unsafe {
+        (jack.set_process_callback)(handle, process, arg);
+        if (jack.activate)(handle) != 0 {
+            client.plugin.deactivate();
+            return Err(JackError::Activate);
+        }
+        connect_outputs(jack, handle, descriptor, &client.jack_ports);
+    }
+
+    read_controls(descriptor, &controls);
+
+    unsafe {
+        (jack.deactivate)(handle);
+    }
+    client.plugin.deactivate();
+    Ok(())
+}
+
+// Connects the audio outputs to the physical playback ports, in order.
+unsafe fn connect_outputs(jack: &Jack, handle: *mut c_void, descriptor: &PluginDescriptor,
+                          jack_ports: &[*mut c_void]) {
+    let playback = (jack.get_ports)(handle, ptr::null(), ptr::null(),
+                                    PORT_IS_PHYSICAL | PORT_IS_INPUT);
+    if playback.is_null() {
+        return;
+    }
+    let mut next = playback;
+    for (port, &jack_port) in descriptor.ports.iter().zip(jack_ports) {
+        if let PortDescriptor::AudioOutput = port.desc {
+            if (*next).is_null() {
+                break;
+            }
+            (jack.connect)(handle, (jack.port_name)(jack_port), *next);
+            next = next.add(1);
+        }
+    }
+    (jack.free)(playback as *mut c_void);
+}
+
+// Applies the settings typed on standard input until it is closed or `quit` is typed.
+fn read_controls(descriptor: &PluginDescriptor, controls: &[AtomicU32]) {
+    let is_control = |desc: PortDescriptor| {
+        matches!(desc, PortDescriptor::ControlInput | PortDescriptor::ControlOutput)
+    };
+    println!("{} running. Type \"<port> <value>\" to set a control, \"list\" or \"quit\".",
+             descriptor.name);
+    let stdin = io::stdin();
+    for line in stdin.lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        let line = line.trim();
+        match line {
+            "" => continue,
+            "quit" => break,
+            "list" => {
+                for (index, port) in descriptor.ports.iter().enumerate() {
+                    if is_control(port.desc) {
+                        let value = Data::from_bits(controls[index].load(Ordering::Relaxed));
+                        println!("{:3} {}: {}", index, port.name, value);
+                    }
+                }
+                continue;
+            }
+            _ => {}
+        }
+        // Port names may contain spaces, so the value is whatever follows the last one.
+        let (port, value) = match line.rfind(char::is_whitespace) {
+            Some(split) => (line[..split].trim(), line[split..].trim()),
+            None => {
+                println!("expected \"<port> <value>\"");
+                continue;
+            }
+        };
+        let index = port.parse::<usize>()
+            .ok()
+            .or_else(|| descriptor.ports.iter().position(|p| p.name == port))
+            .filter(|&index| {
+                descriptor.ports
+                    .get(index)
+                    .is_some_and(|port| matches!(port.desc, PortDescriptor::ControlInput))
+            });
+        match (index, value.parse::<Data>()) {
+            (Some(index), Ok(value)) => controls[index].store(value.to_bits(), Ordering::Relaxed),
+            (None, _) => println!("no control input \"{}\"", port),
+            (_, Err(_)) => println!("invalid value \"{}\"", value),
+        }
+    }
+}
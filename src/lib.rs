@@ -7,13 +7,13 @@
  * Run ```cargo new my_ladspa_plugin``` to generate a Cargo project for your plugin, then add
  * the following to the generated Cargo.toml:
  *
- * ```
+ * ```toml
  * [dependencies]
  * ladspa = "*"
  *
  * [lib]
  * name = "my_ladspa_plugin"
- * crate-type = ["dylib"]
+ * crate-type = ["cdylib"]
  * ```
  * This will pull in the correct dependency and ensure that the library generated when you build
  * your plugin is compatible with LADSPA hosts.
@@ -21,36 +21,170 @@
  * ## Writing the code
  * You'll want to implement
  * ```get_ladspa_descriptor``` in your src/lib.rs. This function is expected to return 1 or more
- * ```PluginDescriptor```s describing the plugins exposed by your library. See the documentation
- * for ```get_ladspa_descriptor``` and the examples
+ * ```PluginDescriptor```s describing the plugins exposed by your library. The
+ * ```export_ladspa!``` macro defines it, along with everything else the library needs to be
+ * loaded by hosts. See the documentation for ```get_ladspa_descriptor``` and the examples
  * [on Github](https://github.com/nwoeanhinnogaehr/ladspa.rs/tree/master/examples) for more
  * information.
  *
+ * With the ```macros``` feature enabled, the ```#[ladspa_plugin]``` attribute can generate a
+ * plugin's descriptor from a short list of its properties and ports.
+ *
+ * The ```examples-plugins``` feature adds the ```plugins``` module, a handful of complete effects
+ * to start from.
+ *
+ * The ```lv2``` feature additionally exports your plugins as LV2 plugins; see the ```lv2```
+ * module. Similarly, the ```dssi``` feature exports them as DSSI plugins, which can receive MIDI
+ * through ```Plugin::run_synth```.
+ *
  * ## Testing it out
  * There is a list of host software supporting LADSPA on the
  * [LADSPA home page](http://www.ladspa.org/). In order for a host to find your plugin, you will
  * either need to copy the *.so file from target/ after building to /usr/lib/ladspa/ (on most
  * systems, it may be different on your system) or set the enviornment variable ```LADSPA_PATH```
  * to equal the directory where you store your plugins.
+ *
+ * ```install::install``` copies the library to a directory hosts search by default, under the
+ * name they expect; the ```cargo-ladspa``` binary does so as ```cargo ladspa install LIBRARY```.
+ * On macOS, Cargo builds a .dylib, which is installed as a .so in ~/Library/Audio/Plug-Ins/LADSPA
+ * or /Library/Audio/Plug-Ins/LADSPA, where hosts such as Audacity look for it.
+ *
+ * On Windows, the library is a DLL named after the crate, without the lib prefix. Hosts such as
+ * Audacity load it from their plug-in directory or one listed in ```LADSPA_PATH```. Build it
+ * with the toolchain matching the host, 64 bit for 64 bit hosts: LADSPA uses the C calling
+ * convention, which is the default on every Windows target.
+ *
+ * Some hosts also read LRDF metadata describing your plugins. ```rdf::write``` generates it from
+ * your descriptors; install the result in /usr/share/ladspa/rdf/ or a directory listed in
+ * ```LADSPA_RDF_PATH```.
+ *
+ * ## Hosting plugins
+ * The ```host``` module goes the other way: it loads existing LADSPA libraries, whether written
+ * with this crate or not, and runs the plugins they expose.
+ *
+ * ## Without the standard library
+ * Disabling the default ```std``` feature builds the crate with ```core``` and ```alloc``` only,
+ * for targets with a minimal runtime. The plugin library then provides the global allocator and
+ * panic handler, and since panics can no longer be caught, it should be built with
+ * ```panic = "abort"```. Floating point functions are taken from the C library's libm. Code
+ * generated by ```#[ladspa_plugin]``` uses ```vec!```, which such libraries import with
+ * ```#[macro_use] extern crate alloc;```.
  */
 
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(feature = "std")]
+extern crate core;
+extern crate alloc;
 extern crate libc;
 #[macro_use] extern crate bitflags;
-extern crate vec_map;
+#[cfg(feature = "macros")]
+extern crate ladspa_macros;
 
 #[doc(hidden)]
 pub mod ffi;
 
+mod builder;
+pub use builder::{BuildError, PluginDescriptorBuilder};
+
+mod validation;
+pub use validation::ValidationError;
+
+mod category;
+pub use category::Category;
+
+pub mod unique_id;
+pub use unique_id::UniqueId;
+
+mod lock;
+mod intern;
+
+mod registry;
+pub use registry::registered_descriptor;
+#[doc(hidden)]
+pub use registry::register;
+
+mod named_ports;
+#[doc(hidden)]
+pub use named_ports::reexports as __ports;
+
+// Test builds link std, which brings back the inherent float methods.
+#[cfg(not(any(feature = "std", test)))]
+mod math;
+
+#[cfg(all(any(unix, windows), feature = "std"))]
+pub mod host;
+
+#[cfg(feature = "std")]
+pub mod rdf;
+
+#[cfg(all(any(unix, windows), feature = "std"))]
+pub mod install;
+
+pub mod util;
+
+pub mod ring;
+
+pub mod units;
+pub use units::Unit;
+
+pub mod mapping;
+
+pub mod modulation;
+
+pub mod dsp;
+
+pub mod adapter;
+
+#[cfg(feature = "examples-plugins")]
+pub mod plugins;
+
+#[cfg(feature = "rt-audit")]
+pub mod rt_audit;
+
+#[cfg(feature = "harness")]
+pub mod harness;
+
+#[cfg(feature = "scaffold")]
+pub mod scaffold;
+
+#[cfg(all(unix, feature = "jack"))]
+pub mod jack;
+
+#[cfg(feature = "lv2")]
+pub mod lv2;
+
+#[cfg(feature = "dssi")]
+pub mod dssi;
+
+#[cfg(all(unix, feature = "gstreamer"))]
+pub mod gstreamer;
+
+#[cfg(feature = "macros")]
+pub use ladspa_macros::ladspa_plugin;
+
 use ffi::ladspa_h;
 
 #[doc(hidden)]
 pub use ffi::ladspa_descriptor;
 
-use std::cell::{RefCell, RefMut};
-use std::default::Default;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::default::Default;
+use core::error::Error;
+use core::fmt;
+use core::ops::{Index, IndexMut, RangeInclusive};
+
+use util::{ControlRamp, Ramp};
+
+#[cfg(not(any(feature = "std", test)))]
+use math::Float;
 
 #[allow(improper_ctypes)]
-extern {
+extern "C" {
     /**
      * Your plugin must implement this function.
      * ```get_ladspa_descriptor``` returns a description of a supported plugin for a given plugin
@@ -59,12 +193,15 @@ extern {
      *
      * Example no-op implementation:
      *
-     * ```rust{.ignore}
+     * ```no_run
      * #[no_mangle]
      * pub extern fn get_ladspa_descriptor(index: u64) -> Option<ladspa::PluginDescriptor> {
      *     None
      * }
      * ```
+     *
+     * Rather than defining it by hand, use ```export_ladspa!```, which also keeps the library's
+     * entry points from being removed by link time optimization.
      */
     pub fn get_ladspa_descriptor(index: u64) -> Option<PluginDescriptor>;
 }
@@ -75,8 +212,8 @@ pub type Data = f32;
 /// Describes the properties of a ```Plugin``` to be exposed as a LADSPA plugin.
 pub struct PluginDescriptor {
     /// Unique IDs are an unfortunate remnant of the LADSPA API. During development, it is
-    /// suggested to pick one under 1000, but it should be changed before release. More information
-    /// is available here: http://www.ladspa.org/ladspa_sdk/unique_ids.html
+    /// suggested to pick one under 1000 with ```UniqueId::experimental```, but it should be changed
+    /// before release. See the ```unique_id``` module for checks against clashes.
     pub unique_id: u64,
 
     /// Plugin labels are expected to be a unique descriptor string for this specific plugin within
@@ -96,19 +233,87 @@ pub struct PluginDescriptor {
     /// Indicates copyright of the plugin. If no copyright applies, "None" should be used.
     pub copyright: &'static str,
 
+    /// The kind of processing the plugin does, for hosts to file it under in their menus. Only
+    /// reaches them through the RDF and LV2 metadata.
+    pub category: Option<Category>,
+
     /// A vector of input and output ports exposed by the plugin. See the documentation for
     /// ```Port``` for more information.
     pub ports: Vec<Port>,
 
+    /// Factory presets for the plugin's control inputs. LADSPA itself has no notion of presets,
+    /// so these only reach hosts through the LRDF file generated by the ```rdf``` module.
+    pub presets: Vec<Preset>,
+
     /// A function which creates a new instance of the plugin.
     ///
     /// Note: Initialization, such as resetting plugin state, should go in ```Plugin::activate``` rather
     /// than here. This should just return a basic instance, ready to be activated.
     /// If your plugin has no internal state, you may optionally not implement ```Plugin::activate```
     /// and do everything here.
-    pub new: fn(desc: &PluginDescriptor, sample_rate: u64) -> Box<Plugin + Send>,
+    ///
+    /// If the plugin cannot be created, for example because a buffer could not be allocated, return
+    /// an ```InstantiateError``` and the host will be told that instantiation failed.
+    pub new: PluginConstructor,
+}
+
+/// A named set of control input values, such as "Small Room" for a reverb.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Preset {
+    /// The name shown to the user.
+    pub name: &'static str,
+
+    /// Pairs of a control input's index in ```PluginDescriptor::ports``` and the value to set it
+    /// to. Control inputs which aren't listed keep their current value.
+    pub values: Vec<(usize, Data)>,
+}
+
+/// A plugin type which can describe itself, usually implemented with the ```#[ladspa_plugin]```
+/// attribute (enabled by the ```macros``` feature).
+pub trait DescribedPlugin {
+    /// Returns the descriptor under which this plugin is exposed to hosts.
+    fn descriptor() -> PluginDescriptor;
+}
+
+/// A function which creates a new instance of a plugin. See ```PluginDescriptor::new```.
+pub type PluginConstructor = fn(desc: &PluginDescriptor, sample_rate: u64)
+                                -> Result<Box<dyn Plugin + Send>, InstantiateError>;
+
+/// A ```PluginConstructor``` which creates instances with ```Default::default()```, for plugins
+/// which don't depend on the sample rate.
+pub fn new_default<T>(_: &PluginDescriptor, _: u64) -> Result<Box<dyn Plugin + Send>, InstantiateError>
+    where T: Plugin + Default + Send + 'static
+{
+    Ok(Box::new(T::default()))
+}
+
+/// Returned by ```PluginDescriptor::new``` when a plugin instance can't be created.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InstantiateError {
+    /// The plugin does not support the requested sample rate.
+    UnsupportedSampleRate(u64),
+    /// Memory or another resource needed by the plugin could not be obtained.
+    ResourceUnavailable(String),
+    /// Any other reason, described by the contained message.
+    Other(String),
+}
+
+impl fmt::Display for InstantiateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InstantiateError::UnsupportedSampleRate(rate) => {
+                write!(f, "unsupported sample rate {}", rate)
+            }
+            InstantiateError::ResourceUnavailable(ref what) => {
+                write!(f, "resource unavailable: {}", what)
+            }
+            InstantiateError::Other(ref msg) => write!(f, "{}", msg),
+        }
+    }
 }
 
+impl Error for InstantiateError {}
+
 #[derive(Copy, Clone, Default)]
 /// Represents an input or output to the plugin representing either audio or
 /// control data.
@@ -132,11 +337,316 @@ pub struct Port {
 
     /// The upper bound of values to accepted by default (the host may ignore this).
     pub upper_bound: Option<Data>,
+
+    /// For audio ports carrying one channel of a multichannel signal, the name of the
+    /// ```PortGroup``` they belong to.
+    pub group: Option<&'static str>,
+
+    /// For integer control inputs, the names of their values from the lower bound up, which
+    /// hosts reading the RDF or LV2 metadata show in a list to pick from. See
+    /// ```Port::enumeration```.
+    pub scale_points: &'static [&'static str],
+
+    /// What the value of a control port measures, which hosts use to format it.
+    pub unit: Option<Unit>,
 }
 
-#[derive(Copy, Clone)]
+impl Port {
+    /// Returns a control output named "latency", which by convention tells hosts how many samples
+    /// of delay the plugin introduces so that they can compensate for it. Plugins with lookahead
+    /// or linear phase filters should declare one and report their delay through
+    /// ```Ports::set_latency```.
+    pub fn latency_output() -> Port {
+        Port {
+            name: "latency",
+            desc: PortDescriptor::ControlOutput,
+            hint: Some(HINT_INTEGER),
+            default: None,
+            lower_bound: Some(0.0),
+            upper_bound: None,
+            group: None,
+            scale_points: &[],
+            unit: None,
+        }
+    }
+
+    /// Returns a control input named "Dry/Wet" going from 0, only the input, to 1, only the output
+    /// of the plugin, fully wet by default. ```adapter::WithDryWet``` expects it as the last port.
+    pub fn dry_wet() -> Port {
+        Port {
+            name: "Dry/Wet",
+            desc: PortDescriptor::ControlInput,
+            hint: None,
+            default: Some(DefaultValue::Maximum),
+            lower_bound: Some(0.0),
+            upper_bound: Some(1.0),
+            group: None,
+            scale_points: &[],
+            unit: None,
+        }
+    }
+
+    /// Returns an on/off control input named "Bypass", off by default.
+    /// ```adapter::WithBypass``` expects it as the last port.
+    pub fn bypass() -> Port {
+        Port::toggle("Bypass")
+    }
+
+    /// Returns a control input named "Tempo (BPM)", from 20 to 220 beats per minute and at 120 by
+    /// default, the informal convention for plugins synchronizing to the tempo of the song. The
+    /// LV2 metadata designates it as the tempo, for hosts to set it. Convert note divisions to
+    /// times with ```units::TempoSync```.
+    pub fn tempo() -> Port {
+        Port {
+            name: "Tempo (BPM)",
+            desc: PortDescriptor::ControlInput,
+            hint: None,
+            default: Some(DefaultValue::Middle),
+            lower_bound: Some(20.0),
+            upper_bound: Some(220.0),
+            group: None,
+            scale_points: &[],
+            unit: Some(Unit::Bpm),
+        }
+    }
+
+    /// Returns an on/off control input, off by default. Read it with ```ControlPort::as_bool```.
+    pub fn toggle(name: &'static str) -> Port {
+        Port {
+            name,
+            desc: PortDescriptor::ControlInput,
+            hint: Some(HINT_TOGGLED),
+            default: Some(DefaultValue::Value0),
+            ..Default::default()
+        }
+    }
+
+    /// Returns a control input taking the whole numbers in ```range```, starting at the lowest.
+    /// Read it with ```ControlPort::as_i32```.
+    pub fn integer(name: &'static str, range: RangeInclusive<i32>) -> Port {
+        Port {
+            name,
+            desc: PortDescriptor::ControlInput,
+            hint: Some(HINT_INTEGER),
+            default: Some(DefaultValue::Minimum),
+            lower_bound: Some(*range.start() as Data),
+            upper_bound: Some(*range.end() as Data),
+            group: None,
+            scale_points: &[],
+            unit: None,
+        }
+    }
+
+    /// Returns a control input picking one of ```labels```, as the index of the label, starting
+    /// at the first. Read it with ```ControlPort::as_i32```:
+    ///
+    /// ```no_run
+    /// # use ladspa::{Port, PluginDescriptor};
+    /// # let _ = PluginDescriptor::builder()
+    /// .port(Port::enumeration("Mode", &["Lowpass", "Bandpass", "Highpass"]))
+    /// # ;
+    /// ```
+    pub fn enumeration(name: &'static str, labels: &'static [&'static str]) -> Port {
+        Port {
+            name,
+            desc: PortDescriptor::ControlInput,
+            hint: Some(HINT_INTEGER),
+            default: Some(DefaultValue::Minimum),
+            lower_bound: Some(0.0),
+            upper_bound: Some(labels.len().saturating_sub(1) as Data),
+            group: None,
+            scale_points: labels,
+            unit: None,
+        }
+    }
+
+    /// Returns the ```scale_points``` with the values they name.
+    pub fn scale_point_values(&self) -> impl Iterator<Item = (Data, &'static str)> {
+        let lower = self.lower_bound.unwrap_or(0.0);
+        self.scale_points.iter().enumerate().map(move |(n, &label)| (lower + n as Data, label))
+    }
+
+    /// Returns whether this port follows the tempo convention: a control input measured in beats
+    /// per minute, by its unit or its name, as in "BPM" or "Tempo (bpm)".
+    pub fn is_tempo(&self) -> bool {
+        matches!(self.desc, PortDescriptor::ControlInput) &&
+        (self.unit == Some(Unit::Bpm) || self.name.eq_ignore_ascii_case("bpm") ||
+         Unit::from_name(self.name) == Some(Unit::Bpm))
+    }
+
+    /// Returns whether this port follows the latency reporting convention.
+    pub fn is_latency_output(&self) -> bool {
+        matches!(self.desc, PortDescriptor::ControlOutput) && self.name == "latency"
+    }
+
+    /// The values this port covers when running at ```sample_rate```, to map them to and from
+    /// the normalized range of sliders and controllers.
+    pub fn range(&self, sample_rate: u64) -> mapping::ControlRange {
+        mapping::ControlRange::from_hint(self.hint.map_or(0, |hint| hint.bits()),
+                                         self.lower_bound,
+                                         self.upper_bound,
+                                         sample_rate)
+    }
+
+    /// Returns the value hosts start this port at, as described by ```default``` and the bounds,
+    /// or ```None``` if it has no default. For ```HINT_SAMPLE_RATE``` ports, this is the value
+    /// relative to the sample rate; ```default_at``` gives the one hosts actually send.
+    pub fn default_value(&self) -> Option<Data> {
+        self.default_at(1)
+    }
+
+    /// Returns the value hosts start this port at when running at ```sample_rate```, or
+    /// ```None``` if it has no default. See ```default_for```.
+    pub fn default_at(&self, sample_rate: u64) -> Option<Data> {
+        match self.default {
+            Some(DefaultValue::Value(value)) => Some(value),
+            default => self.default_with(default.map_or(0, DefaultValue::bits), sample_rate),
+        }
+    }
+
+    // The default selected by the `HINT_DEFAULT_*` bits `bits` for this port.
+    fn default_with(&self, bits: i32, sample_rate: u64) -> Option<Data> {
+        default_for(self.hint.map_or(0, |hint| hint.bits()) | bits,
+                    self.lower_bound.unwrap_or(0.0),
+                    self.upper_bound.unwrap_or(0.0),
+                    sample_rate)
+    }
+
+    // The `HINT_DEFAULT_*` bits given to LADSPA hosts. A `DefaultValue::Value` is replaced by the
+    // closest default within the bounds, as computed at 48 kHz for `HINT_SAMPLE_RATE` ports.
+    fn default_hint(&self) -> i32 {
+        let value = match self.default {
+            None => return 0,
+            Some(DefaultValue::Value(value)) => value,
+            Some(default) => return default.bits(),
+        };
+        let sample_rate = 48000;
+        let scale = match self.hint {
+            Some(hint) if hint.contains(HINT_SAMPLE_RATE) => sample_rate as Data,
+            _ => 1.0,
+        };
+        let within = |candidate: Data| {
+            self.lower_bound.is_none_or(|lower| candidate >= lower * scale) &&
+            self.upper_bound.is_none_or(|upper| candidate <= upper * scale)
+        };
+        let (lower, upper) = (self.lower_bound.is_some(), self.upper_bound.is_some());
+        [(ladspa_h::HINT_DEFAULT_0, true),
+         (ladspa_h::HINT_DEFAULT_1, true),
+         (ladspa_h::HINT_DEFAULT_100, true),
+         (ladspa_h::HINT_DEFAULT_440, true),
+         (ladspa_h::HINT_DEFAULT_MINIMUM, lower),
+         (ladspa_h::HINT_DEFAULT_LOW, lower && upper),
+         (ladspa_h::HINT_DEFAULT_MIDDLE, lower && upper),
+         (ladspa_h::HINT_DEFAULT_HIGH, lower && upper),
+         (ladspa_h::HINT_DEFAULT_MAXIMUM, upper)]
+            .iter()
+            .filter(|&&(_, usable)| usable)
+            .filter_map(|&(bits, _)| self.default_with(bits, sample_rate).map(|at| (bits, at)))
+            .filter(|&(_, at)| within(at))
+            .min_by(|a, b| (a.1 - value).abs().total_cmp(&(b.1 - value).abs()))
+            .map_or(0, |(bits, _)| bits)
+    }
+}
+
+/// A set of audio ports carrying the channels of one signal, such as the left and right channels
+/// of a stereo input, declared as a unit.
+///
+/// Each channel becomes a port named after the group and the channel, "Input L" and "Input R"
+/// for ```PortGroup::stereo_in("Input")```. In ```Plugin::run```, ```Ports::input_channels``` and
+/// ```Ports::output_channels``` return the buffers of a group by its name:
+///
+/// ```no_run
+/// # use ladspa::{PortConnection, Ports};
+/// # fn run<'a>(ports: &mut [PortConnection<'a>]) {
+/// let mut ports = Ports::new(ports);
+/// let [in_l, in_r] = ports.input_channels("Input");
+/// let [out_l, out_r] = ports.output_channels("Output");
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct PortGroup {
+    /// The name of the group, shared by the ports of its channels.
+    pub name: &'static str,
+
+    /// Whether the group is made of audio inputs or audio outputs.
+    pub desc: PortDescriptor,
+
+    /// The names of the channels, in order, appended to the group name to name each port.
+    pub channels: Vec<&'static str>,
+}
+
+impl PortGroup {
+    /// Creates a group of audio inputs with the given channels.
+    pub fn input(name: &'static str, channels: &[&'static str]) -> PortGroup {
+        PortGroup {
+            name,
+            desc: PortDescriptor::AudioInput,
+            channels: channels.to_vec(),
+        }
+    }
+
+    /// Creates a group of audio outputs with the given channels.
+    pub fn output(name: &'static str, channels: &[&'static str]) -> PortGroup {
+        PortGroup {
+            name,
+            desc: PortDescriptor::AudioOutput,
+            channels: channels.to_vec(),
+        }
+    }
+
+    /// Creates a stereo input, with channels "L" and "R".
+    pub fn stereo_in(name: &'static str) -> PortGroup {
+        PortGroup::input(name, &["L", "R"])
+    }
+
+    /// Creates a stereo output, with channels "L" and "R".
+    pub fn stereo_out(name: &'static str) -> PortGroup {
+        PortGroup::output(name, &["L", "R"])
+    }
+
+    /// Creates a 5.1 surround input, with channels "L", "R", "C", "LFE", "Ls" and "Rs".
+    pub fn surround_5_1_in(name: &'static str) -> PortGroup {
+        PortGroup::input(name, &SURROUND_5_1)
+    }
+
+    /// Creates a 5.1 surround output, with channels "L", "R", "C", "LFE", "Ls" and "Rs".
+    pub fn surround_5_1_out(name: &'static str) -> PortGroup {
+        PortGroup::output(name, &SURROUND_5_1)
+    }
+
+    /// Returns the ports of the group, one per channel.
+    pub fn ports(&self) -> Vec<Port> {
+        self.channels
+            .iter()
+            .map(|channel| Port {
+                name: intern::intern(format!("{} {}", self.name, channel)),
+                desc: self.desc,
+                group: Some(self.name),
+                scale_points: &[],
+                unit: None,
+                ..Default::default()
+            })
+            .collect()
+    }
+}
+
+// The channels of a 5.1 surround group, in the usual order.
+const SURROUND_5_1: [&str; 6] = ["L", "R", "C", "LFE", "Ls", "Rs"];
+
+// The names of the channels of a group of `count` ports: left and right for two, those of 5.1
+// surround for six and numbers from 1 otherwise.
+fn channel_names(count: usize) -> Vec<&'static str> {
+    match count {
+        2 => vec!["L", "R"],
+        6 => SURROUND_5_1.to_vec(),
+        _ => (1..=count).map(|n| intern::intern(format!("{}", n))).collect(),
+    }
+}
+
+#[derive(Copy, Clone, Default)]
 /// Represents the 4 types of ports: audio or control, input or output.
 pub enum PortDescriptor {
+    #[default]
     Invalid = 0,
     AudioInput = (ladspa_h::PORT_AUDIO | ladspa_h::PORT_INPUT) as isize,
     AudioOutput = (ladspa_h::PORT_AUDIO | ladspa_h::PORT_OUTPUT) as isize,
@@ -144,74 +654,209 @@ pub enum PortDescriptor {
     ControlOutput = (ladspa_h::PORT_CONTROL | ladspa_h::PORT_OUTPUT) as isize,
 }
 
-impl Default for PortDescriptor {
-    fn default() -> PortDescriptor {
-        PortDescriptor::Invalid
-    }
-}
-
-bitflags!(
-    #[doc="Represents the special properties a control port may hold. These are merely hints as to the
-    use of the port and may be completely ignored by the host. For audio ports, use ```CONTROL_HINT_NONE```.
-    To attach multiple properties, bitwise-or them together.
-    See documentation for the constants beginning with HINT_ for the more information."]
-    pub flags ControlHint: i32 {
-        #[doc="Indicates that this is a toggled port. Toggled ports may only have default values
-        of zero or one, although the host may send any value, where <= 0 is false and > 0 is true."]
-        const HINT_TOGGLED = ::ffi::ladspa_h::HINT_TOGGLED,
+// bitflags 0.8 expands to uses of the deprecated `try!` macro.
+#[allow(deprecated)]
+mod control_hint {
+    bitflags!(
+        #[doc="Represents the special properties a control port may hold. These are merely hints as to the
+        use of the port and may be completely ignored by the host. For audio ports, use ```CONTROL_HINT_NONE```.
+        To attach multiple properties, bitwise-or them together.
+        See documentation for the constants beginning with HINT_ for the more information."]
+        pub flags ControlHint: i32 {
+            #[doc="Indicates that this is a toggled port. Toggled ports may only have default values
+            of zero or one, although the host may send any value, where <= 0 is false and > 0 is true."]
+            const HINT_TOGGLED = ::ffi::ladspa_h::HINT_TOGGLED,
 
-        #[doc="Indicates that all values related to the port will be multiplied by the sample rate by
-        the host before passing them to your plugin. This includes the lower and upper bounds. If you
-        want an upper bound of 22050 with this property and a sample rate of 44100, set the upper bound
-        to 0.5"]
-        const HINT_SAMPLE_RATE = ::ffi::ladspa_h::HINT_SAMPLE_RATE,
+            #[doc="Indicates that all values related to the port will be multiplied by the sample rate by
+            the host before passing them to your plugin. This includes the lower and upper bounds. If you
+            want an upper bound of 22050 with this property and a sample rate of 44100, set the upper bound
+            to 0.5"]
+            const HINT_SAMPLE_RATE = ::ffi::ladspa_h::HINT_SAMPLE_RATE,
 
-        #[doc="Indicates that the data passed through this port would be better represented on a
-        logarithmic scale"]
-        const HINT_LOGARITHMIC = ::ffi::ladspa_h::HINT_LOGARITHMIC,
+            #[doc="Indicates that the data passed through this port would be better represented on a
+            logarithmic scale"]
+            const HINT_LOGARITHMIC = ::ffi::ladspa_h::HINT_LOGARITHMIC,
 
-        #[doc="Indicates that the data passed through this port should be represented as integers. Bounds
-        may be interpreted exclusively depending on the host"]
-        const HINT_INTEGER = ::ffi::ladspa_h::HINT_INTEGER,
-    }
-);
+            #[doc="Indicates that the data passed through this port should be represented as integers. Bounds
+            may be interpreted exclusively depending on the host"]
+            const HINT_INTEGER = ::ffi::ladspa_h::HINT_INTEGER,
+        }
+    );
+}
+pub use control_hint::*;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 /// The default values that a control port may hold. For audio ports, use DefaultControlValue::None.
 pub enum DefaultValue {
     /// Equal to the ```lower_bound``` of the ```Port```.
-    Minimum = ladspa_h::HINT_DEFAULT_MINIMUM as isize,
+    Minimum,
     /// For ports with
     /// ```LADSPA_HINT_LOGARITHMIC```, this should be ```exp(log(lower_bound) * 0.75 +
     /// log(upper_bound) * 0.25)```. Otherwise, this should be ```(lower_bound * 0.75 +
     /// upper_bound * 0.25)```.
-    Low = ladspa_h::HINT_DEFAULT_LOW as isize,
+    Low,
     /// For ports with
     /// ```CONTROL_HINT_LOGARITHMIC```, this should be ```exp(log(lower_bound) * 0.5 +
     /// log(upper_bound) * 0.5)```. Otherwise, this should be ```(lower_bound * 0.5 +
     /// upper_bound * 0.5)```.
-    Middle = ladspa_h::HINT_DEFAULT_MIDDLE as isize,
+    Middle,
     /// For ports with
     /// ```LADSPA_HINT_LOGARITHMIC```, this should be ```exp(log(lower_bound) * 0.25 +
     /// log(upper_bound) * 0.75)```. Otherwise, this should be ```(lower_bound * 0.25 +
     /// upper_bound * 0.75)```.
-    High = ladspa_h::HINT_DEFAULT_HIGH as isize,
+    High,
     /// Equal to the ```upper_bound``` of the ```Port```.
-    Maximum = ladspa_h::HINT_DEFAULT_MAXIMUM as isize,
+    Maximum,
 
     /// Equal to 0 or false for toggled values.
-    Value0 = ladspa_h::HINT_DEFAULT_0 as isize,
+    Value0,
     /// Equal to 1 or true for toggled values.
-    Value1 = ladspa_h::HINT_DEFAULT_1 as isize,
+    Value1,
     /// Equal to 100.
-    Value100 = ladspa_h::HINT_DEFAULT_100 as isize,
+    Value100,
     /// Equal to 440, concert A. This may be off by a few Hz if the host is using an alternate
     /// tuning.
-    Value440 = ladspa_h::HINT_DEFAULT_440 as isize,
+    Value440,
+    /// Any other value, used as is like the fixed values above. The LADSPA descriptor can't
+    /// express it, so LADSPA hosts are given the closest default it can express instead. The
+    /// exact value is returned by ```Port::default_at```, and so used by the test harness and
+    /// the JACK and GStreamer wrappers, and is written to LV2 and RDF metadata.
+    Value(Data),
+}
+
+impl DefaultValue {
+    // The `HINT_DEFAULT_*` bits selecting this default, or 0 for `Value`.
+    fn bits(self) -> i32 {
+        match self {
+            DefaultValue::Minimum => ladspa_h::HINT_DEFAULT_MINIMUM,
+            DefaultValue::Low => ladspa_h::HINT_DEFAULT_LOW,
+            DefaultValue::Middle => ladspa_h::HINT_DEFAULT_MIDDLE,
+            DefaultValue::High => ladspa_h::HINT_DEFAULT_HIGH,
+            DefaultValue::Maximum => ladspa_h::HINT_DEFAULT_MAXIMUM,
+            DefaultValue::Value0 => ladspa_h::HINT_DEFAULT_0,
+            DefaultValue::Value1 => ladspa_h::HINT_DEFAULT_1,
+            DefaultValue::Value100 => ladspa_h::HINT_DEFAULT_100,
+            DefaultValue::Value440 => ladspa_h::HINT_DEFAULT_440,
+            DefaultValue::Value(_) => 0,
+        }
+    }
+}
+
+/// Computes the value hosts start a control port at, following the LADSPA default hints.
+///
+/// ```hint``` is the raw range hint descriptor of the port, as passed to hosts: its
+/// ```HINT_DEFAULT_*``` bits select the default, and the ```HINT_LOGARITHMIC``` and
+/// ```HINT_SAMPLE_RATE``` bits how it is computed. For ```HINT_SAMPLE_RATE``` ports, the bounds
+/// are multiplied by ```sample_rate``` before the defaults relative to them are computed, while
+/// the fixed defaults of 0, 1, 100 and 440 are used as they are. Logarithmic defaults fall back to
+/// linear interpolation when a bound is not positive.
+///
+/// Returns ```None``` if the hint has no default, or one this crate doesn't know.
+pub fn default_for(hint: i32, lower: Data, upper: Data, sample_rate: u64) -> Option<Data> {
+    let (lower, upper) = if hint & ladspa_h::HINT_SAMPLE_RATE != 0 {
+        (lower * sample_rate as Data, upper * sample_rate as Data)
+    } else {
+        (lower, upper)
+    };
+    let range = mapping::ControlRange::from_hint(hint & ladspa_h::HINT_LOGARITHMIC,
+                                                 Some(lower),
+                                                 Some(upper),
+                                                 sample_rate);
+    let between = |weight: Data| range.from_normalized(weight);
+    match hint & ladspa_h::HINT_DEFAULT_MASK {
+        ladspa_h::HINT_DEFAULT_MINIMUM => Some(lower),
+        ladspa_h::HINT_DEFAULT_LOW => Some(between(0.25)),
+        ladspa_h::HINT_DEFAULT_MIDDLE => Some(between(0.5)),
+        ladspa_h::HINT_DEFAULT_HIGH => Some(between(0.75)),
+        ladspa_h::HINT_DEFAULT_MAXIMUM => Some(upper),
+        ladspa_h::HINT_DEFAULT_0 => Some(0.0),
+        ladspa_h::HINT_DEFAULT_1 => Some(1.0),
+        ladspa_h::HINT_DEFAULT_100 => Some(100.0),
+        ladspa_h::HINT_DEFAULT_440 => Some(440.0),
+        _ => None,
+    }
+}
+
+/// What a running plugin instance can learn about itself: the descriptor it was instantiated
+/// from and the sample rate. Passed to ```Plugin::run_with_context```.
+///
+/// Generic wrappers can use it to look up the declared properties of the ports they are given,
+/// for example to keep control values within their bounds:
+///
+/// ```rust,ignore
+/// fn run_with_context<'a>(&mut self, context: Context, sample_count: usize,
+///                         ports: &mut [PortConnection<'a>]) {
+///     let gain = context.clamp(2, ports[2].unwrap_control());
+///     ...
+/// }
+/// ```
+#[derive(Copy, Clone)]
+pub struct Context<'a> {
+    descriptor: &'a PluginDescriptor,
+    sample_rate: u64,
+}
+
+impl<'a> Context<'a> {
+    /// Describes an instance of ```descriptor``` running at ```sample_rate```.
+    pub fn new(descriptor: &'a PluginDescriptor, sample_rate: u64) -> Context<'a> {
+        Context { descriptor, sample_rate }
+    }
+
+    /// The descriptor the plugin was instantiated from.
+    pub fn descriptor(self) -> &'a PluginDescriptor {
+        self.descriptor
+    }
+
+    /// The sample rate the plugin was instantiated at.
+    pub fn sample_rate(self) -> u64 {
+        self.sample_rate
+    }
+
+    /// The declaration of port ```port```.
+    pub fn port(self, port: usize) -> &'a Port {
+        &self.descriptor.ports[port]
+    }
+
+    // The factor the host multiplies the values of `port` by.
+    fn scale(self, port: usize) -> Data {
+        match self.port(port).hint {
+            Some(hint) if hint.contains(HINT_SAMPLE_RATE) => self.sample_rate as Data,
+            _ => 1.0,
+        }
+    }
+
+    /// The lower and upper bounds of port ```port```, multiplied by the sample rate for
+    /// ```HINT_SAMPLE_RATE``` ports so that they compare with the values the host sends.
+    pub fn bounds(self, port: usize) -> (Option<Data>, Option<Data>) {
+        let scale = self.scale(port);
+        let declared = self.port(port);
+        (declared.lower_bound.map(|bound| bound * scale),
+         declared.upper_bound.map(|bound| bound * scale))
+    }
+
+    /// The default value of port ```port``` at the sample rate. See ```default_for```.
+    pub fn default_value(self, port: usize) -> Option<Data> {
+        self.port(port).default_at(self.sample_rate)
+    }
+
+    /// Limits ```value``` to the bounds of port ```port```. Hosts are not required to respect
+    /// the bounds, so values read from control inputs may be anywhere.
+    pub fn clamp(self, port: usize, value: Data) -> Data {
+        match self.bounds(port) {
+            (Some(lower), _) if value < lower => lower,
+            (_, Some(upper)) if value > upper => upper,
+            _ => value,
+        }
+    }
 }
 
 /// Represents a connection between a port and the data attached to the port by the plugin
 /// host.
+///
+/// Plugins are handed the connections of all their ports at once, mutably, so that each output
+/// can be written through a plain ```&mut``` slice. The buffers of the outputs never overlap each
+/// other or those of the inputs: when a LADSPA host connects an input and an output to the same
+/// buffer, the exported plugin copies the input aside before running the plugin.
 pub struct PortConnection<'a> {
     /// The port which the data is connected to.
     pub port: Port,
@@ -224,113 +869,733 @@ pub struct PortConnection<'a> {
 /// Represents the four types of data a port can hold.
 pub enum PortData<'a> {
     AudioInput(&'a [Data]),
-    AudioOutput(RefCell<&'a mut [Data]>),
+    AudioOutput(&'a mut [Data]),
     ControlInput(&'a Data),
-    ControlOutput(RefCell<&'a mut Data>),
+    ControlOutput(&'a mut Data),
 }
 
-unsafe impl<'a> Sync for PortData<'a> { }
-
 impl<'a> PortConnection<'a> {
     /// Returns a slice pointing to the internal data of an audio input port. Panics if this port
     /// is not an ```AudioIn``` port.
-    pub fn unwrap_audio(&'a self) -> &'a [Data] {
-        if let PortData::AudioInput(ref data) = self.data {
+    pub fn unwrap_audio(&self) -> &'a [Data] {
+        if let PortData::AudioInput(data) = self.data {
             data
         } else {
             panic!("PortConnection::unwrap_audio called on a non audio input port!")
         }
     }
 
+    /// Same as ```unwrap_audio```, named to match ```unwrap_audio_out```.
+    pub fn unwrap_audio_in(&self) -> &'a [Data] {
+        self.unwrap_audio()
+    }
+
     /// Returns a mutable slice pointing to the internal data of an audio output port. Panics if
     /// this port is not an ```AudioOut``` port.
-    pub fn unwrap_audio_mut(&'a self) -> RefMut<'a, &'a mut [Data]> {
-        if let PortData::AudioOutput(ref data) = self.data {
-            data.borrow_mut()
+    pub fn unwrap_audio_mut(&mut self) -> &mut [Data] {
+        if let PortData::AudioOutput(ref mut data) = self.data {
+            data
         } else {
             panic!("PortConnection::unwrap_audio_mut called on a non audio output port!")
         }
     }
 
-    /// Returns a refrence to the internal data of an control input port. Panics if this port
+    /// Same as ```unwrap_audio_mut```, named to match ```unwrap_audio_in```.
+    pub fn unwrap_audio_out(&mut self) -> &mut [Data] {
+        self.unwrap_audio_mut()
+    }
+
+    /// Returns the current value of a control input port. Panics if this port
     /// is not an ```ControlIn``` port.
-    pub fn unwrap_control(&'a self) -> &'a Data {
+    pub fn unwrap_control(&self) -> Data {
         if let PortData::ControlInput(data) = self.data {
-            data
+            *data
         } else {
             panic!("PortConnection::unwrap_control called on a non control input port!")
         }
     }
 
-    /// Returns a mutable refrence to the internal data of an audio output port. Panics if
+    /// Returns a mutable refrence to the internal data of a control output port. Panics if
     /// this port is not an ```ControlOut``` port.
-    pub fn unwrap_control_mut(&'a self) -> RefMut<'a, &'a mut Data> {
-        if let PortData::ControlOutput(ref data) = self.data {
-            data.borrow_mut()
+    pub fn unwrap_control_mut(&mut self) -> &mut Data {
+        if let PortData::ControlOutput(ref mut data) = self.data {
+            data
         } else {
             panic!("PortConnection::unwrap_control called on a non control output port!")
         }
     }
+
+    /// Same as ```unwrap_control_mut```, named to match ```unwrap_audio_out```.
+    pub fn unwrap_control_out(&mut self) -> &mut Data {
+        self.unwrap_control_mut()
+    }
+
+    /// Returns the values of a control input port across the block, ramping from its value in
+    /// the previous block as recorded by ```ramp```. Panics if this port is not a ```ControlIn```
+    /// port.
+    pub fn unwrap_control_ramp(&self, ramp: &mut ControlRamp, sample_count: usize) -> Ramp {
+        ramp.ramp(self.unwrap_control(), sample_count)
+    }
+}
+
+/// Reads a control value as the type its port's hints describe, following the LADSPA rules
+/// rather than rounding by hand in each plugin:
+///
+/// ```rust,ignore
+/// if !p.bypass.as_bool() {
+///     self.filter.set_mode(p.mode.as_i32());
+/// }
+/// ```
+pub trait ControlPort {
+    /// The value of a ```HINT_TOGGLED``` port: on if it is above 0, off otherwise.
+    fn as_bool(&self) -> bool;
+
+    /// The value of a ```HINT_INTEGER``` port, rounded to the nearest whole number as hosts are
+    /// expected to send. NaN reads as 0, and values beyond the range of ```i32``` as its ends.
+    fn as_i32(&self) -> i32;
+}
+
+impl ControlPort for Data {
+    #[inline]
+    fn as_bool(&self) -> bool {
+        *self > 0.0
+    }
+
+    #[inline]
+    fn as_i32(&self) -> i32 {
+        self.round() as i32
+    }
 }
 
-bitflags!(
-    #[doc="Represents the special properties a LADSPA plugin can have.
-    To attach multiple properties, bitwise-or them together, for example
-    ```PROP_REALTIME | PROP_INPLACE_BROKEN```.
-    See documentation for the constants beginning with PROP_ for the more information."]
-    pub flags Properties: i32 {
+/// Groups the ports passed to ```Plugin::run``` by type, so they can be used without indexing
+/// into the port list or matching on ```PortData```. Ports of each type are returned in the order
+/// they were declared in the ```PluginDescriptor```.
+///
+/// ```no_run
+/// # use ladspa::{Plugin, PortConnection, Ports};
+/// # struct Amp;
+/// # impl Plugin for Amp {
+/// fn run<'a>(&mut self, sample_count: usize, ports: &mut [PortConnection<'a>]) {
+///     let mut ports = Ports::new(ports);
+///     let gain = ports.control(0);
+///     let input = ports.audio_input(0);
+///     for (i, o) in input.iter().zip(ports.audio_output(0).iter_mut()) {
+///         *o = *i * gain;
+///     }
+/// }
+/// # }
+/// ```
+///
+/// Inputs are returned for as long as the ports themselves live, so they can be held while the
+/// outputs are written. To refer to ports by name instead, declare them with ```ports!```, or look
+/// them up with ```by_name```, which inserting a port in the middle of the list doesn't break:
+///
+/// ```rust,ignore
+/// // In the function creating the plugin:
+/// let names = PortNames::new(&descriptor.ports);
+/// // In run:
+/// let ports = Ports::with_names(ports, &self.names);
+/// let gain = db_to_gain(ports["Gain (dB)"].unwrap_control());
+/// ```
+pub struct Ports<'p, 'a: 'p> {
+    ports: &'p mut [PortConnection<'a>],
+    names: Option<&'p PortNames>,
+}
+
+impl<'p, 'a: 'p> Ports<'p, 'a> {
+    /// Wraps the ports passed to ```Plugin::run```.
+    pub fn new(ports: &'p mut [PortConnection<'a>]) -> Ports<'p, 'a> {
+        Ports { ports, names: None }
+    }
+
+    /// Wraps the ports passed to ```Plugin::run```, finding them by name through ```names```
+    /// rather than by comparing every name in turn.
+    pub fn with_names(ports: &'p mut [PortConnection<'a>], names: &'p PortNames) -> Ports<'p, 'a> {
+        Ports { ports, names: Some(names) }
+    }
 
-        #[doc="No properties."]
-        const PROP_NONE = 0,
+    /// Returns the port named ```name```, or ```None``` if there is no such port. The first
+    /// declared port wins if several share the name.
+    pub fn by_name(&self, name: &str) -> Option<&PortConnection<'a>> {
+        self.position(name).map(|index| &self.ports[index])
+    }
+
+    /// Returns the port named ```name```, or ```None``` if there is no such port.
+    pub fn by_name_mut(&mut self, name: &str) -> Option<&mut PortConnection<'a>> {
+        self.position(name).map(move |index| &mut self.ports[index])
+    }
 
-        #[doc="Indicates that the plugin has a realtime dependency so it's output may not be cached."]
-        const PROP_REALTIME = ::ffi::ladspa_h::PROPERTY_REALTIME,
+    // The index of the port named `name`.
+    fn position(&self, name: &str) -> Option<usize> {
+        match self.names {
+            Some(names) => names.index(name).filter(|&index| index < self.ports.len()),
+            None => self.ports.iter().position(|port| port.port.name == name),
+        }
+    }
+
+    /// Returns every port, in declaration order.
+    pub fn all(&mut self) -> &mut [PortConnection<'a>] {
+        self.ports
+    }
+
+    /// Returns the data of each audio input port.
+    pub fn audio_inputs(&self) -> impl Iterator<Item = &'a [Data]> + '_ {
+        self.ports.iter().filter_map(|port| match port.data {
+            PortData::AudioInput(data) => Some(data),
+            _ => None,
+        })
+    }
+
+    /// Returns the data of the ```n```th audio input port. Panics if there are not that many
+    /// audio inputs.
+    pub fn audio_input(&self, n: usize) -> &'a [Data] {
+        match self.audio_inputs().nth(n) {
+            Some(data) => data,
+            None => panic!("Ports::audio_input called for input {} but there are only {}!",
+                           n,
+                           self.audio_inputs().count()),
+        }
+    }
+
+    /// Returns the data of each audio output port.
+    pub fn audio_outputs(&mut self) -> impl Iterator<Item = &mut [Data]> + use<'_, 'p, 'a> {
+        self.ports.iter_mut().filter_map(|port| match port.data {
+            PortData::AudioOutput(ref mut data) => Some(&mut **data),
+            _ => None,
+        })
+    }
+
+    /// Returns the data of the ```n```th audio output port. Panics if there are not that many
+    /// audio outputs.
+    pub fn audio_output(&mut self, n: usize) -> &mut [Data] {
+        let count = self.count(|data| matches!(data, PortData::AudioOutput(_)));
+        match self.audio_outputs().nth(n) {
+            Some(data) => data,
+            None => panic!("Ports::audio_output called for output {} but there are only {}!",
+                           n,
+                           count),
+        }
+    }
+
+    // The number of ports whose data matches `kind`.
+    fn count<F: Fn(&PortData) -> bool>(&self, kind: F) -> usize {
+        self.ports.iter().filter(|port| kind(&port.data)).count()
+    }
+
+    /// Returns the value of each control input port.
+    pub fn controls(&self) -> impl Iterator<Item = Data> + '_ {
+        self.ports.iter().filter_map(|port| match port.data {
+            PortData::ControlInput(data) => Some(*data),
+            _ => None,
+        })
+    }
+
+    /// Returns the value of the ```n```th control input port. Panics if there are not that many
+    /// control inputs.
+    pub fn control(&self, n: usize) -> Data {
+        match self.controls().nth(n) {
+            Some(value) => value,
+            None => panic!("Ports::control called for input {} but there are only {}!",
+                           n,
+                           self.controls().count()),
+        }
+    }
+
+    /// Returns the values of the ```n```th control input port across the block, ramping from its
+    /// value in the previous block as recorded by ```ramp```. Panics if there are not that many
+    /// control inputs.
+    pub fn control_ramp(&self, n: usize, ramp: &mut ControlRamp, sample_count: usize) -> Ramp {
+        ramp.ramp(self.control(n), sample_count)
+    }
+
+    /// Returns the data of each control output port.
+    pub fn control_outputs(&mut self) -> impl Iterator<Item = &mut Data> + use<'_, 'p, 'a> {
+        self.ports.iter_mut().filter_map(|port| match port.data {
+            PortData::ControlOutput(ref mut data) => Some(&mut **data),
+            _ => None,
+        })
+    }
+
+    /// Returns the data of each channel of the input ```PortGroup``` named ```group```, in
+    /// order.
+    pub fn input_group<'s>(&'s self, group: &'s str) -> impl Iterator<Item = &'a [Data]> + 's {
+        self.ports.iter().filter_map(move |port| match port.data {
+            PortData::AudioInput(data) if port.port.group == Some(group) => Some(data),
+            _ => None,
+        })
+    }
+
+    /// Returns the data of each channel of the output ```PortGroup``` named ```group```, in
+    /// order.
+    pub fn output_group<'s>(&'s mut self,
+                            group: &'s str)
+                            -> impl Iterator<Item = &'s mut [Data]> + use<'s, 'p, 'a> {
+        self.ports.iter_mut().filter_map(move |port| match port.data {
+            PortData::AudioOutput(ref mut data) if port.port.group == Some(group) => {
+                Some(&mut **data)
+            }
+            _ => None,
+        })
+    }
+
+    /// Returns the data of the ```N``` channels of the input ```PortGroup``` named ```group```.
+    /// Panics if the group does not have exactly ```N``` channels.
+    pub fn input_channels<const N: usize>(&self, group: &str) -> [&'a [Data]; N] {
+        let mut channels = self.input_group(group);
+        let result = core::array::from_fn(|n| match channels.next() {
+            Some(channel) => channel,
+            None => panic!("Ports::input_channels called for {} channels of {} but there are only {}!",
+                           N,
+                           group,
+                           n),
+        });
+        assert!(channels.next().is_none(),
+                "Ports::input_channels called for {} channels of {} but there are more!",
+                N,
+                group);
+        result
+    }
+
+    /// Returns the data of the ```N``` channels of the output ```PortGroup``` named ```group```.
+    /// Panics if the group does not have exactly ```N``` channels.
+    pub fn output_channels<'s, const N: usize>(&'s mut self,
+                                               group: &'s str)
+                                               -> [&'s mut [Data]; N] {
+        let mut channels = self.output_group(group);
+        let result = core::array::from_fn(|n| match channels.next() {
+            Some(channel) => channel,
+            None => panic!("Ports::output_channels called for {} channels of {} but there are only {}!",
+                           N,
+                           group,
+                           n),
+        });
+        assert!(channels.next().is_none(),
+                "Ports::output_channels called for {} channels of {} but there are more!",
+                N,
+                group);
+        result
+    }
+
+    /// Calls ```f``` for each of the first ```sample_count``` frames, with the samples of the
+    /// ```N``` audio inputs at that point in time and the ```M``` samples to write to the audio
+    /// outputs, which start at zero. Panics if the plugin does not have exactly ```N``` audio
+    /// inputs and ```M``` audio outputs.
+    ///
+    /// ```no_run
+    /// # use ladspa::{Data, PortConnection, Ports};
+    /// # fn run<'a>(sample_count: usize, ports: &mut [PortConnection<'a>]) {
+    /// Ports::new(ports).zip_audio(sample_count, |&[left, right], out: &mut [Data; 1]| {
+    ///     out[0] = (left + right) * 0.5;
+    /// });
+    /// # }
+    /// ```
+    pub fn zip_audio<const N: usize, const M: usize, F>(&mut self, sample_count: usize, mut f: F)
+        where F: FnMut(&[Data; N], &mut [Data; M])
+    {
+        let inputs: [&'a [Data]; N] = {
+            let mut inputs = self.audio_inputs();
+            core::array::from_fn(|n| match inputs.next() {
+                Some(input) => input,
+                None => {
+                    panic!("Ports::zip_audio called for {} audio inputs but there are only {}!",
+                           N,
+                           n)
+                }
+            })
+        };
+        assert!(self.audio_inputs().count() == N,
+                "Ports::zip_audio called for {} audio inputs but there are more!",
+                N);
+        assert!(self.count(|data| matches!(data, PortData::AudioOutput(_))) == M,
+                "Ports::zip_audio called for {} audio outputs but there are {}!",
+                M,
+                self.count(|data| matches!(data, PortData::AudioOutput(_))));
+        let mut outputs = self.audio_outputs();
+        let mut outputs: [&mut [Data]; M] = core::array::from_fn(|_| outputs.next().unwrap());
+        for i in 0..sample_count {
+            let frame = core::array::from_fn(|n| inputs[n][i]);
+            let mut out = [0.0; M];
+            f(&frame, &mut out);
+            for (output, sample) in outputs.iter_mut().zip(out.iter()) {
+                output[i] = *sample;
+            }
+        }
+    }
+
+    /// Reports the plugin's latency, in samples, through its ```Port::latency_output```. Panics if
+    /// the plugin has no latency output.
+    pub fn set_latency(&mut self, samples: usize) {
+        match self.ports.iter_mut().find(|port| port.port.is_latency_output()) {
+            Some(port) => *port.unwrap_control_out() = samples as Data,
+            None => panic!("Ports::set_latency called but the plugin has no latency output!"),
+        }
+    }
+}
 
-        #[doc="Indicates that the plugin will not function correctly if the input and output audio
-        data has the same memory location. This could be an issue if you copy input to output
-        then refer back to previous values of the input as they will be overwritten. It is
-        recommended that you avoid using this flag if possible as it can decrease the speed of
-        the plugin."]
-        const PROP_INPLACE_BROKEN = ::ffi::ladspa_h::PROPERTY_INPLACE_BROKEN,
+/// Panics if there is no port named ```name```.
+impl<'p, 'a: 'p, 'n> Index<&'n str> for Ports<'p, 'a> {
+    type Output = PortConnection<'a>;
 
-        #[doc="Indicates that the plugin is capable of running not only in a conventional host but
-        also in a 'hard real-time' environment. To qualify for this the plugin must
-        satisfy all of the following:
+    fn index(&self, name: &'n str) -> &PortConnection<'a> {
+        match self.by_name(name) {
+            Some(port) => port,
+            None => panic!("Ports indexed with {:?} but there is no such port!", name),
+        }
+    }
+}
 
-        * The plugin must not use malloc(), free() or other heap memory
-        management within its run() function. All new
-        memory used in run() must be managed via the stack. These
-        restrictions only apply to the run() function.
+/// Panics if there is no port named ```name```.
+impl<'p, 'a: 'p, 'n> IndexMut<&'n str> for Ports<'p, 'a> {
+    fn index_mut(&mut self, name: &'n str) -> &mut PortConnection<'a> {
+        match self.by_name_mut(name) {
+            Some(port) => port,
+            None => panic!("Ports indexed with {:?} but there is no such port!", name),
+        }
+    }
+}
 
-        * The plugin will not attempt to make use of any library
-        functions with the exceptions of functions in the ANSI standard C
-        and C maths libraries, which the host is expected to provide.
+/// The index of each port of a plugin, by name, for ```Ports::with_names```. Built once, when
+/// the plugin is instantiated, from the ports of its descriptor, so that looking ports up while
+/// running neither allocates nor compares every name.
+#[derive(Clone, Debug, Default)]
+pub struct PortNames {
+    // Sorted by name, then by index.
+    names: Vec<(&'static str, usize)>,
+}
 
-        * The plugin will not access files, devices, pipes, sockets, IPC
-        or any other mechanism that might result in process or thread
-        blocking.
+impl PortNames {
+    /// Indexes ```ports```, as declared in the ```PluginDescriptor```.
+    pub fn new(ports: &[Port]) -> PortNames {
+        let mut names: Vec<_> = ports.iter().enumerate().map(|(n, port)| (port.name, n)).collect();
+        names.sort_unstable();
+        PortNames { names }
+    }
 
-        * The plugin will take an amount of time to execute a run()
-        call approximately of form (A+B*SampleCount) where A
-        and B depend on the machine and host in use. This amount of time
-        may not depend on input signals or plugin state. The host is left
-        the responsibility to perform timings to estimate upper bounds for
-        A and B."]
-        const PROP_HARD_REALTIME_CAPABLE = ::ffi::ladspa_h::PROPERTY_HARD_RT_CAPABLE,
+    /// The index of the port named ```name```, or ```None``` if there is no such port. The first
+    /// declared port wins if several share the name.
+    pub fn index(&self, name: &str) -> Option<usize> {
+        let first = self.names.partition_point(|&(other, _)| other < name);
+        match self.names.get(first) {
+            Some(&(other, index)) if other == name => Some(index),
+            _ => None,
+        }
     }
-);
+}
+
+// bitflags 0.8 expands to uses of the deprecated `try!` macro.
+#[allow(deprecated)]
+mod properties {
+    bitflags!(
+        #[doc="Represents the special properties a LADSPA plugin can have.
+        To attach multiple properties, bitwise-or them together, for example
+        ```PROP_REALTIME | PROP_INPLACE_BROKEN```.
+        See documentation for the constants beginning with PROP_ for the more information."]
+        pub flags Properties: i32 {
+
+            #[doc="No properties."]
+            const PROP_NONE = 0,
+
+            #[doc="Indicates that the plugin has a realtime dependency so it's output may not be cached."]
+            const PROP_REALTIME = ::ffi::ladspa_h::PROPERTY_REALTIME,
+
+            #[doc="Indicates that the plugin will not function correctly if the input and output audio
+            data has the same memory location. This could be an issue if you copy input to output
+            then refer back to previous values of the input as they will be overwritten. It is
+            recommended that you avoid using this flag if possible as it can decrease the speed of
+            the plugin."]
+            const PROP_INPLACE_BROKEN = ::ffi::ladspa_h::PROPERTY_INPLACE_BROKEN,
+
+            /// Indicates that the plugin is capable of running not only in a conventional host but
+            /// also in a 'hard real-time' environment. To qualify for this the plugin must
+            /// satisfy all of the following:
+            ///
+            /// * The plugin must not use malloc(), free() or other heap memory
+            ///   management within its run() function. All new
+            ///   memory used in run() must be managed via the stack. These
+            ///   restrictions only apply to the run() function.
+            ///
+            /// * The plugin will not attempt to make use of any library
+            ///   functions with the exceptions of functions in the ANSI standard C
+            ///   and C maths libraries, which the host is expected to provide.
+            ///
+            /// * The plugin will not access files, devices, pipes, sockets, IPC
+            ///   or any other mechanism that might result in process or thread
+            ///   blocking.
+            ///
+            /// * The plugin will take an amount of time to execute a run()
+            ///   call approximately of form (A+B*SampleCount) where A
+            ///   and B depend on the machine and host in use. This amount of time
+            ///   may not depend on input signals or plugin state. The host is left
+            ///   the responsibility to perform timings to estimate upper bounds for
+            ///   A and B.
+            const PROP_HARD_REALTIME_CAPABLE = ::ffi::ladspa_h::PROPERTY_HARD_RT_CAPABLE,
+        }
+    );
+}
+pub use properties::*;
 
 /// Represents an instance of a plugin which may be exposed as a LADSPA plugin using
 /// ```get_ladspa_descriptor```. It is not necessary to implement activate to deactivate.
-pub trait Plugin {
+///
+/// Panics never unwind into the host. If any of these methods panic, the instance is poisoned:
+/// the plugin is not called again and its audio outputs are silenced until the host cleans it up.
+///
+/// The calls are always made in the order LADSPA prescribes, even when the host gets it wrong:
+/// ```activate```, any number of blocks, then ```deactivate```, possibly repeated. A host running
+/// the plugin before activating it, activating it twice or cleaning it up while active gets the
+/// missing calls made for it, and a message on standard output.
+///
+/// Hosts commonly create an instance on one thread, run it on their audio thread and clean it up
+/// on yet another, so plugins must be ```Send```. They need not be ```Sync```: LADSPA forbids
+/// calling into an instance from several threads at once, and the exported plugin never does.
+/// Types holding raw pointers or ```Rc``` must either avoid them or assert that moving them
+/// between threads is sound with an ```unsafe impl Send```.
+pub trait Plugin: Send {
+    /// Prepares the plugin to run at ```sample_rate```, in blocks of at most ```max_block_hint```
+    /// samples when the host knows that size. Called once after the instance is created and
+    /// before ```activate```, and again whenever the sample rate changes while the plugin is
+    /// inactive: plugins should derive their coefficients and size their buffers here rather
+    /// than only in their constructor.
+    ///
+    /// Adapters pass the call on to the plugins they wrap, with the rate and block size those
+    /// plugins actually see. The default implementation does nothing.
+    fn initialize(&mut self, sample_rate: u64, max_block_hint: Option<usize>) {
+        let _ = (sample_rate, max_block_hint);
+    }
+
     /// The plugin instance must reset all state information dependent
     /// on the history of the plugin instance here.
     /// Will be called before `run` is called for the first time.
     fn activate(&mut self) { }
 
     /// Runs the plugin on a number of samples, given the connected ports.
-    fn run<'a>(&mut self, sample_count: usize, ports: &[&'a PortConnection<'a>]);
+    fn run<'a>(&mut self, sample_count: usize, ports: &mut [PortConnection<'a>]);
+
+    /// Runs the plugin like ```run```, also passing the descriptor and sample rate of the
+    /// instance. Hosts call this instead of ```run```; the default implementation ignores the
+    /// context and calls ```run```.
+    fn run_with_context<'a>(&mut self,
+                            context: Context,
+                            sample_count: usize,
+                            ports: &mut [PortConnection<'a>]) {
+        let _ = context;
+        self.run(sample_count, ports);
+    }
+
+    /// Returns whether denormal numbers should be flushed to zero while the plugin runs, by
+    /// installing a ```util::DenormalGuard``` around every call to ```run```, ```run_adding```
+    /// and ```run_synth```. Plugins with recursive filters or feedback loops should return
+    /// ```true```. The default is ```false```, leaving the host's floating point mode alone.
+    fn flush_denormals(&self) -> bool {
+        false
+    }
+
+    /// Whether ```run_adding``` is overridden with a native implementation which LADSPA hosts
+    /// should call. The default is ```false```, letting the exported plugin implement additive
+    /// output itself without allocating.
+    fn native_run_adding(&self) -> bool {
+        false
+    }
+
+    /// Runs the plugin on a number of samples, adding its output to the data already present in
+    /// the audio output ports after scaling it by ```gain```.
+    ///
+    /// The default implementation saves the current contents of the outputs, calls ```run``` and
+    /// mixes the saved data back in. It allocates on every call, but LADSPA hosts never reach it:
+    /// unless ```native_run_adding``` returns ```true```, the exported plugin runs the plugin into
+    /// scratch buffers allocated at instantiation and mixes those in instead.
+    fn run_adding<'a>(&mut self, gain: Data, sample_count: usize, ports: &mut [PortConnection<'a>]) {
+        let saved: Vec<Vec<Data>> = Ports::new(ports)
+            .audio_outputs()
+            .map(|data| data.to_vec())
+            .collect();
+
+        self.run(sample_count, ports);
+
+        let mut ports = Ports::new(ports);
+        for (data, saved) in ports.audio_outputs().zip(saved.iter()) {
+            for (out, old) in data.iter_mut().zip(saved.iter()) {
+                *out = *old + gain * *out;
+            }
+        }
+    }
+
+    /// Runs the plugin like ```run```, also passing the MIDI events which happen during the block,
+    /// in order. Only DSSI hosts call this, instead of ```run```. At most
+    /// ```dssi::MAX_BLOCK_EVENTS``` events are passed per block, so as not to allocate; any
+    /// further events in the block are dropped.
+    ///
+    /// The default implementation ignores the events and calls ```run```.
+    #[cfg(feature = "dssi")]
+    fn run_synth<'a>(&mut self,
+                     sample_count: usize,
+                     ports: &mut [PortConnection<'a>],
+                     events: &[dssi::MidiEvent]) {
+        let _ = events;
+        self.run(sample_count, ports);
+    }
+
+    /// Returns the plugin as ```Stateful``` if it keeps state beyond its control values, such as a
+    /// loaded sample or a learnt noise profile, which hosts should save along with a session.
+    /// Plugins implementing ```Stateful``` return ```Some(self)```, from this and from ```state```;
+    /// the default is ```None```.
+    fn stateful(&mut self) -> Option<&mut dyn Stateful> {
+        None
+    }
+
+    /// Returns the plugin as ```Stateful``` for saving its state, as ```stateful``` does for
+    /// restoring it. The two must agree; the default is ```None```.
+    fn state(&self) -> Option<&dyn Stateful> {
+        None
+    }
 
     /// Indicates the plugin is no longer live.
     fn deactivate(&mut self) { }
 }
+
+/// Boxed plugins are plugins too, so that the instances created by a ```PluginDescriptor``` can be
+/// wrapped in the types of the ```adapter``` module.
+impl<P: Plugin + ?Sized> Plugin for Box<P> {
+    fn initialize(&mut self, sample_rate: u64, max_block_hint: Option<usize>) {
+        (**self).initialize(sample_rate, max_block_hint);
+    }
+
+    fn activate(&mut self) {
+        (**self).activate();
+    }
+
+    fn run<'a>(&mut self, sample_count: usize, ports: &mut [PortConnection<'a>]) {
+        (**self).run(sample_count, ports);
+    }
+
+    fn run_with_context<'a>(&mut self,
+                            context: Context,
+                            sample_count: usize,
+                            ports: &mut [PortConnection<'a>]) {
+        (**self).run_with_context(context, sample_count, ports);
+    }
+
+    fn flush_denormals(&self) -> bool {
+        (**self).flush_denormals()
+    }
+
+    fn native_run_adding(&self) -> bool {
+        (**self).native_run_adding()
+    }
+
+    fn run_adding<'a>(&mut self,
+                      gain: Data,
+                      sample_count: usize,
+                      ports: &mut [PortConnection<'a>]) {
+        (**self).run_adding(gain, sample_count, ports);
+    }
+
+    #[cfg(feature = "dssi")]
+    fn run_synth<'a>(&mut self,
+                     sample_count: usize,
+                     ports: &mut [PortConnection<'a>],
+                     events: &[dssi::MidiEvent]) {
+        (**self).run_synth(sample_count, ports, events);
+    }
+
+    fn stateful(&mut self) -> Option<&mut dyn Stateful> {
+        (**self).stateful()
+    }
+
+    fn state(&self) -> Option<&dyn Stateful> {
+        (**self).state()
+    }
+
+    fn deactivate(&mut self) {
+        (**self).deactivate();
+    }
+}
+
+/// A plugin whose state can be saved and restored, as described by ```Plugin::stateful```.
+///
+/// The state is opaque to hosts, which store it and hand it back unchanged, possibly to an instance
+/// created by a later version of the plugin: include a version number in it if its format may
+/// change. LADSPA has no notion of state, so only the hosts of the ```host``` module know about
+/// it; others keep restoring just the control values.
+pub trait Stateful {
+    /// Returns the current state. Not called while the plugin runs.
+    fn save(&self) -> Vec<u8>;
+
+    /// Restores a state returned by ```save```. Not called while the plugin runs. Invalid states
+    /// should be ignored rather than panic on.
+    fn restore(&mut self, state: &[u8]);
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    // Scales its input by its control input.
+    struct Gain;
+
+    impl Plugin for Gain {
+        fn run<'a>(&mut self, sample_count: usize, ports: &mut [PortConnection<'a>]) {
+            let gain = ports[2].unwrap_control();
+            let input = ports[0].unwrap_audio();
+            let output = &mut ports[1].unwrap_audio_mut()[..sample_count];
+            for (out, &sample) in output.iter_mut().zip(input) {
+                *out = sample * gain;
+            }
+        }
+    }
+
+    fn gain() -> PluginDescriptor {
+        PluginDescriptor::builder()
+            .unique_id(UniqueId::experimental(998).get())
+            .label("test_gain")
+            .name("Test Gain")
+            .maker("ladspa.rs")
+            .copyright("None")
+            .audio_in("Input")
+            .audio_out("Output")
+            .control_in("Gain", 0.0..4.0, DefaultValue::Value1)
+            .new(|_, _| Ok(Box::new(Gain)))
+            .build()
+            .unwrap()
+    }
+
+    fn assert_send<T: Send>() {}
+
+    #[test]
+    fn constructors_and_instances_are_send() {
+        assert_send::<PluginConstructor>();
+        assert_send::<Box<dyn Plugin + Send>>();
+    }
+
+    #[test]
+    fn instances_run_on_another_thread_than_they_were_created_on() {
+        let descriptor = gain();
+        let mut plugin = (descriptor.new)(&descriptor, 48000).unwrap();
+        let ports = descriptor.ports.clone();
+        let output = thread::spawn(move || {
+            let input = [0.5; 4];
+            let mut output = [0.0; 4];
+            let gain = 3.0;
+            plugin.activate();
+            plugin.run(4,
+                       &mut [PortConnection {
+                                 port: ports[0],
+                                 data: PortData::AudioInput(&input),
+                             },
+                             PortConnection {
+                                 port: ports[1],
+                                 data: PortData::AudioOutput(&mut output),
+                             },
+                             PortConnection {
+                                 port: ports[2],
+                                 data: PortData::ControlInput(&gain),
+                             }]);
+            output
+        })
+        .join()
+        .unwrap();
+        assert_eq!(output, [1.5; 4]);
+    }
+}
@@ -0,0 +1,128 @@
+#[cfg(not(feature = "std"))]
+use core::cell::UnsafeCell;
+#[cfg(not(feature = "std"))]
+use core::ops::{Deref, DerefMut};
+#[cfg(not(feature = "std"))]
+use core::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "std")]
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+// A lock for the global tables, which are only locked briefly, mostly while the library is
+// loaded, and never while user code runs. A `std::sync::Mutex` with std.
+#[cfg(feature = "std")]
+pub struct Lock<T>(Mutex<T>);
+
+#[cfg(feature = "std")]
+pub type LockGuard<'l, T> = MutexGuard<'l, T>;
+
+#[cfg(feature = "std")]
+impl<T> Lock<T> {
+    pub const fn new(value: T) -> Lock<T> {
+        Lock(Mutex::new(value))
+    }
+
+    // The tables are left consistent by a panic, so a poisoned lock is still used.
+    pub fn lock(&self) -> LockGuard<'_, T> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+// A spin lock, as `std::sync::Mutex` is not available without std.
+#[cfg(not(feature = "std"))]
+pub struct Lock<T> {
+    locked: AtomicBool,
+    value: UnsafeCell<T>,
+}
+
+#[cfg(not(feature = "std"))]
+unsafe impl<T: Send> Sync for Lock<T> {}
+
+// Unlocks when dropped, including when unwinding from a panic.
+#[cfg(not(feature = "std"))]
+pub struct LockGuard<'l, T: 'l> {
+    lock: &'l Lock<T>,
+}
+
+#[cfg(not(feature = "std"))]
+impl<T> Lock<T> {
+    pub const fn new(value: T) -> Lock<T> {
+        Lock {
+            locked: AtomicBool::new(false),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn lock(&self) -> LockGuard<'_, T> {
+        while self.locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err() {
+            core::hint::spin_loop();
+        }
+        LockGuard { lock: self }
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl<'l, T> Deref for LockGuard<'l, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl<'l, T> DerefMut for LockGuard<'l, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl<'l, T> Drop for LockGuard<'l, T> {
+    fn drop(&mut self) {
+        self.lock.locked.store(false, Ordering::Release);
+    }
+}
+
+// Calls `f` when the library is unloaded, to free its global tables. On Linux, `atexit` handlers
+// registered by a shared library run when it is unloaded. Windows runs them at process exit,
+// possibly after the library is gone, so the tables are left for the system to reclaim there.
+pub fn at_unload(f: extern "C" fn()) {
+    #[cfg(all(unix, not(target_os = "macos")))]
+    unsafe {
+        libc::atexit(f);
+    }
+    #[cfg(target_os = "macos")]
+    macos::at_unload(f);
+    #[cfg(not(unix))]
+    let _ = f;
+}
+
+// macOS only ties `atexit` handlers to the image registering them when it can find that image
+// from the handler's address, so the handler is registered against this library's
+// `__dso_handle` explicitly, as C++ static destructors are. dyld runs it on `dlclose`, or at
+// exit if the library is never unloaded, which is the case once Rust thread locals were used.
+#[cfg(target_os = "macos")]
+mod macos {
+    use core::mem;
+
+    use libc::{c_int, c_void};
+
+    extern "C" {
+        static __dso_handle: c_void;
+        fn __cxa_atexit(f: extern "C" fn(*mut c_void), arg: *mut c_void, dso: *const c_void)
+                        -> c_int;
+    }
+
+    extern "C" fn trampoline(f: *mut c_void) {
+        let f: extern "C" fn() = unsafe { mem::transmute(f) };
+        f();
+    }
+
+    pub fn at_unload(f: extern "C" fn()) {
+        unsafe {
+            __cxa_atexit(trampoline, f as *mut c_void, &__dso_handle);
+        }
+    }
+}
@@ -0,0 +1,443 @@
+//! Exporting plugins as LV2 plugins.
+//!
+//! With the ```lv2``` feature enabled, the plugin library exports ```lv2_descriptor``` next to
+//! ```ladspa_descriptor```, so the same build can be loaded by LV2 hosts. Every plugin returned by
+//! ```get_ladspa_descriptor``` is exposed under the URI given by ```plugin_uri```, running the
+//! same ```Plugin``` implementation.
+//!
+//! Unlike LADSPA, LV2 describes plugins in Turtle files installed in a bundle directory next to
+//! the library. ```write_bundle``` generates them:
+//!
+//! ```no_run
+//! # use std::path::Path;
+//! # fn my_plugin_descriptor() -> ladspa::PluginDescriptor { unimplemented!() }
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let plugins = vec![my_plugin_descriptor()];
+//! ladspa::lv2::write_bundle(Path::new("my_plugins.lv2"), "libmy_plugins.so", &plugins)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! after which the library is copied into the bundle and the bundle into a directory listed in
+//! ```LV2_PATH``` (usually ~/.lv2/ or /usr/lib/lv2/).
+
+// c_ulong is only 32 bits wide on some platforms, so widening it is not always a no-op.
+#![allow(clippy::useless_conversion)]
+
+use alloc::boxed::Box;
+use alloc::ffi::CString;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write as FmtWrite;
+use core::ptr;
+#[cfg(feature = "std")]
+use std::fs::{self, File};
+#[cfg(feature = "std")]
+use std::io::{self, Write};
+#[cfg(feature = "std")]
+use std::path::Path;
+
+use libc::{c_char, c_double, c_ulong, c_void};
+
+use ffi::{self, ladspa_h};
+use lock::{at_unload, Lock};
+#[cfg(not(any(feature = "std", test)))]
+use math::Float;
+use super::{Category, PluginDescriptor, Port, PortDescriptor, Unit, HINT_INTEGER,
+            HINT_LOGARITHMIC, HINT_SAMPLE_RATE, HINT_TOGGLED, PROP_HARD_REALTIME_CAPABLE,
+            PROP_INPLACE_BROKEN};
+
+// The parts of lv2/core/lv2.h needed to export a plugin.
+mod lv2_h {
+    use libc::{c_char, c_double, c_void};
+
+    pub type Handle = *mut c_void;
+
+    #[repr(C)]
+    pub struct Feature {
+        pub uri: *const c_char,
+        pub data: *mut c_void,
+    }
+
+    #[repr(C)]
+    pub struct Descriptor {
+        pub uri: *const c_char,
+        pub instantiate: extern "C" fn(descriptor: *const Descriptor,
+                                       sample_rate: c_double,
+                                       bundle_path: *const c_char,
+                                       features: *const *const Feature)
+                                       -> Handle,
+        pub connect_port: extern "C" fn(instance: Handle, port: u32, data_location: *mut c_void),
+        pub activate: Option<extern "C" fn(instance: Handle)>,
+        pub run: extern "C" fn(instance: Handle, sample_count: u32),
+        pub deactivate: Option<extern "C" fn(instance: Handle)>,
+        pub cleanup: extern "C" fn(instance: Handle),
+        pub extension_data: extern "C" fn(uri: *const c_char) -> *const c_void,
+    }
+}
+
+/// Returns the URI under which ```plugin``` is exposed to LV2 hosts, derived from its unique ID.
+pub fn plugin_uri(plugin: &PluginDescriptor) -> String {
+    format!("urn:ladspa:{}", plugin.unique_id)
+}
+
+// An LV2 descriptor wrapping the LADSPA descriptor of the same plugin. The LV2 part comes first
+// so that a pointer to it is also a pointer to the whole struct.
+#[repr(C)]
+struct Wrapper {
+    lv2: lv2_h::Descriptor,
+    ladspa: &'static ladspa_h::Descriptor,
+    // Only borrowed by `lv2`.
+    _uri: CString,
+}
+
+// Hosts may query descriptors from any thread, and only read them.
+unsafe impl Send for Wrapper {}
+
+// An LV2 instance, which is a LADSPA instance along with the descriptor needed to drive it.
+struct Instance {
+    ladspa: &'static ladspa_h::Descriptor,
+    handle: ladspa_h::Handle,
+}
+
+// The descriptors built so far. `None` until the library is first queried, and again once it is
+// unloaded.
+static WRAPPERS: Lock<Option<Vec<Option<Box<Wrapper>>>>> = Lock::new(None);
+
+#[doc(hidden)]
+#[no_mangle]
+// Exported so the plugin is recognised by LV2 hosts.
+pub extern "C" fn lv2_descriptor(index: u32) -> *const c_void {
+    unsafe {
+        let index = index as usize;
+        if let Some(Some(wrapper)) = WRAPPERS.lock().as_ref().and_then(|table| table.get(index)) {
+            return &**wrapper as *const Wrapper as *const c_void;
+        }
+
+        // The LADSPA descriptor is cached (and eventually freed) by the ffi module, so it can be
+        // borrowed for as long as the library is loaded. Building it runs the plugin's code, so
+        // the table isn't locked meanwhile.
+        let ladspa = match ffi::ladspa_descriptor(index as c_ulong).as_ref() {
+            Some(ladspa) => ladspa,
+            None => return ptr::null(),
+        };
+        let rust_desc = &*(ladspa.implementation_data as *const PluginDescriptor);
+
+        let mut wrappers = WRAPPERS.lock();
+        let wrappers = &mut wrappers.get_or_insert_with(|| {
+            at_unload(global_destruct);
+            Vec::new()
+        });
+        if wrappers.len() <= index {
+            wrappers.resize_with(index + 1, || None);
+        }
+        let wrapper = wrappers[index].get_or_insert_with(|| {
+            let uri = CString::new(plugin_uri(rust_desc)).unwrap();
+            Box::new(Wrapper {
+                lv2: lv2_h::Descriptor {
+                    uri: uri.as_ptr(),
+                    instantiate,
+                    connect_port,
+                    activate: Some(activate),
+                    run,
+                    deactivate: Some(deactivate),
+                    cleanup,
+                    extension_data,
+                },
+                ladspa,
+                _uri: uri,
+            })
+        });
+        &**wrapper as *const Wrapper as *const c_void
+    }
+}
+
+extern "C" fn global_destruct() {
+    drop(WRAPPERS.lock().take());
+}
+
+unsafe fn instance<'a>(instance: lv2_h::Handle) -> &'a Instance {
+    &*(instance as *const Instance)
+}
+
+extern "C" fn instantiate(descriptor: *const lv2_h::Descriptor,
+                          sample_rate: c_double,
+                          _bundle_path: *const c_char,
+                          _features: *const *const lv2_h::Feature)
+                          -> lv2_h::Handle {
+    unsafe {
+        let ladspa = (*(descriptor as *const Wrapper)).ladspa;
+        let handle = (ladspa.instantiate)(ladspa, sample_rate.round() as c_ulong);
+        if handle.is_null() {
+            return ptr::null_mut();
+        }
+        Box::into_raw(Box::new(Instance {
+            ladspa,
+            handle,
+        })) as lv2_h::Handle
+    }
+}
+
+extern "C" fn connect_port(instance_ptr: lv2_h::Handle, port: u32, data_location: *mut c_void) {
+    unsafe {
+        let instance = instance(instance_ptr);
+        (instance.ladspa.connect_port)(instance.handle,
+                                       c_ulong::from(port),
+                                       data_location as *mut ladspa_h::Data);
+    }
+}
+
+extern "C" fn activate(instance_ptr: lv2_h::Handle) {
+    unsafe {
+        let instance = instance(instance_ptr);
+        if let Some(activate) = instance.ladspa.activate {
+            activate(instance.handle);
+        }
+    }
+}
+
+extern "C" fn run(instance_ptr: lv2_h::Handle, sample_count: u32) {
+    unsafe {
+        let instance = instance(instance_ptr);
+        (instance.ladspa.run)(instance.handle, c_ulong::from(sample_count));
+    }
+}
+
+extern "C" fn deactivate(instance_ptr: lv2_h::Handle) {
+    unsafe {
+        let instance = instance(instance_ptr);
+        if let Some(deactivate) = instance.ladspa.deactivate {
+            deactivate(instance.handle);
+        }
+    }
+}
+
+extern "C" fn cleanup(instance_ptr: lv2_h::Handle) {
+    unsafe {
+        let instance = Box::from_raw(instance_ptr as *mut Instance);
+        (instance.ladspa.cleanup)(instance.handle);
+    }
+}
+
+extern "C" fn extension_data(_uri: *const c_char) -> *const c_void {
+    ptr::null()
+}
+
+// Escapes a string for use in a Turtle string literal.
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+// Turns a port name into a valid, unique LV2 symbol: a C identifier.
+fn symbols(ports: &[Port]) -> Vec<String> {
+    let mut symbols: Vec<String> = Vec::with_capacity(ports.len());
+    for (index, port) in ports.iter().enumerate() {
+        let mut symbol: String = port.name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+            .collect();
+        if symbol.is_empty() || symbol.starts_with(|c: char| c.is_ascii_digit()) {
+            symbol.insert(0, '_');
+        }
+        if symbols.contains(&symbol) {
+            symbol = format!("{}_{}", symbol, index);
+        }
+        symbols.push(symbol);
+    }
+    symbols
+}
+
+const PREFIXES: &str = "@prefix doap: <http://usefulinc.com/ns/doap#> .
+@prefix foaf: <http://xmlns.com/foaf/0.1/> .
+@prefix lv2: <http://lv2plug.in/ns/lv2core#> .
+@prefix pprops: <http://lv2plug.in/ns/ext/port-props#> .
+@prefix pset: <http://lv2plug.in/ns/ext/presets#> .
+@prefix rdf: <http://www.w3.org/1999/02/22-rdf-syntax-ns#> .
+@prefix rdfs: <http://www.w3.org/2000/01/rdf-schema#> .
+@prefix time: <http://lv2plug.in/ns/ext/time#> .
+@prefix units: <http://lv2plug.in/ns/extensions/units#> .
+
+";
+
+// The LV2 class of a category. LV2 has the classes of the LADSPA ontology under the same names,
+// except for the broadest ones, which it leaves out or calls differently.
+fn lv2_class(category: Category) -> Option<&'static str> {
+    match category {
+        Category::Time | Category::Amplitude => None,
+        Category::Frequency => Some("SpectralPlugin"),
+        Category::FrequencyMeter => Some("AnalyserPlugin"),
+        category => Some(category.rdf_class()),
+    }
+}
+
+// The name of a unit in the LV2 units extension.
+fn lv2_unit(unit: Unit) -> &'static str {
+    match unit {
+        Unit::Hz => "hz",
+        Unit::Db => "db",
+        Unit::Seconds => "s",
+        Unit::Milliseconds => "ms",
+        Unit::Semitones => "semitone12TET",
+        Unit::Cents => "cent",
+        Unit::Percent => "pc",
+        Unit::Bpm => "bpm",
+    }
+}
+
+// The name of the Turtle file describing a plugin within its bundle.
+fn ttl_name(plugin: &PluginDescriptor) -> String {
+    format!("{}.ttl", plugin.label)
+}
+
+/// Returns the ```manifest.ttl``` of a bundle holding ```plugins```, whose library is named
+/// ```binary``` (for example "libmy_plugins.so").
+pub fn manifest(plugins: &[PluginDescriptor], binary: &str) -> String {
+    let mut ttl = String::from(PREFIXES);
+    for plugin in plugins {
+        let uri = plugin_uri(plugin);
+        let _ = writeln!(ttl,
+                         "<{}>\n    a lv2:Plugin ;\n    lv2:binary <{}> ;\n    rdfs:seeAlso <{}> .\n",
+                         uri,
+                         binary,
+                         ttl_name(plugin));
+        for number in 1..plugin.presets.len() + 1 {
+            let _ = writeln!(ttl,
+                             "<{}#preset{}>\n    a pset:Preset ;\n    lv2:appliesTo <{}> ;\n    \
+                              rdfs:seeAlso <{}> .\n",
+                             uri,
+                             number,
+                             uri,
+                             ttl_name(plugin));
+        }
+    }
+    ttl
+}
+
+/// Returns the Turtle description of ```plugin``` and its ports and presets.
+pub fn plugin_ttl(plugin: &PluginDescriptor) -> String {
+    let uri = plugin_uri(plugin);
+    let symbols = symbols(&plugin.ports);
+
+    let mut ttl = String::from(PREFIXES);
+    match plugin.category.and_then(lv2_class) {
+        Some(class) => {
+            let _ = writeln!(ttl, "<{}>\n    a lv2:Plugin , lv2:{} ;", uri, class);
+        }
+        None => {
+            let _ = writeln!(ttl, "<{}>\n    a lv2:Plugin ;", uri);
+        }
+    }
+    let _ = writeln!(ttl, "    doap:name \"{}\" ;", escape(plugin.name));
+    if !plugin.maker.is_empty() {
+        let _ = writeln!(ttl, "    doap:maintainer [ foaf:name \"{}\" ] ;", escape(plugin.maker));
+    }
+    if plugin.properties.contains(PROP_HARD_REALTIME_CAPABLE) {
+        let _ = writeln!(ttl, "    lv2:optionalFeature lv2:hardRTCapable ;");
+    }
+    if plugin.properties.contains(PROP_INPLACE_BROKEN) {
+        let _ = writeln!(ttl, "    lv2:requiredFeature lv2:inPlaceBroken ;");
+    }
+
+    let ports: Vec<String> = plugin.ports
+        .iter()
+        .enumerate()
+        .map(|(index, port)| {
+            let mut desc = String::from("[\n");
+            let class = match port.desc {
+                PortDescriptor::AudioInput => "lv2:AudioPort , lv2:InputPort",
+                PortDescriptor::AudioOutput => "lv2:AudioPort , lv2:OutputPort",
+                PortDescriptor::ControlInput => "lv2:ControlPort , lv2:InputPort",
+                PortDescriptor::ControlOutput => "lv2:ControlPort , lv2:OutputPort",
+                PortDescriptor::Invalid => "lv2:Port",
+            };
+            let _ = writeln!(desc, "        a {} ;", class);
+            let _ = writeln!(desc, "        lv2:index {} ;", index);
+            let _ = writeln!(desc, "        lv2:symbol \"{}\" ;", symbols[index]);
+            let _ = writeln!(desc, "        lv2:name \"{}\" ;", escape(port.name));
+            if let Some(default) = port.default_value() {
+                let _ = writeln!(desc, "        lv2:default {:?} ;", default);
+            }
+            if let Some(lower) = port.lower_bound {
+                let _ = writeln!(desc, "        lv2:minimum {:?} ;", lower);
+            }
+            if let Some(upper) = port.upper_bound {
+                let _ = writeln!(desc, "        lv2:maximum {:?} ;", upper);
+            }
+            if port.is_latency_output() {
+                let _ = writeln!(desc, "        lv2:designation lv2:latency ;");
+                let _ = writeln!(desc, "        lv2:portProperty lv2:reportsLatency ;");
+            }
+            if port.is_tempo() {
+                let _ = writeln!(desc, "        lv2:designation time:beatsPerMinute ;");
+            }
+            if let Some(hint) = port.hint {
+                let properties = [(HINT_TOGGLED, "lv2:toggled"),
+                                  (HINT_SAMPLE_RATE, "lv2:sampleRate"),
+                                  (HINT_LOGARITHMIC, "pprops:logarithmic"),
+                                  (HINT_INTEGER, "lv2:integer")];
+                for &(flag, property) in properties.iter() {
+                    if hint.contains(flag) {
+                        let _ = writeln!(desc, "        lv2:portProperty {} ;", property);
+                    }
+                }
+            }
+            if let Some(unit) = port.unit {
+                let _ = writeln!(desc, "        units:unit units:{} ;", lv2_unit(unit));
+            }
+            if !port.scale_points.is_empty() {
+                let _ = writeln!(desc, "        lv2:portProperty lv2:enumeration ;");
+                for (value, label) in port.scale_point_values() {
+                    let _ = writeln!(desc,
+                                     "        lv2:scalePoint [ rdfs:label \"{}\" ; \
+                                      rdf:value {:?} ] ;",
+                                     escape(label),
+                                     value);
+                }
+            }
+            desc.push_str("    ]");
+            desc
+        })
+        .collect();
+    if ports.is_empty() {
+        let _ = writeln!(ttl, "    rdfs:label \"{}\" .\n", escape(plugin.label));
+    } else {
+        let _ = writeln!(ttl, "    rdfs:label \"{}\" ;", escape(plugin.label));
+        let _ = writeln!(ttl, "    lv2:port {} .\n", ports.join(" , "));
+    }
+
+    for (number, preset) in plugin.presets.iter().enumerate() {
+        let _ = writeln!(ttl, "<{}#preset{}>\n    a pset:Preset ;", uri, number + 1);
+        let _ = writeln!(ttl, "    lv2:appliesTo <{}> ;", uri);
+        let values: Vec<String> = preset.values
+            .iter()
+            .filter_map(|&(index, value)| {
+                symbols.get(index).map(|symbol| {
+                    format!("[\n        lv2:symbol \"{}\" ;\n        pset:value {:?}\n    ]",
+                            symbol,
+                            value)
+                })
+            })
+            .collect();
+        if values.is_empty() {
+            let _ = writeln!(ttl, "    rdfs:label \"{}\" .\n", escape(preset.name));
+        } else {
+            let _ = writeln!(ttl, "    rdfs:label \"{}\" ;", escape(preset.name));
+            let _ = writeln!(ttl, "    lv2:port {} .\n", values.join(" , "));
+        }
+    }
+    ttl
+}
+
+/// Creates the directory ```bundle``` if needed and writes the ```manifest.ttl``` and plugin
+/// descriptions of ```plugins``` into it. ```binary``` is the file name of the plugin library,
+/// which must be copied into the bundle separately. Requires the ```std``` feature.
+#[cfg(feature = "std")]
+pub fn write_bundle(bundle: &Path, binary: &str, plugins: &[PluginDescriptor]) -> io::Result<()> {
+    fs::create_dir_all(bundle)?;
+    File::create(bundle.join("manifest.ttl"))?.write_all(manifest(plugins, binary).as_bytes())?;
+    for plugin in plugins {
+        File::create(bundle.join(ttl_name(plugin)))?.write_all(plugin_ttl(plugin).as_bytes())?;
+    }
+    Ok(())
+}
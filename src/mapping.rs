@@ -0,0 +1,241 @@
+//! Mapping control values to and from a normalized range of 0 to 1.
+//!
+//! Sliders, knobs, automation lanes and MIDI controllers all work in a fixed range, while control
+//! ports each declare their own bounds and hints. A ```ControlRange``` translates between the two
+//! the way LADSPA hosts do:
+//!
+//! * ```HINT_SAMPLE_RATE``` bounds are multiplied by the sample rate,
+//! * ```HINT_LOGARITHMIC``` ranges with positive bounds are covered evenly on a logarithmic scale,
+//!   as the ```HINT_DEFAULT_*``` hints are,
+//! * ```HINT_INTEGER``` values are rounded to whole numbers,
+//! * ```HINT_TOGGLED``` values are 0 below the middle and 1 from it.
+//!
+//! A range can also be skewed, to spend more of the normalized range on one end of a linear
+//! range, for example on the low frequencies of a filter whose author did not make it
+//! logarithmic:
+//!
+//! ```rust,ignore
+//! let range = port.range(sample_rate).with_center(1000.0);
+//! host.set_control(2, range.from_normalized(knob));
+//! ```
+
+use Data;
+use ffi::ladspa_h;
+#[cfg(not(any(feature = "std", test)))]
+use math::Float;
+
+/// The values a control port covers at a given sample rate, and how normalized values are spread
+/// over them. See the module documentation.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ControlRange {
+    /// The value a normalized 0 maps to.
+    pub lower: Data,
+    /// The value a normalized 1 maps to.
+    pub upper: Data,
+    /// Whether the range is covered on a logarithmic scale. Only honoured if both bounds are
+    /// positive.
+    pub logarithmic: bool,
+    /// Whether values are rounded to whole numbers.
+    pub integer: bool,
+    /// Whether values are either 0 or 1.
+    pub toggled: bool,
+    /// The exponent applied to normalized values before they are mapped: 1 spreads them evenly,
+    /// less than 1 spends more of them on the upper end of the range and more than 1 on the
+    /// lower end.
+    pub skew: Data,
+}
+
+impl ControlRange {
+    /// The range of a port with the raw range hint descriptor ```hint``` and bounds ```lower```
+    /// and ```upper```, running at ```sample_rate```. A missing bound is replaced by one a unit
+    /// away from the other, or the range by -1 to 1 if both are missing.
+    ///
+    /// Whether the bounds are given is passed as ```Option```s rather than through the
+    /// ```HINT_BOUNDED_*``` bits of ```hint```, which are ignored.
+    pub fn from_hint(hint: i32,
+                     lower: Option<Data>,
+                     upper: Option<Data>,
+                     sample_rate: u64)
+                     -> ControlRange {
+        let (lower, upper) = match (lower, upper) {
+            (Some(lower), Some(upper)) => (lower, upper),
+            (Some(lower), None) => (lower, lower + 1.0),
+            (None, Some(upper)) => (upper - 1.0, upper),
+            (None, None) => (-1.0, 1.0),
+        };
+        let scale = if hint & ladspa_h::HINT_SAMPLE_RATE != 0 { sample_rate as Data } else { 1.0 };
+        ControlRange {
+            lower: lower * scale,
+            upper: upper * scale,
+            logarithmic: hint & ladspa_h::HINT_LOGARITHMIC != 0,
+            integer: hint & ladspa_h::HINT_INTEGER != 0,
+            toggled: hint & ladspa_h::HINT_TOGGLED != 0,
+            skew: 1.0,
+        }
+    }
+
+    /// Returns the range skewed so that a normalized 0.5 maps to ```center```. Does nothing if
+    /// ```center``` is not strictly between the bounds.
+    ///
+    /// Useful for frequencies and times, whose interesting values bunch up at the low end, and
+    /// for gains in dB, where they bunch up at the high end.
+    pub fn with_center(self, center: Data) -> ControlRange {
+        let proportion = self.linear_to_normalized(center);
+        if proportion <= 0.0 || proportion >= 1.0 {
+            return self;
+        }
+        ControlRange { skew: proportion.ln() / 0.5f32.ln(), ..self }
+    }
+
+    /// Maps ```normalized```, clamped to 0 to 1, to a value within the range.
+    pub fn from_normalized(&self, normalized: Data) -> Data {
+        let normalized = normalized.clamp(0.0, 1.0);
+        if self.toggled {
+            return if normalized < 0.5 { 0.0 } else { 1.0 };
+        }
+        let proportion = if self.skew == 1.0 { normalized } else { normalized.powf(self.skew) };
+        let value = if self.is_logarithmic() {
+            // In double precision so that the ends come out as the bounds.
+            let (lower, upper) = (f64::from(self.lower).ln(), f64::from(self.upper).ln());
+            (lower + f64::from(proportion) * (upper - lower)).exp() as Data
+        } else {
+            self.lower + proportion * (self.upper - self.lower)
+        };
+        if self.integer {
+            // The value is within the bounds, so rounding moves it at most one past them.
+            let (lower, upper) = ordered(self.lower, self.upper);
+            let rounded = value.round();
+            if rounded < lower && rounded + 1.0 <= upper {
+                rounded + 1.0
+            } else if rounded > upper && rounded - 1.0 >= lower {
+                rounded - 1.0
+            } else {
+                rounded
+            }
+        } else {
+            value
+        }
+    }
+
+    /// Maps ```value``` to where it is in the range, between 0 and 1. Values outside of the
+    /// range are clamped to it.
+    pub fn to_normalized(&self, value: Data) -> Data {
+        if self.toggled {
+            return if value > 0.0 { 1.0 } else { 0.0 };
+        }
+        let proportion = self.linear_to_normalized(value);
+        if self.skew == 1.0 || proportion == 0.0 {
+            proportion
+        } else {
+            proportion.powf(1.0 / self.skew)
+        }
+    }
+
+    // Where `value` is in the range, ignoring the skew.
+    fn linear_to_normalized(&self, value: Data) -> Data {
+        if self.lower == self.upper || value.is_nan() {
+            return 0.0;
+        }
+        let (lower, upper) = ordered(self.lower, self.upper);
+        let value = value.clamp(lower, upper);
+        let proportion = if self.is_logarithmic() {
+            (value.ln() - self.lower.ln()) / (self.upper.ln() - self.lower.ln())
+        } else {
+            (value - self.lower) / (self.upper - self.lower)
+        };
+        proportion.clamp(0.0, 1.0)
+    }
+
+    fn is_logarithmic(&self) -> bool {
+        self.logarithmic && self.lower > 0.0 && self.upper > 0.0
+    }
+}
+
+// Plugins may declare their bounds upside down, which hosts accept as long as the values sent
+// stay between them.
+fn ordered(a: Data, b: Data) -> (Data, Data) {
+    if a <= b { (a, b) } else { (b, a) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ffi::ladspa_h::{HINT_INTEGER, HINT_LOGARITHMIC, HINT_SAMPLE_RATE, HINT_TOGGLED};
+
+    fn hinted(hint: i32, lower: Data, upper: Data) -> ControlRange {
+        ControlRange::from_hint(hint, Some(lower), Some(upper), 48000)
+    }
+
+    fn assert_close(actual: Data, expected: Data) {
+        assert!((actual - expected).abs() <= expected.abs() * 1e-5 + 1e-6,
+                "{} is not {}",
+                actual,
+                expected);
+    }
+
+    const NORMALIZED: [Data; 6] = [0.0, 0.1, 0.25, 0.5, 0.9, 1.0];
+
+    #[test]
+    fn logarithmic_ranges_are_covered_evenly_in_ratios() {
+        let range = hinted(HINT_LOGARITHMIC, 20.0, 20000.0);
+        assert_eq!(range.from_normalized(0.0), 20.0);
+        assert_eq!(range.from_normalized(1.0), 20000.0);
+        assert_close(range.from_normalized(0.5), 632.4555);
+        assert_close(range.from_normalized(1.0 / 3.0), 200.0);
+        for &normalized in &NORMALIZED {
+            assert_close(range.to_normalized(range.from_normalized(normalized)), normalized);
+        }
+    }
+
+    #[test]
+    fn logarithmic_ranges_with_a_bound_which_is_not_positive_are_linear() {
+        for &(lower, upper) in &[(0.0, 10.0), (-10.0, 10.0)] {
+            let range = hinted(HINT_LOGARITHMIC, lower, upper);
+            assert_eq!(range.from_normalized(0.5), (lower + upper) / 2.0);
+            for &normalized in &NORMALIZED {
+                assert_close(range.to_normalized(range.from_normalized(normalized)), normalized);
+            }
+        }
+    }
+
+    #[test]
+    fn integer_values_are_whole_numbers_within_the_bounds() {
+        let range = hinted(HINT_INTEGER, 0.0, 10.0);
+        assert_eq!(range.from_normalized(0.34), 3.0);
+        for value in 0..11 {
+            let value = value as Data;
+            assert_eq!(range.from_normalized(range.to_normalized(value)), value);
+        }
+
+        let range = hinted(HINT_INTEGER, 0.5, 3.5);
+        assert_eq!(range.from_normalized(0.0), 1.0);
+        assert_eq!(range.from_normalized(1.0), 3.0);
+    }
+
+    #[test]
+    fn toggled_values_are_either_0_or_1() {
+        let range = hinted(HINT_TOGGLED, 0.0, 1.0);
+        assert_eq!(range.from_normalized(0.49), 0.0);
+        assert_eq!(range.from_normalized(0.5), 1.0);
+        assert_eq!(range.to_normalized(0.3), 1.0);
+        for &value in &[0.0, 1.0] {
+            assert_eq!(range.from_normalized(range.to_normalized(value)), value);
+        }
+    }
+
+    #[test]
+    fn sample_rate_bounds_scale_with_the_sample_rate() {
+        let range = hinted(HINT_SAMPLE_RATE, 0.0, 0.5);
+        assert_eq!((range.lower, range.upper), (0.0, 24000.0));
+        assert_eq!(range.from_normalized(0.25), 6000.0);
+    }
+
+    #[test]
+    fn skewed_ranges_map_the_middle_to_the_center() {
+        let range = hinted(0, 0.0, 100.0).with_center(10.0);
+        assert_close(range.from_normalized(0.5), 10.0);
+        for &normalized in &NORMALIZED {
+            assert_close(range.to_normalized(range.from_normalized(normalized)), normalized);
+        }
+    }
+}
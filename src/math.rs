@@ -0,0 +1,60 @@
+//! Floating point functions for builds without ```std```.
+//!
+//! ```core``` lacks the transcendental functions of ```f32``` and ```f64```, so without the
+//! ```std``` feature they are taken from the C library's libm, which every LADSPA host links
+//! anyway. Importing ```Float``` makes ```x.exp()``` and friends resolve to these; with ```std```
+//! the inherent methods are used and this module is not compiled. Neither is it in test builds,
+//! which link std regardless.
+
+use libc::{c_double, c_float};
+
+extern "C" {
+    fn expf(x: c_float) -> c_float;
+    fn logf(x: c_float) -> c_float;
+    fn roundf(x: c_float) -> c_float;
+    fn sinf(x: c_float) -> c_float;
+    fn cosf(x: c_float) -> c_float;
+    fn sqrtf(x: c_float) -> c_float;
+    fn powf(x: c_float, y: c_float) -> c_float;
+    fn atan2f(y: c_float, x: c_float) -> c_float;
+
+    fn exp(x: c_double) -> c_double;
+    fn log(x: c_double) -> c_double;
+    fn round(x: c_double) -> c_double;
+    fn sin(x: c_double) -> c_double;
+    fn cos(x: c_double) -> c_double;
+    fn sqrt(x: c_double) -> c_double;
+    fn pow(x: c_double, y: c_double) -> c_double;
+    fn atan2(y: c_double, x: c_double) -> c_double;
+}
+
+/// The ```std``` floating point methods used by this crate.
+pub trait Float: Sized {
+    fn exp(self) -> Self;
+    fn ln(self) -> Self;
+    fn round(self) -> Self;
+    fn sin(self) -> Self;
+    fn cos(self) -> Self;
+    fn sqrt(self) -> Self;
+    fn powf(self, n: Self) -> Self;
+    fn atan2(self, other: Self) -> Self;
+}
+
+macro_rules! impl_float {
+    ($t:ty, $exp:ident, $ln:ident, $round:ident, $sin:ident, $cos:ident, $sqrt:ident,
+     $pow:ident, $atan2:ident) => {
+        impl Float for $t {
+            fn exp(self) -> $t { unsafe { $exp(self) } }
+            fn ln(self) -> $t { unsafe { $ln(self) } }
+            fn round(self) -> $t { unsafe { $round(self) } }
+            fn sin(self) -> $t { unsafe { $sin(self) } }
+            fn cos(self) -> $t { unsafe { $cos(self) } }
+            fn sqrt(self) -> $t { unsafe { $sqrt(self) } }
+            fn powf(self, n: $t) -> $t { unsafe { $pow(self, n) } }
+            fn atan2(self, other: $t) -> $t { unsafe { $atan2(self, other) } }
+        }
+    }
+}
+
+impl_float!(f32, expf, logf, roundf, sinf, cosf, sqrtf, powf, atan2f);
+impl_float!(f64, exp, log, round, sin, cos, sqrt, pow, atan2);
@@ -0,0 +1,331 @@
+//! Routing modulation sources to the control inputs of a plugin.
+//!
+//! A ```ModMatrix``` holds named sources, such as LFOs, envelope followers or other control
+//! inputs, and routes which add each of them to a control input with a depth and a curve. Once
+//! per block, or once per chunk of a ```adapter::SubBlock```, it reads the ports, advances the
+//! sources and computes the modulated value of every control input, which the plugin reads
+//! instead of the port:
+//!
+//! ```rust,ignore
+//! fn new_filter(_: &PluginDescriptor, sample_rate: u64)
+//!               -> Result<Box<dyn Plugin + Send>, InstantiateError> {
+//!     let matrix = ModMatrix::new(sample_rate)
+//!         .source("LFO", Source::Lfo(Lfo::new(LfoShape::Sine, 0.5, sample_rate)))
+//!         .source("Envelope", Source::envelope("Input", sample_rate))
+//!         .route("LFO", "Cutoff", 0.2, Curve::Linear)
+//!         .automated("Envelope", "Resonance", Curve::Logarithmic);
+//!     Ok(Box::new(Filter::new(matrix, sample_rate)))
+//! }
+//!
+//! // In run:
+//! self.matrix.evaluate(ports, sample_count);
+//! self.filter.set_cutoff(self.matrix.value(CUTOFF));
+//! ```
+//!
+//! Modulation is added to the position of the control within its range, as given by
+//! ```Port::range```, so a depth of 1 sweeps a logarithmic frequency control over its whole range
+//! just as it does a linear gain, and the result is clamped to the range. The depths of
+//! ```automated``` routes are control inputs of their own, listed by ```ModMatrix::ports``` to add
+//! to the descriptor of the plugin, so that hosts can automate them.
+
+use alloc::format;
+use alloc::vec::Vec;
+
+use super::{Data, DefaultValue, Port, PortConnection, PortData, PortDescriptor};
+use super::dsp::{Detection, EnvelopeFollower, Lfo};
+use super::intern::intern;
+use super::mapping::ControlRange;
+#[cfg(not(any(feature = "std", test)))]
+use math::Float;
+
+/// Where the value of a modulation source comes from.
+#[derive(Clone, Debug)]
+pub enum Source {
+    /// An LFO, advanced by the number of samples evaluated each time and taking its last value.
+    Lfo(Lfo),
+    /// The level of the audio input with the given name, as tracked by the follower.
+    Envelope(EnvelopeFollower, &'static str),
+    /// The position of the control input with the given name within its range, from 0 to 1.
+    Control(&'static str),
+    /// A value set by the plugin with ```ModMatrix::set```, such as the output of an
+    /// ```dsp::Adsr```.
+    Value(Data),
+}
+
+impl Source {
+    /// The peak level of the audio input named ```input```, with an attack of 10 ms and a
+    /// release of 200 ms.
+    pub fn envelope(input: &'static str, sample_rate: u64) -> Source {
+        Source::Envelope(EnvelopeFollower::new(Detection::Peak, 10.0, 200.0, sample_rate), input)
+    }
+}
+
+/// How the value of a source is bent before being scaled by the depth of a route. Each curve
+/// keeps the sign of the value, and 0, 1 and -1 where they are.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Curve {
+    /// As is.
+    Linear,
+    /// Squared, for finer control over small values.
+    Exponential,
+    /// Square rooted, for a stronger response to small values.
+    Logarithmic,
+}
+
+impl Curve {
+    /// Applies the curve to ```value```.
+    pub fn apply(self, value: Data) -> Data {
+        match self {
+            Curve::Linear => value,
+            Curve::Exponential => value * value.abs(),
+            Curve::Logarithmic => value.signum() * value.abs().sqrt(),
+        }
+    }
+}
+
+// A source routed to a control input.
+#[derive(Clone, Debug)]
+struct Route {
+    source: &'static str,
+    destination: &'static str,
+    curve: Curve,
+    // The fixed depth, or `None` when it is set by a depth port.
+    depth: Option<Data>,
+    // The name of the depth port, for automated routes.
+    port: Option<&'static str>,
+}
+
+// The indices of a route's source, destination and depth port, found on the first evaluation.
+#[derive(Copy, Clone, Debug)]
+struct Resolved {
+    source: Option<usize>,
+    destination: Option<usize>,
+    depth: Option<usize>,
+}
+
+/// Modulation sources and the routes from them to control inputs. See the module documentation.
+///
+/// Ports are found by name on the first evaluation, which allocates; routes whose source or
+/// destination doesn't exist, or whose destination isn't a control input, are ignored. Evaluating
+/// doesn't allocate afterwards.
+#[derive(Clone, Debug)]
+pub struct ModMatrix {
+    sample_rate: u64,
+    names: Vec<&'static str>,
+    sources: Vec<Source>,
+    routes: Vec<Route>,
+    // The index of the port each source reads, if any, by source.
+    source_ports: Vec<Option<usize>>,
+    resolved: Vec<Resolved>,
+    // The range of each port, by port. Empty until the first evaluation.
+    ranges: Vec<ControlRange>,
+    // The current value of each source, by source.
+    levels: Vec<Data>,
+    // Whether each port is the destination of a route, by port.
+    modulated: Vec<bool>,
+    // The modulated value of each control input, and the position of each within its range as it
+    // is modulated, by port.
+    values: Vec<Data>,
+    positions: Vec<Data>,
+}
+
+impl ModMatrix {
+    /// Creates a matrix without sources or routes, for a plugin running at ```sample_rate```.
+    pub fn new(sample_rate: u64) -> ModMatrix {
+        ModMatrix {
+            sample_rate,
+            names: Vec::new(),
+            sources: Vec::new(),
+            routes: Vec::new(),
+            source_ports: Vec::new(),
+            resolved: Vec::new(),
+            ranges: Vec::new(),
+            modulated: Vec::new(),
+            levels: Vec::new(),
+            values: Vec::new(),
+            positions: Vec::new(),
+        }
+    }
+
+    /// Adds a source, which routes refer to by ```name```.
+    pub fn source(mut self, name: &'static str, source: Source) -> ModMatrix {
+        self.names.push(name);
+        self.sources.push(source);
+        self.levels.push(0.0);
+        self
+    }
+
+    /// Routes the source named ```source``` to the control input named ```destination```, with a
+    /// fixed ```depth```: the proportion of the range of the control the source moves it by at
+    /// its largest, negative to move it the other way.
+    pub fn route(mut self,
+                 source: &'static str,
+                 destination: &'static str,
+                 depth: Data,
+                 curve: Curve)
+                 -> ModMatrix {
+        self.routes.push(Route { source, destination, curve, depth: Some(depth), port: None });
+        self
+    }
+
+    /// Routes the source named ```source``` to the control input named ```destination```, with a
+    /// depth set by a control input named after both, as in "LFO > Cutoff". The port is one of
+    /// those returned by ```ports```.
+    pub fn automated(mut self,
+                     source: &'static str,
+                     destination: &'static str,
+                     curve: Curve)
+                     -> ModMatrix {
+        let port = intern(format!("{} > {}", source, destination));
+        self.routes.push(Route { source, destination, curve, depth: None, port: Some(port) });
+        self
+    }
+
+    /// The depth controls of the ```automated``` routes, from -1 to 1 and at 0 by default, to add
+    /// to the descriptor of the plugin. They don't depend on the sample rate, so a matrix built
+    /// the same way at any rate lists them in ```DescribedPlugin::descriptor```.
+    pub fn ports(&self) -> Vec<Port> {
+        self.routes
+            .iter()
+            .filter_map(|route| route.port)
+            .map(|name| {
+                Port {
+                    name,
+                    desc: PortDescriptor::ControlInput,
+                    hint: None,
+                    default: Some(DefaultValue::Middle),
+                    lower_bound: Some(-1.0),
+                    upper_bound: Some(1.0),
+                    group: None,
+                    scale_points: &[],
+                    unit: None,
+                }
+            })
+            .collect()
+    }
+
+    /// Sets the value of the ```Source::Value``` named ```name```. Does nothing if there is no
+    /// such source.
+    pub fn set(&mut self, name: &'static str, value: Data) {
+        let sources = self.names.iter().zip(&mut self.sources);
+        for (_, source) in sources.filter(|&(&source, _)| source == name) {
+            if let Source::Value(ref mut current) = *source {
+                *current = value;
+            }
+        }
+    }
+
+    /// Resets the LFOs and envelope followers, as needed in ```Plugin::activate```.
+    pub fn reset(&mut self) {
+        for source in &mut self.sources {
+            match *source {
+                Source::Lfo(ref mut lfo) => lfo.reset(),
+                Source::Envelope(ref mut follower, _) => follower.reset(),
+                _ => {}
+            }
+        }
+    }
+
+    /// The current value of the source named ```name```, or ```None``` if there is no such
+    /// source.
+    pub fn level(&self, name: &'static str) -> Option<Data> {
+        self.names.iter().position(|&source| source == name).map(|index| self.levels[index])
+    }
+
+    /// The modulated value of the control input with index ```port```, as of the last
+    /// evaluation. Ports which are not control inputs, and all ports before the first evaluation,
+    /// read as 0.
+    pub fn value(&self, port: usize) -> Data {
+        self.values.get(port).copied().unwrap_or(0.0)
+    }
+
+    // Finds the ports of the sources and routes, and their ranges.
+    fn resolve(&mut self, ports: &[PortConnection]) {
+        let index = |name: &'static str| ports.iter().position(|port| port.port.name == name);
+        self.source_ports = self.sources
+            .iter()
+            .map(|source| match *source {
+                Source::Envelope(_, name) | Source::Control(name) => index(name),
+                _ => None,
+            })
+            .collect();
+        let names = &self.names;
+        self.resolved = self.routes
+            .iter()
+            .map(|route| {
+                Resolved {
+                    source: names.iter().position(|&name| name == route.source),
+                    destination: index(route.destination)
+                        .filter(|&port| matches!(ports[port].data, PortData::ControlInput(_))),
+                    depth: route.port.and_then(index),
+                }
+            })
+            .collect();
+        self.ranges = ports.iter().map(|port| port.port.range(self.sample_rate)).collect();
+        self.modulated = ports.iter().map(|_| false).collect();
+        for destination in self.resolved.iter().filter(|route| route.source.is_some()) {
+            if let Some(port) = destination.destination {
+                self.modulated[port] = true;
+            }
+        }
+        self.values = ports.iter().map(|_| 0.0).collect();
+        self.positions = ports.iter().map(|_| 0.0).collect();
+    }
+
+    /// Advances the sources over a block or chunk of ```sample_count``` samples connected to
+    /// ```ports```, and computes the modulated value of each control input.
+    pub fn evaluate(&mut self, ports: &[PortConnection], sample_count: usize) {
+        if self.ranges.len() != ports.len() {
+            self.resolve(ports);
+        }
+
+        let ranges = &self.ranges;
+        let sources = self.sources.iter_mut().zip(&self.source_ports).zip(&mut self.levels);
+        for ((source, &port), level) in sources {
+            let connected = port.map(|port| (&ports[port].data, &ranges[port]));
+            *level = match (source, connected) {
+                (&mut Source::Lfo(ref mut lfo), _) => {
+                    for _ in 0..sample_count {
+                        *level = lfo.tick();
+                    }
+                    *level
+                }
+                (&mut Source::Envelope(ref mut follower, _),
+                 Some((&PortData::AudioInput(data), _))) => {
+                    follower.process_block(&data[..sample_count.min(data.len())])
+                }
+                (&mut Source::Control(_), Some((&PortData::ControlInput(&value), range))) => {
+                    range.to_normalized(value)
+                }
+                (&mut Source::Value(value), _) => value,
+                _ => 0.0,
+            };
+        }
+
+        for ((position, range), port) in self.positions.iter_mut().zip(&self.ranges).zip(ports) {
+            if let PortData::ControlInput(&value) = port.data {
+                *position = range.to_normalized(value);
+            }
+        }
+        for (route, resolved) in self.routes.iter().zip(&self.resolved) {
+            if let (Some(source), Some(destination)) = (resolved.source, resolved.destination) {
+                let depth = match (route.depth, resolved.depth.map(|port| &ports[port].data)) {
+                    (Some(depth), _) => depth,
+                    (None, Some(&PortData::ControlInput(&depth))) => depth,
+                    _ => 0.0,
+                };
+                self.positions[destination] += route.curve.apply(self.levels[source]) * depth;
+            }
+        }
+        let values = self.values.iter_mut().zip(&self.positions).zip(&self.ranges);
+        for ((((value, &position), range), port), &modulated) in
+            values.zip(ports).zip(&self.modulated) {
+            *value = match port.data {
+                // Passed through exactly rather than mapped back and forth.
+                PortData::ControlInput(&value) if !modulated => value,
+                PortData::ControlInput(_) => range.from_normalized(position),
+                _ => 0.0,
+            };
+        }
+    }
+}
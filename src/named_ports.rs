@@ -0,0 +1,114 @@
+// The paths the expansion of `ports!` uses, reachable from crates which don't link `core`
+// by name.
+pub mod reexports {
+    pub use core::ops::{Index, IndexMut};
+}
+
+/// Declares names for the ports of a plugin: an enum with one variant per port, and a struct
+/// binding every port to a field of the matching type for one call to ```Plugin::run```.
+///
+/// Ports are listed in the order they are declared in the ```PluginDescriptor```, each as
+/// ```Variant => field: Kind```, where ```Kind``` is one of ```AudioInput```, ```AudioOutput```,
+/// ```ControlInput``` or ```ControlOutput```. The fields have the types returned by the matching
+/// ```PortConnection::unwrap_*``` method: ```&[Data]```, ```&mut [Data]```, ```Data``` and
+/// ```&mut Data```.
+///
+/// ```no_run
+/// # #[macro_use] extern crate ladspa;
+/// # use ladspa::{Plugin, PortConnection};
+/// # struct Amp;
+/// ports! {
+///     pub struct AmpPorts: AmpPort {
+///         InL => in_l: AudioInput,
+///         InR => in_r: AudioInput,
+///         OutL => out_l: AudioOutput,
+///         OutR => out_r: AudioOutput,
+///         Gain => gain: ControlInput,
+///     }
+/// }
+///
+/// # impl Plugin for Amp {
+/// fn run<'a>(&mut self, sample_count: usize, ports: &mut [PortConnection<'a>]) {
+///     let p = AmpPorts::new(ports);
+///     for i in 0..sample_count {
+///         p.out_l[i] = p.in_l[i] * p.gain;
+///         p.out_r[i] = p.in_r[i] * p.gain;
+///     }
+/// }
+/// # }
+/// ```
+///
+/// The enum converts to the port's index with ```AmpPort::Gain.index()```, and indexes the port
+/// slice directly: ```ports[AmpPort::Gain].unwrap_control()```. ```AmpPort::COUNT``` is the
+/// number of ports.
+///
+/// Attributes and doc comments on the struct and on each port are kept, on the struct and on
+/// both the variant and the field respectively.
+#[macro_export]
+macro_rules! ports {
+    (@type $lt:lifetime AudioInput) => { &$lt [$crate::Data] };
+    (@type $lt:lifetime AudioOutput) => { &$lt mut [$crate::Data] };
+    (@type $lt:lifetime ControlInput) => { $crate::Data };
+    (@type $lt:lifetime ControlOutput) => { &$lt mut $crate::Data };
+
+    (@bind $port:expr, AudioInput) => { $port.unwrap_audio_in() };
+    (@bind $port:expr, AudioOutput) => { $port.unwrap_audio_out() };
+    (@bind $port:expr, ControlInput) => { $port.unwrap_control() };
+    (@bind $port:expr, ControlOutput) => { $port.unwrap_control_out() };
+
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $ports:ident : $port:ident {
+            $($(#[$port_meta:meta])* $variant:ident => $field:ident : $kind:ident),* $(,)*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+        $vis enum $port {
+            $($(#[$port_meta])* $variant),*
+        }
+
+        impl $port {
+            /// The number of ports.
+            pub const COUNT: usize = [$($port::$variant),*].len();
+
+            /// The index of the port in the ```PluginDescriptor```.
+            pub fn index(self) -> usize {
+                self as usize
+            }
+        }
+
+        impl<'a> $crate::__ports::Index<$port> for [$crate::PortConnection<'a>] {
+            type Output = $crate::PortConnection<'a>;
+
+            fn index(&self, port: $port) -> &$crate::PortConnection<'a> {
+                &self[port.index()]
+            }
+        }
+
+        impl<'a> $crate::__ports::IndexMut<$port> for [$crate::PortConnection<'a>] {
+            fn index_mut(&mut self, port: $port) -> &mut $crate::PortConnection<'a> {
+                &mut self[port.index()]
+            }
+        }
+
+        $(#[$meta])*
+        $vis struct $ports<'a> {
+            $($(#[$port_meta])* pub $field: $crate::ports!(@type 'a $kind)),*
+        }
+
+        impl<'a> $ports<'a> {
+            /// Binds the ports passed to ```Plugin::run```. Panics if there are not exactly
+            /// as many as declared, or if one is of a different kind.
+            pub fn new<'b>(ports: &'a mut [$crate::PortConnection<'b>]) -> $ports<'a> {
+                assert_eq!(ports.len(), $port::COUNT,
+                           "expected {} ports, got {}", $port::COUNT, ports.len());
+                // The fields are bound in declaration order, which is that of the ports.
+                let mut ports = ports.iter_mut();
+                $ports {
+                    $($field: $crate::ports!(@bind ports.next().unwrap(), $kind)),*
+                }
+            }
+        }
+    };
+}
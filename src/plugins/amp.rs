@@ -0,0 +1,56 @@
+use units::db_to_gain;
+use util::ControlRamp;
+use {new_default, Category, DefaultValue, DescribedPlugin, Plugin, PluginDescriptor, PortConnection,
+     UniqueId, PROP_HARD_REALTIME_CAPABLE};
+
+use super::{COPYRIGHT, MAKER};
+
+::ports! {
+    struct AmpPorts: AmpPort {
+        Input => input: AudioInput,
+        Output => output: AudioOutput,
+        Gain => gain: ControlInput,
+    }
+}
+
+/// A mono amplifier, with its gain in decibels.
+///
+/// The gain glides across each block from its previous setting, so that automating it doesn't
+/// click.
+#[derive(Default)]
+pub struct Amp {
+    gain: ControlRamp,
+}
+
+impl Plugin for Amp {
+    fn activate(&mut self) {
+        self.gain.reset();
+    }
+
+    fn run<'a>(&mut self, sample_count: usize, ports: &mut [PortConnection<'a>]) {
+        let p = AmpPorts::new(ports);
+        let gain = self.gain.ramp(db_to_gain(p.gain), sample_count);
+        for (i, gain) in gain.enumerate() {
+            p.output[i] = p.input[i] * gain;
+        }
+    }
+}
+
+impl DescribedPlugin for Amp {
+    fn descriptor() -> PluginDescriptor {
+        PluginDescriptor::builder()
+            .unique_id(UniqueId::experimental(901).get())
+            .label("rs_amp")
+            .name("Amplifier")
+            .maker(MAKER)
+            .copyright(COPYRIGHT)
+            .category(Category::Amplifier)
+            .properties(PROP_HARD_REALTIME_CAPABLE)
+            .audio_in("Input")
+            .audio_out("Output")
+            .control_in("Gain (dB)", -60.0..24.0, DefaultValue::Value0)
+            .new(new_default::<Amp>)
+            .build()
+            .unwrap()
+    }
+}
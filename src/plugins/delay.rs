@@ -0,0 +1,89 @@
+use alloc::boxed::Box;
+
+use dsp::delay::DelayLine;
+use util::ControlRamp;
+use {Category, DefaultValue, DescribedPlugin, InstantiateError, Plugin, PluginDescriptor,
+     PortConnection, UniqueId, PROP_HARD_REALTIME_CAPABLE};
+
+use super::{COPYRIGHT, MAKER};
+
+// The longest delay, in seconds.
+const MAX_TIME: f32 = 2.0;
+
+::ports! {
+    struct DelayPorts: DelayPort {
+        Input => input: AudioInput,
+        Output => output: AudioOutput,
+        Time => time: ControlInput,
+        Feedback => feedback: ControlInput,
+        Mix => mix: ControlInput,
+    }
+}
+
+/// A mono echo, with feedback for repeats and a mix between the dry and delayed signals.
+///
+/// The delay line is allocated when the plugin is instantiated, for the longest time at that
+/// sample rate, so ```run``` never allocates. Changes to the time glide across a block, bending
+/// the pitch of the echoes rather than clicking.
+pub struct Delay {
+    line: DelayLine,
+    // The delay in samples.
+    delay: ControlRamp,
+    sample_rate: f32,
+}
+
+impl Delay {
+    fn instantiate(_: &PluginDescriptor, sample_rate: u64)
+           -> Result<Box<dyn Plugin + Send>, InstantiateError> {
+        Ok(Box::new(Delay {
+            line: DelayLine::with_time(MAX_TIME, sample_rate),
+            delay: ControlRamp::new(),
+            sample_rate: sample_rate as f32,
+        }))
+    }
+}
+
+impl Plugin for Delay {
+    fn activate(&mut self) {
+        self.line.clear();
+        self.delay.reset();
+    }
+
+    fn flush_denormals(&self) -> bool {
+        true
+    }
+
+    fn run<'a>(&mut self, sample_count: usize, ports: &mut [PortConnection<'a>]) {
+        let p = DelayPorts::new(ports);
+        let delay = self.delay.ramp(p.time.clamp(0.0, MAX_TIME) * self.sample_rate, sample_count);
+        let feedback = p.feedback.clamp(0.0, 0.95);
+        let mix = p.mix.clamp(0.0, 1.0);
+        for (i, delay) in delay.enumerate() {
+            let input = p.input[i];
+            let delayed = self.line.read_cubic(delay);
+            self.line.write(input + delayed * feedback);
+            p.output[i] = input + (delayed - input) * mix;
+        }
+    }
+}
+
+impl DescribedPlugin for Delay {
+    fn descriptor() -> PluginDescriptor {
+        PluginDescriptor::builder()
+            .unique_id(UniqueId::experimental(903).get())
+            .label("rs_delay")
+            .name("Delay")
+            .maker(MAKER)
+            .copyright(COPYRIGHT)
+            .category(Category::Delay)
+            .properties(PROP_HARD_REALTIME_CAPABLE)
+            .audio_in("Input")
+            .audio_out("Output")
+            .control_in("Time (s)", 0.0..MAX_TIME, DefaultValue::Low)
+            .control_in("Feedback", 0.0..0.95, DefaultValue::Low)
+            .control_in("Mix", 0.0..1.0, DefaultValue::Middle)
+            .new(Delay::instantiate)
+            .build()
+            .unwrap()
+    }
+}
@@ -0,0 +1,101 @@
+use alloc::boxed::Box;
+
+use dsp::biquad::{Biquad, FilterType};
+use {Category, ControlPort, DefaultValue, DescribedPlugin, InstantiateError, Plugin,
+     PluginDescriptor, Port, PortConnection, PortDescriptor, UniqueId, Unit, HINT_LOGARITHMIC,
+     PROP_HARD_REALTIME_CAPABLE};
+
+use super::{COPYRIGHT, MAKER};
+
+::ports! {
+    struct FilterPorts: FilterPort {
+        Input => input: AudioInput,
+        Output => output: AudioOutput,
+        Cutoff => cutoff: ControlInput,
+        Resonance => resonance: ControlInput,
+        Highpass => highpass: ControlInput,
+    }
+}
+
+/// A resonant two pole lowpass or highpass filter, with a 12 dB per octave slope.
+///
+/// The coefficients are only recomputed when a control changes, so leaving the controls alone
+/// costs nothing. The cutoff is kept below the Nyquist frequency, where the filter would be
+/// unstable.
+pub struct Filter {
+    filter: Biquad,
+    nyquist: f32,
+}
+
+impl Filter {
+    fn instantiate(_: &PluginDescriptor, sample_rate: u64)
+           -> Result<Box<dyn Plugin + Send>, InstantiateError> {
+        Ok(Box::new(Filter {
+            filter: Biquad::new(FilterType::Lowpass, 1000.0, 0.707, sample_rate),
+            nyquist: sample_rate as f32 / 2.0,
+        }))
+    }
+}
+
+impl Plugin for Filter {
+    fn activate(&mut self) {
+        self.filter.reset();
+    }
+
+    fn flush_denormals(&self) -> bool {
+        true
+    }
+
+    fn run<'a>(&mut self, sample_count: usize, ports: &mut [PortConnection<'a>]) {
+        let p = FilterPorts::new(ports);
+        let filter_type = if p.highpass.as_bool() {
+            FilterType::Highpass
+        } else {
+            FilterType::Lowpass
+        };
+        self.filter.set(filter_type,
+                        p.cutoff.clamp(1.0, self.nyquist * 0.95),
+                        p.resonance.max(0.1));
+        for i in 0..sample_count {
+            p.output[i] = self.filter.tick(p.input[i]);
+        }
+    }
+}
+
+impl DescribedPlugin for Filter {
+    fn descriptor() -> PluginDescriptor {
+        PluginDescriptor::builder()
+            .unique_id(UniqueId::experimental(904).get())
+            .label("rs_filter")
+            .name("Filter")
+            .maker(MAKER)
+            .copyright(COPYRIGHT)
+            .category(Category::Filter)
+            .properties(PROP_HARD_REALTIME_CAPABLE)
+            .audio_in("Input")
+            .audio_out("Output")
+            .port(Port {
+                name: "Cutoff (Hz)",
+                desc: PortDescriptor::ControlInput,
+                hint: Some(HINT_LOGARITHMIC),
+                default: Some(DefaultValue::Value440),
+                lower_bound: Some(20.0),
+                upper_bound: Some(20000.0),
+                unit: Some(Unit::Hz),
+                ..Default::default()
+            })
+            .port(Port {
+                name: "Resonance (Q)",
+                desc: PortDescriptor::ControlInput,
+                hint: Some(HINT_LOGARITHMIC),
+                default: Some(DefaultValue::Value1),
+                lower_bound: Some(0.5),
+                upper_bound: Some(10.0),
+                ..Default::default()
+            })
+            .port(Port::toggle("Highpass"))
+            .new(Filter::instantiate)
+            .build()
+            .unwrap()
+    }
+}
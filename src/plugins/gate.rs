@@ -0,0 +1,81 @@
+use alloc::boxed::Box;
+
+use dsp::{Detection, EnvelopeFollower};
+use units::db_to_gain;
+use {Category, DefaultValue, DescribedPlugin, InstantiateError, Plugin, PluginDescriptor,
+     PortConnection, UniqueId, PROP_HARD_REALTIME_CAPABLE};
+
+use super::{COPYRIGHT, MAKER};
+
+::ports! {
+    struct GatePorts: GatePort {
+        Input => input: AudioInput,
+        Output => output: AudioOutput,
+        Threshold => threshold: ControlInput,
+        Attack => attack: ControlInput,
+        Release => release: ControlInput,
+    }
+}
+
+/// A noise gate: silences the signal while its level stays below a threshold.
+///
+/// A fast peak detector measures the level. Whether it is above the threshold then drives a
+/// second envelope follower, whose attack and release shape how the gate opens and closes.
+pub struct Gate {
+    level: EnvelopeFollower,
+    gain: EnvelopeFollower,
+}
+
+impl Gate {
+    fn instantiate(_: &PluginDescriptor, sample_rate: u64)
+           -> Result<Box<dyn Plugin + Send>, InstantiateError> {
+        Ok(Box::new(Gate {
+            level: EnvelopeFollower::new(Detection::Peak, 0.1, 20.0, sample_rate),
+            gain: EnvelopeFollower::new(Detection::Peak, 1.0, 100.0, sample_rate),
+        }))
+    }
+}
+
+impl Plugin for Gate {
+    fn activate(&mut self) {
+        self.level.reset();
+        self.gain.reset();
+    }
+
+    fn flush_denormals(&self) -> bool {
+        true
+    }
+
+    fn run<'a>(&mut self, sample_count: usize, ports: &mut [PortConnection<'a>]) {
+        let p = GatePorts::new(ports);
+        let threshold = db_to_gain(p.threshold);
+        self.gain.set_attack(p.attack.max(0.0));
+        self.gain.set_release(p.release.max(0.0));
+        for i in 0..sample_count {
+            let input = p.input[i];
+            let open = if self.level.tick(input) > threshold { 1.0 } else { 0.0 };
+            p.output[i] = input * self.gain.tick(open);
+        }
+    }
+}
+
+impl DescribedPlugin for Gate {
+    fn descriptor() -> PluginDescriptor {
+        PluginDescriptor::builder()
+            .unique_id(UniqueId::experimental(905).get())
+            .label("rs_gate")
+            .name("Noise Gate")
+            .maker(MAKER)
+            .copyright(COPYRIGHT)
+            .category(Category::Gate)
+            .properties(PROP_HARD_REALTIME_CAPABLE)
+            .audio_in("Input")
+            .audio_out("Output")
+            .control_in("Threshold (dB)", -80.0..0.0, DefaultValue::Low)
+            .control_in("Attack (ms)", 0.1..50.0, DefaultValue::Minimum)
+            .control_in("Release (ms)", 1.0..1000.0, DefaultValue::Value100)
+            .new(Gate::instantiate)
+            .build()
+            .unwrap()
+    }
+}
@@ -0,0 +1,36 @@
+//! Reference plugins, enabled by the ```examples-plugins``` feature.
+//!
+//! Each plugin is a small, complete example of a common kind of effect, built from the ```dsp```
+//! and ```util``` modules: copy one as a starting point for your own. They implement
+//! ```DescribedPlugin```, so a library exports them all with:
+//!
+//! ```no_run
+//! # use ladspa::plugins::{Amp, Delay, Filter, Gate, Panner};
+//! ladspa::export_ladspa!(Amp, Panner, Delay, Filter, Gate);
+//! ```
+//!
+//! The examples/plugins crate builds such a library, which exercises the whole FFI layer when
+//! loaded into a host or ```ladspa-validate```.
+//!
+//! Their unique IDs are from the range set aside for development, so give copies IDs of their
+//! own before releasing them.
+
+mod amp;
+pub use self::amp::Amp;
+
+mod panner;
+pub use self::panner::Panner;
+
+mod delay;
+pub use self::delay::Delay;
+
+mod filter;
+pub use self::filter::Filter;
+
+mod gate;
+pub use self::gate::Gate;
+
+// The copyright of every plugin in this module.
+const COPYRIGHT: &str = "None";
+// The maker of every plugin in this module.
+const MAKER: &str = "ladspa.rs";
@@ -0,0 +1,64 @@
+use core::f32::consts::FRAC_PI_4;
+
+use util::ControlRamp;
+use {new_default, Category, DefaultValue, DescribedPlugin, Plugin, PluginDescriptor, PortConnection,
+     PortGroup, UniqueId, PROP_HARD_REALTIME_CAPABLE};
+#[cfg(not(any(feature = "std", test)))]
+use math::Float;
+
+use super::{COPYRIGHT, MAKER};
+
+::ports! {
+    struct PannerPorts: PannerPort {
+        Input => input: AudioInput,
+        Left => left: AudioOutput,
+        Right => right: AudioOutput,
+        Pan => pan: ControlInput,
+    }
+}
+
+/// Places a mono signal in the stereo field, from -1 for hard left to 1 for hard right.
+///
+/// Uses a constant power law: the channels follow a quarter of a sine and cosine, so the
+/// perceived loudness stays the same as the signal moves across, 3 dB down in each channel at
+/// the center. Like the gain of ```Amp```, the position glides across each block.
+#[derive(Default)]
+pub struct Panner {
+    pan: ControlRamp,
+}
+
+impl Plugin for Panner {
+    fn activate(&mut self) {
+        self.pan.reset();
+    }
+
+    fn run<'a>(&mut self, sample_count: usize, ports: &mut [PortConnection<'a>]) {
+        let p = PannerPorts::new(ports);
+        let pan = self.pan.ramp(p.pan.clamp(-1.0, 1.0), sample_count);
+        for (i, pan) in pan.enumerate() {
+            let angle = (pan + 1.0) * FRAC_PI_4;
+            let input = p.input[i];
+            p.left[i] = input * angle.cos();
+            p.right[i] = input * angle.sin();
+        }
+    }
+}
+
+impl DescribedPlugin for Panner {
+    fn descriptor() -> PluginDescriptor {
+        PluginDescriptor::builder()
+            .unique_id(UniqueId::experimental(902).get())
+            .label("rs_panner")
+            .name("Panner")
+            .maker(MAKER)
+            .copyright(COPYRIGHT)
+            .category(Category::Utility)
+            .properties(PROP_HARD_REALTIME_CAPABLE)
+            .audio_in("Input")
+            .group(PortGroup::stereo_out("Output"))
+            .control_in("Pan", -1.0..1.0, DefaultValue::Middle)
+            .new(new_default::<Panner>)
+            .build()
+            .unwrap()
+    }
+}
@@ -0,0 +1,181 @@
+//! Generation of LRDF metadata for plugins.
+//!
+//! Hosts such as Ardour and Audacity read RDF files from ```LADSPA_RDF_PATH``` (usually
+//! /usr/share/ladspa/rdf/) for information which doesn't fit into the plain LADSPA descriptor.
+//! ```write``` produces such a file for a set of ```PluginDescriptor```s, which can then be
+//! installed alongside the plugin library.
+//!
+//! Plugins with a ```category``` are given its class, which hosts use to sort them into menus.
+//!
+//! Besides port information, the file carries the plugin's ```presets```, which is the only way
+//! LADSPA hosts can learn about them, and the exact defaults of ports declared with
+//! ```DefaultValue::Value```, which the descriptor can only approximate. The labels of
+//! ```Port::scale_points``` let hosts show enumerations as a list rather than a slider, and the
+//! ```Port::unit``` lets them format values, for the units the LADSPA ontology knows.
+
+use std::io::{self, Write};
+
+use super::{Data, DefaultValue, PluginDescriptor, PortDescriptor, Unit};
+
+const HEADER: &str = "<?xml version='1.0' encoding='UTF-8'?>
+<!DOCTYPE rdf:RDF [
+  <!ENTITY rdf 'http://www.w3.org/1999/02/22-rdf-syntax-ns#'>
+  <!ENTITY rdfs 'http://www.w3.org/2000/01/rdf-schema#'>
+  <!ENTITY dc 'http://purl.org/dc/elements/1.1/'>
+  <!ENTITY ladspa 'http://ladspa.org/ontology#'>
+]>
+<rdf:RDF xmlns:rdf=\"&rdf;\" xmlns:rdfs=\"&rdfs;\" xmlns:dc=\"&dc;\" xmlns:ladspa=\"&ladspa;\">
+";
+
+const FOOTER: &str = "</rdf:RDF>\n";
+
+// Escapes the characters which may not appear literally in XML text or attribute values.
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn port_class(desc: PortDescriptor) -> Option<&'static str> {
+    match desc {
+        PortDescriptor::AudioInput => Some("InputAudioPort"),
+        PortDescriptor::AudioOutput => Some("OutputAudioPort"),
+        PortDescriptor::ControlInput => Some("InputControlPort"),
+        PortDescriptor::ControlOutput => Some("OutputControlPort"),
+        PortDescriptor::Invalid => None,
+    }
+}
+
+// The units the LADSPA ontology defines. Hosts have no way to learn about the others from LRDF.
+fn lrdf_unit(unit: Unit) -> Option<&'static str> {
+    match unit {
+        Unit::Hz => Some("Hz"),
+        Unit::Db => Some("dB"),
+        Unit::Seconds => Some("seconds"),
+        Unit::Milliseconds => Some("milliseconds"),
+        _ => None,
+    }
+}
+
+fn write_plugin<W: Write>(out: &mut W, plugin: &PluginDescriptor) -> io::Result<()> {
+    let id = plugin.unique_id;
+    // LRDF gives the category as the class of the plugin rather than as a property.
+    let plugin_class = plugin.category.map_or("Plugin", |category| category.rdf_class());
+    writeln!(out, "  <ladspa:{} rdf:about=\"&ladspa;{}\">", plugin_class, id)?;
+    writeln!(out, "    <dc:title>{}</dc:title>", escape(plugin.name))?;
+    if !plugin.maker.is_empty() {
+        writeln!(out, "    <dc:creator>{}</dc:creator>", escape(plugin.maker))?;
+    }
+    writeln!(out, "    <dc:rights>{}</dc:rights>", escape(plugin.copyright))?;
+
+    for (index, port) in plugin.ports.iter().enumerate() {
+        let class = match port_class(port.desc) {
+            Some(class) => class,
+            None => continue,
+        };
+        writeln!(out, "    <ladspa:hasPort>")?;
+        write!(out,
+               "      <ladspa:{} rdf:about=\"&ladspa;{}.{}\" ladspa:hasLabel=\"{}\"",
+               class,
+               id,
+               index,
+               escape(port.name))?;
+        let unit = port.unit.and_then(lrdf_unit);
+        if port.scale_points.is_empty() && unit.is_none() {
+            writeln!(out, "/>")?;
+            writeln!(out, "    </ladspa:hasPort>")?;
+            continue;
+        }
+        writeln!(out, ">")?;
+        if let Some(unit) = unit {
+            writeln!(out, "        <ladspa:hasUnit rdf:resource=\"&ladspa;{}\"/>", unit)?;
+        }
+        if !port.scale_points.is_empty() {
+            writeln!(out, "        <ladspa:hasScale>")?;
+            writeln!(out, "          <ladspa:Scale>")?;
+            for (value, label) in port.scale_point_values() {
+                writeln!(out, "            <ladspa:hasPoint>")?;
+                writeln!(out,
+                         "              <ladspa:Point rdf:value=\"{}\" ladspa:hasLabel=\"{}\"/>",
+                         value,
+                         escape(label))?;
+                writeln!(out, "            </ladspa:hasPoint>")?;
+            }
+            writeln!(out, "          </ladspa:Scale>")?;
+            writeln!(out, "        </ladspa:hasScale>")?;
+        }
+        writeln!(out, "      </ladspa:{}>", class)?;
+        writeln!(out, "    </ladspa:hasPort>")?;
+    }
+
+    // Hosts reading LRDF take the defaults of the ports listed in the `Default` setting from it,
+    // and compute the others from the hints as usual.
+    let exact: Vec<_> = plugin.ports
+        .iter()
+        .enumerate()
+        .filter_map(|(index, port)| match port.default {
+            Some(DefaultValue::Value(value)) => Some((index, value)),
+            _ => None,
+        })
+        .collect();
+    if !exact.is_empty() {
+        writeln!(out, "    <ladspa:hasSetting>")?;
+        writeln!(out, "      <ladspa:Default rdf:about=\"&ladspa;{}.default\">", id)?;
+        write_port_values(out, id, &exact)?;
+        writeln!(out, "      </ladspa:Default>")?;
+        writeln!(out, "    </ladspa:hasSetting>")?;
+    }
+
+    for (number, preset) in plugin.presets.iter().enumerate() {
+        writeln!(out, "    <ladspa:hasSetting>")?;
+        writeln!(out,
+                 "      <ladspa:Preset rdf:about=\"&ladspa;{}.preset{}\" ladspa:hasLabel=\"{}\">",
+                 id,
+                 number + 1,
+                 escape(preset.name))?;
+        write_port_values(out, id, &preset.values)?;
+        writeln!(out, "      </ladspa:Preset>")?;
+        writeln!(out, "    </ladspa:hasSetting>")?;
+    }
+
+    writeln!(out, "  </ladspa:{}>", plugin_class)
+}
+
+// Writes the values of a setting, as pairs of a port index and a value.
+fn write_port_values<W: Write>(out: &mut W, id: u64, values: &[(usize, Data)]) -> io::Result<()> {
+    for &(index, value) in values {
+        writeln!(out, "        <ladspa:hasPortValue>")?;
+        writeln!(out,
+                 "          <ladspa:PortValue rdf:value=\"{}\" ladspa:forPort=\"&ladspa;{}.{}\"/>",
+                 value,
+                 id,
+                 index)?;
+        writeln!(out, "        </ladspa:hasPortValue>")?;
+    }
+    Ok(())
+}
+
+/// Writes an LRDF document describing ```plugins``` to ```out```.
+pub fn write<W: Write>(out: &mut W, plugins: &[PluginDescriptor]) -> io::Result<()> {
+    out.write_all(HEADER.as_bytes())?;
+    for plugin in plugins {
+        write_plugin(out, plugin)?;
+    }
+    out.write_all(FOOTER.as_bytes())
+}
+
+/// Returns an LRDF document describing ```plugins```.
+pub fn to_string(plugins: &[PluginDescriptor]) -> String {
+    let mut out = Vec::new();
+    write(&mut out, plugins).expect("writing to a Vec can't fail");
+    String::from_utf8(out).expect("generated RDF is valid UTF-8")
+}
@@ -0,0 +1,137 @@
+use alloc::vec::Vec;
+
+use super::PluginDescriptor;
+use super::lock::Lock;
+
+struct Registry {
+    plugins: Vec<fn() -> PluginDescriptor>,
+    sorted: bool,
+}
+
+static REGISTRY: Lock<Registry> = Lock::new(Registry {
+    plugins: Vec::new(),
+    sorted: false,
+});
+
+/// Registers a plugin type so that ```registered_descriptor``` will return its descriptor.
+///
+/// Registration happens when the library is loaded, before any of its code runs, so
+/// ```get_ladspa_descriptor``` can simply forward to ```registered_descriptor``` instead of
+/// keeping its own list of plugins:
+///
+/// ```no_run
+/// # #[macro_use] extern crate ladspa;
+/// # use ladspa::{DescribedPlugin, PluginDescriptor};
+/// # struct Amplifier;
+/// # impl DescribedPlugin for Amplifier {
+/// #     fn descriptor() -> PluginDescriptor { unimplemented!() }
+/// # }
+/// # struct Delay;
+/// # impl DescribedPlugin for Delay {
+/// #     fn descriptor() -> PluginDescriptor { unimplemented!() }
+/// # }
+/// register_plugin!(Amplifier);
+/// register_plugin!(Delay);
+///
+/// #[no_mangle]
+/// pub extern "C" fn get_ladspa_descriptor(index: u64) -> Option<ladspa::PluginDescriptor> {
+///     ladspa::registered_descriptor(index)
+/// }
+/// ```
+///
+/// ```export_ladspa!(Amplifier, Delay)``` does the same in one line. The type must implement
+/// ```DescribedPlugin```.
+#[macro_export]
+macro_rules! register_plugin {
+    ($plugin:ty) => {
+        const _: () = {
+            extern "C" fn register() {
+                $crate::register(<$plugin as $crate::DescribedPlugin>::descriptor);
+            }
+
+            // Run `register` when the library is loaded, like a C++ static constructor.
+            #[used]
+            #[cfg_attr(any(target_os = "linux", target_os = "android", target_os = "freebsd",
+                           target_os = "netbsd", target_os = "openbsd", target_os = "dragonfly"),
+                       link_section = ".init_array")]
+            #[cfg_attr(any(target_os = "macos", target_os = "ios"),
+                       link_section = "__DATA,__mod_init_func")]
+            #[cfg_attr(windows, link_section = ".CRT$XCU")]
+            static REGISTER: extern "C" fn() = register;
+        };
+    };
+}
+
+/// Makes the crate a plugin library: defines ```get_ladspa_descriptor``` and keeps the entry
+/// points hosts look for in the built library, which link time optimization would otherwise
+/// remove. Invoke it once, at the root of the crate, in one of three ways:
+///
+/// ```rust,ignore
+/// // Registers the plugin types, which must implement `DescribedPlugin`, and exports them.
+/// export_ladspa!(Amplifier, Delay);
+///
+/// // Exports the plugins registered with `register_plugin!`, possibly in other modules.
+/// export_ladspa!();
+///
+/// // Exports the plugins described by a function with the signature of
+/// // `get_ladspa_descriptor`.
+/// export_ladspa!(fn descriptor);
+/// ```
+#[macro_export]
+macro_rules! export_ladspa {
+    () => {
+        $crate::export_ladspa!(fn $crate::registered_descriptor);
+    };
+    (fn $describe:path) => {
+        /// Called by the ladspa crate to find the plugins of this library.
+        #[no_mangle]
+        #[allow(improper_ctypes_definitions)]
+        pub extern "C" fn get_ladspa_descriptor(index: u64)
+                                                -> Option<$crate::PluginDescriptor> {
+            $describe(index)
+        }
+
+        const _: () = {
+            #[used]
+            static ENTRY_POINTS: &$crate::ffi::EntryPoints = &$crate::ffi::ENTRY_POINTS;
+        };
+    };
+    ($($plugin:ty),+ $(,)*) => {
+        $($crate::register_plugin!($plugin);)+
+        $crate::export_ladspa!();
+    };
+}
+
+#[doc(hidden)]
+pub fn register(describe: fn() -> PluginDescriptor) {
+    let mut registry = REGISTRY.lock();
+    registry.plugins.push(describe);
+    registry.sorted = false;
+}
+
+/// Returns the descriptor of the ```index```th plugin registered with ```register_plugin!```,
+/// or ```None``` once ```index``` is past the last one.
+///
+/// The order in which a library's registrations run is up to the linker, so plugins are indexed
+/// in order of their unique IDs instead.
+pub fn registered_descriptor(index: u64) -> Option<PluginDescriptor> {
+    // Describing a plugin runs its code, which is never done with the registry locked.
+    let mut plugins = {
+        let registry = REGISTRY.lock();
+        if registry.sorted {
+            let describe = registry.plugins.get(index as usize).copied();
+            drop(registry);
+            return describe.map(|describe| describe());
+        }
+        registry.plugins.clone()
+    };
+    plugins.sort_by_cached_key(|describe| describe().unique_id);
+    let mut registry = REGISTRY.lock();
+    // Plugins registered meanwhile are left for the next call to sort.
+    if registry.plugins.len() == plugins.len() {
+        registry.plugins.clone_from(&plugins);
+        registry.sorted = true;
+    }
+    drop(registry);
+    plugins.get(index as usize).map(|describe| describe())
+}
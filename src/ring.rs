@@ -0,0 +1,268 @@
+//! A wait-free queue between two threads, for exchanging data with ```Plugin::run``` without
+//! locking.
+//!
+//! Create the queue when the plugin is instantiated, keep one end in the plugin and hand the
+//! other to the thread it talks to, such as a GUI or a worker thread:
+//!
+//! ```no_run
+//! # use ladspa::ring::{self, Consumer, Producer};
+//! # use ladspa::dsp::simd;
+//! # fn spawn_meter_thread(_: Consumer<f32>) {}
+//! # struct P { levels: Producer<f32> }
+//! # fn new() -> P {
+//! let (levels, meter) = ring::channel(64);
+//! spawn_meter_thread(meter);
+//! # P { levels }
+//! # }
+//! # impl P { fn run(&mut self, input: &[f32]) {
+//!
+//! // In run:
+//! let _ = self.levels.push(simd::peak(input));
+//! # }
+//! # }
+//! ```
+//!
+//! Pushing and popping never block, never allocate and never wait on the other thread, so both
+//! are safe to use in hard real-time code. A full queue rejects new values rather than waiting
+//! for room.
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+struct Shared<T> {
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    // The number of values ever popped and pushed. Slot `n % slots.len()` holds the `n`th value;
+    // the length being a power of two keeps that true when the counters wrap around.
+    head: AtomicUsize,
+    tail: AtomicUsize,
+}
+
+// The producer only writes the slots between `tail` and `head + capacity`, and the consumer only
+// reads those between `head` and `tail`, so they never access the same slot at once.
+unsafe impl<T: Send> Send for Shared<T> {}
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+impl<T> Shared<T> {
+    fn slot(&self, index: usize) -> *mut MaybeUninit<T> {
+        self.slots[index & (self.slots.len() - 1)].get()
+    }
+}
+
+impl<T> Drop for Shared<T> {
+    fn drop(&mut self) {
+        let (head, tail) = (*self.head.get_mut(), *self.tail.get_mut());
+        let mut index = head;
+        while index != tail {
+            unsafe { (*self.slot(index)).assume_init_drop() };
+            index = index.wrapping_add(1);
+        }
+    }
+}
+
+/// The end of a queue values are pushed into. Created by ```channel```.
+pub struct Producer<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// The end of a queue values are popped from. Created by ```channel```.
+pub struct Consumer<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// Creates a queue holding up to ```capacity``` values, rounded up to a power of two, and
+/// returns its two ends.
+pub fn channel<T: Send>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+    let len = capacity.max(1).next_power_of_two();
+    let slots: Vec<_> = (0..len).map(|_| UnsafeCell::new(MaybeUninit::uninit())).collect();
+    let shared = Arc::new(Shared {
+        slots: slots.into_boxed_slice(),
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+    });
+    (Producer { shared: shared.clone() }, Consumer { shared })
+}
+
+impl<T> Producer<T> {
+    /// The number of values the queue holds when full.
+    pub fn capacity(&self) -> usize {
+        self.shared.slots.len()
+    }
+
+    /// The number of values which can be pushed before the queue is full. The consumer may pop
+    /// values concurrently, so there may be more room by the time it is used.
+    pub fn free_len(&self) -> usize {
+        let tail = self.shared.tail.load(Ordering::Relaxed);
+        let head = self.shared.head.load(Ordering::Acquire);
+        self.capacity() - tail.wrapping_sub(head)
+    }
+
+    /// Whether the queue is full.
+    pub fn is_full(&self) -> bool {
+        self.free_len() == 0
+    }
+
+    /// Adds ```value``` at the end of the queue, or gives it back if the queue is full.
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        if self.is_full() {
+            return Err(value);
+        }
+        let tail = self.shared.tail.load(Ordering::Relaxed);
+        unsafe { (*self.shared.slot(tail)).write(value) };
+        self.shared.tail.store(tail.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+
+    /// Pushes as many values from the start of ```values``` as there is room for, and returns
+    /// how many.
+    pub fn push_slice(&mut self, values: &[T]) -> usize where T: Copy {
+        let count = values.len().min(self.free_len());
+        let tail = self.shared.tail.load(Ordering::Relaxed);
+        for (offset, &value) in values[..count].iter().enumerate() {
+            unsafe { (*self.shared.slot(tail.wrapping_add(offset))).write(value) };
+        }
+        self.shared.tail.store(tail.wrapping_add(count), Ordering::Release);
+        count
+    }
+}
+
+impl<T> Consumer<T> {
+    /// The number of values the queue holds when full.
+    pub fn capacity(&self) -> usize {
+        self.shared.slots.len()
+    }
+
+    /// The number of values waiting to be popped. The producer may push values concurrently, so
+    /// there may be more by the time they are popped.
+    pub fn len(&self) -> usize {
+        let head = self.shared.head.load(Ordering::Relaxed);
+        let tail = self.shared.tail.load(Ordering::Acquire);
+        tail.wrapping_sub(head)
+    }
+
+    /// Whether there is no value waiting.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes the value at the front of the queue, if there is one.
+    pub fn pop(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        let head = self.shared.head.load(Ordering::Relaxed);
+        let value = unsafe { (*self.shared.slot(head)).assume_init_read() };
+        self.shared.head.store(head.wrapping_add(1), Ordering::Release);
+        Some(value)
+    }
+
+    /// Pops as many values as are waiting and fit into ```values```, and returns how many.
+    pub fn pop_slice(&mut self, values: &mut [T]) -> usize where T: Copy {
+        let count = values.len().min(self.len());
+        let head = self.shared.head.load(Ordering::Relaxed);
+        for (offset, value) in values[..count].iter_mut().enumerate() {
+            *value = unsafe { (*self.shared.slot(head.wrapping_add(offset))).assume_init_read() };
+        }
+        self.shared.head.store(head.wrapping_add(count), Ordering::Release);
+        count
+    }
+
+    /// Pops every waiting value and returns the last one, if any. Suits values where only the
+    /// latest matters, such as settings sent to ```run```.
+    pub fn pop_latest(&mut self) -> Option<T> {
+        let mut latest = None;
+        while let Some(value) = self.pop() {
+            latest = Some(value);
+        }
+        latest
+    }
+}
+
+impl<T> fmt::Debug for Producer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Producer").field("capacity", &self.capacity()).finish()
+    }
+}
+
+impl<T> fmt::Debug for Consumer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Consumer").field("len", &self.len()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_come_out_in_order_until_the_queue_is_full() {
+        let (mut producer, mut consumer) = channel(3);
+        assert_eq!(producer.capacity(), 4);
+        for value in 0..4 {
+            assert_eq!(producer.push(value), Ok(()));
+        }
+        assert!(producer.is_full());
+        assert_eq!(producer.push(4), Err(4));
+        assert_eq!(consumer.len(), 4);
+        assert_eq!(consumer.pop(), Some(0));
+        assert_eq!(producer.push(4), Ok(()));
+        assert_eq!(consumer.pop_latest(), Some(4));
+        assert!(consumer.is_empty());
+        assert_eq!(consumer.pop(), None);
+    }
+
+    #[test]
+    fn slices_wrap_around_the_end_of_the_buffer() {
+        let (mut producer, mut consumer) = channel(4);
+        let mut values = [0; 4];
+        for start in 0..10 {
+            assert_eq!(producer.push_slice(&[start, start + 1, start + 2]), 3);
+            assert_eq!(producer.push_slice(&[9, 9]), 1);
+            assert_eq!(consumer.pop_slice(&mut values[..3]), 3);
+            assert_eq!(values[..3], [start, start + 1, start + 2]);
+            assert_eq!(consumer.pop_slice(&mut values), 1);
+        }
+    }
+
+    #[test]
+    fn values_left_in_the_queue_are_dropped_with_it() {
+        let value = Arc::new(());
+        {
+            let (mut producer, mut consumer) = channel(4);
+            for _ in 0..3 {
+                producer.push(value.clone()).unwrap();
+            }
+            consumer.pop();
+            assert_eq!(Arc::strong_count(&value), 3);
+        }
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn values_cross_threads_in_order() {
+        let (mut producer, mut consumer) = channel(16);
+        let sender = std::thread::spawn(move || {
+            for value in 0..10_000 {
+                while producer.push(value).is_err() {
+                    std::thread::yield_now();
+                }
+            }
+        });
+        let mut expected = 0;
+        while expected < 10_000 {
+            match consumer.pop() {
+                Some(value) => {
+                    assert_eq!(value, expected);
+                    expected += 1;
+                }
+                None => std::thread::yield_now(),
+            }
+        }
+        sender.join().unwrap();
+    }
+}
@@ -0,0 +1,182 @@
+//! Checks that plugins claiming ```PROP_HARD_REALTIME_CAPABLE``` live up to it.
+//!
+//! With the ```rt-audit``` feature enabled, every call to ```Plugin::run```, ```run_adding``` and
+//! ```run_synth``` on a plugin with that property is audited: if it allocates, frees or blocks,
+//! the call panics once it returns, which poisons the instance and logs the violation. This is
+//! meant for development builds; leave the feature off in releases.
+//!
+//! The panic message includes a backtrace of the first violation, pointing at the code which
+//! allocated or blocked. ```audit_traced``` gives the same information to other callers.
+//!
+//! Allocations are detected by a global allocator installed by this module, so the feature can't
+//! be combined with another ```#[global_allocator]```. Locks from the standard library can't be
+//! intercepted, so blocking is only detected through ```Mutex``` and ```blocking```.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::backtrace::Backtrace;
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::sync::{self, LockResult, MutexGuard, TryLockResult};
+
+/// What audited code did which it shouldn't have.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Violations {
+    /// The number of allocations and reallocations.
+    pub allocations: usize,
+    /// The number of deallocations.
+    pub deallocations: usize,
+    /// The number of potentially blocking operations.
+    pub blocking: usize,
+}
+
+impl Violations {
+    /// Returns whether there were no violations at all.
+    pub fn is_empty(&self) -> bool {
+        *self == Violations::default()
+    }
+}
+
+impl fmt::Display for Violations {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "{} allocations, {} deallocations and {} blocking operations",
+               self.allocations,
+               self.deallocations,
+               self.blocking)
+    }
+}
+
+thread_local! {
+    static ACTIVE: Cell<bool> = const { Cell::new(false) };
+    static VIOLATIONS: Cell<Violations> = const {
+        Cell::new(Violations { allocations: 0, deallocations: 0, blocking: 0 })
+    };
+    // Whether the current audit wants a backtrace of its first violation, and that backtrace.
+    static TRACING: Cell<bool> = const { Cell::new(false) };
+    static BACKTRACE: RefCell<Option<Backtrace>> = const { RefCell::new(None) };
+}
+
+// Records a violation if the current thread is being audited.
+fn record<F: FnOnce(&mut Violations)>(f: F) {
+    // The thread locals may already be gone while a thread exits.
+    if ACTIVE.try_with(Cell::get).unwrap_or(false) {
+        let _ = VIOLATIONS.try_with(|violations| {
+            let mut v = violations.get();
+            f(&mut v);
+            violations.set(v);
+        });
+        if TRACING.try_with(Cell::get).unwrap_or(false) {
+            capture();
+        }
+    }
+}
+
+// Stores a backtrace of the current violation unless the audit already has one. Capturing
+// allocates, so the audit is suspended meanwhile.
+fn capture() {
+    let _ = BACKTRACE.try_with(|backtrace| {
+        if let Ok(mut backtrace) = backtrace.try_borrow_mut() {
+            if backtrace.is_none() {
+                ACTIVE.with(|active| active.set(false));
+                *backtrace = Some(Backtrace::force_capture());
+                ACTIVE.with(|active| active.set(true));
+            }
+        }
+    });
+}
+
+/// Runs ```f```, returning its result along with everything it did which isn't real-time safe.
+/// Audits may be nested.
+pub fn audit<R, F: FnOnce() -> R>(f: F) -> (R, Violations) {
+    let (result, found, _) = run_audit(false, f);
+    (result, found)
+}
+
+/// Like ```audit```, but also returns a backtrace of the first violation, if there was one.
+/// Capturing it is slow, but only happens once per audit.
+pub fn audit_traced<R, F: FnOnce() -> R>(f: F) -> (R, Violations, Option<Backtrace>) {
+    run_audit(true, f)
+}
+
+fn run_audit<R, F: FnOnce() -> R>(trace: bool, f: F) -> (R, Violations, Option<Backtrace>) {
+    let outer_backtrace = BACKTRACE.with(|backtrace| backtrace.borrow_mut().take());
+    let was_tracing = TRACING.with(|tracing| tracing.replace(trace));
+    let was_active = ACTIVE.with(|active| active.replace(true));
+    let outer = VIOLATIONS.with(|violations| violations.replace(Violations::default()));
+    let result = f();
+    let found = VIOLATIONS.with(|violations| violations.replace(outer));
+    ACTIVE.with(|active| active.set(was_active));
+    TRACING.with(|tracing| tracing.set(was_tracing));
+    let backtrace = BACKTRACE.with(|backtrace| backtrace.replace(outer_backtrace));
+    (result, found, backtrace)
+}
+
+/// Reports a potentially blocking operation, such as waiting on a lock or a channel, to the
+/// current audit. Call it before operations this module can't see.
+pub fn blocking() {
+    record(|v| v.blocking += 1);
+}
+
+/// The allocator installed by the ```rt-audit``` feature. Forwards to the system allocator,
+/// recording every call made during an audit.
+pub struct AuditAllocator;
+
+unsafe impl GlobalAlloc for AuditAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        record(|v| v.allocations += 1);
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        record(|v| v.allocations += 1);
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        record(|v| v.allocations += 1);
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        record(|v| v.deallocations += 1);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: AuditAllocator = AuditAllocator;
+
+/// A ```std::sync::Mutex``` whose ```lock``` counts as blocking in an audit. ```try_lock```
+/// never blocks, so it remains allowed.
+#[derive(Debug, Default)]
+pub struct Mutex<T> {
+    inner: sync::Mutex<T>,
+}
+
+impl<T> Mutex<T> {
+    /// Creates a new mutex holding ```value```.
+    pub const fn new(value: T) -> Mutex<T> {
+        Mutex { inner: sync::Mutex::new(value) }
+    }
+
+    /// Locks the mutex, blocking until it is available.
+    pub fn lock(&self) -> LockResult<MutexGuard<'_, T>> {
+        blocking();
+        self.inner.lock()
+    }
+
+    /// Locks the mutex if it is available.
+    pub fn try_lock(&self) -> TryLockResult<MutexGuard<'_, T>> {
+        self.inner.try_lock()
+    }
+
+    /// Returns a mutable reference to the contents, which needs no locking.
+    pub fn get_mut(&mut self) -> LockResult<&mut T> {
+        self.inner.get_mut()
+    }
+
+    /// Consumes the mutex, returning its contents.
+    pub fn into_inner(self) -> LockResult<T> {
+        self.inner.into_inner()
+    }
+}
@@ -0,0 +1,196 @@
+//! Generating new plugin crates.
+//!
+//! A LADSPA plugin is a shared library exporting ```ladspa_descriptor```, which takes a
+//! ```cdylib``` crate type, this crate as a dependency and a ```get_ladspa_descriptor```
+//! function. ```generate``` writes a crate with all of that set up around a simple gain plugin,
+//! ready to build and load into a host:
+//!
+//! ```no_run
+//! # use std::path::Path;
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! ladspa::scaffold::generate(Path::new("my-gain"), "my-gain")?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The ```cargo-ladspa``` binary, built with the ```scaffold``` feature, does the same from the
+//! command line as ```cargo ladspa new my-gain```.
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The ways in which generating a crate can fail.
+#[derive(Debug)]
+pub enum ScaffoldError {
+    /// The name is not a valid crate name: it must start with a letter and contain only ASCII
+    /// letters, digits, ```-``` and ```_```.
+    InvalidName(String),
+    /// The directory to generate the crate in already exists.
+    Exists(PathBuf),
+    /// A file could not be written.
+    Io(io::Error),
+}
+
+impl fmt::Display for ScaffoldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ScaffoldError::InvalidName(ref name) => {
+                write!(f, "\"{}\" is not a valid crate name", name)
+            }
+            ScaffoldError::Exists(ref path) => write!(f, "{} already exists", path.display()),
+            ScaffoldError::Io(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for ScaffoldError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            ScaffoldError::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ScaffoldError {
+    fn from(err: io::Error) -> ScaffoldError {
+        ScaffoldError::Io(err)
+    }
+}
+
+const CARGO_TOML: &str = r#"[package]
+name = "{name}"
+version = "0.1.0"
+edition = "2021"
+
+[lib]
+# cdylib is the shared library hosts load; rlib lets the tests link against the plugin.
+crate-type = ["cdylib", "rlib"]
+
+[dependencies]
+ladspa = "{version}"
+
+[dev-dependencies]
+ladspa = { version = "{version}", features = ["harness"] }
+"#;
+
+const LIB_RS: &str = r#"//! {title}, a LADSPA plugin.
+//!
+//! Build it with `cargo build --release`, then copy target/release/lib{ident}.so (.dylib on
+//! macOS, {ident}.dll on Windows) to a directory listed in LADSPA_PATH, such as ~/.ladspa.
+
+use ladspa::{DefaultValue, DescribedPlugin, Plugin, PluginDescriptor, PortConnection, UniqueId};
+
+// Hosts tell plugins apart by their unique ID. This one is reserved for development: get an
+// assigned one before releasing the plugin, see http://www.ladspa.org/ladspa_sdk/unique_ids.html
+const UNIQUE_ID: UniqueId = UniqueId::experimental(1);
+
+/// Scales its input by a gain.
+#[derive(Default)]
+pub struct {type_name};
+
+impl Plugin for {type_name} {
+    fn run<'a>(&mut self, _sample_count: usize, ports: &mut [PortConnection<'a>]) {
+        let input = ports[0].unwrap_audio();
+        let gain = ports[2].unwrap_control();
+        let output = ports[1].unwrap_audio_mut();
+        for (out, sample) in output.iter_mut().zip(input) {
+            *out = sample * gain;
+        }
+    }
+}
+
+impl DescribedPlugin for {type_name} {
+    fn descriptor() -> PluginDescriptor {
+        PluginDescriptor::builder()
+            .unique_id(UNIQUE_ID.get())
+            .label("{ident}")
+            .name("{title}")
+            .audio_in("Input")
+            .audio_out("Output")
+            .control_in("Gain", 0.0..2.0, DefaultValue::Value1)
+            .new(ladspa::new_default::<{type_name}>)
+            .build()
+            .unwrap()
+    }
+}
+
+ladspa::export_ladspa!({type_name});
+"#;
+
+const TEST_RS: &str = r#"use ladspa::harness::TestHost;
+use ladspa::DescribedPlugin;
+use {ident}::{type_name};
+
+#[test]
+fn applies_gain() {
+    let descriptor = {type_name}::descriptor();
+    let mut host = TestHost::new(&descriptor, 44100, 64).unwrap();
+    host.set_control(2, 0.5);
+    host.input_mut(0).fill(1.0);
+    host.activate();
+    host.run(64);
+    assert!(host.output(1).iter().all(|&sample| sample == 0.5));
+}
+"#;
+
+const GITIGNORE: &str = "/target\n";
+
+// Whether `name` is usable as a crate name.
+fn is_valid_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic()) &&
+    name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+// Splits `name` into words at `-` and `_`, capitalizing each.
+fn words(name: &str) -> Vec<String> {
+    name.split(['-', '_'])
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            chars.next().map_or(String::new(), |first| first.to_ascii_uppercase().to_string()) +
+            chars.as_str()
+        })
+        .collect()
+}
+
+/// Returns the path and contents of each file of a plugin crate called ```name```, relative to
+/// the crate's root directory.
+pub fn files(name: &str) -> Result<Vec<(&'static str, String)>, ScaffoldError> {
+    if !is_valid_name(name) {
+        return Err(ScaffoldError::InvalidName(String::from(name)));
+    }
+    let ident = name.replace('-', "_");
+    let type_name = words(name).concat();
+    let title = words(name).join(" ");
+    let fill = |template: &str| {
+        template.replace("{name}", name)
+                .replace("{version}", env!("CARGO_PKG_VERSION"))
+                .replace("{ident}", &ident)
+                .replace("{type_name}", &type_name)
+                .replace("{title}", &title)
+    };
+    Ok(vec![("Cargo.toml", fill(CARGO_TOML)),
+            ("src/lib.rs", fill(LIB_RS)),
+            ("tests/plugin.rs", fill(TEST_RS)),
+            (".gitignore", String::from(GITIGNORE))])
+}
+
+/// Writes a plugin crate called ```name``` into ```dir```, which must not exist yet.
+pub fn generate(dir: &Path, name: &str) -> Result<(), ScaffoldError> {
+    let files = files(name)?;
+    if dir.exists() {
+        return Err(ScaffoldError::Exists(dir.to_path_buf()));
+    }
+    for (path, contents) in files {
+        let path = dir.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, contents)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,141 @@
+//! Checks on plugin unique IDs.
+//!
+//! Hosts identify plugins by their unique ID, so two plugins sharing one, in the same library or
+//! in different ones, can't both be used. IDs are assigned centrally to avoid that, except for
+//! the range from 1 to 1000, which is set aside for plugins under development and must not be
+//! shipped:
+//!
+//! ```rust,ignore
+//! PluginDescriptor {
+//!     unique_id: UniqueId::experimental(3).get(),
+//!     ...
+//! }
+//! ```
+//!
+//! More information is available here: http://www.ladspa.org/ladspa_sdk/unique_ids.html
+
+use core::error::Error;
+use core::fmt;
+use core::ops::RangeInclusive;
+use alloc::vec::Vec;
+
+use super::PluginDescriptor;
+use super::get_ladspa_descriptor;
+
+/// The IDs set aside for plugins under development.
+pub const EXPERIMENTAL: RangeInclusive<u64> = 1..=1000;
+
+/// The largest ID hosts are guaranteed to support: IDs fit in 24 bits.
+pub const MAX: u64 = 0xff_ffff;
+
+/// A unique ID, checked to be within the range hosts support.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UniqueId(u64);
+
+impl UniqueId {
+    /// Wraps an assigned ID. Panics if ```id``` is 0 or above ```MAX```.
+    pub const fn new(id: u64) -> UniqueId {
+        assert!(is_valid(id), "unique IDs must be between 1 and 0xffffff");
+        UniqueId(id)
+    }
+
+    /// The ```n```th ID of the range set aside for development. Panics unless ```n``` is between
+    /// 1 and 1000.
+    pub const fn experimental(n: u64) -> UniqueId {
+        assert!(n >= *EXPERIMENTAL.start() && n <= *EXPERIMENTAL.end(),
+                "experimental unique IDs must be between 1 and 1000");
+        UniqueId(n)
+    }
+
+    /// The ID, as stored in ```PluginDescriptor::unique_id```.
+    pub const fn get(self) -> u64 {
+        self.0
+    }
+
+    /// Whether the ID is one set aside for development.
+    pub const fn is_experimental(self) -> bool {
+        is_experimental(self.0)
+    }
+}
+
+impl From<UniqueId> for u64 {
+    fn from(id: UniqueId) -> u64 {
+        id.0
+    }
+}
+
+impl fmt::Display for UniqueId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Whether ```id``` is within the range hosts support, from 1 to ```MAX```.
+pub const fn is_valid(id: u64) -> bool {
+    id != 0 && id <= MAX
+}
+
+/// Whether ```id``` is one set aside for development.
+pub const fn is_experimental(id: u64) -> bool {
+    id >= *EXPERIMENTAL.start() && id <= *EXPERIMENTAL.end()
+}
+
+/// The ways in which the unique IDs of a library can be wrong.
+#[derive(Debug, Clone, PartialEq)]
+pub enum UniqueIdError {
+    /// The plugin with the given label has an ID of 0 or above ```MAX```.
+    Invalid {
+        /// The ID.
+        id: u64,
+        /// The label of the plugin.
+        label: &'static str,
+    },
+    /// Two plugins, given by their labels, share an ID.
+    Duplicate {
+        /// The ID.
+        id: u64,
+        /// The label of the plugin listed first.
+        first: &'static str,
+        /// The label of the plugin listed second.
+        second: &'static str,
+    },
+}
+
+impl fmt::Display for UniqueIdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UniqueIdError::Invalid { id, label } => {
+                write!(f, "plugin \"{}\" has unique ID {}, outside of 1 to {}", label, id, MAX)
+            }
+            UniqueIdError::Duplicate { id, first, second } => {
+                write!(f, "plugins \"{}\" and \"{}\" share unique ID {}", first, second, id)
+            }
+        }
+    }
+}
+
+impl Error for UniqueIdError {}
+
+/// Checks that the plugins in ```descriptors``` all have valid IDs, and that no two share one.
+pub fn check(descriptors: &[PluginDescriptor]) -> Result<(), UniqueIdError> {
+    let mut ids: Vec<(u64, &'static str)> = Vec::with_capacity(descriptors.len());
+    for descriptor in descriptors {
+        let (id, label) = (descriptor.unique_id, descriptor.label);
+        if !is_valid(id) {
+            return Err(UniqueIdError::Invalid { id, label });
+        }
+        if let Some(&(_, first)) = ids.iter().find(|&&(other, _)| other == id) {
+            return Err(UniqueIdError::Duplicate { id, first, second: label });
+        }
+        ids.push((id, label));
+    }
+    Ok(())
+}
+
+/// Runs ```check``` on every plugin returned by ```get_ladspa_descriptor```, as a test or a
+/// startup check of the whole library.
+pub fn check_library() -> Result<(), UniqueIdError> {
+    let descriptors: Vec<_> = (0..).map_while(|index| unsafe { get_ladspa_descriptor(index) })
+                                   .collect();
+    check(&descriptors)
+}
@@ -0,0 +1,342 @@
+//! Conversions between the units plugins present to users and the values they compute with.
+//!
+//! Gains of 0 are -∞ dB and the other way round, so a "mute" setting at the bottom of a dB range
+//! given as ```f32::NEG_INFINITY``` silences the signal instead of producing NaNs. Frequencies of
+//! 0 Hz or less have no pitch, and convert to a note of -∞.
+//!
+//! ```Unit``` records what the value of a port means, for hosts to format it and for the RDF and
+//! LV2 metadata.
+
+use alloc::format;
+use alloc::string::String;
+use core::f64::consts::{LN_10, LN_2};
+
+use Data;
+use dsp::bpm_to_hz;
+#[cfg(not(any(feature = "std", test)))]
+use math::Float;
+
+/// Converts a level in decibels to a linear gain: 0 dB is a gain of 1, -6 dB about 0.5 and
+/// -∞ dB 0.
+#[inline]
+pub fn db_to_gain(db: Data) -> Data {
+    (f64::from(db) * LN_10 / 20.0).exp() as Data
+}
+
+/// Converts a linear gain to a level in decibels. The sign of the gain is ignored, and a gain of
+/// 0 is -∞ dB.
+#[inline]
+pub fn gain_to_db(gain: Data) -> Data {
+    (f64::from(gain).abs().ln() * 20.0 / LN_10) as Data
+}
+
+/// Converts a MIDI note number to a frequency in Hz, with A4, note 69, at 440 Hz. Fractional
+/// notes are between semitones.
+#[inline]
+pub fn midi_note_to_hz(note: Data) -> Data {
+    (440.0 * ((f64::from(note) - 69.0) * LN_2 / 12.0).exp()) as Data
+}
+
+/// Converts a frequency in Hz to a fractional MIDI note number, with A4, note 69, at 440 Hz.
+#[inline]
+pub fn hz_to_midi_note(hz: Data) -> Data {
+    if hz <= 0.0 {
+        return Data::NEG_INFINITY;
+    }
+    (69.0 + 12.0 * (f64::from(hz) / 440.0).ln() / LN_2) as Data
+}
+
+/// The frequency ratio of an interval of ```semitones``` semitones: 2 for 12 semitones, 0.5 for
+/// -12.
+#[inline]
+pub fn semitones_to_ratio(semitones: Data) -> Data {
+    (f64::from(semitones) * LN_2 / 12.0).exp() as Data
+}
+
+/// The interval in semitones of a frequency ratio.
+#[inline]
+pub fn ratio_to_semitones(ratio: Data) -> Data {
+    (12.0 * f64::from(ratio).ln() / LN_2) as Data
+}
+
+/// The frequency ratio of an interval of ```cents``` cents, hundredths of a semitone.
+#[inline]
+pub fn cents_to_ratio(cents: Data) -> Data {
+    (f64::from(cents) * LN_2 / 1200.0).exp() as Data
+}
+
+/// The interval in cents of a frequency ratio.
+#[inline]
+pub fn ratio_to_cents(ratio: Data) -> Data {
+    (1200.0 * f64::from(ratio).ln() / LN_2) as Data
+}
+
+/// What the value of a control port measures, set in ```Port::unit```.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Unit {
+    /// A frequency in Hz.
+    Hz,
+    /// A level in decibels.
+    Db,
+    /// A time in seconds.
+    Seconds,
+    /// A time in milliseconds.
+    Milliseconds,
+    /// An interval in semitones.
+    Semitones,
+    /// An interval in cents, hundredths of a semitone.
+    Cents,
+    /// A proportion in percent.
+    Percent,
+    /// A tempo in beats per minute.
+    Bpm,
+}
+
+impl Unit {
+    /// The symbol written after values, such as ```"Hz"``` or ```"ms"```.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Unit::Hz => "Hz",
+            Unit::Db => "dB",
+            Unit::Seconds => "s",
+            Unit::Milliseconds => "ms",
+            Unit::Semitones => "st",
+            Unit::Cents => "ct",
+            Unit::Percent => "%",
+            Unit::Bpm => "BPM",
+        }
+    }
+
+    /// Finds the unit a port name ends with in parentheses, as in ```"Cutoff (Hz)"``` or
+    /// ```"Delay (seconds)"```, the way LADSPA plugins conventionally name their ports.
+    pub fn from_name(name: &str) -> Option<Unit> {
+        let name = name.trim_end();
+        let open = name.rfind('(')?;
+        let unit = name[open + 1..].strip_suffix(')')?.trim();
+        let units = [(Unit::Hz, &["hz"][..]),
+                     (Unit::Db, &["db", "dbfs"][..]),
+                     (Unit::Seconds, &["s", "sec", "secs", "second", "seconds"][..]),
+                     (Unit::Milliseconds, &["ms", "msec", "msecs", "milliseconds"][..]),
+                     (Unit::Semitones, &["st", "semi", "semitone", "semitones"][..]),
+                     (Unit::Cents, &["ct", "cent", "cents"][..]),
+                     (Unit::Percent, &["%", "percent"][..]),
+                     (Unit::Bpm, &["bpm"][..])];
+        units.iter()
+            .find(|&&(_, spellings)| {
+                spellings.iter().any(|spelling| unit.eq_ignore_ascii_case(spelling))
+            })
+            .map(|&(unit, _)| unit)
+    }
+
+    /// Converts ```value``` from this unit to ```to```, or returns ```None``` if they measure
+    /// different things.
+    pub fn convert(self, value: Data, to: Unit) -> Option<Data> {
+        let factor = match (self, to) {
+            _ if self == to => 1.0,
+            (Unit::Seconds, Unit::Milliseconds) => 1000.0,
+            (Unit::Milliseconds, Unit::Seconds) => 0.001,
+            (Unit::Semitones, Unit::Cents) => 100.0,
+            (Unit::Cents, Unit::Semitones) => 0.01,
+            _ => return None,
+        };
+        Some(value * factor)
+    }
+
+    /// Formats ```value``` with the symbol of the unit, in the form users expect: with a
+    /// sensible number of decimals, times and frequencies switching to seconds and kHz when
+    /// large, and -∞ dB as such.
+    pub fn format(self, value: Data) -> String {
+        match self {
+            Unit::Hz if value.abs() >= 1000.0 => format!("{:.2} kHz", value / 1000.0),
+            Unit::Hz => format!("{:.1} Hz", value),
+            Unit::Db if value == Data::NEG_INFINITY => String::from("-∞ dB"),
+            Unit::Db => format!("{:.1} dB", value),
+            Unit::Seconds if value.abs() < 1.0 => Unit::Milliseconds.format(value * 1000.0),
+            Unit::Seconds => format!("{:.2} s", value),
+            Unit::Milliseconds if value.abs() >= 1000.0 => Unit::Seconds.format(value / 1000.0),
+            Unit::Milliseconds => format!("{:.1} ms", value),
+            Unit::Semitones => format!("{:.2} st", value),
+            Unit::Cents => format!("{:.0} ct", value),
+            Unit::Percent => format!("{:.0}%", value),
+            Unit::Bpm => format!("{:.1} BPM", value),
+        }
+    }
+}
+
+/// A note value to synchronize times to the tempo with, as a number of quarter note beats.
+///
+/// The common divisions have constants, and can be offered to users as an enumeration port with
+/// ```Division::LABELS```, read back with ```Division::from_index```:
+///
+/// ```rust,ignore
+/// .port(Port::enumeration("Division", Division::LABELS))
+/// // In run:
+/// let division = Division::from_index(p.division.as_i32());
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Division {
+    beats: f32,
+}
+
+impl Division {
+    /// A whole note, four beats.
+    pub const WHOLE: Division = Division { beats: 4.0 };
+    /// A half note, two beats.
+    pub const HALF: Division = Division { beats: 2.0 };
+    /// A quarter note, one beat.
+    pub const QUARTER: Division = Division { beats: 1.0 };
+    /// An eighth note, half a beat.
+    pub const EIGHTH: Division = Division { beats: 0.5 };
+    /// A sixteenth note, a quarter of a beat.
+    pub const SIXTEENTH: Division = Division { beats: 0.25 };
+    /// A thirty-second note, an eighth of a beat.
+    pub const THIRTY_SECOND: Division = Division { beats: 0.125 };
+
+    /// The labels of the divisions returned by ```from_index```, from the longest to the
+    /// shortest, with "." marking dotted notes and "T" triplets.
+    pub const LABELS: &'static [&'static str] = &["1/1", "1/2", "1/2.", "1/2T", "1/4", "1/4.",
+                                                  "1/4T", "1/8", "1/8.", "1/8T", "1/16",
+                                                  "1/16.", "1/16T", "1/32"];
+
+    /// A division lasting ```beats``` quarter note beats.
+    pub fn new(beats: f32) -> Division {
+        Division { beats }
+    }
+
+    /// The division labelled ```LABELS[index]```. Indices past either end give the first or the
+    /// last division.
+    pub fn from_index(index: i32) -> Division {
+        DIVISIONS[index.clamp(0, DIVISIONS.len() as i32 - 1) as usize]
+    }
+
+    /// The same note, dotted: half as long again.
+    pub const fn dotted(self) -> Division {
+        Division { beats: self.beats * 1.5 }
+    }
+
+    /// The same note as part of a triplet: two thirds as long.
+    pub const fn triplet(self) -> Division {
+        Division { beats: self.beats * 2.0 / 3.0 }
+    }
+
+    /// The length of the division, in quarter note beats.
+    pub fn beats(self) -> f32 {
+        self.beats
+    }
+}
+
+// The divisions named by `Division::LABELS`.
+const DIVISIONS: [Division; 14] = [Division::WHOLE,
+                                   Division::HALF,
+                                   Division::HALF.dotted(),
+                                   Division::HALF.triplet(),
+                                   Division::QUARTER,
+                                   Division::QUARTER.dotted(),
+                                   Division::QUARTER.triplet(),
+                                   Division::EIGHTH,
+                                   Division::EIGHTH.dotted(),
+                                   Division::EIGHTH.triplet(),
+                                   Division::SIXTEENTH,
+                                   Division::SIXTEENTH.dotted(),
+                                   Division::SIXTEENTH.triplet(),
+                                   Division::THIRTY_SECOND];
+
+/// Converts note divisions to times at a tempo, for plugins offering delay times or modulation
+/// rates synchronized to the tempo of a ```Port::tempo``` control:
+///
+/// ```rust,ignore
+/// self.sync.set_tempo(p.tempo);
+/// self.lfo.sync(&self.sync, Division::QUARTER);
+/// let delayed = self.delay.read_linear(self.sync.samples(Division::EIGHTH.dotted()));
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TempoSync {
+    bpm: f32,
+    sample_rate: u64,
+}
+
+// The slowest tempo a `TempoSync` follows, keeping times finite.
+const MIN_BPM: f32 = 1.0;
+
+impl TempoSync {
+    /// Creates a converter for a tempo of ```bpm``` quarter note beats per minute, at
+    /// ```sample_rate```.
+    pub fn new(bpm: f32, sample_rate: u64) -> TempoSync {
+        let mut sync = TempoSync { bpm: MIN_BPM, sample_rate };
+        sync.set_tempo(bpm);
+        sync
+    }
+
+    /// Changes the tempo, in beats per minute. Tempos below 1 BPM, and NaN, are taken as 1 BPM.
+    pub fn set_tempo(&mut self, bpm: f32) {
+        self.bpm = if bpm >= MIN_BPM { bpm } else { MIN_BPM };
+    }
+
+    /// The tempo, in beats per minute.
+    pub fn tempo(&self) -> f32 {
+        self.bpm
+    }
+
+    /// The length of ```division```, in seconds.
+    pub fn seconds(&self, division: Division) -> f32 {
+        60.0 * division.beats / self.bpm
+    }
+
+    /// The length of ```division```, in samples. Not rounded, for ```delay::DelayLine```'s
+    /// interpolated reads.
+    pub fn samples(&self, division: Division) -> f32 {
+        (f64::from(self.seconds(division)) * self.sample_rate as f64) as f32
+    }
+
+    /// The frequency, in Hz, of a cycle lasting ```division```.
+    pub fn hz(&self, division: Division) -> f32 {
+        bpm_to_hz(self.bpm, division.beats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(value: Data, expected: Data) {
+        assert!((value - expected).abs() < 1e-4, "{} is not {}", value, expected);
+    }
+
+    #[test]
+    fn silence_is_minus_infinity_db() {
+        assert_eq!(db_to_gain(Data::NEG_INFINITY), 0.0);
+        assert_eq!(gain_to_db(0.0), Data::NEG_INFINITY);
+    }
+
+    #[test]
+    fn decibels_convert_to_gains_and_back() {
+        assert_eq!(db_to_gain(0.0), 1.0);
+        assert_close(db_to_gain(6.0206), 2.0);
+        assert_close(gain_to_db(0.5), -6.0206);
+        assert_close(gain_to_db(-2.0), 6.0206);
+    }
+
+    #[test]
+    fn a4_is_440_hz() {
+        assert_eq!(midi_note_to_hz(69.0), 440.0);
+        assert_eq!(hz_to_midi_note(440.0), 69.0);
+        assert_close(hz_to_midi_note(midi_note_to_hz(60.0)), 60.0);
+        assert_close(midi_note_to_hz(81.0), 880.0);
+    }
+
+    #[test]
+    fn frequencies_of_0_hz_have_no_pitch() {
+        assert_eq!(hz_to_midi_note(0.0), Data::NEG_INFINITY);
+        assert_eq!(hz_to_midi_note(-1.0), Data::NEG_INFINITY);
+    }
+
+    #[test]
+    fn an_octave_doubles_the_frequency() {
+        assert_close(cents_to_ratio(1200.0), 2.0);
+        assert_close(semitones_to_ratio(12.0), 2.0);
+        assert_close(semitones_to_ratio(-12.0), 0.5);
+        assert_close(ratio_to_cents(2.0), 1200.0);
+        assert_close(ratio_to_semitones(0.5), -12.0);
+        assert_eq!(cents_to_ratio(0.0), 1.0);
+    }
+}
@@ -0,0 +1,588 @@
+//! Building blocks which come up in most plugins.
+
+use core::array;
+use core::ops::{Index, IndexMut};
+use core::slice;
+
+use super::Data;
+use super::dsp::Sample;
+#[cfg(not(any(feature = "std", test)))]
+use math::Float;
+
+/// The shape of the ramp a ```SmoothedValue``` follows towards its target.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Smoothing {
+    /// Moves towards the target at a constant rate, arriving after the smoothing time.
+    Linear,
+    /// Closes a fixed fraction of the distance to the target every sample, the smoothing time
+    /// being the time constant: after that long, about 63% of the distance has been covered.
+    /// Sounds more natural for gains and frequencies.
+    Exponential,
+}
+
+// Below this distance from the target, an exponential ramp snaps to it.
+const EXPONENTIAL_EPSILON: f64 = 1e-6;
+
+/// A control value which glides to new settings instead of jumping, avoiding the "zipper noise"
+/// caused by control inputs changing abruptly between blocks.
+///
+/// Set the target to the control input once per block and read one value per sample:
+///
+/// ```no_run
+/// # use ladspa::{Plugin, PortConnection};
+/// # use ladspa::util::SmoothedValue;
+/// # struct Amp { gain: SmoothedValue }
+/// # impl Plugin for Amp {
+/// fn run<'a>(&mut self, sample_count: usize, ports: &mut [PortConnection<'a>]) {
+///     let input = ports[0].unwrap_audio();
+///     self.gain.set_target(ports[2].unwrap_control());
+///     let output = ports[1].unwrap_audio_mut();
+///     for i in 0..sample_count {
+///         output[i] = input[i] * self.gain.tick();
+///     }
+/// }
+/// # }
+/// ```
+///
+/// The value is a ```Data``` unless another ```Sample``` type is given, such as ```f64``` for
+/// plugins processing in double precision.
+#[derive(Clone, Debug)]
+pub struct SmoothedValue<S: Sample = Data> {
+    smoothing: Smoothing,
+    time: f32,
+    sample_rate: u64,
+    current: S,
+    target: S,
+    // Linear ramps: the change per sample and the number of samples until the target is reached.
+    step: S,
+    remaining: usize,
+    // Exponential ramps: the fraction of the distance to the target left after each sample.
+    coefficient: S,
+}
+
+impl<S: Sample> SmoothedValue<S> {
+    /// Creates a value starting at ```initial``` which reaches new targets in ```time```
+    /// seconds.
+    pub fn linear(initial: S, time: f32, sample_rate: u64) -> SmoothedValue<S> {
+        SmoothedValue::new(Smoothing::Linear, initial, time, sample_rate)
+    }
+
+    /// Creates a value starting at ```initial``` which approaches new targets with a time
+    /// constant of ```time``` seconds.
+    pub fn exponential(initial: S, time: f32, sample_rate: u64) -> SmoothedValue<S> {
+        SmoothedValue::new(Smoothing::Exponential, initial, time, sample_rate)
+    }
+
+    /// Creates a value starting at ```initial``` which follows ramps of the given shape.
+    pub fn new(smoothing: Smoothing, initial: S, time: f32, sample_rate: u64) -> SmoothedValue<S> {
+        let mut value = SmoothedValue {
+            smoothing,
+            time,
+            sample_rate,
+            current: initial,
+            target: initial,
+            step: S::ZERO,
+            remaining: 0,
+            coefficient: S::ZERO,
+        };
+        value.update_coefficient();
+        value
+    }
+
+    /// Changes the smoothing time, in seconds. A ramp in progress continues at its old rate.
+    pub fn set_time(&mut self, time: f32) {
+        self.time = time;
+        self.update_coefficient();
+    }
+
+    /// Changes the sample rate the value is read at. A ramp in progress continues at its old
+    /// rate.
+    pub fn set_sample_rate(&mut self, sample_rate: u64) {
+        self.sample_rate = sample_rate;
+        self.update_coefficient();
+    }
+
+    fn ramp_samples(&self) -> usize {
+        (self.time * self.sample_rate as f32).round().max(0.0) as usize
+    }
+
+    fn update_coefficient(&mut self) {
+        let samples = f64::from(self.time) * self.sample_rate as f64;
+        self.coefficient = if samples > 0.0 { S::from_f64((-1.0 / samples).exp()) } else { S::ZERO };
+    }
+
+    /// Starts a ramp from the current value to ```target```. Setting the target it is already
+    /// heading to does not restart the ramp, so this may be called every block.
+    pub fn set_target(&mut self, target: S) {
+        if target == self.target {
+            return;
+        }
+        self.target = target;
+        if let Smoothing::Linear = self.smoothing {
+            self.remaining = self.ramp_samples();
+            if self.remaining == 0 {
+                self.current = target;
+            } else {
+                self.step = (target - self.current) / S::from_f64(self.remaining as f64);
+            }
+        }
+    }
+
+    /// Jumps to ```value``` immediately, abandoning any ramp in progress. Useful in
+    /// ```Plugin::activate```.
+    pub fn reset(&mut self, value: S) {
+        self.current = value;
+        self.target = value;
+        self.remaining = 0;
+    }
+
+    /// Advances by one sample and returns the new value.
+    pub fn tick(&mut self) -> S {
+        match self.smoothing {
+            Smoothing::Linear => {
+                if self.remaining > 0 {
+                    self.remaining -= 1;
+                    self.current = if self.remaining == 0 {
+                        self.target
+                    } else {
+                        self.current + self.step
+                    };
+                }
+            }
+            Smoothing::Exponential => {
+                if self.current != self.target {
+                    self.current = self.target + (self.current - self.target) * self.coefficient;
+                    if (self.current - self.target).abs() < S::from_f64(EXPONENTIAL_EPSILON) {
+                        self.current = self.target;
+                    }
+                }
+            }
+        }
+        self.current
+    }
+
+    /// Advances by one sample for each element of ```out```, storing the values in it.
+    pub fn fill(&mut self, out: &mut [S]) {
+        for sample in out.iter_mut() {
+            *sample = self.tick();
+        }
+    }
+
+    /// Returns the value most recently returned by ```tick```.
+    pub fn current(&self) -> S {
+        self.current
+    }
+
+    /// Returns the value being ramped towards.
+    pub fn target(&self) -> S {
+        self.target
+    }
+
+    /// Returns whether a ramp is in progress.
+    pub fn is_smoothing(&self) -> bool {
+        self.current != self.target
+    }
+}
+
+/// Interpolates a control input linearly across each block, from its value in the previous block
+/// to its value in the current one, so that slow hosts with long blocks don't turn smooth
+/// automation into audible steps.
+///
+/// Unlike ```SmoothedValue```, the ramp always lasts exactly one block: the output lags the
+/// control by a block but follows automation curves closely whatever the block size.
+///
+/// ```no_run
+/// # use ladspa::{Plugin, PortConnection, Ports};
+/// # use ladspa::util::ControlRamp;
+/// # struct Amp { gain: ControlRamp }
+/// # impl Plugin for Amp {
+/// fn run<'a>(&mut self, sample_count: usize, ports: &mut [PortConnection<'a>]) {
+///     let input = ports[0].unwrap_audio();
+///     let gain = Ports::new(ports).control_ramp(0, &mut self.gain, sample_count);
+///     let output = ports[1].unwrap_audio_out();
+///     for ((out, sample), gain) in output.iter_mut().zip(input).zip(gain) {
+///         *out = *sample * gain;
+///     }
+/// }
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ControlRamp<S: Sample = Data> {
+    // The value at the end of the previous block, if there was one.
+    previous: Option<S>,
+}
+
+impl<S: Sample> ControlRamp<S> {
+    /// Creates a ramp whose first block jumps straight to its value.
+    pub fn new() -> ControlRamp<S> {
+        ControlRamp { previous: None }
+    }
+
+    /// Creates a ramp whose first block starts from ```value```.
+    pub fn with_value(value: S) -> ControlRamp<S> {
+        ControlRamp { previous: Some(value) }
+    }
+
+    /// Forgets the previous block, so the next one jumps straight to its value. Useful in
+    /// ```Plugin::activate```.
+    pub fn reset(&mut self) {
+        self.previous = None;
+    }
+
+    /// The value at the end of the previous block, if there was one.
+    pub fn previous(&self) -> Option<S> {
+        self.previous
+    }
+
+    /// Returns the values for each of the ```sample_count``` samples of a block in which the
+    /// control is ```value```, ending exactly at it.
+    pub fn ramp(&mut self, value: S, sample_count: usize) -> Ramp<S> {
+        let start = self.previous.unwrap_or(value);
+        self.previous = Some(value);
+        let step = if sample_count > 0 {
+            (value - start) / S::from_f64(sample_count as f64)
+        } else {
+            S::ZERO
+        };
+        Ramp { current: start, step, target: value, remaining: sample_count }
+    }
+}
+
+/// The values of a control across one block, as returned by ```ControlRamp::ramp```.
+#[derive(Clone, Debug)]
+pub struct Ramp<S: Sample = Data> {
+    current: S,
+    step: S,
+    target: S,
+    remaining: usize,
+}
+
+impl<S: Sample> Ramp<S> {
+    /// Whether the control changed since the previous block. When it didn't, every value is the
+    /// same and plugins may skip the per-sample work.
+    pub fn is_constant(&self) -> bool {
+        self.step == S::ZERO
+    }
+}
+
+impl<S: Sample> Iterator for Ramp<S> {
+    type Item = S;
+
+    fn next(&mut self) -> Option<S> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        self.current = if self.remaining == 0 { self.target } else { self.current + self.step };
+        Some(self.current)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<S: Sample> ExactSizeIterator for Ramp<S> {}
+
+/// Blends the output of a processor in its old state into its output in a new one, for changes
+/// which can't be smoothed: switching the topology of a filter, jumping to another delay time or
+/// loading another wavetable all click when done from one sample to the next.
+///
+/// While a fade is in progress, run the processor twice, in its old and new states, and mix the
+/// two:
+///
+/// ```rust,ignore
+/// if self.fader.on_param_change(self.mode, mode) {
+///     self.old_filter = self.filter.clone();
+///     self.filter.set_mode(mode);
+/// }
+/// self.mode = mode;
+/// self.filter.process(input, &mut output);
+/// if self.fader.is_fading() {
+///     self.old_filter.process(input, &mut self.scratch);
+///     self.fader.process(&self.scratch, &mut output);
+/// }
+/// ```
+///
+/// The fade is linear, which keeps the level constant for the correlated signals the two states
+/// produce from the same input.
+#[derive(Clone, Debug)]
+pub struct Crossfader<S: Sample = Data> {
+    length: usize,
+    // The number of samples of the current fade done, equal to `length` when not fading.
+    position: usize,
+    step: S,
+}
+
+impl<S: Sample> Crossfader<S> {
+    /// Creates a crossfader whose fades last ```time``` seconds, and at least one sample.
+    pub fn new(time: f32, sample_rate: u64) -> Crossfader<S> {
+        let length = ((f64::from(time) * sample_rate as f64).round() as usize).max(1);
+        Crossfader {
+            length,
+            position: length,
+            step: S::from_f64(1.0 / length as f64),
+        }
+    }
+
+    /// The length of a fade, in samples.
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    /// Starts a fade from the old state to the new one, abandoning any fade in progress.
+    pub fn start(&mut self) {
+        self.position = 0;
+    }
+
+    /// Starts a fade if a parameter changes from ```old``` to ```new```, unless one is already in
+    /// progress, which carries on with the new value in its new state. Returns whether a fade
+    /// started, in which case the caller must keep a copy of its old state to fade out.
+    ///
+    /// Wrappers can call it for every parameter they watch, once per block.
+    pub fn on_param_change(&mut self, old: S, new: S) -> bool {
+        if old == new || self.is_fading() {
+            return false;
+        }
+        self.start();
+        true
+    }
+
+    /// Returns whether a fade is in progress.
+    pub fn is_fading(&self) -> bool {
+        self.position < self.length
+    }
+
+    /// Ends any fade in progress, leaving only the new state. Useful in ```Plugin::activate```.
+    pub fn reset(&mut self) {
+        self.position = self.length;
+    }
+
+    /// The weight of the new state ```offset``` samples from now, from just above 0 at the start
+    /// of a fade to 1 at its end and afterwards. Mixing several channels with it, then calling
+    /// ```advance```, fades them all together.
+    pub fn gain(&self, offset: usize) -> S {
+        let position = self.position + offset + 1;
+        if position >= self.length { S::ONE } else { S::from_f64(position as f64) * self.step }
+    }
+
+    /// Moves the fade ```samples``` samples on.
+    pub fn advance(&mut self, samples: usize) {
+        self.position = (self.position + samples).min(self.length);
+    }
+
+    /// Mixes one sample of each state and advances by one sample.
+    pub fn tick(&mut self, old: S, new: S) -> S {
+        let gain = self.gain(0);
+        self.advance(1);
+        old + (new - old) * gain
+    }
+
+    /// Mixes ```old``` into ```new```, in place, and advances by their length. Does nothing to
+    /// the samples past the end of the fade.
+    pub fn process(&mut self, old: &[S], new: &mut [S]) {
+        let len = new.len().min(old.len()).min(self.length - self.position);
+        for (offset, (new, &old)) in new[..len].iter_mut().zip(old).enumerate() {
+            *new = old + (*new - old) * self.gain(offset);
+        }
+        self.advance(new.len());
+    }
+}
+
+/// Makes the FPU treat denormal numbers as zero until dropped, restoring the previous mode.
+///
+/// Recursive filters and reverb tails decay into denormals, which are many times slower to
+/// compute with on most CPUs. Flushing them to zero is inaudible. On x86 this sets the FTZ and DAZ
+/// bits of MXCSR and on AArch64 the FZ bit of FPCR; elsewhere the guard does nothing.
+///
+/// ```Plugin::flush_denormals``` installs a guard around every call to ```run```. The mode is
+/// per thread, so a guard must be dropped on the thread that created it.
+pub struct DenormalGuard {
+    saved: usize,
+}
+
+#[cfg(any(target_arch = "x86_64", all(target_arch = "x86", target_feature = "sse")))]
+mod fpu {
+    use core::arch::asm;
+
+    // Flush to zero and denormals are zero.
+    const FLUSH_BITS: u32 = 0x8040;
+
+    pub fn enable_flush() -> usize {
+        let mut csr: u32 = 0;
+        unsafe {
+            asm!("stmxcsr [{}]", in(reg) &mut csr, options(nostack));
+            let flushed = csr | FLUSH_BITS;
+            asm!("ldmxcsr [{}]", in(reg) &flushed, options(nostack));
+        }
+        csr as usize
+    }
+
+    pub fn restore(saved: usize) {
+        let csr = saved as u32;
+        unsafe {
+            asm!("ldmxcsr [{}]", in(reg) &csr, options(nostack));
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod fpu {
+    use core::arch::asm;
+
+    // Flush to zero.
+    const FLUSH_BITS: usize = 1 << 24;
+
+    pub fn enable_flush() -> usize {
+        let fpcr: usize;
+        unsafe {
+            asm!("mrs {}, fpcr", out(reg) fpcr, options(nomem, nostack));
+            asm!("msr fpcr, {}", in(reg) fpcr | FLUSH_BITS, options(nomem, nostack));
+        }
+        fpcr
+    }
+
+    pub fn restore(saved: usize) {
+        unsafe {
+            asm!("msr fpcr, {}", in(reg) saved, options(nomem, nostack));
+        }
+    }
+}
+
+#[cfg(not(any(target_arch = "x86_64",
+              all(target_arch = "x86", target_feature = "sse"),
+              target_arch = "aarch64")))]
+mod fpu {
+    pub fn enable_flush() -> usize {
+        0
+    }
+
+    pub fn restore(_: usize) {}
+}
+
+impl DenormalGuard {
+    /// Starts flushing denormals to zero on the current thread.
+    pub fn new() -> DenormalGuard {
+        DenormalGuard { saved: fpu::enable_flush() }
+    }
+}
+
+impl Default for DenormalGuard {
+    fn default() -> DenormalGuard {
+        DenormalGuard::new()
+    }
+}
+
+impl Drop for DenormalGuard {
+    fn drop(&mut self) {
+        fpu::restore(self.saved);
+    }
+}
+
+/// One sample from each input channel, as yielded by ```Frames```. Destructure it to name the
+/// channels: ```let [l, r] = input;```.
+pub type FrameIn<const I: usize> = [Data; I];
+
+/// One sample of each output channel, as yielded by ```Frames```. Index it by channel, or write
+/// all channels at once with ```set```.
+pub struct FrameOut<'a, const O: usize> {
+    samples: [&'a mut Data; O],
+}
+
+impl<'a, const O: usize> FrameOut<'a, O> {
+    /// Writes one sample to each channel.
+    pub fn set(&mut self, samples: [Data; O]) {
+        for (out, sample) in self.samples.iter_mut().zip(samples) {
+            **out = sample;
+        }
+    }
+}
+
+impl<'a, const O: usize> Index<usize> for FrameOut<'a, O> {
+    type Output = Data;
+
+    fn index(&self, channel: usize) -> &Data {
+        self.samples[channel]
+    }
+}
+
+impl<'a, const O: usize> IndexMut<usize> for FrameOut<'a, O> {
+    fn index_mut(&mut self, channel: usize) -> &mut Data {
+        self.samples[channel]
+    }
+}
+
+/// Walks ```I``` input and ```O``` output channels in step, one frame per iteration. Created by
+/// ```frames``` and ```frames_stereo```.
+pub struct Frames<'a, const I: usize, const O: usize> {
+    inputs: [slice::Iter<'a, Data>; I],
+    outputs: [slice::IterMut<'a, Data>; O],
+    remaining: usize,
+}
+
+/// Iterates over the frames of ```inputs``` and ```outputs``` together, yielding an input frame
+/// and an output frame per sample. Stops at the end of the shortest channel.
+///
+/// ```no_run
+/// # use ladspa::{PortConnection, Ports};
+/// # use ladspa::util::frames;
+/// # fn run<'a>(ports: &mut [PortConnection<'a>]) {
+/// # let mut ports = Ports::new(ports);
+/// let input = ports.input_channels("Input");
+/// for (input, mut output) in frames(input, ports.output_channels("Output")) {
+///     let [l, r] = input;
+///     output.set([r, l]);
+/// }
+/// # }
+/// ```
+pub fn frames<'a, const I: usize, const O: usize>(inputs: [&'a [Data]; I],
+                                                  outputs: [&'a mut [Data]; O])
+                                                  -> Frames<'a, I, O> {
+    let remaining = inputs.iter().map(|input| input.len())
+        .chain(outputs.iter().map(|output| output.len()))
+        .min()
+        .unwrap_or(0);
+    Frames {
+        inputs: inputs.map(|input| input.iter()),
+        outputs: outputs.map(|output| output.iter_mut()),
+        remaining,
+    }
+}
+
+/// ```frames``` for a stereo plugin.
+///
+/// ```no_run
+/// # use ladspa::util::frames_stereo;
+/// # let (in_l, in_r, gain) = ([0.0; 64], [0.0; 64], 0.5);
+/// # let (mut out_l, mut out_r) = ([0.0; 64], [0.0; 64]);
+/// for (input, mut output) in frames_stereo(&in_l, &in_r, &mut out_l, &mut out_r) {
+///     output[0] = input[0] * gain;
+///     output[1] = input[1] * gain;
+/// }
+/// ```
+pub fn frames_stereo<'a>(in_l: &'a [Data], in_r: &'a [Data],
+                         out_l: &'a mut [Data], out_r: &'a mut [Data])
+                         -> Frames<'a, 2, 2> {
+    frames([in_l, in_r], [out_l, out_r])
+}
+
+impl<'a, const I: usize, const O: usize> Iterator for Frames<'a, I, O> {
+    type Item = (FrameIn<I>, FrameOut<'a, O>);
+
+    fn next(&mut self) -> Option<(FrameIn<I>, FrameOut<'a, O>)> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        // Every channel has at least `remaining` samples left.
+        let input = array::from_fn(|channel| *self.inputs[channel].next().unwrap());
+        let samples = array::from_fn(|channel| self.outputs[channel].next().unwrap());
+        Some((input, FrameOut { samples }))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, const I: usize, const O: usize> ExactSizeIterator for Frames<'a, I, O> {}
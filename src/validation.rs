@@ -0,0 +1,220 @@
+use core::error::Error;
+use core::fmt;
+use alloc::vec::Vec;
+
+use super::{Data, DefaultValue, PluginDescriptor, PortDescriptor, HINT_LOGARITHMIC,
+            HINT_SAMPLE_RATE};
+
+// The bounds of `HINT_SAMPLE_RATE` ports scale with the sample rate while fixed defaults don't,
+// so defaults are checked at both ends of the range of rates hosts commonly run at.
+const SAMPLE_RATES: [u64; 2] = [22050, 192000];
+
+/// A mistake in a ```PluginDescriptor```, as found by ```PluginDescriptor::validate```. Hosts
+/// react to most of them in confusing ways, such as rejecting the plugin without a word or
+/// showing controls which can't reach their defaults.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationError {
+    /// The label is empty.
+    EmptyLabel,
+    /// The label contains whitespace, which hosts use to separate labels on command lines.
+    LabelWhitespace(&'static str),
+    /// The named port has a default of the given value, outside of its bounds. For
+    /// ```HINT_SAMPLE_RATE``` ports, the value is the default at the sample rate it is outside
+    /// of the bounds at.
+    DefaultOutOfBounds(&'static str, Data),
+    /// The named port has a default relative to a bound it doesn't have.
+    DefaultWithoutBound(&'static str),
+    /// The named port is logarithmic, but one of its bounds is zero or negative.
+    LogarithmicNonPositive(&'static str),
+    /// Several ports have this name, so users can't tell them apart.
+    DuplicatePortName(&'static str),
+    /// The plugin has no audio ports at all.
+    NoAudioPorts,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ValidationError::EmptyLabel => write!(f, "the label is empty"),
+            ValidationError::LabelWhitespace(label) => {
+                write!(f, "label \"{}\" contains whitespace", label)
+            }
+            ValidationError::DefaultOutOfBounds(port, value) => {
+                write!(f, "port \"{}\" has a default of {} outside of its bounds", port, value)
+            }
+            ValidationError::DefaultWithoutBound(port) => {
+                write!(f, "port \"{}\" has a default relative to a bound it doesn't have", port)
+            }
+            ValidationError::LogarithmicNonPositive(port) => {
+                write!(f, "port \"{}\" is logarithmic but has a bound which is not positive", port)
+            }
+            ValidationError::DuplicatePortName(port) => {
+                write!(f, "several ports are named \"{}\"", port)
+            }
+            ValidationError::NoAudioPorts => write!(f, "the plugin has no audio ports"),
+        }
+    }
+}
+
+impl Error for ValidationError {}
+
+impl PluginDescriptor {
+    /// Checks the descriptor for mistakes which hosts don't report clearly, returning all of
+    /// them. Descriptors made with ```PluginDescriptor::builder``` already had their ports and
+    /// presets checked; this goes further, into what makes sense to users.
+    ///
+    /// Debug builds check every descriptor before exporting it, and refuse to export those with
+    /// mistakes.
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors = Vec::new();
+        if self.label.is_empty() {
+            errors.push(ValidationError::EmptyLabel);
+        } else if self.label.contains(char::is_whitespace) {
+            errors.push(ValidationError::LabelWhitespace(self.label));
+        }
+
+        for (index, port) in self.ports.iter().enumerate() {
+            if self.ports[..index].iter().any(|other| other.name == port.name) &&
+               !errors.contains(&ValidationError::DuplicatePortName(port.name)) {
+                errors.push(ValidationError::DuplicatePortName(port.name));
+            }
+
+            let hint = port.hint.map_or(0, |hint| hint.bits());
+            let positive = |bound: Option<Data>| bound.is_none_or(|bound| bound > 0.0);
+            if hint & HINT_LOGARITHMIC.bits() != 0 &&
+               !(positive(port.lower_bound) && positive(port.upper_bound)) {
+                errors.push(ValidationError::LogarithmicNonPositive(port.name));
+            }
+
+            let (needs_lower, needs_upper) = match port.default {
+                None => continue,
+                Some(DefaultValue::Minimum) => (true, false),
+                Some(DefaultValue::Maximum) => (false, true),
+                Some(DefaultValue::Low) | Some(DefaultValue::Middle) |
+                Some(DefaultValue::High) => (true, true),
+                Some(_) => (false, false),
+            };
+            if needs_lower && port.lower_bound.is_none() ||
+               needs_upper && port.upper_bound.is_none() {
+                errors.push(ValidationError::DefaultWithoutBound(port.name));
+                continue;
+            }
+            let scaled = hint & HINT_SAMPLE_RATE.bits() != 0;
+            let sample_rates: &[u64] = if scaled { &SAMPLE_RATES } else { &[1] };
+            for &sample_rate in sample_rates {
+                let value = port.default_at(sample_rate).unwrap_or(0.0);
+                let scale = if scaled { sample_rate as Data } else { 1.0 };
+                if port.lower_bound.is_some_and(|lower| value < lower * scale) ||
+                   port.upper_bound.is_some_and(|upper| value > upper * scale) {
+                    errors.push(ValidationError::DefaultOutOfBounds(port.name, value));
+                    break;
+                }
+            }
+        }
+
+        if !self.ports.iter().any(|port| {
+            matches!(port.desc, PortDescriptor::AudioInput | PortDescriptor::AudioOutput)
+        }) {
+            errors.push(ValidationError::NoAudioPorts);
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+    use super::super::{new_default, Plugin, Port, PortConnection, PROP_NONE};
+
+    #[derive(Default)]
+    struct Silent;
+
+    impl Plugin for Silent {
+        fn run<'a>(&mut self, _: usize, _: &mut [PortConnection<'a>]) {}
+    }
+
+    // A descriptor without mistakes: a mono effect with a logarithmic frequency control.
+    fn descriptor() -> PluginDescriptor {
+        PluginDescriptor {
+            unique_id: 1,
+            label: "valid",
+            properties: PROP_NONE,
+            name: "Valid",
+            maker: "ladspa.rs",
+            copyright: "None",
+            category: None,
+            ports: vec![Port {
+                            name: "Input",
+                            desc: PortDescriptor::AudioInput,
+                            ..Default::default()
+                        },
+                        Port {
+                            name: "Output",
+                            desc: PortDescriptor::AudioOutput,
+                            ..Default::default()
+                        },
+                        Port {
+                            name: "Frequency",
+                            desc: PortDescriptor::ControlInput,
+                            hint: Some(HINT_LOGARITHMIC),
+                            default: Some(DefaultValue::Middle),
+                            lower_bound: Some(20.0),
+                            upper_bound: Some(20000.0),
+                            ..Default::default()
+                        }],
+            presets: vec![],
+            new: new_default::<Silent>,
+        }
+    }
+
+    // The mistakes found in `descriptor` once changed by `change`.
+    fn errors<F: FnOnce(&mut PluginDescriptor)>(change: F) -> Vec<ValidationError> {
+        let mut descriptor = descriptor();
+        change(&mut descriptor);
+        descriptor.validate().err().unwrap_or_default()
+    }
+
+    #[test]
+    fn descriptors_without_mistakes_validate() {
+        assert_eq!(descriptor().validate(), Ok(()));
+    }
+
+    #[test]
+    fn labels_must_be_single_words() {
+        assert_eq!(errors(|descriptor| descriptor.label = ""), [ValidationError::EmptyLabel]);
+        assert_eq!(errors(|descriptor| descriptor.label = "my plugin"),
+                   [ValidationError::LabelWhitespace("my plugin")]);
+    }
+
+    #[test]
+    fn defaults_must_be_within_the_bounds() {
+        assert_eq!(errors(|descriptor| descriptor.ports[2].default = Some(DefaultValue::Value0)),
+                   [ValidationError::DefaultOutOfBounds("Frequency", 0.0)]);
+    }
+
+    #[test]
+    fn logarithmic_bounds_must_be_positive() {
+        let errors = errors(|descriptor| {
+            descriptor.ports[2].lower_bound = Some(0.0);
+            descriptor.ports[2].default = None;
+        });
+        assert_eq!(errors, [ValidationError::LogarithmicNonPositive("Frequency")]);
+    }
+
+    #[test]
+    fn port_names_must_be_unique() {
+        assert_eq!(errors(|descriptor| descriptor.ports[1].name = "Input"),
+                   [ValidationError::DuplicatePortName("Input")]);
+    }
+
+    #[test]
+    fn plugins_need_audio_ports() {
+        let errors = errors(|descriptor| {
+            descriptor.ports.drain(..2);
+        });
+        assert_eq!(errors, [ValidationError::NoAudioPorts]);
+    }
+}
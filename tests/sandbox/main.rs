@@ -0,0 +1,85 @@
+//! Runs a plugin which crashes and hangs on demand in a sandbox. This program is its own sandbox
+//! process, so it has no test harness: the harness would get to ```main``` first.
+
+extern crate ladspa;
+
+use std::env::{self, consts};
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ladspa::host::{self, SandboxedInstance};
+
+const INPUT: usize = 0;
+const OUTPUT: usize = 1;
+const MODE: usize = 2;
+const BLOCK_SIZE: usize = 64;
+
+// The plugin of tests/sandbox/plugin.rs, which cargo builds as an example next to the directory
+// of this program.
+fn library() -> PathBuf {
+    let exe = env::current_exe().unwrap();
+    let target = exe.parent().unwrap().parent().unwrap();
+    let library = target.join("examples")
+        .join(format!("{}sandbox_plugin{}", consts::DLL_PREFIX, consts::DLL_SUFFIX));
+    assert!(library.exists(),
+            "{} not found: build it with `cargo build --example sandbox_plugin`",
+            library.display());
+    library
+}
+
+// Runs blocks until a new process has replaced the one which crashed.
+fn wait_for_restart(instance: &mut SandboxedInstance) {
+    let start = Instant::now();
+    while !instance.is_running() {
+        assert!(start.elapsed() < Duration::from_secs(10), "the sandbox was not restarted");
+        thread::sleep(Duration::from_millis(10));
+        instance.run(BLOCK_SIZE);
+    }
+}
+
+fn runs_the_plugin(instance: &mut SandboxedInstance) {
+    instance.run(BLOCK_SIZE);
+    assert!(instance.output(OUTPUT).iter().all(|&sample| sample == 1.5));
+}
+
+fn silences_and_restarts_a_crashed_plugin(instance: &mut SandboxedInstance) {
+    instance.set_control(MODE, 1.0);
+    instance.run(BLOCK_SIZE);
+    assert!(instance.output(OUTPUT).iter().all(|&sample| sample == 0.0));
+    assert_eq!(instance.crashes(), 1);
+    assert!(!instance.is_running());
+
+    instance.set_control(MODE, 0.0);
+    wait_for_restart(instance);
+    runs_the_plugin(instance);
+    assert_eq!(instance.crashes(), 1);
+}
+
+fn kills_a_hung_plugin(instance: &mut SandboxedInstance) {
+    instance.set_timeout(Duration::from_millis(200));
+    instance.set_control(MODE, 2.0);
+    let start = Instant::now();
+    instance.run(BLOCK_SIZE);
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(instance.output(OUTPUT).iter().all(|&sample| sample == 0.0));
+    assert_eq!(instance.crashes(), 2);
+    assert!(!instance.is_running());
+
+    instance.set_control(MODE, 0.0);
+    wait_for_restart(instance);
+    runs_the_plugin(instance);
+}
+
+fn main() {
+    host::serve_sandbox();
+    let unique_id = ladspa::UniqueId::experimental(999).get();
+    let mut instance = SandboxedInstance::spawn(library(), unique_id, 44100, BLOCK_SIZE)
+        .expect("failed to start the sandbox");
+    instance.activate();
+    instance.input_mut(INPUT).fill(0.5);
+
+    runs_the_plugin(&mut instance);
+    silences_and_restarts_a_crashed_plugin(&mut instance);
+    kills_a_hung_plugin(&mut instance);
+}
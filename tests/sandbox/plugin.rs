@@ -0,0 +1,55 @@
+//! A plugin for the sandbox tests, built as a library for them to load.
+
+#[macro_use]
+extern crate ladspa;
+
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+use ladspa::{DefaultValue, InstantiateError, Plugin, PluginDescriptor, PortConnection};
+
+// Adds one to its input while its "Mode" control is 0, aborts when it is 1 and hangs when it is 2.
+struct Unreliable;
+
+fn new_unreliable(_: &PluginDescriptor, _: u64)
+                  -> Result<Box<dyn Plugin + Send>, InstantiateError> {
+    Ok(Box::new(Unreliable))
+}
+
+impl Plugin for Unreliable {
+    fn run<'a>(&mut self, sample_count: usize, ports: &mut [PortConnection<'a>]) {
+        match ports[2].unwrap_control() as i32 {
+            1 => process::abort(),
+            2 => loop {
+                thread::sleep(Duration::from_secs(1));
+            },
+            _ => {}
+        }
+        let input = ports[0].unwrap_audio();
+        let output = ports[1].unwrap_audio_mut();
+        for i in 0..sample_count {
+            output[i] = input[i] + 1.0;
+        }
+    }
+}
+
+fn get_descriptor(index: u64) -> Option<PluginDescriptor> {
+    match index {
+        0 => {
+            PluginDescriptor::builder()
+                .unique_id(ladspa::UniqueId::experimental(999).get())
+                .label("unreliable")
+                .name("Unreliable")
+                .audio_in("Input")
+                .audio_out("Output")
+                .control_in("Mode", 0.0..2.0, DefaultValue::Minimum)
+                .new(new_unreliable)
+                .build()
+                .ok()
+        }
+        _ => None,
+    }
+}
+
+export_ladspa!(fn get_descriptor);